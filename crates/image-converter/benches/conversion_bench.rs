@@ -215,7 +215,7 @@ fn is_slow_format_pair(pair: &FormatPair) -> bool {
 /// they are significantly slower than PNG/JPEG/WebP conversions.
 fn bench_by_size(c: &mut Criterion) {
     for size in SIZES {
-        let pixels = size.width as u64 * size.height as u64;
+        let pixels = u64::from(size.width) * u64::from(size.height);
         let is_large = pixels >= 2_000_000;
 
         // Split into fast and slow groups so each gets appropriate timing.
@@ -0,0 +1,876 @@
+use std::fmt;
+
+use image::{imageops, DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::color;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+
+/// Largest exposure change, in stops, in either direction.
+pub const MAX_EXPOSURE_STOPS: f64 = 10.0;
+
+/// Share of the brightest pixels [`Adjustment::WhiteBalance`] takes as the white point.
+const WHITE_PATCH: f64 = 0.05;
+
+/// Share of pixels at each end [`Adjustment::ContrastStretch`] lets clip, so a few
+/// specks of dust or glare don't pin the range.
+const STRETCH_CLIP: f64 = 0.005;
+
+/// Furthest [`Enhancement::detect`] lets white balance scale a channel either way, so
+/// a scene that really is warm (a sunset, candlelight) isn't neutralized outright.
+const AUTO_GAIN_LIMIT: f64 = 1.25;
+
+/// The vibrance boost [`Enhancement::detect`] picks, in percent.
+const AUTO_VIBRANCE: f64 = 15.0;
+
+/// The unsharp mask strength [`Enhancement::detect`] picks.
+const AUTO_SHARPEN: f64 = 0.3;
+
+/// Largest unsharp mask strength an [`Enhancement`] accepts.
+pub const MAX_SHARPEN: f64 = 3.0;
+
+/// Largest white balance gain an [`Enhancement`] accepts for a channel.
+pub const MAX_GAIN: f64 = 4.0;
+
+/// Radius (Gaussian sigma, in pixels) of the blur an [`Enhancement`] sharpens against.
+const SHARPEN_SIGMA: f32 = 1.0;
+
+/// A photo-correction adjustment. Alpha is never changed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Adjustment {
+    /// Multiplies linear light by `2^stops`, like changing the exposure of the shot.
+    Exposure(f64),
+    /// Boosts (or, when negative, reduces) saturation by up to `amount` percent,
+    /// weighted toward muted colors so already-saturated colors don't clip.
+    Vibrance(f64),
+    /// Automatic white balance: scales each channel so the brightest pixels (the paper
+    /// of a document, a white wall) come out neutral gray at the same brightness.
+    WhiteBalance,
+    /// Automatic levels: stretches luma so the darkest and brightest pixels reach
+    /// black and white, applied equally to every channel so hues don't shift.
+    ContrastStretch,
+    /// Turns a scanned or photographed color negative into a positive: divides out the
+    /// film base (including its orange mask), inverts to density, and levels each
+    /// channel on its own so the dyes' differing contrast is balanced.
+    InvertNegative,
+}
+
+impl Adjustment {
+    /// An exposure change of `stops` EV.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AdjustError::InvalidExposure` if `stops` is outside
+    /// ±[`MAX_EXPOSURE_STOPS`].
+    pub fn exposure(stops: f64) -> Result<Self, AdjustError> {
+        if (-MAX_EXPOSURE_STOPS..=MAX_EXPOSURE_STOPS).contains(&stops) {
+            Ok(Self::Exposure(stops))
+        } else {
+            Err(AdjustError::InvalidExposure(stops))
+        }
+    }
+
+    /// A vibrance change of `amount` percent, from -100 (muted colors go gray) to 100.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AdjustError::InvalidVibrance` if `amount` is outside -100..=100.
+    pub fn vibrance(amount: f64) -> Result<Self, AdjustError> {
+        if (-100.0..=100.0).contains(&amount) {
+            Ok(Self::Vibrance(amount))
+        } else {
+            Err(AdjustError::InvalidVibrance(amount))
+        }
+    }
+
+    /// Applies the adjustment to every pixel.
+    pub fn apply(self, img: &mut RgbaImage) {
+        match self {
+            Self::Exposure(stops) => apply_exposure(img, stops),
+            Self::Vibrance(amount) => apply_vibrance(img, amount / 100.0),
+            Self::WhiteBalance => apply_white_balance(img),
+            Self::ContrastStretch => apply_contrast_stretch(img),
+            Self::InvertNegative => apply_invert_negative(img),
+        }
+    }
+}
+
+/// Exposure is a per-channel curve, so it is precomputed as a lookup table.
+fn apply_exposure(img: &mut RgbaImage, stops: f64) {
+    let gain = stops.exp2();
+    let lut: Vec<u8> = (0..=u8::MAX)
+        .map(|value| color::linear_to_srgb(color::srgb_to_linear(value) * gain))
+        .collect();
+    for pixel in img.pixels_mut() {
+        for value in pixel.0.iter_mut().take(3) {
+            *value = lut.get(usize::from(*value)).copied().unwrap_or(*value);
+        }
+    }
+}
+
+/// Scales each pixel's distance from its own luminance by `1 + amount * (1 - s)`,
+/// where `s` is the pixel's saturation, so grays and saturated colors are left alone.
+fn apply_vibrance(img: &mut RgbaImage, amount: f64) {
+    for pixel in img.pixels_mut() {
+        let rgb = pixel.0.iter().take(3);
+        let max = rgb.clone().max().copied().unwrap_or(0);
+        let min = rgb.min().copied().unwrap_or(0);
+        let saturation = f64::from(max - min) / 255.0;
+        let factor = 1.0 + amount * (1.0 - saturation);
+
+        let luma = f64::from(color::luminance(pixel.0));
+        for value in pixel.0.iter_mut().take(3) {
+            *value = color::to_u8(luma + (f64::from(*value) - luma) * factor);
+        }
+    }
+}
+
+/// Luma histogram of the visible pixels.
+fn luma_histogram(img: &RgbaImage) -> [u64; 256] {
+    let mut histogram = [0u64; 256];
+    for pixel in img.pixels().filter(|pixel| pixel.0[3] > 0) {
+        if let Some(bin) = histogram.get_mut(usize::from(color::luminance(pixel.0))) {
+            *bin += 1;
+        }
+    }
+    histogram
+}
+
+/// The luma level below which `share` of the counted pixels fall.
+fn percentile(histogram: &[u64; 256], share: f64) -> u8 {
+    let total: u64 = histogram.iter().sum();
+    let mut seen = 0;
+    for (level, &count) in (0..=u8::MAX).zip(histogram) {
+        seen += count;
+        if color::to_f64(seen) > color::to_f64(total) * share {
+            return level;
+        }
+    }
+    u8::MAX
+}
+
+/// Maps each color channel through its lookup table in `curves`.
+fn apply_curves(img: &mut RgbaImage, curves: &[[u8; 256]; 3]) {
+    for pixel in img.pixels_mut() {
+        for (value, curve) in pixel.0.iter_mut().zip(curves) {
+            *value = curve.get(usize::from(*value)).copied().unwrap_or(*value);
+        }
+    }
+}
+
+/// White-patch balance on the brightest [`WHITE_PATCH`] of visible pixels.
+fn apply_white_balance(img: &mut RgbaImage) {
+    apply_gains(img, white_balance_gains(img));
+}
+
+/// The per-channel gains that make the brightest [`WHITE_PATCH`] of visible pixels
+/// neutral gray at the same brightness; all 1.0 if nothing is visible.
+fn white_balance_gains(img: &RgbaImage) -> [f64; 3] {
+    let threshold = percentile(&luma_histogram(img), 1.0 - WHITE_PATCH);
+    let mut sums = [0u64; 3];
+    let mut count = 0u64;
+    for pixel in img.pixels() {
+        if pixel.0[3] > 0 && color::luminance(pixel.0) >= threshold {
+            for (sum, value) in sums.iter_mut().zip(pixel.0) {
+                *sum += u64::from(value);
+            }
+            count += 1;
+        }
+    }
+    if count == 0 {
+        return [1.0; 3];
+    }
+    let [r, g, b] = sums.map(|sum| color::to_f64(sum) / color::to_f64(count));
+    // Rec. 709 luma of the patch, the brightness the neutral result should keep.
+    let target = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    [r, g, b].map(|mean| if mean > 0.0 { target / mean } else { 1.0 })
+}
+
+/// Multiplies each color channel by its gain.
+fn apply_gains(img: &mut RgbaImage, gains: [f64; 3]) {
+    let curves = gains.map(|gain| {
+        let mut curve = [0u8; 256];
+        for (value, out) in (0..=u8::MAX).zip(curve.iter_mut()) {
+            *out = color::to_u8(f64::from(value) * gain);
+        }
+        curve
+    });
+    apply_curves(img, &curves);
+}
+
+/// Maps the [`STRETCH_CLIP`] and `1 - STRETCH_CLIP` luma percentiles to 0 and 255.
+fn apply_contrast_stretch(img: &mut RgbaImage) {
+    let (low, high) = stretch_levels(img);
+    apply_levels(img, low, high);
+}
+
+/// The [`STRETCH_CLIP`] and `1 - STRETCH_CLIP` luma percentiles, or `(0, 255)` (no
+/// change) if the image is too flat to stretch.
+fn stretch_levels(img: &RgbaImage) -> (u8, u8) {
+    let histogram = luma_histogram(img);
+    let low = percentile(&histogram, STRETCH_CLIP);
+    let high = percentile(&histogram, 1.0 - STRETCH_CLIP);
+    if high <= low {
+        (0, u8::MAX)
+    } else {
+        (low, high)
+    }
+}
+
+/// Maps luma level `low` to black and `high` to white in every channel.
+fn apply_levels(img: &mut RgbaImage, low: u8, high: u8) {
+    if high <= low {
+        return;
+    }
+    let (low, high) = (f64::from(low), f64::from(high));
+    let mut curve = [0u8; 256];
+    for (value, out) in (0..=u8::MAX).zip(curve.iter_mut()) {
+        *out = color::to_u8((f64::from(value) - low) * 255.0 / (high - low));
+    }
+    apply_curves(img, &[curve; 3]);
+}
+
+/// The settings of an automatic enhancement, applied in field order. Returned by
+/// [`Enhancement::detect`] so callers can see what was chosen and tweak it.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Enhancement {
+    /// White balance gains for red, green and blue (1.0 leaves a channel alone).
+    pub white_balance: [f64; 3],
+    /// The level that becomes black.
+    pub black_point: u8,
+    /// The level that becomes white.
+    pub white_point: u8,
+    /// Vibrance boost in percent, from -100 to 100.
+    pub vibrance: f64,
+    /// Unsharp mask strength, from 0 (off) to [`MAX_SHARPEN`].
+    pub sharpen: f64,
+}
+
+impl Default for Enhancement {
+    /// Settings that leave the image unchanged.
+    fn default() -> Self {
+        Self {
+            white_balance: [1.0; 3],
+            black_point: 0,
+            white_point: u8::MAX,
+            vibrance: 0.0,
+            sharpen: 0.0,
+        }
+    }
+}
+
+impl Enhancement {
+    /// Picks conservative settings for `img`: white balance from the brightest pixels
+    /// with each gain held within ±25%, a contrast stretch of the balanced result
+    /// (ignoring the extreme 0.5% at each end), a 15% vibrance boost and light
+    /// sharpening.
+    pub fn detect(img: &RgbaImage) -> Self {
+        let white_balance =
+            white_balance_gains(img).map(|gain| gain.clamp(1.0 / AUTO_GAIN_LIMIT, AUTO_GAIN_LIMIT));
+        let mut balanced = img.clone();
+        apply_gains(&mut balanced, white_balance);
+        let (black_point, white_point) = stretch_levels(&balanced);
+        Self {
+            white_balance,
+            black_point,
+            white_point,
+            vibrance: AUTO_VIBRANCE,
+            sharpen: AUTO_SHARPEN,
+        }
+    }
+
+    /// Checks that every setting is in range, e.g. after a caller has edited them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AdjustError::InvalidGain` for a gain that isn't positive or is above
+    /// [`MAX_GAIN`], `AdjustError::InvalidLevels` if the black point isn't below the
+    /// white point, `AdjustError::InvalidVibrance` for vibrance outside -100..=100, or
+    /// `AdjustError::InvalidSharpen` for sharpening outside 0..=[`MAX_SHARPEN`].
+    pub fn validate(&self) -> Result<(), AdjustError> {
+        if let Some(&gain) = self
+            .white_balance
+            .iter()
+            .find(|&&gain| !(gain > 0.0 && gain <= MAX_GAIN))
+        {
+            return Err(AdjustError::InvalidGain(gain));
+        }
+        if self.black_point >= self.white_point {
+            return Err(AdjustError::InvalidLevels {
+                black: self.black_point,
+                white: self.white_point,
+            });
+        }
+        Adjustment::vibrance(self.vibrance)?;
+        if !(0.0..=MAX_SHARPEN).contains(&self.sharpen) {
+            return Err(AdjustError::InvalidSharpen(self.sharpen));
+        }
+        Ok(())
+    }
+
+    /// Applies the white balance, levels, vibrance and sharpening in turn.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        apply_gains(img, self.white_balance);
+        apply_levels(img, self.black_point, self.white_point);
+        apply_vibrance(img, self.vibrance / 100.0);
+        apply_sharpen(img, self.sharpen);
+    }
+}
+
+/// Unsharp mask: pushes each color channel away from a Gaussian blur of itself.
+fn apply_sharpen(img: &mut RgbaImage, amount: f64) {
+    if amount <= 0.0 {
+        return;
+    }
+    let blurred = imageops::blur(img, SHARPEN_SIGMA);
+    for (pixel, blurred) in img.pixels_mut().zip(blurred.pixels()) {
+        for (value, blurred) in pixel.0.iter_mut().zip(blurred.0).take(3) {
+            let value_f = f64::from(*value);
+            *value = color::to_u8(value_f + amount * (value_f - f64::from(blurred)));
+        }
+    }
+}
+
+/// Decodes `input`, picks settings with [`Enhancement::detect`] and applies them, and
+/// encodes the result as `target`. Returns the encoded image and the settings used.
+///
+/// # Errors
+///
+/// Returns an `AdjustError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn auto_enhance(
+    input: &[u8],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<(Vec<u8>, Enhancement), AdjustError> {
+    let img = image::load_from_memory(input)
+        .map_err(AdjustError::Decode)?
+        .into_rgba8();
+    let enhancement = Enhancement::detect(&img);
+    let data = enhance_image(img, &enhancement, target, quality)?;
+    Ok((data, enhancement))
+}
+
+/// Decodes `input`, applies `enhancement` (for example one returned by
+/// [`auto_enhance`] and then tweaked), and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns an `AdjustError` if a setting is out of range (see
+/// [`Enhancement::validate`]), the input cannot be decoded, or the output cannot be
+/// encoded.
+pub fn enhance(
+    input: &[u8],
+    enhancement: &Enhancement,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AdjustError> {
+    enhancement.validate()?;
+    let img = image::load_from_memory(input)
+        .map_err(AdjustError::Decode)?
+        .into_rgba8();
+    enhance_image(img, enhancement, target, quality)
+}
+
+fn enhance_image(
+    mut img: RgbaImage,
+    enhancement: &Enhancement,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AdjustError> {
+    enhancement.apply(&mut img);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(AdjustError::Convert)
+}
+
+/// Per-channel histograms of the visible pixels.
+fn channel_histograms(img: &RgbaImage) -> [[u64; 256]; 3] {
+    let mut histograms = [[0u64; 256]; 3];
+    for pixel in img.pixels().filter(|pixel| pixel.0[3] > 0) {
+        for (histogram, value) in histograms.iter_mut().zip(pixel.0) {
+            if let Some(bin) = histogram.get_mut(usize::from(value)) {
+                *bin += 1;
+            }
+        }
+    }
+    histograms
+}
+
+/// Inverts a color negative through density, per channel. The brightest
+/// `1 - STRETCH_CLIP` percentile of a channel is taken as the bare film base (which
+/// prints as black) and the darkest [`STRETCH_CLIP`] as the densest highlight (white).
+fn apply_invert_negative(img: &mut RgbaImage) {
+    // Linear light can't reach zero density, so very dark pixels clip to white.
+    const FLOOR: f64 = 1e-4;
+    let curves = channel_histograms(img).map(|histogram| {
+        let base = color::srgb_to_linear(percentile(&histogram, 1.0 - STRETCH_CLIP)).max(FLOOR);
+        let densest = color::srgb_to_linear(percentile(&histogram, STRETCH_CLIP)).max(FLOOR);
+        let range = (base / densest).log10();
+        let mut curve = [0u8; 256];
+        for (value, out) in (0..=u8::MAX).zip(curve.iter_mut()) {
+            *out = if range > 0.0 {
+                let density = (base / color::srgb_to_linear(value).max(FLOOR)).log10();
+                color::to_u8(density / range * 255.0)
+            } else {
+                u8::MAX - value
+            };
+        }
+        curve
+    });
+    apply_curves(img, &curves);
+}
+
+/// Remaps each color channel of `img` so its histogram matches `reference`'s, e.g. to
+/// make a batch of product photos consistent with one approved shot. Only visible
+/// pixels are counted, and alpha is kept.
+///
+/// # Errors
+///
+/// Returns `AdjustError::EmptyReference` if `reference` has no visible pixels.
+pub fn match_histogram_to(img: &mut RgbaImage, reference: &RgbaImage) -> Result<(), AdjustError> {
+    let targets = channel_histograms(reference).map(|histogram| cumulative(&histogram));
+    if targets.iter().all(|target| total(target) == 0) {
+        return Err(AdjustError::EmptyReference);
+    }
+    let sources = channel_histograms(img).map(|histogram| cumulative(&histogram));
+    let mut curves = [[0u8; 256]; 3];
+    for ((curve, source), target) in curves.iter_mut().zip(&sources).zip(&targets) {
+        let (source_total, target_total) = (u128::from(total(source)), u128::from(total(target)));
+        // Each level goes to the first reference level whose cumulative share reaches
+        // the level's own share; cross-multiplying keeps the comparison exact.
+        let mut level = 0;
+        for ((out, &seen), value) in curve.iter_mut().zip(source).zip(0..=u8::MAX) {
+            if seen == 0 {
+                *out = value;
+                continue;
+            }
+            while level < u8::MAX
+                && target.get(usize::from(level)).is_some_and(|&reached| {
+                    u128::from(reached) * source_total < u128::from(seen) * target_total
+                })
+            {
+                level += 1;
+            }
+            *out = level;
+        }
+    }
+    apply_curves(img, &curves);
+    Ok(())
+}
+
+/// Running totals of a histogram: entry `i` counts the pixels at or below level `i`.
+fn cumulative(histogram: &[u64; 256]) -> [u64; 256] {
+    let mut total = 0;
+    histogram.map(|count| {
+        total += count;
+        total
+    })
+}
+
+/// The number of pixels a cumulative histogram counts.
+fn total(cumulative: &[u64; 256]) -> u64 {
+    cumulative.last().copied().unwrap_or(0)
+}
+
+/// Decodes `source` and `reference`, matches the source's histogram to the
+/// reference's with [`match_histogram_to`], and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns an `AdjustError` if either input cannot be decoded, the reference has no
+/// visible pixels, or the output cannot be encoded.
+pub fn match_histogram(
+    source: &[u8],
+    reference: &[u8],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AdjustError> {
+    let mut img = image::load_from_memory(source)
+        .map_err(AdjustError::Decode)?
+        .into_rgba8();
+    let reference = image::load_from_memory(reference)
+        .map_err(AdjustError::Decode)?
+        .into_rgba8();
+    match_histogram_to(&mut img, &reference)?;
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(AdjustError::Convert)
+}
+
+/// Decodes `input`, applies the adjustments in order, and encodes the result as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns an `AdjustError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn adjust(
+    input: &[u8],
+    adjustments: &[Adjustment],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AdjustError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(AdjustError::Decode)?
+        .into_rgba8();
+    for adjustment in adjustments {
+        adjustment.apply(&mut img);
+    }
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(AdjustError::Convert)
+}
+
+/// Errors that can occur while applying adjustments.
+#[derive(Debug)]
+pub enum AdjustError {
+    /// The exposure change was outside ±10 stops.
+    InvalidExposure(f64),
+    /// The vibrance amount was outside -100..=100.
+    InvalidVibrance(f64),
+    /// The histogram reference image had no visible pixels.
+    EmptyReference,
+    /// A white balance gain was not positive or above [`MAX_GAIN`].
+    InvalidGain(f64),
+    /// The black point was not below the white point.
+    InvalidLevels { black: u8, white: u8 },
+    /// The sharpening strength was outside 0..=[`MAX_SHARPEN`].
+    InvalidSharpen(f64),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for AdjustError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidExposure(stops) => write!(
+                f,
+                "Exposure must be between -{MAX_EXPOSURE_STOPS} and {MAX_EXPOSURE_STOPS} stops, got {stops}"
+            ),
+            Self::InvalidVibrance(amount) => {
+                write!(f, "Vibrance must be between -100 and 100, got {amount}")
+            }
+            Self::InvalidGain(gain) => write!(
+                f,
+                "White balance gains must be above 0 and at most {MAX_GAIN}, got {gain}"
+            ),
+            Self::InvalidLevels { black, white } => write!(
+                f,
+                "Black point must be below white point, got {black} and {white}"
+            ),
+            Self::InvalidSharpen(amount) => write!(
+                f,
+                "Sharpening must be between 0 and {MAX_SHARPEN}, got {amount}"
+            ),
+            Self::EmptyReference => {
+                write!(f, "The reference image has no visible pixels to match")
+            }
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for AdjustError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::Rgba;
+
+    use super::*;
+
+    fn single(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(1, 1, Rgba(color))
+    }
+
+    fn adjusted(color: [u8; 4], adjustment: Adjustment) -> [u8; 4] {
+        let mut img = single(color);
+        adjustment.apply(&mut img);
+        img.get_pixel(0, 0).0
+    }
+
+    // ===== Exposure Tests =====
+
+    #[test]
+    fn exposure_doubles_linear_light() {
+        let up = Adjustment::exposure(1.0).unwrap();
+        let [r, g, b, a] = adjusted([100, 0, 255, 77], up);
+        let expected = color::linear_to_srgb(color::srgb_to_linear(100) * 2.0);
+        assert_eq!([r, g, b, a], [expected, 0, 255, 77]);
+        // Doubling linear light is far less than doubling the encoded value.
+        assert!(expected < 200 && expected > 130, "{expected}");
+    }
+
+    #[test]
+    fn exposure_round_trips_within_rounding() {
+        let color = [60, 120, 180, 255];
+        let darker = adjusted(color, Adjustment::exposure(-1.0).unwrap());
+        let restored = adjusted(darker, Adjustment::exposure(1.0).unwrap());
+        for (original, restored) in color.iter().zip(restored) {
+            assert!(
+                original.abs_diff(restored) <= 2,
+                "{color:?} -> {restored:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn exposure_validates_range() {
+        assert!(Adjustment::exposure(0.0).is_ok());
+        assert!(matches!(
+            Adjustment::exposure(10.5),
+            Err(AdjustError::InvalidExposure(_))
+        ));
+        assert!(matches!(
+            Adjustment::exposure(f64::NAN),
+            Err(AdjustError::InvalidExposure(_))
+        ));
+    }
+
+    // ===== Vibrance Tests =====
+
+    #[test]
+    fn vibrance_boosts_muted_colors_more() {
+        let boost = Adjustment::vibrance(100.0).unwrap();
+        let muted = [140, 120, 110, 255];
+        let vivid = [250, 20, 10, 255];
+        let growth = |before: [u8; 4]| {
+            let after = adjusted(before, boost);
+            f64::from(after[0] - after[2]) / f64::from(before[0] - before[2])
+        };
+        assert!(growth(muted) > 1.5, "{}", growth(muted));
+        assert!(growth(vivid) < 1.1, "{}", growth(vivid));
+    }
+
+    #[test]
+    fn vibrance_leaves_grays_alone() {
+        for amount in [-100.0, 100.0] {
+            let gray = [90, 90, 90, 200];
+            assert_eq!(adjusted(gray, Adjustment::vibrance(amount).unwrap()), gray);
+        }
+    }
+
+    #[test]
+    fn negative_vibrance_mutes_toward_gray() {
+        let mute = Adjustment::vibrance(-100.0).unwrap();
+        let [r, g, b, _] = adjusted([140, 120, 110, 255], mute);
+        assert!(r.abs_diff(b) <= 4 && r.abs_diff(g) <= 4, "{:?}", [r, g, b]);
+        assert!(matches!(
+            Adjustment::vibrance(-101.0),
+            Err(AdjustError::InvalidVibrance(_))
+        ));
+    }
+
+    // ===== Automatic Tests =====
+
+    #[test]
+    fn white_balance_neutralizes_tinted_paper() {
+        let mut img = RgbaImage::from_fn(10, 10, |x, _| {
+            Rgba(if x < 8 {
+                [230, 220, 180, 255]
+            } else {
+                [40, 38, 30, 255]
+            })
+        });
+        Adjustment::WhiteBalance.apply(&mut img);
+        let [r, g, b, a] = img.get_pixel(0, 0).0;
+        assert!(r.abs_diff(g) <= 1 && g.abs_diff(b) <= 1, "{:?}", [r, g, b]);
+        assert_eq!(a, 255);
+        // The ink is scaled by the same gains, so it stays dark.
+        assert!(img.get_pixel(9, 0).0[0] < 60);
+    }
+
+    #[test]
+    fn contrast_stretch_fills_the_range() {
+        let mut img = RgbaImage::from_fn(64, 1, |x, _| {
+            let v = u8::try_from(80 + x).unwrap();
+            Rgba([v, v, v, 255])
+        });
+        Adjustment::ContrastStretch.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(63, 0).0, [255; 4]);
+
+        let mut flat = single([90, 90, 90, 255]);
+        Adjustment::ContrastStretch.apply(&mut flat);
+        assert_eq!(flat.get_pixel(0, 0).0, [90, 90, 90, 255]);
+    }
+
+    #[test]
+    fn invert_negative_removes_the_mask_and_balances_channels() {
+        // A gray ramp shot on film: an orange base, and dye densities that grow with
+        // scene brightness at a different rate per channel.
+        let base = [0.9, 0.45, 0.2];
+        let max_density = [1.8, 2.2, 2.6];
+        let mut img = RgbaImage::from_fn(101, 1, |x, _| {
+            let brightness = f64::from(x) / 100.0;
+            let [r, g, b] = [0, 1, 2].map(|c: usize| {
+                let density = brightness * max_density[c];
+                color::linear_to_srgb(base[c] * 10f64.powf(-density))
+            });
+            Rgba([r, g, b, 255])
+        });
+        Adjustment::InvertNegative.apply(&mut img);
+
+        let [r, g, b, a] = img.get_pixel(0, 0).0;
+        assert!(r < 8 && g < 8 && b < 8, "{:?}", [r, g, b]);
+        assert_eq!(a, 255);
+        assert!(img.get_pixel(100, 0).0.iter().all(|&v| v > 245));
+        let [r, g, b, _] = img.get_pixel(50, 0).0;
+        assert!(r.abs_diff(g) <= 6 && g.abs_diff(b) <= 6, "{:?}", [r, g, b]);
+        assert!((110..=145).contains(&g), "{g}");
+    }
+
+    #[test]
+    fn adjust_encoded_applies_in_order() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(single([100, 110, 120, 255]))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let adjustments = [
+            Adjustment::exposure(1.0).unwrap(),
+            Adjustment::vibrance(50.0).unwrap(),
+        ];
+        let output = adjust(&png, &adjustments, ImageFormat::Png, None).unwrap();
+        let output = image::load_from_memory(&output).unwrap().into_rgba8();
+
+        let mut expected = single([100, 110, 120, 255]);
+        for adjustment in adjustments {
+            adjustment.apply(&mut expected);
+        }
+        assert_eq!(output, expected);
+
+        assert!(matches!(
+            adjust(&[0], &adjustments, ImageFormat::Png, None),
+            Err(AdjustError::Decode(_))
+        ));
+    }
+
+    // ===== Histogram Matching Tests =====
+
+    #[test]
+    fn histogram_matching_takes_on_the_reference_tones() {
+        // A dark gradient matched to a bright, reddish one.
+        let mut img = RgbaImage::from_fn(64, 1, |x, _| {
+            let v = u8::try_from(x).unwrap();
+            Rgba([v, v, v, 255])
+        });
+        let reference = RgbaImage::from_fn(64, 1, |x, _| {
+            let v = u8::try_from(x).unwrap();
+            Rgba([192 + v, 128 + v, 100 + v, 255])
+        });
+        match_histogram_to(&mut img, &reference).unwrap();
+        assert_eq!(img, reference);
+
+        // Transparent pixels neither count nor change alpha.
+        let mut img = RgbaImage::from_fn(2, 1, |x, _| {
+            Rgba([40, 40, 40, u8::try_from(x).unwrap() * 255])
+        });
+        let mut reference = RgbaImage::from_pixel(2, 1, Rgba([200, 150, 100, 255]));
+        reference.put_pixel(1, 0, Rgba([0, 0, 0, 0]));
+        match_histogram_to(&mut img, &reference).unwrap();
+        assert_eq!(img.get_pixel(1, 0).0, [200, 150, 100, 255]);
+        assert_eq!(img.get_pixel(0, 0).0[3], 0);
+
+        let empty = RgbaImage::new(2, 2);
+        assert!(matches!(
+            match_histogram_to(&mut img, &empty),
+            Err(AdjustError::EmptyReference)
+        ));
+    }
+
+    // ===== Auto-Enhance Tests =====
+
+    #[test]
+    fn auto_enhance_balances_stretches_and_reports_its_settings() {
+        // A flat, warm gradient.
+        let img = RgbaImage::from_fn(64, 8, |x, _| {
+            let v = 80 + u8::try_from(x).unwrap();
+            Rgba([v + 30, v, v - 20, 255])
+        });
+        let enhancement = Enhancement::detect(&img);
+        let [r, g, b] = enhancement.white_balance;
+        assert!(r < 1.0 && b > 1.0 && (g - 1.0).abs() < 0.1);
+        assert!(enhancement
+            .white_balance
+            .iter()
+            .all(|gain| (0.8..=1.25).contains(gain)));
+        assert!(enhancement.black_point > 50 && enhancement.white_point < 180);
+        assert!(enhancement.validate().is_ok());
+
+        let mut enhanced = img.clone();
+        enhancement.apply(&mut enhanced);
+        let bright = enhanced.get_pixel(60, 4).0;
+        assert!(
+            bright[0].abs_diff(bright[2]) < 10,
+            "still tinted: {bright:?}"
+        );
+        assert!(enhanced.get_pixel(0, 4).0[1] < 20);
+        assert!(enhanced.get_pixel(63, 4).0[1] > 235);
+        assert_eq!(enhanced.get_pixel(0, 0).0[3], 255);
+
+        let mut unchanged = img.clone();
+        Enhancement::default().apply(&mut unchanged);
+        assert_eq!(unchanged, img);
+    }
+
+    #[test]
+    fn enhancement_sharpening_raises_edge_contrast() {
+        let edge = RgbaImage::from_fn(12, 4, |x, _| {
+            if x < 6 {
+                Rgba([100, 100, 100, 255])
+            } else {
+                Rgba([150, 150, 150, 255])
+            }
+        });
+        let mut sharpened = edge.clone();
+        Enhancement {
+            sharpen: 1.0,
+            ..Enhancement::default()
+        }
+        .apply(&mut sharpened);
+        assert!(sharpened.get_pixel(5, 2).0[0] < 100);
+        assert!(sharpened.get_pixel(6, 2).0[0] > 150);
+        assert_eq!(sharpened.get_pixel(0, 2).0[0], 100);
+    }
+
+    #[test]
+    fn enhancement_validation_rejects_out_of_range_settings() {
+        let valid = Enhancement::default();
+        assert!(matches!(
+            Enhancement {
+                white_balance: [1.0, 0.0, 1.0],
+                ..valid
+            }
+            .validate(),
+            Err(AdjustError::InvalidGain(_))
+        ));
+        assert!(matches!(
+            Enhancement {
+                black_point: 200,
+                white_point: 100,
+                ..valid
+            }
+            .validate(),
+            Err(AdjustError::InvalidLevels {
+                black: 200,
+                white: 100
+            })
+        ));
+        assert!(matches!(
+            Enhancement {
+                vibrance: 150.0,
+                ..valid
+            }
+            .validate(),
+            Err(AdjustError::InvalidVibrance(_))
+        ));
+        assert!(matches!(
+            Enhancement {
+                sharpen: -1.0,
+                ..valid
+            }
+            .validate(),
+            Err(AdjustError::InvalidSharpen(_))
+        ));
+    }
+}
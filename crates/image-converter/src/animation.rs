@@ -0,0 +1,1104 @@
+use std::fmt;
+use std::io::Cursor;
+
+use image::codecs::gif::{GifDecoder, GifEncoder, Repeat};
+use image::codecs::png::PngDecoder;
+use image::codecs::webp::WebPDecoder;
+use image::{AnimationDecoder, Delay, DynamicImage, Frame, Frames, RgbaImage};
+use serde::Serialize;
+
+use crate::convert::{self, ConvertError};
+use crate::formats::{FormatError, ImageFormat};
+use crate::webp_anim::{self, WebpAnimError};
+
+/// Highest frame rate accepted by [`resample_frames`]. GIF delays have 10 ms
+/// granularity, so anything faster cannot be represented faithfully.
+pub const MAX_FPS: u32 = 100;
+
+/// A full-canvas frame borrowed for encoding, with how long it shows in milliseconds,
+/// so a frame shown several times is stored once.
+pub type FrameRef<'a> = (&'a RgbaImage, u32);
+
+/// Which frame of an animation to extract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameSelection {
+    /// The first frame.
+    First,
+    /// The frame at `frame_count / 2`.
+    Middle,
+    /// A specific zero-based frame index.
+    Index(usize),
+}
+
+impl FrameSelection {
+    /// Parses a frame selector string into a `FrameSelection`.
+    ///
+    /// Accepts `"first"`, `"middle"`, or a zero-based decimal index (e.g. `"3"`).
+    /// An empty string selects the first frame.
+    ///
+    /// Returns an error if the string is not a recognized selector.
+    pub fn from_name(name: &str) -> Result<Self, AnimationError> {
+        match name.trim() {
+            "" | "first" => Ok(Self::First),
+            "middle" => Ok(Self::Middle),
+            other => other
+                .parse()
+                .map(Self::Index)
+                .map_err(|_| AnimationError::UnknownFrameSelection(name.to_owned())),
+        }
+    }
+
+    /// Resolves this selection to a concrete frame index for an animation
+    /// with `frame_count` frames.
+    fn resolve(self, frame_count: usize) -> usize {
+        match self {
+            Self::First => 0,
+            Self::Middle => frame_count / 2,
+            Self::Index(index) => index,
+        }
+    }
+}
+
+/// Counts the frames in an image by walking its container structure.
+///
+/// Only chunk/block headers are read — no pixel data is decompressed. Static images
+/// (including PNGs without an `acTL` chunk and WebPs without `ANMF` chunks) report 1.
+///
+/// # Errors
+///
+/// Returns an `AnimationError::Format` if the input format cannot be detected.
+pub fn frame_count(input: &[u8]) -> Result<usize, AnimationError> {
+    let format = ImageFormat::detect_from_bytes(input).map_err(AnimationError::Format)?;
+    let count = match format {
+        ImageFormat::Gif => count_gif_frames(input),
+        ImageFormat::Png => count_apng_frames(input),
+        ImageFormat::WebP => count_webp_frames(input),
+        ImageFormat::Jpeg
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => None,
+    };
+    Ok(count.unwrap_or(1).max(1))
+}
+
+/// Where a frame is drawn on an animation's canvas, as the container records it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct FrameRect {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// The rectangle the first frame of a GIF, WebP or APNG covers on the canvas, read
+/// from the image descriptor, `ANMF` chunk or `fcTL` chunk. Frames may be smaller than
+/// the canvas (the GIF logical screen), which is what the decoders report as the
+/// image's size. `None` for other formats and still WebPs and PNGs.
+pub fn first_frame_rect(input: &[u8]) -> Option<FrameRect> {
+    match ImageFormat::detect_from_bytes(input).ok()? {
+        ImageFormat::Gif => first_gif_frame(input),
+        ImageFormat::Png => first_apng_frame(input),
+        ImageFormat::WebP => first_webp_frame(input),
+        ImageFormat::Jpeg
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => None,
+    }
+}
+
+/// Returns a lazy iterator over the composited frames of an animated GIF, WebP, or APNG.
+///
+/// Returns `Ok(None)` for static images so callers can fall back to a regular decode.
+/// Frames are decoded on demand, so stopping iteration early skips the remaining frames.
+///
+/// # Errors
+///
+/// Returns an `AnimationError` if the format cannot be detected or the decoder
+/// fails to read the container headers.
+pub fn frames(input: &[u8]) -> Result<Option<Frames<'_>>, AnimationError> {
+    let format = ImageFormat::detect_from_bytes(input).map_err(AnimationError::Format)?;
+    let frames = match format {
+        ImageFormat::Gif => {
+            let decoder = GifDecoder::new(Cursor::new(input)).map_err(AnimationError::Decode)?;
+            Some(decoder.into_frames())
+        }
+        ImageFormat::WebP => {
+            let decoder = WebPDecoder::new(Cursor::new(input)).map_err(AnimationError::Decode)?;
+            decoder.has_animation().then(|| decoder.into_frames())
+        }
+        ImageFormat::Png => {
+            let decoder = PngDecoder::new(Cursor::new(input)).map_err(AnimationError::Decode)?;
+            if decoder.is_apng().map_err(AnimationError::Decode)? {
+                Some(
+                    decoder
+                        .apng()
+                        .map_err(AnimationError::Decode)?
+                        .into_frames(),
+                )
+            } else {
+                None
+            }
+        }
+        ImageFormat::Jpeg
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => None,
+    };
+    Ok(frames)
+}
+
+/// Decodes every frame of an image.
+///
+/// Animated GIF/WebP/APNG inputs yield one full-canvas frame per animation frame with
+/// its original delay. Static images yield a single frame with a zero delay.
+///
+/// # Errors
+///
+/// Returns an `AnimationError::Decode` if any frame fails to decode.
+pub fn decode_frames(input: &[u8]) -> Result<Vec<Frame>, AnimationError> {
+    match frames(input)? {
+        Some(frames) => frames.collect_frames().map_err(AnimationError::Decode),
+        None => {
+            let decoded = image::load_from_memory(input).map_err(AnimationError::Decode)?;
+            Ok(vec![Frame::new(decoded.into_rgba8())])
+        }
+    }
+}
+
+/// Returns the delay of a frame in whole milliseconds (rounded down).
+pub fn frame_delay_ms(frame: &Frame) -> u32 {
+    let (numer, denom) = frame.delay().numer_denom_ms();
+    numer.checked_div(denom).unwrap_or(0)
+}
+
+/// Extracts a single frame of an animation and encodes it as a static image.
+///
+/// Frames after the selected one are never decoded. Frames before it still have to be
+/// decoded because GIF/WebP/APNG frames are composited onto the previous canvas.
+/// Static inputs are treated as a one-frame animation.
+///
+/// # Errors
+///
+/// Returns an `AnimationError` if the input cannot be decoded, the selected index is
+/// past the last frame, or encoding to `target` fails.
+pub fn poster_frame(
+    input: &[u8],
+    selection: FrameSelection,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AnimationError> {
+    let count = frame_count(input)?;
+    let index = selection.resolve(count);
+    if index >= count {
+        return Err(AnimationError::FrameOutOfRange { index, count });
+    }
+
+    let image = match frames(input)? {
+        Some(mut frames) => {
+            let frame = frames
+                .nth(index)
+                .ok_or(AnimationError::FrameOutOfRange { index, count })?
+                .map_err(AnimationError::Decode)?;
+            DynamicImage::ImageRgba8(frame.into_buffer())
+        }
+        None => image::load_from_memory(input).map_err(AnimationError::Decode)?,
+    };
+
+    convert::encode(&image, target, quality).map_err(AnimationError::Convert)
+}
+
+/// Encodes frames as an animated GIF that loops forever.
+///
+/// Each frame keeps its own delay and offset. GIF supports only 1-bit transparency
+/// and a 256-color palette per frame, so colors are quantized by the encoder, at the
+/// speed [`convert::gif_speed`] picks for `quality` (its best without one).
+///
+/// # Errors
+///
+/// Returns an `AnimationError::Encode` if the GIF encoder fails.
+pub fn encode_gif(frames: Vec<Frame>, quality: Option<u8>) -> Result<Vec<u8>, AnimationError> {
+    let mut buf = Vec::new();
+    {
+        let mut encoder =
+            GifEncoder::new_with_speed(&mut buf, quality.map_or(1, convert::gif_speed));
+        encoder
+            .set_repeat(Repeat::Infinite)
+            .map_err(AnimationError::Encode)?;
+        encoder
+            .encode_frames(frames)
+            .map_err(AnimationError::Encode)?;
+    }
+    Ok(buf)
+}
+
+/// Encodes frames as a looping APNG with full 8-bit RGBA color.
+///
+/// Unlike GIF, APNG keeps every frame's full color depth and alpha channel. All frames
+/// must share the first frame's dimensions. Delays longer than 65.535 seconds are
+/// clamped because APNG stores them as 16-bit millisecond fractions. `quality` picks
+/// the compression level as for still PNG output (see
+/// [`convert::png_compression_level`]).
+///
+/// # Errors
+///
+/// Returns an `AnimationError` if there are no frames, frame sizes differ, or the
+/// PNG encoder fails.
+pub fn encode_apng(frames: &[Frame], quality: Option<u8>) -> Result<Vec<u8>, AnimationError> {
+    write_apng(&frame_refs(frames), quality)
+}
+
+/// [`encode_apng`] for borrowed frames.
+fn write_apng(frames: &[FrameRef<'_>], quality: Option<u8>) -> Result<Vec<u8>, AnimationError> {
+    let (first, _) = frames.first().ok_or(AnimationError::NoFrames)?;
+    let (width, height) = first.dimensions();
+    let num_frames = u32::try_from(frames.len()).map_err(|_| AnimationError::TooManyFrames)?;
+
+    let mut buf = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut buf, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        if let Some(quality) = quality {
+            encoder.set_deflate_compression(png::DeflateCompression::Level(
+                convert::png_compression_level(quality),
+            ));
+            encoder.set_filter(png::Filter::Adaptive);
+        }
+        // 0 plays = loop forever.
+        encoder
+            .set_animated(num_frames, 0)
+            .map_err(AnimationError::ApngEncode)?;
+        let mut writer = encoder.write_header().map_err(AnimationError::ApngEncode)?;
+
+        for &(buffer, delay_ms) in frames {
+            if buffer.dimensions() != (width, height) {
+                return Err(AnimationError::FrameSizeMismatch);
+            }
+            let delay_ms = u16::try_from(delay_ms).unwrap_or(u16::MAX);
+            writer
+                .set_frame_delay(delay_ms, 1000)
+                .map_err(AnimationError::ApngEncode)?;
+            writer
+                .set_blend_op(png::BlendOp::Source)
+                .map_err(AnimationError::ApngEncode)?;
+            writer
+                .write_image_data(buffer.as_raw())
+                .map_err(AnimationError::ApngEncode)?;
+        }
+        writer.finish().map_err(AnimationError::ApngEncode)?;
+    }
+    Ok(buf)
+}
+
+/// Builds animation frames from a list of encoded still images and per-frame delays.
+///
+/// Each image may be in any supported input format. `delays_ms` must have one entry
+/// per image.
+///
+/// # Errors
+///
+/// Returns an `AnimationError` if the lists are empty or differ in length, an image
+/// cannot be decoded, or the images have different dimensions.
+pub fn frames_from_images(
+    images: &[Vec<u8>],
+    delays_ms: &[u32],
+) -> Result<Vec<Frame>, AnimationError> {
+    if images.is_empty() {
+        return Err(AnimationError::NoFrames);
+    }
+    if images.len() != delays_ms.len() {
+        return Err(AnimationError::DelayCountMismatch {
+            frames: images.len(),
+            delays: delays_ms.len(),
+        });
+    }
+
+    let mut frames: Vec<Frame> = Vec::with_capacity(images.len());
+    for (bytes, delay_ms) in images.iter().zip(delays_ms) {
+        let buffer = image::load_from_memory(bytes)
+            .map_err(AnimationError::Decode)?
+            .into_rgba8();
+        if let Some(first) = frames.first() {
+            if first.buffer().dimensions() != buffer.dimensions() {
+                return Err(AnimationError::FrameSizeMismatch);
+            }
+        }
+        frames.push(Frame::from_parts(
+            buffer,
+            0,
+            0,
+            Delay::from_numer_denom_ms(*delay_ms, 1),
+        ));
+    }
+    Ok(frames)
+}
+
+/// Encodes frames as an animation in the given container.
+///
+/// `Gif` produces an animated GIF, `Png` an APNG, and `WebP` a lossless animated WebP.
+/// `quality` is used as by [`encode_gif`] and [`encode_apng`]; lossless WebP ignores it.
+///
+/// # Errors
+///
+/// Returns an `AnimationError` if the target cannot hold an animation or the
+/// encoder fails.
+pub fn encode_animation(
+    frames: Vec<Frame>,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AnimationError> {
+    match target {
+        ImageFormat::Gif => encode_gif(frames, quality),
+        ImageFormat::Png
+        | ImageFormat::WebP
+        | ImageFormat::Jpeg
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => encode_frame_refs(&frame_refs(&frames), target, quality),
+    }
+}
+
+/// Like [`encode_animation`], for borrowed full-canvas frames such as
+/// [`resample_frames`] returns. GIF frames are copied one at a time as they are
+/// encoded; APNG and WebP read the borrowed pixels directly.
+///
+/// # Errors
+///
+/// Returns an `AnimationError` if the target cannot hold an animation or the
+/// encoder fails.
+pub fn encode_frame_refs(
+    frames: &[FrameRef<'_>],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AnimationError> {
+    match target {
+        ImageFormat::Gif => {
+            let mut buf = Vec::new();
+            {
+                let mut encoder =
+                    GifEncoder::new_with_speed(&mut buf, quality.map_or(1, convert::gif_speed));
+                encoder
+                    .set_repeat(Repeat::Infinite)
+                    .map_err(AnimationError::Encode)?;
+                encoder
+                    .encode_frames(frames.iter().map(|&(buffer, delay_ms)| {
+                        Frame::from_parts(
+                            buffer.clone(),
+                            0,
+                            0,
+                            Delay::from_numer_denom_ms(delay_ms, 1),
+                        )
+                    }))
+                    .map_err(AnimationError::Encode)?;
+            }
+            Ok(buf)
+        }
+        ImageFormat::Png => write_apng(frames, quality),
+        ImageFormat::WebP => webp_anim::encode_frame_refs(frames)
+            .map_err(|e| AnimationError::WebpEncode(Box::new(e))),
+        ImageFormat::Jpeg
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => Err(AnimationError::UnsupportedTarget(target)),
+    }
+}
+
+/// Borrows every frame's pixels and delay.
+pub fn frame_refs(frames: &[Frame]) -> Vec<FrameRef<'_>> {
+    frames
+        .iter()
+        .map(|frame| (frame.buffer(), frame_delay_ms(frame)))
+        .collect()
+}
+
+/// Resamples an animation to a constant frame rate.
+///
+/// Output frames are spaced `1000 / fps` ms apart (rounded so the total duration is
+/// unchanged) and each shows whichever source frame is visible at its start time.
+/// Lowering the rate merges frames away; raising it repeats frames, which borrow the
+/// source frame rather than copying it. Animations with a single frame or a total
+/// duration of zero are returned unchanged.
+///
+/// `max_bytes` caps the pixels the output shows, at 4 bytes per pixel of every output
+/// frame, as [`ConvertOptions::max_memory_bytes`](convert::ConvertOptions) caps a
+/// conversion: an hour-long GIF resampled to 100 fps would otherwise have the encoder
+/// process 360,000 frames.
+///
+/// # Errors
+///
+/// Returns `AnimationError::InvalidFps` if `fps` is zero or above [`MAX_FPS`],
+/// `AnimationError::TooManyFrames` if the output would have more than `u32::MAX`
+/// frames, or `AnimationError::Convert` with `ConvertError::LimitExceeded` if it would
+/// show more than `max_bytes`.
+pub fn resample_frames(
+    frames: &[Frame],
+    fps: u32,
+    max_bytes: Option<u64>,
+) -> Result<Vec<FrameRef<'_>>, AnimationError> {
+    if fps == 0 || fps > MAX_FPS {
+        return Err(AnimationError::InvalidFps(fps));
+    }
+
+    let delays: Vec<u64> = frames
+        .iter()
+        .map(|frame| u64::from(frame_delay_ms(frame)))
+        .collect();
+    let total_ms: u64 = delays.iter().sum();
+    if frames.len() < 2 || total_ms == 0 {
+        return Ok(frame_refs(frames));
+    }
+
+    let fps = u64::from(fps);
+    let tick_ms = |tick: u64| (tick * 1000 + fps / 2) / fps;
+
+    // Ticks start every 1000 / fps ms (rounded), so there are at most this many.
+    let max_ticks = total_ms.saturating_mul(fps) / 1000 + 1;
+    if max_ticks > u64::from(u32::MAX) {
+        return Err(AnimationError::TooManyFrames);
+    }
+    if let (Some(limit_bytes), Some(first)) = (max_bytes, frames.first()) {
+        let (width, height) = first.buffer().dimensions();
+        let required_bytes = max_ticks.saturating_mul(u64::from(width) * u64::from(height) * 4);
+        if required_bytes > limit_bytes {
+            return Err(AnimationError::Convert(ConvertError::LimitExceeded {
+                required_bytes,
+                limit_bytes,
+            }));
+        }
+    }
+
+    let mut resampled = Vec::with_capacity(usize::try_from(max_ticks).unwrap_or(0));
+    let mut source = 0;
+    let mut source_end = delays.first().copied().unwrap_or(0);
+    let mut tick = 0;
+    loop {
+        let start = tick_ms(tick);
+        if start >= total_ms {
+            break;
+        }
+        while start >= source_end {
+            source += 1;
+            source_end += delays.get(source).copied().unwrap_or(total_ms);
+        }
+        let end = tick_ms(tick + 1).min(total_ms);
+        let delay_ms = u32::try_from(end - start).unwrap_or(u32::MAX);
+        let frame = frames.get(source).ok_or(AnimationError::NoFrames)?;
+        resampled.push((frame.buffer(), delay_ms));
+        tick += 1;
+    }
+    Ok(resampled)
+}
+
+/// Counts GIF image descriptors by skipping over extension and image data sub-blocks.
+fn count_gif_frames(input: &[u8]) -> Option<usize> {
+    // 6-byte signature + 7-byte logical screen descriptor.
+    let flags = *input.get(10)?;
+    let mut pos = 13 + color_table_len(flags);
+    let mut count = 0;
+
+    loop {
+        match *input.get(pos)? {
+            // Extension: introducer, label, then data sub-blocks.
+            0x21 => pos = skip_gif_sub_blocks(input, pos + 2)?,
+            // Image descriptor: 10-byte header, optional local color table,
+            // LZW minimum code size, then data sub-blocks.
+            0x2C => {
+                let local_flags = *input.get(pos + 9)?;
+                pos = skip_gif_sub_blocks(input, pos + 10 + color_table_len(local_flags) + 1)?;
+                count += 1;
+            }
+            0x3B => return Some(count),
+            _ => return None,
+        }
+    }
+}
+
+fn first_gif_frame(input: &[u8]) -> Option<FrameRect> {
+    let flags = *input.get(10)?;
+    let mut pos = 13 + color_table_len(flags);
+    loop {
+        match *input.get(pos)? {
+            0x21 => pos = skip_gif_sub_blocks(input, pos + 2)?,
+            // Image descriptor: separator, then left, top, width and height as
+            // little-endian u16s.
+            0x2C => {
+                let field = |at: usize| {
+                    let bytes = input.get(pos + at..pos + at + 2)?.try_into().ok()?;
+                    Some(u32::from(u16::from_le_bytes(bytes)))
+                };
+                return Some(FrameRect {
+                    left: field(1)?,
+                    top: field(3)?,
+                    width: field(5)?,
+                    height: field(7)?,
+                });
+            }
+            _ => return None,
+        }
+    }
+}
+
+/// Returns the byte length of a GIF color table described by a packed flags byte.
+fn color_table_len(flags: u8) -> usize {
+    if flags & 0x80 == 0 {
+        0
+    } else {
+        3 * (1 << ((flags & 0x07) + 1))
+    }
+}
+
+/// Skips a chain of GIF data sub-blocks starting at `pos`, returning the position
+/// after the zero-length terminator.
+fn skip_gif_sub_blocks(input: &[u8], mut pos: usize) -> Option<usize> {
+    loop {
+        let len = usize::from(*input.get(pos)?);
+        pos += 1;
+        if len == 0 {
+            return Some(pos);
+        }
+        pos += len;
+    }
+}
+
+/// Reads the frame count from an APNG `acTL` chunk. Returns `None` for plain PNGs.
+fn count_apng_frames(input: &[u8]) -> Option<usize> {
+    let mut pos = 8;
+    while let Some(header) = input.get(pos..pos + 8) {
+        let (len_bytes, chunk_type) = header.split_at(4);
+        let len = usize::try_from(u32::from_be_bytes(len_bytes.try_into().ok()?)).ok()?;
+        match chunk_type {
+            b"acTL" => {
+                let num_frames = input.get(pos + 8..pos + 12)?;
+                return usize::try_from(u32::from_be_bytes(num_frames.try_into().ok()?)).ok();
+            }
+            // acTL must precede the image data, so stop looking once IDAT is reached.
+            b"IDAT" | b"IEND" => return None,
+            _ => pos = pos.checked_add(len)?.checked_add(12)?,
+        }
+    }
+    None
+}
+
+/// Reads the first `fcTL` chunk of an APNG: sequence number, width, height, x and y
+/// offsets as big-endian u32s.
+fn first_apng_frame(input: &[u8]) -> Option<FrameRect> {
+    let mut pos = 8;
+    while let Some(header) = input.get(pos..pos + 8) {
+        let (len_bytes, chunk_type) = header.split_at(4);
+        let len = usize::try_from(u32::from_be_bytes(len_bytes.try_into().ok()?)).ok()?;
+        if chunk_type == b"fcTL" {
+            let field = |at: usize| {
+                let bytes = input.get(pos + 8 + at..pos + 12 + at)?.try_into().ok()?;
+                Some(u32::from_be_bytes(bytes))
+            };
+            return Some(FrameRect {
+                left: field(12)?,
+                top: field(16)?,
+                width: field(4)?,
+                height: field(8)?,
+            });
+        }
+        if chunk_type == b"IEND" {
+            return None;
+        }
+        pos = pos.checked_add(len)?.checked_add(12)?;
+    }
+    None
+}
+
+/// Reads the first `ANMF` chunk of a WebP: x and y offsets halved, then width and
+/// height minus one, as little-endian 24-bit values.
+fn first_webp_frame(input: &[u8]) -> Option<FrameRect> {
+    let mut pos = 12;
+    while let Some(header) = input.get(pos..pos + 8) {
+        let (fourcc, len_bytes) = header.split_at(4);
+        let len = usize::try_from(u32::from_le_bytes(len_bytes.try_into().ok()?)).ok()?;
+        if fourcc == b"ANMF" {
+            let field = |at: usize| {
+                let &[a, b, c] = input.get(pos + 8 + at..pos + 11 + at)? else {
+                    return None;
+                };
+                Some(u32::from_le_bytes([a, b, c, 0]))
+            };
+            return Some(FrameRect {
+                left: field(0)? * 2,
+                top: field(3)? * 2,
+                width: field(6)? + 1,
+                height: field(9)? + 1,
+            });
+        }
+        pos = pos.checked_add(len)?.checked_add(8 + (len & 1))?;
+    }
+    None
+}
+
+/// Counts `ANMF` chunks in a WebP RIFF container. Returns `None` for still WebPs.
+fn count_webp_frames(input: &[u8]) -> Option<usize> {
+    // 12-byte RIFF header ("RIFF", size, "WEBP").
+    let mut pos = 12;
+    let mut count = 0;
+    while let Some(header) = input.get(pos..pos + 8) {
+        let (fourcc, len_bytes) = header.split_at(4);
+        let len = usize::try_from(u32::from_le_bytes(len_bytes.try_into().ok()?)).ok()?;
+        if fourcc == b"ANMF" {
+            count += 1;
+        }
+        // Chunks are padded to an even length.
+        pos = pos.checked_add(len)?.checked_add(8 + (len & 1))?;
+    }
+    (count > 0).then_some(count)
+}
+
+/// Errors that can occur while reading or extracting animation frames.
+#[derive(Debug)]
+pub enum AnimationError {
+    /// The input format could not be detected.
+    Format(FormatError),
+    /// Failed to decode the input or one of its frames.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+    /// Failed to encode an animation.
+    Encode(image::ImageError),
+    /// The PNG encoder failed while writing an APNG.
+    ApngEncode(png::EncodingError),
+    /// There were no frames to encode.
+    NoFrames,
+    /// The animation has more frames than the container can store.
+    TooManyFrames,
+    /// A frame's dimensions differ from the first frame.
+    FrameSizeMismatch,
+    /// The number of delays does not match the number of frames.
+    DelayCountMismatch { frames: usize, delays: usize },
+    /// The animated WebP encoder failed.
+    WebpEncode(Box<WebpAnimError>),
+    /// The target format cannot hold an animation.
+    UnsupportedTarget(ImageFormat),
+    /// The requested frame rate is zero or above [`MAX_FPS`].
+    InvalidFps(u32),
+    /// The frame selector string was not recognized.
+    UnknownFrameSelection(String),
+    /// The requested frame index is past the last frame.
+    FrameOutOfRange { index: usize, count: usize },
+}
+
+impl fmt::Display for AnimationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(e) => write!(f, "{e}"),
+            Self::Decode(e) => write!(f, "Failed to decode animation: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+            Self::Encode(e) => write!(f, "Failed to encode animation: {e}"),
+            Self::ApngEncode(e) => write!(f, "Failed to encode APNG: {e}"),
+            Self::NoFrames => write!(f, "Animation has no frames"),
+            Self::TooManyFrames => write!(f, "Animation has too many frames"),
+            Self::FrameSizeMismatch => write!(f, "All frames must have the same dimensions"),
+            Self::DelayCountMismatch { frames, delays } => {
+                write!(f, "Expected {frames} frame delays, got {delays}")
+            }
+            Self::WebpEncode(e) => write!(f, "{e}"),
+            Self::UnsupportedTarget(format) => {
+                write!(
+                    f,
+                    "Format \"{format}\" cannot hold an animation (use gif, png, or webp)"
+                )
+            }
+            Self::InvalidFps(fps) => {
+                write!(f, "Frame rate must be between 1 and {MAX_FPS}, got {fps}")
+            }
+            Self::UnknownFrameSelection(name) => {
+                write!(
+                    f,
+                    "Unknown frame selection: \"{name}\" (expected \"first\", \"middle\", or an index)"
+                )
+            }
+            Self::FrameOutOfRange { index, count } => {
+                write!(
+                    f,
+                    "Frame {index} is out of range (animation has {count} frames)"
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for AnimationError {}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::gif::GifEncoder;
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    // ===== Fixture Helpers =====
+
+    fn solid_frame(color: [u8; 4], delay_ms: u32) -> Frame {
+        let img = RgbaImage::from_pixel(8, 6, Rgba(color));
+        Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+    }
+
+    fn make_animated_gif(colors: &[[u8; 4]]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut buf);
+            encoder
+                .encode_frames(colors.iter().map(|c| solid_frame(*c, 100)))
+                .unwrap();
+        }
+        buf
+    }
+
+    fn make_png(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbaImage::from_pixel(width, height, Rgba([10, 20, 30, 255]));
+        let mut buf = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    fn center_pixel(encoded: &[u8]) -> Rgba<u8> {
+        let img = image::load_from_memory(encoded).unwrap().into_rgba8();
+        *img.get_pixel(img.width() / 2, img.height() / 2)
+    }
+
+    // ===== FrameSelection Tests =====
+
+    #[test]
+    fn frame_selection_from_name() {
+        assert_eq!(
+            FrameSelection::from_name("").unwrap(),
+            FrameSelection::First
+        );
+        assert_eq!(
+            FrameSelection::from_name("first").unwrap(),
+            FrameSelection::First
+        );
+        assert_eq!(
+            FrameSelection::from_name("middle").unwrap(),
+            FrameSelection::Middle
+        );
+        assert_eq!(
+            FrameSelection::from_name("4").unwrap(),
+            FrameSelection::Index(4)
+        );
+        assert!(matches!(
+            FrameSelection::from_name("last"),
+            Err(AnimationError::UnknownFrameSelection(_))
+        ));
+    }
+
+    // ===== frame_count Tests =====
+
+    #[test]
+    fn frame_count_gif() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE]);
+        assert_eq!(frame_count(&gif).unwrap(), 3);
+    }
+
+    #[test]
+    fn frame_count_static_png() {
+        let png = make_png(4, 4);
+        assert_eq!(frame_count(&png).unwrap(), 1);
+    }
+
+    #[test]
+    fn frame_count_empty_input() {
+        assert!(matches!(frame_count(&[]), Err(AnimationError::Format(_))));
+    }
+
+    // ===== decode_frames Tests =====
+
+    #[test]
+    fn decode_frames_preserves_delays() {
+        let gif = make_animated_gif(&[RED, GREEN]);
+        let frames = decode_frames(&gif).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frame_delay_ms(&frames[0]), 100);
+    }
+
+    #[test]
+    fn decode_frames_static_is_single_frame() {
+        let png = make_png(5, 3);
+        let frames = decode_frames(&png).unwrap();
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].buffer().dimensions(), (5, 3));
+    }
+
+    // ===== poster_frame Tests =====
+
+    #[test]
+    fn poster_frame_first() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE]);
+        let png = poster_frame(&gif, FrameSelection::First, ImageFormat::Png, None).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&png).unwrap(),
+            ImageFormat::Png
+        );
+        assert_eq!(center_pixel(&png), Rgba(RED));
+    }
+
+    #[test]
+    fn poster_frame_middle() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE]);
+        let png = poster_frame(&gif, FrameSelection::Middle, ImageFormat::Png, None).unwrap();
+        assert_eq!(center_pixel(&png), Rgba(GREEN));
+    }
+
+    #[test]
+    fn poster_frame_index() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE]);
+        let png = poster_frame(&gif, FrameSelection::Index(2), ImageFormat::Png, None).unwrap();
+        assert_eq!(center_pixel(&png), Rgba(BLUE));
+    }
+
+    #[test]
+    fn poster_frame_index_out_of_range() {
+        let gif = make_animated_gif(&[RED, GREEN]);
+        let result = poster_frame(&gif, FrameSelection::Index(5), ImageFormat::Png, None);
+        assert!(matches!(
+            result,
+            Err(AnimationError::FrameOutOfRange { index: 5, count: 2 })
+        ));
+    }
+
+    #[test]
+    fn poster_frame_static_input() {
+        let png = make_png(6, 4);
+        let jpeg = poster_frame(&png, FrameSelection::Middle, ImageFormat::Jpeg, Some(90)).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&jpeg).unwrap(),
+            ImageFormat::Jpeg
+        );
+        let dims = convert::dimensions(&jpeg).unwrap();
+        assert_eq!((dims.width, dims.height), (6, 4));
+    }
+
+    // ===== encode_gif Tests =====
+
+    #[test]
+    fn encode_gif_round_trip() {
+        let gif = encode_gif(vec![solid_frame(RED, 40), solid_frame(BLUE, 80)], None).unwrap();
+        let frames = decode_frames(&gif).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frame_delay_ms(&frames[0]), 40);
+        assert_eq!(frame_delay_ms(&frames[1]), 80);
+        assert_eq!(*frames[1].buffer().get_pixel(0, 0), Rgba(BLUE));
+    }
+
+    // ===== encode_apng Tests =====
+
+    #[test]
+    fn encode_apng_round_trip() {
+        let translucent = [0, 128, 255, 100];
+        let apng =
+            encode_apng(&[solid_frame(RED, 30), solid_frame(translucent, 250)], None).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&apng).unwrap(),
+            ImageFormat::Png
+        );
+        assert_eq!(frame_count(&apng).unwrap(), 2);
+
+        let frames = decode_frames(&apng).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frame_delay_ms(&frames[0]), 30);
+        assert_eq!(frame_delay_ms(&frames[1]), 250);
+        assert_eq!(
+            *frames[1].buffer().get_pixel(0, 0),
+            Rgba(translucent),
+            "APNG should keep full color and partial alpha"
+        );
+    }
+
+    #[test]
+    fn encode_apng_from_gif() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE]);
+        let apng = encode_apng(&decode_frames(&gif).unwrap(), None).unwrap();
+        let png = poster_frame(&apng, FrameSelection::Middle, ImageFormat::Png, None).unwrap();
+        assert_eq!(center_pixel(&png), Rgba(GREEN));
+    }
+
+    #[test]
+    fn encode_apng_no_frames() {
+        assert!(matches!(
+            encode_apng(&[], None),
+            Err(AnimationError::NoFrames)
+        ));
+    }
+
+    #[test]
+    fn frames_from_images_builds_frames() {
+        let images = vec![make_png(6, 4), make_png(6, 4)];
+        let frames = frames_from_images(&images, &[10, 20]).unwrap();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frame_delay_ms(&frames[1]), 20);
+    }
+
+    #[test]
+    fn frames_from_images_rejects_mismatches() {
+        let images = vec![make_png(6, 4), make_png(5, 4)];
+        assert!(matches!(
+            frames_from_images(&images, &[10, 20]),
+            Err(AnimationError::FrameSizeMismatch)
+        ));
+        assert!(matches!(
+            frames_from_images(&images, &[10]),
+            Err(AnimationError::DelayCountMismatch {
+                frames: 2,
+                delays: 1
+            })
+        ));
+    }
+
+    // ===== resample_frames Tests =====
+
+    fn frames_with_delays(delays: &[u32]) -> Vec<Frame> {
+        [RED, GREEN, BLUE]
+            .iter()
+            .cycle()
+            .zip(delays)
+            .map(|(color, delay)| solid_frame(*color, *delay))
+            .collect()
+    }
+
+    fn total_ms(frames: &[FrameRef<'_>]) -> u32 {
+        frames.iter().map(|&(_, delay_ms)| delay_ms).sum()
+    }
+
+    #[test]
+    fn resample_down_merges_frames() {
+        // 30 fps source, ~33 ms per frame, 1 second long.
+        let source = frames_with_delays(&[33, 34, 33].repeat(10));
+        let resampled = resample_frames(&source, 10, None).unwrap();
+        assert_eq!(resampled.len(), 10);
+        assert!(resampled.iter().all(|&(_, delay_ms)| delay_ms == 100));
+        assert_eq!(total_ms(&resampled), 1000);
+    }
+
+    #[test]
+    fn resample_up_duplicates_frames() {
+        let source = frames_with_delays(&[200, 200]);
+        let resampled = resample_frames(&source, 20, None).unwrap();
+        assert_eq!(resampled.len(), 8);
+        assert_eq!(total_ms(&resampled), 400);
+        assert_eq!(*resampled[3].0.get_pixel(0, 0), Rgba(RED));
+        assert_eq!(*resampled[4].0.get_pixel(0, 0), Rgba(GREEN));
+        // Repeats borrow the source frame instead of copying it.
+        assert!(std::ptr::eq(resampled[0].0, resampled[3].0));
+        assert!(std::ptr::eq(resampled[4].0, source[1].buffer()));
+    }
+
+    #[test]
+    fn resample_preserves_duration_with_uneven_ticks() {
+        let source = frames_with_delays(&[100, 100, 100]);
+        let resampled = resample_frames(&source, 7, None).unwrap();
+        assert_eq!(total_ms(&resampled), 300);
+    }
+
+    #[test]
+    fn resample_picks_visible_frame() {
+        // RED for 50 ms, GREEN for 250 ms, BLUE for 100 ms; sample at 10 fps.
+        let source = frames_with_delays(&[50, 250, 100]);
+        let resampled = resample_frames(&source, 10, None).unwrap();
+        let colors: Vec<Rgba<u8>> = resampled
+            .iter()
+            .map(|(buffer, _)| *buffer.get_pixel(0, 0))
+            .collect();
+        assert_eq!(
+            colors,
+            vec![Rgba(RED), Rgba(GREEN), Rgba(GREEN), Rgba(BLUE)]
+        );
+    }
+
+    #[test]
+    fn resample_invalid_fps() {
+        assert!(matches!(
+            resample_frames(&frames_with_delays(&[10, 10]), 0, None),
+            Err(AnimationError::InvalidFps(0))
+        ));
+        assert!(matches!(
+            resample_frames(&frames_with_delays(&[10, 10]), 101, None),
+            Err(AnimationError::InvalidFps(101))
+        ));
+    }
+
+    #[test]
+    fn resample_zero_duration_unchanged() {
+        let source = frames_with_delays(&[0, 0, 0]);
+        let resampled = resample_frames(&source, 10, None).unwrap();
+        assert_eq!(resampled.len(), 3);
+    }
+
+    #[test]
+    fn resample_output_is_capped() {
+        // 8 x 6 frames, 192 bytes each; 400 ms at 20 fps is at most 9 ticks.
+        let source = frames_with_delays(&[200, 200]);
+        assert_eq!(resample_frames(&source, 20, Some(1728)).unwrap().len(), 8);
+        assert!(matches!(
+            resample_frames(&source, 20, Some(1727)),
+            Err(AnimationError::Convert(ConvertError::LimitExceeded {
+                required_bytes: 1728,
+                limit_bytes: 1727
+            }))
+        ));
+
+        let endless = frames_with_delays(&[u32::MAX; 20]);
+        assert!(matches!(
+            resample_frames(&endless, MAX_FPS, None),
+            Err(AnimationError::TooManyFrames)
+        ));
+    }
+
+    #[test]
+    fn resampled_frames_encode_to_every_target() {
+        let source = frames_with_delays(&[200, 200]);
+        let resampled = resample_frames(&source, 20, None).unwrap();
+        for target in [ImageFormat::Gif, ImageFormat::Png, ImageFormat::WebP] {
+            let encoded = encode_frame_refs(&resampled, target, None).unwrap();
+            let frames = decode_frames(&encoded).unwrap();
+            assert_eq!(frames.len(), 8, "{target}");
+            assert_eq!(*frames[4].buffer().get_pixel(0, 0), Rgba(GREEN), "{target}");
+        }
+    }
+
+    // ===== encode_animation Tests =====
+
+    #[test]
+    fn encode_animation_targets() {
+        for target in [ImageFormat::Gif, ImageFormat::Png, ImageFormat::WebP] {
+            let encoded = encode_animation(frames_with_delays(&[20, 20]), target, None).unwrap();
+            assert_eq!(ImageFormat::detect_from_bytes(&encoded).unwrap(), target);
+            assert_eq!(
+                frame_count(&encoded).unwrap(),
+                2,
+                "{target} should keep both frames"
+            );
+        }
+        assert!(matches!(
+            encode_animation(frames_with_delays(&[20]), ImageFormat::Jpeg, None),
+            Err(AnimationError::UnsupportedTarget(ImageFormat::Jpeg))
+        ));
+    }
+
+    #[test]
+    fn poster_frame_webp_target_unsupported() {
+        let gif = make_animated_gif(&[RED]);
+        let result = poster_frame(&gif, FrameSelection::First, ImageFormat::WebP, None);
+        assert!(matches!(result, Err(AnimationError::Convert(_))));
+    }
+}
@@ -0,0 +1,228 @@
+use std::fmt;
+use std::fmt::Write;
+use std::io::Cursor;
+
+use image::{imageops, ImageReader, RgbaImage};
+
+use crate::color;
+use crate::region::{self, RegionError};
+
+/// Characters from darkest to brightest, for light text on a dark terminal.
+pub const DEFAULT_CHARSET: &str = " .:-=+*#%@";
+
+/// Widest preview, in characters.
+pub const MAX_COLUMNS: u32 = 1000;
+
+/// Pixels decoded across each character cell. Resampling blurs edges over a pixel or
+/// two, so cells need many to keep their averages sharp; an 80-column preview still
+/// decodes a large photo at a fraction of its size.
+const SAMPLES_PER_CELL: u64 = 16;
+
+/// Terminal cells are roughly twice as tall as they are wide, so each character
+/// covers a `1 × CELL_ASPECT` block of the downsampled image.
+const CELL_ASPECT: f64 = 2.0;
+
+/// Settings for [`to_ascii`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AsciiOptions {
+    /// Width of the preview in characters, 1 to [`MAX_COLUMNS`]. The row count follows
+    /// from the aspect ratio.
+    pub columns: u32,
+    /// Characters from darkest to brightest; at least two. Empty selects
+    /// [`DEFAULT_CHARSET`].
+    pub charset: String,
+    /// Color each character with its cell's average color using 24-bit ANSI escapes.
+    pub ansi: bool,
+}
+
+impl Default for AsciiOptions {
+    fn default() -> Self {
+        Self {
+            columns: 80,
+            charset: String::new(),
+            ansi: false,
+        }
+    }
+}
+
+/// Decodes `input` and renders it as text, one line per row of character cells.
+///
+/// Each cell's average luminance picks a character from the charset. Transparent
+/// areas are treated as black, the usual terminal background. With `ansi`, every
+/// line ends with a reset sequence so colors never leak past the preview.
+///
+/// # Errors
+///
+/// Returns an `AsciiError` if the options are out of range or the input cannot be
+/// decoded.
+pub fn to_ascii(input: &[u8], options: &AsciiOptions) -> Result<String, AsciiError> {
+    if options.columns == 0 || options.columns > MAX_COLUMNS {
+        return Err(AsciiError::InvalidColumns(options.columns));
+    }
+    let charset: Vec<char> = if options.charset.is_empty() {
+        DEFAULT_CHARSET.chars().collect()
+    } else {
+        options.charset.chars().collect()
+    };
+    if charset.len() < 2 {
+        return Err(AsciiError::CharsetTooShort);
+    }
+
+    let (width, height) = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|e| RegionError::Decode(image::ImageError::IoError(e)))
+        .and_then(|reader| reader.into_dimensions().map_err(RegionError::Decode))
+        .map_err(AsciiError::Decode)?;
+    let columns = options.columns.min(width.max(1));
+    let rows = (f64::from(height) * f64::from(columns) / f64::from(width.max(1)) / CELL_ASPECT)
+        .round()
+        .max(1.0);
+    // Safe: at least 1, and at most `height * MAX_COLUMNS / 2`, which fits in u32.
+    #[allow(clippy::as_conversions)]
+    let rows = rows as u32;
+    // Only as much detail as the cells need is decoded.
+    let long_edge = (u64::from(columns) * SAMPLES_PER_CELL * u64::from(width.max(height)))
+        .div_ceil(u64::from(width.max(1)));
+    let max_edge = u32::try_from(long_edge).unwrap_or(u32::MAX);
+    let decoded = region::decode_scaled(input, max_edge).map_err(AsciiError::Decode)?;
+    let img = RgbaImage::from_raw(decoded.width, decoded.height, decoded.rgba).unwrap_or_default();
+    let cells = imageops::thumbnail(&img, columns, rows);
+
+    let last = charset.len() - 1;
+    let mut out = String::new();
+    for row in cells.rows() {
+        for px in row {
+            let [r, g, b, a] = px.0;
+            let visible = u32::from(color::luminance(px.0)) * u32::from(a) / 255;
+            let index = usize::try_from(visible).unwrap_or(0) * last / 255;
+            let ch = charset.get(index).copied().unwrap_or(' ');
+            if options.ansi {
+                // Writing to a String cannot fail.
+                let _ = write!(out, "\x1b[38;2;{r};{g};{b}m{ch}");
+            } else {
+                out.push(ch);
+            }
+        }
+        if options.ansi {
+            out.push_str("\x1b[0m");
+        }
+        out.push('\n');
+    }
+    Ok(out)
+}
+
+/// Errors that can occur while rendering a text preview.
+#[derive(Debug)]
+pub enum AsciiError {
+    /// The column count was 0 or above [`MAX_COLUMNS`].
+    InvalidColumns(u32),
+    /// The charset had fewer than two characters.
+    CharsetTooShort,
+    /// Failed to decode the input image.
+    Decode(RegionError),
+}
+
+impl fmt::Display for AsciiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidColumns(columns) => write!(
+                f,
+                "Columns must be between 1 and {MAX_COLUMNS}, got {columns}"
+            ),
+            Self::CharsetTooShort => write!(f, "Charset must have at least two characters"),
+            Self::Decode(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for AsciiError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn encode(img: RgbaImage) -> Vec<u8> {
+        let mut buf = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    fn options(columns: u32, charset: &str) -> AsciiOptions {
+        AsciiOptions {
+            columns,
+            charset: charset.to_owned(),
+            ansi: false,
+        }
+    }
+
+    #[test]
+    fn maps_luminance_to_charset() {
+        // Left half black, right half white.
+        let img = RgbaImage::from_fn(40, 40, |x, _| {
+            Rgba(if x < 20 { [0, 0, 0, 255] } else { [255; 4] })
+        });
+        let text = to_ascii(&encode(img), &options(4, " .#")).unwrap();
+        assert_eq!(text, "  ##\n  ##\n");
+    }
+
+    #[test]
+    fn rows_follow_aspect_ratio() {
+        let img = RgbaImage::from_pixel(100, 100, Rgba([128, 128, 128, 255]));
+        let text = to_ascii(&encode(img), &options(20, "")).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), 10);
+        assert!(lines.iter().all(|line| line.chars().count() == 20));
+        assert!(lines.iter().all(|line| line.chars().all(|c| c == '=')));
+    }
+
+    #[test]
+    fn never_upsamples_past_the_source() {
+        let img = RgbaImage::from_pixel(3, 2, Rgba([255; 4]));
+        let text = to_ascii(&encode(img), &options(80, "")).unwrap();
+        assert_eq!(text, "@@@\n");
+    }
+
+    #[test]
+    fn transparent_pixels_are_background() {
+        let img = RgbaImage::from_pixel(4, 2, Rgba([255, 255, 255, 0]));
+        assert_eq!(to_ascii(&encode(img), &options(4, "")).unwrap(), "    \n");
+    }
+
+    #[test]
+    fn ansi_colors_each_cell_and_resets_lines() {
+        let img = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 255]));
+        let ansi = AsciiOptions {
+            ansi: true,
+            ..options(2, " #")
+        };
+        let text = to_ascii(&encode(img), &ansi).unwrap();
+        assert_eq!(text, "\x1b[38;2;255;0;0m \x1b[38;2;255;0;0m \x1b[0m\n");
+    }
+
+    #[test]
+    fn rejects_bad_options() {
+        let png = encode(RgbaImage::from_pixel(2, 2, Rgba([0; 4])));
+        assert!(matches!(
+            to_ascii(&png, &options(0, "")),
+            Err(AsciiError::InvalidColumns(0))
+        ));
+        assert!(matches!(
+            to_ascii(&png, &options(1001, "")),
+            Err(AsciiError::InvalidColumns(1001))
+        ));
+        assert!(matches!(
+            to_ascii(&png, &options(10, "#")),
+            Err(AsciiError::CharsetTooShort)
+        ));
+        assert!(matches!(
+            to_ascii(&[0, 1], &options(10, "")),
+            Err(AsciiError::Decode(_))
+        ));
+    }
+}
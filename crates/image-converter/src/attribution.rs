@@ -0,0 +1,363 @@
+//! "Export with attribution": the artist and copyright notice from a file's EXIF data,
+//! drawn as a caption in a corner of the image and kept in the output's EXIF data.
+
+use std::fmt;
+use std::io::Cursor;
+
+use crate::composite;
+use crate::convert::{self, ConvertError};
+use crate::exif_write::{self, ExifEdits, ExifWriteError};
+use crate::formats::ImageFormat;
+use crate::text;
+use image::{DynamicImage, ImageDecoder, ImageReader, Rgba, RgbaImage};
+
+/// Smallest caption height, in pixels: the built-in font's own size.
+const MIN_TEXT_HEIGHT: u32 = 7;
+
+/// A corner of the image to place the caption in.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Corner {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    #[default]
+    BottomRight,
+}
+
+impl Corner {
+    /// Parses a corner name: `"top_left"`, `"top_right"`, `"bottom_left"` or
+    /// `"bottom_right"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `AttributionError::UnknownCorner` for any other name.
+    pub fn from_name(name: &str) -> Result<Self, AttributionError> {
+        match name {
+            "top_left" => Ok(Self::TopLeft),
+            "top_right" => Ok(Self::TopRight),
+            "bottom_left" => Ok(Self::BottomLeft),
+            "bottom_right" => Ok(Self::BottomRight),
+            _ => Err(AttributionError::UnknownCorner(name.to_owned())),
+        }
+    }
+}
+
+/// Who made an image and who holds its copyright, as its EXIF data records them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Attribution {
+    /// The `Artist` tag.
+    pub artist: Option<String>,
+    /// The `Copyright` tag. When it holds separate photographer and editor notices,
+    /// they are joined with `", "`.
+    pub copyright: Option<String>,
+}
+
+impl Attribution {
+    /// The caption drawn for this attribution: the copyright notice, marked with
+    /// `(c)` if it doesn't say so already and preceded by the artist unless it names
+    /// them, e.g. `"Jane Doe - (c) 2026 Example Agency"`. `None` when both are unset.
+    pub fn caption(&self) -> Option<String> {
+        let copyright = self.copyright.as_deref().map(|notice| {
+            let notice = notice.replace('\u{a9}', "(c)");
+            let lower = notice.to_ascii_lowercase();
+            if lower.starts_with("(c)") || lower.starts_with("copyright") {
+                notice
+            } else {
+                format!("(c) {notice}")
+            }
+        });
+        match (self.artist.as_deref(), copyright) {
+            (Some(artist), Some(notice)) if !notice.contains(artist) => {
+                Some(format!("{artist} - {notice}"))
+            }
+            (_, Some(notice)) => Some(notice),
+            (artist, None) => artist.map(str::to_owned),
+        }
+    }
+}
+
+/// How [`draw_caption`] lays out the caption.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaptionOptions {
+    pub corner: Corner,
+    /// Height of the text in pixels; by default 1/30 of the image's shorter side, and
+    /// never less than 7.
+    pub text_height: Option<u32>,
+    /// Distance from the image's edges to the caption box; by default the text height.
+    pub margin: Option<u32>,
+    pub color: [u8; 4],
+    /// A box drawn behind the text to keep it readable; `None` draws bare text.
+    pub background: Option<[u8; 4]>,
+    /// Caption for files with neither an artist nor a copyright notice; without it,
+    /// [`export_with_attribution`] rejects such files.
+    pub fallback: Option<String>,
+}
+
+impl Default for CaptionOptions {
+    fn default() -> Self {
+        Self {
+            corner: Corner::default(),
+            text_height: None,
+            margin: None,
+            color: [u8::MAX; 4],
+            background: Some([0, 0, 0, 0x80]),
+            fallback: None,
+        }
+    }
+}
+
+/// Reads the artist and copyright notice from the EXIF data of `input`. Both are
+/// `None` for files without EXIF data.
+///
+/// # Errors
+///
+/// Returns `AttributionError::Decode` if the file's headers cannot be read.
+pub fn read(input: &[u8]) -> Result<Attribution, AttributionError> {
+    let mut decoder = open(input)?;
+    Ok(read_from(&mut decoder))
+}
+
+/// Draws `caption` in a corner of `img` as [`CaptionOptions`] describe, over a
+/// background box unless it is `None`. Captions too wide for the image are cut short
+/// with `...`; nothing is drawn when not even that fits.
+pub fn draw_caption(img: &mut RgbaImage, caption: &str, options: &CaptionOptions) {
+    let (width, height) = img.dimensions();
+    let text_height = options
+        .text_height
+        .unwrap_or(width.min(height) / 30)
+        .max(MIN_TEXT_HEIGHT);
+    let margin = options.margin.unwrap_or(text_height);
+    let padding = if options.background.is_some() {
+        text_height.div_ceil(3)
+    } else {
+        0
+    };
+    let inset = margin.saturating_add(padding).saturating_mul(2);
+    let caption = text::fit(caption, text_height, width.saturating_sub(inset));
+    let (text_width, _) = text::measure(&caption, text_height);
+    if text_width == 0 {
+        return;
+    }
+
+    let box_width = text_width + 2 * padding;
+    let box_height = text_height + 2 * padding;
+    let left = match options.corner {
+        Corner::TopLeft | Corner::BottomLeft => i64::from(margin),
+        Corner::TopRight | Corner::BottomRight => {
+            i64::from(width) - i64::from(margin) - i64::from(box_width)
+        }
+    };
+    let top = match options.corner {
+        Corner::TopLeft | Corner::TopRight => i64::from(margin),
+        Corner::BottomLeft | Corner::BottomRight => {
+            i64::from(height) - i64::from(margin) - i64::from(box_height)
+        }
+    };
+    if let Some(background) = options.background {
+        let backdrop = RgbaImage::from_pixel(box_width, box_height, Rgba(background));
+        composite::overlay(img, &backdrop, left, top, 1.0);
+    }
+    let (x, y) = (left + i64::from(padding), top + i64::from(padding));
+    text::draw_text(img, &caption, x, y, text_height, options.color);
+}
+
+/// Decodes `input` upright (applying its EXIF orientation), captions it with the
+/// artist and copyright from its EXIF data using [`draw_caption`], and encodes the
+/// result as `target`. JPEG and PNG output also keeps the artist and copyright in its
+/// EXIF data; other metadata is not carried over.
+///
+/// # Errors
+///
+/// Returns `AttributionError::NoAttribution` if the file names neither an artist nor
+/// a copyright holder and `options.fallback` is unset, `AttributionError::Decode` or
+/// `AttributionError::Convert` if the image can't be decoded or encoded, or
+/// `AttributionError::Exif` if the fields can't be written into the output.
+pub fn export_with_attribution(
+    input: &[u8],
+    options: &CaptionOptions,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, AttributionError> {
+    let mut decoder = open(input)?;
+    let attribution = read_from(&mut decoder);
+    let caption = attribution
+        .caption()
+        .or_else(|| options.fallback.clone())
+        .ok_or(AttributionError::NoAttribution)?;
+    let orientation = decoder.orientation().map_err(AttributionError::Decode)?;
+    let mut img = DynamicImage::from_decoder(decoder).map_err(AttributionError::Decode)?;
+    img.apply_orientation(orientation);
+
+    let mut rgba = img.into_rgba8();
+    draw_caption(&mut rgba, &caption, options);
+    let output = convert::encode(&DynamicImage::ImageRgba8(rgba), target, quality)
+        .map_err(AttributionError::Convert)?;
+
+    let edits = ExifEdits {
+        artist: attribution.artist,
+        copyright: attribution.copyright,
+        ..ExifEdits::default()
+    };
+    if edits.is_empty() || !matches!(target, ImageFormat::Jpeg | ImageFormat::Png) {
+        return Ok(output);
+    }
+    exif_write::set_exif(&output, &edits).map_err(AttributionError::Exif)
+}
+
+fn open(input: &[u8]) -> Result<impl ImageDecoder + '_, AttributionError> {
+    ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|e| AttributionError::Decode(image::ImageError::IoError(e)))?
+        .into_decoder()
+        .map_err(AttributionError::Decode)
+}
+
+fn read_from(decoder: &mut impl ImageDecoder) -> Attribution {
+    let Some(exif) = decoder
+        .exif_metadata()
+        .ok()
+        .flatten()
+        .and_then(|raw| exif::Reader::new().read_raw(raw).ok())
+    else {
+        return Attribution::default();
+    };
+    let text = |tag| {
+        let field = exif.get_field(tag, exif::In::PRIMARY)?;
+        let exif::Value::Ascii(ref strings) = field.value else {
+            return None;
+        };
+        let parts: Vec<String> = strings
+            .iter()
+            .map(|bytes| String::from_utf8_lossy(bytes).trim().to_owned())
+            .filter(|part| !part.is_empty())
+            .collect();
+        (!parts.is_empty()).then(|| parts.join(", "))
+    };
+    Attribution {
+        artist: text(exif::Tag::Artist),
+        copyright: text(exif::Tag::Copyright),
+    }
+}
+
+/// Errors that can occur while exporting with attribution.
+#[derive(Debug)]
+pub enum AttributionError {
+    /// The corner name was not recognized.
+    UnknownCorner(String),
+    /// The file names neither an artist nor a copyright holder, and no fallback
+    /// caption was given.
+    NoAttribution,
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+    /// Failed to write the artist and copyright into the output.
+    Exif(ExifWriteError),
+}
+
+impl fmt::Display for AttributionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownCorner(name) => write!(
+                f,
+                "Unknown corner: \"{name}\" (expected top_left, top_right, bottom_left or bottom_right)"
+            ),
+            Self::NoAttribution => write!(
+                f,
+                "The image has no artist or copyright in its EXIF data and no fallback caption was given"
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+            Self::Exif(e) => write!(f, "Failed to write EXIF: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AttributionError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribution(artist: Option<&str>, copyright: Option<&str>) -> Attribution {
+        Attribution {
+            artist: artist.map(str::to_owned),
+            copyright: copyright.map(str::to_owned),
+        }
+    }
+
+    #[test]
+    fn captions_combine_artist_and_copyright() {
+        let caption = |artist, copyright| attribution(artist, copyright).caption();
+        assert_eq!(
+            caption(Some("Jane Doe"), Some("2026 Example Agency")).as_deref(),
+            Some("Jane Doe - (c) 2026 Example Agency")
+        );
+        assert_eq!(
+            caption(Some("Jane Doe"), Some("\u{a9} 2026 Jane Doe")).as_deref(),
+            Some("(c) 2026 Jane Doe")
+        );
+        assert_eq!(
+            caption(None, Some("Copyright 2026")).as_deref(),
+            Some("Copyright 2026")
+        );
+        assert_eq!(caption(Some("Jane"), None).as_deref(), Some("Jane"));
+        assert_eq!(caption(None, None), None);
+    }
+
+    #[test]
+    fn captions_are_placed_in_the_chosen_corner() {
+        let drawn = |corner| {
+            let mut img = RgbaImage::from_pixel(120, 60, Rgba([255; 4]));
+            let options = CaptionOptions {
+                corner,
+                margin: Some(4),
+                background: Some([0, 0, 0, 255]),
+                ..CaptionOptions::default()
+            };
+            draw_caption(&mut img, "(c) JD", &options);
+            img
+        };
+        let dark = |img: &RgbaImage, x, y| img.get_pixel(x, y).0[0] < 128;
+
+        let img = drawn(Corner::BottomRight);
+        assert!(dark(&img, 115, 55) && !dark(&img, 4, 4));
+        let img = drawn(Corner::TopLeft);
+        assert!(dark(&img, 4, 4) && !dark(&img, 115, 55));
+        assert!(!dark(&img, 3, 3));
+        assert!(matches!(
+            Corner::from_name("middle"),
+            Err(AttributionError::UnknownCorner(_))
+        ));
+    }
+
+    #[test]
+    fn exports_keep_the_attribution() {
+        let mut jpeg = Vec::new();
+        DynamicImage::ImageRgb8(image::RgbImage::from_pixel(90, 60, image::Rgb([40; 3])))
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let options = CaptionOptions::default();
+        assert!(matches!(
+            export_with_attribution(&jpeg, &options, ImageFormat::Png, None),
+            Err(AttributionError::NoAttribution)
+        ));
+
+        let edits = ExifEdits {
+            artist: Some("Jane Doe".into()),
+            copyright: Some("2026 Example Agency".into()),
+            ..ExifEdits::default()
+        };
+        let jpeg = exif_write::set_exif(&jpeg, &edits).unwrap();
+        let png = export_with_attribution(&jpeg, &options, ImageFormat::Png, None).unwrap();
+        assert_eq!(
+            read(&png).unwrap(),
+            attribution(Some("Jane Doe"), Some("2026 Example Agency"))
+        );
+
+        // The caption's light text sits in the bottom-right box.
+        let img = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert!((0..60).any(|y| (45..90).any(|x| img.get_pixel(x, y).0[0] > 200)));
+        assert!(img.get_pixel(2, 2).0[0] < 60);
+    }
+}
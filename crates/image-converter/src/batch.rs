@@ -0,0 +1,399 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::formats::{self, ImageFormat};
+use crate::metadata;
+
+/// ZIP version needed to extract stored entries (2.0).
+const ZIP_VERSION: u16 = 20;
+
+/// General-purpose flag marking entry names as UTF-8.
+const ZIP_UTF8: u16 = 1 << 11;
+
+/// MS-DOS date of 1980-01-01, the earliest ZIP can store, so archives don't depend on
+/// when they were built.
+const ZIP_DATE: u16 = (1 << 5) | 1;
+
+/// One file of a batch, converted by whichever worker its shard is sent to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WorkUnit {
+    /// Position of the file in the batch's input list.
+    pub index: u32,
+    /// Name of the converted file in the archive, unique within the batch.
+    pub name: String,
+    /// Relative cost of converting the file: its encoded size plus its estimated
+    /// decoded size.
+    pub cost: u64,
+}
+
+/// The units one worker converts, in input order.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Shard {
+    pub units: Vec<WorkUnit>,
+    /// Sum of the units' costs.
+    pub cost: u64,
+}
+
+/// A batch split into independent shards of roughly equal cost. Plans are plain data,
+/// so they can be posted to workers and handed back to [`merge_zip`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BatchPlan {
+    pub shards: Vec<Shard>,
+}
+
+impl BatchPlan {
+    /// Every unit of every shard, in input order.
+    pub fn units(&self) -> Vec<&WorkUnit> {
+        let mut units: Vec<&WorkUnit> = self.shards.iter().flat_map(|s| &s.units).collect();
+        units.sort_by_key(|unit| unit.index);
+        units
+    }
+}
+
+/// Splits a batch converting `inputs` to `target` into at most `workers` shards that
+/// can be converted concurrently, one per WASM worker instance.
+///
+/// Costs come from [`metadata::estimate_decode_memory`], which only reads headers;
+/// files whose headers can't be read cost their encoded size, and fail in their worker.
+/// Units are dealt largest first to the shard with the least work so far, which keeps
+/// every shard within one file of the others. Each unit is named with
+/// [`formats::suggest_filename`], with ` (2)`, ` (3)`, ... added to names already taken
+/// (compared case-insensitively, as most file systems do).
+///
+/// # Errors
+///
+/// Returns a `BatchError` if there are no inputs, `names` doesn't have one name per
+/// input, or `workers` is zero.
+pub fn plan_batch(
+    inputs: &[Vec<u8>],
+    names: &[String],
+    target: ImageFormat,
+    workers: u32,
+) -> Result<BatchPlan, BatchError> {
+    if inputs.is_empty() {
+        return Err(BatchError::NoInputs);
+    }
+    if names.len() != inputs.len() {
+        return Err(BatchError::NamesMismatch {
+            inputs: inputs.len(),
+            names: names.len(),
+        });
+    }
+    if workers == 0 {
+        return Err(BatchError::NoWorkers);
+    }
+
+    let mut taken = HashSet::new();
+    let mut units = Vec::with_capacity(inputs.len());
+    for ((input, name), index) in inputs.iter().zip(names).zip(0u32..) {
+        let encoded = u64::try_from(input.len()).unwrap_or(u64::MAX);
+        let decoded = metadata::estimate_decode_memory(input).map_or(0, |memory| memory.bytes);
+        units.push(WorkUnit {
+            index,
+            name: unique_name(&formats::suggest_filename(name, target), &mut taken),
+            cost: encoded.saturating_add(decoded),
+        });
+    }
+
+    let count = usize::try_from(workers)
+        .unwrap_or(usize::MAX)
+        .min(units.len());
+    let mut shards = vec![Shard::default(); count];
+    units.sort_by_key(|unit| (std::cmp::Reverse(unit.cost), unit.index));
+    for unit in units {
+        if let Some(shard) = shards.iter_mut().min_by_key(|shard| shard.cost) {
+            shard.cost = shard.cost.saturating_add(unit.cost);
+            shard.units.push(unit);
+        }
+    }
+    for shard in &mut shards {
+        shard.units.sort_by_key(|unit| unit.index);
+    }
+    Ok(BatchPlan { shards })
+}
+
+/// `name`, or `name` with ` (2)`, ` (3)`, ... before its extension if it is already in
+/// `taken` (which holds lowercased names). Records the result in `taken`.
+pub fn unique_name(name: &str, taken: &mut HashSet<String>) -> String {
+    let (stem, extension) = match name.rsplit_once('.') {
+        Some((stem, extension)) => (stem, format!(".{extension}")),
+        None => (name, String::new()),
+    };
+    let mut candidate = name.to_owned();
+    let mut copy = 1;
+    while !taken.insert(candidate.to_lowercase()) {
+        copy += 1;
+        candidate = format!("{stem} ({copy}){extension}");
+    }
+    candidate
+}
+
+/// Merges the workers' results into one ZIP archive: `outputs[i]` is the converted
+/// file of the unit with index `i`, stored under the unit's name.
+///
+/// Entries are stored uncompressed, since converted images are already compressed,
+/// in input order and with a fixed timestamp, so the same outputs always give the same
+/// archive.
+///
+/// # Errors
+///
+/// Returns a `BatchError` if `outputs` doesn't have one file per unit of `plan`, or the
+/// archive would need ZIP64 (over 65535 files or 4 GiB).
+pub fn merge_zip(plan: &BatchPlan, outputs: &[Vec<u8>]) -> Result<Vec<u8>, BatchError> {
+    let units = plan.units();
+    let in_order = units
+        .iter()
+        .zip(0u32..)
+        .all(|(unit, index)| unit.index == index);
+    if units.len() != outputs.len() || !in_order {
+        return Err(BatchError::OutputsMismatch {
+            units: units.len(),
+            outputs: outputs.len(),
+        });
+    }
+    let files: Vec<(&str, &[u8])> = units
+        .iter()
+        .zip(outputs)
+        .map(|(unit, data)| (unit.name.as_str(), data.as_slice()))
+        .collect();
+    write_zip(&files)
+}
+
+/// Writes `files` (name and contents) into a ZIP archive, uncompressed, in the given
+/// order and with a fixed timestamp. Names should be unique.
+///
+/// # Errors
+///
+/// Returns `BatchError::ZipTooLarge` if the archive would need ZIP64 (over 65535 files
+/// or 4 GiB).
+pub fn write_zip(files: &[(&str, &[u8])]) -> Result<Vec<u8>, BatchError> {
+    let count = u16::try_from(files.len()).map_err(|_| BatchError::ZipTooLarge)?;
+
+    let mut out = Vec::new();
+    let mut directory = Vec::new();
+    for &(name, data) in files {
+        let offset = u32::try_from(out.len()).map_err(|_| BatchError::ZipTooLarge)?;
+        let size = u32::try_from(data.len()).map_err(|_| BatchError::ZipTooLarge)?;
+        let name = name.as_bytes();
+        let name_len = u16::try_from(name.len()).map_err(|_| BatchError::ZipTooLarge)?;
+        let crc = crc32fast::hash(data);
+
+        // Fields shared by the local header and the central directory entry, from
+        // "version needed" through the name length.
+        let mut common = Vec::with_capacity(24);
+        for field in [ZIP_VERSION, ZIP_UTF8, 0, 0, ZIP_DATE] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        for field in [crc, size, size] {
+            common.extend_from_slice(&field.to_le_bytes());
+        }
+        common.extend_from_slice(&name_len.to_le_bytes());
+
+        out.extend_from_slice(&0x0403_4b50u32.to_le_bytes());
+        out.extend_from_slice(&common);
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(name);
+        out.extend_from_slice(data);
+
+        directory.extend_from_slice(&0x0201_4b50u32.to_le_bytes());
+        directory.extend_from_slice(&ZIP_VERSION.to_le_bytes());
+        directory.extend_from_slice(&common);
+        // Extra field and comment lengths, disk number, internal and external attributes.
+        directory.extend_from_slice(&[0; 12]);
+        directory.extend_from_slice(&offset.to_le_bytes());
+        directory.extend_from_slice(name);
+    }
+
+    let directory_offset = u32::try_from(out.len()).map_err(|_| BatchError::ZipTooLarge)?;
+    let directory_size = u32::try_from(directory.len()).map_err(|_| BatchError::ZipTooLarge)?;
+    out.extend_from_slice(&directory);
+    out.extend_from_slice(&0x0605_4b50u32.to_le_bytes());
+    out.extend_from_slice(&[0; 4]);
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&count.to_le_bytes());
+    out.extend_from_slice(&directory_size.to_le_bytes());
+    out.extend_from_slice(&directory_offset.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    Ok(out)
+}
+
+/// Errors that can occur while planning or merging a batch.
+#[derive(Debug)]
+pub enum BatchError {
+    /// No inputs were given.
+    NoInputs,
+    /// `names` didn't have one name per input.
+    NamesMismatch { inputs: usize, names: usize },
+    /// The worker count was zero.
+    NoWorkers,
+    /// The outputs didn't match the plan's units one for one.
+    OutputsMismatch { units: usize, outputs: usize },
+    /// The archive would need ZIP64 extensions.
+    ZipTooLarge,
+}
+
+impl fmt::Display for BatchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoInputs => write!(f, "At least one input is required"),
+            Self::NamesMismatch { inputs, names } => {
+                write!(f, "Got {names} names for {inputs} inputs")
+            }
+            Self::NoWorkers => write!(f, "At least one worker is required"),
+            Self::OutputsMismatch { units, outputs } => write!(
+                f,
+                "Got {outputs} outputs for a plan of {units} files; pass one per file, in input order"
+            ),
+            Self::ZipTooLarge => write!(
+                f,
+                "The archive would exceed 65535 files or 4 GiB; split the batch"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for BatchError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, RgbaImage};
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(width, height))
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|&name| name.to_owned()).collect()
+    }
+
+    #[test]
+    fn shards_balance_cost_and_keep_input_order() {
+        let inputs = vec![
+            png(100, 100),
+            png(10, 10),
+            png(60, 60),
+            png(70, 70),
+            png(5, 5),
+        ];
+        let plan = plan_batch(
+            &inputs,
+            &names(&["a.png", "b.png", "c.png", "d.png", "e.png"]),
+            ImageFormat::Jpeg,
+            2,
+        )
+        .unwrap();
+
+        assert_eq!(plan.shards.len(), 2);
+        let indices: Vec<Vec<u32>> = plan
+            .shards
+            .iter()
+            .map(|shard| shard.units.iter().map(|unit| unit.index).collect())
+            .collect();
+        // 100² alone balances 70² + 60² + 10² + 5².
+        assert_eq!(indices, [vec![0], vec![1, 2, 3, 4]]);
+        for shard in &plan.shards {
+            assert_eq!(shard.cost, shard.units.iter().map(|u| u.cost).sum::<u64>());
+        }
+        assert_eq!(plan.units()[2].name, "c.jpg");
+
+        let few = plan_batch(&inputs[..1], &names(&["a.png"]), ImageFormat::Png, 8).unwrap();
+        assert_eq!(few.shards.len(), 1);
+    }
+
+    #[test]
+    fn names_are_unique() {
+        let inputs = vec![png(1, 1), png(1, 1), png(1, 1), vec![1, 2, 3]];
+        let plan = plan_batch(
+            &inputs,
+            &names(&["photo.png", "photo.gif", "Photo.jpg", ""]),
+            ImageFormat::WebP,
+            1,
+        )
+        .unwrap();
+        let names: Vec<&str> = plan.units().iter().map(|u| u.name.as_str()).collect();
+        assert_eq!(
+            names,
+            [
+                "photo.webp",
+                "photo (2).webp",
+                "Photo (3).webp",
+                "image.webp"
+            ]
+        );
+        // Unreadable inputs still get a unit, costed by their size.
+        assert_eq!(plan.units()[3].cost, 3);
+    }
+
+    #[test]
+    fn merge_writes_a_stored_zip() {
+        let inputs = vec![png(1, 1), png(1, 1)];
+        let plan = plan_batch(&inputs, &names(&["a.png", "b.png"]), ImageFormat::Png, 2).unwrap();
+        let outputs = vec![b"first".to_vec(), b"second!".to_vec()];
+        let zip = merge_zip(&plan, &outputs).unwrap();
+
+        let u16_at = |pos: usize| u16::from_le_bytes([zip[pos], zip[pos + 1]]);
+        let u32_at = |pos: usize| u32::from_le_bytes(zip[pos..pos + 4].try_into().unwrap());
+        let end = zip.len() - 22;
+        assert_eq!(u32_at(end), 0x0605_4b50);
+        assert_eq!(u16_at(end + 10), 2);
+
+        let mut entry = usize::try_from(u32_at(end + 16)).unwrap();
+        for (name, data) in ["a.png", "b.png"].iter().zip(&outputs) {
+            assert_eq!(u32_at(entry), 0x0201_4b50);
+            assert_eq!(u32_at(entry + 16), crc32fast::hash(data));
+            let name_len = usize::from(u16_at(entry + 28));
+            assert_eq!(&zip[entry + 46..entry + 46 + name_len], name.as_bytes());
+
+            let local = usize::try_from(u32_at(entry + 42)).unwrap();
+            assert_eq!(u32_at(local), 0x0403_4b50);
+            let start = local + 30 + name_len;
+            assert_eq!(&zip[start..start + data.len()], data.as_slice());
+            entry += 46 + name_len;
+        }
+
+        assert!(matches!(
+            merge_zip(&plan, &outputs[..1]),
+            Err(BatchError::OutputsMismatch {
+                units: 2,
+                outputs: 1
+            })
+        ));
+    }
+
+    #[test]
+    fn plans_round_trip_through_serde() {
+        let plan = plan_batch(&[png(2, 2)], &names(&["x.bmp"]), ImageFormat::Png, 1).unwrap();
+        let json = serde_json::to_string(&plan).unwrap();
+        assert_eq!(serde_json::from_str::<BatchPlan>(&json).unwrap(), plan);
+    }
+
+    #[test]
+    fn rejects_bad_requests() {
+        let one = vec![png(1, 1)];
+        assert!(matches!(
+            plan_batch(&[], &[], ImageFormat::Png, 1),
+            Err(BatchError::NoInputs)
+        ));
+        assert!(matches!(
+            plan_batch(&one, &[], ImageFormat::Png, 1),
+            Err(BatchError::NamesMismatch {
+                inputs: 1,
+                names: 0
+            })
+        ));
+        assert!(matches!(
+            plan_batch(&one, &names(&["a"]), ImageFormat::Png, 0),
+            Err(BatchError::NoWorkers)
+        ));
+    }
+}
@@ -0,0 +1,307 @@
+use std::fmt;
+
+use image::{GrayImage, Luma, RgbaImage};
+
+use crate::color;
+use crate::png_optimize::{self, ChunkPolicy, PngOptimizeError};
+use crate::quantize::{self, Dither};
+
+/// Default gray level at or above which a pixel becomes white.
+pub const DEFAULT_THRESHOLD: u8 = 128;
+
+/// File format for 1-bit output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BilevelFormat {
+    /// Grayscale PNG at bit depth 1.
+    #[default]
+    Png,
+    /// Binary portable bitmap (`P4`), the format most thermal-printer and e-paper
+    /// tooling accepts directly.
+    Pbm,
+}
+
+impl BilevelFormat {
+    /// Parses an output format.
+    ///
+    /// Accepts `"png"` or `"pbm"`. An empty string selects `"png"`.
+    ///
+    /// Returns an error if the string is not a recognized format.
+    pub fn from_name(name: &str) -> Result<Self, BilevelError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "png" => Ok(Self::Png),
+            "pbm" => Ok(Self::Pbm),
+            _ => Err(BilevelError::UnknownFormat(name.to_owned())),
+        }
+    }
+}
+
+/// Settings for [`to_bilevel`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BilevelOptions {
+    /// How gray levels are spread between black and white.
+    pub dither: Dither,
+    /// Gray level (0–255) at or above which a pixel becomes white. Raising it darkens
+    /// the output.
+    pub threshold: u8,
+    pub format: BilevelFormat,
+}
+
+impl Default for BilevelOptions {
+    fn default() -> Self {
+        Self {
+            dither: Dither::default(),
+            threshold: DEFAULT_THRESHOLD,
+            format: BilevelFormat::default(),
+        }
+    }
+}
+
+/// Decodes `input`, reduces it to black and white, and encodes it as a 1-bit PNG or
+/// PBM for thermal printers and e-paper displays.
+///
+/// # Errors
+///
+/// Returns a `BilevelError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn to_bilevel(input: &[u8], options: &BilevelOptions) -> Result<Vec<u8>, BilevelError> {
+    let img = image::load_from_memory(input)
+        .map_err(BilevelError::Decode)?
+        .into_rgba8();
+    let bilevel = bilevel(&img, options.dither, options.threshold);
+    match options.format {
+        BilevelFormat::Png => encode_png(&bilevel),
+        BilevelFormat::Pbm => Ok(encode_pbm(&bilevel)),
+    }
+}
+
+/// Reduces an image to pure black (0) and white (255).
+///
+/// Transparent areas are composited over white, like ink on paper, and each pixel's
+/// luminance is compared with `threshold`. Floyd–Steinberg carries the rounding error
+/// to neighbouring pixels; ordered dithering shifts the threshold by a Bayer pattern.
+pub fn bilevel(img: &RgbaImage, dither: Dither, threshold: u8) -> GrayImage {
+    let threshold = f64::from(threshold);
+    let gray = |x: u32, y: u32| {
+        let px = img.get_pixel(x, y).0;
+        let alpha = f64::from(px[3]) / 255.0;
+        f64::from(color::luminance(px)) * alpha + 255.0 * (1.0 - alpha)
+    };
+    let level = |value: f64| if value >= threshold { u8::MAX } else { 0 };
+
+    match dither {
+        Dither::None => {
+            GrayImage::from_fn(img.width(), img.height(), |x, y| Luma([level(gray(x, y))]))
+        }
+        Dither::Ordered => GrayImage::from_fn(img.width(), img.height(), |x, y| {
+            Luma([level(gray(x, y) + quantize::bayer_offset(x, y) * 255.0)])
+        }),
+        Dither::FloydSteinberg => {
+            let width = usize::try_from(img.width()).unwrap_or(0);
+            // One slot of padding on each side so neighbours never fall off the row.
+            let mut current = vec![0.0; width + 2];
+            let mut next = vec![0.0; width + 2];
+            let mut out = GrayImage::new(img.width(), img.height());
+            for y in 0..img.height() {
+                for (slot, x) in (1..).zip(0..img.width()) {
+                    let value = gray(x, y) + current.get(slot).copied().unwrap_or(0.0);
+                    let chosen = level(value);
+                    out.put_pixel(x, y, Luma([chosen]));
+
+                    let error = value - f64::from(chosen);
+                    diffuse(&mut current, slot + 1, error * 7.0 / 16.0);
+                    diffuse(&mut next, slot - 1, error * 3.0 / 16.0);
+                    diffuse(&mut next, slot, error * 5.0 / 16.0);
+                    diffuse(&mut next, slot + 1, error / 16.0);
+                }
+                std::mem::swap(&mut current, &mut next);
+                next.fill(0.0);
+            }
+            out
+        }
+    }
+}
+
+fn diffuse(row: &mut [f64], slot: usize, amount: f64) {
+    if let Some(cell) = row.get_mut(slot) {
+        *cell += amount;
+    }
+}
+
+/// Packs each row to bytes, most significant bit first, with `set` deciding which
+/// pixels are 1 bits. Rows are padded to a whole byte.
+fn pack_rows(img: &GrayImage, set: impl Fn(u8) -> bool) -> Vec<u8> {
+    let width = usize::try_from(img.width()).unwrap_or(0);
+    let mut packed = Vec::with_capacity(width.div_ceil(8) * img.rows().len());
+    for row in img.rows() {
+        let pixels: Vec<u8> = row.map(|p| p.0[0]).collect();
+        for group in pixels.chunks(8) {
+            let byte = group
+                .iter()
+                .zip((0..8u8).rev())
+                .filter(|(&value, _)| set(value))
+                .fold(0u8, |byte, (_, bit)| byte | 1 << bit);
+            packed.push(byte);
+        }
+    }
+    packed
+}
+
+/// Encodes a black-and-white image as an optimized 1-bit grayscale PNG.
+///
+/// # Errors
+///
+/// Returns an error if the PNG encoder fails.
+pub fn encode_png(img: &GrayImage) -> Result<Vec<u8>, BilevelError> {
+    let mut buf = Vec::new();
+    {
+        let mut encoder = png::Encoder::new(&mut buf, img.width(), img.height());
+        encoder.set_color(png::ColorType::Grayscale);
+        encoder.set_depth(png::BitDepth::One);
+        let mut writer = encoder.write_header().map_err(BilevelError::Encode)?;
+        writer
+            .write_image_data(&pack_rows(img, |value| value >= 128))
+            .map_err(BilevelError::Encode)?;
+        writer.finish().map_err(BilevelError::Encode)?;
+    }
+    Ok(png_optimize::optimize_png(&buf, 2, ChunkPolicy::StripAll)
+        .map_err(BilevelError::Optimize)?
+        .data)
+}
+
+/// Encodes a black-and-white image as a binary PBM (`P4`), where 1 bits are black.
+pub fn encode_pbm(img: &GrayImage) -> Vec<u8> {
+    let mut out = format!("P4\n{} {}\n", img.width(), img.height()).into_bytes();
+    out.extend(pack_rows(img, |value| value < 128));
+    out
+}
+
+/// Errors that can occur while producing 1-bit output.
+#[derive(Debug)]
+pub enum BilevelError {
+    /// The output format name was not recognized.
+    UnknownFormat(String),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// The PNG encoder failed.
+    Encode(png::EncodingError),
+    /// The 1-bit PNG could not be recompressed.
+    Optimize(PngOptimizeError),
+}
+
+impl fmt::Display for BilevelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFormat(name) => {
+                write!(f, "Unknown 1-bit format \"{name}\" (expected png or pbm)")
+            }
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Encode(e) => write!(f, "Failed to encode PNG: {e}"),
+            Self::Optimize(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BilevelError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, Rgba};
+
+    use super::*;
+
+    fn gray_ramp(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, _| {
+            let v = u8::try_from(x * 255 / (width - 1)).unwrap();
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    fn white_fraction(img: &GrayImage) -> f64 {
+        let white = img.pixels().filter(|p| p.0[0] == 255).count();
+        f64::from(u32::try_from(white).unwrap()) / f64::from(img.width() * img.height())
+    }
+
+    #[test]
+    fn output_is_pure_black_and_white() {
+        for dither in [Dither::None, Dither::FloydSteinberg, Dither::Ordered] {
+            let out = bilevel(&gray_ramp(64, 8), dither, DEFAULT_THRESHOLD);
+            assert!(
+                out.pixels().all(|p| p.0[0] == 0 || p.0[0] == 255),
+                "{dither:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn dithering_keeps_mid_gray_tone() {
+        let gray = RgbaImage::from_pixel(32, 32, Rgba([64, 64, 64, 255]));
+        let plain = bilevel(&gray, Dither::None, DEFAULT_THRESHOLD);
+        assert_eq!(white_fraction(&plain), 0.0);
+        for dither in [Dither::FloydSteinberg, Dither::Ordered] {
+            let fraction = white_fraction(&bilevel(&gray, dither, DEFAULT_THRESHOLD));
+            assert!((fraction - 0.25).abs() < 0.03, "{dither:?}: {fraction}");
+        }
+    }
+
+    #[test]
+    fn threshold_and_transparency() {
+        let img = RgbaImage::from_fn(3, 1, |x, _| match x {
+            0 => Rgba([100, 100, 100, 255]),
+            1 => Rgba([0, 0, 0, 0]),
+            _ => Rgba([200, 200, 200, 255]),
+        });
+        let row = |threshold| {
+            let out = bilevel(&img, Dither::None, threshold);
+            [0, 1, 2].map(|x| out.get_pixel(x, 0).0[0])
+        };
+        assert_eq!(row(128), [0, 255, 255]);
+        assert_eq!(row(90), [255, 255, 255]);
+        assert_eq!(row(210), [0, 255, 0]);
+    }
+
+    #[test]
+    fn png_output_is_1bit() {
+        let mut input = Vec::new();
+        DynamicImage::ImageRgba8(gray_ramp(19, 5))
+            .write_to(&mut Cursor::new(&mut input), image::ImageFormat::Png)
+            .unwrap();
+        let options = BilevelOptions {
+            dither: Dither::None,
+            ..BilevelOptions::default()
+        };
+        let png = to_bilevel(&input, &options).unwrap();
+        let reader = png::Decoder::new(Cursor::new(&png)).read_info().unwrap();
+        assert_eq!(reader.info().bit_depth, png::BitDepth::One);
+        assert_eq!(reader.info().color_type, png::ColorType::Grayscale);
+
+        let decoded = image::load_from_memory(&png).unwrap().into_luma8();
+        assert_eq!(
+            decoded,
+            bilevel(&gray_ramp(19, 5), Dither::None, DEFAULT_THRESHOLD)
+        );
+    }
+
+    #[test]
+    fn pbm_packs_black_as_set_bits() {
+        let img = GrayImage::from_fn(10, 2, |x, y| Luma([if x == y { 0 } else { 255 }]));
+        let pbm = encode_pbm(&img);
+        let header = b"P4\n10 2\n";
+        assert_eq!(&pbm[..header.len()], header);
+        assert_eq!(&pbm[header.len()..], &[0b1000_0000, 0, 0b0100_0000, 0]);
+    }
+
+    #[test]
+    fn format_from_name() {
+        assert_eq!(BilevelFormat::from_name("").unwrap(), BilevelFormat::Png);
+        assert_eq!(
+            BilevelFormat::from_name(" PBM").unwrap(),
+            BilevelFormat::Pbm
+        );
+        assert!(matches!(
+            BilevelFormat::from_name("bmp"),
+            Err(BilevelError::UnknownFormat(_))
+        ));
+    }
+}
@@ -0,0 +1,378 @@
+use std::fmt;
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+use serde::Serialize;
+
+use crate::color::{self, ColorError};
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::MAX_SIDE;
+
+/// How the margins added by [`extend`] are filled.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FillMode {
+    /// A solid RGBA color.
+    Color([u8; 4]),
+    /// The image reflected at its edges, edge pixels included (`cba|abc`), repeating
+    /// back and forth for margins wider than the image.
+    Mirror,
+    /// The edge pixels stretched outward.
+    Repeat,
+}
+
+impl FillMode {
+    /// Parses `"mirror"`, `"repeat"` or a hex color such as `"#ffffff"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CanvasError::InvalidMode` if the name is neither a mode nor a color.
+    pub fn from_name(name: &str) -> Result<Self, CanvasError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "mirror" => Ok(Self::Mirror),
+            "repeat" => Ok(Self::Repeat),
+            _ => color::parse_color(name)
+                .map(Self::Color)
+                .map_err(CanvasError::InvalidMode),
+        }
+    }
+
+    /// The source coordinate a margin pixel at `offset` (relative to the image, so
+    /// negative or past `size` in the margins) takes its color from.
+    fn source(self, offset: i64, size: i64) -> i64 {
+        match self {
+            Self::Color(_) | Self::Repeat => offset.clamp(0, size - 1),
+            Self::Mirror => {
+                let folded = offset.rem_euclid(2 * size);
+                if folded < size {
+                    folded
+                } else {
+                    2 * size - 1 - folded
+                }
+            }
+        }
+    }
+}
+
+/// Pixels added to (or, for [`trim`], removed from) each side of an image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct Margins {
+    pub left: u32,
+    pub right: u32,
+    pub top: u32,
+    pub bottom: u32,
+}
+
+/// Adds `margins` around `img`, filled according to `mode`.
+///
+/// # Errors
+///
+/// Returns `CanvasError::TooLarge` if a side of the extended image would exceed
+/// [`MAX_SIDE`].
+pub fn extend(img: &RgbaImage, margins: Margins, mode: FillMode) -> Result<RgbaImage, CanvasError> {
+    let side = |size: u32, before: u32, after: u32| {
+        size.checked_add(before)
+            .and_then(|side| side.checked_add(after))
+            .filter(|&side| side <= MAX_SIDE)
+            .ok_or(CanvasError::TooLarge)
+    };
+    let width = side(img.width(), margins.left, margins.right)?;
+    let height = side(img.height(), margins.top, margins.bottom)?;
+    let (src_width, src_height) = (i64::from(img.width()), i64::from(img.height()));
+    let (left, top) = (i64::from(margins.left), i64::from(margins.top));
+
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let (sx, sy) = (i64::from(x) - left, i64::from(y) - top);
+        let inside = (0..src_width).contains(&sx) && (0..src_height).contains(&sy);
+        match mode {
+            FillMode::Color(fill) if !inside => Rgba(fill),
+            _ => {
+                let sx = mode.source(sx, src_width);
+                let sy = mode.source(sy, src_height);
+                u32::try_from(sx)
+                    .ok()
+                    .zip(u32::try_from(sy).ok())
+                    .and_then(|(sx, sy)| img.get_pixel_checked(sx, sy))
+                    .copied()
+                    .unwrap_or(Rgba([0; 4]))
+            }
+        }
+    }))
+}
+
+/// Decodes `input`, adds `margins` filled according to `mode`, and encodes the result
+/// as `target`, e.g. to add print bleed or room for a caption.
+///
+/// # Errors
+///
+/// Returns a `CanvasError` if the input cannot be decoded, the result would be too
+/// large, or the output cannot be encoded.
+pub fn extend_canvas(
+    input: &[u8],
+    margins: Margins,
+    mode: FillMode,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, CanvasError> {
+    let img = image::load_from_memory(input)
+        .map_err(CanvasError::Decode)?
+        .into_rgba8();
+    let extended = extend(&img, margins, mode)?;
+    convert::encode(&DynamicImage::ImageRgba8(extended), target, quality)
+        .map_err(CanvasError::Convert)
+}
+
+/// An image with its borders trimmed off.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Trimmed {
+    pub data: Vec<u8>,
+    /// The borders that were removed.
+    pub margins: Margins,
+}
+
+/// Removes the uniform border around `img`: the rows and columns at each edge whose
+/// pixels all match the top-left pixel within `tolerance` per channel (any fully
+/// transparent pixel matches a fully transparent corner). Returns the cropped image
+/// and the removed margins; an image that is all border is returned unchanged.
+pub fn trim(img: &RgbaImage, tolerance: u8) -> (RgbaImage, Margins) {
+    let Some(&Rgba(corner)) = img.get_pixel_checked(0, 0) else {
+        return (img.clone(), Margins::default());
+    };
+    let is_border = |x: u32, y: u32| {
+        img.get_pixel_checked(x, y).is_some_and(|&Rgba(pixel)| {
+            let [.., corner_alpha] = corner;
+            let [.., alpha] = pixel;
+            (corner_alpha == 0 && alpha == 0)
+                || corner
+                    .iter()
+                    .zip(pixel)
+                    .all(|(&a, b)| a.abs_diff(b) <= tolerance)
+        })
+    };
+    let (width, height) = img.dimensions();
+    let row_is_border = |y: u32| (0..width).all(|x| is_border(x, y));
+    let column_is_border =
+        |x: u32, rows: std::ops::Range<u32>| rows.into_iter().all(|y| is_border(x, y));
+
+    let Some(top) = (0..height).find(|&y| !row_is_border(y)) else {
+        return (img.clone(), Margins::default());
+    };
+    let bottom = (top..height)
+        .rev()
+        .find(|&y| !row_is_border(y))
+        .unwrap_or(top);
+    let left = (0..width)
+        .find(|&x| !column_is_border(x, top..bottom + 1))
+        .unwrap_or(0);
+    let right = (left..width)
+        .rev()
+        .find(|&x| !column_is_border(x, top..bottom + 1))
+        .unwrap_or(left);
+
+    let margins = Margins {
+        left,
+        right: width - 1 - right,
+        top,
+        bottom: height - 1 - bottom,
+    };
+    let cropped = imageops::crop_imm(img, left, top, right - left + 1, bottom - top + 1).to_image();
+    (cropped, margins)
+}
+
+/// Decodes `input`, removes its uniform border with [`trim`], and encodes the result
+/// as `target`, e.g. to tidy up a screenshot.
+///
+/// # Errors
+///
+/// Returns a `CanvasError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn trim_borders(
+    input: &[u8],
+    tolerance: u8,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Trimmed, CanvasError> {
+    let img = image::load_from_memory(input)
+        .map_err(CanvasError::Decode)?
+        .into_rgba8();
+    let (trimmed, margins) = trim(&img, tolerance);
+    let data = convert::encode(&DynamicImage::ImageRgba8(trimmed), target, quality)
+        .map_err(CanvasError::Convert)?;
+    Ok(Trimmed { data, margins })
+}
+
+/// Errors that can occur while extending or trimming a canvas.
+#[derive(Debug)]
+pub enum CanvasError {
+    /// The fill mode was neither `"mirror"`, `"repeat"` nor a hex color.
+    InvalidMode(ColorError),
+    /// A side of the extended image would exceed [`MAX_SIDE`].
+    TooLarge,
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for CanvasError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidMode(e) => write!(
+                f,
+                "Fill mode must be \"mirror\", \"repeat\" or a color: {e}"
+            ),
+            Self::TooLarge => write!(
+                f,
+                "The extended image would be larger than {MAX_SIDE}x{MAX_SIDE}"
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for CanvasError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const A: Rgba<u8> = Rgba([255, 0, 0, 255]);
+    const B: Rgba<u8> = Rgba([0, 0, 255, 255]);
+
+    fn row(img: &RgbaImage, y: u32) -> Vec<Rgba<u8>> {
+        (0..img.width()).map(|x| *img.get_pixel(x, y)).collect()
+    }
+
+    fn two_pixels() -> RgbaImage {
+        RgbaImage::from_fn(2, 1, |x, _| if x == 0 { A } else { B })
+    }
+
+    #[test]
+    fn parses_modes() {
+        assert_eq!(FillMode::from_name(" Mirror ").unwrap(), FillMode::Mirror);
+        assert_eq!(FillMode::from_name("repeat").unwrap(), FillMode::Repeat);
+        assert_eq!(
+            FillMode::from_name("#fff").unwrap(),
+            FillMode::Color([255, 255, 255, 255])
+        );
+        assert!(matches!(
+            FillMode::from_name("wrap"),
+            Err(CanvasError::InvalidMode(_))
+        ));
+    }
+
+    #[test]
+    fn mirror_reflects_back_and_forth() {
+        let margins = Margins {
+            left: 3,
+            right: 2,
+            ..Margins::default()
+        };
+        let img = extend(&two_pixels(), margins, FillMode::Mirror).unwrap();
+        assert_eq!(row(&img, 0), [B, B, A, A, B, B, A]);
+    }
+
+    #[test]
+    fn repeat_stretches_edges() {
+        let margins = Margins {
+            left: 2,
+            top: 1,
+            bottom: 1,
+            ..Margins::default()
+        };
+        let img = extend(&two_pixels(), margins, FillMode::Repeat).unwrap();
+        assert_eq!(img.dimensions(), (4, 3));
+        for y in 0..3 {
+            assert_eq!(row(&img, y), [A, A, A, B]);
+        }
+    }
+
+    #[test]
+    fn color_fills_only_the_margins() {
+        let white = [255; 4];
+        let margins = Margins {
+            right: 1,
+            top: 1,
+            ..Margins::default()
+        };
+        let img = extend(&two_pixels(), margins, FillMode::Color(white)).unwrap();
+        assert_eq!(row(&img, 0), [Rgba(white); 3]);
+        assert_eq!(row(&img, 1), [A, B, Rgba(white)]);
+    }
+
+    #[test]
+    fn trim_removes_uniform_borders() {
+        let white = Rgba([255; 4]);
+        let mut img = RgbaImage::from_pixel(6, 5, white);
+        img.put_pixel(2, 1, A);
+        img.put_pixel(3, 3, Rgba([250, 250, 250, 255]));
+
+        let (trimmed, margins) = trim(&img, 0);
+        assert_eq!(trimmed.dimensions(), (2, 3));
+        assert_eq!(
+            margins,
+            Margins {
+                left: 2,
+                right: 2,
+                top: 1,
+                bottom: 1,
+            }
+        );
+
+        // Within tolerance the near-white pixel is border too.
+        let (trimmed, margins) = trim(&img, 8);
+        assert_eq!(row(&trimmed, 0), [A]);
+        assert_eq!(margins.bottom, 3);
+    }
+
+    #[test]
+    fn trim_treats_any_transparent_pixel_as_border() {
+        let mut img = RgbaImage::from_pixel(4, 4, Rgba([0, 0, 0, 0]));
+        img.put_pixel(3, 0, Rgba([90, 10, 10, 0]));
+        img.put_pixel(1, 2, B);
+        let (trimmed, margins) = trim(&img, 0);
+        assert_eq!(row(&trimmed, 0), [B]);
+        assert_eq!((margins.left, margins.top), (1, 2));
+    }
+
+    #[test]
+    fn trim_keeps_images_that_are_all_border() {
+        let img = RgbaImage::from_pixel(3, 2, A);
+        assert_eq!(trim(&img, 0), (img, Margins::default()));
+    }
+
+    #[test]
+    fn rejects_oversized_canvases() {
+        for margins in [
+            Margins {
+                left: u32::MAX,
+                ..Margins::default()
+            },
+            Margins {
+                top: u32::MAX - 10,
+                ..Margins::default()
+            },
+            Margins {
+                left: 70_000,
+                right: 70_000,
+                ..Margins::default()
+            },
+            Margins {
+                bottom: MAX_SIDE,
+                ..Margins::default()
+            },
+        ] {
+            assert!(matches!(
+                extend(&two_pixels(), margins, FillMode::Repeat),
+                Err(CanvasError::TooLarge)
+            ));
+        }
+
+        let margins = Margins {
+            bottom: MAX_SIDE - 1,
+            ..Margins::default()
+        };
+        let extended = extend(&two_pixels(), margins, FillMode::Repeat).unwrap();
+        assert_eq!(extended.height(), MAX_SIDE);
+    }
+}
@@ -0,0 +1,156 @@
+use serde::Serialize;
+
+use crate::codecs;
+use crate::formats::ImageFormat;
+use crate::operations;
+use crate::psd;
+use crate::transforms::Transform;
+
+/// What this build supports, for front-ends that build their menus at runtime.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Capabilities {
+    /// The crate version.
+    pub version: &'static str,
+    /// Formats conversions accept, by [`ImageFormat::as_str`] name, followed by those
+    /// of registered [`codecs::Decoder`]s.
+    pub input_formats: Vec<&'static str>,
+    /// Input formats that can't be detected from their bytes, so conversions need them
+    /// named as the source format.
+    pub hint_required: Vec<&'static str>,
+    /// Formats conversions can produce, followed by those of registered
+    /// [`codecs::Encoder`]s.
+    pub output_formats: Vec<&'static str>,
+    /// Transforms the conversion pipeline accepts, by [`Transform::name`], followed by
+    /// the operations registered with [`operations::register_operation`].
+    pub operations: Vec<&'static str>,
+    pub features: Features,
+}
+
+/// Optional behavior compiled into this build.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Features {
+    /// Whether work is spread across threads. Always `false`: every operation runs on
+    /// the caller's thread.
+    pub threads: bool,
+    /// Whether the build targets WASM SIMD (`simd128`), which the codecs vectorize for.
+    pub simd: bool,
+    /// Whether JPEG output uses mozjpeg (the `mozjpeg` feature).
+    pub mozjpeg: bool,
+    /// Whether `set_log_level` is available (the `logging` feature).
+    pub logging: bool,
+    /// Whether conversion stages open `tracing` spans (the `tracing` feature).
+    pub tracing: bool,
+    /// Whether camera RAW files are read through their embedded JPEG previews (the
+    /// `raw-preview` feature).
+    pub raw_preview: bool,
+    /// Whether `develop_raw` demosaics RAW sensor data, and files without a preview
+    /// convert through it (the `raw-decode` feature).
+    pub raw_decode: bool,
+    /// Whether `detect_faces` and `smart_crop_faces` are available (the `faces`
+    /// feature).
+    pub faces: bool,
+}
+
+/// Reports the formats, operations and features of this build.
+pub fn capabilities() -> Capabilities {
+    let formats = |supported: fn(ImageFormat) -> bool| {
+        ImageFormat::ALL
+            .into_iter()
+            .filter(move |&format| supported(format))
+            .map(|format| format.as_str())
+    };
+    Capabilities {
+        version: env!("CARGO_PKG_VERSION"),
+        input_formats: formats(|_| true)
+            .chain(raw_formats())
+            .chain([psd::NAME])
+            .chain(codecs::decoder_names())
+            .collect(),
+        hint_required: formats(|format| !format.is_detectable()).collect(),
+        output_formats: formats(ImageFormat::can_encode)
+            .chain(codecs::encoder_names())
+            .collect(),
+        operations: Transform::ALL
+            .into_iter()
+            .map(Transform::name)
+            .chain(operations::registered_operations())
+            .collect(),
+        features: Features {
+            threads: false,
+            simd: cfg!(target_feature = "simd128"),
+            mozjpeg: cfg!(feature = "mozjpeg"),
+            logging: cfg!(feature = "logging"),
+            tracing: cfg!(feature = "tracing"),
+            raw_preview: cfg!(feature = "raw-preview"),
+            raw_decode: cfg!(feature = "raw-decode"),
+            faces: cfg!(feature = "faces"),
+        },
+    }
+}
+
+/// Camera RAW formats this build reads.
+fn raw_formats() -> Vec<&'static str> {
+    #[cfg(feature = "raw-preview")]
+    return crate::raw::RAW_FORMATS.to_vec();
+    #[cfg(not(feature = "raw-preview"))]
+    Vec::new()
+}
+
+/// Whether this build can convert images named `source` into `target`, by format name
+/// as [`ImageFormat::from_name`] accepts them. Unknown names are never convertible.
+///
+/// Every format can be decoded, and any source converts to any encodable target since
+/// conversions go through decoded pixels. Sources listed in
+/// [`Capabilities::hint_required`] must be named as the source format. Registered
+/// codecs count by name.
+pub fn can_convert(source: &str, target: &str) -> bool {
+    let source = source.trim();
+    let decodable = ImageFormat::from_name(source).is_ok()
+        || raw_formats()
+            .iter()
+            .any(|raw| raw.eq_ignore_ascii_case(source))
+        || psd::NAME.eq_ignore_ascii_case(source)
+        || codecs::find_decoder(source).is_some();
+    let encodable = match ImageFormat::from_name(target) {
+        Ok(target) => target.can_encode(),
+        Err(_) => codecs::find_encoder(target).is_some(),
+    };
+    decodable && encodable
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lists_this_builds_formats() {
+        let caps = capabilities();
+        let mut inputs = vec![
+            "png", "jpeg", "webp", "gif", "bmp", "tiff", "ico", "tga", "qoi",
+        ];
+        if cfg!(feature = "raw-preview") {
+            inputs.extend(["cr2", "nef", "arw"]);
+        }
+        inputs.push("psd");
+        assert_eq!(caps.input_formats, inputs);
+        assert_eq!(caps.hint_required, ["tga"]);
+        assert_eq!(
+            caps.output_formats,
+            ["png", "jpeg", "gif", "bmp", "tiff", "ico", "tga", "qoi"]
+        );
+        assert_eq!(caps.operations.len(), Transform::ALL.len());
+        assert_eq!(caps.features.mozjpeg, cfg!(feature = "mozjpeg"));
+    }
+
+    #[test]
+    fn conversion_matrix() {
+        assert!(can_convert("webp", "png"));
+        assert!(can_convert("PSD", "png"));
+        assert!(can_convert("JPG", "gif"));
+        assert!(can_convert("png", "png"));
+        assert!(!can_convert("png", "webp"));
+        assert!(can_convert("tga", "png"));
+        assert!(!can_convert("avif", "png"));
+        assert!(!can_convert("png", ""));
+    }
+}
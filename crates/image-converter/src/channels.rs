@@ -0,0 +1,387 @@
+use std::fmt;
+
+use image::{DynamicImage, GrayImage, Luma, Rgba, RgbaImage};
+
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+
+/// One channel of an RGBA image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Channel {
+    Red,
+    Green,
+    Blue,
+    Alpha,
+}
+
+impl Channel {
+    /// Parses a channel name.
+    ///
+    /// Accepts `"red"`, `"green"`, `"blue"`, `"alpha"` or their initials.
+    ///
+    /// Returns an error if the string is not a recognized channel.
+    pub fn from_name(name: &str) -> Result<Self, ChannelError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "r" | "red" => Ok(Self::Red),
+            "g" | "green" => Ok(Self::Green),
+            "b" | "blue" => Ok(Self::Blue),
+            "a" | "alpha" => Ok(Self::Alpha),
+            _ => Err(ChannelError::UnknownChannel(name.to_owned())),
+        }
+    }
+
+    fn index(self) -> usize {
+        match self {
+            Self::Red => 0,
+            Self::Green => 1,
+            Self::Blue => 2,
+            Self::Alpha => 3,
+        }
+    }
+}
+
+/// Where an output channel of [`swap_channels`] takes its value from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelSource {
+    /// Copy a channel of the source pixel.
+    Channel(Channel),
+    /// Fill with 0.
+    Zero,
+    /// Fill with 255.
+    Full,
+}
+
+/// Parses a channel order such as `"bgra"`: four characters, one per output channel
+/// (red, green, blue, alpha), each `r`, `g`, `b`, `a`, `0` or `1`.
+///
+/// Channels may repeat, so `"rrr1"` spreads red into gray and makes the image opaque.
+///
+/// # Errors
+///
+/// Returns `ChannelError::InvalidOrder` if the string is not four valid characters.
+pub fn parse_order(order: &str) -> Result<[ChannelSource; 4], ChannelError> {
+    let invalid = || ChannelError::InvalidOrder(order.to_owned());
+    let sources = order
+        .trim()
+        .chars()
+        .map(|c| match c.to_ascii_lowercase() {
+            '0' => Ok(ChannelSource::Zero),
+            '1' => Ok(ChannelSource::Full),
+            c => Channel::from_name(c.encode_utf8(&mut [0; 4]))
+                .map(ChannelSource::Channel)
+                .map_err(|_| invalid()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    <[ChannelSource; 4]>::try_from(sources).map_err(|_| invalid())
+}
+
+/// Copies one channel of the image into a grayscale image, e.g. alpha as a mask.
+pub fn extract(img: &RgbaImage, channel: Channel) -> GrayImage {
+    let index = channel.index();
+    GrayImage::from_fn(img.width(), img.height(), |x, y| {
+        Luma([img.get_pixel(x, y).0.get(index).copied().unwrap_or(0)])
+    })
+}
+
+/// Rearranges the channels of every pixel according to `order`.
+pub fn swap(img: &mut RgbaImage, order: [ChannelSource; 4]) {
+    for pixel in img.pixels_mut() {
+        let source = pixel.0;
+        pixel.0 = order.map(|from| match from {
+            ChannelSource::Channel(channel) => source.get(channel.index()).copied().unwrap_or(0),
+            ChannelSource::Zero => 0,
+            ChannelSource::Full => u8::MAX,
+        });
+    }
+}
+
+/// Builds an RGBA image from per-channel grayscale planes.
+///
+/// Missing color channels are filled with 0 and a missing alpha channel with 255.
+///
+/// # Errors
+///
+/// Returns `ChannelError::NoChannels` if every plane is missing and
+/// `ChannelError::DimensionMismatch` if the planes differ in size.
+pub fn merge(planes: [Option<&GrayImage>; 4]) -> Result<RgbaImage, ChannelError> {
+    let mut present = planes.iter().flatten();
+    let (width, height) = present
+        .next()
+        .map(|plane| plane.dimensions())
+        .ok_or(ChannelError::NoChannels)?;
+    if let Some(plane) = present.find(|plane| plane.dimensions() != (width, height)) {
+        return Err(ChannelError::DimensionMismatch {
+            expected: (width, height),
+            found: plane.dimensions(),
+        });
+    }
+
+    let fill = [0, 0, 0, u8::MAX];
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let mut pixel = fill;
+        for ((value, plane), default) in pixel.iter_mut().zip(planes).zip(fill) {
+            *value = plane.map_or(default, |plane| plane.get_pixel(x, y).0[0]);
+        }
+        Rgba(pixel)
+    }))
+}
+
+/// Decodes `input`, extracts one channel as grayscale, and encodes it as `target`.
+///
+/// # Errors
+///
+/// Returns a `ChannelError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn extract_channel(
+    input: &[u8],
+    channel: Channel,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, ChannelError> {
+    let img = decode(input)?;
+    convert::encode(
+        &DynamicImage::ImageLuma8(extract(&img, channel)),
+        target,
+        quality,
+    )
+    .map_err(ChannelError::Convert)
+}
+
+/// Decodes `input`, rearranges its channels (see [`parse_order`]), and encodes it as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns a `ChannelError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn swap_channels(
+    input: &[u8],
+    order: [ChannelSource; 4],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, ChannelError> {
+    let mut img = decode(input)?;
+    swap(&mut img, order);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(ChannelError::Convert)
+}
+
+/// Decodes up to four images as grayscale planes (red, green, blue, alpha), merges
+/// them with [`merge`], and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns a `ChannelError` if a plane cannot be decoded, no planes are given, the
+/// planes differ in size, or the output cannot be encoded.
+pub fn merge_channels(
+    inputs: [Option<&[u8]>; 4],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, ChannelError> {
+    let [red, green, blue, alpha] = inputs.map(|input| {
+        input
+            .map(|bytes| image::load_from_memory(bytes).map(DynamicImage::into_luma8))
+            .transpose()
+            .map_err(ChannelError::Decode)
+    });
+    let planes = [red?, green?, blue?, alpha?];
+
+    let merged = merge(planes.each_ref().map(Option::as_ref))?;
+    convert::encode(&DynamicImage::ImageRgba8(merged), target, quality)
+        .map_err(ChannelError::Convert)
+}
+
+fn decode(input: &[u8]) -> Result<RgbaImage, ChannelError> {
+    Ok(image::load_from_memory(input)
+        .map_err(ChannelError::Decode)?
+        .into_rgba8())
+}
+
+/// Errors that can occur while extracting, swapping or merging channels.
+#[derive(Debug)]
+pub enum ChannelError {
+    /// The channel name was not red, green, blue or alpha.
+    UnknownChannel(String),
+    /// The channel order was not four characters from `rgba01`.
+    InvalidOrder(String),
+    /// No channel planes were given to merge.
+    NoChannels,
+    /// The channel planes to merge differ in size.
+    DimensionMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    /// Failed to decode an input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for ChannelError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownChannel(name) => write!(
+                f,
+                "Unknown channel \"{name}\" (expected red, green, blue or alpha)"
+            ),
+            Self::InvalidOrder(order) => write!(
+                f,
+                "Invalid channel order \"{order}\" (expected four of r, g, b, a, 0, 1)"
+            ),
+            Self::NoChannels => write!(f, "At least one channel is required"),
+            Self::DimensionMismatch { expected, found } => write!(
+                f,
+                "Channel is {}x{} but the first channel is {}x{}",
+                found.0, found.1, expected.0, expected.1
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ChannelError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn sample() -> RgbaImage {
+        RgbaImage::from_fn(3, 2, |x, y| {
+            let x = u8::try_from(x).unwrap();
+            let y = u8::try_from(y).unwrap();
+            Rgba([10 + x, 20 + y, 30, 40 + x + y])
+        })
+    }
+
+    fn png(img: RgbaImage) -> Vec<u8> {
+        let mut buf = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    // ===== Parsing Tests =====
+
+    #[test]
+    fn parse_channel_names() {
+        assert_eq!(Channel::from_name("A").unwrap(), Channel::Alpha);
+        assert_eq!(Channel::from_name("green").unwrap(), Channel::Green);
+        assert!(matches!(
+            Channel::from_name("cyan"),
+            Err(ChannelError::UnknownChannel(_))
+        ));
+    }
+
+    #[test]
+    fn parse_channel_order() {
+        assert_eq!(
+            parse_order("BGRA").unwrap(),
+            [
+                ChannelSource::Channel(Channel::Blue),
+                ChannelSource::Channel(Channel::Green),
+                ChannelSource::Channel(Channel::Red),
+                ChannelSource::Channel(Channel::Alpha),
+            ]
+        );
+        assert_eq!(parse_order("rrr1").unwrap()[3], ChannelSource::Full);
+        for bad in ["rgb", "rgbaa", "rgbx", ""] {
+            assert!(
+                matches!(parse_order(bad), Err(ChannelError::InvalidOrder(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    // ===== Operation Tests =====
+
+    #[test]
+    fn extract_alpha_as_mask() {
+        let mask = extract(&sample(), Channel::Alpha);
+        assert_eq!(mask.dimensions(), (3, 2));
+        assert_eq!(mask.get_pixel(2, 1).0, [43]);
+    }
+
+    #[test]
+    fn swap_rgba_to_bgra() {
+        let mut img = sample();
+        swap(&mut img, parse_order("bgra").unwrap());
+        assert_eq!(img.get_pixel(1, 0).0, [30, 20, 11, 41]);
+
+        swap(&mut img, parse_order("0g01").unwrap());
+        assert_eq!(img.get_pixel(1, 0).0, [0, 20, 0, 255]);
+    }
+
+    #[test]
+    fn merge_round_trips_extracted_channels() {
+        let img = sample();
+        let planes = [Channel::Red, Channel::Green, Channel::Blue, Channel::Alpha]
+            .map(|channel| extract(&img, channel));
+        let merged = merge([
+            Some(&planes[0]),
+            Some(&planes[1]),
+            Some(&planes[2]),
+            Some(&planes[3]),
+        ])
+        .unwrap();
+        assert_eq!(merged, img);
+    }
+
+    #[test]
+    fn merge_fills_missing_channels() {
+        let red = GrayImage::from_pixel(2, 2, Luma([200]));
+        let merged = merge([Some(&red), None, None, None]).unwrap();
+        assert_eq!(merged.get_pixel(0, 0).0, [200, 0, 0, 255]);
+    }
+
+    #[test]
+    fn merge_rejects_bad_planes() {
+        assert!(matches!(
+            merge([None, None, None, None]),
+            Err(ChannelError::NoChannels)
+        ));
+        let small = GrayImage::new(2, 2);
+        let large = GrayImage::new(3, 2);
+        assert!(matches!(
+            merge([Some(&small), None, Some(&large), None]),
+            Err(ChannelError::DimensionMismatch {
+                expected: (2, 2),
+                found: (3, 2)
+            })
+        ));
+    }
+
+    // ===== Encoded Round-Trip Tests =====
+
+    #[test]
+    fn extract_and_merge_encoded_images() {
+        let input = png(sample());
+        let alpha = extract_channel(&input, Channel::Alpha, ImageFormat::Png, None).unwrap();
+        let decoded = image::load_from_memory(&alpha).unwrap();
+        assert!(matches!(decoded, DynamicImage::ImageLuma8(_)));
+
+        let merged =
+            merge_channels([None, None, None, Some(&alpha)], ImageFormat::Png, None).unwrap();
+        let merged = image::load_from_memory(&merged).unwrap().into_rgba8();
+        assert_eq!(merged.get_pixel(2, 1).0, [0, 0, 0, 43]);
+    }
+
+    #[test]
+    fn swap_channels_encoded() {
+        let input = png(sample());
+        let output =
+            swap_channels(&input, parse_order("bgra").unwrap(), ImageFormat::Png, None).unwrap();
+        let output = image::load_from_memory(&output).unwrap().into_rgba8();
+        assert_eq!(output.get_pixel(0, 0).0, [30, 20, 10, 40]);
+    }
+
+    #[test]
+    fn invalid_input_is_decode_error() {
+        assert!(matches!(
+            extract_channel(&[0xDE, 0xAD], Channel::Red, ImageFormat::Png, None),
+            Err(ChannelError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,325 @@
+//! Codecs for formats this crate doesn't build in (camera RAW through an external
+//! library, in-house formats), registered by Rust code that embeds the crate.
+//!
+//! A registered decoder is tried for inputs none of the built-in formats recognize, so
+//! its format is detected and converted like any other. A registered encoder is named
+//! as the target of [`convert::convert_with_encoder`](crate::convert::convert_with_encoder).
+//! Both are listed by [`capabilities`](crate::capabilities::capabilities).
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use image::DynamicImage;
+
+use crate::formats::ImageFormat;
+
+/// Reads a format the crate doesn't build in.
+pub trait Decoder {
+    /// The format's name, e.g. `"cr2"`, as detection reports it.
+    fn name(&self) -> &'static str;
+
+    /// Whether `input` is in this format, usually from its magic bytes.
+    fn detect(&self, input: &[u8]) -> bool;
+
+    /// Decodes `input`, which [`Decoder::detect`] accepted.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CodecError::Failed` if the input can't be decoded.
+    fn decode(&self, input: &[u8]) -> Result<DynamicImage, CodecError>;
+}
+
+/// Writes a format the crate doesn't build in.
+pub trait Encoder {
+    /// The name conversions use as the target format.
+    fn name(&self) -> &'static str;
+
+    /// Encodes `image`. `quality` is 1-100 when given; formats without a quality
+    /// setting ignore it.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CodecError::Failed` if the image can't be encoded.
+    fn encode(&self, image: &DynamicImage, quality: Option<u8>) -> Result<Vec<u8>, CodecError>;
+}
+
+thread_local! {
+    /// Registered decoders in registration order, per thread like the font registry.
+    static DECODERS: RefCell<Vec<Rc<dyn Decoder>>> = const { RefCell::new(Vec::new()) };
+    /// Registered encoders in registration order.
+    static ENCODERS: RefCell<Vec<Rc<dyn Encoder>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Registers `decoder`, replacing any decoder already registered under its name
+/// (compared case-insensitively). Decoders are tried in registration order.
+///
+/// # Errors
+///
+/// Returns `CodecError::InvalidName` if the name is blank or names a built-in format.
+pub fn register_decoder(decoder: impl Decoder + 'static) -> Result<(), CodecError> {
+    check_name(decoder.name())?;
+    DECODERS.with(|decoders| {
+        let mut decoders = decoders.borrow_mut();
+        let name = decoder.name();
+        let decoder: Rc<dyn Decoder> = Rc::new(decoder);
+        match decoders
+            .iter_mut()
+            .find(|registered| registered.name().eq_ignore_ascii_case(name))
+        {
+            Some(existing) => *existing = decoder,
+            None => decoders.push(decoder),
+        }
+    });
+    Ok(())
+}
+
+/// Registers `encoder`, replacing any encoder already registered under its name
+/// (compared case-insensitively).
+///
+/// # Errors
+///
+/// Returns `CodecError::InvalidName` if the name is blank or names a built-in format.
+pub fn register_encoder(encoder: impl Encoder + 'static) -> Result<(), CodecError> {
+    check_name(encoder.name())?;
+    ENCODERS.with(|encoders| {
+        let mut encoders = encoders.borrow_mut();
+        let name = encoder.name();
+        let encoder: Rc<dyn Encoder> = Rc::new(encoder);
+        match encoders
+            .iter_mut()
+            .find(|registered| registered.name().eq_ignore_ascii_case(name))
+        {
+            Some(existing) => *existing = encoder,
+            None => encoders.push(encoder),
+        }
+    });
+    Ok(())
+}
+
+/// Built-in formats (and their aliases) can't be taken over.
+fn check_name(name: &str) -> Result<(), CodecError> {
+    if name.trim().is_empty() || ImageFormat::from_name(name).is_ok() {
+        return Err(CodecError::InvalidName(name.to_owned()));
+    }
+    Ok(())
+}
+
+/// Removes the decoder and encoder registered under `name`. Returns whether there was
+/// either.
+pub fn unregister_codec(name: &str) -> bool {
+    let name = name.trim();
+    let decoder = DECODERS.with(|decoders| {
+        let mut decoders = decoders.borrow_mut();
+        let before = decoders.len();
+        decoders.retain(|registered| !registered.name().eq_ignore_ascii_case(name));
+        decoders.len() != before
+    });
+    let encoder = ENCODERS.with(|encoders| {
+        let mut encoders = encoders.borrow_mut();
+        let before = encoders.len();
+        encoders.retain(|registered| !registered.name().eq_ignore_ascii_case(name));
+        encoders.len() != before
+    });
+    decoder || encoder
+}
+
+/// The names of the registered decoders, in registration order.
+pub fn decoder_names() -> Vec<&'static str> {
+    DECODERS.with(|decoders| decoders.borrow().iter().map(|d| d.name()).collect())
+}
+
+/// The names of the registered encoders, in registration order.
+pub fn encoder_names() -> Vec<&'static str> {
+    ENCODERS.with(|encoders| encoders.borrow().iter().map(|e| e.name()).collect())
+}
+
+/// The first registered decoder that recognizes `input`.
+pub fn detect(input: &[u8]) -> Option<Rc<dyn Decoder>> {
+    DECODERS.with(|decoders| {
+        decoders
+            .borrow()
+            .iter()
+            .find(|decoder| decoder.detect(input))
+            .map(Rc::clone)
+    })
+}
+
+/// The decoder registered under `name`.
+pub fn find_decoder(name: &str) -> Option<Rc<dyn Decoder>> {
+    let name = name.trim();
+    DECODERS.with(|decoders| {
+        decoders
+            .borrow()
+            .iter()
+            .find(|registered| registered.name().eq_ignore_ascii_case(name))
+            .map(Rc::clone)
+    })
+}
+
+/// The encoder registered under `name`.
+pub fn find_encoder(name: &str) -> Option<Rc<dyn Encoder>> {
+    let name = name.trim();
+    ENCODERS.with(|encoders| {
+        encoders
+            .borrow()
+            .iter()
+            .find(|registered| registered.name().eq_ignore_ascii_case(name))
+            .map(Rc::clone)
+    })
+}
+
+/// Errors that can occur while registering or running codecs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CodecError {
+    /// The name was blank or belongs to a built-in format.
+    InvalidName(String),
+    /// A registered codec failed.
+    Failed(String),
+}
+
+impl fmt::Display for CodecError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(f, "{name:?} can't be used as a codec name"),
+            Self::Failed(msg) => write!(f, "{msg}"),
+        }
+    }
+}
+
+impl std::error::Error for CodecError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::capabilities;
+    use crate::convert::{self, ConvertOptions};
+    use crate::transforms::Transform;
+
+    /// A toy grayscale format: `TOY1`, width and height as little-endian `u32`s, then
+    /// one byte per pixel.
+    struct Toy;
+
+    const MAGIC: &[u8] = b"TOY1";
+
+    impl Decoder for Toy {
+        fn name(&self) -> &'static str {
+            "toy"
+        }
+
+        fn detect(&self, input: &[u8]) -> bool {
+            input.starts_with(MAGIC)
+        }
+
+        fn decode(&self, input: &[u8]) -> Result<DynamicImage, CodecError> {
+            let field = |at: usize| {
+                input
+                    .get(at..at + 4)
+                    .and_then(|bytes| bytes.try_into().ok())
+                    .map(u32::from_le_bytes)
+                    .ok_or_else(|| CodecError::Failed("truncated header".into()))
+            };
+            let (width, height) = (field(4)?, field(8)?);
+            image::GrayImage::from_raw(width, height, input[12..].to_vec())
+                .map(DynamicImage::ImageLuma8)
+                .ok_or_else(|| CodecError::Failed("truncated pixels".into()))
+        }
+    }
+
+    impl Encoder for Toy {
+        fn name(&self) -> &'static str {
+            "toy"
+        }
+
+        fn encode(
+            &self,
+            image: &DynamicImage,
+            _quality: Option<u8>,
+        ) -> Result<Vec<u8>, CodecError> {
+            let gray = image.to_luma8();
+            let mut out = MAGIC.to_vec();
+            out.extend_from_slice(&gray.width().to_le_bytes());
+            out.extend_from_slice(&gray.height().to_le_bytes());
+            out.extend_from_slice(gray.as_raw());
+            Ok(out)
+        }
+    }
+
+    struct Named(&'static str);
+
+    impl Encoder for Named {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn encode(
+            &self,
+            _image: &DynamicImage,
+            _quality: Option<u8>,
+        ) -> Result<Vec<u8>, CodecError> {
+            Ok(Vec::new())
+        }
+    }
+
+    fn toy(width: u32, height: u32) -> Vec<u8> {
+        Toy.encode(&DynamicImage::new_luma8(width, height), None)
+            .unwrap()
+    }
+
+    #[test]
+    fn registered_codecs_convert_both_ways() {
+        register_decoder(Toy).unwrap();
+        register_encoder(Toy).unwrap();
+        assert_eq!(detect(&toy(1, 1)).map(|d| d.name()), Some("toy"));
+
+        let png =
+            convert::convert(toy(3, 2), ImageFormat::Png, None, &[Transform::Rotate90]).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!((img.width(), img.height()), (2, 3));
+        let plan = convert::plan(
+            &toy(3, 2),
+            ImageFormat::Png,
+            &ConvertOptions::default(),
+            &[],
+        )
+        .unwrap();
+        assert_eq!((plan.input_format, plan.width), ("toy", 3));
+
+        let mut bmp = Vec::new();
+        DynamicImage::new_rgb8(5, 4)
+            .write_to(&mut Cursor::new(&mut bmp), image::ImageFormat::Bmp)
+            .unwrap();
+        let output =
+            convert::convert_with_encoder(bmp, "TOY", &ConvertOptions::default(), &[]).unwrap();
+        let img = Toy.decode(&output).unwrap();
+        assert_eq!((img.width(), img.height()), (5, 4));
+
+        let caps = capabilities::capabilities();
+        assert_eq!(caps.input_formats.last(), Some(&"toy"));
+        assert_eq!(caps.output_formats.last(), Some(&"toy"));
+        assert!(capabilities::can_convert("toy", "png"));
+        assert!(capabilities::can_convert("png", "toy"));
+
+        assert!(unregister_codec("toy"));
+        assert!(convert::convert(toy(3, 2), ImageFormat::Png, None, &[]).is_err());
+        assert!(!capabilities::can_convert("png", "toy"));
+    }
+
+    #[test]
+    fn builtin_format_names_are_reserved() {
+        for name in ["png", "JPG", ".tif", " "] {
+            assert_eq!(
+                register_encoder(Named(name)),
+                Err(CodecError::InvalidName(name.to_owned()))
+            );
+        }
+        register_encoder(Named("raw")).unwrap();
+        register_encoder(Named("dng")).unwrap();
+        register_encoder(Named("RAW")).unwrap();
+        assert_eq!(encoder_names(), ["RAW", "dng"]);
+        assert!(find_encoder("raw").is_some());
+        assert!(find_decoder("raw").is_none());
+    }
+}
@@ -0,0 +1,546 @@
+//! Locating QR codes so they can be redacted before an image is shared.
+//!
+//! Codes are found by their three finder patterns (the nested squares in three
+//! corners), whose rows and columns cross dark and light runs in a 1:1:3:1:1 ratio.
+//! Locating doesn't decode payloads; [`qr::read`] does, for the codes found here, and
+//! [`detect`] does both in one pass.
+//! Linear barcodes and other 2D symbologies aren't found.
+
+use std::fmt;
+
+use image::{imageops, DynamicImage, GrayImage, Luma, RgbaImage};
+use serde::Serialize;
+
+use crate::color;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::qr::{self, QrCode};
+use crate::stats;
+
+/// Where a QR code is in an image.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct QrCodeLocation {
+    /// Bounding box in image pixels, including the code's edge modules.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// Estimated size of one module (the code's smallest square), in pixels.
+    pub module_size: f64,
+}
+
+/// A QR code [`detect`] found: where it is, and what it says if it could be read.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DetectedCode {
+    /// The decoded text, as in [`QrCode::payload`], or `None` for a code that was
+    /// located but can't be read.
+    pub payload: Option<String>,
+    pub location: QrCodeLocation,
+}
+
+/// The codes [`detect_codes`] found, and the redacted image if one was asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectedCodes {
+    pub codes: Vec<DetectedCode>,
+    /// The input with every code blurred as [`redact`] does, encoded as asked.
+    pub redacted: Option<Vec<u8>>,
+}
+
+/// A finder pattern's center and module size.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Finder {
+    pub(crate) x: f64,
+    pub(crate) y: f64,
+    pub(crate) module: f64,
+    /// Scan lines that found this pattern.
+    hits: u32,
+}
+
+/// Finds the QR codes in `img`, largest first.
+pub fn locate(img: &RgbaImage) -> Vec<QrCodeLocation> {
+    let gray = DynamicImage::ImageRgba8(img.clone()).into_luma8();
+    let threshold = stats::otsu_threshold(gray.as_raw());
+    let mut codes: Vec<QrCodeLocation> = find(&gray, threshold)
+        .into_iter()
+        .map(|[a, b, c]| bounds(a, b, c, img.width(), img.height()))
+        .collect();
+    codes.sort_by_key(|code| std::cmp::Reverse(u64::from(code.width) * u64::from(code.height)));
+    codes
+}
+
+/// Finds the QR codes in `img` and reads their payloads in one pass, largest first.
+/// Unlike [`qr::read`], codes that can't be read are kept, without a payload.
+pub fn detect(img: &RgbaImage) -> Vec<DetectedCode> {
+    let gray = DynamicImage::ImageRgba8(img.clone()).into_luma8();
+    let threshold = stats::otsu_threshold(gray.as_raw());
+    let mut codes: Vec<DetectedCode> = find(&gray, threshold)
+        .into_iter()
+        .map(|finders| {
+            let [a, b, c] = finders;
+            DetectedCode {
+                payload: qr::read_at(&gray, threshold, finders).map(|code| code.payload),
+                location: bounds(a, b, c, img.width(), img.height()),
+            }
+        })
+        .collect();
+    codes.sort_by_key(|code| {
+        std::cmp::Reverse(u64::from(code.location.width) * u64::from(code.location.height))
+    });
+    codes
+}
+
+/// The finder patterns of each QR code in `gray`, where pixels at or below `threshold`
+/// are dark, as `[leg end, corner, leg end]` (see [`group_finders`]).
+pub(crate) fn find(gray: &GrayImage, threshold: u8) -> Vec<[Finder; 3]> {
+    let dark = |x: u32, y: u32| {
+        gray.get_pixel_checked(x, y)
+            .is_some_and(|&Luma([value])| value <= threshold)
+    };
+
+    let mut finders: Vec<Finder> = Vec::new();
+    for y in 0..gray.height() {
+        let row: Vec<bool> = (0..gray.width()).map(|x| dark(x, y)).collect();
+        for (center, module) in finder_runs(&row) {
+            let column: Vec<bool> = (0..gray.height()).map(|y| dark(center, y)).collect();
+            let Some((cy, vertical)) = finder_runs(&column)
+                .into_iter()
+                .find(|&(cy, _)| cy.abs_diff(y) <= to_u32(module * 2.0))
+            else {
+                continue;
+            };
+            add_finder(
+                &mut finders,
+                Finder {
+                    x: f64::from(center),
+                    y: f64::from(cy),
+                    module: (module + vertical) / 2.0,
+                    hits: 1,
+                },
+            );
+        }
+    }
+
+    group_finders(&finders)
+}
+
+/// Centers and module sizes of the 1:1:3:1:1 dark-light runs along a line of pixels.
+fn finder_runs(line: &[bool]) -> Vec<(u32, f64)> {
+    // Run lengths with the start of each run, beginning with the first dark run.
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    let mut previous = false;
+    for (position, &dark) in (0u32..).zip(line) {
+        if dark != previous {
+            runs.push((position, 1));
+        } else if let Some((_, length)) = runs.last_mut() {
+            *length += 1;
+        }
+        previous = dark;
+    }
+
+    let mut found = Vec::new();
+    // Dark runs sit at even indexes, so every window starting at one is d-l-d-l-d.
+    for window in runs.windows(5).step_by(2) {
+        let &[(_, a), (_, b), (start, c), (_, d), (_, e)] = window else {
+            continue;
+        };
+        let module = f64::from(a + b + c + d + e) / 7.0;
+        let tolerance = module / 2.0;
+        let close = |length: u32, modules: f64| {
+            (f64::from(length) - module * modules).abs() <= tolerance * modules
+        };
+        if module >= 1.0
+            && close(a, 1.0)
+            && close(b, 1.0)
+            && close(c, 3.0)
+            && close(d, 1.0)
+            && close(e, 1.0)
+        {
+            found.push((start + c / 2, module));
+        }
+    }
+    found
+}
+
+/// Merges `finder` into a nearby pattern found on an earlier line, or adds it.
+fn add_finder(finders: &mut Vec<Finder>, finder: Finder) {
+    let nearby = finders.iter_mut().find(|known| {
+        (known.x - finder.x).abs() <= known.module * 2.0
+            && (known.y - finder.y).abs() <= known.module * 2.0
+    });
+    match nearby {
+        Some(known) => {
+            let hits = f64::from(known.hits);
+            known.x = (known.x * hits + finder.x) / (hits + 1.0);
+            known.y = (known.y * hits + finder.y) / (hits + 1.0);
+            known.module = (known.module * hits + finder.module) / (hits + 1.0);
+            known.hits += 1;
+        }
+        None => finders.push(finder),
+    }
+}
+
+/// Picks triples of finder patterns that form a QR code's corners: similar module
+/// sizes, and a right angle at the middle pattern with equally long legs. Returns each
+/// triple as `[leg end, corner, leg end]`, using each pattern once.
+fn group_finders(finders: &[Finder]) -> Vec<[Finder; 3]> {
+    // A pattern must span at least its 3-module center on two lines to count.
+    let finders: Vec<Finder> = finders
+        .iter()
+        .copied()
+        .filter(|finder| f64::from(finder.hits) >= finder.module.min(3.0))
+        .collect();
+
+    let mut candidates = Vec::new();
+    for (i, &a) in finders.iter().enumerate() {
+        for (j, &b) in finders.iter().enumerate().skip(i + 1) {
+            for (k, &c) in finders.iter().enumerate().skip(j + 1) {
+                let modules = [a.module, b.module, c.module];
+                let smallest = modules.into_iter().fold(f64::MAX, f64::min);
+                let largest = modules.into_iter().fold(0.0, f64::max);
+                if largest > smallest * 1.4 {
+                    continue;
+                }
+                for (corner, [p, q]) in [(b, [a, c]), (a, [b, c]), (c, [a, b])] {
+                    if let Some(error) = right_angle_error(p, corner, q) {
+                        candidates.push((error, [i, j, k], [p, corner, q]));
+                    }
+                }
+            }
+        }
+    }
+
+    candidates.sort_by(|x, y| x.0.total_cmp(&y.0));
+    let mut used = vec![false; finders.len()];
+    let mut groups = Vec::new();
+    for (_, indexes, triple) in candidates {
+        if indexes
+            .iter()
+            .any(|&i| used.get(i).copied().unwrap_or(true))
+        {
+            continue;
+        }
+        for i in indexes {
+            if let Some(slot) = used.get_mut(i) {
+                *slot = true;
+            }
+        }
+        groups.push(triple);
+    }
+    groups
+}
+
+/// How far `p`-`corner`-`q` is from an isosceles right angle at `corner`, or `None`
+/// if it is too far off or the legs are implausibly short.
+fn right_angle_error(p: Finder, corner: Finder, q: Finder) -> Option<f64> {
+    let (ux, uy) = (p.x - corner.x, p.y - corner.y);
+    let (vx, vy) = (q.x - corner.x, q.y - corner.y);
+    let (u, v) = (ux.hypot(uy), vx.hypot(vy));
+    // The smallest QR code (21 modules) has its finder centers 14 modules apart.
+    if u < corner.module * 10.0 || v < corner.module * 10.0 {
+        return None;
+    }
+    let cosine = (ux * vx + uy * vy) / (u * v);
+    let ratio = u.max(v) / u.min(v);
+    (cosine.abs() < 0.15 && ratio < 1.2).then_some(cosine.abs() + (ratio - 1.0))
+}
+
+/// The bounding box of the code whose finder patterns are `p`, `corner` and `q`.
+pub(crate) fn bounds(
+    p: Finder,
+    corner: Finder,
+    q: Finder,
+    width: u32,
+    height: u32,
+) -> QrCodeLocation {
+    // The fourth corner completes the parallelogram; finder centers sit 3.5 modules in
+    // from the code's edges.
+    let fourth = (p.x + q.x - corner.x, p.y + q.y - corner.y);
+    let module = (p.module + corner.module + q.module) / 3.0;
+    let xs = [p.x, corner.x, q.x, fourth.0];
+    let ys = [p.y, corner.y, q.y, fourth.1];
+    let margin = module * 3.5;
+    let left = xs.into_iter().fold(f64::MAX, f64::min) - margin;
+    let right = xs.into_iter().fold(f64::MIN, f64::max) + margin;
+    let top = ys.into_iter().fold(f64::MAX, f64::min) - margin;
+    let bottom = ys.into_iter().fold(f64::MIN, f64::max) + margin;
+
+    let x = to_u32(left).min(width);
+    let y = to_u32(top).min(height);
+    QrCodeLocation {
+        x,
+        y,
+        width: to_u32(right.ceil()).min(width).saturating_sub(x),
+        height: to_u32(bottom.ceil()).min(height).saturating_sub(y),
+        module_size: module,
+    }
+}
+
+// Safe: the value is floored and clamped to u32's range before the cast (NaN
+// saturates to 0).
+#[allow(clippy::as_conversions)]
+pub(crate) fn to_u32(value: f64) -> u32 {
+    value.floor().clamp(0.0, f64::from(u32::MAX)) as u32
+}
+
+/// Blurs every code [`locate`] finds in `img` beyond recognition. Returns the
+/// codes that were redacted.
+pub fn redact(img: &mut RgbaImage) -> Vec<QrCodeLocation> {
+    let codes = locate(img);
+    blur(img, &codes);
+    codes
+}
+
+/// Blurs each of `codes` in `img` beyond recognition.
+fn blur(img: &mut RgbaImage, codes: &[QrCodeLocation]) {
+    for code in codes {
+        if code.width == 0 || code.height == 0 {
+            continue;
+        }
+        let region = imageops::crop_imm(img, code.x, code.y, code.width, code.height).to_image();
+        // A blur wider than a finder pattern leaves nothing a decoder can lock onto.
+        let sigma = color::to_f32(code.module_size * 4.0).max(2.0);
+        let blurred = imageops::blur(&region, sigma);
+        imageops::replace(img, &blurred, i64::from(code.x), i64::from(code.y));
+    }
+}
+
+/// Decodes `input` and finds its QR codes with [`locate`].
+///
+/// # Errors
+///
+/// Returns `CodeError::Decode` if the input cannot be decoded.
+pub fn locate_qr_codes(input: &[u8]) -> Result<Vec<QrCodeLocation>, CodeError> {
+    let img = image::load_from_memory(input)
+        .map_err(CodeError::Decode)?
+        .into_rgba8();
+    Ok(locate(&img))
+}
+
+/// Decodes `input` and reads its QR codes' payloads with [`qr::read`].
+///
+/// # Errors
+///
+/// Returns `CodeError::Decode` if the input cannot be decoded.
+pub fn read_qr_codes(input: &[u8]) -> Result<Vec<QrCode>, CodeError> {
+    let img = image::load_from_memory(input)
+        .map_err(CodeError::Decode)?
+        .into_rgba8();
+    Ok(qr::read(&img))
+}
+
+/// Decodes `input` and finds and reads its QR codes with [`detect`]. When `redact` is
+/// a target, also blurs every code found as [`redact`] does and encodes the result as
+/// that target.
+///
+/// # Errors
+///
+/// Returns a `CodeError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn detect_codes(
+    input: &[u8],
+    redact: Option<ImageFormat>,
+    quality: Option<u8>,
+) -> Result<DetectedCodes, CodeError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(CodeError::Decode)?
+        .into_rgba8();
+    let codes = detect(&img);
+    let redacted = match redact {
+        Some(target) => {
+            let locations: Vec<QrCodeLocation> = codes.iter().map(|code| code.location).collect();
+            blur(&mut img, &locations);
+            let encoded = convert::encode(&DynamicImage::ImageRgba8(img), target, quality)
+                .map_err(CodeError::Convert)?;
+            Some(encoded)
+        }
+        None => None,
+    };
+    Ok(DetectedCodes { codes, redacted })
+}
+
+/// Decodes `input`, blurs its QR codes with [`redact`], and encodes the result as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns a `CodeError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn redact_qr_codes(
+    input: &[u8],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, CodeError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(CodeError::Decode)?
+        .into_rgba8();
+    redact(&mut img);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(CodeError::Convert)
+}
+
+/// Errors that can occur while finding, reading or redacting codes.
+#[derive(Debug)]
+pub enum CodeError {
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for CodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for CodeError {}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    const MODULE: u32 = 4;
+    const QUIET: u32 = 4;
+
+    /// Whether module (`col`, `row`) of a 21x21 QR-like grid is dark: three finder
+    /// patterns, timing lines, and a fixed filler in the data area.
+    fn qr_module(col: u32, row: u32) -> bool {
+        let finder = |origin_col: u32, origin_row: u32| {
+            let (c, r) = (col.wrapping_sub(origin_col), row.wrapping_sub(origin_row));
+            (c < 7 && r < 7).then(|| {
+                let ring = c.min(r).min(6 - c).min(6 - r);
+                ring != 1
+            })
+        };
+        if let Some(dark) = finder(0, 0).or(finder(14, 0)).or(finder(0, 14)) {
+            return dark;
+        }
+        if (row < 8 && !(8..=12).contains(&col)) || (col < 8 && row > 12) {
+            return false;
+        }
+        if row == 6 || col == 6 {
+            return (col + row).is_multiple_of(2);
+        }
+        (col * 7 + row * 3) % 5 < 2
+    }
+
+    /// A code at (`left`, `top`) on a white image.
+    fn with_code(width: u32, height: u32, left: u32, top: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let (col, row) = (
+                (x.wrapping_sub(left)) / MODULE,
+                (y.wrapping_sub(top)) / MODULE,
+            );
+            let inside = x >= left && y >= top && col < 21 && row < 21;
+            if inside && qr_module(col, row) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255; 4])
+            }
+        })
+    }
+
+    #[test]
+    fn finds_the_runs_of_a_finder_pattern() {
+        let line: Vec<bool> = [false, true, false, true, true, true, false, true, false]
+            .iter()
+            .flat_map(|&dark| [dark; 3])
+            .collect();
+        assert_eq!(finder_runs(&line), [(13, 3.0)]);
+        assert!(finder_runs(&[true, false, true, false, true]).len() <= 1);
+        assert!(finder_runs(&[false; 20]).is_empty());
+    }
+
+    #[test]
+    fn locates_a_code() {
+        let left = 30;
+        let top = 20;
+        let img = with_code(160, 140, left, top);
+        let codes = locate(&img);
+        assert_eq!(codes.len(), 1, "{codes:?}");
+        let code = codes[0];
+        assert!((code.module_size - f64::from(MODULE)).abs() < 0.5);
+        let side = 21 * MODULE;
+        assert!(
+            code.x.abs_diff(left) <= 2 && code.y.abs_diff(top) <= 2,
+            "{code:?}"
+        );
+        assert!(
+            code.width.abs_diff(side) <= 3 && code.height.abs_diff(side) <= 3,
+            "{code:?}"
+        );
+    }
+
+    #[test]
+    fn ignores_images_without_codes() {
+        let img = RgbaImage::from_fn(80, 80, |x, y| {
+            Rgba(if (x / 8 + y / 8).is_multiple_of(2) {
+                [0, 0, 0, 255]
+            } else {
+                [255; 4]
+            })
+        });
+        assert!(locate(&img).is_empty());
+    }
+
+    #[test]
+    fn redaction_blurs_the_code_away() {
+        let mut img = with_code(140, 140, QUIET * MODULE, QUIET * MODULE);
+        let redacted = redact(&mut img);
+        assert_eq!(redacted.len(), 1);
+        assert!(locate(&img).is_empty());
+        // Pixels outside the code are untouched.
+        assert_eq!(img.get_pixel(135, 135).0, [255; 4]);
+    }
+
+    #[test]
+    fn detection_reads_and_redacts_in_one_call() {
+        // A readable code on the left, and a located but unreadable one on the right.
+        let code = qrcodegen::QrCode::encode_text("TICKET-42", qrcodegen::QrCodeEcc::Low).unwrap();
+        let side = (21 + 2 * QUIET) * MODULE;
+        let fake = with_code(side, side, QUIET * MODULE, QUIET * MODULE);
+        let img = RgbaImage::from_fn(side * 2, side, |x, y| {
+            if x >= side {
+                return *fake.get_pixel(x - side, y);
+            }
+            let col = i32::try_from(x / MODULE).unwrap() - 4;
+            let row = i32::try_from(y / MODULE).unwrap() - 4;
+            Rgba(if code.get_module(col, row) {
+                [0, 0, 0, 255]
+            } else {
+                [255; 4]
+            })
+        });
+
+        let mut detected = detect(&img);
+        detected.sort_by_key(|code| code.location.x);
+        assert_eq!(detected.len(), 2, "{detected:?}");
+        assert_eq!(detected[0].payload.as_deref(), Some("TICKET-42"));
+        assert_eq!(detected[1].payload, None);
+        let mut located = locate(&img);
+        located.sort_by_key(|code| code.x);
+        assert_eq!(
+            detected
+                .iter()
+                .map(|code| code.location)
+                .collect::<Vec<_>>(),
+            located
+        );
+
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let plain = detect_codes(&png, None, None).unwrap();
+        assert_eq!(plain.codes.len(), 2);
+        assert_eq!(plain.redacted, None);
+        let redacted = detect_codes(&png, Some(ImageFormat::Png), None).unwrap();
+        assert_eq!(redacted.codes, plain.codes);
+        let redacted = image::load_from_memory(&redacted.redacted.unwrap()).unwrap();
+        assert!(locate(&redacted.into_rgba8()).is_empty());
+    }
+}
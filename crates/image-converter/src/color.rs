@@ -0,0 +1,219 @@
+use std::fmt;
+
+/// Parses a CSS-style hex color into RGBA.
+///
+/// Accepts `#rgb`, `#rgba`, `#rrggbb` and `#rrggbbaa`, with or without the `#`.
+/// Colors without an alpha component are opaque.
+///
+/// # Errors
+///
+/// Returns `ColorError::InvalidColor` if the string is not a hex color.
+pub fn parse_color(text: &str) -> Result<[u8; 4], ColorError> {
+    let invalid = || ColorError::InvalidColor(text.to_owned());
+    let hex = text.trim();
+    let hex = hex.strip_prefix('#').unwrap_or(hex);
+    if !hex.is_ascii() {
+        return Err(invalid());
+    }
+
+    let digits = hex
+        .chars()
+        .map(|c| c.to_digit(16).and_then(|d| u8::try_from(d).ok()))
+        .collect::<Option<Vec<u8>>>()
+        .ok_or_else(invalid)?;
+    let channels: Vec<u8> = match digits.len() {
+        3 | 4 => digits.iter().map(|d| d * 17).collect(),
+        6 | 8 => digits
+            .chunks(2)
+            .map(|pair| pair.iter().fold(0, |acc, d| acc * 16 + d))
+            .collect(),
+        _ => return Err(invalid()),
+    };
+
+    let mut color = [0, 0, 0, u8::MAX];
+    for (slot, value) in color.iter_mut().zip(channels) {
+        *slot = value;
+    }
+    Ok(color)
+}
+
+/// Parses a comma-separated list of hex colors, e.g. `"#000, #fff, #f00"`.
+///
+/// # Errors
+///
+/// Returns `ColorError::InvalidColor` for the first entry that isn't a hex color.
+pub fn parse_palette(text: &str) -> Result<Vec<[u8; 4]>, ColorError> {
+    text.split(',').map(parse_color).collect()
+}
+
+/// Rec. 709 luma of an RGB color, matching `image`'s grayscale conversion.
+pub fn luminance(color: [u8; 4]) -> u8 {
+    let [r, g, b, _] = color.map(u32::from);
+    // The weights sum to 10000, so the result stays within 0..=255.
+    u8::try_from((r * 2126 + g * 7152 + b * 722 + 5000) / 10000).unwrap_or(u8::MAX)
+}
+
+/// Largest [`distance`] between two colors (black to white).
+pub const MAX_DISTANCE: f64 = 441.672_955_930_063_7;
+
+/// Euclidean distance between the RGB components of two colors; alpha is ignored.
+pub fn distance(a: [u8; 4], b: [u8; 4]) -> f64 {
+    a.iter()
+        .zip(b)
+        .take(3)
+        .map(|(x, y)| {
+            let diff = f64::from(*x) - f64::from(y);
+            diff * diff
+        })
+        .sum::<f64>()
+        .sqrt()
+}
+
+/// Linear interpolation between two colors, per channel; `t` is clamped to `0.0..=1.0`.
+pub fn lerp(from: [u8; 4], to: [u8; 4], t: f64) -> [u8; 4] {
+    let t = t.clamp(0.0, 1.0);
+    let mut out = from;
+    for (value, target) in out.iter_mut().zip(to) {
+        let start = f64::from(*value);
+        *value = to_u8(start + (f64::from(target) - start) * t);
+    }
+    out
+}
+
+/// Converts an sRGB-encoded channel value to linear light, in 0.0..=1.0.
+pub fn srgb_to_linear(value: u8) -> f64 {
+    let v = f64::from(value) / 255.0;
+    if v <= 0.040_45 {
+        v / 12.92
+    } else {
+        ((v + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts linear light (clamped to 0.0..=1.0) back to an sRGB-encoded channel value.
+pub fn linear_to_srgb(value: f64) -> u8 {
+    let v = value.clamp(0.0, 1.0);
+    let encoded = if v <= 0.003_130_8 {
+        v * 12.92
+    } else {
+        1.055 * v.powf(1.0 / 2.4) - 0.055
+    };
+    to_u8(encoded * 255.0)
+}
+
+/// Rounds and clamps a channel value to `u8`.
+pub fn to_u8(value: f64) -> u8 {
+    // Safe: the value is rounded and clamped to 0..=255 first.
+    #[allow(clippy::as_conversions)]
+    let byte = value.round().clamp(0.0, 255.0) as u8;
+    byte
+}
+
+/// Widens a pixel count or sum of channel values to `f64`.
+pub(crate) fn to_f64(value: u64) -> f64 {
+    // Safe: counts and sums of pixels stay far below f64's 53-bit mantissa.
+    #[allow(clippy::as_conversions)]
+    let widened = value as f64;
+    widened
+}
+
+/// Narrows a blur sigma or other small, finite value to `f32`.
+pub(crate) fn to_f32(value: f64) -> f32 {
+    // Safe: callers pass values well within f32's range; precision lost past its
+    // 24-bit mantissa doesn't matter for them.
+    #[allow(clippy::as_conversions)]
+    let narrowed = value as f32;
+    narrowed
+}
+
+/// Errors from parsing colors.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ColorError {
+    /// The string was not a `#rgb`, `#rgba`, `#rrggbb` or `#rrggbbaa` hex color.
+    InvalidColor(String),
+}
+
+impl fmt::Display for ColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidColor(text) => write!(
+                f,
+                "Invalid color \"{text}\" (expected #rgb, #rrggbb or #rrggbbaa)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ColorError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_forms() {
+        assert_eq!(parse_color("#ff8000").unwrap(), [255, 128, 0, 255]);
+        assert_eq!(parse_color("FF800080").unwrap(), [255, 128, 0, 128]);
+        assert_eq!(parse_color("#f80").unwrap(), [255, 136, 0, 255]);
+        assert_eq!(parse_color(" #0f08 ").unwrap(), [0, 255, 0, 136]);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_colors() {
+        for bad in ["", "#", "#12345", "#gggggg", "red", "#ff00ff00ff", "#é00"] {
+            assert!(
+                matches!(parse_color(bad), Err(ColorError::InvalidColor(_))),
+                "{bad}"
+            );
+        }
+    }
+
+    #[test]
+    fn parse_palette_lists() {
+        assert_eq!(
+            parse_palette("#000, #fff,#ff000080").unwrap(),
+            vec![[0, 0, 0, 255], [255; 4], [255, 0, 0, 128]]
+        );
+        assert!(parse_palette("#000,,#fff").is_err());
+    }
+
+    #[test]
+    fn luminance_matches_image_grayscale() {
+        for color in [[255, 0, 0, 255], [0, 255, 0, 255], [12, 200, 90, 255]] {
+            let expected = image::DynamicImage::ImageRgba8(image::RgbaImage::from_pixel(
+                1,
+                1,
+                image::Rgba(color),
+            ))
+            .into_luma8()
+            .get_pixel(0, 0)
+            .0[0];
+            assert_eq!(luminance(color), expected, "{color:?}");
+        }
+        assert_eq!(luminance([255, 255, 255, 0]), 255);
+    }
+
+    #[test]
+    fn distance_ignores_alpha() {
+        assert_eq!(distance([0, 0, 0, 0], [255, 255, 255, 255]), MAX_DISTANCE);
+        assert_eq!(distance([3, 4, 0, 255], [0, 0, 0, 0]), 5.0);
+    }
+
+    #[test]
+    fn srgb_linear_round_trip() {
+        for value in 0..=u8::MAX {
+            assert_eq!(linear_to_srgb(srgb_to_linear(value)), value);
+        }
+        assert!((srgb_to_linear(128) - 0.2158).abs() < 1e-4);
+    }
+
+    #[test]
+    fn lerp_endpoints_and_midpoint() {
+        let black = [0, 0, 0, 255];
+        let white = [255, 255, 255, 0];
+        assert_eq!(lerp(black, white, 0.0), black);
+        assert_eq!(lerp(black, white, 1.0), white);
+        assert_eq!(lerp(black, white, 0.5), [128, 128, 128, 128]);
+        assert_eq!(lerp(black, white, 7.0), white);
+    }
+}
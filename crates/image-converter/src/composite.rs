@@ -0,0 +1,298 @@
+use std::fmt;
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::color;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::{Fill, GenerateError};
+use crate::smart_crop::CropBox;
+
+/// What gets drawn over the base image.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    /// A decoded image, drawn at its own size.
+    Image(RgbaImage),
+    /// A gradient or pattern rendered at `width` x `height` (by default the base
+    /// image's size), so badge backgrounds, fades and ribbons need no bitmap assets.
+    Fill {
+        fill: Fill,
+        width: Option<u32>,
+        height: Option<u32>,
+    },
+}
+
+impl Source {
+    /// The source's pixels, for drawing over a base of `base_width` x `base_height`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `CompositeError::Fill` if a fill's size or settings are invalid.
+    pub fn render(&self, base_width: u32, base_height: u32) -> Result<RgbaImage, CompositeError> {
+        match self {
+            Self::Image(img) => Ok(img.clone()),
+            Self::Fill {
+                fill,
+                width,
+                height,
+            } => fill
+                .render(width.unwrap_or(base_width), height.unwrap_or(base_height))
+                .map_err(CompositeError::Fill),
+        }
+    }
+}
+
+/// Draws `layer` over `base` with its top-left corner at (`x`, `y`), which may be
+/// negative or past the edge; only the overlapping part is drawn. `opacity` (clamped
+/// to 0.0..=1.0) scales the layer's own alpha.
+pub fn overlay(base: &mut RgbaImage, layer: &RgbaImage, x: i64, y: i64, opacity: f64) {
+    let opacity = opacity.clamp(0.0, 1.0);
+    for (lx, ly, pixel) in layer.enumerate_pixels() {
+        let (Ok(bx), Ok(by)) = (
+            u32::try_from(x + i64::from(lx)),
+            u32::try_from(y + i64::from(ly)),
+        ) else {
+            continue;
+        };
+        if let Some(below) = base.get_pixel_mut_checked(bx, by) {
+            below.0 = over(below.0, pixel.0, opacity);
+        }
+    }
+}
+
+/// Porter-Duff "source over" on straight (non-premultiplied) RGBA, with the source's
+/// alpha scaled by `opacity`.
+pub fn over(below: [u8; 4], above: [u8; 4], opacity: f64) -> [u8; 4] {
+    let top = f64::from(above[3]) / 255.0 * opacity;
+    let bottom = f64::from(below[3]) / 255.0;
+    let alpha = top + bottom * (1.0 - top);
+    if alpha <= 0.0 {
+        return [0; 4];
+    }
+    let mut out = [0; 4];
+    for ((slot, &a), &b) in out.iter_mut().zip(&above).zip(&below).take(3) {
+        let mixed = f64::from(a) * top + f64::from(b) * bottom * (1.0 - top);
+        *slot = color::to_u8(mixed / alpha);
+    }
+    out[3] = color::to_u8(alpha * 255.0);
+    out
+}
+
+/// Clone stamp: copies `source` (which must lie inside the image) so its top-left
+/// corner lands at (`x`, `y`), covering a blemish or sensitive detail with nearby
+/// texture instead of a flat box. The copy fades out over its outer `feather` pixels
+/// so its edges blend into the surroundings; 0 gives hard edges. Parts that land past
+/// the edge of the image are dropped.
+///
+/// # Errors
+///
+/// Returns `CompositeError::PatchOutOfBounds` if `source` is empty or extends past the
+/// image.
+pub fn patch(
+    img: &mut RgbaImage,
+    source: CropBox,
+    x: u32,
+    y: u32,
+    feather: u32,
+) -> Result<(), CompositeError> {
+    let fits = |start: u32, len: u32, limit: u32| {
+        len > 0 && start.checked_add(len).is_some_and(|end| end <= limit)
+    };
+    if !(fits(source.x, source.width, img.width()) && fits(source.y, source.height, img.height())) {
+        return Err(CompositeError::PatchOutOfBounds(source));
+    }
+
+    let stamp =
+        image::imageops::crop_imm(img, source.x, source.y, source.width, source.height).to_image();
+    let ramp = f64::from(feather) + 1.0;
+    for (sx, sy, pixel) in stamp.enumerate_pixels() {
+        let (Some(dx), Some(dy)) = (x.checked_add(sx), y.checked_add(sy)) else {
+            continue;
+        };
+        let Some(below) = img.get_pixel_mut_checked(dx, dy) else {
+            continue;
+        };
+        let edge = sx
+            .min(sy)
+            .min(source.width - 1 - sx)
+            .min(source.height - 1 - sy);
+        let weight = ((f64::from(edge) + 1.0) / ramp).min(1.0);
+        for (value, &stamped) in below.0.iter_mut().zip(&pixel.0) {
+            let (old, new) = (f64::from(*value), f64::from(stamped));
+            *value = color::to_u8(old + (new - old) * weight);
+        }
+    }
+    Ok(())
+}
+
+/// Decodes `input`, clones `source` to (`x`, `y`) with [`patch`], and encodes the
+/// result as `target`.
+///
+/// # Errors
+///
+/// Returns a `CompositeError` if the input cannot be decoded, `source` doesn't fit in
+/// the image, or the output cannot be encoded.
+pub fn patch_image(
+    input: &[u8],
+    source: CropBox,
+    x: u32,
+    y: u32,
+    feather: u32,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, CompositeError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(CompositeError::Decode)?
+        .into_rgba8();
+    patch(&mut img, source, x, y, feather)?;
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality)
+        .map_err(CompositeError::Convert)
+}
+
+/// Decodes `input`, draws `source` over it with [`overlay`], and encodes the result as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns a `CompositeError` if the input cannot be decoded, the fill is invalid, or
+/// the output cannot be encoded.
+pub fn composite(
+    input: &[u8],
+    source: &Source,
+    x: i64,
+    y: i64,
+    opacity: f64,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, CompositeError> {
+    let mut base = image::load_from_memory(input)
+        .map_err(CompositeError::Decode)?
+        .into_rgba8();
+    let layer = source.render(base.width(), base.height())?;
+    overlay(&mut base, &layer, x, y, opacity);
+    convert::encode(&DynamicImage::ImageRgba8(base), target, quality)
+        .map_err(CompositeError::Convert)
+}
+
+/// Errors that can occur while compositing.
+#[derive(Debug)]
+pub enum CompositeError {
+    /// Failed to decode an input image.
+    Decode(image::ImageError),
+    /// A fill source could not be rendered.
+    Fill(GenerateError),
+    /// A patch's source rectangle was empty or extended past the image.
+    PatchOutOfBounds(CropBox),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for CompositeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Fill(e) => write!(f, "{e}"),
+            Self::PatchOutOfBounds(rect) => write!(
+                f,
+                "Patch source {}x{} at ({}, {}) must be non-empty and inside the image",
+                rect.width, rect.height, rect.x, rect.y
+            ),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for CompositeError {}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn over_blends_by_alpha_and_opacity() {
+        let white = [255; 4];
+        assert_eq!(over(white, [0, 0, 0, 255], 1.0), [0, 0, 0, 255]);
+        assert_eq!(over(white, [0, 0, 0, 255], 0.5), [128, 128, 128, 255]);
+        assert_eq!(over(white, [0, 0, 0, 0], 1.0), white);
+        // Over a transparent base the layer keeps its own color.
+        assert_eq!(over([0; 4], [200, 10, 10, 128], 1.0), [200, 10, 10, 128]);
+    }
+
+    #[test]
+    fn overlay_clips_to_the_base() {
+        let mut base = RgbaImage::from_pixel(4, 4, Rgba([255; 4]));
+        let layer = RgbaImage::from_pixel(3, 3, Rgba([0, 0, 0, 255]));
+        overlay(&mut base, &layer, -1, 2, 1.0);
+        assert_eq!(base.get_pixel(0, 1).0, [255; 4]);
+        assert_eq!(base.get_pixel(1, 3).0, [0, 0, 0, 255]);
+        assert_eq!(base.get_pixel(2, 3).0, [255; 4]);
+    }
+
+    #[test]
+    fn fills_are_sources() {
+        let fade = Source::Fill {
+            fill: Fill::LinearGradient {
+                from: [0, 0, 0, 0],
+                to: [0, 0, 0, 255],
+                angle: 90.0,
+            },
+            width: None,
+            height: None,
+        };
+        let mut base = RgbaImage::from_pixel(8, 8, Rgba([255; 4]));
+        let layer = fade.render(8, 8).unwrap();
+        assert_eq!(layer.dimensions(), (8, 8));
+        overlay(&mut base, &layer, 0, 0, 1.0);
+        assert!(base.get_pixel(4, 0).0[0] > 230);
+        assert!(base.get_pixel(4, 7).0[0] < 25);
+
+        let empty = Source::Fill {
+            fill: Fill::Solid { color: [0; 4] },
+            width: Some(0),
+            height: None,
+        };
+        assert!(matches!(empty.render(8, 8), Err(CompositeError::Fill(_))));
+    }
+
+    #[test]
+    fn patch_clones_a_rectangle_with_feathered_edges() {
+        let mut img = RgbaImage::from_fn(20, 10, |x, _| {
+            if x < 10 {
+                Rgba([200, 100, 50, 255])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        let source = CropBox {
+            x: 0,
+            y: 0,
+            width: 8,
+            height: 8,
+        };
+
+        let mut hard = img.clone();
+        patch(&mut hard, source, 11, 1, 0).unwrap();
+        assert_eq!(hard.get_pixel(11, 1).0, [200, 100, 50, 255]);
+        assert_eq!(hard.get_pixel(18, 8).0, [200, 100, 50, 255]);
+        assert_eq!(hard.get_pixel(19, 9).0, [0, 0, 0, 255]);
+        assert_eq!(hard.get_pixel(10, 5).0, [0, 0, 0, 255]);
+
+        patch(&mut img, source, 11, 1, 3).unwrap();
+        assert_eq!(img.get_pixel(14, 4).0, [200, 100, 50, 255]);
+        assert_eq!(img.get_pixel(11, 4).0, [50, 25, 13, 255]);
+        assert!(img.get_pixel(12, 4).0[0] > img.get_pixel(11, 4).0[0]);
+
+        // Stamps hanging off the edge are clipped; sources must fit.
+        patch(&mut img, source, 15, 5, 0).unwrap();
+        assert_eq!(img.get_pixel(19, 9).0, [200, 100, 50, 255]);
+        let outside = CropBox { x: 15, ..source };
+        assert!(matches!(
+            patch(&mut img, outside, 0, 0, 0),
+            Err(CompositeError::PatchOutOfBounds(_))
+        ));
+        let empty = CropBox { width: 0, ..source };
+        assert!(patch(&mut img, empty, 0, 0, 0).is_err());
+    }
+}
@@ -1,10 +1,28 @@
+use std::borrow::Cow;
 use std::io::Cursor;
+use std::rc::Rc;
 
+use image::codecs::gif::GifEncoder;
 use image::codecs::jpeg::JpegEncoder;
 use image::codecs::png::{CompressionType, FilterType, PngEncoder};
-use image::ImageReader;
-
+use image::{Delay, DynamicImage, Frame, ImageDecoder, ImageReader, RgbaImage};
+use serde::Serialize;
+
+use crate::animation::{self, AnimationError};
+use crate::codecs::{self, CodecError, Decoder};
+use crate::dither;
+use crate::events::TimedOperation;
+use crate::exif_write::{self, ExifEdits, ExifWriteError};
 use crate::formats::ImageFormat;
+use crate::ico;
+use crate::operations::{OperationError, OperationStep};
+use crate::png_chunks::{self, ColorTag, PngChunkError, PngChunkPolicy};
+use crate::proof;
+use crate::psd::{self, PsdDecoder};
+use crate::quantize::{self, IndexedPng, QuantizeError, QuantizeOptions};
+use crate::region;
+use crate::resize::{self, ResizeError, ResizeMode, ResizeOptions};
+use crate::smart_crop::CropBox;
 use crate::transforms::{self, Transform};
 
 /// Result of reading image dimensions.
@@ -14,6 +32,291 @@ pub struct Dimensions {
     pub height: u32,
 }
 
+/// Per-conversion encoder settings beyond the target format.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ConvertOptions {
+    /// Output quality (1-100); see [`convert`]. Animations are encoded at it too.
+    pub quality: Option<u8>,
+    /// Which ancillary chunks PNG output carries over from the source. Ignored for
+    /// other targets and for animated PNG output.
+    pub png_chunks: PngChunkPolicy,
+    /// How PNG output is tagged with color-space chunks. Ignored for other targets
+    /// and for animated PNG output.
+    pub png_color_tag: ColorTag,
+    /// Whether PNG output is written as indexed color (PNG8). Ignored for other
+    /// targets and for animated PNG output.
+    pub png_indexed: IndexedPng,
+    /// Dither 16-bit and float sources when the output only holds 8 bits per channel,
+    /// instead of rounding each sample.
+    pub dither_16bit: bool,
+    /// Builds the palette of GIF output with [`quantize::quantize_gif`] instead of
+    /// leaving it to the GIF encoder. Animated GIF output gets one palette shared by
+    /// every frame ([`quantize::quantize_gif_frames`]). Ignored for other targets.
+    pub gif_quantize: Option<QuantizeOptions>,
+    /// Guarantees byte-identical output for identical input and options, on every
+    /// build; see [`convert_with_options`] for what that pins down.
+    pub deterministic: bool,
+    /// The format to decode the input as when its bytes don't identify one, as for TGA,
+    /// which has no magic bytes. A recognized input is decoded as what it is.
+    pub source_format: Option<ImageFormat>,
+    /// Which size of a multi-size ICO input to decode: the smallest at least this many
+    /// pixels on its longer side (see [`ico::select`]). By default, and when no size is
+    /// that large, the largest is decoded.
+    pub icon_size: Option<u32>,
+    /// A memory budget for the conversion, in bytes, checked against
+    /// [`ConversionPlan::approx_memory_bytes`] before anything is decoded. Over budget,
+    /// tiled and striped TIFFs are decoded at reduced resolution (see
+    /// [`region::decode_reduced`]), and `crop`, `resize` and `watermark` are scaled
+    /// down by the same factor; anything else fails with
+    /// [`ConvertError::LimitExceeded`].
+    pub max_memory_bytes: Option<u64>,
+    /// Crops the image to this rectangle after the transforms. Animations are cropped
+    /// frame by frame.
+    pub crop: Option<CropBox>,
+    /// Registered [`operations`](crate::operations) to run after the crop, in order.
+    /// Animations run them frame by frame.
+    pub operations: Vec<OperationStep>,
+    /// Resizes the image after the transforms and operations, which leaves it as 8-bit
+    /// RGBA. Animations are resized frame by frame.
+    pub resize: Option<ResizeStep>,
+    /// Tiles a watermark across the image after every other step, which leaves it as
+    /// 8-bit RGBA. Animations are watermarked frame by frame.
+    pub watermark: Option<WatermarkStep>,
+    /// EXIF fields to write into JPEG and PNG output (see [`exif_write::set_exif`]),
+    /// amending whatever EXIF data the output already carries. Ignored for other
+    /// targets.
+    pub exif: Option<ExifEdits>,
+    /// Removes the GPS fields from EXIF data the output carries (see
+    /// [`exif_write::strip_gps`]), keeping the rest. Ignored for targets other than
+    /// JPEG and PNG.
+    pub strip_gps: bool,
+}
+
+/// JPEG quality used when [`ConvertOptions::quality`] is unset.
+pub const DEFAULT_JPEG_QUALITY: u8 = 80;
+
+/// Largest factor a reduced-resolution decode divides each side by.
+pub const MAX_DECODE_REDUCTION: u32 = 32;
+
+/// A resize done as part of a conversion; see [`resize::resize_image`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeStep {
+    pub mode: ResizeMode,
+    pub width: u32,
+    pub height: u32,
+    pub options: ResizeOptions,
+}
+
+impl ResizeStep {
+    fn apply(&self, img: &DynamicImage) -> Result<DynamicImage, ConvertError> {
+        self.apply_reduced(img, img.width(), img.height())
+    }
+
+    /// Applies the step to `img` decoded at reduced resolution from a `source_width` x
+    /// `source_height` original; see [`resize::resize_reduced`].
+    fn apply_reduced(
+        &self,
+        img: &DynamicImage,
+        source_width: u32,
+        source_height: u32,
+    ) -> Result<DynamicImage, ConvertError> {
+        resize::resize_reduced(
+            &img.to_rgba8(),
+            source_width,
+            source_height,
+            self.mode,
+            self.width,
+            self.height,
+            self.options,
+        )
+        .map(DynamicImage::ImageRgba8)
+        .map_err(|e| ConvertError::Resize(Box::new(e)))
+    }
+
+    /// The same step for an image decoded with each side divided by `factor`.
+    fn reduced(self, factor: u32) -> Self {
+        Self {
+            width: self.width.div_ceil(factor),
+            height: self.height.div_ceil(factor),
+            ..self
+        }
+    }
+}
+
+/// A watermark drawn as part of a conversion: `logo` repeated across the image at
+/// `opacity` (0.0 to 1.0), as [`proof::tile_watermark`] draws it.
+#[derive(Debug, Clone, PartialEq)]
+pub struct WatermarkStep {
+    pub logo: RgbaImage,
+    pub opacity: f64,
+}
+
+impl WatermarkStep {
+    fn apply(&self, img: DynamicImage) -> DynamicImage {
+        let mut rgba = img.into_rgba8();
+        proof::tile_watermark(&mut rgba, &self.logo, self.opacity);
+        DynamicImage::ImageRgba8(rgba)
+    }
+
+    /// The same step for an image decoded with each side divided by `factor`: the
+    /// logo shrinks with it, so it covers the same share of the image.
+    fn reduced(&self, factor: u32) -> Self {
+        let logo = image::imageops::resize(
+            &self.logo,
+            self.logo.width().div_ceil(factor),
+            self.logo.height().div_ceil(factor),
+            image::imageops::FilterType::Triangle,
+        );
+        Self {
+            logo,
+            opacity: self.opacity,
+        }
+    }
+}
+
+/// Crops `img` to `rect`, which must be non-empty and inside it.
+fn crop(img: &DynamicImage, rect: CropBox) -> Result<DynamicImage, ConvertError> {
+    if !crop_fits(rect, img.width(), img.height()) {
+        return Err(ConvertError::InvalidCrop {
+            crop: rect,
+            width: img.width(),
+            height: img.height(),
+        });
+    }
+    Ok(img.crop_imm(rect.x, rect.y, rect.width, rect.height))
+}
+
+/// `rect` on an image decoded with each side divided by `factor`, grown outward to
+/// whole pixels. A rect inside the full image stays inside the reduced one, whose
+/// sides are rounded up.
+fn reduce_crop(rect: CropBox, factor: u32) -> CropBox {
+    let (x, y) = (rect.x / factor, rect.y / factor);
+    let end = |start: u32, len: u32| {
+        let end = u64::from(start) + u64::from(len);
+        u32::try_from(end.div_ceil(u64::from(factor))).unwrap_or(u32::MAX)
+    };
+    CropBox {
+        x,
+        y,
+        width: end(rect.x, rect.width) - x,
+        height: end(rect.y, rect.height) - y,
+    }
+}
+
+/// Whether `rect` is non-empty and inside a `width` x `height` image.
+fn crop_fits(rect: CropBox, width: u32, height: u32) -> bool {
+    let fits = |start: u32, len: u32, limit: u32| {
+        len > 0 && start.checked_add(len).is_some_and(|end| end <= limit)
+    };
+    fits(rect.x, rect.width, width) && fits(rect.y, rect.height, height)
+}
+
+/// Output of [`convert_with_report`].
+#[derive(Debug, Clone)]
+pub struct ReportedConversion {
+    pub data: Vec<u8>,
+    pub report: ConversionReport,
+}
+
+/// Where a conversion spent its time and memory, and what it actually did.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct ConversionReport {
+    /// Decoding the input, including reading metadata carried into PNG output.
+    pub decode_ms: f64,
+    /// Each step between decoding and encoding, in order. For animations, a
+    /// transform's time covers every frame.
+    pub ops: Vec<OpTiming>,
+    /// Encoding the output, including palette building and chunk insertion.
+    pub encode_ms: f64,
+    pub input_bytes: u64,
+    pub output_bytes: u64,
+    /// Output dimensions.
+    pub width: u32,
+    pub height: u32,
+    /// Frames written; 1 for still output.
+    pub frames: u32,
+    /// Rough estimate of the most memory held by buffers at once: the input alongside
+    /// the decoded pixels, a transform's source and result, or the final pixels
+    /// alongside any RGBA copy and the output.
+    pub peak_memory_bytes: u64,
+    pub applied: AppliedOptions,
+}
+
+/// Time taken by one processing step.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct OpTiming {
+    /// A transform name (see [`Transform::name`]), a registered operation's name,
+    /// `"crop"`, `"resize"`, `"watermark"` or `"dither_16bit"`.
+    pub name: &'static str,
+    pub ms: f64,
+}
+
+/// The settings a conversion ended up using, after defaults and fallbacks.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct AppliedOptions {
+    pub target: &'static str,
+    /// The JPEG quality (80 unless given), or the PNG compression or GIF quantizer
+    /// quality as given; `None` for outputs that ignore quality.
+    pub quality: Option<u8>,
+    pub transforms: Vec<&'static str>,
+    /// Whether a 16-bit or float image was dithered down to 8 bits.
+    pub dithered_16bit: bool,
+    /// Whether the output is an indexed (PNG8) PNG.
+    pub indexed_png: bool,
+    /// Whether the GIF palette came from [`quantize`] rather than the GIF encoder.
+    pub quantized_gif: bool,
+    /// Ancillary chunks written into PNG output, in file order.
+    pub png_chunks: Vec<String>,
+    pub deterministic: bool,
+    /// The factor each side was divided by when `max_memory_bytes` forced a
+    /// reduced-resolution decode. The crop, resize and watermark were divided by it
+    /// too, so the output is the full-resolution result at that reduction.
+    pub reduced_by: Option<u32>,
+}
+
+/// What a conversion would produce, worked out by [`plan`] from the input's headers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ConversionPlan {
+    pub input_format: &'static str,
+    pub target: &'static str,
+    pub input_width: u32,
+    pub input_height: u32,
+    /// Output dimensions, after transforms.
+    pub width: u32,
+    pub height: u32,
+    /// Frames the output will have; 1 unless an animation is kept.
+    pub frames: u32,
+    /// Rough guess at the output size, tuned for photographic content. Flat graphics
+    /// usually compress much better than this for PNG, GIF and QOI.
+    pub approx_bytes: u64,
+    /// Rough peak memory of the conversion: the input, the decoded pixels and one
+    /// working copy of them, and the output. Compare with
+    /// [`ConvertOptions::max_memory_bytes`].
+    pub approx_memory_bytes: u64,
+    /// Steps that lose or may lose information, in pipeline order.
+    pub lossy_steps: Vec<LossyStep>,
+}
+
+/// A step of a conversion that can lose information.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LossyStep {
+    /// Only the first frame of an animation is kept.
+    AnimationFlattened,
+    /// A `grayscale` transform discards color.
+    Grayscale,
+    /// 16-bit or float samples are reduced to 8 bits, dithered with
+    /// [`ConvertOptions::dither_16bit`] and rounded otherwise.
+    BitDepthReduced,
+    /// The input has an alpha channel the output can't store.
+    AlphaDropped,
+    /// GIF output holds at most 256 colors per frame and only fully opaque or fully
+    /// transparent pixels; lossless only for inputs that already fit.
+    PaletteReduced,
+    /// JPEG's lossy compression.
+    JpegCompression,
+}
+
 /// Decodes the input image bytes, applies any requested transforms, and re-encodes
 /// in the target format.
 ///
@@ -22,11 +325,18 @@ pub struct Dimensions {
 ///
 /// An optional `quality` parameter (1-100) controls output quality for formats
 /// that support it: JPEG uses it directly as encoder quality, PNG maps it to
-/// compression levels 1-9. Formats without quality support ignore this parameter.
+/// compression levels 1-9, and GIF to the speed of the encoder's quantizer (30 for
+/// quality 1 down to 1 for 100). Formats without quality support ignore this parameter.
 ///
 /// The `transforms` slice specifies image transforms (flip, rotate, grayscale, invert)
 /// to apply in order between decoding and encoding. An empty slice skips transforms.
 ///
+/// Animated inputs keep their animation when the target can carry it: any animated
+/// GIF/WebP/APNG converted to GIF, or an APNG converted to PNG, is re-encoded frame by
+/// frame with transforms applied to every frame, at the same `quality` as a still
+/// image, and with the original delays preserved. Other targets receive the first
+/// frame.
+///
 /// Returns the encoded image as a byte vector.
 pub fn convert(
     input: Vec<u8>,
@@ -34,27 +344,799 @@ pub fn convert(
     quality: Option<u8>,
     transforms_list: &[Transform],
 ) -> Result<Vec<u8>, ConvertError> {
+    let options = ConvertOptions {
+        quality,
+        ..ConvertOptions::default()
+    };
+    convert_with_options(input, target, &options, transforms_list)
+}
+
+/// Like [`convert`], with the full set of [`ConvertOptions`].
+///
+/// PNG output gets the source's ancillary chunks that `options.png_chunks` keeps
+/// (see [`png_chunks::carried_chunks`]); the default policy writes none. Its color
+/// space is then tagged per `options.png_color_tag` (see
+/// [`png_chunks::apply_color_tag`]), by default from the source's own color chunks or
+/// ICC profile, falling back to sRGB. A profile for a different color model than the
+/// encoded PNG, e.g. an RGB profile on grayscale output, is replaced by the sRGB tag
+/// (see [`png_chunks::match_color_model`]).
+///
+/// With `options.png_indexed` set, PNG output uses an exact palette of the image's
+/// colors (see [`quantize::exact_indexed_png`]), which implies 8 bits per channel.
+/// `Require` fails with [`ConvertError::TooManyColors`] rather than falling back to
+/// truecolor.
+///
+/// With `options.gif_quantize`, GIF output gets its palette from
+/// [`quantize::quantize_gif`], with a choice of quantizer; animations share one
+/// palette across all frames.
+///
+/// With `options.dither_16bit`, 16-bit and float images are reduced to 8 bits with
+/// [`dither::dither_to_8bit`] whenever the output can't keep 16 bits: every target
+/// except PNG and TIFF, and indexed PNG.
+///
+/// With `options.deterministic`, the output bytes depend only on the input and the
+/// options, so they can be cached or content-addressed:
+/// - PNG (including indexed and APNG): fixed compression and filter settings; carried
+///   chunks keep their source order, and `tIME` is never carried.
+/// - JPEG: always the built-in baseline encoder, so builds with and without the
+///   `mozjpeg` feature agree.
+/// - GIF, BMP, TIFF, ICO, TGA, QOI and WebP: the encoders write no timestamps or
+///   other variable fields.
+///
+/// Palettes (GIF quantization, PNG8) are built from colors in sorted order whether
+/// or not the option is set.
+pub fn convert_with_options(
+    input: Vec<u8>,
+    target: ImageFormat,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+) -> Result<Vec<u8>, ConvertError> {
+    convert_with_report(input, target, options, transforms_list).map(|converted| converted.data)
+}
+
+/// Like [`convert_with_options`], also returning a [`ConversionReport`] of stage
+/// timings, sizes and the settings actually applied.
+///
+/// # Errors
+///
+/// Returns a `ConvertError` under the same conditions as [`convert_with_options`].
+pub fn convert_with_report(
+    input: Vec<u8>,
+    target: ImageFormat,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+) -> Result<ReportedConversion, ConvertError> {
+    let quality = options.quality;
     if let Some(q) = quality {
         if q == 0 || q > 100 {
             return Err(ConvertError::InvalidQuality(q));
         }
     }
 
-    let decoded = image::load_from_memory(&input).map_err(ConvertError::Decode)?;
+    let reduced = match options.max_memory_bytes {
+        Some(limit) => reduce_to_fit(&input, target, options, transforms_list, limit)?,
+        None => None,
+    };
+
+    let conversion = TimedOperation::start("convert");
+    let mut report = ConversionReport {
+        input_bytes: byte_len(&input),
+        frames: 1,
+        applied: AppliedOptions {
+            target: target.as_str(),
+            transforms: transforms_list.iter().map(|t| t.name()).collect(),
+            deterministic: options.deterministic,
+            ..AppliedOptions::default()
+        },
+        ..ConversionReport::default()
+    };
+
+    if let Some(data) = convert_animated(&input, target, options, transforms_list, &mut report)? {
+        let data = write_exif(data, target, options)?;
+        report.output_bytes = byte_len(&data);
+        conversion.finish(report.output_bytes);
+        return Ok(ReportedConversion { data, report });
+    }
+
+    let decoding = TimedOperation::start("decode");
+    let mut carried = if target == ImageFormat::Png {
+        let mut carried = png_chunks::carried_chunks(&input, &options.png_chunks, transforms_list);
+        png_chunks::apply_color_tag(
+            &mut carried,
+            options.png_color_tag,
+            &options.png_chunks,
+            &input,
+        );
+        carried
+    } else {
+        Vec::new()
+    };
+    if options.deterministic && carried.iter().any(|chunk| &chunk.kind == b"tIME") {
+        log::debug!("deterministic output: dropping the tIME chunk");
+        carried.retain(|chunk| &chunk.kind != b"tIME");
+    }
+
+    // A reduced decode shrinks every pixel-sized step with it, so the output is the
+    // full-resolution result with each side divided by the same factor. Otherwise a
+    // downscale may still decode at reduced resolution, resized from the source's size.
+    let mut resize_source = None;
+    let (mut decoded, crop_rect, resize_step, watermark) = match reduced {
+        Some((reduced, factor)) => {
+            report.applied.reduced_by = Some(factor);
+            (
+                DynamicImage::ImageRgba8(reduced),
+                options.crop.map(|rect| reduce_crop(rect, factor)),
+                options.resize.map(|step| step.reduced(factor)),
+                options
+                    .watermark
+                    .as_ref()
+                    .map(|step| Cow::Owned(step.reduced(factor))),
+            )
+        }
+        None => (
+            match decode_for_resize(&input, options, transforms_list)? {
+                Some((reduced, width, height)) => {
+                    resize_source = Some((width, height));
+                    DynamicImage::ImageRgba8(reduced)
+                }
+                None => decode_input(&input, options.source_format, options.icon_size)?,
+            },
+            options.crop,
+            options.resize,
+            options.watermark.as_ref().map(Cow::Borrowed),
+        ),
+    };
+    report.decode_ms = decoding.finish(byte_len(decoded.as_bytes()));
 
     // Drop the input buffer now that decoding is complete due to the limited memory environment of WASM. This allows the memory used by the input bytes to be freed before we attempt to encode the output, which can help avoid OOM errors when processing large images.
     drop(input);
+    let mut peak = report.input_bytes + byte_len(decoded.as_bytes());
+
+    for &transform in transforms_list {
+        let step = TimedOperation::start(transform.name());
+        let before = byte_len(decoded.as_bytes());
+        decoded = transform.apply(decoded);
+        let after = byte_len(decoded.as_bytes());
+        peak = peak.max(before + after);
+        report.ops.push(OpTiming {
+            name: transform.name(),
+            ms: step.finish(after),
+        });
+    }
+    if let Some(rect) = crop_rect {
+        let step = TimedOperation::start("crop");
+        let before = byte_len(decoded.as_bytes());
+        decoded = crop(&decoded, rect)?;
+        let after = byte_len(decoded.as_bytes());
+        peak = peak.max(before + after);
+        report.ops.push(OpTiming {
+            name: "crop",
+            ms: step.finish(after),
+        });
+    }
+    for operation_step in &options.operations {
+        let operation = operation_step.resolve().map_err(ConvertError::Operation)?;
+        let step = TimedOperation::start(operation.name());
+        let before = byte_len(decoded.as_bytes());
+        decoded = operation
+            .apply(decoded, &operation_step.params)
+            .map_err(ConvertError::Operation)?;
+        let after = byte_len(decoded.as_bytes());
+        peak = peak.max(before + after);
+        report.ops.push(OpTiming {
+            name: operation.name(),
+            ms: step.finish(after),
+        });
+    }
+    if let Some(resize_step) = resize_step {
+        let step = TimedOperation::start("resize");
+        let before = byte_len(decoded.as_bytes());
+        decoded = match resize_source {
+            Some((width, height)) => resize_step.apply_reduced(&decoded, width, height)?,
+            None => resize_step.apply(&decoded)?,
+        };
+        let after = byte_len(decoded.as_bytes());
+        peak = peak.max(before + after);
+        report.ops.push(OpTiming {
+            name: "resize",
+            ms: step.finish(after),
+        });
+    }
+    if let Some(watermark) = &watermark {
+        let step = TimedOperation::start("watermark");
+        let before = byte_len(decoded.as_bytes());
+        decoded = watermark.apply(decoded);
+        let after = byte_len(decoded.as_bytes());
+        peak = peak.max(before + after);
+        report.ops.push(OpTiming {
+            name: "watermark",
+            ms: step.finish(after),
+        });
+    }
+    let color = decoded.color();
+    let wide = color.bits_per_pixel() / u16::from(color.channel_count()) > 8;
+    if wide && options.dither_16bit && !keeps_16bit(target, options.png_indexed) {
+        log::debug!("dithering {color:?} down to 8 bits for {}", target.as_str());
+        let step = TimedOperation::start("dither_16bit");
+        let before = byte_len(decoded.as_bytes());
+        decoded = dither::dither_to_8bit(decoded);
+        let after = byte_len(decoded.as_bytes());
+        peak = peak.max(before + after);
+        report.ops.push(OpTiming {
+            name: "dither_16bit",
+            ms: step.finish(after),
+        });
+        report.applied.dithered_16bit = true;
+    }
+
+    let encoding = TimedOperation::start("encode");
+    let rgba_copy = u64::from(decoded.width()) * u64::from(decoded.height()) * 4;
+    let mut scratch = 0;
+    let mut output = match (target, options.png_indexed, options.gif_quantize) {
+        (ImageFormat::Gif, _, Some(gif_options)) => {
+            scratch = rgba_copy;
+            report.applied.quantized_gif = true;
+            quantize::quantize_gif(&decoded.to_rgba8(), &gif_options)
+                .map_err(ConvertError::Quantize)?
+        }
+        (ImageFormat::Png, IndexedPng::Prefer | IndexedPng::Require, _) => {
+            scratch = rgba_copy;
+            match quantize::exact_indexed_png(&decoded.to_rgba8())
+                .map_err(ConvertError::Quantize)?
+            {
+                Some(indexed) => {
+                    log::debug!("writing an indexed PNG");
+                    report.applied.indexed_png = true;
+                    indexed
+                }
+                None if options.png_indexed == IndexedPng::Require => {
+                    return Err(ConvertError::TooManyColors)
+                }
+                None => {
+                    log::debug!("more than 256 colors, falling back to a truecolor PNG");
+                    encode(&decoded, target, quality)?
+                }
+            }
+        }
+        (ImageFormat::Jpeg, _, _) if options.deterministic => {
+            log::debug!("deterministic output: using the built-in JPEG encoder");
+            encode_builtin_jpeg(&decoded, quality)?
+        }
+        _ => encode(&decoded, target, quality)?,
+    };
+    if let Some(model) = png_chunks::ColorModel::of_png(&output) {
+        png_chunks::match_color_model(&mut carried, model, &options.png_chunks);
+    }
+    png_chunks::insert_chunks(&mut output, &carried).map_err(ConvertError::PngChunks)?;
+    let output = write_exif(output, target, options)?;
+    report.output_bytes = byte_len(&output);
+    report.encode_ms = encoding.finish(report.output_bytes);
+
+    report.width = decoded.width();
+    report.height = decoded.height();
+    report.peak_memory_bytes =
+        peak.max(byte_len(decoded.as_bytes()) + scratch + report.output_bytes);
+    report.applied.quality = applied_quality(target, quality, &report.applied);
+    report.applied.png_chunks = carried
+        .iter()
+        .map(|chunk| String::from_utf8_lossy(&chunk.kind).into_owned())
+        .collect();
+    let ms = conversion.finish(report.output_bytes);
+    log::debug!(
+        "converted to {}x{} {} ({} bytes) in {ms:.1} ms",
+        report.width,
+        report.height,
+        target.as_str(),
+        report.output_bytes
+    );
+    Ok(ReportedConversion {
+        data: output,
+        report,
+    })
+}
+
+/// Like [`convert_with_options`], writing the format of the [`codecs::Encoder`]
+/// registered under `encoder` instead of a built-in one.
+///
+/// Transforms, operations and `resize` run as usual, and animations keep their first
+/// frame. The options that configure built-in encoders (PNG chunks and tagging,
+/// indexed PNG, GIF palettes) and `max_memory_bytes` don't apply.
+///
+/// # Errors
+///
+/// Returns a `ConvertError` if no encoder is registered under the name, the quality is
+/// out of range, the input can't be decoded, a step fails, or the encoder fails.
+pub fn convert_with_encoder(
+    input: Vec<u8>,
+    encoder: &str,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+) -> Result<Vec<u8>, ConvertError> {
+    let encoder = codecs::find_encoder(encoder).ok_or_else(|| {
+        ConvertError::UnsupportedTarget(format!("No encoder is registered as {encoder:?}"))
+    })?;
+    if let Some(q) = options.quality {
+        if q == 0 || q > 100 {
+            return Err(ConvertError::InvalidQuality(q));
+        }
+    }
+
+    let mut decoded = decode_input(&input, options.source_format, options.icon_size)?;
+    drop(input);
+    decoded = transforms::apply_transforms(decoded, transforms_list);
+    if let Some(rect) = options.crop {
+        decoded = crop(&decoded, rect)?;
+    }
+    for operation_step in &options.operations {
+        let operation = operation_step.resolve().map_err(ConvertError::Operation)?;
+        decoded = operation
+            .apply(decoded, &operation_step.params)
+            .map_err(ConvertError::Operation)?;
+    }
+    if let Some(resize_step) = options.resize {
+        decoded = resize_step.apply(&decoded)?;
+    }
+    if let Some(watermark) = &options.watermark {
+        decoded = watermark.apply(decoded);
+    }
+    encoder
+        .encode(&decoded, options.quality)
+        .map_err(ConvertError::Codec)
+}
+
+/// Works out what [`convert_with_options`] would do with `input` without decoding any
+/// pixels: the output dimensions and frame count, a rough size estimate, and which
+/// steps would lose information.
+///
+/// Only the headers are read (inputs for a registered [`codecs::Decoder`] are decoded,
+/// as decoders don't expose headers), so a plan can succeed for an input whose pixel data
+/// turns out to be corrupt, and `IndexedPng::Require` can still fail the conversion
+/// with `ConvertError::TooManyColors`.
+///
+/// # Errors
+///
+/// Returns a `ConvertError` if the quality is out of range, the target cannot be
+/// encoded, the input's headers cannot be read, or the output would be larger than
+/// the target allows (ICO holds at most 256×256 pixels).
+pub fn plan(
+    input: &[u8],
+    target: ImageFormat,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+) -> Result<ConversionPlan, ConvertError> {
+    if let Some(q) = options.quality {
+        if q == 0 || q > 100 {
+            return Err(ConvertError::InvalidQuality(q));
+        }
+    }
+    let icon = ico::select_entry(input, options.icon_size);
+    let input = icon.as_deref().unwrap_or(input);
+    target
+        .to_image_format()
+        .map_err(|e| ConvertError::UnsupportedTarget(e.to_string()))?;
+    let (input_format, (input_width, input_height), color, still_bytes) =
+        match extra_decoder(input, options.source_format) {
+            // Registered decoders have no header-only path, so the input is decoded.
+            Some(decoder) => {
+                let decoded = decoder.decode(input).map_err(ConvertError::Codec)?;
+                let bytes = byte_len(decoded.as_bytes());
+                let dimensions = (decoded.width(), decoded.height());
+                (decoder.name(), dimensions, decoded.color(), bytes)
+            }
+            None => {
+                let decoder = open(input, options.source_format)?
+                    .into_decoder()
+                    .map_err(ConvertError::Decode)?;
+                // Only our formats' codecs are compiled in, so whatever decodes is either
+                // detected or the given source format.
+                let input_format = ImageFormat::detect_from_bytes(input)
+                    .ok()
+                    .or(options.source_format)
+                    .map_or("unknown", |f| f.as_str());
+                (
+                    input_format,
+                    decoder.dimensions(),
+                    decoder.color_type(),
+                    decoder.total_bytes(),
+                )
+            }
+        };
+
+    let (mut width, mut height) = (input_width, input_height);
+    let mut has_color = color.has_color();
+    let mut lossy_steps = Vec::new();
+    let input_frames = animation::frame_count(input).unwrap_or(1);
+    let animated = input_frames > 1 && keeps_animation(input, target);
+    if input_frames > 1 && !animated {
+        lossy_steps.push(LossyStep::AnimationFlattened);
+    }
+    for transform in transforms_list {
+        match transform {
+            Transform::Rotate90 | Transform::Rotate270 => (width, height) = (height, width),
+            Transform::Grayscale => {
+                if has_color {
+                    has_color = false;
+                    lossy_steps.push(LossyStep::Grayscale);
+                }
+            }
+            Transform::FlipHorizontal
+            | Transform::FlipVertical
+            | Transform::Rotate180
+            | Transform::Invert => {}
+        }
+    }
+    if let Some(rect) = options.crop {
+        if !crop_fits(rect, width, height) {
+            return Err(ConvertError::InvalidCrop {
+                crop: rect,
+                width,
+                height,
+            });
+        }
+        (width, height) = (rect.width, rect.height);
+    }
+    for operation_step in &options.operations {
+        let operation = operation_step.resolve().map_err(ConvertError::Operation)?;
+        (width, height) = operation.dimensions(width, height, &operation_step.params);
+    }
+    if let Some(step) = options.resize {
+        let geometry = resize::geometry(
+            width,
+            height,
+            step.mode,
+            step.width,
+            step.height,
+            step.options.no_upscale,
+        )
+        .map_err(|e| ConvertError::Resize(Box::new(e)))?;
+        (width, height) = (geometry.width, geometry.height);
+    }
+    if target == ImageFormat::Ico && (width > 256 || height > 256) {
+        return Err(ConvertError::TooLargeForTarget {
+            target,
+            width,
+            height,
+        });
+    }
+
+    // Animations are re-encoded from 8-bit RGBA frames, and resizing and watermarking
+    // work on 8-bit RGBA.
+    let wide = color.bytes_per_pixel() / color.channel_count() > 1;
+    let eight_bit = animated || options.resize.is_some() || options.watermark.is_some();
+    if wide && (eight_bit || !keeps_16bit(target, options.png_indexed)) {
+        lossy_steps.push(LossyStep::BitDepthReduced);
+    }
+    if color.has_alpha() && target == ImageFormat::Jpeg {
+        lossy_steps.push(LossyStep::AlphaDropped);
+    }
+    match target {
+        ImageFormat::Gif => lossy_steps.push(LossyStep::PaletteReduced),
+        ImageFormat::Jpeg => lossy_steps.push(LossyStep::JpegCompression),
+        ImageFormat::Png
+        | ImageFormat::WebP
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => {}
+    }
+
+    let frames = if animated { input_frames } else { 1 };
+    let channels = match (has_color, color.has_alpha() && target != ImageFormat::Jpeg) {
+        (true, true) => 4,
+        (true, false) => 3,
+        (false, true) => 2,
+        (false, false) => 1,
+    };
+    let sample_bytes = if wide && !lossy_steps.contains(&LossyStep::BitDepthReduced) {
+        2
+    } else {
+        1
+    };
+    let pixels = u64::from(width) * u64::from(height) * u64::try_from(frames).unwrap_or(1);
+    let approx_bytes = approx_size(
+        target,
+        pixels,
+        channels * sample_bytes,
+        options.quality,
+        options.png_indexed == IndexedPng::Require,
+    );
+    // Animations are decoded to 8-bit RGBA frames.
+    let decoded_bytes = if animated {
+        u64::from(input_width) * u64::from(input_height) * 4 * u64::try_from(frames).unwrap_or(1)
+    } else {
+        still_bytes
+    };
+
+    Ok(ConversionPlan {
+        input_format,
+        target: target.as_str(),
+        input_width,
+        input_height,
+        width,
+        height,
+        frames: u32::try_from(frames).unwrap_or(u32::MAX),
+        approx_bytes,
+        approx_memory_bytes: approx_memory(byte_len(input), decoded_bytes, approx_bytes),
+        lossy_steps,
+    })
+}
 
-    let decoded = transforms::apply_transforms(decoded, transforms_list);
+/// Rough peak memory of a conversion: the input, the decoded pixels and one working
+/// copy of them (a transform's result, or an RGBA copy for encoding), and the output.
+fn approx_memory(input_bytes: u64, decoded_bytes: u64, output_bytes: u64) -> u64 {
+    input_bytes
+        .saturating_add(decoded_bytes.saturating_mul(2))
+        .saturating_add(output_bytes)
+}
 
+/// Checks the planned memory use of converting `input` against `limit`.
+///
+/// Returns `None` if the conversion fits. Otherwise, for tiled and striped TIFFs, the
+/// input decoded at the smallest reduction that fits, with the factor used.
+///
+/// # Errors
+///
+/// Returns `ConvertError::LimitExceeded` if the conversion doesn't fit and can't be
+/// reduced to fit, plus the errors of [`plan`].
+fn reduce_to_fit(
+    input: &[u8],
+    target: ImageFormat,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+    limit: u64,
+) -> Result<Option<(image::RgbaImage, u32)>, ConvertError> {
+    let planned = plan(input, target, options, transforms_list)?;
+    if planned.approx_memory_bytes <= limit {
+        return Ok(None);
+    }
+    let exceeded = ConvertError::LimitExceeded {
+        required_bytes: planned.approx_memory_bytes,
+        limit_bytes: limit,
+    };
+    if planned.input_format != ImageFormat::Tiff.as_str() || planned.frames > 1 {
+        return Err(exceeded);
+    }
+
+    let tiff_error = |e| {
+        ConvertError::Decode(image::ImageError::Decoding(
+            image::error::DecodingError::new(
+                image::error::ImageFormatHint::Exact(image::ImageFormat::Tiff),
+                e,
+            ),
+        ))
+    };
+    // The reduced decode holds its sums and a chunk alongside the input, then the
+    // conversion runs on the reduced pixels; both have to fit.
+    let mut chosen = None;
+    for factor in 2..=MAX_DECODE_REDUCTION {
+        let Some(decoding) = region::reduced_decode_bytes(input, factor).map_err(tiff_error)?
+        else {
+            return Err(exceeded);
+        };
+        let area = u64::from(factor) * u64::from(factor);
+        let pixels = u64::from(planned.input_width.div_ceil(factor))
+            * u64::from(planned.input_height.div_ceil(factor));
+        let converting = approx_memory(byte_len(input), pixels * 4, planned.approx_bytes / area);
+        if converting.max(byte_len(input).saturating_add(decoding)) <= limit {
+            chosen = Some(factor);
+            break;
+        }
+    }
+    let Some(factor) = chosen else {
+        return Err(exceeded);
+    };
+    let reduced = region::decode_reduced(input, factor).map_err(tiff_error)?;
+    match reduced {
+        Some(reduced) => {
+            log::warn!(
+                "{} bytes needed against a limit of {limit}; decoding at 1/{factor} size",
+                planned.approx_memory_bytes
+            );
+            Ok(Some((reduced, factor)))
+        }
+        None => Err(exceeded),
+    }
+}
+
+/// Rough encoded size of `pixels` pixels of `bytes_per_pixel` each.
+fn approx_size(
+    target: ImageFormat,
+    pixels: u64,
+    bytes_per_pixel: u64,
+    quality: Option<u8>,
+    indexed: bool,
+) -> u64 {
+    let raw = pixels * bytes_per_pixel;
+    match target {
+        // Uncompressed pixels behind a small header.
+        ImageFormat::Bmp | ImageFormat::Tga | ImageFormat::Tiff => raw + 128,
+        // DEFLATE typically halves photographic content; ICO wraps a PNG.
+        ImageFormat::Png | ImageFormat::Ico if indexed => pixels / 2 + 1024,
+        ImageFormat::Png | ImageFormat::Ico => raw / 2 + 128,
+        // One LZW-compressed palette index per pixel, plus the palette.
+        ImageFormat::Gif => pixels * 3 / 5 + 800,
+        ImageFormat::Qoi => raw * 3 / 5 + 22,
+        ImageFormat::Jpeg => {
+            let millibits = jpeg_millibits_per_pixel(quality.unwrap_or(DEFAULT_JPEG_QUALITY));
+            // Grayscale JPEGs carry a single channel.
+            let millibits = if bytes_per_pixel < 3 {
+                millibits / 2
+            } else {
+                millibits
+            };
+            pixels * millibits / 8000 + 600
+        }
+        ImageFormat::WebP => raw,
+    }
+}
+
+/// Typical bits per pixel of a color JPEG at `quality`, in thousandths,
+/// interpolated between measured points.
+fn jpeg_millibits_per_pixel(quality: u8) -> u64 {
+    const POINTS: [(u64, u64); 5] = [(1, 200), (50, 1000), (75, 1500), (90, 2500), (100, 6000)];
+    let q = u64::from(quality);
+    POINTS
+        .windows(2)
+        .find_map(|pair| match *pair {
+            [(q0, b0), (q1, b1)] if q <= q1 => Some(b0 + (b1 - b0) * (q.max(q0) - q0) / (q1 - q0)),
+            _ => None,
+        })
+        .unwrap_or(6000)
+}
+
+/// Opens `input` for decoding, falling back to `source_format` when its bytes don't
+/// identify a format.
+fn open(
+    input: &[u8],
+    source_format: Option<ImageFormat>,
+) -> Result<ImageReader<Cursor<&[u8]>>, ConvertError> {
+    let mut reader = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|e| ConvertError::Decode(image::ImageError::IoError(e)))?;
+    if reader.format().is_none() {
+        if let Some(format) = source_format {
+            reader.set_format(format.decoder_format());
+        }
+    }
+    Ok(reader)
+}
+
+/// Decodes `input` with the built-in codecs, or with a registered decoder when the
+/// bytes aren't in a built-in format and no source format is given. Multi-size ICO
+/// inputs decode the size `icon_size` selects.
+fn decode_input(
+    input: &[u8],
+    source_format: Option<ImageFormat>,
+    icon_size: Option<u32>,
+) -> Result<DynamicImage, ConvertError> {
+    if let Some(icon) = ico::select_entry(input, icon_size) {
+        return image::load_from_memory(&icon).map_err(ConvertError::Decode);
+    }
+    if let Some(decoder) = extra_decoder(input, source_format) {
+        return decoder.decode(input).map_err(ConvertError::Codec);
+    }
+    open(input, source_format)?
+        .decode()
+        .map_err(ConvertError::Decode)
+}
+
+/// Decodes `input` at reduced resolution when `options` resize it and nothing before
+/// the resize depends on its pixel size, i.e. there is no crop and no operations (see
+/// [`region::decode_at_least`]). Returns the image with the source's width and height
+/// after `transforms_list`, for [`ResizeStep::apply_reduced`], or `None` when the input
+/// should be decoded in full.
+fn decode_for_resize(
+    input: &[u8],
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+) -> Result<Option<(RgbaImage, u32, u32)>, ConvertError> {
+    let Some(step) = options.resize else {
+        return Ok(None);
+    };
+    if options.crop.is_some()
+        || !options.operations.is_empty()
+        || options.source_format.is_some()
+        || extra_decoder(input, None).is_some()
+    {
+        return Ok(None);
+    }
+    let (width, height) = region::dimensions(input).map_err(ConvertError::Decode)?;
+    let turns = transforms_list
+        .iter()
+        .filter(|transform| matches!(transform, Transform::Rotate90 | Transform::Rotate270))
+        .count();
+    let source = if turns % 2 == 1 {
+        (height, width)
+    } else {
+        (width, height)
+    };
+    let plan = resize::geometry(
+        source.0,
+        source.1,
+        step.mode,
+        step.width,
+        step.height,
+        step.options.no_upscale,
+    )
+    .map_err(|e| ConvertError::Resize(Box::new(e)))?;
+    let (min_width, min_height) = if turns % 2 == 1 {
+        (plan.scaled_height, plan.scaled_width)
+    } else {
+        (plan.scaled_width, plan.scaled_height)
+    };
+    Ok(region::try_decode_at_least(input, min_width, min_height)
+        .map(|image| (image, source.0, source.1)))
+}
+
+/// The decoder for `input` if the built-in codecs shouldn't read it: camera RAW (with
+/// the `raw-preview` feature), whose TIFF container would otherwise decode as its
+/// thumbnail, PSD, which the `image` crate doesn't read, or a registered decoder for
+/// bytes no built-in format recognizes.
+fn extra_decoder(input: &[u8], source_format: Option<ImageFormat>) -> Option<Rc<dyn Decoder>> {
+    if source_format.is_some() {
+        return None;
+    }
+    #[cfg(feature = "raw-preview")]
+    if let Some(format) = crate::raw::camera_format(input) {
+        return Some(Rc::new(crate::raw::RawDecoder(format)));
+    }
+    if psd::is_psd(input) {
+        return Some(Rc::new(PsdDecoder));
+    }
+    if ImageFormat::detect_from_bytes(input).is_ok() {
+        return None;
+    }
+    codecs::detect(input)
+}
+
+/// Applies `options.exif` and `options.strip_gps` to JPEG and PNG output; other
+/// targets are returned as they are.
+fn write_exif(
+    output: Vec<u8>,
+    target: ImageFormat,
+    options: &ConvertOptions,
+) -> Result<Vec<u8>, ConvertError> {
+    if !matches!(target, ImageFormat::Jpeg | ImageFormat::Png) {
+        return Ok(output);
+    }
+    let mut edits = options.exif.clone().unwrap_or_default();
+    edits.remove_gps |= options.strip_gps;
+    if edits.is_empty() {
+        return Ok(output);
+    }
+    exif_write::set_exif(&output, &edits).map_err(ConvertError::Exif)
+}
+
+fn byte_len(bytes: &[u8]) -> u64 {
+    u64::try_from(bytes.len()).unwrap_or(u64::MAX)
+}
+
+/// Encodes an already-decoded image in the target format.
+///
+/// `quality` follows the same rules as [`convert`]: JPEG uses it directly, PNG maps it
+/// to compression levels 1-9, and other formats ignore it. Callers are expected to have
+/// validated the quality range.
+///
+/// # Errors
+///
+/// Returns `ConvertError::UnsupportedTarget` for decode-only formats (e.g. WebP) and
+/// `ConvertError::Encode` if the encoder fails.
+pub fn encode(
+    image: &DynamicImage,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, ConvertError> {
     let mut output_buf = Vec::new();
     match target {
+        #[cfg(feature = "mozjpeg")]
         ImageFormat::Jpeg => {
-            let encoder =
-                JpegEncoder::new_with_quality(Cursor::new(&mut output_buf), quality.unwrap_or(80));
-            decoded
-                .write_with_encoder(encoder)
-                .map_err(ConvertError::Encode)?;
+            log::debug!("encoding JPEG with mozjpeg");
+            output_buf = encode_mozjpeg(image, quality.unwrap_or(DEFAULT_JPEG_QUALITY))?;
+        }
+        #[cfg(not(feature = "mozjpeg"))]
+        ImageFormat::Jpeg => {
+            output_buf = encode_builtin_jpeg(image, quality)?;
         }
         ImageFormat::Png => {
             let encoder = PngEncoder::new_with_quality(
@@ -62,12 +1144,20 @@ pub fn convert(
                 map_png_quality(quality),
                 FilterType::Adaptive,
             );
-            decoded
+            image
                 .write_with_encoder(encoder)
                 .map_err(ConvertError::Encode)?;
         }
-        ImageFormat::Gif
-        | ImageFormat::Bmp
+        ImageFormat::Gif => {
+            let encoder = GifEncoder::new_with_speed(
+                Cursor::new(&mut output_buf),
+                quality.map_or(1, gif_speed),
+            );
+            image
+                .write_with_encoder(encoder)
+                .map_err(ConvertError::Encode)?;
+        }
+        ImageFormat::Bmp
         | ImageFormat::Tiff
         | ImageFormat::Ico
         | ImageFormat::Tga
@@ -76,7 +1166,7 @@ pub fn convert(
             let output_format = target
                 .to_image_format()
                 .map_err(|e| ConvertError::UnsupportedTarget(e.to_string()))?;
-            decoded
+            image
                 .write_to(&mut Cursor::new(&mut output_buf), output_format)
                 .map_err(ConvertError::Encode)?;
         }
@@ -85,24 +1175,281 @@ pub fn convert(
     Ok(output_buf)
 }
 
-fn map_png_quality(quality: Option<u8>) -> CompressionType {
-    match quality {
-        None => CompressionType::Default,
-        Some(q) => {
-            // Map 1-100 linearly to compression levels 1-9.
-            // The result is always in 1..=9 since q is validated to 1..=100,
-            // so the truncation from u32 to u8 is safe.
-            #[allow(clippy::as_conversions)]
-            let level = (1 + u32::from(q - 1) * 8 / 99) as u8;
-            CompressionType::Level(level)
+/// Encodes a baseline JPEG with `image`'s own encoder.
+fn encode_builtin_jpeg(image: &DynamicImage, quality: Option<u8>) -> Result<Vec<u8>, ConvertError> {
+    log::debug!("encoding JPEG with the built-in encoder");
+    let mut output_buf = Vec::new();
+    let encoder = JpegEncoder::new_with_quality(
+        Cursor::new(&mut output_buf),
+        quality.unwrap_or(DEFAULT_JPEG_QUALITY),
+    );
+    image
+        .write_with_encoder(encoder)
+        .map_err(ConvertError::Encode)?;
+    Ok(output_buf)
+}
+
+/// Whether the output stores 16-bit samples as-is.
+fn keeps_16bit(target: ImageFormat, png_indexed: IndexedPng) -> bool {
+    match target {
+        ImageFormat::Png => png_indexed == IndexedPng::Never,
+        ImageFormat::Tiff => true,
+        ImageFormat::Jpeg
+        | ImageFormat::WebP
+        | ImageFormat::Gif
+        | ImageFormat::Bmp
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => false,
+    }
+}
+
+/// Re-encodes an animation frame by frame if both the input and the target support it,
+/// filling in `report` as it goes.
+///
+/// Returns `Ok(None)` when the input is static or the target cannot hold an animation,
+/// so the caller falls back to the single-image path.
+fn convert_animated(
+    input: &[u8],
+    target: ImageFormat,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+    report: &mut ConversionReport,
+) -> Result<Option<Vec<u8>>, ConvertError> {
+    if !keeps_animation(input, target) || animation::frame_count(input).unwrap_or(1) < 2 {
+        return Ok(None);
+    }
+
+    log::debug!("keeping the animation as {}", target.as_str());
+    let decoding = TimedOperation::start("decode");
+    let mut frames: Vec<(DynamicImage, Delay)> = animation::decode_frames(input)
+        .map_err(|e| ConvertError::Animation(Box::new(e)))?
+        .into_iter()
+        .map(|frame| {
+            let delay = frame.delay();
+            (DynamicImage::ImageRgba8(frame.into_buffer()), delay)
+        })
+        .collect();
+    let pixel_bytes = |frames: &[(DynamicImage, Delay)]| {
+        frames.iter().map(|(img, _)| byte_len(img.as_bytes())).sum()
+    };
+    report.decode_ms = decoding.finish(pixel_bytes(&frames));
+
+    for &transform in transforms_list {
+        let step = TimedOperation::start(transform.name());
+        frames = frames
+            .into_iter()
+            .map(|(img, delay)| (transform.apply(img), delay))
+            .collect();
+        report.ops.push(OpTiming {
+            name: transform.name(),
+            ms: step.finish(pixel_bytes(&frames)),
+        });
+    }
+    if let Some(rect) = options.crop {
+        let step = TimedOperation::start("crop");
+        frames = frames
+            .into_iter()
+            .map(|(img, delay)| Ok((crop(&img, rect)?, delay)))
+            .collect::<Result<_, ConvertError>>()?;
+        report.ops.push(OpTiming {
+            name: "crop",
+            ms: step.finish(pixel_bytes(&frames)),
+        });
+    }
+    for operation_step in &options.operations {
+        let operation = operation_step.resolve().map_err(ConvertError::Operation)?;
+        let step = TimedOperation::start(operation.name());
+        frames = frames
+            .into_iter()
+            .map(|(img, delay)| Ok((operation.apply(img, &operation_step.params)?, delay)))
+            .collect::<Result<_, OperationError>>()
+            .map_err(ConvertError::Operation)?;
+        report.ops.push(OpTiming {
+            name: operation.name(),
+            ms: step.finish(pixel_bytes(&frames)),
+        });
+    }
+    if let Some(resize_step) = options.resize {
+        let step = TimedOperation::start("resize");
+        frames = frames
+            .into_iter()
+            .map(|(img, delay)| Ok((resize_step.apply(&img)?, delay)))
+            .collect::<Result<_, ConvertError>>()?;
+        report.ops.push(OpTiming {
+            name: "resize",
+            ms: step.finish(pixel_bytes(&frames)),
+        });
+    }
+    if let Some(watermark) = &options.watermark {
+        let step = TimedOperation::start("watermark");
+        frames = frames
+            .into_iter()
+            .map(|(img, delay)| (watermark.apply(img), delay))
+            .collect();
+        report.ops.push(OpTiming {
+            name: "watermark",
+            ms: step.finish(pixel_bytes(&frames)),
+        });
+    }
+    let frames: Vec<Frame> = frames
+        .into_iter()
+        .map(|(img, delay)| Frame::from_parts(img.into_rgba8(), 0, 0, delay))
+        .collect();
+    let frame_bytes: u64 = frames.iter().map(|f| byte_len(f.buffer().as_raw())).sum();
+    if let Some(first) = frames.first() {
+        (report.width, report.height) = first.buffer().dimensions();
+    }
+    report.frames = u32::try_from(frames.len()).unwrap_or(u32::MAX);
+
+    let encoding = TimedOperation::start("encode");
+    let output = if let (ImageFormat::Gif, Some(gif_options)) = (target, options.gif_quantize) {
+        log::debug!("quantizing {} GIF frames", frames.len());
+        report.applied.quantized_gif = true;
+        quantize::quantize_gif_frames(&frames, &gif_options).map_err(ConvertError::Quantize)?
+    } else {
+        animation::encode_animation(frames, target, options.quality)
+            .map_err(|e| ConvertError::Animation(Box::new(e)))?
+    };
+    report.applied.quality = applied_quality(target, options.quality, &report.applied);
+    report.encode_ms = encoding.finish(byte_len(&output));
+    report.peak_memory_bytes =
+        (report.input_bytes + frame_bytes).max(frame_bytes + byte_len(&output));
+    Ok(Some(output))
+}
+
+/// The quality `target` output was encoded at, as [`AppliedOptions::quality`] reports
+/// it once `applied` records how it was encoded.
+fn applied_quality(
+    target: ImageFormat,
+    quality: Option<u8>,
+    applied: &AppliedOptions,
+) -> Option<u8> {
+    match target {
+        ImageFormat::Jpeg => Some(quality.unwrap_or(DEFAULT_JPEG_QUALITY)),
+        ImageFormat::Png => (!applied.indexed_png).then_some(quality).flatten(),
+        ImageFormat::Gif => (!applied.quantized_gif).then_some(quality).flatten(),
+        ImageFormat::WebP
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => None,
+    }
+}
+
+/// Whether an animated `input` stays animated when converted to `target`.
+fn keeps_animation(input: &[u8], target: ImageFormat) -> bool {
+    match target {
+        ImageFormat::Gif => true,
+        // Only APNG sources stay animated as PNG; GIF → PNG keeps producing a still image.
+        ImageFormat::Png => ImageFormat::detect_from_bytes(input).ok() == Some(ImageFormat::Png),
+        ImageFormat::Jpeg
+        | ImageFormat::WebP
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => false,
+    }
+}
+
+/// Encodes a JPEG with mozjpeg: progressive scans, trellis quantization and optimized
+/// Huffman tables, which typically shrink output by 10-20% at the same quality setting.
+///
+/// Grayscale images are encoded as single-channel JPEGs; everything else is flattened
+/// to RGB (alpha is dropped, as JPEG cannot carry it).
+///
+/// libjpeg reports fatal errors by unwinding, so the whole encode runs inside
+/// `catch_unwind` and any failure is surfaced as `ConvertError::Encode`.
+#[cfg(feature = "mozjpeg")]
+fn encode_mozjpeg(image: &DynamicImage, quality: u8) -> Result<Vec<u8>, ConvertError> {
+    let (color_space, pixels) = if image.color().has_color() {
+        (mozjpeg::ColorSpace::JCS_RGB, image.to_rgb8().into_raw())
+    } else {
+        (
+            mozjpeg::ColorSpace::JCS_GRAYSCALE,
+            image.to_luma8().into_raw(),
+        )
+    };
+    let width = usize::try_from(image.width()).map_err(|_| mozjpeg_error("width too large"))?;
+    let height = usize::try_from(image.height()).map_err(|_| mozjpeg_error("height too large"))?;
+
+    let result = std::panic::catch_unwind(|| -> std::io::Result<Vec<u8>> {
+        let mut compress = mozjpeg::Compress::new(color_space);
+        compress.set_size(width, height);
+        compress.set_quality(f32::from(quality));
+        compress.set_progressive_mode();
+        compress.set_optimize_coding(true);
+        compress.set_optimize_scans(true);
+
+        let mut started = compress.start_compress(Vec::new())?;
+        started.write_scanlines(&pixels)?;
+        started.finish()
+    });
+
+    match result {
+        Ok(encoded) => encoded.map_err(|e| ConvertError::Encode(image::ImageError::IoError(e))),
+        Err(payload) => {
+            let msg = payload
+                .downcast_ref::<String>()
+                .map_or("libjpeg fatal error", String::as_str);
+            Err(mozjpeg_error(msg))
         }
     }
 }
 
+#[cfg(feature = "mozjpeg")]
+fn mozjpeg_error(msg: &str) -> ConvertError {
+    ConvertError::Encode(image::ImageError::IoError(std::io::Error::other(
+        msg.to_string(),
+    )))
+}
+
+fn map_png_quality(quality: Option<u8>) -> CompressionType {
+    quality.map_or(CompressionType::Default, |q| {
+        CompressionType::Level(png_compression_level(q))
+    })
+}
+
+/// The zlib compression level (1-9) PNG output is written at for `quality` (1-100).
+pub fn png_compression_level(quality: u8) -> u8 {
+    // Map 1-100 linearly to compression levels 1-9.
+    u8::try_from(1 + u32::from(quality.saturating_sub(1).min(99)) * 8 / 99).unwrap_or(9)
+}
+
+/// The speed (1-30, lower is better) GIF output's colors are quantized at for `quality`
+/// (1-100): 30 for quality 1 down to 1 for quality 100.
+pub fn gif_speed(quality: u8) -> i32 {
+    30 - i32::from(quality.saturating_sub(1).min(99)) * 29 / 99
+}
+
 /// Reads image dimensions from the raw bytes without fully decoding the pixel data.
 ///
 /// Uses the image reader to extract width and height from headers.
 pub fn dimensions(input: &[u8]) -> Result<Dimensions, ConvertError> {
+    let icon_sizes = ico::sizes(input);
+    if let Some(size) = ico::select(&icon_sizes, None).and_then(|index| icon_sizes.get(index)) {
+        return Ok(Dimensions {
+            width: size.width,
+            height: size.height,
+        });
+    }
+    if psd::is_psd(input) {
+        let info = psd::inspect(input)
+            .map_err(|e| ConvertError::Codec(CodecError::Failed(e.to_string())))?;
+        return Ok(Dimensions {
+            width: info.width,
+            height: info.height,
+        });
+    }
+    if let Some(decoder) = extra_decoder(input, None) {
+        let decoded = decoder.decode(input).map_err(ConvertError::Codec)?;
+        return Ok(Dimensions {
+            width: decoded.width(),
+            height: decoded.height(),
+        });
+    }
     let reader = ImageReader::new(Cursor::new(input))
         .with_guessed_format()
         .map_err(|e| ConvertError::Decode(image::ImageError::IoError(e)))?;
@@ -121,10 +1468,7 @@ pub fn dimensions(input: &[u8]) -> Result<Dimensions, ConvertError> {
 ///
 /// Returns a `ConvertError::Decode` if the input cannot be decoded or the format is unrecognized.
 pub fn decode_rgba(input: &[u8]) -> Result<Vec<u8>, ConvertError> {
-    Ok(image::load_from_memory(input)
-        .map_err(ConvertError::Decode)?
-        .into_rgba8()
-        .into_raw())
+    Ok(decode_input(input, None, None)?.into_rgba8().into_raw())
 }
 
 /// Decodes the input image, applies transforms, and returns the transformed RGBA8 pixel data
@@ -140,7 +1484,7 @@ pub fn decode_rgba_with_transforms(
     input: &[u8],
     transforms_list: &[Transform],
 ) -> Result<(Vec<u8>, Dimensions), ConvertError> {
-    let decoded = image::load_from_memory(input).map_err(ConvertError::Decode)?;
+    let decoded = decode_input(input, None, None)?;
     let transformed = transforms::apply_transforms(decoded, transforms_list);
     let width = transformed.width();
     let height = transformed.height();
@@ -159,6 +1503,41 @@ pub enum ConvertError {
     UnsupportedTarget(String),
     /// Quality value is outside the valid 1-100 range.
     InvalidQuality(u8),
+    /// Failed to decode or re-encode an animated input.
+    Animation(Box<AnimationError>),
+    /// The resize step was invalid.
+    Resize(Box<ResizeError>),
+    /// A registered operation was unknown, rejected its parameters or failed.
+    Operation(OperationError),
+    /// A registered codec failed, or no encoder is registered under the target name.
+    Codec(CodecError),
+    /// Failed to carry ancillary chunks into PNG output.
+    PngChunks(PngChunkError),
+    /// Failed to write EXIF fields into the output.
+    Exif(ExifWriteError),
+    /// Indexed PNG output was required but the image has more than 256 colors.
+    TooManyColors,
+    /// Failed to write indexed PNG output.
+    Quantize(QuantizeError),
+    /// The crop rectangle was empty or didn't fit in the `width` x `height` image.
+    InvalidCrop {
+        crop: CropBox,
+        width: u32,
+        height: u32,
+    },
+    /// The output would exceed the largest dimensions the target can hold.
+    TooLargeForTarget {
+        target: ImageFormat,
+        width: u32,
+        height: u32,
+    },
+    /// The conversion would need about `required_bytes` of memory, more than
+    /// [`ConvertOptions::max_memory_bytes`] allows, and the input can't be decoded at
+    /// reduced resolution.
+    LimitExceeded {
+        required_bytes: u64,
+        limit_bytes: u64,
+    },
 }
 
 impl std::fmt::Display for ConvertError {
@@ -170,6 +1549,41 @@ impl std::fmt::Display for ConvertError {
             Self::InvalidQuality(q) => {
                 write!(f, "Quality must be between 1 and 100, got {q}")
             }
+            Self::Animation(e) => write!(f, "{e}"),
+            Self::Resize(e) => write!(f, "{e}"),
+            Self::Operation(e) => write!(f, "{e}"),
+            Self::Codec(e) => write!(f, "{e}"),
+            Self::PngChunks(e) => write!(f, "{e}"),
+            Self::Exif(e) => write!(f, "Failed to write EXIF: {e}"),
+            Self::TooManyColors => write!(
+                f,
+                "Image has more than 256 colors, so it cannot be written as an indexed PNG"
+            ),
+            Self::Quantize(e) => write!(f, "{e}"),
+            Self::InvalidCrop {
+                crop,
+                width,
+                height,
+            } => write!(
+                f,
+                "Crop {}×{} at ({}, {}) doesn't fit in the {width}×{height} image",
+                crop.width, crop.height, crop.x, crop.y
+            ),
+            Self::TooLargeForTarget {
+                target,
+                width,
+                height,
+            } => write!(
+                f,
+                "{width}×{height} is too large for {target} output (at most 256×256)"
+            ),
+            Self::LimitExceeded {
+                required_bytes,
+                limit_bytes,
+            } => write!(
+                f,
+                "Converting this image needs about {required_bytes} bytes of memory, more than the limit of {limit_bytes}"
+            ),
         }
     }
 }
@@ -181,6 +1595,7 @@ mod tests {
     use std::time::Instant;
 
     use super::*;
+    use crate::events::{self, Event, EventSink};
 
     // ===== Fixture Generation Helpers =====
 
@@ -229,6 +1644,8 @@ mod tests {
         buf
     }
 
+    // Safe: wrapping_mul and modulo 256 guarantee values fit in u8.
+    #[allow(clippy::as_conversions)]
     fn make_patterned_rgba(width: u32, height: u32) -> image::RgbaImage {
         let mut img = image::RgbaImage::new(width, height);
         for (x, y, pixel) in img.enumerate_pixels_mut() {
@@ -506,6 +1923,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::as_conversions)] // Safe: modulo 256 guarantees the value fits in u8.
     fn convert_random_bytes() {
         let random: Vec<u8> = (0..1024u16)
             .map(|i| (i.wrapping_mul(137).wrapping_add(43) % 256) as u8)
@@ -654,6 +2072,7 @@ mod tests {
     }
 
     #[test]
+    #[allow(clippy::as_conversions)] // Safe: modulo 256 guarantees the value fits in u8.
     fn decode_rgba_random_bytes() {
         let random: Vec<u8> = (0..1024u16)
             .map(|i| (i.wrapping_mul(137).wrapping_add(43) % 256) as u8)
@@ -896,4 +2315,1209 @@ mod tests {
         // None maps to Default
         assert_eq!(map_png_quality(None), CompressionType::Default);
     }
+
+    // ===== mozjpeg Backend Tests =====
+
+    #[cfg(feature = "mozjpeg")]
+    #[test]
+    fn mozjpeg_output_is_decodable() {
+        let (_, png_data) = make_patterned_png(64, 48);
+        let jpeg = convert(png_data, ImageFormat::Jpeg, Some(75), &[]).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&jpeg).unwrap(),
+            ImageFormat::Jpeg
+        );
+        let decoded = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (64, 48));
+    }
+
+    #[cfg(feature = "mozjpeg")]
+    #[test]
+    fn mozjpeg_grayscale_stays_single_channel() {
+        let gray = DynamicImage::ImageLuma8(image::GrayImage::from_fn(32, 32, |x, y| {
+            image::Luma([u8::try_from((x * 8 + y) % 256).unwrap()])
+        }));
+        let jpeg = encode(&gray, ImageFormat::Jpeg, None).unwrap();
+        let decoded = image::load_from_memory(&jpeg).unwrap();
+        assert_eq!(decoded.color(), image::ColorType::L8);
+    }
+
+    #[cfg(feature = "mozjpeg")]
+    #[test]
+    fn mozjpeg_smaller_than_baseline_encoder() {
+        let img = DynamicImage::ImageRgba8(make_patterned_rgba(256, 256)).to_rgb8();
+        let mut baseline = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut baseline), 80)
+            .encode_image(&img)
+            .unwrap();
+
+        let mozjpeg = encode(&DynamicImage::ImageRgb8(img), ImageFormat::Jpeg, Some(80)).unwrap();
+        assert!(
+            mozjpeg.len() < baseline.len(),
+            "mozjpeg ({} bytes) should beat the baseline encoder ({} bytes)",
+            mozjpeg.len(),
+            baseline.len()
+        );
+    }
+
+    // ===== Animated Input Tests =====
+
+    fn make_animated_gif(width: u32, height: u32, delays_ms: &[u32]) -> Vec<u8> {
+        let frames = delays_ms
+            .iter()
+            .zip(
+                [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]]
+                    .iter()
+                    .cycle(),
+            )
+            .map(|(delay, color)| {
+                let mut img = image::RgbaImage::from_pixel(width, height, image::Rgba(*color));
+                // Mark the top-left corner so orientation changes are observable.
+                img.put_pixel(0, 0, image::Rgba([255, 255, 255, 255]));
+                Frame::from_parts(img, 0, 0, image::Delay::from_numer_denom_ms(*delay, 1))
+            })
+            .collect();
+        animation::encode_gif(frames, None).unwrap()
+    }
+
+    #[test]
+    fn animated_gif_transforms_apply_to_every_frame() {
+        let gif = make_animated_gif(6, 4, &[30, 60, 90]);
+        let result = convert(
+            gif,
+            ImageFormat::Gif,
+            None,
+            &[Transform::Rotate90, Transform::FlipVertical],
+        )
+        .unwrap();
+
+        let frames = animation::decode_frames(&result).unwrap();
+        assert_eq!(frames.len(), 3, "Animation should not be flattened");
+        for (frame, expected_delay) in frames.iter().zip([30, 60, 90]) {
+            assert_eq!(frame.buffer().dimensions(), (4, 6));
+            assert_eq!(animation::frame_delay_ms(frame), expected_delay);
+            // Rotating 90° CW moves (0,0) to (3,0); flipping vertically moves it to (3,5).
+            assert_eq!(frame.buffer().get_pixel(3, 5).0, [255, 255, 255, 255]);
+        }
+    }
+
+    #[test]
+    fn resize_step_follows_transforms_and_applies_to_every_frame() {
+        let options = ConvertOptions {
+            resize: Some(ResizeStep {
+                mode: ResizeMode::Fit,
+                width: 10,
+                height: 10,
+                options: ResizeOptions::default(),
+            }),
+            ..ConvertOptions::default()
+        };
+        let (_, png) = make_patterned_png(40, 20);
+        let planned = plan(&png, ImageFormat::Png, &options, &[Transform::Rotate90]).unwrap();
+        let converted =
+            convert_with_report(png, ImageFormat::Png, &options, &[Transform::Rotate90]).unwrap();
+        // Rotated to 20x40 first, then fitted into 10x10.
+        assert_eq!((converted.report.width, converted.report.height), (5, 10));
+        assert_eq!((planned.width, planned.height), (5, 10));
+        let names: Vec<&str> = converted.report.ops.iter().map(|op| op.name).collect();
+        assert_eq!(names, ["rotate_90", "resize"]);
+
+        let gif = make_animated_gif(6, 4, &[30, 60]);
+        let result = convert_with_options(gif, ImageFormat::Gif, &options, &[]).unwrap();
+        let frames = animation::decode_frames(&result).unwrap();
+        assert_eq!(frames.len(), 2);
+        for frame in &frames {
+            assert_eq!(frame.buffer().dimensions(), (10, 7));
+        }
+
+        let zero = ConvertOptions {
+            resize: Some(ResizeStep {
+                width: 0,
+                ..options.resize.unwrap()
+            }),
+            ..ConvertOptions::default()
+        };
+        assert!(matches!(
+            convert_with_options(make_png(4, 4), ImageFormat::Png, &zero, &[]),
+            Err(ConvertError::Resize(_))
+        ));
+    }
+
+    /// The FLEVEL field of the zlib stream in `png`'s first `IDAT` chunk: 0 for the
+    /// fastest compression up to 3 for the best.
+    fn idat_zlib_level(png: &[u8]) -> u8 {
+        let at = png.windows(4).position(|kind| kind == b"IDAT").unwrap();
+        png[at + 5] >> 6
+    }
+
+    #[test]
+    fn animated_frames_get_every_step_and_the_quality() {
+        let gif = make_animated_gif(40, 20, &[40, 60, 80]);
+        let apng = animation::encode_apng(&animation::decode_frames(&gif).unwrap(), None).unwrap();
+        let options = |quality| ConvertOptions {
+            quality: Some(quality),
+            crop: Some(CropBox {
+                x: 10,
+                y: 0,
+                width: 20,
+                height: 20,
+            }),
+            resize: Some(ResizeStep {
+                mode: ResizeMode::Fit,
+                width: 10,
+                height: 10,
+                options: ResizeOptions::default(),
+            }),
+            watermark: Some(WatermarkStep {
+                logo: RgbaImage::from_pixel(2, 2, image::Rgba([0, 0, 0, 255])),
+                opacity: 1.0,
+            }),
+            ..ConvertOptions::default()
+        };
+
+        let planned = plan(&apng, ImageFormat::Png, &options(1), &[]).unwrap();
+        assert_eq!((planned.width, planned.height, planned.frames), (10, 10, 3));
+        let fast = convert_with_report(apng.clone(), ImageFormat::Png, &options(1), &[]).unwrap();
+        let names: Vec<&str> = fast.report.ops.iter().map(|op| op.name).collect();
+        assert_eq!(names, ["crop", "resize", "watermark"]);
+        assert_eq!(fast.report.applied.quality, Some(1));
+        let frames = animation::decode_frames(&fast.data).unwrap();
+        assert_eq!(frames.len(), 3);
+        for (frame, expected_delay) in frames.iter().zip([40, 60, 80]) {
+            assert_eq!(frame.buffer().dimensions(), (10, 10));
+            assert_eq!(animation::frame_delay_ms(frame), expected_delay);
+            // The first logo of the tiling covers (0, 1).
+            assert_eq!(frame.buffer().get_pixel(0, 1).0, [0, 0, 0, 255]);
+        }
+
+        let best =
+            convert_with_options(apng.clone(), ImageFormat::Png, &options(100), &[]).unwrap();
+        assert_eq!(idat_zlib_level(&fast.data), 0);
+        assert_eq!(idat_zlib_level(&best), 3);
+
+        let gif = convert_with_report(apng.clone(), ImageFormat::Gif, &options(50), &[]).unwrap();
+        assert_eq!(gif.report.applied.quality, Some(50));
+        for frame in animation::decode_frames(&gif.data).unwrap() {
+            assert_eq!(frame.buffer().dimensions(), (10, 10));
+        }
+
+        let outside = ConvertOptions {
+            crop: Some(CropBox {
+                x: 30,
+                y: 0,
+                width: 20,
+                height: 20,
+            }),
+            ..ConvertOptions::default()
+        };
+        assert!(matches!(
+            plan(&apng, ImageFormat::Png, &outside, &[]),
+            Err(ConvertError::InvalidCrop { width: 40, .. })
+        ));
+        assert!(matches!(
+            convert_with_options(apng, ImageFormat::Png, &outside, &[]),
+            Err(ConvertError::InvalidCrop { width: 40, .. })
+        ));
+    }
+
+    #[test]
+    fn animated_apng_to_png_stays_animated() {
+        let gif = make_animated_gif(5, 5, &[40, 40]);
+        let apng = animation::encode_apng(&animation::decode_frames(&gif).unwrap(), None).unwrap();
+        let result = convert(apng, ImageFormat::Png, None, &[Transform::Invert]).unwrap();
+        assert_eq!(animation::frame_count(&result).unwrap(), 2);
+    }
+
+    #[test]
+    fn animated_gif_to_png_is_still() {
+        let gif = make_animated_gif(5, 5, &[40, 40]);
+        let result = convert(gif, ImageFormat::Png, None, &[]).unwrap();
+        assert_eq!(animation::frame_count(&result).unwrap(), 1);
+    }
+
+    #[test]
+    fn animated_gif_to_jpeg_uses_first_frame() {
+        let gif = make_animated_gif(8, 8, &[40, 40]);
+        let result = convert(gif, ImageFormat::Jpeg, None, &[Transform::Rotate180]).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&result).unwrap(),
+            ImageFormat::Jpeg
+        );
+        let dims = dimensions(&result).unwrap();
+        assert_eq!((dims.width, dims.height), (8, 8));
+    }
+
+    // ===== PNG Chunk Policy Tests =====
+
+    fn png_with_text(width: u32, height: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, width, height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .add_text_chunk("Title".to_owned(), "Chunk test".to_owned())
+            .unwrap();
+        encoder.set_pixel_dims(Some(png::PixelDimensions {
+            xppu: 2835,
+            yppu: 5670,
+            unit: png::Unit::Meter,
+        }));
+        let mut writer = encoder.write_header().unwrap();
+        let pixels = vec![128; usize::try_from(width * height * 4).unwrap()];
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        buf
+    }
+
+    fn png_info(data: &[u8]) -> png::Info<'static> {
+        png::Decoder::new(Cursor::new(data.to_vec()))
+            .read_info()
+            .unwrap()
+            .info()
+            .clone()
+    }
+
+    #[test]
+    fn png_chunks_stripped_by_default() {
+        let result = convert(png_with_text(4, 2), ImageFormat::Png, None, &[]).unwrap();
+        let info = png_info(&result);
+        assert!(info.uncompressed_latin1_text.is_empty());
+        assert!(info.pixel_dims.is_none());
+    }
+
+    #[test]
+    fn png_chunk_policy_keeps_selected_chunks() {
+        let options = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("safe,+tEXt").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let result =
+            convert_with_options(png_with_text(4, 2), ImageFormat::Png, &options, &[]).unwrap();
+        let info = png_info(&result);
+        assert_eq!(info.uncompressed_latin1_text[0].text, "Chunk test");
+        assert_eq!(info.pixel_dims.unwrap().xppu, 2835);
+
+        let options = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("keep,-tEXt").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let result =
+            convert_with_options(png_with_text(4, 2), ImageFormat::Png, &options, &[]).unwrap();
+        let info = png_info(&result);
+        assert!(info.uncompressed_latin1_text.is_empty());
+        assert!(info.pixel_dims.is_some());
+    }
+
+    #[test]
+    fn png_chunk_policy_rewrites_phys_on_rotation() {
+        let options = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("+pHYs").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let result = convert_with_options(
+            png_with_text(4, 2),
+            ImageFormat::Png,
+            &options,
+            &[Transform::Rotate270],
+        )
+        .unwrap();
+        let dims = png_info(&result).pixel_dims.unwrap();
+        assert_eq!((dims.xppu, dims.yppu), (5670, 2835));
+    }
+
+    /// A profile header with the given data color space; nothing reads further.
+    fn icc_profile(space: [u8; 4]) -> Vec<u8> {
+        let mut profile = vec![0; 128];
+        profile[16..20].copy_from_slice(&space);
+        profile
+    }
+
+    #[test]
+    fn png_chunk_policy_carries_jpeg_icc_profile() {
+        let profile = icc_profile(*b"RGB ");
+        let mut jpeg = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg);
+        image::ImageEncoder::set_icc_profile(&mut encoder, profile.clone()).unwrap();
+        encoder.encode_image(&image::RgbImage::new(8, 8)).unwrap();
+
+        let options = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("safe").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let result = convert_with_options(jpeg.clone(), ImageFormat::Png, &options, &[]).unwrap();
+        assert_eq!(png_info(&result).icc_profile.unwrap().as_ref(), profile);
+
+        let options = ConvertOptions {
+            png_color_tag: ColorTag::Untagged,
+            ..ConvertOptions::default()
+        };
+        let result = convert_with_options(jpeg, ImageFormat::Png, &options, &[]).unwrap();
+        assert!(png_info(&result).icc_profile.is_none());
+    }
+
+    #[test]
+    fn grayscale_transform_drops_carried_rgb_profile() {
+        let mut jpeg = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg);
+        image::ImageEncoder::set_icc_profile(&mut encoder, icc_profile(*b"RGB ")).unwrap();
+        encoder.encode_image(&image::RgbImage::new(8, 8)).unwrap();
+
+        let options = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("safe").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let report =
+            convert_with_report(jpeg, ImageFormat::Png, &options, &[Transform::Grayscale]).unwrap();
+        let info = png_info(&report.data);
+        assert!(info.icc_profile.is_none());
+        assert!(info.srgb.is_some());
+        assert!(!report
+            .report
+            .applied
+            .png_chunks
+            .contains(&"iCCP".to_owned()));
+    }
+
+    #[test]
+    fn png_chunk_policy_ignored_for_other_targets() {
+        let options = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("keep").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let result =
+            convert_with_options(png_with_text(4, 2), ImageFormat::Bmp, &options, &[]).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&result).unwrap(),
+            ImageFormat::Bmp
+        );
+    }
+
+    // ===== PNG Color Tag Tests =====
+
+    #[test]
+    fn png_output_tagged_srgb_by_default() {
+        let result = convert(png_with_text(4, 2), ImageFormat::Png, None, &[]).unwrap();
+        let info = png_info(&result);
+        assert!(info.srgb.is_some());
+        assert_eq!(info.gama_chunk.unwrap().into_scaled(), 45455);
+        assert!(info.chrm_chunk.is_some());
+        assert!(info.icc_profile.is_none());
+    }
+
+    #[test]
+    fn png_output_keeps_jpeg_icc_profile_instead_of_srgb() {
+        let profile = icc_profile(*b"RGB ");
+        let mut jpeg = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg);
+        image::ImageEncoder::set_icc_profile(&mut encoder, profile.clone()).unwrap();
+        encoder.encode_image(&image::RgbImage::new(8, 8)).unwrap();
+
+        let result = convert(jpeg.clone(), ImageFormat::Png, None, &[]).unwrap();
+        let info = png_info(&result);
+        assert_eq!(info.icc_profile.unwrap().as_ref(), profile);
+        assert!(info.srgb.is_none());
+
+        let options = ConvertOptions {
+            png_color_tag: ColorTag::Srgb,
+            ..ConvertOptions::default()
+        };
+        let result = convert_with_options(jpeg, ImageFormat::Png, &options, &[]).unwrap();
+        let info = png_info(&result);
+        assert!(info.icc_profile.is_none());
+        assert!(info.srgb.is_some());
+    }
+
+    #[test]
+    fn grayscale_png_output_drops_rgb_icc_profile() {
+        let mut jpeg = Vec::new();
+        let mut encoder = image::codecs::jpeg::JpegEncoder::new(&mut jpeg);
+        image::ImageEncoder::set_icc_profile(&mut encoder, icc_profile(*b"RGB ")).unwrap();
+        encoder.encode_image(&image::GrayImage::new(8, 8)).unwrap();
+
+        let result = convert(jpeg, ImageFormat::Png, None, &[]).unwrap();
+        let info = png_info(&result);
+        assert_eq!(info.color_type, png::ColorType::Grayscale);
+        assert!(info.icc_profile.is_none());
+        assert!(info.srgb.is_some());
+    }
+
+    #[test]
+    fn png_output_untagged_on_request() {
+        let options = ConvertOptions {
+            png_color_tag: ColorTag::Untagged,
+            ..ConvertOptions::default()
+        };
+        let result =
+            convert_with_options(png_with_text(4, 2), ImageFormat::Png, &options, &[]).unwrap();
+        let info = png_info(&result);
+        assert!(info.srgb.is_none());
+        assert!(info.gama_chunk.is_none());
+        assert!(info.chrm_chunk.is_none());
+    }
+
+    // ===== Indexed PNG Output Tests =====
+
+    fn png_color_type(data: &[u8]) -> png::ColorType {
+        png_info(data).color_type
+    }
+
+    fn few_color_png() -> Vec<u8> {
+        let img = image::RgbaImage::from_fn(12, 12, |x, y| match (x + y) % 3 {
+            0 => image::Rgba([255, 0, 0, 255]),
+            1 => image::Rgba([0, 255, 0, 128]),
+            _ => image::Rgba([0, 0, 0, 0]),
+        });
+        let mut buf = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    fn indexed_options(mode: IndexedPng) -> ConvertOptions {
+        ConvertOptions {
+            png_indexed: mode,
+            ..ConvertOptions::default()
+        }
+    }
+
+    #[test]
+    fn indexed_png_used_when_colors_fit() {
+        let input = few_color_png();
+        for mode in [IndexedPng::Prefer, IndexedPng::Require] {
+            let result =
+                convert_with_options(input.clone(), ImageFormat::Png, &indexed_options(mode), &[])
+                    .unwrap();
+            assert_eq!(png_color_type(&result), png::ColorType::Indexed);
+            assert_eq!(decode_rgba(&result).unwrap(), decode_rgba(&input).unwrap());
+        }
+
+        let truecolor = convert(input, ImageFormat::Png, None, &[]).unwrap();
+        assert_eq!(png_color_type(&truecolor), png::ColorType::Rgba);
+    }
+
+    #[test]
+    fn indexed_png_prefer_falls_back_to_truecolor() {
+        let input = make_patterned_png(32, 32).1;
+        let result = convert_with_options(
+            input,
+            ImageFormat::Png,
+            &indexed_options(IndexedPng::Prefer),
+            &[],
+        )
+        .unwrap();
+        assert_ne!(png_color_type(&result), png::ColorType::Indexed);
+    }
+
+    #[test]
+    fn indexed_png_require_reports_too_many_colors() {
+        let input = make_patterned_png(32, 32).1;
+        let result = convert_with_options(
+            input,
+            ImageFormat::Png,
+            &indexed_options(IndexedPng::Require),
+            &[],
+        );
+        assert!(matches!(result, Err(ConvertError::TooManyColors)));
+    }
+
+    #[test]
+    fn indexed_png_keeps_carried_chunks() {
+        let options = ConvertOptions {
+            png_indexed: IndexedPng::Require,
+            png_chunks: PngChunkPolicy::parse("+tEXt").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let result =
+            convert_with_options(png_with_text(4, 2), ImageFormat::Png, &options, &[]).unwrap();
+        let info = png_info(&result);
+        assert_eq!(info.color_type, png::ColorType::Indexed);
+        assert_eq!(info.uncompressed_latin1_text[0].keyword, "Title");
+    }
+
+    // ===== GIF Quantizer Tests =====
+
+    #[test]
+    fn gif_quantize_limits_palette() {
+        let (_, input) = make_patterned_png(40, 24);
+        let options = ConvertOptions {
+            gif_quantize: Some(QuantizeOptions {
+                max_colors: 8,
+                quantizer: quantize::Quantizer::Wu { refine: 1 },
+                ..QuantizeOptions::default()
+            }),
+            ..ConvertOptions::default()
+        };
+        let result =
+            convert_with_options(input, ImageFormat::Gif, &options, &[Transform::Rotate90])
+                .unwrap();
+        let img = image::load_from_memory(&result).unwrap().to_rgba8();
+        assert_eq!(img.dimensions(), (24, 40));
+        let colors: std::collections::HashSet<[u8; 4]> = img.pixels().map(|p| p.0).collect();
+        assert!(colors.len() <= 8, "{}", colors.len());
+    }
+
+    #[test]
+    fn gif_quantize_keeps_binary_transparency() {
+        let options = ConvertOptions {
+            gif_quantize: Some(QuantizeOptions::default()),
+            ..ConvertOptions::default()
+        };
+        let result =
+            convert_with_options(make_alpha_png(6, 6), ImageFormat::Gif, &options, &[]).unwrap();
+        let img = image::load_from_memory(&result).unwrap().to_rgba8();
+        for (x, y, px) in img.enumerate_pixels() {
+            let expected = if (x + y) % 2 == 0 {
+                [255, 0, 0, 255]
+            } else {
+                [0; 4]
+            };
+            assert_eq!(px.0, expected, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn gif_quantize_shares_palette_across_frames() {
+        let gif = make_animated_gif(6, 4, &[30, 60, 90]);
+        let options = ConvertOptions {
+            gif_quantize: Some(QuantizeOptions::default()),
+            ..ConvertOptions::default()
+        };
+        let result = convert_with_options(
+            gif,
+            ImageFormat::Gif,
+            &options,
+            &[Transform::FlipHorizontal],
+        )
+        .unwrap();
+        let frames = animation::decode_frames(&result).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(animation::frame_delay_ms(&frames[1]), 60);
+        // The white marker moved to the top-right corner in every frame.
+        for frame in &frames {
+            assert_eq!(*frame.buffer().get_pixel(5, 0), image::Rgba([255; 4]));
+        }
+        assert_eq!(
+            *frames[2].buffer().get_pixel(0, 0),
+            image::Rgba([0, 0, 255, 255])
+        );
+
+        let mut decoder = gif::DecodeOptions::new()
+            .read_info(result.as_slice())
+            .unwrap();
+        assert!(decoder.global_palette().is_some());
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert!(frame.palette.is_none());
+        }
+    }
+
+    // ===== 16-bit Dithering Tests =====
+
+    fn make_16bit_gradient_png(width: u32, height: u32) -> Vec<u8> {
+        let img: image::ImageBuffer<image::Rgb<u16>, Vec<u16>> =
+            image::ImageBuffer::from_fn(width, height, |x, _| {
+                let v = u16::try_from(30_000 + x * 3).unwrap();
+                image::Rgb([v, v, v])
+            });
+        let mut buf = Vec::new();
+        image::DynamicImage::ImageRgb16(img)
+            .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    fn distinct_values_in_row(data: &[u8]) -> usize {
+        let img = image::load_from_memory(data).unwrap().to_rgb8();
+        let row: std::collections::HashSet<u8> =
+            (0..img.width()).map(|x| img.get_pixel(x, 0).0[0]).collect();
+        row.len()
+    }
+
+    fn dither_options() -> ConvertOptions {
+        ConvertOptions {
+            dither_16bit: true,
+            ..ConvertOptions::default()
+        }
+    }
+
+    #[test]
+    fn dither_16bit_breaks_up_bands_for_8bit_targets() {
+        let input = make_16bit_gradient_png(128, 4);
+        let plain = convert(input.clone(), ImageFormat::Bmp, None, &[]).unwrap();
+        let dithered =
+            convert_with_options(input, ImageFormat::Bmp, &dither_options(), &[]).unwrap();
+
+        // Rounding a 0-1.5 level ramp leaves long runs; dithering alternates levels.
+        let runs = |data: &[u8]| {
+            let img = image::load_from_memory(data).unwrap().to_rgb8();
+            (1..img.width())
+                .filter(|&x| img.get_pixel(x, 1).0[0] != img.get_pixel(x - 1, 1).0[0])
+                .count()
+        };
+        assert!(runs(&dithered) > runs(&plain) * 4);
+        assert_eq!(
+            distinct_values_in_row(&plain),
+            distinct_values_in_row(&dithered)
+        );
+    }
+
+    #[test]
+    fn dither_16bit_keeps_16bit_png_output() {
+        let input = make_16bit_gradient_png(16, 2);
+        let result = convert_with_options(input, ImageFormat::Png, &dither_options(), &[]).unwrap();
+        assert_eq!(png_info(&result).bit_depth, png::BitDepth::Sixteen);
+    }
+
+    #[test]
+    fn dither_16bit_ignores_8bit_sources() {
+        let (_, input) = make_patterned_png(16, 16);
+        let plain = convert(input.clone(), ImageFormat::Bmp, None, &[]).unwrap();
+        let dithered =
+            convert_with_options(input, ImageFormat::Bmp, &dither_options(), &[]).unwrap();
+        assert_eq!(plain, dithered);
+    }
+
+    // ===== Deterministic Output Tests =====
+
+    fn deterministic_options() -> ConvertOptions {
+        ConvertOptions {
+            deterministic: true,
+            ..ConvertOptions::default()
+        }
+    }
+
+    fn contains_chunk(png: &[u8], kind: [u8; 4]) -> bool {
+        png.windows(4).any(|w| w == kind)
+    }
+
+    #[test]
+    fn deterministic_output_is_byte_identical() {
+        let (_, input) = make_patterned_png(48, 40);
+        for target in [
+            ImageFormat::Png,
+            ImageFormat::Jpeg,
+            ImageFormat::Gif,
+            ImageFormat::Bmp,
+            ImageFormat::Tiff,
+            ImageFormat::Ico,
+            ImageFormat::Tga,
+            ImageFormat::Qoi,
+        ] {
+            let first =
+                convert_with_options(input.clone(), target, &deterministic_options(), &[]).unwrap();
+            let second =
+                convert_with_options(input.clone(), target, &deterministic_options(), &[]).unwrap();
+            assert_eq!(first, second, "{target:?}");
+        }
+    }
+
+    #[test]
+    fn deterministic_palettes_ignore_hash_order() {
+        // Each histogram is a fresh HashMap with its own random iteration order, so
+        // repeated runs would disagree if palette building depended on it.
+        let (_, input) = make_patterned_png(64, 64);
+        for quantizer in [
+            quantize::Quantizer::default(),
+            quantize::Quantizer::Wu { refine: 2 },
+        ] {
+            let options = ConvertOptions {
+                gif_quantize: Some(QuantizeOptions {
+                    quantizer,
+                    ..QuantizeOptions::default()
+                }),
+                ..deterministic_options()
+            };
+            let first =
+                convert_with_options(input.clone(), ImageFormat::Gif, &options, &[]).unwrap();
+            for _ in 0..4 {
+                let again =
+                    convert_with_options(input.clone(), ImageFormat::Gif, &options, &[]).unwrap();
+                assert_eq!(first, again, "{quantizer:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn deterministic_png_never_carries_time() {
+        let mut input = png_with_text(4, 2);
+        png_chunks::insert_chunks(
+            &mut input,
+            &[png_chunks::CarriedChunk {
+                kind: *b"tIME",
+                data: vec![0x07, 0xE8, 1, 2, 3, 4, 5],
+            }],
+        )
+        .unwrap();
+        let keep = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("keep").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let kept = convert_with_options(input.clone(), ImageFormat::Png, &keep, &[]).unwrap();
+        assert!(contains_chunk(&kept, *b"tIME"));
+
+        let options = ConvertOptions {
+            deterministic: true,
+            ..keep
+        };
+        let result = convert_with_options(input, ImageFormat::Png, &options, &[]).unwrap();
+        assert!(!contains_chunk(&result, *b"tIME"));
+        assert_eq!(
+            png_info(&result).uncompressed_latin1_text[0].text,
+            "Chunk test"
+        );
+    }
+
+    // ===== Conversion Report Tests =====
+
+    #[test]
+    fn report_covers_each_stage() {
+        let (_, input) = make_patterned_png(32, 24);
+        let input_bytes = u64::try_from(input.len()).unwrap();
+        let options = ConvertOptions::default();
+        let transforms = [Transform::Rotate90, Transform::Grayscale];
+        let plain =
+            convert_with_options(input.clone(), ImageFormat::Jpeg, &options, &transforms).unwrap();
+        let ReportedConversion { data, report } =
+            convert_with_report(input, ImageFormat::Jpeg, &options, &transforms).unwrap();
+
+        assert_eq!(data, plain);
+        assert_eq!(report.input_bytes, input_bytes);
+        assert_eq!(report.output_bytes, u64::try_from(data.len()).unwrap());
+        assert_eq!((report.width, report.height, report.frames), (24, 32, 1));
+        let names: Vec<&str> = report.ops.iter().map(|op| op.name).collect();
+        assert_eq!(names, ["rotate_90", "grayscale"]);
+        assert!(report.decode_ms >= 0.0 && report.encode_ms >= 0.0);
+        assert!(report.ops.iter().all(|op| op.ms >= 0.0));
+        // The input and the decoded RGBA pixels were alive together.
+        assert!(report.peak_memory_bytes >= input_bytes + 32 * 24 * 4);
+
+        assert_eq!(report.applied.target, "jpeg");
+        assert_eq!(report.applied.quality, Some(80));
+        assert_eq!(report.applied.transforms, ["rotate_90", "grayscale"]);
+        assert!(!report.applied.indexed_png && !report.applied.dithered_16bit);
+    }
+
+    #[test]
+    fn report_records_applied_fallbacks() {
+        let options = ConvertOptions {
+            quality: Some(40),
+            png_chunks: PngChunkPolicy::parse("+tEXt").unwrap(),
+            png_color_tag: ColorTag::Untagged,
+            png_indexed: IndexedPng::Prefer,
+            ..ConvertOptions::default()
+        };
+        let report = convert_with_report(png_with_text(4, 2), ImageFormat::Png, &options, &[])
+            .unwrap()
+            .report;
+        assert!(report.applied.indexed_png);
+        assert_eq!(report.applied.quality, None);
+        assert_eq!(report.applied.png_chunks, ["tEXt"]);
+
+        let report = convert_with_report(
+            make_16bit_gradient_png(16, 2),
+            ImageFormat::Jpeg,
+            &dither_options(),
+            &[],
+        )
+        .unwrap()
+        .report;
+        assert!(report.applied.dithered_16bit);
+        assert_eq!(report.ops.last().unwrap().name, "dither_16bit");
+    }
+
+    #[test]
+    fn report_covers_animations() {
+        let gif = make_animated_gif(6, 4, &[30, 60, 90]);
+        let report = convert_with_report(
+            gif,
+            ImageFormat::Gif,
+            &ConvertOptions::default(),
+            &[Transform::Rotate270],
+        )
+        .unwrap()
+        .report;
+        assert_eq!((report.width, report.height, report.frames), (4, 6, 3));
+        assert_eq!(report.ops.len(), 1);
+        assert!(report.peak_memory_bytes >= 3 * 6 * 4 * 4);
+    }
+
+    // ===== Instrumentation Tests =====
+
+    struct Recorder(std::rc::Rc<std::cell::RefCell<Vec<Event>>>);
+
+    impl EventSink for Recorder {
+        fn event(&self, event: &Event) {
+            self.0.borrow_mut().push(event.clone());
+        }
+    }
+
+    #[test]
+    fn conversion_emits_operation_events() {
+        let events = std::rc::Rc::default();
+        events::set_event_sink(Some(Box::new(Recorder(std::rc::Rc::clone(&events)))));
+        let (_, input) = make_patterned_png(8, 4);
+        let output = convert(input, ImageFormat::Bmp, None, &[Transform::Rotate90]).unwrap();
+        events::set_event_sink(None);
+
+        let events = events.borrow();
+        let sequence: Vec<(bool, &str)> = events
+            .iter()
+            .map(|event| match event {
+                Event::Started { operation } => (true, *operation),
+                Event::Finished { operation, .. } => (false, *operation),
+            })
+            .collect();
+        assert_eq!(
+            sequence,
+            [
+                (true, "convert"),
+                (true, "decode"),
+                (false, "decode"),
+                (true, "rotate_90"),
+                (false, "rotate_90"),
+                (true, "encode"),
+                (false, "encode"),
+                (false, "convert"),
+            ]
+        );
+        let Some(Event::Finished { bytes, .. }) = events.last() else {
+            panic!("last event should finish the conversion");
+        };
+        assert_eq!(*bytes, u64::try_from(output.len()).unwrap());
+        assert!(matches!(events[2], Event::Finished { bytes: 128, .. }));
+    }
+
+    // ===== Conversion Plan Tests =====
+
+    #[test]
+    fn plan_matches_conversion() {
+        let (_, png) = make_patterned_png(40, 20);
+        let transforms = [Transform::Rotate90];
+        let options = ConvertOptions::default();
+        let plan = plan(&png, ImageFormat::Bmp, &options, &transforms).unwrap();
+        let report = convert_with_report(png, ImageFormat::Bmp, &options, &transforms)
+            .unwrap()
+            .report;
+
+        assert_eq!((plan.input_format, plan.target), ("png", "bmp"));
+        assert_eq!((plan.input_width, plan.input_height), (40, 20));
+        assert_eq!((plan.width, plan.height), (report.width, report.height));
+        assert_eq!(plan.frames, report.frames);
+        assert!(plan.lossy_steps.is_empty());
+        // Uncompressed output makes the estimate close.
+        assert!(plan.approx_bytes.abs_diff(report.output_bytes) < report.output_bytes / 4);
+    }
+
+    #[test]
+    fn plan_lists_lossy_steps() {
+        let options = ConvertOptions::default();
+        let steps = |input: &[u8], target, transforms: &[Transform]| {
+            plan(input, target, &options, transforms)
+                .unwrap()
+                .lossy_steps
+        };
+
+        assert_eq!(
+            steps(
+                &make_alpha_png(4, 4),
+                ImageFormat::Jpeg,
+                &[Transform::Grayscale]
+            ),
+            [
+                LossyStep::Grayscale,
+                LossyStep::AlphaDropped,
+                LossyStep::JpegCompression
+            ]
+        );
+        assert_eq!(
+            steps(&make_16bit_gradient_png(8, 2), ImageFormat::Bmp, &[]),
+            [LossyStep::BitDepthReduced]
+        );
+        assert!(steps(&make_16bit_gradient_png(8, 2), ImageFormat::Png, &[]).is_empty());
+
+        let gif = make_animated_gif(6, 4, &[30, 60, 90]);
+        assert_eq!(
+            steps(&gif, ImageFormat::Png, &[]),
+            [LossyStep::AnimationFlattened]
+        );
+        assert_eq!(
+            steps(&gif, ImageFormat::Gif, &[]),
+            [LossyStep::PaletteReduced]
+        );
+        assert_eq!(
+            plan(&gif, ImageFormat::Gif, &options, &[]).unwrap().frames,
+            3
+        );
+    }
+
+    #[test]
+    fn plan_size_estimates_follow_quality() {
+        let png = make_png(200, 100);
+        let at = |quality| {
+            let options = ConvertOptions {
+                quality: Some(quality),
+                ..ConvertOptions::default()
+            };
+            plan(&png, ImageFormat::Jpeg, &options, &[])
+                .unwrap()
+                .approx_bytes
+        };
+        assert!(at(30) < at(80));
+        assert!(at(80) < at(100));
+        assert_eq!(jpeg_millibits_per_pixel(1), 200);
+        assert_eq!(jpeg_millibits_per_pixel(75), 1500);
+        assert_eq!(jpeg_millibits_per_pixel(100), 6000);
+    }
+
+    #[test]
+    fn plan_rejects_invalid_pipelines() {
+        let png = make_png(300, 10);
+        let options = ConvertOptions::default();
+        assert!(matches!(
+            plan(&png, ImageFormat::WebP, &options, &[]),
+            Err(ConvertError::UnsupportedTarget(_))
+        ));
+        assert!(matches!(
+            plan(&png, ImageFormat::Ico, &options, &[]),
+            Err(ConvertError::TooLargeForTarget {
+                width: 300,
+                height: 10,
+                ..
+            })
+        ));
+        assert!(plan(&make_png(256, 10), ImageFormat::Ico, &options, &[]).is_ok());
+        assert!(matches!(
+            plan(&[1, 2, 3], ImageFormat::Png, &options, &[]),
+            Err(ConvertError::Decode(_))
+        ));
+        let bad_quality = ConvertOptions {
+            quality: Some(0),
+            ..ConvertOptions::default()
+        };
+        assert!(matches!(
+            plan(&png, ImageFormat::Jpeg, &bad_quality, &[]),
+            Err(ConvertError::InvalidQuality(0))
+        ));
+    }
+
+    // ===== Memory Limit Tests =====
+
+    fn limited(limit: u64) -> ConvertOptions {
+        ConvertOptions {
+            max_memory_bytes: Some(limit),
+            ..ConvertOptions::default()
+        }
+    }
+
+    /// An RGBA TIFF in 16-row strips, which a reduced decode reads one at a time.
+    fn make_striped_tiff(img: &RgbaImage) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        let mut encoder = tiff::encoder::TiffEncoder::new(&mut buf).unwrap();
+        let mut image = encoder
+            .new_image::<tiff::encoder::colortype::RGBA8>(img.width(), img.height())
+            .unwrap();
+        image.rows_per_strip(16).unwrap();
+        image.write_data(img.as_raw()).unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn memory_limit_rejects_what_cannot_be_reduced() {
+        let (_, png) = make_patterned_png(64, 64);
+        let planned = plan(&png, ImageFormat::Png, &ConvertOptions::default(), &[]).unwrap();
+        assert!(planned.approx_memory_bytes > 2 * 64 * 64 * 4);
+
+        let fits = limited(planned.approx_memory_bytes);
+        let report = convert_with_report(png.clone(), ImageFormat::Png, &fits, &[])
+            .unwrap()
+            .report;
+        assert_eq!((report.width, report.applied.reduced_by), (64, None));
+
+        let result = convert_with_report(png, ImageFormat::Png, &limited(1000), &[]);
+        let Err(ConvertError::LimitExceeded {
+            required_bytes,
+            limit_bytes,
+        }) = result
+        else {
+            panic!("expected LimitExceeded, got {result:?}");
+        };
+        assert_eq!(
+            (required_bytes, limit_bytes),
+            (planned.approx_memory_bytes, 1000)
+        );
+    }
+
+    #[test]
+    fn memory_limit_reduces_chunked_tiffs() {
+        let tiff = make_striped_tiff(&make_patterned_rgba(64, 300));
+        let full = plan(&tiff, ImageFormat::Png, &ConvertOptions::default(), &[])
+            .unwrap()
+            .approx_memory_bytes;
+
+        let converted =
+            convert_with_report(tiff.clone(), ImageFormat::Png, &limited(full / 2), &[]).unwrap();
+        assert_eq!(converted.report.applied.reduced_by, Some(2));
+        assert_eq!((converted.report.width, converted.report.height), (32, 150));
+        let decoded = image::load_from_memory(&converted.data).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (32, 150));
+
+        assert!(matches!(
+            convert_with_report(tiff, ImageFormat::Png, &limited(10), &[]),
+            Err(ConvertError::LimitExceeded { .. })
+        ));
+    }
+
+    #[test]
+    fn memory_limit_counts_the_reduced_decode_itself() {
+        let tiff = make_striped_tiff(&make_patterned_rgba(64, 300));
+        let input = byte_len(&tiff);
+        let decoding = |factor| {
+            region::reduced_decode_bytes(&tiff, factor)
+                .unwrap()
+                .unwrap()
+        };
+
+        // Just short of what decoding at half size holds, so a third is needed.
+        let limit = input + decoding(2) - 1;
+        assert!(input + decoding(3) <= limit);
+        let converted =
+            convert_with_report(tiff.clone(), ImageFormat::Png, &limited(limit), &[]).unwrap();
+        assert_eq!(converted.report.applied.reduced_by, Some(3));
+    }
+
+    #[test]
+    fn large_downscales_decode_reduced_even_without_a_limit() {
+        let tiff = make_striped_tiff(&make_patterned_rgba(400, 200));
+        let options = ConvertOptions {
+            resize: Some(ResizeStep {
+                mode: ResizeMode::Fit,
+                width: 50,
+                height: 50,
+                options: ResizeOptions::default(),
+            }),
+            ..ConvertOptions::default()
+        };
+        // Turned upright, the 200x400 source fits 25x50: its 1/8 reduction, rotated.
+        let converted = convert_with_report(
+            tiff.clone(),
+            ImageFormat::Png,
+            &options,
+            &[Transform::Rotate90],
+        )
+        .unwrap();
+        assert_eq!(converted.report.applied.reduced_by, None);
+        assert_eq!((converted.report.width, converted.report.height), (25, 50));
+        let decoded = image::load_from_memory(&converted.data)
+            .unwrap()
+            .into_rgba8();
+        let reduced = region::decode_reduced(&tiff, 8).unwrap().unwrap();
+        assert_eq!(decoded, image::imageops::rotate90(&reduced));
+    }
+
+    #[test]
+    fn memory_limit_scales_crop_and_resize_with_the_reduction() {
+        let tiff = make_striped_tiff(&make_patterned_rgba(400, 400));
+        let full = plan(&tiff, ImageFormat::Png, &ConvertOptions::default(), &[])
+            .unwrap()
+            .approx_memory_bytes;
+
+        for x in [150, 250, 300] {
+            let options = ConvertOptions {
+                crop: Some(CropBox {
+                    x,
+                    y: 0,
+                    width: 100,
+                    height: 100,
+                }),
+                ..limited(full / 2)
+            };
+            let converted =
+                convert_with_report(tiff.clone(), ImageFormat::Png, &options, &[]).unwrap();
+            assert_eq!(converted.report.applied.reduced_by, Some(2));
+            assert_eq!((converted.report.width, converted.report.height), (50, 50));
+            let decoded = image::load_from_memory(&converted.data)
+                .unwrap()
+                .into_rgba8();
+            let reduced = region::decode_reduced(&tiff, 2).unwrap().unwrap();
+            assert_eq!(
+                decoded,
+                image::imageops::crop_imm(&reduced, x / 2, 0, 50, 50).to_image()
+            );
+        }
+
+        let options = ConvertOptions {
+            crop: Some(CropBox {
+                x: 301,
+                y: 0,
+                width: 100,
+                height: 100,
+            }),
+            ..limited(full / 2)
+        };
+        assert!(matches!(
+            convert_with_report(tiff.clone(), ImageFormat::Png, &options, &[]),
+            Err(ConvertError::InvalidCrop { .. })
+        ));
+
+        let options = ConvertOptions {
+            resize: Some(ResizeStep {
+                mode: ResizeMode::Fit,
+                width: 300,
+                height: 300,
+                options: ResizeOptions::default(),
+            }),
+            watermark: Some(WatermarkStep {
+                logo: RgbaImage::from_pixel(40, 20, image::Rgba([255, 0, 0, 255])),
+                opacity: 1.0,
+            }),
+            ..limited(full / 2)
+        };
+        let converted = convert_with_report(tiff, ImageFormat::Png, &options, &[]).unwrap();
+        assert_eq!(converted.report.applied.reduced_by, Some(2));
+        assert_eq!(
+            (converted.report.width, converted.report.height),
+            (150, 150)
+        );
+    }
+
+    // ===== Source Format Tests =====
+
+    fn make_tga(width: u32, height: u32) -> Vec<u8> {
+        let img = make_patterned_rgba(width, height);
+        let mut buf = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Tga)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn source_format_decodes_undetectable_input() {
+        let tga = make_tga(5, 3);
+        assert!(matches!(
+            convert(tga.clone(), ImageFormat::Png, None, &[]),
+            Err(ConvertError::Decode(_))
+        ));
+
+        let options = ConvertOptions {
+            source_format: Some(ImageFormat::Tga),
+            ..ConvertOptions::default()
+        };
+        let png = convert_with_options(tga.clone(), ImageFormat::Png, &options, &[]).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert_eq!(decoded, make_patterned_rgba(5, 3));
+
+        let plan = plan(&tga, ImageFormat::Png, &options, &[]).unwrap();
+        assert_eq!(plan.input_format, "tga");
+        assert_eq!((plan.width, plan.height), (5, 3));
+    }
+
+    #[test]
+    fn source_format_never_overrides_detection() {
+        let options = ConvertOptions {
+            source_format: Some(ImageFormat::Tga),
+            ..ConvertOptions::default()
+        };
+        let output = convert_with_options(make_png(4, 4), ImageFormat::Bmp, &options, &[]);
+        assert!(output.is_ok());
+    }
 }
@@ -0,0 +1,289 @@
+use std::fmt;
+
+use image::{imageops, DynamicImage, Rgba, RgbaImage};
+
+use crate::color;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::stats;
+use crate::warp::{self, WarpError};
+
+/// Largest skew, in degrees either way, that [`estimate_skew`] looks for.
+pub const MAX_SKEW_DEGREES: f64 = 15.0;
+
+/// Longest side the skew is estimated at; larger images are thumbnailed first, which
+/// leaves the angle unchanged.
+const ANALYSIS_SIDE: u32 = 800;
+
+/// A deskewed image and the angle it was corrected by.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Deskewed {
+    pub data: Vec<u8>,
+    /// The detected skew in degrees; positive when the content was turned clockwise.
+    /// The image was rotated by the opposite amount.
+    pub angle: f64,
+}
+
+/// Foreground (ink) pixels of an image, split from the background by Otsu's threshold.
+struct Ink {
+    /// Coordinates of the foreground pixels.
+    points: Vec<(f64, f64)>,
+    /// The average background color, used to fill the corners a rotation uncovers.
+    background: Rgba<u8>,
+}
+
+/// Estimates how far the text lines or edges of `img` are turned from level, in
+/// degrees (positive is clockwise), by finding the angle at which the horizontal
+/// projection profile of the ink is sharpest. Returns `0.0` for blank images.
+pub fn estimate_skew(img: &RgbaImage) -> f64 {
+    skew_of(&ink(&thumbnail(img)).points)
+}
+
+/// Levels `img`: estimates its skew and rotates it back about its center, keeping its
+/// size and filling the uncovered corners with the background color. Returns the
+/// corrected image and the detected angle.
+///
+/// # Errors
+///
+/// Returns `DeskewError::Warp` if the image is too large to rotate.
+pub fn deskew_image(img: &RgbaImage) -> Result<(RgbaImage, f64), DeskewError> {
+    let ink = ink(&thumbnail(img));
+    let angle = skew_of(&ink.points);
+    if angle == 0.0 {
+        return Ok((img.clone(), angle));
+    }
+
+    let (sin, cos) = (-angle).to_radians().sin_cos();
+    let (cx, cy) = (f64::from(img.width()) / 2.0, f64::from(img.height()) / 2.0);
+    let matrix = [
+        cos,
+        -sin,
+        cx - cos * cx + sin * cy,
+        sin,
+        cos,
+        cy - sin * cx - cos * cy,
+    ];
+    let mut rotated =
+        warp::affine(img, matrix, img.width(), img.height()).map_err(DeskewError::Warp)?;
+    let [br, bg, bb, ba] = ink.background.0;
+    for pixel in rotated.pixels_mut() {
+        let [r, g, b, a] = pixel.0;
+        if a < 255 {
+            // Source-over onto the background.
+            let t = f64::from(a) / 255.0;
+            let over = |top: u8, bottom: u8| {
+                color::to_u8(f64::from(top) * t + f64::from(bottom) * (1.0 - t))
+            };
+            let alpha = color::to_u8(f64::from(a) + f64::from(ba) * (1.0 - t));
+            *pixel = Rgba([over(r, br), over(g, bg), over(b, bb), alpha]);
+        }
+    }
+    Ok((rotated, angle))
+}
+
+/// Decodes `input`, levels it with [`deskew_image`], and encodes the result as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns a `DeskewError` if the input cannot be decoded, is too large to rotate, or
+/// the output cannot be encoded.
+pub fn deskew(
+    input: &[u8],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Deskewed, DeskewError> {
+    let img = image::load_from_memory(input)
+        .map_err(DeskewError::Decode)?
+        .into_rgba8();
+    let (leveled, angle) = deskew_image(&img)?;
+    let data = convert::encode(&DynamicImage::ImageRgba8(leveled), target, quality)
+        .map_err(DeskewError::Convert)?;
+    Ok(Deskewed { data, angle })
+}
+
+fn thumbnail(img: &RgbaImage) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    let longest = width.max(height);
+    if longest <= ANALYSIS_SIDE {
+        return img.clone();
+    }
+    let scale = |side: u32| {
+        u32::try_from(u64::from(side) * u64::from(ANALYSIS_SIDE) / u64::from(longest))
+            .unwrap_or(ANALYSIS_SIDE)
+            .max(1)
+    };
+    imageops::thumbnail(img, scale(width), scale(height))
+}
+
+/// Splits `img` into ink and background with Otsu's threshold on luma (composited over
+/// white, so transparent areas count as background). The minority class is the ink,
+/// so light text on a dark page works too.
+fn ink(img: &RgbaImage) -> Ink {
+    let luma: Vec<u8> = img
+        .pixels()
+        .map(|pixel| {
+            let [.., a] = pixel.0;
+            let t = f64::from(a) / 255.0;
+            let value = f64::from(color::luminance(pixel.0));
+            color::to_u8(value * t + 255.0 * (1.0 - t))
+        })
+        .collect();
+    let threshold = stats::otsu_threshold(&luma);
+
+    let dark = luma.iter().filter(|&&value| value <= threshold).count();
+    let dark_is_ink = dark * 2 <= luma.len();
+    let mut points = Vec::new();
+    let mut sum = [0u64; 4];
+    let mut count = 0u64;
+    for ((x, y, pixel), &value) in img.enumerate_pixels().zip(&luma) {
+        if (value <= threshold) == dark_is_ink {
+            points.push((f64::from(x), f64::from(y)));
+        } else {
+            for (total, channel) in sum.iter_mut().zip(pixel.0) {
+                *total += u64::from(channel);
+            }
+            count += 1;
+        }
+    }
+    let background = Rgba(
+        sum.map(|total| u8::try_from(total.checked_div(count).unwrap_or(255)).unwrap_or(u8::MAX)),
+    );
+    Ink { points, background }
+}
+
+/// The angle in `-MAX_SKEW_DEGREES..=MAX_SKEW_DEGREES` with the sharpest projection
+/// profile of `points`: a coarse half-degree search refined to a twentieth of a degree.
+fn skew_of(points: &[(f64, f64)]) -> f64 {
+    if points.is_empty() {
+        return 0.0;
+    }
+    let search = |center: f64, step: f64, steps: i32| {
+        (-steps..=steps)
+            .map(|i| center + f64::from(i) * step)
+            .filter(|angle| angle.abs() <= MAX_SKEW_DEGREES)
+            .map(|angle| (angle, sharpness(points, angle)))
+            .fold((0.0, f64::MIN), |best, candidate| {
+                if candidate.1 > best.1 {
+                    candidate
+                } else {
+                    best
+                }
+            })
+            .0
+    };
+    let coarse = search(0.0, 0.5, 30);
+    let fine = search(coarse, 0.05, 10);
+    // Snap away rounding noise so a level image reports exactly zero.
+    if fine.abs() < 0.025 {
+        0.0
+    } else {
+        (fine * 100.0).round() / 100.0
+    }
+}
+
+/// Sum of squared row counts when `points` are projected onto lines turned by `angle`
+/// degrees: largest when the lines run along the rows of text. Each point is split
+/// between the two nearest rows so the score changes smoothly with the angle.
+fn sharpness(points: &[(f64, f64)], angle: f64) -> f64 {
+    let (sin, cos) = angle.to_radians().sin_cos();
+    let offset = points.iter().map(|&(x, _)| x).fold(0.0, f64::max);
+    let mut bins = Vec::new();
+    for &(x, y) in points {
+        let position = (y * cos - x * sin + offset).max(0.0);
+        let row = to_bin(position);
+        let fraction = position - position.floor();
+        if bins.len() <= row + 1 {
+            bins.resize(row + 2, 0.0);
+        }
+        if let Some([here, next]) = bins.get_mut(row..row + 2) {
+            *here += 1.0 - fraction;
+            *next += fraction;
+        }
+    }
+    bins.iter().map(|n| n * n).sum()
+}
+
+// Safe: the value is non-negative and floored, and the analysis image is at most
+// ANALYSIS_SIDE pixels on a side, so it fits in usize.
+#[allow(clippy::as_conversions)]
+fn to_bin(value: f64) -> usize {
+    value.floor() as usize
+}
+
+/// Errors that can occur while deskewing an image.
+#[derive(Debug)]
+pub enum DeskewError {
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// The image was too large to rotate.
+    Warp(WarpError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for DeskewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Warp(e) => write!(f, "{e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for DeskewError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A white page with black text lines 3px thick every 12px, turned clockwise by
+    /// `angle` degrees.
+    fn page(angle: f64) -> RgbaImage {
+        let (sin, cos) = angle.to_radians().sin_cos();
+        RgbaImage::from_fn(300, 200, |x, y| {
+            let (x, y) = (f64::from(x) - 150.0, f64::from(y) - 100.0);
+            let (u, v) = (x * cos + y * sin, y * cos - x * sin);
+            if u.abs() < 120.0 && v.abs() < 80.0 && v.rem_euclid(12.0) < 3.0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255; 4])
+            }
+        })
+    }
+
+    /// A pixel of drift across the 240px lines is about a quarter of a degree, so the
+    /// jagged rasterized lines can't pin the angle down more closely than that.
+    const TOLERANCE: f64 = 0.25;
+
+    #[test]
+    fn estimates_the_skew_of_text_lines() {
+        for angle in [-7.0, -2.5, 0.0, 1.2, 4.0] {
+            let estimate = estimate_skew(&page(angle));
+            assert!((estimate - angle).abs() <= TOLERANCE, "{angle}: {estimate}");
+        }
+    }
+
+    #[test]
+    fn blank_pages_are_left_alone() {
+        let blank = RgbaImage::from_pixel(40, 30, Rgba([255; 4]));
+        assert_eq!(deskew_image(&blank).unwrap(), (blank, 0.0));
+    }
+
+    #[test]
+    fn deskewing_levels_the_page_and_fills_corners() {
+        let (leveled, angle) = deskew_image(&page(5.0)).unwrap();
+        assert!((angle - 5.0).abs() <= TOLERANCE, "{angle}");
+        assert_eq!(leveled.dimensions(), (300, 200));
+        assert!(estimate_skew(&leveled).abs() <= TOLERANCE);
+        assert_eq!(leveled.get_pixel(0, 0).0, [255; 4]);
+    }
+
+    #[test]
+    fn finds_light_ink_on_dark_pages() {
+        let mut img = page(-3.0);
+        imageops::invert(&mut img);
+        assert!((estimate_skew(&img) + 3.0).abs() <= TOLERANCE);
+    }
+}
@@ -0,0 +1,171 @@
+use image::{DynamicImage, ImageBuffer, Pixel};
+
+/// Reduces a 16-bit or float image to 8 bits per channel with Floyd–Steinberg error
+/// diffusion on the color channels, which avoids the banding plain rounding leaves
+/// in smooth gradients. Alpha is rounded, since dithering it roughens edges.
+///
+/// The channel layout is kept (16-bit grayscale becomes 8-bit grayscale, and so on);
+/// float images become RGB8/RGBA8. Images that are already 8-bit are returned as-is.
+pub fn dither_to_8bit(img: DynamicImage) -> DynamicImage {
+    match img {
+        DynamicImage::ImageLuma16(buf) => DynamicImage::ImageLuma8(dither_buffer(&buf, 1)),
+        DynamicImage::ImageLumaA16(buf) => DynamicImage::ImageLumaA8(dither_buffer(&buf, 1)),
+        DynamicImage::ImageRgb16(buf) => DynamicImage::ImageRgb8(dither_buffer(&buf, 3)),
+        DynamicImage::ImageRgba16(buf) => DynamicImage::ImageRgba8(dither_buffer(&buf, 3)),
+        DynamicImage::ImageRgb32F(_) => DynamicImage::ImageRgb8(dither_buffer(&img.to_rgb16(), 3)),
+        DynamicImage::ImageRgba32F(_) => {
+            DynamicImage::ImageRgba8(dither_buffer(&img.to_rgba16(), 3))
+        }
+        other => other,
+    }
+}
+
+/// Dithers a 16-bit buffer into the 8-bit buffer with the same pixel type. The first
+/// `color_channels` channels of each pixel are dithered, the rest rounded.
+fn dither_buffer<P8, P16>(
+    buf: &ImageBuffer<P16, Vec<u16>>,
+    color_channels: usize,
+) -> ImageBuffer<P8, Vec<u8>>
+where
+    P8: Pixel<Subpixel = u8>,
+    P16: Pixel<Subpixel = u16>,
+{
+    let width = usize::try_from(buf.width()).unwrap_or(0);
+    let channels = usize::from(P16::CHANNEL_COUNT);
+    let samples = dither_samples(buf.as_raw(), width, channels, color_channels);
+    // The sample count is unchanged, so the buffer always fits its dimensions.
+    ImageBuffer::from_raw(buf.width(), buf.height(), samples).unwrap_or_default()
+}
+
+/// Floyd–Steinberg over interleaved samples. Error for each channel is diffused only
+/// to the same channel of neighbouring pixels.
+fn dither_samples(samples: &[u16], width: usize, channels: usize, dithered: usize) -> Vec<u8> {
+    let row_len = width * channels;
+    // One pixel of padding on each side so neighbours never fall off the row.
+    let mut current = vec![0i32; row_len + 2 * channels];
+    let mut next = vec![0i32; row_len + 2 * channels];
+    let mut out = Vec::with_capacity(samples.len());
+
+    for row in samples.chunks(row_len.max(1)) {
+        for (i, &sample) in row.iter().enumerate() {
+            if i % channels >= dithered {
+                out.push(to_8bit(i32::from(sample)));
+                continue;
+            }
+
+            let slot = i + channels;
+            let carried = current.get(slot).copied().unwrap_or(0);
+            let value = (i32::from(sample) + carried).clamp(0, i32::from(u16::MAX));
+            let quantized = to_8bit(value);
+            out.push(quantized);
+
+            let error = value - i32::from(quantized) * 257;
+            diffuse(&mut current, slot + channels, error * 7 / 16);
+            diffuse(&mut next, slot - channels, error * 3 / 16);
+            diffuse(&mut next, slot, error * 5 / 16);
+            diffuse(&mut next, slot + channels, error / 16);
+        }
+        std::mem::swap(&mut current, &mut next);
+        next.fill(0);
+    }
+    out
+}
+
+fn diffuse(row: &mut [i32], slot: usize, amount: i32) {
+    if let Some(cell) = row.get_mut(slot) {
+        *cell += amount;
+    }
+}
+
+/// Rounds a 16-bit sample to the nearest 8-bit level (`v / 257`).
+fn to_8bit(value: i32) -> u8 {
+    u8::try_from((value + 128) / 257).unwrap_or(u8::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A horizontal 16-bit gradient spanning only a few 8-bit levels, where rounding
+    /// produces wide flat bands.
+    fn shallow_gradient(width: u32, height: u32) -> image::ImageBuffer<image::Rgb<u16>, Vec<u16>> {
+        image::ImageBuffer::from_fn(width, height, |x, _| {
+            let v = u16::try_from(20_000 + x * 4).unwrap();
+            image::Rgb([v, v, v])
+        })
+    }
+
+    /// Sum of absolute differences between 16-px column band averages of the source
+    /// and the 8-bit result, in 16-bit units.
+    fn band_error(
+        source: &image::ImageBuffer<image::Rgb<u16>, Vec<u16>>,
+        result: &image::RgbImage,
+    ) -> f64 {
+        let (width, height) = source.dimensions();
+        (0..width)
+            .step_by(16)
+            .map(|x0| {
+                let mut expected = 0.0;
+                let mut actual = 0.0;
+                for x in x0..(x0 + 16).min(width) {
+                    for y in 0..height {
+                        expected += f64::from(source.get_pixel(x, y).0[0]);
+                        actual += f64::from(result.get_pixel(x, y).0[0]) * 257.0;
+                    }
+                }
+                (expected - actual).abs()
+            })
+            .sum()
+    }
+
+    #[test]
+    fn dithering_tracks_gradient_better_than_rounding() {
+        let source = shallow_gradient(256, 16);
+        let rounded = DynamicImage::ImageRgb16(source.clone()).to_rgb8();
+        let dithered = dither_to_8bit(DynamicImage::ImageRgb16(source.clone())).to_rgb8();
+        assert!(band_error(&source, &dithered) < band_error(&source, &rounded) / 4.0);
+    }
+
+    #[test]
+    fn flat_levels_are_exact() {
+        let img =
+            image::ImageBuffer::from_pixel(8, 8, image::Rgba([257 * 40, 0, u16::MAX, 257 * 9]));
+        let out = dither_to_8bit(DynamicImage::ImageRgba16(img));
+        let DynamicImage::ImageRgba8(out) = out else {
+            panic!("expected RGBA8");
+        };
+        assert!(out.pixels().all(|p| p.0 == [40, 0, 255, 9]));
+    }
+
+    #[test]
+    fn channel_layout_is_preserved() {
+        let luma = image::ImageBuffer::from_pixel(3, 3, image::Luma([1000u16]));
+        assert!(matches!(
+            dither_to_8bit(DynamicImage::ImageLuma16(luma)),
+            DynamicImage::ImageLuma8(_)
+        ));
+        let luma_alpha = image::ImageBuffer::from_pixel(3, 3, image::LumaA([1000u16, 500]));
+        assert!(matches!(
+            dither_to_8bit(DynamicImage::ImageLumaA16(luma_alpha)),
+            DynamicImage::ImageLumaA8(_)
+        ));
+        let rgb8 = DynamicImage::ImageRgb8(image::RgbImage::new(2, 2));
+        assert_eq!(dither_to_8bit(rgb8.clone()), rgb8);
+    }
+
+    #[test]
+    fn alpha_is_rounded_not_dithered() {
+        let img = image::ImageBuffer::from_fn(64, 4, |x, _| {
+            let a = u16::try_from(30_000 + x * 3).unwrap();
+            image::Rgba([0u16, 0, 0, a])
+        });
+        let DynamicImage::ImageRgba8(out) = dither_to_8bit(DynamicImage::ImageRgba16(img.clone()))
+        else {
+            panic!("expected RGBA8");
+        };
+        for (x, y, px) in out.enumerate_pixels() {
+            let expected = to_8bit(i32::from(img.get_pixel(x, y).0[3]));
+            assert_eq!(px.0[3], expected);
+        }
+    }
+}
@@ -0,0 +1,530 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use image::{imageops, DynamicImage, GrayImage, Luma, RgbaImage};
+use serde::Serialize;
+
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+
+/// Default Canny threshold below which a gradient is never an edge.
+pub const DEFAULT_LOW: f64 = 20.0;
+
+/// Default Canny threshold at or above which a gradient is always an edge.
+pub const DEFAULT_HIGH: f64 = 50.0;
+
+/// Default distance, in pixels, a simplified contour may stray from the traced edge.
+pub const DEFAULT_EPSILON: f64 = 1.5;
+
+/// Blur applied before Canny so noise doesn't register as edges.
+const CANNY_SIGMA: f32 = 1.4;
+
+/// How edges are detected.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum EdgeMethod {
+    /// Gradient magnitude: soft edges whose brightness follows their strength.
+    Sobel,
+    /// Thin, connected one-pixel edges: blurred, non-maximum suppressed and
+    /// hysteresis thresholded.
+    #[default]
+    Canny,
+}
+
+impl EdgeMethod {
+    /// Parses `"sobel"` or `"canny"`. An empty string selects `"canny"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EdgeError::UnknownMethod` if the name is not recognized.
+    pub fn from_name(name: &str) -> Result<Self, EdgeError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "canny" => Ok(Self::Canny),
+            "sobel" => Ok(Self::Sobel),
+            _ => Err(EdgeError::UnknownMethod(name.to_owned())),
+        }
+    }
+}
+
+/// Settings for [`edge_map`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct EdgeOptions {
+    pub method: EdgeMethod,
+    /// Canny's hysteresis thresholds, on the same 0–255 scale as the Sobel map: weak
+    /// edges between `low` and `high` are kept only where they touch a strong one.
+    pub low: f64,
+    pub high: f64,
+}
+
+impl Default for EdgeOptions {
+    fn default() -> Self {
+        Self {
+            method: EdgeMethod::default(),
+            low: DEFAULT_LOW,
+            high: DEFAULT_HIGH,
+        }
+    }
+}
+
+impl EdgeOptions {
+    fn validate(&self) -> Result<(), EdgeError> {
+        if self.low.is_finite() && self.high.is_finite() && (0.0..=self.high).contains(&self.low) {
+            Ok(())
+        } else {
+            Err(EdgeError::InvalidThresholds {
+                low: self.low,
+                high: self.high,
+            })
+        }
+    }
+}
+
+/// A traced edge, simplified to the points where it changes direction.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Contour {
+    /// `[x, y]` pixel coordinates.
+    pub points: Vec<[u32; 2]>,
+    /// Whether the edge loops back to its start, making `points` a polygon.
+    pub closed: bool,
+}
+
+/// Horizontal and vertical Sobel gradients of a grayscale image, row-major.
+struct Gradients {
+    width: u32,
+    height: u32,
+    gx: Vec<f64>,
+    gy: Vec<f64>,
+}
+
+impl Gradients {
+    fn new(img: &GrayImage) -> Self {
+        let (width, height) = img.dimensions();
+        let at = |x: i64, y: i64| {
+            let x = u32::try_from(x.clamp(0, i64::from(width) - 1)).unwrap_or(0);
+            let y = u32::try_from(y.clamp(0, i64::from(height) - 1)).unwrap_or(0);
+            img.get_pixel_checked(x, y)
+                .map_or(0.0, |&Luma([value])| f64::from(value))
+        };
+        let (mut gx, mut gy) = (Vec::new(), Vec::new());
+        for y in 0..i64::from(height) {
+            for x in 0..i64::from(width) {
+                let column = |dx: i64| at(x + dx, y - 1) + 2.0 * at(x + dx, y) + at(x + dx, y + 1);
+                let row = |dy: i64| at(x - 1, y + dy) + 2.0 * at(x, y + dy) + at(x + 1, y + dy);
+                gx.push(column(1) - column(-1));
+                gy.push(row(1) - row(-1));
+            }
+        }
+        Self {
+            width,
+            height,
+            gx,
+            gy,
+        }
+    }
+
+    /// Gradient magnitude scaled so a hard black-to-white step reads 255.
+    fn magnitude(&self, index: usize) -> f64 {
+        match (self.gx.get(index), self.gy.get(index)) {
+            (Some(gx), Some(gy)) => gx.hypot(*gy) / 4.0,
+            _ => 0.0,
+        }
+    }
+
+    fn index(&self, x: i64, y: i64) -> Option<usize> {
+        let inside =
+            (0..i64::from(self.width)).contains(&x) && (0..i64::from(self.height)).contains(&y);
+        inside
+            .then(|| usize::try_from(y * i64::from(self.width) + x).ok())
+            .flatten()
+    }
+}
+
+/// Detects the edges of `img` as a grayscale map: the Sobel gradient magnitude, or
+/// white-on-black Canny edges.
+///
+/// # Errors
+///
+/// Returns `EdgeError::InvalidThresholds` if the Canny thresholds aren't finite with
+/// `0 <= low <= high`.
+pub fn edge_map(img: &RgbaImage, options: &EdgeOptions) -> Result<GrayImage, EdgeError> {
+    options.validate()?;
+    let gray = DynamicImage::ImageRgba8(img.clone()).into_luma8();
+    Ok(match options.method {
+        EdgeMethod::Sobel => {
+            let gradients = Gradients::new(&gray);
+            GrayImage::from_fn(gray.width(), gray.height(), |x, y| {
+                let value = gradients
+                    .index(i64::from(x), i64::from(y))
+                    .map_or(0.0, |i| gradients.magnitude(i));
+                Luma([crate::color::to_u8(value)])
+            })
+        }
+        EdgeMethod::Canny => canny(&gray, options.low, options.high),
+    })
+}
+
+fn canny(gray: &GrayImage, low: f64, high: f64) -> GrayImage {
+    let gradients = Gradients::new(&imageops::blur(gray, CANNY_SIGMA));
+    let (width, height) = (gray.width(), gray.height());
+
+    // Keep only gradients that peak across the edge, thinning it to one pixel.
+    let mut suppressed = Vec::new();
+    for y in 0..i64::from(height) {
+        for x in 0..i64::from(width) {
+            let index = gradients.index(x, y).unwrap_or(0);
+            let magnitude = gradients.magnitude(index);
+            let gx = gradients.gx.get(index).copied().unwrap_or(0.0);
+            let gy = gradients.gy.get(index).copied().unwrap_or(0.0);
+            let (dx, dy) = step_along(gx, gy);
+            let neighbour = |sign: i64| {
+                gradients
+                    .index(x + sign * dx, y + sign * dy)
+                    .map_or(0.0, |i| gradients.magnitude(i))
+            };
+            let peak = magnitude >= neighbour(1) && magnitude > neighbour(-1);
+            suppressed.push(if peak { magnitude } else { 0.0 });
+        }
+    }
+
+    // Hysteresis: grow strong edges through connected weak ones.
+    let mut edges = GrayImage::new(width, height);
+    let mut queue: VecDeque<(i64, i64)> = VecDeque::new();
+    for y in 0..i64::from(height) {
+        for x in 0..i64::from(width) {
+            let strong = gradients
+                .index(x, y)
+                .and_then(|i| suppressed.get(i))
+                .is_some_and(|&value| value >= high && value > 0.0);
+            if strong {
+                queue.push_back((x, y));
+            }
+        }
+    }
+    while let Some((x, y)) = queue.pop_front() {
+        let Some(pixel) = to_u32(x, y).and_then(|(x, y)| edges.get_pixel_mut_checked(x, y)) else {
+            continue;
+        };
+        if pixel.0 == [255] {
+            continue;
+        }
+        *pixel = Luma([255]);
+        for (nx, ny) in neighbours(x, y) {
+            let weak = gradients
+                .index(nx, ny)
+                .and_then(|i| suppressed.get(i))
+                .is_some_and(|&value| value >= low && value > 0.0);
+            if weak {
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    edges
+}
+
+/// The neighbouring pixel offset closest to the gradient direction.
+fn step_along(gx: f64, gy: f64) -> (i64, i64) {
+    // tan(22.5°): within this of an axis, the gradient counts as along it.
+    const TAN_22_5: f64 = 0.414_213_562_373_095;
+    if gy.abs() <= gx.abs() * TAN_22_5 {
+        (1, 0)
+    } else if gx.abs() <= gy.abs() * TAN_22_5 {
+        (0, 1)
+    } else if gx * gy > 0.0 {
+        (1, 1)
+    } else {
+        (1, -1)
+    }
+}
+
+/// The 8 neighbours of `x`, `y`, edge-adjacent ones first.
+fn neighbours(x: i64, y: i64) -> [(i64, i64); 8] {
+    [
+        (x + 1, y),
+        (x, y + 1),
+        (x - 1, y),
+        (x, y - 1),
+        (x + 1, y + 1),
+        (x - 1, y + 1),
+        (x - 1, y - 1),
+        (x + 1, y - 1),
+    ]
+}
+
+fn to_u32(x: i64, y: i64) -> Option<(u32, u32)> {
+    u32::try_from(x).ok().zip(u32::try_from(y).ok())
+}
+
+/// Traces the edge pixels (any nonzero value) of `edges` into polylines and simplifies
+/// them with Ramer–Douglas–Peucker, so no dropped point lies more than `epsilon`
+/// pixels from the simplified line. Isolated pixels are skipped.
+pub fn contours(edges: &GrayImage, epsilon: f64) -> Vec<Contour> {
+    let (width, height) = edges.dimensions();
+    let is_edge = |x: i64, y: i64| {
+        to_u32(x, y)
+            .and_then(|(x, y)| edges.get_pixel_checked(x, y))
+            .is_some_and(|&Luma([value])| value > 0)
+    };
+    let mut visited =
+        vec![false; usize::try_from(u64::from(width) * u64::from(height)).unwrap_or(0)];
+    let index = |x: i64, y: i64| usize::try_from(y * i64::from(width) + x).unwrap_or(usize::MAX);
+    let visit = |visited: &mut Vec<bool>, x: i64, y: i64| {
+        visited
+            .get_mut(index(x, y))
+            .is_some_and(|seen| !std::mem::replace(seen, true))
+    };
+
+    let mut out = Vec::new();
+    for y in 0..i64::from(height) {
+        for x in 0..i64::from(width) {
+            if !is_edge(x, y) || !visit(&mut visited, x, y) {
+                continue;
+            }
+            // Walk from this pixel, always taking an unvisited neighbouring edge pixel.
+            let mut path = vec![(x, y)];
+            let (mut cx, mut cy) = (x, y);
+            while let Some((nx, ny)) = neighbours(cx, cy)
+                .into_iter()
+                .find(|&(nx, ny)| is_edge(nx, ny) && visited.get(index(nx, ny)) == Some(&false))
+            {
+                visit(&mut visited, nx, ny);
+                path.push((nx, ny));
+                (cx, cy) = (nx, ny);
+            }
+            if path.len() < 2 {
+                continue;
+            }
+            let closed = path.len() > 2
+                && path
+                    .first()
+                    .zip(path.last())
+                    .is_some_and(|(&(x0, y0), &(x1, y1))| {
+                        (x0 - x1).abs() <= 1 && (y0 - y1).abs() <= 1
+                    });
+            let points = simplify(&path, epsilon)
+                .into_iter()
+                .filter_map(|(x, y)| to_u32(x, y).map(|(x, y)| [x, y]))
+                .collect();
+            out.push(Contour { points, closed });
+        }
+    }
+    out
+}
+
+/// Ramer–Douglas–Peucker: keeps the endpoints and, recursively, the point farthest from
+/// the line between them while it is more than `epsilon` away.
+fn simplify(path: &[(i64, i64)], epsilon: f64) -> Vec<(i64, i64)> {
+    let (Some(&first), Some(&last)) = (path.first(), path.last()) else {
+        return Vec::new();
+    };
+    if path.len() < 3 {
+        return path.to_vec();
+    }
+    let to_f64 = |(x, y): (i64, i64)| {
+        (
+            f64::from(i32::try_from(x).unwrap_or(i32::MAX)),
+            f64::from(i32::try_from(y).unwrap_or(i32::MAX)),
+        )
+    };
+    let ((x0, y0), (x1, y1)) = (to_f64(first), to_f64(last));
+    let length = (x1 - x0).hypot(y1 - y0);
+    let distance = |point: (i64, i64)| {
+        let (x, y) = to_f64(point);
+        if length == 0.0 {
+            (x - x0).hypot(y - y0)
+        } else {
+            ((x1 - x0) * (y0 - y) - (x0 - x) * (y1 - y0)).abs() / length
+        }
+    };
+    let farthest = path
+        .iter()
+        .enumerate()
+        .map(|(i, &point)| (i, distance(point)))
+        .fold((0, 0.0), |best, candidate| {
+            if candidate.1 > best.1 {
+                candidate
+            } else {
+                best
+            }
+        });
+    match farthest {
+        (split, max) if max > epsilon => {
+            let (head, tail) = (path.get(..=split), path.get(split..));
+            let mut points = head.map(|head| simplify(head, epsilon)).unwrap_or_default();
+            points.pop();
+            points.extend(tail.map(|tail| simplify(tail, epsilon)).unwrap_or_default());
+            points
+        }
+        _ => vec![first, last],
+    }
+}
+
+/// Decodes `input`, detects its edges with [`edge_map`], and encodes the map as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns an `EdgeError` if the thresholds are invalid, the input cannot be decoded,
+/// or the output cannot be encoded.
+pub fn detect_edges(
+    input: &[u8],
+    options: &EdgeOptions,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, EdgeError> {
+    let img = image::load_from_memory(input)
+        .map_err(EdgeError::Decode)?
+        .into_rgba8();
+    let edges = edge_map(&img, options)?;
+    convert::encode(&DynamicImage::ImageLuma8(edges), target, quality).map_err(EdgeError::Convert)
+}
+
+/// Decodes `input`, finds its Canny edges with the `low` and `high` thresholds, and
+/// traces them into [`contours`] simplified to within `epsilon` pixels.
+///
+/// # Errors
+///
+/// Returns an `EdgeError` if the thresholds or `epsilon` are invalid, or the input
+/// cannot be decoded.
+pub fn find_contours(
+    input: &[u8],
+    low: f64,
+    high: f64,
+    epsilon: f64,
+) -> Result<Vec<Contour>, EdgeError> {
+    if !epsilon.is_finite() || epsilon < 0.0 {
+        return Err(EdgeError::InvalidEpsilon(epsilon));
+    }
+    let img = image::load_from_memory(input)
+        .map_err(EdgeError::Decode)?
+        .into_rgba8();
+    let options = EdgeOptions {
+        method: EdgeMethod::Canny,
+        low,
+        high,
+    };
+    Ok(contours(&edge_map(&img, &options)?, epsilon))
+}
+
+/// Errors that can occur while detecting edges.
+#[derive(Debug)]
+pub enum EdgeError {
+    /// The edge detection method was not recognized.
+    UnknownMethod(String),
+    /// The Canny thresholds weren't finite with `0 <= low <= high`.
+    InvalidThresholds { low: f64, high: f64 },
+    /// The contour simplification distance was negative or not finite.
+    InvalidEpsilon(f64),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for EdgeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownMethod(name) => {
+                write!(
+                    f,
+                    "Unknown edge method \"{name}\" (expected sobel or canny)"
+                )
+            }
+            Self::InvalidThresholds { low, high } => write!(
+                f,
+                "Edge thresholds must satisfy 0 <= low <= high, got low {low} and high {high}"
+            ),
+            Self::InvalidEpsilon(epsilon) => {
+                write!(f, "Contour epsilon must be zero or more, got {epsilon}")
+            }
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for EdgeError {}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// A white square on black, inset `inset` pixels from each side.
+    fn square(size: u32, inset: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| {
+            let inside = (inset..size - inset).contains(&x) && (inset..size - inset).contains(&y);
+            if inside {
+                Rgba([255; 4])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn parses_methods() {
+        assert_eq!(EdgeMethod::from_name("").unwrap(), EdgeMethod::Canny);
+        assert_eq!(EdgeMethod::from_name(" SOBEL ").unwrap(), EdgeMethod::Sobel);
+        assert!(matches!(
+            EdgeMethod::from_name("prewitt"),
+            Err(EdgeError::UnknownMethod(_))
+        ));
+    }
+
+    #[test]
+    fn sobel_is_bright_on_edges_and_dark_on_flat_areas() {
+        let options = EdgeOptions {
+            method: EdgeMethod::Sobel,
+            ..EdgeOptions::default()
+        };
+        let map = edge_map(&square(20, 5), &options).unwrap();
+        assert_eq!(map.get_pixel(2, 2).0, [0]);
+        assert_eq!(map.get_pixel(10, 10).0, [0]);
+        assert_eq!(map.get_pixel(5, 10).0, [255]);
+        assert_eq!(map.get_pixel(4, 10).0, [255]);
+    }
+
+    #[test]
+    fn canny_edges_are_thin_and_binary() {
+        let map = edge_map(&square(30, 8), &EdgeOptions::default()).unwrap();
+        assert!(map.pixels().all(|&Luma([v])| v == 0 || v == 255));
+        // Across the left side, exactly one pixel is an edge.
+        let across: Vec<u32> = (0..15)
+            .filter(|&x| map.get_pixel(x, 15).0 == [255])
+            .collect();
+        assert_eq!(across.len(), 1, "{across:?}");
+        assert!(map.get_pixel(2, 2).0 == [0] && map.get_pixel(15, 15).0 == [0]);
+    }
+
+    #[test]
+    fn contours_trace_a_square_into_a_closed_polygon() {
+        let map = edge_map(&square(30, 8), &EdgeOptions::default()).unwrap();
+        let found = contours(&map, DEFAULT_EPSILON);
+        assert_eq!(found.len(), 1, "{found:?}");
+        let contour = &found[0];
+        assert!(contour.closed);
+        assert!((4..=9).contains(&contour.points.len()), "{contour:?}");
+    }
+
+    #[test]
+    fn simplify_keeps_only_corners() {
+        let path: Vec<(i64, i64)> = (0..5)
+            .map(|x| (x, 0))
+            .chain((1..5).map(|y| (4, y)))
+            .collect();
+        assert_eq!(simplify(&path, 0.5), [(0, 0), (4, 0), (4, 4)]);
+    }
+
+    #[test]
+    fn rejects_invalid_thresholds() {
+        let options = EdgeOptions {
+            low: 60.0,
+            high: 10.0,
+            ..EdgeOptions::default()
+        };
+        assert!(matches!(
+            edge_map(&square(4, 1), &options),
+            Err(EdgeError::InvalidThresholds { .. })
+        ));
+    }
+}
@@ -0,0 +1,1184 @@
+use std::fmt;
+
+use image::codecs::webp::WebPEncoder;
+use image::{imageops, DynamicImage, ExtendedColorType, GrayImage, ImageEncoder, Luma, RgbaImage};
+
+use crate::color::{self, ColorError};
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::{splitmix64, unit};
+
+/// Largest grain size accepted by [`Grain::new`], in pixels.
+pub const MAX_GRAIN_SIZE: u32 = 16;
+
+/// How far a channel moves at 100% grain intensity, in 8-bit levels either way.
+const GRAIN_AMPLITUDE: f64 = 64.0;
+
+/// Largest blur radius (Gaussian sigma) accepted by [`TiltShift::new`], in pixels.
+pub const MAX_BLUR_RADIUS: f64 = 100.0;
+
+/// A color at a position along a gradient, from 0.0 (shadows) to 1.0 (highlights).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorStop {
+    pub position: f64,
+    pub color: [u8; 4],
+}
+
+/// Parses gradient stops such as `"#000 0, #f80 40%, #fff 1"`.
+///
+/// Each comma-separated stop is a hex color (see [`color::parse_color`]) optionally
+/// followed by a position, either a fraction (`0.4`) or a percentage (`40%`). Either
+/// every stop has a position or none do; without positions the stops are spaced
+/// evenly.
+///
+/// # Errors
+///
+/// Returns an `EffectError` if a color or position is malformed, or if only some stops
+/// have positions. Positions are range-checked by [`GradientMap::new`].
+pub fn parse_stops(text: &str) -> Result<Vec<ColorStop>, EffectError> {
+    let invalid = || EffectError::InvalidStops(text.to_owned());
+    let mut colors = Vec::new();
+    let mut positions = Vec::new();
+    for stop in text.split(',') {
+        let mut parts = stop.split_whitespace();
+        let color =
+            color::parse_color(parts.next().unwrap_or_default()).map_err(EffectError::Color)?;
+        colors.push(color);
+        if let Some(position) = parts.next() {
+            positions.push(parse_position(position).ok_or_else(invalid)?);
+        }
+        if parts.next().is_some() {
+            return Err(invalid());
+        }
+    }
+
+    if positions.is_empty() {
+        let last = colors.len().saturating_sub(1).max(1);
+        let last = f64::from(u32::try_from(last).map_err(|_| invalid())?);
+        return (0..)
+            .zip(colors)
+            .map(|(index, color)| {
+                Ok(ColorStop {
+                    position: f64::from(index) / last,
+                    color,
+                })
+            })
+            .collect();
+    }
+    if positions.len() != colors.len() {
+        return Err(invalid());
+    }
+    Ok(colors
+        .into_iter()
+        .zip(positions)
+        .map(|(color, position)| ColorStop { position, color })
+        .collect())
+}
+
+fn parse_position(text: &str) -> Option<f64> {
+    match text.strip_suffix('%') {
+        Some(percent) => percent.parse::<f64>().ok().map(|p| p / 100.0),
+        None => text.parse().ok(),
+    }
+}
+
+/// Maps each pixel's luminance onto a color ramp.
+///
+/// The ramp is precomputed into a 256-entry lookup table, so applying it is a single
+/// pass over the pixels. The ramp's alpha is multiplied into the pixel's own alpha.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GradientMap {
+    lut: Vec<[u8; 4]>,
+}
+
+impl GradientMap {
+    /// Builds a gradient map from stops ordered by position.
+    ///
+    /// Colors are interpolated linearly between neighbouring stops. Two stops at the
+    /// same position make a hard edge. Luminance before the first stop or after the
+    /// last one takes that stop's color.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EffectError::TooFewStops` for fewer than two stops, and
+    /// `EffectError::StopOutOfRange` / `EffectError::StopsOutOfOrder` if a position is
+    /// outside `0.0..=1.0` or positions decrease.
+    pub fn new(stops: &[ColorStop]) -> Result<Self, EffectError> {
+        if stops.len() < 2 {
+            return Err(EffectError::TooFewStops);
+        }
+        if let Some(stop) = stops
+            .iter()
+            .find(|stop| !(0.0..=1.0).contains(&stop.position))
+        {
+            return Err(EffectError::StopOutOfRange(stop.position));
+        }
+        if stops.windows(2).any(|pair| match pair {
+            [a, b] => b.position < a.position,
+            _ => false,
+        }) {
+            return Err(EffectError::StopsOutOfOrder);
+        }
+
+        let lut = (0..=u8::MAX)
+            .map(|level| color_at(stops, f64::from(level) / 255.0))
+            .collect();
+        Ok(Self { lut })
+    }
+
+    /// A two-color ramp from `shadow` (black) to `highlight` (white).
+    pub fn duotone(shadow: [u8; 4], highlight: [u8; 4]) -> Self {
+        let lut = (0..=u8::MAX)
+            .map(|level| color::lerp(shadow, highlight, f64::from(level) / 255.0))
+            .collect();
+        Self { lut }
+    }
+
+    /// Replaces every pixel with the ramp color for its luminance.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        for pixel in img.pixels_mut() {
+            let [r, g, b, a] = self
+                .lut
+                .get(usize::from(color::luminance(pixel.0)))
+                .copied()
+                .unwrap_or(pixel.0);
+            let alpha = (u16::from(a) * u16::from(pixel.0[3]) + 127) / 255;
+            pixel.0 = [r, g, b, u8::try_from(alpha).unwrap_or(u8::MAX)];
+        }
+    }
+}
+
+/// The interpolated color at `t` along validated, ordered stops.
+fn color_at(stops: &[ColorStop], t: f64) -> [u8; 4] {
+    let after = stops
+        .iter()
+        .position(|stop| stop.position > t)
+        .unwrap_or(stops.len());
+    match (
+        after.checked_sub(1).and_then(|i| stops.get(i)),
+        stops.get(after),
+    ) {
+        (Some(from), Some(to)) => {
+            let span = to.position - from.position;
+            color::lerp(from.color, to.color, (t - from.position) / span)
+        }
+        (Some(only), None) | (None, Some(only)) => only.color,
+        (None, None) => [0; 4],
+    }
+}
+
+/// Decodes `input`, applies the gradient map, and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns an `EffectError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn apply_gradient_map(
+    input: &[u8],
+    map: &GradientMap,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, EffectError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(EffectError::Decode)?
+        .into_rgba8();
+    map.apply(&mut img);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(EffectError::Convert)
+}
+
+/// Settings for turning a background color transparent.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChromaKey {
+    color: [u8; 4],
+    tolerance: f64,
+    softness: f64,
+}
+
+impl ChromaKey {
+    /// Keys out `color` (its alpha is ignored).
+    ///
+    /// `tolerance` and `softness` are percentages (0-100) of the largest RGB distance.
+    /// Pixels within `tolerance` of the key color become fully transparent; the next
+    /// `softness` of distance fades back to opaque, which feathers the edges.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EffectError::InvalidPercentage` if either value is outside 0-100.
+    pub fn new(color: [u8; 4], tolerance: f64, softness: f64) -> Result<Self, EffectError> {
+        check_percentage(tolerance)?;
+        check_percentage(softness)?;
+        Ok(Self {
+            color,
+            tolerance: tolerance / 100.0 * color::MAX_DISTANCE,
+            softness: softness / 100.0 * color::MAX_DISTANCE,
+        })
+    }
+
+    /// Makes pixels close to the key color transparent.
+    ///
+    /// Partially keyed pixels are treated as a blend of the foreground and the key
+    /// color, and the key color is subtracted back out so edges don't keep a fringe of
+    /// the background.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        let key = self.color.map(f64::from);
+        for pixel in img.pixels_mut() {
+            let distance = color::distance(pixel.0, self.color);
+            let coverage = if distance <= self.tolerance {
+                0.0
+            } else if distance >= self.tolerance + self.softness {
+                continue;
+            } else {
+                (distance - self.tolerance) / self.softness
+            };
+
+            for (value, key) in pixel.0.iter_mut().zip(key).take(3) {
+                let observed = f64::from(*value);
+                *value = if coverage > 0.0 {
+                    color::to_u8((observed - (1.0 - coverage) * key) / coverage)
+                } else {
+                    0
+                };
+            }
+            pixel.0[3] = color::to_u8(f64::from(pixel.0[3]) * coverage);
+        }
+    }
+}
+
+/// Recolors pixels close to one color, e.g. to make color variants of a product.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ColorReplace {
+    from: [u8; 4],
+    to: [u8; 4],
+    tolerance: f64,
+    preserve_luminance: bool,
+}
+
+impl ColorReplace {
+    /// Replaces pixels within `tolerance` (a percentage, 0-100, of the largest RGB
+    /// distance) of `from` with `to`. Alpha of both colors is ignored; pixels keep
+    /// their own alpha.
+    ///
+    /// With `preserve_luminance`, each replaced pixel is offset by how much lighter or
+    /// darker it is than `from`, so shading and highlights carry over to the new color.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EffectError::InvalidPercentage` if `tolerance` is outside 0-100.
+    pub fn new(
+        from: [u8; 4],
+        to: [u8; 4],
+        tolerance: f64,
+        preserve_luminance: bool,
+    ) -> Result<Self, EffectError> {
+        check_percentage(tolerance)?;
+        Ok(Self {
+            from,
+            to,
+            tolerance: tolerance / 100.0 * color::MAX_DISTANCE,
+            preserve_luminance,
+        })
+    }
+
+    /// Recolors the matching pixels.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        let from_luma = i16::from(color::luminance(self.from));
+        for pixel in img.pixels_mut() {
+            if color::distance(pixel.0, self.from) > self.tolerance {
+                continue;
+            }
+            let shift = if self.preserve_luminance {
+                i16::from(color::luminance(pixel.0)) - from_luma
+            } else {
+                0
+            };
+            for (value, target) in pixel.0.iter_mut().zip(self.to).take(3) {
+                let shifted = (i16::from(target) + shift).clamp(0, i16::from(u8::MAX));
+                *value = u8::try_from(shifted).unwrap_or(u8::MAX);
+            }
+        }
+    }
+}
+
+/// Decodes `input`, applies the color replacement, and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns an `EffectError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn replace_color(
+    input: &[u8],
+    replace: &ColorReplace,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, EffectError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(EffectError::Decode)?
+        .into_rgba8();
+    replace.apply(&mut img);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(EffectError::Convert)
+}
+
+/// A form of color blindness to simulate, so designs and charts can be checked for
+/// colors that some viewers can't tell apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorVision {
+    /// No working red cones: reds look dark and merge with greens.
+    Protanopia,
+    /// No working green cones, the most common form: reds and greens merge.
+    Deuteranopia,
+    /// No working blue cones: blues merge with greens, and yellows with pinks.
+    Tritanopia,
+}
+
+impl ColorVision {
+    /// Every form, in declaration order.
+    pub const ALL: [Self; 3] = [Self::Protanopia, Self::Deuteranopia, Self::Tritanopia];
+
+    /// Parses a form of color blindness.
+    ///
+    /// Accepts `"protanopia"` (or `"protan"`), `"deuteranopia"` (or `"deutan"`) and
+    /// `"tritanopia"` (or `"tritan"`).
+    ///
+    /// Returns an error if the string is not a recognized form.
+    pub fn from_name(name: &str) -> Result<Self, EffectError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "protanopia" | "protan" => Ok(Self::Protanopia),
+            "deuteranopia" | "deutan" => Ok(Self::Deuteranopia),
+            "tritanopia" | "tritan" => Ok(Self::Tritanopia),
+            _ => Err(EffectError::UnknownColorVision(name.to_owned())),
+        }
+    }
+
+    /// The simulation matrix for linear RGB, from Machado, Oliveira and Fernandes
+    /// (2009) at full severity.
+    fn matrix(self) -> [[f64; 3]; 3] {
+        match self {
+            Self::Protanopia => [
+                [0.152_286, 1.052_583, -0.204_868],
+                [0.114_503, 0.786_281, 0.099_216],
+                [-0.003_882, -0.048_116, 1.051_998],
+            ],
+            Self::Deuteranopia => [
+                [0.367_322, 0.860_646, -0.227_968],
+                [0.280_085, 0.672_501, 0.047_413],
+                [-0.011_820, 0.042_940, 0.968_881],
+            ],
+            Self::Tritanopia => [
+                [1.255_528, -0.076_749, -0.178_779],
+                [-0.078_411, 0.930_809, 0.147_602],
+                [0.004_733, 0.691_367, 0.303_900],
+            ],
+        }
+    }
+
+    /// Replaces every pixel with how it appears with this form of color blindness.
+    /// Alpha is kept.
+    pub fn simulate(self, img: &mut RgbaImage) {
+        let linear: Vec<f64> = (0..=u8::MAX).map(color::srgb_to_linear).collect();
+        let matrix = self.matrix();
+        for pixel in img.pixels_mut() {
+            let [r, g, b, a] = pixel.0;
+            let rgb = [r, g, b].map(|v| linear.get(usize::from(v)).copied().unwrap_or(0.0));
+            let [r, g, b] = matrix.map(|row| {
+                let mixed = row.iter().zip(rgb).map(|(m, v)| m * v).sum::<f64>();
+                color::linear_to_srgb(mixed)
+            });
+            pixel.0 = [r, g, b, a];
+        }
+    }
+}
+
+/// Decodes `input`, simulates `vision` with [`ColorVision::simulate`], and encodes the
+/// result as `target`.
+///
+/// # Errors
+///
+/// Returns an `EffectError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn simulate_color_vision(
+    input: &[u8],
+    vision: ColorVision,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, EffectError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(EffectError::Decode)?
+        .into_rgba8();
+    vision.simulate(&mut img);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(EffectError::Convert)
+}
+
+/// Film grain: seeded noise added to every pixel, for a filmic look or to break up the
+/// banding heavy quantization leaves in smooth gradients.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Grain {
+    amplitude: f64,
+    size: u32,
+    monochrome: bool,
+    seed: u64,
+}
+
+impl Grain {
+    /// Grain of `intensity` (a percentage, 0-100; at 100 channels move by up to 64
+    /// levels) with specks about `size` pixels across (1 to [`MAX_GRAIN_SIZE`]).
+    /// Monochrome grain moves the three channels together, like black-and-white film;
+    /// otherwise each channel gets its own noise. The same `seed` always gives the same
+    /// grain, so re-exports match.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EffectError::InvalidPercentage` if `intensity` is outside 0-100, or
+    /// `EffectError::InvalidGrainSize` if `size` is 0 or above [`MAX_GRAIN_SIZE`].
+    pub fn new(
+        intensity: f64,
+        size: u32,
+        monochrome: bool,
+        seed: u64,
+    ) -> Result<Self, EffectError> {
+        check_percentage(intensity)?;
+        if !(1..=MAX_GRAIN_SIZE).contains(&size) {
+            return Err(EffectError::InvalidGrainSize(size));
+        }
+        Ok(Self {
+            amplitude: intensity / 100.0 * GRAIN_AMPLITUDE,
+            size,
+            monochrome,
+            seed,
+        })
+    }
+
+    /// Adds the grain to the color channels; alpha is kept.
+    pub fn apply(&self, img: &mut RgbaImage) {
+        for (x, y, pixel) in img.enumerate_pixels_mut() {
+            for (channel, value) in (0..3).zip(pixel.0.iter_mut()) {
+                let channel = if self.monochrome { 0 } else { channel };
+                let noise = self.noise(x, y, channel);
+                *value = color::to_u8(f64::from(*value) + noise * self.amplitude);
+            }
+        }
+    }
+
+    /// Noise in `-1.0..=1.0` at (`x`, `y`): random values on a lattice `size` pixels
+    /// apart, blended bilinearly so larger grain has soft edges.
+    fn noise(&self, x: u32, y: u32, channel: u64) -> f64 {
+        let lattice = |gx: u32, gy: u32| {
+            let position = u64::from(gx) | (u64::from(gy) << 32);
+            let hash = splitmix64(self.seed ^ splitmix64(position).wrapping_add(channel));
+            unit(hash) * 2.0 - 1.0
+        };
+        let (gx, gy) = (x / self.size, y / self.size);
+        let size = f64::from(self.size);
+        let (tx, ty) = (
+            f64::from(x % self.size) / size,
+            f64::from(y % self.size) / size,
+        );
+        let top = lattice(gx, gy) * (1.0 - tx) + lattice(gx + 1, gy) * tx;
+        let bottom = lattice(gx, gy + 1) * (1.0 - tx) + lattice(gx + 1, gy + 1) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+/// Decodes `input`, adds `grain` with [`Grain::apply`], and encodes the result as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns an `EffectError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn add_grain(
+    input: &[u8],
+    grain: &Grain,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, EffectError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(EffectError::Decode)?
+        .into_rgba8();
+    grain.apply(&mut img);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(EffectError::Convert)
+}
+
+/// The shape of the area a [`TiltShift`] keeps sharp.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum FocusShape {
+    /// A horizontal band across the image, the classic tilt-shift miniature look.
+    #[default]
+    Linear,
+    /// A circle around a point, like a vignette of blur.
+    Radial,
+}
+
+impl FocusShape {
+    /// Parses a focus shape: `"linear"` (or `"band"`) or `"radial"` (or `"circle"`).
+    ///
+    /// Returns an error if the string is not a recognized shape.
+    pub fn from_name(name: &str) -> Result<Self, EffectError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "linear" | "band" => Ok(Self::Linear),
+            "radial" | "circle" => Ok(Self::Radial),
+            _ => Err(EffectError::UnknownFocusShape(name.to_owned())),
+        }
+    }
+}
+
+/// Tilt-shift: blur that grows away from a sharp focus area, which makes full-size
+/// scenes look like miniatures.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TiltShift {
+    shape: FocusShape,
+    center: [f64; 2],
+    band: f64,
+    transition: f64,
+    radius: f64,
+}
+
+impl TiltShift {
+    /// Keeps the area around `center` (x and y as percentages of the width and height)
+    /// sharp and blurs the rest with a Gaussian of `radius` pixels (up to
+    /// [`MAX_BLUR_RADIUS`]).
+    ///
+    /// `band` is the size of the sharp area: the height of the band for
+    /// [`FocusShape::Linear`] as a percentage of the image height (only the center's y
+    /// matters), or the diameter of the circle for [`FocusShape::Radial`] as a
+    /// percentage of the shorter side. Over the next `transition` (same units) the
+    /// blur fades in to full strength.
+    ///
+    /// # Errors
+    ///
+    /// Returns `EffectError::InvalidPercentage` if the center, `band` or `transition`
+    /// is outside 0-100, or `EffectError::InvalidBlurRadius` if `radius` is not
+    /// positive or above [`MAX_BLUR_RADIUS`].
+    pub fn new(
+        shape: FocusShape,
+        center: [f64; 2],
+        band: f64,
+        transition: f64,
+        radius: f64,
+    ) -> Result<Self, EffectError> {
+        for value in [center[0], center[1], band, transition] {
+            check_percentage(value)?;
+        }
+        if !(radius > 0.0 && radius <= MAX_BLUR_RADIUS) {
+            return Err(EffectError::InvalidBlurRadius(radius));
+        }
+        Ok(Self {
+            shape,
+            center: center.map(|value| value / 100.0),
+            band: band / 100.0,
+            transition: transition / 100.0,
+            radius,
+        })
+    }
+
+    /// How much of the blur each pixel of a `width`×`height` image gets: 0 in the sharp
+    /// area, rising smoothly to 255 where the blur is at full strength.
+    pub fn focus_mask(&self, width: u32, height: u32) -> GrayImage {
+        let (w, h) = (f64::from(width), f64::from(height));
+        let (cx, cy) = (self.center[0] * w, self.center[1] * h);
+        let scale = match self.shape {
+            FocusShape::Linear => h,
+            FocusShape::Radial => w.min(h),
+        };
+        GrayImage::from_fn(width, height, |x, y| {
+            let dy = f64::from(y) + 0.5 - cy;
+            let offset = match self.shape {
+                FocusShape::Linear => dy.abs(),
+                FocusShape::Radial => (f64::from(x) + 0.5 - cx).hypot(dy),
+            };
+            let beyond = offset / scale - self.band / 2.0;
+            let weight = if beyond <= 0.0 {
+                0.0
+            } else if beyond >= self.transition {
+                1.0
+            } else {
+                let t = beyond / self.transition;
+                t * t * (3.0 - 2.0 * t)
+            };
+            Luma([color::to_u8(weight * 255.0)])
+        })
+    }
+
+    /// Blurs the image outside the focus area, blending the blurred and original
+    /// pixels by [`TiltShift::focus_mask`].
+    pub fn apply(&self, img: &mut RgbaImage) {
+        let mask = self.focus_mask(img.width(), img.height());
+        let blurred = imageops::blur(img, color::to_f32(self.radius));
+        for ((pixel, blurred), weight) in img.pixels_mut().zip(blurred.pixels()).zip(mask.pixels())
+        {
+            if weight.0[0] == 0 {
+                continue;
+            }
+            let weight = f64::from(weight.0[0]) / 255.0;
+            for (value, blurred) in pixel.0.iter_mut().zip(blurred.0) {
+                let (sharp, blurred) = (f64::from(*value), f64::from(blurred));
+                *value = color::to_u8(sharp + (blurred - sharp) * weight);
+            }
+        }
+    }
+}
+
+/// Decodes `input`, applies `tilt_shift` with [`TiltShift::apply`], and encodes the
+/// result as `target`.
+///
+/// # Errors
+///
+/// Returns an `EffectError` if the input cannot be decoded or the output cannot be
+/// encoded.
+pub fn tilt_shift(
+    input: &[u8],
+    tilt_shift: &TiltShift,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, EffectError> {
+    let mut img = image::load_from_memory(input)
+        .map_err(EffectError::Decode)?
+        .into_rgba8();
+    tilt_shift.apply(&mut img);
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(EffectError::Convert)
+}
+
+fn check_percentage(value: f64) -> Result<(), EffectError> {
+    if (0.0..=100.0).contains(&value) {
+        Ok(())
+    } else {
+        Err(EffectError::InvalidPercentage(value))
+    }
+}
+
+/// Decodes `input`, keys out the background color, and encodes the result as PNG or
+/// lossless WebP.
+///
+/// # Errors
+///
+/// Returns an `EffectError` if the target isn't PNG or WebP, the input cannot be
+/// decoded, or the output cannot be encoded.
+pub fn chroma_key(
+    input: &[u8],
+    key: &ChromaKey,
+    target: ImageFormat,
+) -> Result<Vec<u8>, EffectError> {
+    if !matches!(target, ImageFormat::Png | ImageFormat::WebP) {
+        return Err(EffectError::UnsupportedTarget(target));
+    }
+
+    let mut img = image::load_from_memory(input)
+        .map_err(EffectError::Decode)?
+        .into_rgba8();
+    key.apply(&mut img);
+    encode_with_alpha(img, target)
+}
+
+/// Encodes an RGBA result as PNG, or as lossless WebP (which [`convert::encode`] can't
+/// write).
+fn encode_with_alpha(img: RgbaImage, target: ImageFormat) -> Result<Vec<u8>, EffectError> {
+    if target != ImageFormat::WebP {
+        return convert::encode(&DynamicImage::ImageRgba8(img), target, None)
+            .map_err(EffectError::Convert);
+    }
+
+    let mut encoded = Vec::new();
+    WebPEncoder::new_lossless(&mut encoded)
+        .write_image(
+            img.as_raw(),
+            img.width(),
+            img.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(EffectError::Encode)?;
+    Ok(encoded)
+}
+
+/// Errors that can occur while applying color effects.
+#[derive(Debug)]
+pub enum EffectError {
+    /// A color could not be parsed.
+    Color(ColorError),
+    /// The gradient stop list was malformed.
+    InvalidStops(String),
+    /// A gradient needs at least two stops.
+    TooFewStops,
+    /// A stop position was outside 0.0..=1.0.
+    StopOutOfRange(f64),
+    /// Stop positions must not decrease.
+    StopsOutOfOrder,
+    /// A tolerance, softness or intensity was outside 0-100.
+    InvalidPercentage(f64),
+    /// The grain size was 0 or above [`MAX_GRAIN_SIZE`].
+    InvalidGrainSize(u32),
+    /// The form of color blindness was not recognized.
+    UnknownColorVision(String),
+    /// The tilt-shift focus shape was not recognized.
+    UnknownFocusShape(String),
+    /// The blur radius was not positive or above [`MAX_BLUR_RADIUS`].
+    InvalidBlurRadius(f64),
+    /// The operation's output needs a format it can't be written in.
+    UnsupportedTarget(ImageFormat),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+    /// Failed to encode WebP output.
+    Encode(image::ImageError),
+}
+
+impl fmt::Display for EffectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Color(e) => write!(f, "{e}"),
+            Self::InvalidStops(text) => write!(
+                f,
+                "Invalid gradient stops \"{text}\" (expected \"#color [position], ...\")"
+            ),
+            Self::TooFewStops => write!(f, "A gradient needs at least two color stops"),
+            Self::StopOutOfRange(position) => write!(
+                f,
+                "Gradient stop position {position} is outside 0 to 1 (0% to 100%)"
+            ),
+            Self::StopsOutOfOrder => write!(f, "Gradient stop positions must be in order"),
+            Self::InvalidPercentage(value) => {
+                write!(f, "Percentage must be between 0 and 100, got {value}")
+            }
+            Self::InvalidGrainSize(size) => write!(
+                f,
+                "Grain size must be between 1 and {MAX_GRAIN_SIZE} pixels, got {size}"
+            ),
+            Self::UnknownColorVision(name) => write!(
+                f,
+                "Unknown color vision \"{name}\" (expected protanopia, deuteranopia or tritanopia)"
+            ),
+            Self::UnknownFocusShape(name) => {
+                write!(
+                    f,
+                    "Unknown focus shape \"{name}\" (expected linear or radial)"
+                )
+            }
+            Self::InvalidBlurRadius(radius) => write!(
+                f,
+                "Blur radius must be above 0 and at most {MAX_BLUR_RADIUS} pixels, got {radius}"
+            ),
+            Self::UnsupportedTarget(format) => {
+                write!(f, "Output must be PNG or WebP, not {}", format.as_str())
+            }
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+            Self::Encode(e) => write!(f, "Failed to encode image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for EffectError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::Rgba;
+
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+    const ORANGE: [u8; 4] = [255, 128, 0, 255];
+
+    fn gray_ramp() -> RgbaImage {
+        RgbaImage::from_fn(256, 1, |x, _| {
+            let v = u8::try_from(x).unwrap();
+            Rgba([v, v, v, 255])
+        })
+    }
+
+    // ===== Stop Parsing Tests =====
+
+    #[test]
+    fn parse_evenly_spaced_stops() {
+        let stops = parse_stops("#000, #ff8000, #fff").unwrap();
+        let positions: Vec<f64> = stops.iter().map(|s| s.position).collect();
+        assert_eq!(positions, vec![0.0, 0.5, 1.0]);
+        assert_eq!(stops[1].color, ORANGE);
+    }
+
+    #[test]
+    fn parse_explicit_positions() {
+        let stops = parse_stops("#000 0, #ff8000 25%, #fff 1").unwrap();
+        assert_eq!(stops[1].position, 0.25);
+    }
+
+    #[test]
+    fn parse_rejects_malformed_stops() {
+        assert!(matches!(
+            parse_stops("#000 0, #fff"),
+            Err(EffectError::InvalidStops(_))
+        ));
+        assert!(matches!(
+            parse_stops("#000 zero, #fff 1"),
+            Err(EffectError::InvalidStops(_))
+        ));
+        assert!(matches!(
+            parse_stops("#000 0 1, #fff 1"),
+            Err(EffectError::InvalidStops(_))
+        ));
+        assert!(matches!(
+            parse_stops("black, white"),
+            Err(EffectError::Color(_))
+        ));
+    }
+
+    // ===== Gradient Map Tests =====
+
+    #[test]
+    fn gradient_map_validates_stops() {
+        let stop = |position| ColorStop {
+            position,
+            color: BLACK,
+        };
+        assert!(matches!(
+            GradientMap::new(&[stop(0.0)]),
+            Err(EffectError::TooFewStops)
+        ));
+        assert!(matches!(
+            GradientMap::new(&[stop(0.0), stop(1.5)]),
+            Err(EffectError::StopOutOfRange(_))
+        ));
+        assert!(matches!(
+            GradientMap::new(&[stop(0.6), stop(0.4)]),
+            Err(EffectError::StopsOutOfOrder)
+        ));
+    }
+
+    #[test]
+    fn gradient_map_interpolates_between_stops() {
+        let map = GradientMap::new(&parse_stops("#000, #ff8000, #fff").unwrap()).unwrap();
+        let mut img = gray_ramp();
+        map.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, BLACK);
+        assert_eq!(img.get_pixel(255, 0).0, WHITE);
+        assert_eq!(img.get_pixel(128, 0).0, [255, 128, 1, 255]);
+        assert_eq!(img.get_pixel(64, 0).0, [128, 64, 0, 255]);
+    }
+
+    #[test]
+    fn gradient_map_hard_edge_and_clamped_ends() {
+        let stops = parse_stops("#000 20%, #000 50%, #fff 50%, #fff 80%").unwrap();
+        let map = GradientMap::new(&stops).unwrap();
+        let mut img = gray_ramp();
+        map.apply(&mut img);
+        assert_eq!(img.get_pixel(10, 0).0, BLACK);
+        assert_eq!(img.get_pixel(127, 0).0, BLACK);
+        assert_eq!(img.get_pixel(128, 0).0, WHITE);
+        assert_eq!(img.get_pixel(250, 0).0, WHITE);
+    }
+
+    #[test]
+    fn duotone_maps_luminance_and_keeps_alpha() {
+        let map = GradientMap::duotone([0, 0, 128, 255], [255, 255, 0, 255]);
+        let mut img =
+            RgbaImage::from_vec(3, 1, vec![0, 0, 0, 255, 255, 255, 255, 100, 255, 0, 0, 255])
+                .unwrap();
+        map.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 128, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [255, 255, 0, 100]);
+        // Red has luminance 54.
+        assert_eq!(
+            img.get_pixel(2, 0).0,
+            color::lerp([0, 0, 128, 255], [255, 255, 0, 255], 54.0 / 255.0)
+        );
+    }
+
+    #[test]
+    fn translucent_stops_scale_alpha() {
+        let map = GradientMap::new(&parse_stops("#00000000, #ffffff80").unwrap()).unwrap();
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([255, 255, 255, 128]));
+        map.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255, 64]);
+    }
+
+    #[test]
+    fn apply_gradient_map_encoded() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(gray_ramp())
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let map = GradientMap::duotone(BLACK, ORANGE);
+        let output = apply_gradient_map(&png, &map, ImageFormat::Png, None).unwrap();
+        let output = image::load_from_memory(&output).unwrap().into_rgba8();
+        assert_eq!(output.get_pixel(255, 0).0, ORANGE);
+
+        assert!(matches!(
+            apply_gradient_map(&[1, 2, 3], &map, ImageFormat::Png, None),
+            Err(EffectError::Decode(_))
+        ));
+    }
+
+    // ===== Chroma Key Tests =====
+
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+
+    #[test]
+    fn chroma_key_validates_percentages() {
+        assert!(matches!(
+            ChromaKey::new(GREEN, 101.0, 0.0),
+            Err(EffectError::InvalidPercentage(_))
+        ));
+        assert!(matches!(
+            ChromaKey::new(GREEN, 10.0, -1.0),
+            Err(EffectError::InvalidPercentage(_))
+        ));
+    }
+
+    #[test]
+    fn chroma_key_removes_background_and_keeps_subject() {
+        let key = ChromaKey::new(GREEN, 10.0, 0.0).unwrap();
+        let mut img = RgbaImage::from_vec(
+            3,
+            1,
+            vec![0, 255, 0, 255, 20, 240, 10, 255, 200, 50, 50, 255],
+        )
+        .unwrap();
+        key.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0[3], 0);
+        assert_eq!(img.get_pixel(1, 0).0[3], 0);
+        assert_eq!(img.get_pixel(2, 0).0, [200, 50, 50, 255]);
+    }
+
+    #[test]
+    fn chroma_key_softness_feathers_and_despills() {
+        let key = ChromaKey::new(GREEN, 0.0, 100.0).unwrap();
+        // Half red subject, half green background.
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([128, 128, 0, 255]));
+        key.apply(&mut img);
+        let [r, g, b, a] = img.get_pixel(0, 0).0;
+        assert!((95..=110).contains(&a), "alpha {a}");
+        assert!(r > 200 && g < 30 && b == 0, "{:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn chroma_key_scales_existing_alpha() {
+        let key = ChromaKey::new(GREEN, 0.0, 100.0).unwrap();
+        let mut img = RgbaImage::from_pixel(1, 1, Rgba([255, 0, 255, 100]));
+        key.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0[3], 100);
+    }
+
+    #[test]
+    fn chroma_key_encodes_png_and_webp() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_fn(4, 4, |x, _| {
+            Rgba(if x < 2 { GREEN } else { ORANGE })
+        }))
+        .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+        .unwrap();
+        let key = ChromaKey::new(GREEN, 5.0, 5.0).unwrap();
+
+        for target in [ImageFormat::Png, ImageFormat::WebP] {
+            let output = chroma_key(&png, &key, target).unwrap();
+            assert_eq!(ImageFormat::detect_from_bytes(&output).unwrap(), target);
+            let output = image::load_from_memory(&output).unwrap().into_rgba8();
+            assert_eq!(output.get_pixel(0, 0).0[3], 0);
+            assert_eq!(output.get_pixel(3, 0).0, ORANGE);
+        }
+
+        assert!(matches!(
+            chroma_key(&png, &key, ImageFormat::Jpeg),
+            Err(EffectError::UnsupportedTarget(ImageFormat::Jpeg))
+        ));
+    }
+
+    // ===== Color Replacement Tests =====
+
+    const RED: [u8; 4] = [200, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 200, 255];
+
+    /// Red shaded from dark to light, next to an unrelated gray.
+    fn shaded_red() -> RgbaImage {
+        RgbaImage::from_vec(
+            4,
+            1,
+            vec![
+                150, 0, 0, 255, 200, 0, 0, 128, 240, 30, 30, 255, 128, 128, 128, 255,
+            ],
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn replace_color_flat() {
+        let replace = ColorReplace::new(RED, BLUE, 20.0, false).unwrap();
+        let mut img = shaded_red();
+        replace.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, BLUE);
+        assert_eq!(img.get_pixel(1, 0).0, [0, 0, 200, 128], "alpha is kept");
+        assert_eq!(img.get_pixel(2, 0).0, BLUE);
+        assert_eq!(img.get_pixel(3, 0).0, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn replace_color_preserving_luminance_keeps_shading() {
+        let replace = ColorReplace::new(RED, BLUE, 20.0, true).unwrap();
+        let mut img = shaded_red();
+        replace.apply(&mut img);
+        let dark = img.get_pixel(0, 0).0;
+        let light = img.get_pixel(2, 0).0;
+        assert_eq!(img.get_pixel(1, 0).0, [0, 0, 200, 128]);
+        assert!(dark[2] < 200 && light[2] > 200, "{dark:?} {light:?}");
+        assert!(color::luminance(dark) < color::luminance(light));
+        assert_eq!(img.get_pixel(3, 0).0, [128, 128, 128, 255]);
+    }
+
+    #[test]
+    fn replace_color_zero_tolerance_is_exact() {
+        let replace = ColorReplace::new(RED, BLUE, 0.0, false).unwrap();
+        let mut img = shaded_red();
+        replace.apply(&mut img);
+        assert_eq!(img.get_pixel(0, 0).0, [150, 0, 0, 255]);
+        assert_eq!(img.get_pixel(1, 0).0, [0, 0, 200, 128]);
+        assert!(matches!(
+            ColorReplace::new(RED, BLUE, 150.0, false),
+            Err(EffectError::InvalidPercentage(_))
+        ));
+    }
+
+    #[test]
+    fn color_vision_keeps_grays_and_merges_confused_colors() {
+        let simulate = |vision: ColorVision, colors: &[[u8; 4]]| {
+            let mut img =
+                RgbaImage::from_fn(3, 1, |x, _| Rgba(colors[usize::try_from(x).unwrap()]));
+            vision.simulate(&mut img);
+            img.pixels().map(|p| p.0).collect::<Vec<_>>()
+        };
+        let red = [200, 40, 40, 255];
+        let green = [60, 160, 40, 128];
+        let gray = [90, 90, 90, 255];
+        let before = color::distance(red, green);
+        for vision in ColorVision::ALL {
+            let out = simulate(vision, &[red, green, gray]);
+            assert!(
+                out[2].iter().take(3).all(|&v| v.abs_diff(90) <= 1),
+                "{out:?}"
+            );
+            assert_eq!(out[1][3], 128);
+            let after = color::distance(out[0], out[1]);
+            if vision == ColorVision::Tritanopia {
+                assert!(after > before * 0.6, "{vision:?}: {before} -> {after}");
+            } else {
+                assert!(after < before * 0.6, "{vision:?}: {before} -> {after}");
+            }
+        }
+        assert_eq!(
+            ColorVision::from_name(" Deutan ").unwrap(),
+            ColorVision::Deuteranopia
+        );
+        assert!(matches!(
+            ColorVision::from_name("colorblind"),
+            Err(EffectError::UnknownColorVision(_))
+        ));
+    }
+
+    // ===== Grain Tests =====
+
+    #[test]
+    fn grain_is_seeded_and_scaled_by_intensity() {
+        let gray = RgbaImage::from_pixel(32, 32, Rgba([128, 128, 128, 200]));
+        let grained = |intensity, size, monochrome, seed| {
+            let mut img = gray.clone();
+            Grain::new(intensity, size, monochrome, seed)
+                .unwrap()
+                .apply(&mut img);
+            img
+        };
+        let spread = |img: &RgbaImage| {
+            let total: u32 = img.pixels().map(|p| u32::from(p.0[0].abs_diff(128))).sum();
+            f64::from(total) / f64::from(img.width() * img.height())
+        };
+
+        let light = grained(10.0, 1, true, 7);
+        let heavy = grained(60.0, 1, true, 7);
+        assert_eq!(light, grained(10.0, 1, true, 7));
+        assert_ne!(light, grained(10.0, 1, true, 8));
+        assert!(spread(&heavy) > spread(&light) * 3.0);
+        assert!(light.pixels().all(|p| p.0[3] == 200));
+        assert_eq!(grained(0.0, 1, false, 7), gray);
+
+        // Monochrome grain keeps pixels gray; chroma grain doesn't.
+        assert!(heavy.pixels().all(|p| p.0[0] == p.0[1] && p.0[1] == p.0[2]));
+        let chroma = grained(60.0, 1, false, 7);
+        assert!(chroma.pixels().any(|p| p.0[0] != p.0[1]));
+
+        // Larger grain changes less between neighbouring pixels.
+        let roughness = |img: &RgbaImage| {
+            (1..img.width())
+                .map(|x| u32::from(img.get_pixel(x, 5).0[0].abs_diff(img.get_pixel(x - 1, 5).0[0])))
+                .sum::<u32>()
+        };
+        assert!(roughness(&grained(60.0, 8, true, 7)) * 3 < roughness(&heavy));
+
+        assert!(matches!(
+            Grain::new(50.0, 0, true, 0),
+            Err(EffectError::InvalidGrainSize(0))
+        ));
+        assert!(matches!(
+            Grain::new(120.0, 2, true, 0),
+            Err(EffectError::InvalidPercentage(_))
+        ));
+    }
+
+    // ===== Tilt-Shift Tests =====
+
+    #[test]
+    fn tilt_shift_mask_is_sharp_in_the_band_and_blurred_beyond() {
+        let linear = TiltShift::new(FocusShape::Linear, [50.0, 50.0], 20.0, 20.0, 4.0).unwrap();
+        let mask = linear.focus_mask(10, 100);
+        assert_eq!(mask.get_pixel(0, 50).0, [0]);
+        assert_eq!(mask.get_pixel(9, 41).0, [0]);
+        assert_eq!(mask.get_pixel(0, 0).0, [255]);
+        assert_eq!(mask.get_pixel(0, 99).0, [255]);
+        let ramp: Vec<u8> = (60..=80).map(|y| mask.get_pixel(0, y).0[0]).collect();
+        assert!(ramp.windows(2).all(|pair| pair[0] <= pair[1]));
+        assert!(ramp[10] > 50 && ramp[10] < 205);
+
+        let radial = TiltShift::new(FocusShape::Radial, [25.0, 50.0], 20.0, 0.0, 4.0).unwrap();
+        let mask = radial.focus_mask(200, 100);
+        assert_eq!(mask.get_pixel(50, 50).0, [0]);
+        assert_eq!(mask.get_pixel(50, 56).0, [0]);
+        assert_eq!(mask.get_pixel(50, 62).0, [255]);
+        assert_eq!(mask.get_pixel(150, 50).0, [255]);
+    }
+
+    #[test]
+    fn tilt_shift_blurs_only_outside_the_focus() {
+        let stripes = RgbaImage::from_fn(40, 60, |x, _| {
+            if x % 2 == 0 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        });
+        let mut img = stripes.clone();
+        TiltShift::new(FocusShape::Linear, [50.0, 50.0], 20.0, 10.0, 3.0)
+            .unwrap()
+            .apply(&mut img);
+
+        assert_eq!(img.get_pixel(10, 30), stripes.get_pixel(10, 30));
+        let top = img.get_pixel(10, 2).0[0];
+        assert!(top > 80 && top < 175, "expected a blurred gray, got {top}");
+    }
+
+    #[test]
+    fn tilt_shift_rejects_invalid_settings() {
+        assert!(matches!(
+            TiltShift::new(FocusShape::Linear, [50.0, 50.0], 20.0, 20.0, 0.0),
+            Err(EffectError::InvalidBlurRadius(_))
+        ));
+        assert!(matches!(
+            TiltShift::new(FocusShape::Radial, [150.0, 50.0], 20.0, 20.0, 4.0),
+            Err(EffectError::InvalidPercentage(_))
+        ));
+        assert_eq!(
+            FocusShape::from_name(" Circle ").unwrap(),
+            FocusShape::Radial
+        );
+        assert!(matches!(
+            FocusShape::from_name("diagonal"),
+            Err(EffectError::UnknownFocusShape(_))
+        ));
+    }
+}
@@ -0,0 +1,326 @@
+//! Images stored inside other images: EXIF thumbnails, the sizes of an icon, the pages
+//! of a multi-page TIFF and the JPEG previews of camera RAW files.
+
+use std::fmt;
+
+use crate::convert;
+use crate::formats::{FormatError, ImageFormat};
+use crate::ico;
+
+/// TIFF pages read at most, so a file with looping IFD offsets can't hang the walk.
+const MAX_PAGES: usize = 256;
+
+/// Where an embedded image came from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmbeddedKind {
+    /// The thumbnail in a JPEG, PNG or WebP file's EXIF data.
+    ExifThumbnail,
+    /// One of the sizes in an ICO file.
+    IconImage,
+    /// One page of a TIFF file.
+    TiffPage,
+    /// A JPEG a camera embedded in a RAW file (with the `raw-preview` feature).
+    RawPreview,
+}
+
+impl EmbeddedKind {
+    /// The kind's name, as `extract_embedded` reports it to JS.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::ExifThumbnail => "exif_thumbnail",
+            Self::IconImage => "icon_image",
+            Self::TiffPage => "tiff_page",
+            Self::RawPreview => "raw_preview",
+        }
+    }
+}
+
+/// An image extracted from a container, encoded and ready to save or convert.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedImage {
+    pub kind: EmbeddedKind,
+    /// Position among the container's images of the same kind.
+    pub index: u32,
+    pub format: ImageFormat,
+    pub width: u32,
+    pub height: u32,
+    pub data: Vec<u8>,
+}
+
+/// Extracts the images `input` carries: its EXIF thumbnail, every size of an ICO file,
+/// every page of a TIFF or the JPEG previews of a RAW file (with the `raw-preview`
+/// feature).
+///
+/// EXIF thumbnails, PNG icon sizes and RAW previews are returned byte for byte. Other
+/// icon sizes are returned as single-image ICO files, and TIFF pages are re-encoded as
+/// PNG. Images that can't be read are left out, so a file without any returns an empty
+/// list.
+///
+/// # Errors
+///
+/// Returns `EmbeddedError::Format` if the input's format can't be detected.
+pub fn extract_embedded(input: &[u8]) -> Result<Vec<EmbeddedImage>, EmbeddedError> {
+    #[cfg(feature = "raw-preview")]
+    if let Ok(previews) = crate::raw::previews(input) {
+        return Ok(describe(EmbeddedKind::RawPreview, previews));
+    }
+    let format = ImageFormat::detect_from_bytes(input).map_err(EmbeddedError::Format)?;
+    let images = match format {
+        ImageFormat::Jpeg | ImageFormat::Png | ImageFormat::WebP => {
+            describe(EmbeddedKind::ExifThumbnail, exif_thumbnail(input))
+        }
+        ImageFormat::Ico => describe(EmbeddedKind::IconImage, icon_images(input)),
+        ImageFormat::Tiff => describe(EmbeddedKind::TiffPage, tiff_pages(input)),
+        ImageFormat::Gif | ImageFormat::Bmp | ImageFormat::Tga | ImageFormat::Qoi => Vec::new(),
+    };
+    Ok(images)
+}
+
+/// Pairs each encoded image with its format and dimensions, dropping unreadable ones.
+fn describe(kind: EmbeddedKind, images: impl IntoIterator<Item = Vec<u8>>) -> Vec<EmbeddedImage> {
+    images
+        .into_iter()
+        .filter_map(|data| {
+            let format = ImageFormat::detect_from_bytes(&data).ok()?;
+            let dimensions = convert::dimensions(&data).ok()?;
+            Some((format, dimensions, data))
+        })
+        .zip(0..)
+        .map(|((format, dimensions, data), index)| EmbeddedImage {
+            kind,
+            index,
+            format,
+            width: dimensions.width,
+            height: dimensions.height,
+            data,
+        })
+        .collect()
+}
+
+/// The JPEG thumbnail in the EXIF data's second IFD.
+fn exif_thumbnail(input: &[u8]) -> Option<Vec<u8>> {
+    let mut decoder = image::ImageReader::new(std::io::Cursor::new(input))
+        .with_guessed_format()
+        .ok()?
+        .into_decoder()
+        .ok()?;
+    let raw = image::ImageDecoder::exif_metadata(&mut decoder).ok()??;
+    let exif = exif::Reader::new().read_raw(raw).ok()?;
+    let field = |tag| {
+        exif.get_field(tag, exif::In::THUMBNAIL)?
+            .value
+            .get_uint(0)
+            .and_then(|value| usize::try_from(value).ok())
+    };
+    let start = field(exif::Tag::JPEGInterchangeFormat)?;
+    let length = field(exif::Tag::JPEGInterchangeFormatLength)?;
+    exif.buf()
+        .get(start..start.checked_add(length)?)
+        .map(<[u8]>::to_vec)
+}
+
+/// Every image in an ICO file, as [`ico::extract`] returns them.
+fn icon_images(input: &[u8]) -> Vec<Vec<u8>> {
+    (0..ico::sizes(input).len())
+        .filter_map(|index| ico::extract(input, index))
+        .collect()
+}
+
+/// Every page of a TIFF file as PNG. Each page is read by pointing a copy of the
+/// file's header at that page's IFD and ending the chain there.
+fn tiff_pages(input: &[u8]) -> Vec<Vec<u8>> {
+    let little = input.starts_with(b"II");
+    let read = |pos: usize| -> Option<u32> {
+        let bytes: [u8; 4] = input.get(pos..pos.checked_add(4)?)?.try_into().ok()?;
+        Some(if little {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        })
+    };
+    let count_at = |pos: usize| -> Option<usize> {
+        let bytes = [*input.get(pos)?, *input.get(pos.checked_add(1)?)?];
+        Some(usize::from(if little {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }))
+    };
+    let write = |value: u32| {
+        if little {
+            value.to_le_bytes()
+        } else {
+            value.to_be_bytes()
+        }
+    };
+
+    let mut pages = Vec::new();
+    let mut next = read(4);
+    while let Some(ifd) = next.filter(|&ifd| ifd != 0) {
+        if pages.len() == MAX_PAGES || pages.iter().any(|&(seen, _)| seen == ifd) {
+            break;
+        }
+        let Some(next_at) = usize::try_from(ifd)
+            .ok()
+            .and_then(|at| Some(at + 2 + count_at(at)? * 12))
+        else {
+            break;
+        };
+        next = read(next_at);
+        pages.push((ifd, next_at));
+    }
+
+    pages
+        .into_iter()
+        .filter_map(|(ifd, next_at)| {
+            let mut page = input.to_vec();
+            page.get_mut(4..8)?.copy_from_slice(&write(ifd));
+            page.get_mut(next_at..next_at + 4)?
+                .copy_from_slice(&write(0));
+            let image =
+                image::load_from_memory_with_format(&page, image::ImageFormat::Tiff).ok()?;
+            convert::encode(&image, ImageFormat::Png, None).ok()
+        })
+        .collect()
+}
+
+/// Errors that can occur while extracting embedded images.
+#[derive(Debug)]
+pub enum EmbeddedError {
+    /// The container's format couldn't be detected.
+    Format(FormatError),
+}
+
+impl fmt::Display for EmbeddedError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for EmbeddedError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::codecs::ico::{IcoEncoder, IcoFrame};
+    use image::{DynamicImage, ExtendedColorType, RgbImage};
+
+    use super::*;
+
+    fn encode(image: &DynamicImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        image.write_to(&mut Cursor::new(&mut out), format).unwrap();
+        out
+    }
+
+    /// A JPEG whose APP1 segment holds an EXIF thumbnail: IFD0 with an orientation,
+    /// then IFD1 pointing at the thumbnail, which follows it.
+    fn jpeg_with_thumbnail(thumbnail: &[u8]) -> Vec<u8> {
+        let mut tiff = b"II\x2a\0\x08\0\0\0".to_vec();
+        // IFD0: one entry, then the offset of IFD1 (8 + 2 + 12 + 4).
+        tiff.extend(1u16.to_le_bytes());
+        tiff.extend([0x12, 0x01, 3, 0, 1, 0, 0, 0, 1, 0, 0, 0]);
+        tiff.extend(26u32.to_le_bytes());
+        // IFD1: the thumbnail's offset (26 + 2 + 24 + 4) and length.
+        let length = u32::try_from(thumbnail.len()).unwrap();
+        tiff.extend(2u16.to_le_bytes());
+        tiff.extend([0x01, 0x02, 4, 0, 1, 0, 0, 0]);
+        tiff.extend(56u32.to_le_bytes());
+        tiff.extend([0x02, 0x02, 4, 0, 1, 0, 0, 0]);
+        tiff.extend(length.to_le_bytes());
+        tiff.extend(0u32.to_le_bytes());
+        tiff.extend(thumbnail);
+
+        let jpeg = encode(&DynamicImage::new_rgb8(32, 24), image::ImageFormat::Jpeg);
+        let mut app1 = b"Exif\0\0".to_vec();
+        app1.extend(tiff);
+        let mut out = jpeg[..2].to_vec();
+        out.extend([0xFF, 0xE1]);
+        out.extend(u16::try_from(app1.len() + 2).unwrap().to_be_bytes());
+        out.extend(app1);
+        out.extend(&jpeg[2..]);
+        out
+    }
+
+    #[test]
+    fn extracts_the_exif_thumbnail() {
+        let thumbnail = encode(&DynamicImage::new_rgb8(8, 6), image::ImageFormat::Jpeg);
+        let images = extract_embedded(&jpeg_with_thumbnail(&thumbnail)).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(images[0].kind, EmbeddedKind::ExifThumbnail);
+        assert_eq!((images[0].width, images[0].height), (8, 6));
+        assert_eq!(images[0].data, thumbnail);
+
+        let png = encode(&DynamicImage::new_rgb8(4, 4), image::ImageFormat::Png);
+        assert_eq!(extract_embedded(&png).unwrap(), []);
+        assert!(matches!(
+            extract_embedded(b"not an image"),
+            Err(EmbeddedError::Format(_))
+        ));
+    }
+
+    #[test]
+    fn extracts_every_icon_size() {
+        let frames: Vec<IcoFrame> = [16, 32]
+            .into_iter()
+            .map(|side| {
+                let pixels = vec![255; usize::try_from(side * side * 4).unwrap()];
+                IcoFrame::as_png(&pixels, side, side, ExtendedColorType::Rgba8).unwrap()
+            })
+            .collect();
+        let mut ico = Vec::new();
+        IcoEncoder::new(&mut ico).encode_images(&frames).unwrap();
+
+        // A 1×1 32-bit BMP entry: its header doubles the height for the AND mask.
+        let mut dib = 40u32.to_le_bytes().to_vec();
+        dib.extend(1u32.to_le_bytes());
+        dib.extend(2u32.to_le_bytes());
+        dib.extend(1u16.to_le_bytes());
+        dib.extend(32u16.to_le_bytes());
+        dib.extend([0; 24]);
+        dib.extend([0, 0, 255, 255]);
+        dib.extend([0; 4]);
+        let mut bmp_ico = vec![0, 0, 1, 0, 1, 0, 1, 1, 0, 0, 1, 0, 32, 0];
+        bmp_ico.extend(u32::try_from(dib.len()).unwrap().to_le_bytes());
+        bmp_ico.extend(22u32.to_le_bytes());
+        bmp_ico.extend(&dib);
+
+        let images = extract_embedded(&ico).unwrap();
+        let sizes: Vec<_> = images
+            .iter()
+            .map(|i| (i.index, i.width, i.format))
+            .collect();
+        assert_eq!(
+            sizes,
+            [(0, 16, ImageFormat::Png), (1, 32, ImageFormat::Png)]
+        );
+
+        let images = extract_embedded(&bmp_ico).unwrap();
+        assert_eq!(images[0].format, ImageFormat::Ico);
+        let pixel = image::load_from_memory(&images[0].data)
+            .unwrap()
+            .into_rgba8();
+        assert_eq!(pixel.get_pixel(0, 0).0, [255, 0, 0, 255]);
+    }
+
+    #[test]
+    fn extracts_every_tiff_page() {
+        let mut tiff = Vec::new();
+        let mut encoder = tiff::encoder::TiffEncoder::new(Cursor::new(&mut tiff)).unwrap();
+        for (side, value) in [(4, 10), (2, 200)] {
+            let page = RgbImage::from_pixel(side, side, image::Rgb([value; 3]));
+            encoder
+                .write_image::<tiff::encoder::colortype::RGB8>(side, side, page.as_raw())
+                .unwrap();
+        }
+
+        let pages = extract_embedded(&tiff).unwrap();
+        assert_eq!(pages.len(), 2);
+        assert!(pages.iter().all(|page| page.kind == EmbeddedKind::TiffPage));
+        let second = image::load_from_memory(&pages[1].data).unwrap().into_rgb8();
+        assert_eq!((second.width(), second.get_pixel(1, 1).0), (2, [200; 3]));
+    }
+}
@@ -0,0 +1,247 @@
+use std::cell::{Cell, RefCell};
+
+use serde::Serialize;
+
+use crate::timing::Stopwatch;
+
+/// A structured instrumentation event.
+///
+/// Operations are `"convert"` for a whole conversion, `"decode"`, `"encode"`, and one
+/// per processing step (a transform name, `"resize"` or `"dither_16bit"`).
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Event {
+    /// An operation began.
+    Started { operation: &'static str },
+    /// An operation completed after `ms` milliseconds, having produced `bytes` bytes:
+    /// pixel data for decoding and processing steps, encoded data otherwise.
+    Finished {
+        operation: &'static str,
+        ms: f64,
+        bytes: u64,
+    },
+}
+
+/// Receives instrumentation events, e.g. to forward them to a host's analytics.
+pub trait EventSink {
+    fn event(&self, event: &Event);
+}
+
+thread_local! {
+    static SINK: RefCell<Option<Box<dyn EventSink>>> = const { RefCell::new(None) };
+    /// Set while a sink is handling an event.
+    static DISPATCHING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Registers the sink that receives events on the current thread, replacing any
+/// previous one; `None` turns events off.
+///
+/// Sinks are per thread because conversions run on the caller's thread (WASM has
+/// only one), and host callbacks such as JavaScript functions can't be shared across
+/// threads.
+///
+/// Registering from inside a sink's `event` has no effect.
+pub fn set_event_sink(sink: Option<Box<dyn EventSink>>) {
+    SINK.with(|slot| {
+        if let Ok(mut slot) = slot.try_borrow_mut() {
+            *slot = sink;
+        }
+    });
+}
+
+/// Sends an event to the current thread's sink, if any.
+///
+/// Events a sink triggers while handling another (say, by running a conversion) are
+/// dropped, so a sink can never recurse into itself.
+pub fn emit(event: &Event) {
+    if DISPATCHING.with(Cell::get) {
+        return;
+    }
+    SINK.with(|slot| {
+        if let Some(sink) = slot.borrow().as_ref() {
+            DISPATCHING.with(|flag| flag.set(true));
+            sink.event(event);
+            DISPATCHING.with(|flag| flag.set(false));
+        }
+    });
+}
+
+/// Times an operation, emitting [`Event::Started`] now and [`Event::Finished`] from
+/// [`TimedOperation::finish`].
+///
+/// With the `tracing` feature, the operation also runs inside a `tracing` span, which
+/// records `bytes` when it finishes.
+pub struct TimedOperation {
+    operation: &'static str,
+    stopwatch: Stopwatch,
+    #[cfg(feature = "tracing")]
+    span: tracing::span::EnteredSpan,
+}
+
+impl TimedOperation {
+    pub fn start(operation: &'static str) -> Self {
+        emit(&Event::Started { operation });
+        Self {
+            operation,
+            stopwatch: Stopwatch::start(),
+            #[cfg(feature = "tracing")]
+            span: span(operation).entered(),
+        }
+    }
+
+    /// Ends the operation, returning its duration in milliseconds.
+    pub fn finish(self, bytes: u64) -> f64 {
+        #[cfg(feature = "tracing")]
+        self.span.record("bytes", bytes);
+        let ms = self.stopwatch.elapsed_ms();
+        emit(&Event::Finished {
+            operation: self.operation,
+            ms,
+            bytes,
+        });
+        ms
+    }
+}
+
+/// Span names must be static, so the fixed stages get their own names and processing
+/// steps share `"op"`, told apart by their `name` field.
+#[cfg(feature = "tracing")]
+fn span(operation: &'static str) -> tracing::Span {
+    use tracing::field::Empty;
+
+    match operation {
+        "convert" => tracing::info_span!("convert", bytes = Empty),
+        "decode" => tracing::info_span!("decode", bytes = Empty),
+        "encode" => tracing::info_span!("encode", bytes = Empty),
+        _ => tracing::info_span!("op", name = operation, bytes = Empty),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use super::*;
+
+    /// Collects events into a shared list.
+    struct Recorder(Rc<RefCell<Vec<Event>>>);
+
+    impl EventSink for Recorder {
+        fn event(&self, event: &Event) {
+            self.0.borrow_mut().push(event.clone());
+        }
+    }
+
+    fn record() -> Rc<RefCell<Vec<Event>>> {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        set_event_sink(Some(Box::new(Recorder(Rc::clone(&events)))));
+        events
+    }
+
+    #[test]
+    fn timed_operations_emit_start_and_finish() {
+        let events = record();
+        let ms = TimedOperation::start("decode").finish(42);
+        set_event_sink(None);
+        TimedOperation::start("ignored").finish(0);
+
+        let events = events.borrow();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0],
+            Event::Started {
+                operation: "decode"
+            }
+        );
+        assert_eq!(
+            events[1],
+            Event::Finished {
+                operation: "decode",
+                ms,
+                bytes: 42
+            }
+        );
+    }
+
+    #[test]
+    fn nested_events_are_dropped() {
+        struct Reentrant(Recorder);
+        impl EventSink for Reentrant {
+            fn event(&self, event: &Event) {
+                self.0.event(event);
+                emit(&Event::Started {
+                    operation: "nested",
+                });
+            }
+        }
+        let events = Rc::new(RefCell::new(Vec::new()));
+        set_event_sink(Some(Box::new(Reentrant(Recorder(Rc::clone(&events))))));
+        emit(&Event::Started { operation: "outer" });
+        emit(&Event::Started { operation: "again" });
+        set_event_sink(None);
+
+        assert_eq!(
+            *events.borrow(),
+            [
+                Event::Started { operation: "outer" },
+                Event::Started { operation: "again" }
+            ]
+        );
+    }
+
+    #[cfg(feature = "tracing")]
+    #[test]
+    fn operations_open_tracing_spans() {
+        use std::sync::{Arc, Mutex};
+
+        use tracing::span::{Attributes, Id, Record};
+        use tracing::{Metadata, Subscriber};
+
+        /// Records span names with their parent, and which spans recorded values.
+        #[derive(Default)]
+        struct Spans {
+            opened: Mutex<Vec<(&'static str, Option<u64>)>>,
+            recorded: Mutex<Vec<u64>>,
+            stack: Mutex<Vec<u64>>,
+        }
+
+        struct Collector(Arc<Spans>);
+
+        impl Subscriber for Collector {
+            fn enabled(&self, _: &Metadata<'_>) -> bool {
+                true
+            }
+            fn new_span(&self, span: &Attributes<'_>) -> Id {
+                let mut opened = self.0.opened.lock().unwrap();
+                let parent = self.0.stack.lock().unwrap().last().copied();
+                opened.push((span.metadata().name(), parent));
+                Id::from_u64(u64::try_from(opened.len()).unwrap())
+            }
+            fn record(&self, span: &Id, _: &Record<'_>) {
+                self.0.recorded.lock().unwrap().push(span.into_u64());
+            }
+            fn record_follows_from(&self, _: &Id, _: &Id) {}
+            fn event(&self, _: &tracing::Event<'_>) {}
+            fn enter(&self, span: &Id) {
+                self.0.stack.lock().unwrap().push(span.into_u64());
+            }
+            fn exit(&self, _: &Id) {
+                self.0.stack.lock().unwrap().pop();
+            }
+        }
+
+        let spans = Arc::new(Spans::default());
+        tracing::subscriber::with_default(Collector(Arc::clone(&spans)), || {
+            let conversion = TimedOperation::start("convert");
+            TimedOperation::start("decode").finish(1);
+            TimedOperation::start("rotate90").finish(2);
+            conversion.finish(3);
+        });
+
+        assert_eq!(
+            *spans.opened.lock().unwrap(),
+            [("convert", None), ("decode", Some(1)), ("op", Some(1))]
+        );
+        assert_eq!(*spans.recorded.lock().unwrap(), [2, 3, 1]);
+    }
+}
@@ -0,0 +1,419 @@
+//! Writing EXIF fields into JPEG and PNG files, e.g. to stamp a copyright notice on
+//! every exported image.
+//!
+//! The file's EXIF data is re-encoded with the edits applied: other fields, including
+//! the thumbnail, are kept. Maker notes are carried as opaque bytes, so vendor notes
+//! that use absolute offsets may not survive the move.
+
+use std::fmt;
+use std::io::Cursor;
+
+use exif::experimental::Writer;
+use exif::{Context, Field, In, Tag, Value};
+
+use crate::formats::ImageFormat;
+use crate::jpeg::{self, JpegError};
+use crate::png_optimize::{self, PngOptimizeError, PNG_SIGNATURE};
+
+/// EXIF fields to set or remove. Unset fields are left as the file has them.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExifEdits {
+    /// The `Artist` tag: who created the image.
+    pub artist: Option<String>,
+    /// The `Copyright` tag, e.g. `"© 2026 Example Agency"`.
+    pub copyright: Option<String>,
+    /// The `DateTime` tag (when the file was last changed), as `"YYYY:MM:DD HH:MM:SS"`
+    /// or ISO 8601 `"YYYY-MM-DDTHH:MM:SS"`.
+    pub date_time: Option<String>,
+    /// Removes every GPS field.
+    pub remove_gps: bool,
+}
+
+impl ExifEdits {
+    /// Whether applying these edits changes nothing.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Checks the edits without writing them.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ExifWriteError::InvalidDateTime` or `ExifWriteError::InvalidText` as
+    /// [`set_exif`] would.
+    pub fn validate(&self) -> Result<(), ExifWriteError> {
+        for (tag, value) in [
+            (Tag::Artist, &self.artist),
+            (Tag::Copyright, &self.copyright),
+        ] {
+            if let Some(value) = value {
+                ascii_field(tag, value)?;
+            }
+        }
+        if let Some(date_time) = &self.date_time {
+            normalize_date_time(date_time)?;
+        }
+        Ok(())
+    }
+}
+
+/// Applies `edits` to the EXIF data of a JPEG or PNG file (the `eXIf` chunk), adding
+/// EXIF data if the file has none. Pixel data and other segments or chunks are copied
+/// unchanged. When no fields are left, the EXIF data is removed.
+///
+/// # Errors
+///
+/// Returns `ExifWriteError::UnsupportedFormat` for other formats,
+/// `ExifWriteError::InvalidDateTime` or `ExifWriteError::InvalidText` for malformed
+/// edits, `ExifWriteError::Exif` if the existing EXIF data can't be re-encoded, or the
+/// JPEG or PNG structure errors.
+pub fn set_exif(input: &[u8], edits: &ExifEdits) -> Result<Vec<u8>, ExifWriteError> {
+    match ImageFormat::detect_from_bytes(input) {
+        Ok(ImageFormat::Jpeg) => {
+            let tiff = jpeg::exif(input).map_err(ExifWriteError::Jpeg)?;
+            let tiff = amend(tiff.as_deref(), edits)?;
+            jpeg::replace_exif(input, tiff.as_deref()).map_err(ExifWriteError::Jpeg)
+        }
+        Ok(ImageFormat::Png) => {
+            let chunks = png_optimize::read_chunks(input).map_err(ExifWriteError::Png)?;
+            let existing = chunks.iter().find(|chunk| &chunk.kind == b"eXIf");
+            let tiff = amend(existing.map(|chunk| chunk.data), edits)?;
+            let mut out = PNG_SIGNATURE.to_vec();
+            for chunk in chunks.iter().filter(|chunk| &chunk.kind != b"eXIf") {
+                png_optimize::write_chunk(&mut out, chunk.kind, chunk.data)
+                    .map_err(ExifWriteError::Png)?;
+                // eXIf has to come before the image data; right after IHDR is safe.
+                if let (b"IHDR", Some(tiff)) = (&chunk.kind, &tiff) {
+                    png_optimize::write_chunk(&mut out, *b"eXIf", tiff)
+                        .map_err(ExifWriteError::Png)?;
+                }
+            }
+            Ok(out)
+        }
+        Ok(format) => Err(ExifWriteError::UnsupportedFormat(
+            format.as_str().to_owned(),
+        )),
+        Err(e) => Err(ExifWriteError::UnsupportedFormat(e.to_string())),
+    }
+}
+
+/// Removes every GPS field from a JPEG's or PNG's EXIF data and keeps the rest (camera
+/// settings, orientation, dates). Files without GPS fields are returned unchanged.
+///
+/// # Errors
+///
+/// Returns the errors of [`set_exif`].
+pub fn strip_gps(input: &[u8]) -> Result<Vec<u8>, ExifWriteError> {
+    let edits = ExifEdits {
+        remove_gps: true,
+        ..ExifEdits::default()
+    };
+    set_exif(input, &edits)
+}
+
+/// `tiff` (an EXIF TIFF structure, or `None` for a file without EXIF data) with
+/// `edits` applied, or `None` if no fields are left.
+///
+/// # Errors
+///
+/// Returns the edit and encoding errors of [`set_exif`].
+pub fn amend(tiff: Option<&[u8]>, edits: &ExifEdits) -> Result<Option<Vec<u8>>, ExifWriteError> {
+    let exif = tiff
+        .map(|tiff| exif::Reader::new().read_raw(tiff.to_vec()))
+        .transpose()
+        .map_err(|e| ExifWriteError::Exif(e.to_string()))?;
+    let sets_fields =
+        edits.artist.is_some() || edits.copyright.is_some() || edits.date_time.is_some();
+    let has_gps = exif.as_ref().is_some_and(|exif| {
+        exif.fields()
+            .any(|field| field.tag.context() == Context::Gps)
+    });
+    let removes_gps = edits.remove_gps && has_gps;
+    if !sets_fields && !removes_gps {
+        // Nothing to change, so the data is kept byte for byte.
+        return Ok(tiff.map(<[u8]>::to_vec));
+    }
+
+    let mut replaced = Vec::new();
+    let mut fields = Vec::new();
+    for (tag, value) in [
+        (Tag::Artist, edits.artist.as_deref()),
+        (Tag::Copyright, edits.copyright.as_deref()),
+    ] {
+        if let Some(value) = value {
+            fields.push(ascii_field(tag, value)?);
+            replaced.push(tag);
+        }
+    }
+    if let Some(date_time) = &edits.date_time {
+        fields.push(ascii_field(
+            Tag::DateTime,
+            &normalize_date_time(date_time)?,
+        )?);
+        replaced.push(Tag::DateTime);
+    }
+
+    let mut thumbnail = None;
+    if let Some(exif) = &exif {
+        let kept = exif.fields().filter(|field| {
+            let replaced = field.ifd_num == In::PRIMARY && replaced.contains(&field.tag);
+            let gps = edits.remove_gps && field.tag.context() == Context::Gps;
+            !replaced && !gps
+        });
+        fields.extend(kept.cloned());
+        thumbnail = thumbnail_jpeg(exif);
+    }
+    // Only the pointers are left if every field of an IFD was removed.
+    let written = |field: &&Field| {
+        !matches!(
+            field.tag,
+            Tag::ExifIFDPointer
+                | Tag::GPSInfoIFDPointer
+                | Tag::InteropIFDPointer
+                | Tag::JPEGInterchangeFormat
+                | Tag::JPEGInterchangeFormatLength
+        )
+    };
+    if !fields
+        .iter()
+        .any(|field| field.ifd_num == In::PRIMARY && written(&field))
+    {
+        return Ok(None);
+    }
+
+    let mut writer = Writer::new();
+    for field in fields.iter().filter(written) {
+        writer.push_field(field);
+    }
+    if let Some(jpeg) = thumbnail {
+        writer.set_jpeg(jpeg, In::THUMBNAIL);
+    }
+    let mut out = Cursor::new(Vec::new());
+    let little_endian = exif.as_ref().is_some_and(exif::Exif::little_endian);
+    writer
+        .write(&mut out, little_endian)
+        .map_err(|e| ExifWriteError::Exif(e.to_string()))?;
+    Ok(Some(out.into_inner()))
+}
+
+/// The JPEG thumbnail the second IFD points at.
+fn thumbnail_jpeg(exif: &exif::Exif) -> Option<&[u8]> {
+    let field = |tag| {
+        exif.get_field(tag, In::THUMBNAIL)?
+            .value
+            .get_uint(0)
+            .and_then(|value| usize::try_from(value).ok())
+    };
+    let start = field(Tag::JPEGInterchangeFormat)?;
+    let length = field(Tag::JPEGInterchangeFormatLength)?;
+    exif.buf().get(start..start.checked_add(length)?)
+}
+
+fn ascii_field(tag: Tag, value: &str) -> Result<Field, ExifWriteError> {
+    if value.contains('\0') {
+        return Err(ExifWriteError::InvalidText(tag.to_string()));
+    }
+    Ok(Field {
+        tag,
+        ifd_num: In::PRIMARY,
+        value: Value::Ascii(vec![value.as_bytes().to_vec()]),
+    })
+}
+
+/// `"YYYY:MM:DD HH:MM:SS"` from that form or ISO 8601 (`-` in the date, `T` or a
+/// space before the time). Fractional seconds and time zones aren't accepted.
+fn normalize_date_time(value: &str) -> Result<String, ExifWriteError> {
+    let invalid = || ExifWriteError::InvalidDateTime(value.to_owned());
+    let bytes = value.trim().as_bytes();
+    if bytes.len() != 19 {
+        return Err(invalid());
+    }
+    let mut out = String::with_capacity(19);
+    for (index, &byte) in bytes.iter().enumerate() {
+        let expected = match index {
+            4 | 7 => matches!(byte, b':' | b'-').then_some(':'),
+            10 => matches!(byte, b' ' | b'T').then_some(' '),
+            13 | 16 => (byte == b':').then_some(':'),
+            _ => byte.is_ascii_digit().then_some(char::from(byte)),
+        };
+        out.push(expected.ok_or_else(invalid)?);
+    }
+    let number = |range: std::ops::Range<usize>| {
+        out.get(range)
+            .and_then(|digits| digits.parse::<u32>().ok())
+            .unwrap_or(0)
+    };
+    let valid = (1..=12).contains(&number(5..7))
+        && (1..=31).contains(&number(8..10))
+        && number(11..13) < 24
+        && number(14..16) < 60
+        && number(17..19) < 60;
+    if !valid {
+        return Err(invalid());
+    }
+    Ok(out)
+}
+
+/// Errors that can occur while writing EXIF fields.
+#[derive(Debug)]
+pub enum ExifWriteError {
+    /// The file isn't a JPEG or PNG.
+    UnsupportedFormat(String),
+    /// The date and time isn't `"YYYY:MM:DD HH:MM:SS"` or ISO 8601.
+    InvalidDateTime(String),
+    /// The named field's text contains a NUL character.
+    InvalidText(String),
+    /// The existing EXIF data couldn't be read or re-encoded.
+    Exif(String),
+    /// The JPEG structure couldn't be read or the EXIF data doesn't fit.
+    Jpeg(JpegError),
+    /// The PNG structure couldn't be read or written.
+    Png(PngOptimizeError),
+}
+
+impl fmt::Display for ExifWriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => {
+                write!(f, "EXIF can only be written to JPEG and PNG, not {format}")
+            }
+            Self::InvalidDateTime(value) => write!(
+                f,
+                "{value:?} is not a date and time like \"2026:10:16 09:30:00\""
+            ),
+            Self::InvalidText(tag) => write!(f, "{tag} can't contain NUL characters"),
+            Self::Exif(msg) => write!(f, "Invalid EXIF data: {msg}"),
+            Self::Jpeg(e) => write!(f, "{e}"),
+            Self::Png(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ExifWriteError {}
+
+#[cfg(test)]
+mod tests {
+    use image::DynamicImage;
+
+    use super::*;
+
+    fn encode(format: image::ImageFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        DynamicImage::new_rgb8(4, 3)
+            .write_to(&mut Cursor::new(&mut out), format)
+            .unwrap();
+        out
+    }
+
+    fn read(tiff: Vec<u8>) -> exif::Exif {
+        exif::Reader::new().read_raw(tiff).unwrap()
+    }
+
+    fn text(exif: &exif::Exif, tag: Tag) -> Option<String> {
+        exif.get_field(tag, In::PRIMARY)
+            .map(|field| field.display_value().to_string())
+    }
+
+    /// EXIF data with a make, a GPS latitude and an artist to be replaced.
+    fn source_tiff() -> Vec<u8> {
+        let fields = [
+            (Tag::Make, In::PRIMARY, Value::Ascii(vec![b"Acme".to_vec()])),
+            (
+                Tag::Artist,
+                In::PRIMARY,
+                Value::Ascii(vec![b"Old".to_vec()]),
+            ),
+            (
+                Tag::GPSLatitudeRef,
+                In::PRIMARY,
+                Value::Ascii(vec![b"N".to_vec()]),
+            ),
+        ]
+        .map(|(tag, ifd_num, value)| Field {
+            tag,
+            ifd_num,
+            value,
+        });
+        let mut writer = Writer::new();
+        for field in &fields {
+            writer.push_field(field);
+        }
+        let mut out = Cursor::new(Vec::new());
+        writer.write(&mut out, true).unwrap();
+        out.into_inner()
+    }
+
+    #[test]
+    fn amends_existing_fields_and_removes_gps() {
+        let edits = ExifEdits {
+            artist: Some("Jo Doe".into()),
+            copyright: Some("© 2026 Agency".into()),
+            date_time: Some("2026-10-16T09:30:00".into()),
+            remove_gps: true,
+        };
+        let exif = read(amend(Some(&source_tiff()), &edits).unwrap().unwrap());
+        assert_eq!(text(&exif, Tag::Make).as_deref(), Some("\"Acme\""));
+        assert_eq!(text(&exif, Tag::Artist).as_deref(), Some("\"Jo Doe\""));
+        let date_time = &exif.get_field(Tag::DateTime, In::PRIMARY).unwrap().value;
+        assert!(matches!(date_time, Value::Ascii(v) if v[0] == b"2026:10:16 09:30:00"));
+        assert!(exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY).is_none());
+        assert!(exif.little_endian());
+
+        let only_gps = ExifEdits {
+            remove_gps: true,
+            ..ExifEdits::default()
+        };
+        assert_eq!(amend(None, &only_gps).unwrap(), None);
+        let stripped = amend(Some(&source_tiff()), &only_gps).unwrap().unwrap();
+        assert!(read(stripped.clone())
+            .get_field(Tag::Make, In::PRIMARY)
+            .is_some());
+        assert_eq!(amend(Some(&stripped), &only_gps).unwrap(), Some(stripped));
+    }
+
+    #[test]
+    fn stamps_jpeg_and_png_files() {
+        let edits = ExifEdits {
+            copyright: Some("© Agency".into()),
+            ..ExifEdits::default()
+        };
+        let jpeg = set_exif(&encode(image::ImageFormat::Jpeg), &edits).unwrap();
+        let exif = read(jpeg::exif(&jpeg).unwrap().unwrap());
+        assert!(text(&exif, Tag::Copyright).unwrap().contains("Agency"));
+        assert_eq!(image::load_from_memory(&jpeg).unwrap().width(), 4);
+
+        let png = set_exif(&encode(image::ImageFormat::Png), &edits).unwrap();
+        let chunks = png_optimize::read_chunks(&png).unwrap();
+        let kinds: Vec<_> = chunks.iter().map(|chunk| &chunk.kind).collect();
+        assert_eq!(kinds[..2], [b"IHDR", b"eXIf"]);
+        let exif = read(chunks[1].data.to_vec());
+        assert!(text(&exif, Tag::Copyright).is_some());
+        assert_eq!(image::load_from_memory(&png).unwrap().height(), 3);
+
+        let options = crate::convert::ConvertOptions {
+            exif: Some(edits.clone()),
+            ..crate::convert::ConvertOptions::default()
+        };
+        let converted = crate::convert::convert_with_options(
+            encode(image::ImageFormat::Bmp),
+            ImageFormat::Jpeg,
+            &options,
+            &[],
+        )
+        .unwrap();
+        assert!(jpeg::exif(&converted).unwrap().is_some());
+
+        assert!(matches!(
+            set_exif(&encode(image::ImageFormat::Bmp), &edits),
+            Err(ExifWriteError::UnsupportedFormat(_))
+        ));
+        let bad_date = ExifEdits {
+            date_time: Some("2026-13-01 00:00:00".into()),
+            ..ExifEdits::default()
+        };
+        assert!(matches!(
+            set_exif(&jpeg, &bad_date),
+            Err(ExifWriteError::InvalidDateTime(_))
+        ));
+    }
+}
@@ -0,0 +1,586 @@
+//! Face detection with pixel-comparison cascades (only in builds with the `faces`
+//! feature), so smart crops can keep faces in frame.
+//!
+//! The detector runs cascades in the format of pico's `facefinder` (Markuš et al.,
+//! "Object Detection with Pixel Intensity Comparisons Organized in Decision Trees"):
+//! boosted decision trees whose nodes compare two pixels of a square window. It needs
+//! no integral images or floating-point features, so a scan is cheap, but the trained
+//! model isn't compiled in. The host loads it (about 230 KB for `facefinder`) and
+//! passes its bytes to [`Cascade::parse`].
+
+use std::fmt;
+
+use image::imageops::{self, FilterType};
+use image::{GrayImage, RgbaImage};
+use serde::Serialize;
+
+use crate::convert;
+use crate::formats::ImageFormat;
+use crate::smart_crop::{self, CropBox, SmartCropError};
+
+/// Longest side of the grayscale copy that windows are scanned on. Faces much smaller
+/// than a twentieth of this don't matter for framing a crop.
+const DETECTION_SIDE: u32 = 640;
+
+/// Side of the smallest window scanned, in pixels of the scanned copy.
+const MIN_WINDOW: u32 = 20;
+
+/// Deepest tree [`Cascade::parse`] accepts. `facefinder` uses depth 6.
+const MAX_DEPTH: u32 = 12;
+
+/// Detections that overlap by more than this (intersection over union) are merged.
+const CLUSTER_OVERLAP: f64 = 0.2;
+
+/// One tree of a cascade.
+#[derive(Debug, Clone)]
+struct Tree {
+    /// Pixel pairs `[row1, col1, row2, col2]` compared at each internal node, indexed
+    /// from 1 in breadth-first order, as offsets in 1/256ths of the window side from its
+    /// center. Entry 0 is unused.
+    tests: Vec<[i8; 4]>,
+    /// Scores of the leaves, left to right.
+    leaves: Vec<f32>,
+    /// The running score must stay above this after the tree, or the window is rejected.
+    threshold: f32,
+}
+
+/// A face detector trained with pico, such as its `facefinder` cascade.
+#[derive(Debug, Clone)]
+pub struct Cascade {
+    depth: u32,
+    trees: Vec<Tree>,
+}
+
+impl Cascade {
+    /// Reads a cascade from the binary format pico writes: 8 bytes of training data,
+    /// the tree depth and count as little-endian `i32`s, then per tree its node tests as
+    /// signed bytes, its leaf scores and its rejection threshold as little-endian `f32`s.
+    ///
+    /// # Errors
+    ///
+    /// Returns `FaceError::InvalidCascade` if the bytes are truncated, or the depth or
+    /// tree count is out of range.
+    pub fn parse(bytes: &[u8]) -> Result<Self, FaceError> {
+        let mut reader = Reader {
+            bytes: bytes.get(8..).ok_or(FaceError::InvalidCascade)?,
+        };
+        let depth = u32::try_from(reader.i32()?)
+            .ok()
+            .filter(|depth| (1..=MAX_DEPTH).contains(depth))
+            .ok_or(FaceError::InvalidCascade)?;
+        let count = usize::try_from(reader.i32()?)
+            .ok()
+            .filter(|&count| count > 0)
+            .ok_or(FaceError::InvalidCascade)?;
+        let leaf_count = 1usize << depth;
+
+        let mut trees = Vec::new();
+        for _ in 0..count {
+            let mut tests = vec![[0; 4]];
+            for _ in 1..leaf_count {
+                let test = reader.take(4)?;
+                tests.push(
+                    [0, 1, 2, 3]
+                        .map(|i| i8::from_le_bytes([test.get(i).copied().unwrap_or_default()])),
+                );
+            }
+            let leaves = (0..leaf_count)
+                .map(|_| reader.f32())
+                .collect::<Result<_, _>>()?;
+            trees.push(Tree {
+                tests,
+                leaves,
+                threshold: reader.f32()?,
+            });
+        }
+        Ok(Self { depth, trees })
+    }
+
+    /// Runs the cascade on the `size`-pixel window centered on `row`, `col`. Returns the
+    /// window's score above the final threshold, or `None` if a tree rejects it.
+    fn classify(&self, gray: &GrayImage, row: u32, col: u32, size: u32) -> Option<f32> {
+        let pixel = |dy: i8, dx: i8| {
+            let at = |center: u32, offset: i8| {
+                (i64::from(center) * 256 + i64::from(offset) * i64::from(size)) >> 8
+            };
+            let y = u32::try_from(at(row, dy)).ok()?;
+            let x = u32::try_from(at(col, dx)).ok()?;
+            gray.get_pixel_checked(x, y).map(|p| p.0[0])
+        };
+
+        let mut score = 0.0;
+        let mut threshold = 0.0;
+        for tree in &self.trees {
+            let mut node = 1;
+            for _ in 0..self.depth {
+                let [y1, x1, y2, x2] = *tree.tests.get(node)?;
+                node = 2 * node + usize::from(pixel(y1, x1)? <= pixel(y2, x2)?);
+            }
+            score += tree.leaves.get(node - tree.leaves.len())?;
+            threshold = tree.threshold;
+            if score <= threshold {
+                return None;
+            }
+        }
+        Some(score - threshold)
+    }
+}
+
+/// Little-endian reads over the bytes of a cascade.
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], FaceError> {
+        if self.bytes.len() < len {
+            return Err(FaceError::InvalidCascade);
+        }
+        let (taken, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn word(&mut self) -> Result<[u8; 4], FaceError> {
+        self.take(4)?
+            .try_into()
+            .map_err(|_| FaceError::InvalidCascade)
+    }
+
+    fn i32(&mut self) -> Result<i32, FaceError> {
+        self.word().map(i32::from_le_bytes)
+    }
+
+    fn f32(&mut self) -> Result<f32, FaceError> {
+        self.word().map(f32::from_le_bytes)
+    }
+}
+
+/// Tuning for [`detect`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FaceOptions {
+    /// Faces scoring at or below this are dropped. A face's score adds up the windows
+    /// merged into it, so it depends on the cascade; 50 suits pico's `facefinder`.
+    pub min_score: f32,
+}
+
+impl Default for FaceOptions {
+    fn default() -> Self {
+        Self { min_score: 50.0 }
+    }
+}
+
+/// A detected face, in image pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Face {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    /// How confident the cascade is; higher is better.
+    pub score: f32,
+}
+
+impl Face {
+    /// The face's box, e.g. as a focus region for [`smart_crop::choose_crop`].
+    pub fn region(&self) -> CropBox {
+        CropBox {
+            x: self.x,
+            y: self.y,
+            width: self.width,
+            height: self.height,
+        }
+    }
+}
+
+/// A square window the cascade accepted, centered on `row`, `col` of the scanned copy.
+#[derive(Debug, Clone, Copy)]
+struct Detection {
+    row: u32,
+    col: u32,
+    size: u32,
+    score: f32,
+}
+
+impl Detection {
+    /// Intersection over union of two windows.
+    fn overlap(&self, other: &Self) -> f64 {
+        let span = |a: u32, b: u32| {
+            let (a, b) = (f64::from(a), f64::from(b));
+            let (size, other_size) = (f64::from(self.size), f64::from(other.size));
+            ((a + size / 2.0).min(b + other_size / 2.0)
+                - (a - size / 2.0).max(b - other_size / 2.0))
+            .max(0.0)
+        };
+        let shared = span(self.row, other.row) * span(self.col, other.col);
+        shared / (f64::from(self.size).powi(2) + f64::from(other.size).powi(2) - shared)
+    }
+}
+
+/// Finds the faces in `img` with `cascade`, highest score first.
+///
+/// Windows from [`MIN_WINDOW`] pixels up to the image's shorter side are scanned on a
+/// grayscale copy at most [`DETECTION_SIDE`] pixels long, in steps of a tenth of their
+/// size, and overlapping hits are merged into one face.
+pub fn detect(img: &RgbaImage, cascade: &Cascade, options: &FaceOptions) -> Vec<Face> {
+    let (width, height) = img.dimensions();
+    let longest = width.max(height);
+    let gray = imageops::grayscale(img);
+    let gray = if longest > DETECTION_SIDE {
+        let scale = |side: u32| {
+            u32::try_from(u64::from(side) * u64::from(DETECTION_SIDE) / u64::from(longest))
+                .unwrap_or(DETECTION_SIDE)
+                .max(1)
+        };
+        imageops::resize(&gray, scale(width), scale(height), FilterType::Triangle)
+    } else {
+        gray
+    };
+    let (scan_width, scan_height) = gray.dimensions();
+
+    let mut detections = Vec::new();
+    let mut size = MIN_WINDOW;
+    while size <= scan_width.min(scan_height) {
+        let step = (size / 10).max(1);
+        let offset = size / 2 + 1;
+        for row in (offset..=scan_height.saturating_sub(offset)).step_by(step_len(step)) {
+            for col in (offset..=scan_width.saturating_sub(offset)).step_by(step_len(step)) {
+                if let Some(score) = cascade.classify(&gray, row, col, size) {
+                    if score > 0.0 {
+                        detections.push(Detection {
+                            row,
+                            col,
+                            size,
+                            score,
+                        });
+                    }
+                }
+            }
+        }
+        size = (size * 11 / 10).max(size + 1);
+    }
+
+    let to_image = |value: u64, scan_side: u32, side: u32| {
+        u32::try_from(value * u64::from(side) / u64::from(scan_side.max(1))).unwrap_or(side)
+    };
+    let mut faces: Vec<Face> = cluster(detections)
+        .into_iter()
+        .filter(|detection| detection.score > options.min_score)
+        .map(
+            |Detection {
+                 row,
+                 col,
+                 size,
+                 score,
+             }| {
+                let half = u64::from(size / 2);
+                let left = u64::from(col).saturating_sub(half);
+                let top = u64::from(row).saturating_sub(half);
+                let x = to_image(left, scan_width, width).min(width);
+                let y = to_image(top, scan_height, height).min(height);
+                let right = to_image(left + u64::from(size), scan_width, width).min(width);
+                let bottom = to_image(top + u64::from(size), scan_height, height).min(height);
+                Face {
+                    x,
+                    y,
+                    width: right - x,
+                    height: bottom - y,
+                    score,
+                }
+            },
+        )
+        .collect();
+    faces.sort_by(|a, b| b.score.total_cmp(&a.score));
+    faces
+}
+
+fn step_len(step: u32) -> usize {
+    usize::try_from(step).unwrap_or(usize::MAX)
+}
+
+/// Merges overlapping detections, best first, into their average window with the sum
+/// of their scores.
+fn cluster(mut detections: Vec<Detection>) -> Vec<Detection> {
+    detections.sort_by(|a, b| b.score.total_cmp(&a.score));
+    let mut merged = vec![false; detections.len()];
+    let mut clusters = Vec::new();
+    for (i, seed) in detections.iter().enumerate() {
+        if merged.get(i).copied().unwrap_or(true) {
+            continue;
+        }
+        let (mut row, mut col, mut size, mut score, mut count) = (0u64, 0u64, 0u64, 0.0, 0u64);
+        for (other, taken) in detections.iter().zip(merged.iter_mut()).skip(i) {
+            if !*taken && seed.overlap(other) > CLUSTER_OVERLAP {
+                *taken = true;
+                row += u64::from(other.row);
+                col += u64::from(other.col);
+                size += u64::from(other.size);
+                score += other.score;
+                count += 1;
+            }
+        }
+        let average = |sum: u64| u32::try_from(sum / count.max(1)).unwrap_or(u32::MAX);
+        clusters.push(Detection {
+            row: average(row),
+            col: average(col),
+            size: average(size),
+            score,
+        });
+    }
+    clusters
+}
+
+/// Decodes `input` and finds its faces with the cascade in `cascade`.
+///
+/// # Errors
+///
+/// Returns `FaceError::InvalidCascade` if the cascade can't be read, or
+/// `FaceError::Decode` if the input can't be decoded.
+pub fn detect_faces(
+    input: &[u8],
+    cascade: &[u8],
+    options: &FaceOptions,
+) -> Result<Vec<Face>, FaceError> {
+    let cascade = Cascade::parse(cascade)?;
+    let img = image::load_from_memory(input)
+        .map_err(FaceError::Decode)?
+        .into_rgba8();
+    Ok(detect(&img, &cascade, options))
+}
+
+/// Like [`smart_crop::smart_crop`], but keeps the faces `cascade` finds in frame along
+/// with the `focus` regions.
+///
+/// # Errors
+///
+/// Returns a `SmartCropError` if the cascade can't be read, the size is zero, the input
+/// cannot be decoded, or the output cannot be encoded.
+pub fn smart_crop_faces(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    focus: &[CropBox],
+    cascade: &[u8],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, SmartCropError> {
+    let cascade = Cascade::parse(cascade).map_err(SmartCropError::Faces)?;
+    let img = image::load_from_memory(input)
+        .map_err(SmartCropError::Decode)?
+        .into_rgba8();
+    let focus: Vec<CropBox> = focus
+        .iter()
+        .copied()
+        .chain(
+            detect(&img, &cascade, &FaceOptions::default())
+                .iter()
+                .map(Face::region),
+        )
+        .collect();
+    let resized = smart_crop::crop_and_resize(&img, width, height, &focus)?;
+    convert::encode(&resized, target, quality).map_err(SmartCropError::Convert)
+}
+
+/// Errors that can occur while detecting faces.
+#[derive(Debug)]
+pub enum FaceError {
+    /// The cascade is truncated or isn't in pico's format.
+    InvalidCascade,
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+}
+
+impl fmt::Display for FaceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCascade => write!(f, "Invalid face detection cascade"),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for FaceError {}
+
+#[cfg(test)]
+mod tests {
+    use image::{Luma, Rgba};
+
+    use super::*;
+
+    /// Pixel offsets, in 1/256ths of the window, of the probes around its center.
+    const PROBE: i8 = 100;
+
+    /// Writes `trees` as a cascade in pico's format.
+    fn cascade_bytes(depth: i32, trees: &[Tree]) -> Vec<u8> {
+        let mut bytes = vec![0; 8];
+        bytes.extend(depth.to_le_bytes());
+        bytes.extend(i32::try_from(trees.len()).unwrap().to_le_bytes());
+        for tree in trees {
+            for test in &tree.tests[1..] {
+                bytes.extend(test.map(|offset| offset.to_le_bytes()[0]));
+            }
+            for leaf in &tree.leaves {
+                bytes.extend(leaf.to_le_bytes());
+            }
+            bytes.extend(tree.threshold.to_le_bytes());
+        }
+        bytes
+    }
+
+    /// A depth-2 cascade that accepts windows whose center is darker than the pixels
+    /// above and below it (first tree), then left and right of it (second tree), each
+    /// tree adding `accept` to the score.
+    fn dark_spot_cascade_bytes(accept: f32) -> Vec<u8> {
+        let tree = |before: [i8; 2], after: [i8; 2], threshold| Tree {
+            tests: vec![
+                [0; 4],
+                // Node 1: the first probe against the center; it must be brighter.
+                [before[0], before[1], 0, 0],
+                // Node 2, reached when it is: the second probe against the center.
+                [after[0], after[1], 0, 0],
+                // Node 3, already rejected.
+                [0, 0, 0, 0],
+            ],
+            leaves: vec![accept, -1.0, -1.0, -1.0],
+            threshold,
+        };
+        cascade_bytes(
+            2,
+            &[
+                tree([-PROBE, 0], [PROBE, 0], 0.5),
+                tree([0, -PROBE], [0, PROBE], 1.5),
+            ],
+        )
+    }
+
+    fn dark_spot_cascade() -> Cascade {
+        Cascade::parse(&dark_spot_cascade_bytes(1.0)).unwrap()
+    }
+
+    /// A light image with a dark 40-pixel square at `x`, `y`.
+    fn dark_square_at(x: u32, y: u32) -> RgbaImage {
+        RgbaImage::from_fn(240, 160, |px, py| {
+            if (x..x + 40).contains(&px) && (y..y + 40).contains(&py) {
+                Rgba([20, 20, 20, 255])
+            } else {
+                Rgba([220, 220, 220, 255])
+            }
+        })
+    }
+
+    const ANY_SCORE: FaceOptions = FaceOptions { min_score: 0.0 };
+
+    #[test]
+    fn parses_pico_cascades() {
+        let cascade = dark_spot_cascade();
+        assert_eq!(cascade.depth, 2);
+        assert_eq!(cascade.trees.len(), 2);
+        assert_eq!(cascade.trees[1].tests[1], [0, -PROBE, 0, 0]);
+        assert_eq!(cascade.trees[1].leaves, [1.0, -1.0, -1.0, -1.0]);
+        assert_eq!(cascade.trees[1].threshold, 1.5);
+    }
+
+    #[test]
+    fn rejects_truncated_and_malformed_cascades() {
+        let bytes = cascade_bytes(
+            1,
+            &[Tree {
+                tests: vec![[0; 4]; 2],
+                leaves: vec![1.0, -1.0],
+                threshold: 0.0,
+            }],
+        );
+        assert!(Cascade::parse(&bytes).is_ok());
+        for len in [0, 7, 12, bytes.len() - 1] {
+            assert!(matches!(
+                Cascade::parse(&bytes[..len]),
+                Err(FaceError::InvalidCascade)
+            ));
+        }
+        assert!(Cascade::parse(&cascade_bytes(0, &[])).is_err());
+        assert!(Cascade::parse(&cascade_bytes(40, &[])).is_err());
+    }
+
+    #[test]
+    fn classifies_windows_through_every_tree() {
+        let cascade = dark_spot_cascade();
+        let mut gray = GrayImage::from_pixel(100, 100, Luma([200]));
+        gray.put_pixel(50, 50, Luma([10]));
+        assert_eq!(cascade.classify(&gray, 50, 50, 40), Some(0.5));
+        // Flat windows fail the first tree, and a bright right-hand probe alone the second.
+        assert_eq!(cascade.classify(&gray, 20, 20, 40), None);
+        gray.put_pixel(65, 50, Luma([5]));
+        assert_eq!(cascade.classify(&gray, 50, 50, 40), None);
+    }
+
+    #[test]
+    fn finds_the_face_like_spot() {
+        let faces = detect(&dark_square_at(150, 60), &dark_spot_cascade(), &ANY_SCORE);
+        let best = faces.first().unwrap();
+        let center = (best.x + best.width / 2, best.y + best.height / 2);
+        assert!((150..190).contains(&center.0), "{best:?}");
+        assert!((60..100).contains(&center.1), "{best:?}");
+        assert!(faces.windows(2).all(|pair| pair[0].score >= pair[1].score));
+
+        let flat = RgbaImage::from_pixel(240, 160, Rgba([220, 220, 220, 255]));
+        assert!(detect(&flat, &dark_spot_cascade(), &ANY_SCORE).is_empty());
+    }
+
+    #[test]
+    fn large_images_are_scanned_downscaled_and_mapped_back() {
+        let img = RgbaImage::from_fn(1920, 1280, |px, py| {
+            if (1200..1520).contains(&px) && (480..800).contains(&py) {
+                Rgba([20, 20, 20, 255])
+            } else {
+                Rgba([220, 220, 220, 255])
+            }
+        });
+        let best = *detect(&img, &dark_spot_cascade(), &ANY_SCORE)
+            .first()
+            .unwrap();
+        let center = (best.x + best.width / 2, best.y + best.height / 2);
+        assert!((1200..1520).contains(&center.0), "{best:?}");
+        assert!((480..800).contains(&center.1), "{best:?}");
+        assert!(best.x + best.width <= 1920 && best.y + best.height <= 1280);
+    }
+
+    #[test]
+    fn min_score_drops_weak_faces() {
+        let img = dark_square_at(150, 60);
+        let faces = detect(&img, &dark_spot_cascade(), &ANY_SCORE);
+        let best = faces.first().unwrap().score;
+        let strict = FaceOptions { min_score: best };
+        assert!(detect(&img, &dark_spot_cascade(), &strict).is_empty());
+    }
+
+    #[test]
+    fn smart_crops_keep_detected_faces_in_frame() {
+        // Busy detail on the left, and a face-like spot on the right.
+        let img = RgbaImage::from_fn(300, 100, |px, py| {
+            if (180..220).contains(&px) && (30..70).contains(&py) {
+                Rgba([20, 20, 20, 255])
+            } else if px < 60 && (px + py) % 2 == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([220, 220, 220, 255])
+            }
+        });
+        let mut png = Vec::new();
+        img.write_to(&mut std::io::Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let has_spot = |output: &[u8]| {
+            image::load_from_memory(output)
+                .unwrap()
+                .into_rgba8()
+                .pixels()
+                .any(|p| p.0[0] < 60)
+        };
+
+        let plain = smart_crop::smart_crop(&png, 100, 100, &[], ImageFormat::Png, None).unwrap();
+        assert!(!has_spot(&plain));
+        let cascade = dark_spot_cascade_bytes(100.0);
+        let output =
+            smart_crop_faces(&png, 100, 100, &[], &cascade, ImageFormat::Png, None).unwrap();
+        assert!(has_spot(&output));
+    }
+}
@@ -0,0 +1,671 @@
+//! TrueType and OpenType fonts registered at runtime for drawing text. Each font is
+//! parsed once, when it is registered, and kept until it is unregistered.
+
+use std::cell::RefCell;
+use std::fmt;
+
+use ab_glyph::{point, Font, FontArc, Glyph, GlyphId, InvalidFont, PxScale, ScaleFont};
+use image::{DynamicImage, RgbaImage};
+use serde::Serialize;
+
+use crate::composite;
+use crate::convert::{self, ConvertError, Dimensions};
+use crate::formats::ImageFormat;
+use crate::generate::MAX_SIDE;
+
+/// Characters checked for [`FontInfo::scripts`]: a font covers a script if it has a
+/// glyph for each of its basic letters.
+const SCRIPTS: [(&str, &[(char, char)]); 2] = [
+    ("latin", &[('A', 'Z'), ('a', 'z')]),
+    ("cyrillic", &[('\u{410}', '\u{44f}')]),
+];
+
+/// Combining marks drawn over the preceding character: the general-purpose, Cyrillic,
+/// extended, supplementary, symbol and half-mark blocks.
+const COMBINING_MARKS: [(char, char); 6] = [
+    ('\u{300}', '\u{36f}'),
+    ('\u{483}', '\u{489}'),
+    ('\u{1ab0}', '\u{1aff}'),
+    ('\u{1dc0}', '\u{1dff}'),
+    ('\u{20d0}', '\u{20ff}'),
+    ('\u{fe20}', '\u{fe2f}'),
+];
+
+thread_local! {
+    /// Registered fonts in registration order. Per thread like the event sink, since
+    /// WASM has only one.
+    static FONTS: RefCell<Vec<(String, FontArc)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A registered font, as listed by [`list_fonts`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FontInfo {
+    pub name: String,
+    pub glyph_count: u32,
+    /// The scripts (`"latin"`, `"cyrillic"`) whose basic letters all have glyphs.
+    pub scripts: Vec<&'static str>,
+}
+
+/// Parses a TrueType or OpenType font and registers it under `name`, replacing any
+/// font already registered under that name (compared case-insensitively).
+///
+/// # Errors
+///
+/// Returns `FontError::InvalidName` if the name is blank or contains a comma (which
+/// separates names in a fallback list), or `FontError::Invalid` if the data isn't a
+/// font.
+pub fn register_font(name: &str, data: Vec<u8>) -> Result<FontInfo, FontError> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(',') {
+        return Err(FontError::InvalidName(name.to_owned()));
+    }
+    let font = FontArc::try_from_vec(data).map_err(FontError::Invalid)?;
+    let info = font_info(name, &font);
+    FONTS.with(|fonts| {
+        let mut fonts = fonts.borrow_mut();
+        match fonts
+            .iter_mut()
+            .find(|(registered, _)| registered.eq_ignore_ascii_case(name))
+        {
+            Some(entry) => *entry = (name.to_owned(), font),
+            None => fonts.push((name.to_owned(), font)),
+        }
+    });
+    Ok(info)
+}
+
+/// Removes the font registered under `name`. Returns whether there was one.
+pub fn unregister_font(name: &str) -> bool {
+    let name = name.trim();
+    FONTS.with(|fonts| {
+        let mut fonts = fonts.borrow_mut();
+        let before = fonts.len();
+        fonts.retain(|(registered, _)| !registered.eq_ignore_ascii_case(name));
+        fonts.len() != before
+    })
+}
+
+/// The registered fonts, in registration order.
+pub fn list_fonts() -> Vec<FontInfo> {
+    FONTS.with(|fonts| {
+        fonts
+            .borrow()
+            .iter()
+            .map(|(name, font)| font_info(name, font))
+            .collect()
+    })
+}
+
+fn font_info(name: &str, font: &FontArc) -> FontInfo {
+    let scripts = SCRIPTS
+        .iter()
+        .filter(|(_, ranges)| {
+            ranges
+                .iter()
+                .flat_map(|&(first, last)| first..=last)
+                .all(|c| font.glyph_id(c) != GlyphId(0))
+        })
+        .map(|&(script, _)| script)
+        .collect();
+    FontInfo {
+        name: name.to_owned(),
+        glyph_count: u32::try_from(font.glyph_count()).unwrap_or(u32::MAX),
+        scripts,
+    }
+}
+
+/// The size in pixels of `text` laid out as [`draw_text`] would, rounded up.
+///
+/// # Errors
+///
+/// Returns a `FontError` if `size` is zero or above [`MAX_SIDE`], a font in `fonts`
+/// isn't registered, or `fonts` is empty and no font is.
+pub fn measure_text(text: &str, fonts: &[&str], size: u32) -> Result<Dimensions, FontError> {
+    check_size(size)?;
+    let layout = Layout::new(text, &resolve(fonts)?, size);
+    Ok(Dimensions {
+        width: to_side(layout.width),
+        height: to_side(layout.height),
+    })
+}
+
+/// Draws `text` on `img` with the top-left corner of its first line at (`x`, `y`),
+/// `size` pixels per em.
+///
+/// `fonts` is a fallback list of registered font names: each character is drawn with
+/// the first font that has a glyph for it, or the first font's missing-glyph box if
+/// none does. An empty list falls back through every registered font in registration
+/// order. Lines break at `\n` and are spaced by the first font's line height.
+///
+/// Shaping is what Latin and Cyrillic need: pairs of glyphs from the same font are
+/// kerned by its `kern` table, and combining marks (such as U+0301 in a decomposed
+/// `é`) are drawn over the preceding character, in its font when that has the mark.
+/// There are no ligatures and no complex-script shaping.
+///
+/// # Errors
+///
+/// Returns a `FontError` if `size` is zero or above [`MAX_SIDE`], a font in `fonts`
+/// isn't registered, or `fonts` is empty and no font is.
+pub fn draw_text(
+    img: &mut RgbaImage,
+    text: &str,
+    fonts: &[&str],
+    x: i64,
+    y: i64,
+    size: u32,
+    color: [u8; 4],
+) -> Result<(), FontError> {
+    check_size(size)?;
+    let chain = resolve(fonts)?;
+    let layout = Layout::new(text, &chain, size);
+    for (index, glyph) in layout.glyphs {
+        let Some(outline) = chain.get(index).and_then(|font| font.outline_glyph(glyph)) else {
+            continue;
+        };
+        let bounds = outline.px_bounds();
+        let (left, top) = (x + to_offset(bounds.min.x), y + to_offset(bounds.min.y));
+        outline.draw(|gx, gy, coverage| {
+            let (Ok(ix), Ok(iy)) = (
+                u32::try_from(left + i64::from(gx)),
+                u32::try_from(top + i64::from(gy)),
+            ) else {
+                return;
+            };
+            if let Some(pixel) = img.get_pixel_mut_checked(ix, iy) {
+                pixel.0 = composite::over(pixel.0, color, f64::from(coverage));
+            }
+        });
+    }
+    Ok(())
+}
+
+/// Decodes `input`, draws `text` on it with [`draw_text`], and encodes the result as
+/// `target`.
+///
+/// # Errors
+///
+/// Returns a `FontError` if `size` is out of range, a font isn't registered, the input
+/// cannot be decoded, or the output cannot be encoded.
+// Mirrors `draw_text`, with the encoding settings after it.
+#[allow(clippy::too_many_arguments)]
+pub fn render_text(
+    input: &[u8],
+    text: &str,
+    fonts: &[&str],
+    x: i64,
+    y: i64,
+    size: u32,
+    color: [u8; 4],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, FontError> {
+    check_size(size)?;
+    let mut img = image::load_from_memory(input)
+        .map_err(FontError::Decode)?
+        .into_rgba8();
+    draw_text(&mut img, text, fonts, x, y, size, color)?;
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(FontError::Convert)
+}
+
+/// Rejects a zero size, and sizes above [`MAX_SIDE`] whose glyphs would be larger
+/// than any image the crate makes.
+fn check_size(size: u32) -> Result<(), FontError> {
+    if (1..=MAX_SIDE).contains(&size) {
+        Ok(())
+    } else {
+        Err(FontError::InvalidSize(size))
+    }
+}
+
+/// The registered fonts named in `names`, in order, or every registered font if
+/// `names` is empty.
+fn resolve(names: &[&str]) -> Result<Vec<FontArc>, FontError> {
+    FONTS.with(|fonts| {
+        let fonts = fonts.borrow();
+        if names.is_empty() {
+            return match fonts.as_slice() {
+                [] => Err(FontError::NoFonts),
+                all => Ok(all.iter().map(|(_, font)| font.clone()).collect()),
+            };
+        }
+        names
+            .iter()
+            .map(|name| {
+                fonts
+                    .iter()
+                    .find(|(registered, _)| registered.eq_ignore_ascii_case(name.trim()))
+                    .map(|(_, font)| font.clone())
+                    .ok_or_else(|| FontError::UnknownFont((*name).to_owned()))
+            })
+            .collect()
+    })
+}
+
+/// Glyphs positioned relative to the top-left corner of the text, each with the index
+/// of its font in the fallback chain.
+struct Layout {
+    glyphs: Vec<(usize, Glyph)>,
+    width: f32,
+    height: f32,
+}
+
+impl Layout {
+    fn new(text: &str, chain: &[FontArc], size: u32) -> Self {
+        // Every font is scaled to the same size per em, rather than the same height.
+        let scale = |font: &FontArc| {
+            let em = font.units_per_em().unwrap_or(1000.0);
+            PxScale::from(to_f32(size) * font.height_unscaled() / em)
+        };
+        let Some(primary) = chain.first().map(|font| font.as_scaled(scale(font))) else {
+            return Self {
+                glyphs: Vec::new(),
+                width: 0.0,
+                height: 0.0,
+            };
+        };
+        let line_height = primary.height() + primary.line_gap();
+
+        let mut glyphs = Vec::new();
+        let mut width: f32 = 0.0;
+        let mut lines = 0u32;
+        for line in text.split('\n') {
+            let baseline = primary.ascent() + to_f32(lines) * line_height;
+            lines += 1;
+            let mut pen = 0.0;
+            // Font index and glyph of the previous character, for kerning and marks.
+            let mut previous: Option<(usize, GlyphId)> = None;
+            for c in line.chars().filter(|c| !c.is_control()) {
+                let mark = COMBINING_MARKS
+                    .iter()
+                    .any(|&(first, last)| (first..=last).contains(&c));
+                let covering = |index: &usize| {
+                    chain
+                        .get(*index)
+                        .is_some_and(|font| font.glyph_id(c) != GlyphId(0))
+                };
+                let preferred = previous.map(|(index, _)| index).filter(|_| mark);
+                let index = match preferred
+                    .filter(covering)
+                    .or_else(|| (0..chain.len()).find(covering))
+                {
+                    Some(index) => index,
+                    // A mark no font has is left out rather than drawn as a box.
+                    None if mark => continue,
+                    None => 0,
+                };
+                let Some(font) = chain.get(index) else {
+                    continue;
+                };
+                let scaled = font.as_scaled(scale(font));
+                let id = font.glyph_id(c);
+                // Marks sit where the pen is after their base character; fonts give
+                // them no advance and draw them back over it.
+                if let Some((previous_index, previous_id)) = previous.filter(|_| !mark) {
+                    if previous_index == index {
+                        pen += scaled.kern(previous_id, id);
+                    }
+                }
+                glyphs.push((
+                    index,
+                    id.with_scale_and_position(scaled.scale(), point(pen, baseline)),
+                ));
+                pen += scaled.h_advance(id);
+                if !mark {
+                    previous = Some((index, id));
+                }
+            }
+            width = width.max(pen);
+        }
+        Self {
+            glyphs,
+            width,
+            height: to_f32(lines) * line_height - primary.line_gap(),
+        }
+    }
+}
+
+// Safe: sizes and line counts are far below 2^24, where f32 stops being exact.
+#[allow(clippy::as_conversions)]
+fn to_f32(value: u32) -> f32 {
+    value as f32
+}
+
+// Safe: the value is floored and clamped to i32's range; glyph bounds are small.
+#[allow(clippy::as_conversions)]
+fn to_offset(value: f32) -> i64 {
+    f64::from(value)
+        .floor()
+        .clamp(f64::from(i32::MIN), f64::from(i32::MAX)) as i64
+}
+
+// Safe: the value is rounded up and clamped to u32's range.
+#[allow(clippy::as_conversions)]
+fn to_side(value: f32) -> u32 {
+    f64::from(value).ceil().clamp(0.0, f64::from(u32::MAX)) as u32
+}
+
+/// Errors that can occur while registering or drawing with fonts.
+#[derive(Debug)]
+pub enum FontError {
+    /// A font name was blank or contained a comma.
+    InvalidName(String),
+    /// The data could not be parsed as a TrueType or OpenType font.
+    Invalid(InvalidFont),
+    /// No font is registered under the name.
+    UnknownFont(String),
+    /// No fonts were named and none are registered.
+    NoFonts,
+    /// The size was zero or above [`MAX_SIDE`].
+    InvalidSize(u32),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(
+                f,
+                "Invalid font name {name:?}: names must be non-empty and contain no commas"
+            ),
+            Self::Invalid(e) => write!(f, "Failed to parse font: {e}"),
+            Self::UnknownFont(name) => write!(f, "No font is registered as {name:?}"),
+            Self::NoFonts => write!(f, "No fonts are registered"),
+            Self::InvalidSize(size) => write!(
+                f,
+                "Font size must be between 1 and {MAX_SIDE} pixels, got {size}"
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+
+    /// A 1000-unit-per-em TrueType font mapping `letters` to a 400x500 box with an
+    /// advance of 600, space to an empty glyph with an advance of 300, and U+0301 to a
+    /// mark above the preceding letter. With `kern`, two boxes in a row are kerned
+    /// together by 100 units.
+    fn test_font(letters: (char, char), kern: bool) -> Vec<u8> {
+        /// A single-contour glyph through `points`, as coordinate deltas.
+        fn glyph(points: &[(i16, i16)], bounds: [i16; 4]) -> Vec<u8> {
+            let mut out = Vec::new();
+            out.extend_from_slice(&1i16.to_be_bytes());
+            for value in bounds {
+                out.extend_from_slice(&value.to_be_bytes());
+            }
+            let last = u16::try_from(points.len() - 1).unwrap();
+            out.extend_from_slice(&last.to_be_bytes());
+            out.extend_from_slice(&0u16.to_be_bytes());
+            // On-curve points with two-byte deltas.
+            out.extend(points.iter().map(|_| 1u8));
+            for axis in [0, 1] {
+                let mut at = 0i16;
+                for &(x, y) in points {
+                    let value = if axis == 0 { x } else { y };
+                    out.extend_from_slice(&(value - at).to_be_bytes());
+                    at = value;
+                }
+            }
+            if out.len() % 2 == 1 {
+                out.push(0);
+            }
+            out
+        }
+        // Clockwise rectangle.
+        let rect = |x0: i16, y0: i16, x1: i16, y1: i16| {
+            glyph(&[(x0, y0), (x0, y1), (x1, y1), (x1, y0)], [x0, y0, x1, y1])
+        };
+        // Missing-glyph box, letter box, space, mark.
+        let glyphs = [
+            rect(50, 0, 450, 700),
+            rect(100, 0, 500, 500),
+            Vec::new(),
+            rect(-400, 600, -200, 700),
+        ];
+        let advances: [(u16, i16); 4] = [(500, 50), (600, 100), (300, 0), (0, -400)];
+
+        let mut glyf = Vec::new();
+        let mut loca = Vec::new();
+        for data in &glyphs {
+            loca.extend_from_slice(&u16::try_from(glyf.len() / 2).unwrap().to_be_bytes());
+            glyf.extend_from_slice(data);
+        }
+        loca.extend_from_slice(&u16::try_from(glyf.len() / 2).unwrap().to_be_bytes());
+
+        let mut head = Vec::new();
+        head.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        head.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        head.extend_from_slice(&0u32.to_be_bytes());
+        head.extend_from_slice(&0x5F0F_3CF5u32.to_be_bytes());
+        head.extend_from_slice(&0u16.to_be_bytes());
+        head.extend_from_slice(&1000u16.to_be_bytes());
+        head.extend_from_slice(&[0; 16]);
+        for value in [-400i16, 0, 500, 900] {
+            head.extend_from_slice(&value.to_be_bytes());
+        }
+        // Style, smallest size, direction hint, short `loca`, glyph format.
+        for value in [0i16, 8, 2, 0, 0] {
+            head.extend_from_slice(&value.to_be_bytes());
+        }
+
+        let mut hhea = Vec::new();
+        hhea.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        // Ascender, descender, line gap, then widest advance, extents and caret.
+        for value in [800i16, -200, 100, 600, -400, 0, 500, 1, 0, 0, 0, 0, 0, 0, 0] {
+            hhea.extend_from_slice(&value.to_be_bytes());
+        }
+        hhea.extend_from_slice(&4u16.to_be_bytes());
+
+        let mut maxp = Vec::new();
+        maxp.extend_from_slice(&0x0000_5000u32.to_be_bytes());
+        maxp.extend_from_slice(&4u16.to_be_bytes());
+
+        let mut hmtx = Vec::new();
+        for (advance, bearing) in advances {
+            hmtx.extend_from_slice(&advance.to_be_bytes());
+            hmtx.extend_from_slice(&bearing.to_be_bytes());
+        }
+
+        // Format 12 subtable for Windows Unicode (full repertoire).
+        // One group per letter, since a group maps a range onto consecutive glyphs.
+        let mut groups: Vec<(u32, u32, u32)> = (letters.0..=letters.1)
+            .map(|c| (u32::from(c), u32::from(c), 1))
+            .collect();
+        groups.extend([(u32::from(' '), u32::from(' '), 2), (0x301, 0x301, 3)]);
+        groups.sort_unstable();
+        let mut cmap = Vec::new();
+        for value in [0u16, 1, 3, 10] {
+            cmap.extend_from_slice(&value.to_be_bytes());
+        }
+        cmap.extend_from_slice(&12u32.to_be_bytes());
+        cmap.extend_from_slice(&12u16.to_be_bytes());
+        cmap.extend_from_slice(&0u16.to_be_bytes());
+        let length = u32::try_from(16 + 12 * groups.len()).unwrap();
+        for value in [length, 0, u32::try_from(groups.len()).unwrap()] {
+            cmap.extend_from_slice(&value.to_be_bytes());
+        }
+        for (first, last, glyph) in groups {
+            for value in [first, last, glyph] {
+                cmap.extend_from_slice(&value.to_be_bytes());
+            }
+        }
+
+        let mut tables: Vec<(&[u8; 4], Vec<u8>)> = vec![
+            (b"cmap", cmap),
+            (b"glyf", glyf),
+            (b"head", head),
+            (b"hhea", hhea),
+            (b"hmtx", hmtx),
+            (b"loca", loca),
+            (b"maxp", maxp),
+        ];
+        if kern {
+            let mut table = Vec::new();
+            // Version, one subtable of format 0 with one pair.
+            for value in [0u16, 1, 0, 20, 1, 1, 6, 0, 0, 1, 1] {
+                table.extend_from_slice(&value.to_be_bytes());
+            }
+            table.extend_from_slice(&(-100i16).to_be_bytes());
+            tables.push((b"kern", table));
+            tables.sort_by_key(|(tag, _)| **tag);
+        }
+
+        let count = u16::try_from(tables.len()).unwrap();
+        let mut font = Vec::new();
+        font.extend_from_slice(&0x0001_0000u32.to_be_bytes());
+        for value in [count, 64, 2, count * 16 - 64] {
+            font.extend_from_slice(&value.to_be_bytes());
+        }
+        let mut offset = 12 + 16 * tables.len();
+        let mut data = Vec::new();
+        for (tag, table) in &tables {
+            font.extend_from_slice(*tag);
+            font.extend_from_slice(&0u32.to_be_bytes());
+            font.extend_from_slice(&u32::try_from(offset).unwrap().to_be_bytes());
+            font.extend_from_slice(&u32::try_from(table.len()).unwrap().to_be_bytes());
+            let padded = table.len().div_ceil(4) * 4;
+            data.extend_from_slice(table);
+            data.resize(data.len() + padded - table.len(), 0);
+            offset += padded;
+        }
+        font.extend_from_slice(&data);
+        font
+    }
+
+    /// The pixels text has darkened, on a white background.
+    fn ink(img: &RgbaImage) -> Vec<(u32, u32)> {
+        img.enumerate_pixels()
+            .filter(|(_, _, pixel)| pixel.0[0] < 128)
+            .map(|(x, y, _)| (x, y))
+            .collect()
+    }
+
+    #[test]
+    fn registry_replaces_lists_and_removes_fonts() {
+        register_font("Test Latin", test_font(('A', 'z'), false)).unwrap();
+        register_font("Test Cyrillic", test_font(('\u{410}', '\u{44f}'), false)).unwrap();
+        let info = register_font("test latin", test_font(('A', 'z'), true)).unwrap();
+        assert_eq!(info.glyph_count, 4);
+        assert_eq!(info.scripts, ["latin"]);
+
+        let fonts = list_fonts();
+        let names: Vec<&str> = fonts.iter().map(|font| font.name.as_str()).collect();
+        assert_eq!(names, ["test latin", "Test Cyrillic"]);
+        assert_eq!(fonts[1].scripts, ["cyrillic"]);
+
+        assert!(unregister_font("TEST LATIN"));
+        assert!(!unregister_font("Test Latin"));
+        assert!(matches!(
+            measure_text("A", &["Test Latin"], 10),
+            Err(FontError::UnknownFont(_))
+        ));
+        assert!(matches!(
+            register_font("a, b", test_font(('A', 'z'), false)),
+            Err(FontError::InvalidName(_))
+        ));
+        assert!(matches!(
+            register_font("junk", b"not a font".to_vec()),
+            Err(FontError::Invalid(_))
+        ));
+        assert!(unregister_font("Test Cyrillic"));
+        assert!(matches!(
+            measure_text("A", &[], 10),
+            Err(FontError::NoFonts)
+        ));
+    }
+
+    #[test]
+    fn text_is_kerned_and_falls_back_by_character() {
+        register_font("Latin", test_font(('A', 'z'), true)).unwrap();
+        register_font("Cyrillic", test_font(('\u{410}', '\u{44f}'), false)).unwrap();
+
+        // 100 px per em: each box advances 60 px, less 10 px kerning between two.
+        let size = |text: &str| measure_text(text, &["Latin", "Cyrillic"], 100).unwrap();
+        assert_eq!(size("A").width, 60);
+        assert_eq!(size("AA").width, 110);
+        assert_eq!(size("A A").width, 150);
+        // Cyrillic letters come from the second font, which isn't kerned against the
+        // first: a box of each is two full advances.
+        assert_eq!(size("A\u{416}").width, 120);
+        // Line height is ascent plus descent, with the line gap between lines.
+        assert_eq!(size("A").height, 100);
+        assert_eq!(size("A\nA").height, 210);
+
+        let mut img = RgbaImage::from_pixel(130, 100, Rgba([255; 4]));
+        draw_text(
+            &mut img,
+            "A\u{416}",
+            &["Latin", "Cyrillic"],
+            0,
+            0,
+            100,
+            BLACK,
+        )
+        .unwrap();
+        let pixels = ink(&img);
+        // Boxes span x 10..50 and 70..110, y 30..80 (the baseline is the ascent, 80).
+        assert!(pixels.contains(&(10, 30)) && pixels.contains(&(109, 79)));
+        assert!(!pixels.contains(&(10, 25)));
+        assert!(!pixels.contains(&(60, 40)) && !pixels.contains(&(30, 85)));
+
+        unregister_font("Latin");
+        unregister_font("Cyrillic");
+    }
+
+    #[test]
+    fn combining_marks_sit_over_their_base() {
+        register_font("Marks", test_font(('A', 'z'), false)).unwrap();
+        assert_eq!(measure_text("A\u{301}", &["Marks"], 100).unwrap().width, 60);
+        let mut img = RgbaImage::from_pixel(60, 100, Rgba([255; 4]));
+        draw_text(&mut img, "A\u{301}", &["Marks"], 0, 0, 100, BLACK).unwrap();
+        let pixels = ink(&img);
+        // The mark spans x 20..40 and y 10..20, above the box.
+        assert!(pixels.contains(&(30, 15)) && !pixels.contains(&(45, 15)));
+        assert!(!pixels.contains(&(30, 25)));
+        unregister_font("Marks");
+    }
+
+    #[test]
+    fn sizes_are_checked() {
+        register_font("Sized", test_font(('A', 'z'), false)).unwrap();
+        for size in [0, MAX_SIDE + 1, u32::MAX] {
+            assert!(matches!(
+                measure_text("A", &["Sized"], size),
+                Err(FontError::InvalidSize(s)) if s == size
+            ));
+            let mut img = RgbaImage::new(1, 1);
+            assert!(matches!(
+                draw_text(&mut img, "A", &["Sized"], 0, 0, size, BLACK),
+                Err(FontError::InvalidSize(_))
+            ));
+        }
+        assert!(matches!(
+            render_text(
+                b"junk",
+                "A",
+                &["Sized"],
+                0,
+                0,
+                0,
+                BLACK,
+                ImageFormat::Png,
+                None
+            ),
+            Err(FontError::InvalidSize(0))
+        ));
+        assert!(measure_text("A", &["Sized"], MAX_SIDE).is_ok());
+        unregister_font("Sized");
+    }
+}
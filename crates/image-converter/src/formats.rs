@@ -1,6 +1,12 @@
 use std::fmt;
 
+use wasm_bindgen::prelude::wasm_bindgen;
+
 /// Supported image formats for conversion.
+///
+/// Exported to JS as a numeric enum whose values follow declaration order, so new
+/// formats must be appended.
+#[wasm_bindgen]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ImageFormat {
     Png,
@@ -15,6 +21,19 @@ pub enum ImageFormat {
 }
 
 impl ImageFormat {
+    /// Every format, in the order UIs list them.
+    pub const ALL: [Self; 9] = [
+        Self::Png,
+        Self::Jpeg,
+        Self::WebP,
+        Self::Gif,
+        Self::Bmp,
+        Self::Tiff,
+        Self::Ico,
+        Self::Tga,
+        Self::Qoi,
+    ];
+
     /// Detects the image format from raw bytes by inspecting file headers.
     ///
     /// Returns an error if the format is unrecognized or the input is empty.
@@ -46,12 +65,15 @@ impl ImageFormat {
 
     /// Parses a format name string into an `ImageFormat`.
     ///
-    /// Accepts lowercase names: `"png"`, `"jpeg"`, `"jpg"`, `"webp"`, `"gif"`, `"bmp"`,
-    /// `"tiff"`, `"tif"`, `"ico"`, `"tga"`, `"qoi"`.
+    /// Accepts names in any case: `"png"`, `"jpeg"`, `"jpg"`, `"webp"`, `"gif"`, `"bmp"`,
+    /// `"tiff"`, `"tif"`, `"ico"`, `"tga"`, `"qoi"`, optionally written as an extension
+    /// with a leading dot (`".jpeg"`).
     ///
     /// Returns an error if the string is not a recognized format name.
     pub fn from_name(name: &str) -> Result<Self, FormatError> {
-        match name.to_ascii_lowercase().as_str() {
+        let trimmed = name.trim();
+        let bare = trimmed.strip_prefix('.').unwrap_or(trimmed);
+        match bare.to_ascii_lowercase().as_str() {
             "png" => Ok(Self::Png),
             "jpeg" | "jpg" => Ok(Self::Jpeg),
             "webp" => Ok(Self::WebP),
@@ -69,16 +91,51 @@ impl ImageFormat {
     ///
     /// Returns an error for formats that are decode-only (e.g. WebP).
     pub fn to_image_format(self) -> Result<image::ImageFormat, FormatError> {
+        if self == Self::WebP {
+            return Err(FormatError::EncodeUnsupported(self));
+        }
+        Ok(self.decoder_format())
+    }
+
+    /// Converts to the `image` crate's format type for decoding, which every format
+    /// supports.
+    pub fn decoder_format(self) -> image::ImageFormat {
         match self {
-            Self::Png => Ok(image::ImageFormat::Png),
-            Self::Jpeg => Ok(image::ImageFormat::Jpeg),
-            Self::Gif => Ok(image::ImageFormat::Gif),
-            Self::Bmp => Ok(image::ImageFormat::Bmp),
-            Self::Tiff => Ok(image::ImageFormat::Tiff),
-            Self::Ico => Ok(image::ImageFormat::Ico),
-            Self::Tga => Ok(image::ImageFormat::Tga),
-            Self::Qoi => Ok(image::ImageFormat::Qoi),
-            Self::WebP => Err(FormatError::EncodeUnsupported(self)),
+            Self::Png => image::ImageFormat::Png,
+            Self::Jpeg => image::ImageFormat::Jpeg,
+            Self::WebP => image::ImageFormat::WebP,
+            Self::Gif => image::ImageFormat::Gif,
+            Self::Bmp => image::ImageFormat::Bmp,
+            Self::Tiff => image::ImageFormat::Tiff,
+            Self::Ico => image::ImageFormat::Ico,
+            Self::Tga => image::ImageFormat::Tga,
+            Self::Qoi => image::ImageFormat::Qoi,
+        }
+    }
+
+    /// Whether [`ImageFormat::detect_from_bytes`] can recognize this format. TGA has no
+    /// magic bytes, so conversions only read it when given it as the source format.
+    pub fn is_detectable(self) -> bool {
+        self != Self::Tga
+    }
+
+    /// Whether conversions can produce this format.
+    pub fn can_encode(self) -> bool {
+        self.to_image_format().is_ok()
+    }
+
+    /// The file extension, without a dot, that files of this format usually carry.
+    pub fn preferred_extension(self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::Jpeg => "jpg",
+            Self::WebP => "webp",
+            Self::Gif => "gif",
+            Self::Bmp => "bmp",
+            Self::Tiff => "tiff",
+            Self::Ico => "ico",
+            Self::Tga => "tga",
+            Self::Qoi => "qoi",
         }
     }
 
@@ -98,6 +155,25 @@ impl ImageFormat {
     }
 }
 
+/// Suggests a file name for `original_name` converted to `target`.
+///
+/// An extension naming an image format is replaced by `target`'s preferred one
+/// (`"photo.PNG"` becomes `"photo.jpg"`); any other name keeps its full text, so
+/// `"notes.v2"` becomes `"notes.v2.jpg"`. Directories in the name are kept, and an empty
+/// name becomes `"image"`.
+pub fn suggest_filename(original_name: &str, target: ImageFormat) -> String {
+    let name = original_name.trim();
+    let file = name.rsplit(['/', '\\']).next().unwrap_or(name);
+    let dir = name.strip_suffix(file).unwrap_or_default();
+    let stem = match file.rsplit_once('.') {
+        // A leading dot marks a hidden file, not an extension.
+        Some((stem, ext)) if !stem.is_empty() && ImageFormat::from_name(ext).is_ok() => stem,
+        _ => file,
+    };
+    let stem = if stem.is_empty() { "image" } else { stem };
+    format!("{dir}{stem}.{}", target.preferred_extension())
+}
+
 impl fmt::Display for ImageFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         f.write_str(self.as_str())
@@ -389,6 +465,58 @@ mod tests {
         assert!(matches!(result, Err(FormatError::UnknownName(_))));
     }
 
+    #[test]
+    fn from_name_accepts_extensions() {
+        assert_eq!(ImageFormat::from_name(".jpeg").unwrap(), ImageFormat::Jpeg);
+        assert_eq!(ImageFormat::from_name(" .PNG ").unwrap(), ImageFormat::Png);
+        assert!(ImageFormat::from_name("..png").is_err());
+        assert!(ImageFormat::from_name(".").is_err());
+    }
+
+    // --- Extension tests ---
+    #[test]
+    fn all_follows_js_values() {
+        for (index, value) in ImageFormat::ALL.into_iter().enumerate() {
+            // Safe: reading a fieldless enum's discriminant, which is its JS value.
+            #[allow(clippy::as_conversions)]
+            let discriminant = value as usize;
+            assert_eq!(discriminant, index);
+        }
+    }
+
+    #[test]
+    fn preferred_extensions_parse_back() {
+        for format in ImageFormat::ALL {
+            let ext = format.preferred_extension();
+            assert_eq!(ImageFormat::from_name(ext).unwrap(), format);
+        }
+        assert_eq!(ImageFormat::Jpeg.preferred_extension(), "jpg");
+    }
+
+    #[test]
+    fn suggest_filename_replaces_image_extensions() {
+        let jpeg = ImageFormat::Jpeg;
+        assert_eq!(suggest_filename("photo.PNG", jpeg), "photo.jpg");
+        assert_eq!(suggest_filename("scan.final.tif", jpeg), "scan.final.jpg");
+        assert_eq!(suggest_filename("notes.v2", jpeg), "notes.v2.jpg");
+        assert_eq!(
+            suggest_filename("untitled", ImageFormat::Png),
+            "untitled.png"
+        );
+        assert_eq!(suggest_filename("shots/a.webp", jpeg), "shots/a.jpg");
+        assert_eq!(suggest_filename("C:\\pics\\b.bmp", jpeg), "C:\\pics\\b.jpg");
+        assert_eq!(suggest_filename("dir.png/photo", jpeg), "dir.png/photo.jpg");
+    }
+
+    #[test]
+    fn suggest_filename_handles_bare_names() {
+        let png = ImageFormat::Png;
+        assert_eq!(suggest_filename("", png), "image.png");
+        assert_eq!(suggest_filename("  ", png), "image.png");
+        assert_eq!(suggest_filename("dir/", png), "dir/image.png");
+        assert_eq!(suggest_filename(".gif", png), ".gif.png");
+    }
+
     // --- to_image_format tests ---
 
     #[test]
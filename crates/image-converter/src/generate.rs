@@ -0,0 +1,440 @@
+use std::fmt;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::{Deserialize, Deserializer};
+
+use crate::color;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::text;
+
+/// Longest side a generated image may have.
+pub const MAX_SIDE: u32 = 16_384;
+
+/// Default side of a checkerboard cell, in pixels.
+pub const DEFAULT_CELL_SIZE: u32 = 8;
+
+/// Most characters an avatar shows.
+pub const MAX_AVATAR_INITIALS: usize = 3;
+
+/// Avatar backgrounds: mid-tone colors that white initials read well on.
+const AVATAR_COLORS: [[u8; 4]; 10] = [
+    [0xe5, 0x39, 0x35, 0xff],
+    [0xd8, 0x1b, 0x60, 0xff],
+    [0x8e, 0x24, 0xaa, 0xff],
+    [0x5e, 0x35, 0xb1, 0xff],
+    [0x39, 0x49, 0xab, 0xff],
+    [0x1e, 0x88, 0xe5, 0xff],
+    [0x00, 0x89, 0x7b, 0xff],
+    [0x43, 0xa0, 0x47, 0xff],
+    [0xf4, 0x51, 0x1e, 0xff],
+    [0x6d, 0x4c, 0x41, 0xff],
+];
+
+/// What fills a generated image. Colors are hex strings, as for
+/// [`color::parse_color`], when deserialized.
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Fill {
+    /// One color everywhere.
+    Solid {
+        #[serde(deserialize_with = "hex_color")]
+        color: [u8; 4],
+    },
+    /// A straight blend from `from` to `to`. `angle` is the direction of travel in
+    /// degrees clockwise from left-to-right, so 90 runs top to bottom.
+    LinearGradient {
+        #[serde(deserialize_with = "hex_color")]
+        from: [u8; 4],
+        #[serde(deserialize_with = "hex_color")]
+        to: [u8; 4],
+        #[serde(default)]
+        angle: f64,
+    },
+    /// A blend from `inner` at the center to `outer` at the farthest corner.
+    RadialGradient {
+        #[serde(deserialize_with = "hex_color")]
+        inner: [u8; 4],
+        #[serde(deserialize_with = "hex_color")]
+        outer: [u8; 4],
+    },
+    /// Alternating `cell_size` squares of `a` and `b`, starting with `a` at the
+    /// top-left.
+    Checkerboard {
+        #[serde(deserialize_with = "hex_color")]
+        a: [u8; 4],
+        #[serde(deserialize_with = "hex_color")]
+        b: [u8; 4],
+        #[serde(default = "default_cell_size")]
+        cell_size: u32,
+    },
+    /// Per-pixel random blends between `from` and `to`. The same `seed` always gives
+    /// the same pixels.
+    Noise {
+        #[serde(default)]
+        seed: u64,
+        #[serde(deserialize_with = "hex_color", default = "black")]
+        from: [u8; 4],
+        #[serde(deserialize_with = "hex_color", default = "white")]
+        to: [u8; 4],
+    },
+}
+
+fn hex_color<'de, D: Deserializer<'de>>(deserializer: D) -> Result<[u8; 4], D::Error> {
+    let text = String::deserialize(deserializer)?;
+    color::parse_color(&text).map_err(serde::de::Error::custom)
+}
+
+fn default_cell_size() -> u32 {
+    DEFAULT_CELL_SIZE
+}
+
+fn black() -> [u8; 4] {
+    [0, 0, 0, u8::MAX]
+}
+
+fn white() -> [u8; 4] {
+    [u8::MAX; 4]
+}
+
+impl Fill {
+    /// Renders the fill at `width` x `height`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `GenerateError::InvalidSize` if either side is zero or above
+    /// [`MAX_SIDE`], and `GenerateError::InvalidCellSize` for a zero checkerboard cell.
+    pub fn render(&self, width: u32, height: u32) -> Result<RgbaImage, GenerateError> {
+        if width == 0 || height == 0 || width > MAX_SIDE || height > MAX_SIDE {
+            return Err(GenerateError::InvalidSize { width, height });
+        }
+        if let Self::Checkerboard { cell_size: 0, .. } = self {
+            return Err(GenerateError::InvalidCellSize);
+        }
+
+        let (w, h) = (f64::from(width), f64::from(height));
+        Ok(match *self {
+            Self::Solid { color } => RgbaImage::from_pixel(width, height, Rgba(color)),
+            Self::LinearGradient { from, to, angle } => {
+                let (dy, dx) = angle.to_radians().sin_cos();
+                // Project pixel centers onto the direction, relative to the center, and
+                // scale so the corners that lead and trail reach exactly 0 and 1.
+                let reach = (w * dx.abs() + h * dy.abs()) / 2.0;
+                RgbaImage::from_fn(width, height, |x, y| {
+                    let px = f64::from(x) + 0.5 - w / 2.0;
+                    let py = f64::from(y) + 0.5 - h / 2.0;
+                    let t = 0.5 + (px * dx + py * dy) / (2.0 * reach);
+                    Rgba(color::lerp(from, to, t))
+                })
+            }
+            Self::RadialGradient { inner, outer } => {
+                let radius = w.hypot(h) / 2.0;
+                RgbaImage::from_fn(width, height, |x, y| {
+                    let px = f64::from(x) + 0.5 - w / 2.0;
+                    let py = f64::from(y) + 0.5 - h / 2.0;
+                    Rgba(color::lerp(inner, outer, px.hypot(py) / radius))
+                })
+            }
+            Self::Checkerboard { a, b, cell_size } => RgbaImage::from_fn(width, height, |x, y| {
+                if (x / cell_size + y / cell_size).is_multiple_of(2) {
+                    Rgba(a)
+                } else {
+                    Rgba(b)
+                }
+            }),
+            Self::Noise { seed, from, to } => RgbaImage::from_fn(width, height, |x, y| {
+                let index = u64::from(y) * u64::from(width) + u64::from(x);
+                Rgba(color::lerp(
+                    from,
+                    to,
+                    unit(splitmix64(seed ^ splitmix64(index))),
+                ))
+            }),
+        })
+    }
+}
+
+/// One round of SplitMix64: a fast, well-mixed hash, so noise depends only on the seed
+/// and position and not on the order pixels are visited.
+pub fn splitmix64(value: u64) -> u64 {
+    let mut z = value.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Maps a random `u64` to `0.0..=1.0`.
+pub fn unit(value: u64) -> f64 {
+    // The top 32 bits are exact in an f64.
+    f64::from(u32::try_from(value >> 32).unwrap_or(u32::MAX)) / f64::from(u32::MAX)
+}
+
+/// What to generate: a size and a [`Fill`].
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GenerateSpec {
+    pub width: u32,
+    pub height: u32,
+    pub fill: Fill,
+}
+
+/// Renders `spec` and encodes it as `target`.
+///
+/// # Errors
+///
+/// Returns a `GenerateError` if the size or fill is invalid, or the image cannot be
+/// encoded.
+pub fn generate(
+    spec: &GenerateSpec,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, GenerateError> {
+    let img = spec.fill.render(spec.width, spec.height)?;
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(GenerateError::Convert)
+}
+
+/// Renders `initials` (uppercased, at most [`MAX_AVATAR_INITIALS`] characters, with
+/// whitespace dropped) centered on a `size` x `size` square, for users without a
+/// profile photo. The background comes from a fixed palette, picked by hashing
+/// `seed` (such as a user ID) so the same user always gets the same color.
+///
+/// # Errors
+///
+/// Returns `GenerateError::InvalidSize` if `size` is zero or above [`MAX_SIDE`].
+pub fn avatar(initials: &str, size: u32, seed: &str) -> Result<RgbaImage, GenerateError> {
+    let background = seed
+        .bytes()
+        .fold(0, |hash, byte| splitmix64(hash ^ u64::from(byte)));
+    let index = usize::try_from(background % 10).unwrap_or(0);
+    let color = AVATAR_COLORS.get(index).copied().unwrap_or(black());
+    let mut img = Fill::Solid { color }.render(size, size)?;
+
+    let initials: String = initials
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .flat_map(char::to_uppercase)
+        .take(MAX_AVATAR_INITIALS)
+        .collect();
+    // Capitals 40% of the side tall, narrowed until they fit with some margin.
+    let mut height = size * 2 / 5;
+    while height > 1 && text::measure(&initials, height).0 > size * 4 / 5 {
+        height -= 1;
+    }
+    let (width, _) = text::measure(&initials, height);
+    let x = i64::from((size - width.min(size)) / 2);
+    let y = i64::from((size - height) / 2);
+    text::draw_text(&mut img, &initials, x, y, height, white());
+    Ok(img)
+}
+
+/// Renders an [`avatar`] and encodes it as `target`.
+///
+/// # Errors
+///
+/// Returns a `GenerateError` if the size is invalid or the image cannot be encoded.
+pub fn generate_avatar(
+    initials: &str,
+    size: u32,
+    seed: &str,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, GenerateError> {
+    let img = avatar(initials, size, seed)?;
+    convert::encode(&DynamicImage::ImageRgba8(img), target, quality).map_err(GenerateError::Convert)
+}
+
+/// Errors that can occur while generating images.
+#[derive(Debug)]
+pub enum GenerateError {
+    /// A side was zero or above [`MAX_SIDE`].
+    InvalidSize { width: u32, height: u32 },
+    /// A checkerboard's cells were zero pixels wide.
+    InvalidCellSize,
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for GenerateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize { width, height } => write!(
+                f,
+                "Image size must be between 1x1 and {MAX_SIDE}x{MAX_SIDE}, got {width}x{height}"
+            ),
+            Self::InvalidCellSize => write!(f, "Checkerboard cell size must be at least 1"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for GenerateError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    #[test]
+    fn linear_gradients_follow_the_angle() {
+        let across = Fill::LinearGradient {
+            from: RED,
+            to: BLUE,
+            angle: 0.0,
+        }
+        .render(100, 10)
+        .unwrap();
+        assert!(across.get_pixel(0, 5).0[0] > 250);
+        assert!(across.get_pixel(99, 5).0[2] > 250);
+        assert_eq!(across.get_pixel(10, 0), across.get_pixel(10, 9));
+
+        let down = Fill::LinearGradient {
+            from: RED,
+            to: BLUE,
+            angle: 90.0,
+        }
+        .render(10, 100)
+        .unwrap();
+        assert!(down.get_pixel(5, 0).0[0] > 250);
+        assert!(down.get_pixel(5, 99).0[2] > 250);
+        assert_eq!(down.get_pixel(0, 10), down.get_pixel(9, 10));
+    }
+
+    #[test]
+    fn radial_gradients_run_from_the_center() {
+        let img = Fill::RadialGradient {
+            inner: [255; 4],
+            outer: [0, 0, 0, 255],
+        }
+        .render(51, 51)
+        .unwrap();
+        assert_eq!(img.get_pixel(25, 25).0, [255; 4]);
+        assert!(img.get_pixel(0, 0).0[0] < 10);
+        assert_eq!(img.get_pixel(0, 25), img.get_pixel(25, 0));
+    }
+
+    #[test]
+    fn checkerboard_cells() {
+        let img = Fill::Checkerboard {
+            a: RED,
+            b: BLUE,
+            cell_size: 4,
+        }
+        .render(16, 16)
+        .unwrap();
+        assert_eq!(img.get_pixel(3, 3).0, RED);
+        assert_eq!(img.get_pixel(4, 3).0, BLUE);
+        assert_eq!(img.get_pixel(4, 4).0, RED);
+    }
+
+    #[test]
+    fn noise_is_deterministic_per_seed() {
+        let noise = |seed| {
+            Fill::Noise {
+                seed,
+                from: black(),
+                to: white(),
+            }
+            .render(32, 32)
+            .unwrap()
+        };
+        assert_eq!(noise(7), noise(7));
+        assert_ne!(noise(7), noise(8));
+        let img = noise(7);
+        let distinct: std::collections::BTreeSet<u8> = img.pixels().map(|p| p.0[0]).collect();
+        assert!(distinct.len() > 100, "{}", distinct.len());
+    }
+
+    #[test]
+    fn specs_deserialize_with_defaults() {
+        let spec: GenerateSpec = serde_json::from_str(
+            r##"{ "width": 4, "height": 2, "fill": { "kind": "checkerboard", "a": "#f00", "b": "#00f" } }"##,
+        )
+        .unwrap();
+        assert_eq!(
+            spec.fill,
+            Fill::Checkerboard {
+                a: RED,
+                b: BLUE,
+                cell_size: DEFAULT_CELL_SIZE,
+            }
+        );
+        let bad = r##"{ "width": 4, "height": 2, "fill": { "kind": "solid", "color": "red" } }"##;
+        assert!(serde_json::from_str::<GenerateSpec>(bad).is_err());
+    }
+
+    #[test]
+    fn generates_encoded_images() {
+        let spec = GenerateSpec {
+            width: 3,
+            height: 2,
+            fill: Fill::Solid { color: RED },
+        };
+        let png = generate(&spec, ImageFormat::Png, None).unwrap();
+        let img = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert_eq!(img.dimensions(), (3, 2));
+        assert_eq!(img.get_pixel(2, 1).0, RED);
+    }
+
+    #[test]
+    fn avatars_center_initials_on_a_seeded_color() {
+        let img = avatar(" ada  l ", 70, "user-42").unwrap();
+        assert_eq!(img.dimensions(), (70, 70));
+        let background = img.get_pixel(0, 0).0;
+        assert!(AVATAR_COLORS.contains(&background));
+        assert_eq!(
+            avatar("x", 8, "user-42").unwrap().get_pixel(0, 0).0,
+            background
+        );
+
+        // "ADA" is shrunk to fit within 80% of the width, and centered.
+        let ink: Vec<(u32, u32)> = img
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p.0 == [255; 4])
+            .map(|(x, y, _)| (x, y))
+            .collect();
+        let left = ink.iter().map(|p| p.0).min().unwrap();
+        let right = ink.iter().map(|p| p.0).max().unwrap();
+        let top = ink.iter().map(|p| p.1).min().unwrap();
+        let bottom = ink.iter().map(|p| p.1).max().unwrap();
+        assert!((50..=56).contains(&(right - left)), "{left}..{right}");
+        assert!((left + right).abs_diff(69) <= 2, "{left}..{right}");
+        assert!((top + bottom).abs_diff(69) <= 2, "{top}..{bottom}");
+    }
+
+    #[test]
+    fn avatar_colors_vary_by_seed() {
+        let colors: std::collections::BTreeSet<[u8; 4]> = (0..50)
+            .map(|i| {
+                avatar("A", 4, &format!("user-{i}"))
+                    .unwrap()
+                    .get_pixel(0, 0)
+                    .0
+            })
+            .collect();
+        assert!(colors.len() > 5, "{colors:?}");
+    }
+
+    #[test]
+    fn rejects_invalid_sizes() {
+        let solid = Fill::Solid { color: RED };
+        assert!(matches!(
+            solid.render(0, 5),
+            Err(GenerateError::InvalidSize { .. })
+        ));
+        assert!(matches!(
+            solid.render(MAX_SIDE + 1, 5),
+            Err(GenerateError::InvalidSize { .. })
+        ));
+        let board = Fill::Checkerboard {
+            a: RED,
+            b: BLUE,
+            cell_size: 0,
+        };
+        assert!(matches!(
+            board.render(4, 4),
+            Err(GenerateError::InvalidCellSize)
+        ));
+    }
+}
@@ -0,0 +1,225 @@
+use std::fmt;
+use std::fmt::Write;
+
+use image::RgbaImage;
+use sha2::{Digest, Sha256};
+
+/// Prefix hashed ahead of the pixels, so pixel hashes never collide with hashes of
+/// other data and the layout can be versioned.
+const PIXEL_HASH_DOMAIN: &[u8] = b"image-converter/pixel-hash/v1\0";
+
+/// Prefix hashed ahead of derivation keys; see [`PIXEL_HASH_DOMAIN`].
+const DERIVATION_KEY_DOMAIN: &[u8] = b"image-converter/derivation-key/v1\0";
+
+/// Decodes `input` and hashes its pixels with [`hash_pixels`].
+///
+/// The container, encoder settings and metadata don't matter, so the same picture
+/// saved losslessly as PNG, WebP, BMP or TIFF hashes identically. Animated inputs
+/// hash their first frame.
+///
+/// # Errors
+///
+/// Returns `HashError::Decode` if the input cannot be decoded.
+pub fn pixel_hash(input: &[u8]) -> Result<String, HashError> {
+    let img = image::load_from_memory(input)
+        .map_err(HashError::Decode)?
+        .into_rgba8();
+    Ok(hash_pixels(&img))
+}
+
+/// SHA-256 of an image's dimensions and RGBA8 pixels, as 64 lowercase hex digits.
+///
+/// Fully transparent pixels are hashed as transparent black, since encoders are free
+/// to change their hidden RGB values.
+pub fn hash_pixels(img: &RgbaImage) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(PIXEL_HASH_DOMAIN);
+    hasher.update(img.width().to_be_bytes());
+    hasher.update(img.height().to_be_bytes());
+    for px in img.pixels() {
+        if px.0[3] == 0 {
+            hasher.update([0; 4]);
+        } else {
+            hasher.update(px.0);
+        }
+    }
+    to_hex(&hasher.finalize())
+}
+
+/// A stable cache key for an image derived from a source by a pipeline of operations,
+/// as 64 lowercase hex digits. Suitable for CDN and object-store keys.
+///
+/// The key covers the source hash (e.g. from [`pixel_hash`] or a byte hash; compared
+/// case-insensitively), the operations and this crate's version, so upgrading the
+/// crate invalidates derived images whose bytes may have changed. `ops_json` is any
+/// JSON value describing the pipeline; it is re-serialized with sorted object keys
+/// and no whitespace first, so formatting and key order don't matter. Array order
+/// does, as it is the order the operations run in.
+///
+/// # Errors
+///
+/// Returns `HashError::EmptyInputHash` if `input_hash` is blank and
+/// `HashError::InvalidOps` if `ops_json` is not valid JSON.
+pub fn derivation_key(input_hash: &str, ops_json: &str) -> Result<String, HashError> {
+    let input_hash = input_hash.trim().to_ascii_lowercase();
+    if input_hash.is_empty() {
+        return Err(HashError::EmptyInputHash);
+    }
+    // serde_json's default map type keeps object keys sorted, so re-serializing gives
+    // a canonical form.
+    let ops: serde_json::Value = serde_json::from_str(ops_json).map_err(HashError::InvalidOps)?;
+
+    let mut hasher = Sha256::new();
+    hasher.update(DERIVATION_KEY_DOMAIN);
+    for field in [
+        env!("CARGO_PKG_VERSION"),
+        input_hash.as_str(),
+        ops.to_string().as_str(),
+    ] {
+        // Length-prefixed so no field can run into the next.
+        hasher.update(u64::try_from(field.len()).unwrap_or(u64::MAX).to_be_bytes());
+        hasher.update(field);
+    }
+    Ok(to_hex(&hasher.finalize()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    let mut hex = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        // Writing to a String cannot fail.
+        let _ = write!(hex, "{byte:02x}");
+    }
+    hex
+}
+
+/// Errors that can occur while hashing images.
+#[derive(Debug)]
+pub enum HashError {
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// The source hash for a derivation key was empty.
+    EmptyInputHash,
+    /// The operation pipeline was not valid JSON.
+    InvalidOps(serde_json::Error),
+}
+
+impl fmt::Display for HashError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::EmptyInputHash => write!(f, "Input hash must not be empty"),
+            Self::InvalidOps(e) => write!(f, "Operations are not valid JSON: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for HashError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, Rgba};
+
+    use super::*;
+
+    fn patterned(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            let x = u8::try_from(x % 256).unwrap();
+            let y = u8::try_from(y % 256).unwrap();
+            Rgba([x.wrapping_mul(37), y.wrapping_mul(53), x ^ y, 255 - (x & 7)])
+        })
+    }
+
+    fn encode(img: &RgbaImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut buf = Vec::new();
+        DynamicImage::ImageRgba8(img.clone())
+            .write_to(&mut Cursor::new(&mut buf), format)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn same_pixels_hash_identically_across_formats() {
+        let img = patterned(24, 17);
+        let expected = hash_pixels(&img);
+        assert_eq!(expected.len(), 64);
+        assert!(expected.bytes().all(|b| b.is_ascii_hexdigit()));
+        for format in [
+            image::ImageFormat::Png,
+            image::ImageFormat::WebP,
+            image::ImageFormat::Tiff,
+            image::ImageFormat::Qoi,
+        ] {
+            let encoded = encode(&img, format);
+            assert_eq!(pixel_hash(&encoded).unwrap(), expected, "{format:?}");
+        }
+    }
+
+    #[test]
+    fn different_pixels_or_shape_change_the_hash() {
+        let img = patterned(8, 8);
+        let mut changed = img.clone();
+        changed.put_pixel(3, 3, Rgba([1, 2, 3, 255]));
+        assert_ne!(hash_pixels(&img), hash_pixels(&changed));
+
+        // Same bytes, different shape.
+        let wide = RgbaImage::from_raw(16, 4, img.as_raw().clone()).unwrap();
+        assert_ne!(hash_pixels(&img), hash_pixels(&wide));
+    }
+
+    #[test]
+    fn hidden_colors_are_ignored() {
+        let a = RgbaImage::from_pixel(2, 2, Rgba([255, 0, 0, 0]));
+        let b = RgbaImage::from_pixel(2, 2, Rgba([0, 0, 255, 0]));
+        assert_eq!(hash_pixels(&a), hash_pixels(&b));
+    }
+
+    #[test]
+    fn derivation_key_ignores_json_formatting() {
+        let key =
+            derivation_key("ABC123", r#"[{"op":"resize","width":64},{"op":"webp"}]"#).unwrap();
+        assert_eq!(key.len(), 64);
+        let reformatted = derivation_key(
+            " abc123 ",
+            "[ { \"width\": 64, \"op\": \"resize\" },\n  { \"op\": \"webp\" } ]",
+        )
+        .unwrap();
+        assert_eq!(key, reformatted);
+    }
+
+    #[test]
+    fn derivation_key_depends_on_every_input() {
+        let key = derivation_key("abc", r#"["grayscale","invert"]"#).unwrap();
+        assert_ne!(
+            key,
+            derivation_key("abd", r#"["grayscale","invert"]"#).unwrap()
+        );
+        assert_ne!(
+            key,
+            derivation_key("abc", r#"["invert","grayscale"]"#).unwrap()
+        );
+        // Field boundaries are unambiguous.
+        assert_ne!(
+            derivation_key("ab", r#""c""#).unwrap(),
+            derivation_key("a", r#""bc""#).unwrap()
+        );
+    }
+
+    #[test]
+    fn derivation_key_rejects_bad_input() {
+        assert!(matches!(
+            derivation_key("  ", "[]"),
+            Err(HashError::EmptyInputHash)
+        ));
+        assert!(matches!(
+            derivation_key("abc", "[resize"),
+            Err(HashError::InvalidOps(_))
+        ));
+    }
+
+    #[test]
+    fn undecodable_input_fails() {
+        assert!(matches!(pixel_hash(&[1, 2, 3]), Err(HashError::Decode(_))));
+    }
+}
@@ -0,0 +1,182 @@
+//! ICO directories: the sizes an icon file holds, and picking which one to decode.
+//!
+//! The `image` crate's ICO decoder picks the entry with the most bits per pixel, then
+//! the largest. PNG entries often record 0 bits per pixel, so it can pass over a
+//! 256×256 PNG for a 32×32 bitmap. Conversions choose the entry here instead.
+
+use serde::Serialize;
+
+use crate::formats::ImageFormat;
+
+/// One size stored in an ICO file, as its directory describes it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct IconSize {
+    pub width: u32,
+    pub height: u32,
+    /// Bits per pixel, as the directory records it; PNG entries often leave it 0.
+    pub bits_per_pixel: u16,
+    /// Whether the entry is stored as PNG rather than as a bitmap.
+    pub png: bool,
+}
+
+/// A directory entry and where its image data lies.
+struct Entry<'a> {
+    size: IconSize,
+    /// The 12 bytes of the directory entry before the data offset.
+    header: &'a [u8],
+    data: &'a [u8],
+}
+
+/// The sizes in an ICO file, in directory order. Empty for anything else.
+pub fn sizes(input: &[u8]) -> Vec<IconSize> {
+    entries(input).iter().map(|entry| entry.size).collect()
+}
+
+/// Index of the size to decode: the smallest whose longer side is at least `size`
+/// pixels, or without `size` (or when none is that large) the largest. Ties go to the
+/// entry with more bits per pixel. `None` if there are no sizes.
+pub fn select(sizes: &[IconSize], size: Option<u32>) -> Option<usize> {
+    let area = |s: &IconSize| u64::from(s.width) * u64::from(s.height);
+    let largest = sizes
+        .iter()
+        .enumerate()
+        .max_by_key(|&(_, s)| (area(s), s.bits_per_pixel))
+        .map(|(index, _)| index);
+    let Some(size) = size else {
+        return largest;
+    };
+    sizes
+        .iter()
+        .enumerate()
+        .filter(|&(_, s)| s.width.max(s.height) >= size)
+        .min_by_key(|&(_, s)| (area(s), std::cmp::Reverse(s.bits_per_pixel)))
+        .map(|(index, _)| index)
+        .or(largest)
+}
+
+/// The entry at `index` as a standalone file: PNG entries as they are, bitmap entries
+/// as a single-image ICO file.
+pub fn extract(input: &[u8], index: usize) -> Option<Vec<u8>> {
+    let entries = entries(input);
+    let entry = entries.get(index)?;
+    if entry.size.png {
+        return Some(entry.data.to_vec());
+    }
+    let mut icon = vec![0, 0, 1, 0, 1, 0];
+    icon.extend(entry.header);
+    icon.extend(22u32.to_le_bytes());
+    icon.extend(entry.data);
+    Some(icon)
+}
+
+/// The entry of a multi-size ICO file that [`select`] picks for `size`, as a
+/// standalone file. `None` for other inputs and single-size icons, which decode as
+/// they are.
+pub fn select_entry(input: &[u8], size: Option<u32>) -> Option<Vec<u8>> {
+    let sizes = sizes(input);
+    if sizes.len() < 2 {
+        return None;
+    }
+    extract(input, select(&sizes, size)?)
+}
+
+fn entries(input: &[u8]) -> Vec<Entry<'_>> {
+    if !matches!(ImageFormat::detect_from_bytes(input), Ok(ImageFormat::Ico)) {
+        return Vec::new();
+    }
+    let count = input
+        .get(4..6)
+        .and_then(|bytes| bytes.try_into().ok())
+        .map_or(0, u16::from_le_bytes);
+    (0..usize::from(count))
+        .filter_map(|index| {
+            let entry = input.get(6 + index * 16..6 + (index + 1) * 16)?;
+            let field = |at: usize| {
+                let bytes = entry.get(at..at + 4)?.try_into().ok()?;
+                usize::try_from(u32::from_le_bytes(bytes)).ok()
+            };
+            let (length, start) = (field(8)?, field(12)?);
+            let data = input.get(start..start.checked_add(length)?)?;
+            // A stored 0 means 256.
+            let side = |at: usize| {
+                entry
+                    .get(at)
+                    .map(|&v| if v == 0 { 256 } else { u32::from(v) })
+            };
+            Some(Entry {
+                size: IconSize {
+                    width: side(0)?,
+                    height: side(1)?,
+                    bits_per_pixel: u16::from_le_bytes(entry.get(6..8)?.try_into().ok()?),
+                    png: data.starts_with(b"\x89PNG"),
+                },
+                header: entry.get(..12)?,
+                data,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use image::codecs::ico::{IcoEncoder, IcoFrame};
+    use image::ExtendedColorType;
+
+    use super::*;
+    use crate::convert::{self, ConvertOptions};
+
+    /// An icon with a PNG entry per side, filled with a gray level equal to the side.
+    fn icon(sides: &[u32]) -> Vec<u8> {
+        let frames: Vec<IcoFrame> = sides
+            .iter()
+            .map(|&side| {
+                let level = u8::try_from(side).unwrap_or(255);
+                let pixels = vec![level; usize::try_from(side * side).unwrap()];
+                IcoFrame::as_png(&pixels, side, side, ExtendedColorType::L8).unwrap()
+            })
+            .collect();
+        let mut ico = Vec::new();
+        IcoEncoder::new(&mut ico).encode_images(&frames).unwrap();
+        ico
+    }
+
+    #[test]
+    fn selects_the_largest_or_the_smallest_large_enough() {
+        let size = |side, bits_per_pixel| IconSize {
+            width: side,
+            height: side,
+            bits_per_pixel,
+            png: false,
+        };
+        let sizes = [size(32, 32), size(256, 0), size(48, 8), size(48, 32)];
+        assert_eq!(select(&sizes, None), Some(1));
+        assert_eq!(select(&sizes, Some(40)), Some(3));
+        assert_eq!(select(&sizes, Some(16)), Some(0));
+        assert_eq!(select(&sizes, Some(512)), Some(1));
+        assert_eq!(select(&[], None), None);
+    }
+
+    #[test]
+    fn conversions_decode_the_selected_size() {
+        let ico = icon(&[16, 64, 32]);
+        assert_eq!(
+            sizes(&ico).iter().map(|s| s.width).collect::<Vec<_>>(),
+            [16, 64, 32]
+        );
+
+        let decoded = |icon_size| {
+            let options = ConvertOptions {
+                icon_size,
+                ..ConvertOptions::default()
+            };
+            let png = convert::convert_with_options(ico.clone(), ImageFormat::Png, &options, &[])
+                .unwrap();
+            image::load_from_memory(&png).unwrap().into_luma8()
+        };
+        assert_eq!(decoded(None).width(), 64);
+        let small = decoded(Some(20));
+        assert_eq!((small.width(), small.get_pixel(0, 0).0), (32, [32]));
+        assert_eq!(convert::dimensions(&ico).unwrap().width, 64);
+        assert_eq!(select_entry(&icon(&[16]), None), None);
+    }
+}
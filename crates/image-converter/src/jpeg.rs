@@ -0,0 +1,3051 @@
+//! Coefficient-level access to JPEG files.
+//!
+//! `image::load_from_memory` always runs the inverse DCT, so any operation routed
+//! through it re-quantizes the pixels and loses quality. This module instead parses
+//! sequential Huffman-coded JPEGs down to their quantized DCT coefficients and can
+//! write them back out unchanged, as sequential or progressive files, which is what
+//! the lossless operations in
+//! [`crate::jpeg_lossless`] build on.
+//!
+//! Supported inputs are 8-bit baseline and extended sequential files (SOF0/SOF1) with
+//! one or more scans and optional restart intervals. Progressive, lossless,
+//! hierarchical and arithmetic-coded files are rejected with [`JpegError::Unsupported`],
+//! except that [`first_scan_preview`] and [`decode_dc`] read the DC scans of
+//! progressive files.
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use image::{Rgba, RgbaImage};
+
+const MARKER_SOF0: u8 = 0xC0;
+const MARKER_SOF1: u8 = 0xC1;
+const MARKER_SOF2: u8 = 0xC2;
+const MARKER_DHT: u8 = 0xC4;
+const MARKER_DAC: u8 = 0xCC;
+const MARKER_SOI: u8 = 0xD8;
+const MARKER_EOI: u8 = 0xD9;
+const MARKER_SOS: u8 = 0xDA;
+const MARKER_DQT: u8 = 0xDB;
+const MARKER_DRI: u8 = 0xDD;
+const MARKER_COM: u8 = 0xFE;
+const MARKER_APP0: u8 = 0xE0;
+const MARKER_APP1: u8 = 0xE1;
+
+/// Start of an APP1 segment holding EXIF data; the TIFF structure follows.
+const EXIF_HEADER: &[u8] = b"Exif\0\0";
+
+/// EXIF/TIFF tag of the orientation field.
+const TAG_ORIENTATION: u16 = 0x0112;
+
+/// TIFF field type of an unsigned 16-bit value.
+const TIFF_SHORT: u16 = 3;
+
+/// Largest payload a marker segment can carry (the 16-bit length includes itself).
+const MAX_SEGMENT_PAYLOAD: usize = 65533;
+
+/// Natural (row-major) coefficient index for each zigzag scan position.
+#[rustfmt::skip]
+const UNZIGZAG: [usize; 64] = [
+     0,  1,  8, 16,  9,  2,  3, 10,
+    17, 24, 32, 25, 18, 11,  4,  5,
+    12, 19, 26, 33, 40, 48, 41, 34,
+    27, 20, 13,  6,  7, 14, 21, 28,
+    35, 42, 49, 56, 57, 50, 43, 36,
+    29, 22, 15, 23, 30, 37, 44, 51,
+    58, 59, 52, 45, 38, 31, 39, 46,
+    53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+// Standard Huffman tables from ITU T.81 Annex K.3 (bit counts per code length, then symbols).
+const STD_DC_LUMA_COUNTS: [u8; 16] = [0, 1, 5, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0];
+const STD_DC_CHROMA_COUNTS: [u8; 16] = [0, 3, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0, 0, 0, 0, 0];
+const STD_DC_VALUES: [u8; 12] = [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11];
+
+const STD_AC_LUMA_COUNTS: [u8; 16] = [0, 2, 1, 3, 3, 2, 4, 3, 5, 5, 4, 4, 0, 0, 1, 0x7D];
+#[rustfmt::skip]
+const STD_AC_LUMA_VALUES: [u8; 162] = [
+    0x01, 0x02, 0x03, 0x00, 0x04, 0x11, 0x05, 0x12, 0x21, 0x31, 0x41, 0x06, 0x13, 0x51, 0x61, 0x07,
+    0x22, 0x71, 0x14, 0x32, 0x81, 0x91, 0xA1, 0x08, 0x23, 0x42, 0xB1, 0xC1, 0x15, 0x52, 0xD1, 0xF0,
+    0x24, 0x33, 0x62, 0x72, 0x82, 0x09, 0x0A, 0x16, 0x17, 0x18, 0x19, 0x1A, 0x25, 0x26, 0x27, 0x28,
+    0x29, 0x2A, 0x34, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x49,
+    0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68, 0x69,
+    0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x83, 0x84, 0x85, 0x86, 0x87, 0x88, 0x89,
+    0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5, 0xA6, 0xA7,
+    0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3, 0xC4, 0xC5,
+    0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA, 0xE1, 0xE2,
+    0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF1, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+const STD_AC_CHROMA_COUNTS: [u8; 16] = [0, 2, 1, 2, 4, 4, 3, 4, 7, 5, 4, 4, 0, 1, 2, 0x77];
+#[rustfmt::skip]
+const STD_AC_CHROMA_VALUES: [u8; 162] = [
+    0x00, 0x01, 0x02, 0x03, 0x11, 0x04, 0x05, 0x21, 0x31, 0x06, 0x12, 0x41, 0x51, 0x07, 0x61, 0x71,
+    0x13, 0x22, 0x32, 0x81, 0x08, 0x14, 0x42, 0x91, 0xA1, 0xB1, 0xC1, 0x09, 0x23, 0x33, 0x52, 0xF0,
+    0x15, 0x62, 0x72, 0xD1, 0x0A, 0x16, 0x24, 0x34, 0xE1, 0x25, 0xF1, 0x17, 0x18, 0x19, 0x1A, 0x26,
+    0x27, 0x28, 0x29, 0x2A, 0x35, 0x36, 0x37, 0x38, 0x39, 0x3A, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48,
+    0x49, 0x4A, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x59, 0x5A, 0x63, 0x64, 0x65, 0x66, 0x67, 0x68,
+    0x69, 0x6A, 0x73, 0x74, 0x75, 0x76, 0x77, 0x78, 0x79, 0x7A, 0x82, 0x83, 0x84, 0x85, 0x86, 0x87,
+    0x88, 0x89, 0x8A, 0x92, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99, 0x9A, 0xA2, 0xA3, 0xA4, 0xA5,
+    0xA6, 0xA7, 0xA8, 0xA9, 0xAA, 0xB2, 0xB3, 0xB4, 0xB5, 0xB6, 0xB7, 0xB8, 0xB9, 0xBA, 0xC2, 0xC3,
+    0xC4, 0xC5, 0xC6, 0xC7, 0xC8, 0xC9, 0xCA, 0xD2, 0xD3, 0xD4, 0xD5, 0xD6, 0xD7, 0xD8, 0xD9, 0xDA,
+    0xE2, 0xE3, 0xE4, 0xE5, 0xE6, 0xE7, 0xE8, 0xE9, 0xEA, 0xF2, 0xF3, 0xF4, 0xF5, 0xF6, 0xF7, 0xF8,
+    0xF9, 0xFA,
+];
+
+// Example quantization tables from ITU T.81 Annex K.1/K.2 (natural order), the base
+// that IJG-style encoders scale by quality.
+#[rustfmt::skip]
+const STD_LUMA_QUANT: [u16; 64] = [
+    16, 11, 10, 16,  24,  40,  51,  61,
+    12, 12, 14, 19,  26,  58,  60,  55,
+    14, 13, 16, 24,  40,  57,  69,  56,
+    14, 17, 22, 29,  51,  87,  80,  62,
+    18, 22, 37, 56,  68, 109, 103,  77,
+    24, 35, 55, 64,  81, 104, 113,  92,
+    49, 64, 78, 87, 103, 121, 120, 101,
+    72, 92, 95, 98, 112, 100, 103,  99,
+];
+#[rustfmt::skip]
+const STD_CHROMA_QUANT: [u16; 64] = [
+    17, 18, 24, 47, 99, 99, 99, 99,
+    18, 21, 26, 66, 99, 99, 99, 99,
+    24, 26, 56, 99, 99, 99, 99, 99,
+    47, 66, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+    99, 99, 99, 99, 99, 99, 99, 99,
+];
+
+/// One 8x8 block of quantized DCT coefficients in natural (row-major) order:
+/// index `row * 8 + col`, where `row` is the vertical and `col` the horizontal frequency.
+pub type Block = [i16; 64];
+
+/// Quantization tables by slot (0-3), values in natural (row-major) order.
+pub type QuantTables = [Option<[u16; 64]>; 4];
+
+/// A marker segment carried through unchanged (APPn metadata and COM comments).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Segment {
+    pub marker: u8,
+    pub data: Vec<u8>,
+}
+
+/// One color component of a frame and its coefficient blocks.
+#[derive(Debug, Clone)]
+pub struct Component {
+    pub id: u8,
+    /// Horizontal sampling factor (1-4).
+    pub h: u8,
+    /// Vertical sampling factor (1-4).
+    pub v: u8,
+    /// Quantization table slot (0-3).
+    pub quant_table: u8,
+    /// Width of the block grid, padded out to whole MCUs.
+    pub blocks_w: usize,
+    /// Height of the block grid, padded out to whole MCUs.
+    pub blocks_h: usize,
+    /// Blocks in row-major order, `blocks_w * blocks_h` entries.
+    pub blocks: Vec<Block>,
+}
+
+impl Component {
+    pub fn block(&self, x: usize, y: usize) -> Option<&Block> {
+        if x >= self.blocks_w {
+            return None;
+        }
+        self.blocks
+            .get(y.checked_mul(self.blocks_w)?.checked_add(x)?)
+    }
+
+    pub fn block_mut(&mut self, x: usize, y: usize) -> Option<&mut Block> {
+        if x >= self.blocks_w {
+            return None;
+        }
+        let index = y.checked_mul(self.blocks_w)?.checked_add(x)?;
+        self.blocks.get_mut(index)
+    }
+}
+
+/// A sequential JPEG decoded down to quantized DCT coefficients.
+#[derive(Debug, Clone)]
+pub struct JpegImage {
+    pub width: u16,
+    pub height: u16,
+    pub components: Vec<Component>,
+    pub quant_tables: QuantTables,
+    /// APPn and COM segments in file order, written back before the frame header.
+    pub segments: Vec<Segment>,
+}
+
+impl JpegImage {
+    /// Parses a baseline or extended sequential JPEG into coefficient blocks.
+    ///
+    /// # Errors
+    ///
+    /// Returns `JpegError::NotJpeg` if the SOI marker is missing,
+    /// `JpegError::Unsupported` for progressive/lossless/arithmetic files, and
+    /// `JpegError::Corrupt` for malformed segments or entropy-coded data.
+    pub fn decode(input: &[u8]) -> Result<Self, JpegError> {
+        if input.get(..2) != Some(&[0xFF, MARKER_SOI]) {
+            return Err(JpegError::NotJpeg);
+        }
+
+        let mut frame: Option<Self> = None;
+        let mut quant_tables: QuantTables = [None; 4];
+        let mut segments = Vec::new();
+        let mut dc_tables: [Option<HuffDecoder>; 4] = Default::default();
+        let mut ac_tables: [Option<HuffDecoder>; 4] = Default::default();
+        let mut restart_interval = 0u16;
+        let mut pos = 2;
+
+        while let Some((marker, marker_end)) = next_marker(input, pos) {
+            if marker == MARKER_EOI {
+                break;
+            }
+            if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+                pos = marker_end;
+                continue;
+            }
+
+            let length = read_u16(input, marker_end)
+                .ok_or_else(|| JpegError::Corrupt("truncated segment length".to_string()))?;
+            let payload = usize::from(length)
+                .checked_sub(2)
+                .and_then(|len| input.get(marker_end + 2..marker_end + 2 + len))
+                .ok_or_else(|| JpegError::Corrupt("truncated segment".to_string()))?;
+            pos = marker_end + 2 + payload.len();
+
+            match marker {
+                MARKER_SOF0 | MARKER_SOF1 => {
+                    if frame.is_some() {
+                        return Err(JpegError::Corrupt("multiple frame headers".to_string()));
+                    }
+                    let mut image = parse_frame_header(payload)?;
+                    image.allocate_blocks()?;
+                    frame = Some(image);
+                }
+                MARKER_SOF2 => return Err(JpegError::Unsupported("progressive JPEG".to_string())),
+                0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | MARKER_DAC => {
+                    return Err(JpegError::Unsupported(
+                        "lossless, hierarchical or arithmetic-coded JPEG".to_string(),
+                    ))
+                }
+                MARKER_DHT => parse_huffman_tables(payload, &mut dc_tables, &mut ac_tables)?,
+                MARKER_DQT => parse_quant_tables(payload, &mut quant_tables)?,
+                MARKER_DRI => {
+                    restart_interval = read_u16(payload, 0)
+                        .ok_or_else(|| JpegError::Corrupt("truncated DRI".to_string()))?;
+                }
+                MARKER_SOS => {
+                    let image = frame.as_mut().ok_or_else(|| {
+                        JpegError::Corrupt("scan before frame header".to_string())
+                    })?;
+                    let scan = parse_scan_header(payload, image)?;
+                    if scan.spectral != (0, 63) || scan.approximation != 0 {
+                        return Err(JpegError::Unsupported(
+                            "spectral selection or successive approximation".to_string(),
+                        ));
+                    }
+                    let end = entropy_end(input, pos);
+                    let data = input.get(pos..end).unwrap_or_default();
+                    decode_scan(image, &scan, data, &dc_tables, &ac_tables, restart_interval)?;
+                    pos = end;
+                }
+                0xE0..=0xEF | MARKER_COM => segments.push(Segment {
+                    marker,
+                    data: payload.to_vec(),
+                }),
+                _ => {}
+            }
+        }
+
+        let mut image = frame.ok_or_else(|| JpegError::Corrupt("no frame header".to_string()))?;
+        for component in &image.components {
+            let defined = quant_tables
+                .get(usize::from(component.quant_table))
+                .is_some_and(Option::is_some);
+            if !defined {
+                return Err(JpegError::Corrupt(
+                    "component references an undefined quantization table".to_string(),
+                ));
+            }
+        }
+        image.quant_tables = quant_tables;
+        image.segments = segments;
+        Ok(image)
+    }
+
+    /// Writes the coefficients back out as a baseline JPEG with the standard
+    /// Huffman tables, a single interleaved scan and no restart markers.
+    ///
+    /// APPn and COM segments are written in their original order.
+    ///
+    /// # Errors
+    ///
+    /// Returns `JpegError::Corrupt` if a coefficient is too large to be represented
+    /// with the standard tables or a component references a missing table.
+    pub fn encode(&self) -> Result<Vec<u8>, JpegError> {
+        let tables = [
+            HuffPair {
+                dc: HuffTable::new(&STD_DC_LUMA_COUNTS, &STD_DC_VALUES),
+                ac: HuffTable::new(&STD_AC_LUMA_COUNTS, &STD_AC_LUMA_VALUES),
+            },
+            HuffPair {
+                dc: HuffTable::new(&STD_DC_CHROMA_COUNTS, &STD_DC_VALUES),
+                ac: HuffTable::new(&STD_AC_CHROMA_COUNTS, &STD_AC_CHROMA_VALUES),
+            },
+        ];
+        self.encode_with_tables(&tables)
+    }
+
+    /// Like [`JpegImage::encode`], but with Huffman tables built from this image's own
+    /// symbol statistics (T.81 Annex K.2), which usually saves 5-10% over the standard
+    /// tables. The coefficients, and therefore the decoded pixels, are unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `JpegError::Corrupt` if a coefficient is out of range or a component
+    /// references a missing table.
+    pub fn encode_optimized(&self) -> Result<Vec<u8>, JpegError> {
+        let mut counter = SymbolCounter {
+            counts: [[0; 256]; 4],
+        };
+        self.entropy_code(&mut counter)?;
+        let [dc_luma, ac_luma, dc_chroma, ac_chroma] = &counter.counts;
+        let tables = [
+            HuffPair {
+                dc: HuffTable::optimal(dc_luma),
+                ac: HuffTable::optimal(ac_luma),
+            },
+            HuffPair {
+                dc: HuffTable::optimal(dc_chroma),
+                ac: HuffTable::optimal(ac_chroma),
+            },
+        ];
+        self.encode_with_tables(&tables)
+    }
+
+    /// Runs every block through `sink` in the order of a single interleaved scan,
+    /// with the first component on the luma tables and the rest on the chroma tables.
+    fn entropy_code<S: EntropySink>(&self, sink: &mut S) -> Result<(), JpegError> {
+        let slots: Vec<usize> = (0..self.components.len()).collect();
+        let geometry = ScanGeometry::new(self, &slots)?;
+        let mut predictions = vec![0i32; self.components.len()];
+        geometry.walk(|_, slot, x, y| {
+            let block = self
+                .components
+                .get(slot)
+                .and_then(|component| component.block(x, y))
+                .ok_or_else(|| JpegError::Corrupt("block outside component grid".to_string()))?;
+            let prediction = predictions
+                .get_mut(slot)
+                .ok_or_else(|| JpegError::Corrupt("component index out of range".to_string()))?;
+            encode_block(sink, block, prediction, usize::from(slot > 0))
+        })
+    }
+
+    /// Like [`JpegImage::encode_optimized`], but written as a progressive JPEG
+    /// (like `jpegtran -progressive`): a DC scan of all components, then for each
+    /// component its AC coefficients in spectral bands, luma in two (1-5, 6-63) and
+    /// chroma in one, each with its own optimized Huffman table. Runs of blocks with
+    /// nothing left in a band are coded together, which is where the savings over
+    /// sequential coding come from. Successive approximation isn't used, so every
+    /// coefficient is written exactly once and the decoded pixels are unchanged.
+    ///
+    /// # Errors
+    ///
+    /// Returns `JpegError::Corrupt` if a coefficient is out of range or a component
+    /// references a missing table.
+    pub fn encode_progressive(&self) -> Result<Vec<u8>, JpegError> {
+        let mut out = self.frame_headers(true)?;
+        let all: Vec<usize> = (0..self.components.len()).collect();
+        out = self.write_progressive_scan(out, &all, (0, 0))?;
+        for slot in all {
+            let bands: &[(u8, u8)] = if slot == 0 {
+                &[(1, 5), (6, 63)]
+            } else {
+                &[(1, 63)]
+            };
+            for &band in bands {
+                out = self.write_progressive_scan(out, &[slot], band)?;
+            }
+        }
+        out.extend_from_slice(&[0xFF, MARKER_EOI]);
+        Ok(out)
+    }
+
+    /// Appends one scan of a progressive JPEG to `out`: the components at `slots`, for
+    /// the coefficients in `band` (zigzag order, inclusive), preceded by Huffman tables
+    /// optimized for just this scan.
+    fn write_progressive_scan(
+        &self,
+        mut out: Vec<u8>,
+        slots: &[usize],
+        band: (u8, u8),
+    ) -> Result<Vec<u8>, JpegError> {
+        let mut counter = SymbolCounter {
+            counts: [[0; 256]; 4],
+        };
+        self.progressive_code(&mut counter, slots, band)?;
+        let tables = counter.counts.each_ref().map(HuffTable::optimal);
+
+        // The DC scan uses table 0 for luma and 1 for chroma; AC scans hold a single
+        // component and always use table 0.
+        let is_dc = band.0 == 0;
+        let mut dht = Vec::new();
+        for (index, table) in (0u8..).zip(&tables) {
+            let (class, slot) = (index % 2, index / 2);
+            let used = if is_dc {
+                class == 0 && (slot == 0 || slots.len() > 1)
+            } else {
+                class == 1 && slot == 0
+            };
+            if used {
+                table.write_dht(&mut dht, (class << 4) | slot);
+            }
+        }
+        push_segment(&mut out, MARKER_DHT, &dht)?;
+
+        let count = u8::try_from(slots.len())
+            .map_err(|_| JpegError::Unsupported("too many components".to_string()))?;
+        let mut sos = vec![count];
+        for &slot in slots {
+            let component = self
+                .components
+                .get(slot)
+                .ok_or_else(|| JpegError::Corrupt("component index out of range".to_string()))?;
+            let table = if is_dc { u8::from(slot > 0) << 4 } else { 0 };
+            sos.extend_from_slice(&[component.id, table]);
+        }
+        sos.extend_from_slice(&[band.0, band.1, 0]);
+        push_segment(&mut out, MARKER_SOS, &sos)?;
+
+        let mut entropy = EntropyWriter {
+            writer: BitWriter::new(out),
+            encoders: tables.each_ref().map(HuffTable::encoder),
+        };
+        self.progressive_code(&mut entropy, slots, band)?;
+        Ok(entropy.writer.finish())
+    }
+
+    /// Runs the blocks of one progressive scan through `sink` (see
+    /// [`JpegImage::write_progressive_scan`]).
+    fn progressive_code<S: EntropySink>(
+        &self,
+        sink: &mut S,
+        slots: &[usize],
+        (start, end): (u8, u8),
+    ) -> Result<(), JpegError> {
+        let geometry = ScanGeometry::new(self, slots)?;
+        let mut predictions = vec![0i32; self.components.len()];
+        let mut eob_run = 0u32;
+        geometry.walk(|_, slot, x, y| {
+            let block = self
+                .components
+                .get(slot)
+                .and_then(|component| component.block(x, y))
+                .ok_or_else(|| JpegError::Corrupt("block outside component grid".to_string()))?;
+            if start == 0 {
+                let prediction = predictions.get_mut(slot).ok_or_else(|| {
+                    JpegError::Corrupt("component index out of range".to_string())
+                })?;
+                let table = TableClass::Dc(usize::from(slot > 0));
+                encode_dc(sink, i32::from(block[0]), prediction, table)
+            } else {
+                encode_ac_band(sink, block, start, end, &mut eob_run)
+            }
+        })?;
+        flush_eob_run(sink, &mut eob_run)
+    }
+
+    /// SOI, the APPn and COM segments in their original order, the quantization tables
+    /// and the frame header: progressive (SOF2), or sequential (SOF0, or SOF1 when a
+    /// table needs 16-bit entries).
+    fn frame_headers(&self, progressive: bool) -> Result<Vec<u8>, JpegError> {
+        if self.components.is_empty() || self.components.len() > 4 {
+            return Err(JpegError::Unsupported(format!(
+                "{} components in one scan",
+                self.components.len()
+            )));
+        }
+
+        let mut out = vec![0xFF, MARKER_SOI];
+        for segment in &self.segments {
+            push_segment(&mut out, segment.marker, &segment.data)?;
+        }
+
+        let mut extended = false;
+        let mut dqt = Vec::new();
+        for (slot, table) in (0u8..).zip(&self.quant_tables) {
+            let Some(table) = table else {
+                continue;
+            };
+            let wide = table.iter().any(|&q| q > 255);
+            extended |= wide;
+            dqt.push((u8::from(wide) << 4) | slot);
+            for &index in &UNZIGZAG {
+                let value = table.get(index).copied().unwrap_or(1);
+                if wide {
+                    dqt.extend_from_slice(&value.to_be_bytes());
+                } else {
+                    dqt.push(u8::try_from(value).unwrap_or(u8::MAX));
+                }
+            }
+        }
+        push_segment(&mut out, MARKER_DQT, &dqt)?;
+
+        let mut sof = vec![8];
+        sof.extend_from_slice(&self.height.to_be_bytes());
+        sof.extend_from_slice(&self.width.to_be_bytes());
+        sof.push(component_count(self)?);
+        for component in &self.components {
+            sof.extend_from_slice(&[
+                component.id,
+                (component.h << 4) | component.v,
+                component.quant_table,
+            ]);
+        }
+        let sof_marker = if progressive {
+            MARKER_SOF2
+        } else if extended {
+            MARKER_SOF1
+        } else {
+            MARKER_SOF0
+        };
+        push_segment(&mut out, sof_marker, &sof)?;
+        Ok(out)
+    }
+
+    fn encode_with_tables(&self, tables: &[HuffPair; 2]) -> Result<Vec<u8>, JpegError> {
+        let mut out = self.frame_headers(false)?;
+
+        let used_tables = if self.components.len() == 1 { 1 } else { 2 };
+        let mut dht = Vec::new();
+        for (slot, pair) in (0u8..).zip(tables.iter().take(used_tables)) {
+            pair.dc.write_dht(&mut dht, slot);
+            pair.ac.write_dht(&mut dht, 0x10 | slot);
+        }
+        push_segment(&mut out, MARKER_DHT, &dht)?;
+
+        let mut sos = vec![component_count(self)?];
+        for (index, component) in self.components.iter().enumerate() {
+            let slot = u8::from(index > 0);
+            sos.extend_from_slice(&[component.id, (slot << 4) | slot]);
+        }
+        sos.extend_from_slice(&[0, 63, 0]);
+        push_segment(&mut out, MARKER_SOS, &sos)?;
+
+        let mut entropy = EntropyWriter {
+            writer: BitWriter::new(out),
+            encoders: [
+                tables[0].dc.encoder(),
+                tables[0].ac.encoder(),
+                tables[1].dc.encoder(),
+                tables[1].ac.encoder(),
+            ],
+        };
+        self.entropy_code(&mut entropy)?;
+
+        let mut out = entropy.writer.finish();
+        out.extend_from_slice(&[0xFF, MARKER_EOI]);
+        Ok(out)
+    }
+
+    /// Largest horizontal and vertical sampling factors across all components.
+    pub fn max_sampling(&self) -> (u8, u8) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (h, v)
+    }
+
+    /// MCU size in pixels. Single-component images are coded one block at a time.
+    pub fn mcu_size(&self) -> (u32, u32) {
+        if self.components.len() == 1 {
+            return (8, 8);
+        }
+        let (h, v) = self.max_sampling();
+        (8 * u32::from(h), 8 * u32::from(v))
+    }
+
+    /// Number of blocks that cover the component's actual samples (excluding MCU padding).
+    pub fn visible_blocks(&self, component: &Component) -> (usize, usize) {
+        let (max_h, max_v) = self.max_sampling();
+        let samples_w = (usize::from(self.width) * usize::from(component.h))
+            .div_ceil(usize::from(max_h.max(1)));
+        let samples_h = (usize::from(self.height) * usize::from(component.v))
+            .div_ceil(usize::from(max_v.max(1)));
+        (samples_w.div_ceil(8), samples_h.div_ceil(8))
+    }
+
+    /// Number of MCUs across and down the image.
+    fn mcu_grid(&self) -> (usize, usize) {
+        let (mcu_w, mcu_h) = self.mcu_size();
+        (
+            usize::from(self.width).div_ceil(usize::try_from(mcu_w).unwrap_or(8)),
+            usize::from(self.height).div_ceil(usize::try_from(mcu_h).unwrap_or(8)),
+        )
+    }
+
+    /// Resizes every component's block grid for the current dimensions and sampling
+    /// factors, filling it with zeroed blocks.
+    pub fn allocate_blocks(&mut self) -> Result<(), JpegError> {
+        let (mcus_x, mcus_y) = self.mcu_grid();
+        let single = self.components.len() == 1;
+        for component in &mut self.components {
+            let (h, v) = if single {
+                (1, 1)
+            } else {
+                (usize::from(component.h), usize::from(component.v))
+            };
+            component.blocks_w = mcus_x * h;
+            component.blocks_h = mcus_y * v;
+            let count = component
+                .blocks_w
+                .checked_mul(component.blocks_h)
+                .ok_or_else(|| JpegError::Corrupt("image too large".to_string()))?;
+            component.blocks = vec![[0; 64]; count];
+        }
+        Ok(())
+    }
+}
+
+/// Estimated encoder quality of a JPEG, derived from its quantization tables.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+pub struct QualityEstimate {
+    /// IJG-scale quality (1-100) whose scaled standard tables best match the file's.
+    pub quality: u8,
+    /// True when the file's tables are exactly the standard tables scaled to `quality`
+    /// (libjpeg, image-rs and most camera/editor defaults); false means the encoder
+    /// used custom tables and `quality` is the nearest equivalent.
+    pub exact: bool,
+}
+
+/// Estimates the quality setting a JPEG was saved with.
+///
+/// Only the headers are read: the luma (and, for color images, chroma) quantization
+/// tables are compared against the Annex K tables scaled with the libjpeg quality
+/// formula for every quality from 1 to 100, and the closest match wins. Works for
+/// progressive and other coding modes too, since no entropy-coded data is touched.
+///
+/// # Errors
+///
+/// Returns `JpegError::NotJpeg` if the SOI marker is missing and `JpegError::Corrupt`
+/// if there is no frame header or a referenced quantization table is missing.
+pub fn estimate_quality(input: &[u8]) -> Result<QualityEstimate, JpegError> {
+    let (quant_tables, table_slots) = read_header_tables(input)?;
+    let table = |slot: Option<&u8>| {
+        slot.and_then(|&slot| quant_tables.get(usize::from(slot)))
+            .copied()
+            .flatten()
+    };
+    let luma = table(table_slots.first())
+        .ok_or_else(|| JpegError::Corrupt("missing luma quantization table".to_string()))?;
+    let chroma = table(table_slots.get(1));
+
+    let mut best = QualityEstimate {
+        quality: 100,
+        exact: false,
+    };
+    let mut best_error = u32::MAX;
+    // Walk from high to low so ties resolve to the higher quality.
+    for quality in (1..=100u8).rev() {
+        let mut error = table_distance(&luma, &scaled_table(&STD_LUMA_QUANT, quality));
+        if let Some(chroma) = &chroma {
+            error += table_distance(chroma, &scaled_table(&STD_CHROMA_QUANT, quality));
+        }
+        if error < best_error {
+            best_error = error;
+            best = QualityEstimate {
+                quality,
+                exact: error == 0,
+            };
+        }
+    }
+    Ok(best)
+}
+
+/// Standard table scaled with the libjpeg quality formula (baseline-clamped to 1-255).
+fn scaled_table(base: &[u16; 64], quality: u8) -> [u16; 64] {
+    let quality = u32::from(quality.clamp(1, 100));
+    let scale = if quality < 50 {
+        5000 / quality
+    } else {
+        200 - quality * 2
+    };
+    base.map(|value| {
+        let scaled = ((u32::from(value) * scale + 50) / 100).clamp(1, 255);
+        u16::try_from(scaled).unwrap_or(255)
+    })
+}
+
+fn table_distance(a: &[u16; 64], b: &[u16; 64]) -> u32 {
+    a.iter()
+        .zip(b)
+        .map(|(&x, &y)| u32::from(x.abs_diff(y)))
+        .sum()
+}
+
+/// Reads quantization tables and the per-component table slots from the headers,
+/// stopping at the first scan.
+fn read_header_tables(input: &[u8]) -> Result<(QuantTables, Vec<u8>), JpegError> {
+    let (segments, _) = header_segments(input)?;
+    let mut quant_tables = [None; 4];
+    let mut table_slots = None;
+    for segment in &segments {
+        match segment.marker {
+            MARKER_DQT => parse_quant_tables(segment.payload, &mut quant_tables)?,
+            // Every SOFn except DHT (C4), JPG (C8) and DAC (CC).
+            marker @ 0xC0..=0xCF if !matches!(marker, MARKER_DHT | 0xC8 | MARKER_DAC) => {
+                let count = usize::from(segment.payload.get(5).copied().unwrap_or(0));
+                let specs = segment
+                    .payload
+                    .get(6..6 + count * 3)
+                    .ok_or_else(|| JpegError::Corrupt("truncated frame header".to_string()))?;
+                table_slots = Some(
+                    specs
+                        .chunks_exact(3)
+                        .filter_map(|c| c.get(2).copied())
+                        .collect(),
+                );
+            }
+            _ => {}
+        }
+    }
+    let table_slots =
+        table_slots.ok_or_else(|| JpegError::Corrupt("no frame header".to_string()))?;
+    Ok((quant_tables, table_slots))
+}
+
+/// Text of every COM (comment) segment in the file, in order.
+///
+/// Only the headers are read, so this works for any JPEG coding mode. Comments are
+/// decoded as UTF-8, with invalid bytes replaced.
+///
+/// # Errors
+///
+/// Returns `JpegError::NotJpeg` if the SOI marker is missing and `JpegError::Corrupt`
+/// if a header segment is truncated.
+pub fn comments(input: &[u8]) -> Result<Vec<String>, JpegError> {
+    let (segments, _) = header_segments(input)?;
+    Ok(segments
+        .iter()
+        .filter(|segment| segment.marker == MARKER_COM)
+        .map(|segment| String::from_utf8_lossy(segment.payload).into_owned())
+        .collect())
+}
+
+/// Whether the file is progressive: its frame header is SOF2, or the arithmetic or
+/// differential progressive SOF6, SOF10 or SOF14.
+///
+/// Only the headers are read.
+///
+/// # Errors
+///
+/// Returns `JpegError::NotJpeg` if the SOI marker is missing and `JpegError::Corrupt`
+/// if a header segment is truncated.
+pub fn is_progressive(input: &[u8]) -> Result<bool, JpegError> {
+    let (segments, _) = header_segments(input)?;
+    Ok(segments
+        .iter()
+        .any(|segment| matches!(segment.marker, MARKER_SOF2 | 0xC6 | 0xCA | 0xCE)))
+}
+
+/// Rebuilds a progressive JPEG at 1/8 scale from the DC coefficients of its first
+/// scans, without reading the AC and refinement scans that carry the detail: each pixel
+/// is the average of one 8x8 block. Reading stops at the first scan that isn't a DC
+/// first pass; components not reached by then are left neutral (mid-gray luma, no
+/// chroma).
+///
+/// # Errors
+///
+/// Returns `JpegError::NotJpeg` if the SOI marker is missing, `JpegError::Unsupported`
+/// for sequential files and anything but gray or YCbCr, and `JpegError::Corrupt` for
+/// malformed segments or entropy-coded data.
+pub fn first_scan_preview(input: &[u8]) -> Result<RgbaImage, JpegError> {
+    let (image, quant_tables, planes) = dc_planes(input, false)?;
+    render_dc(&image, &quant_tables, &planes)
+}
+
+/// Decodes a JPEG at 1/8 scale from its DC coefficients alone, skipping the inverse
+/// DCT: each pixel is the average of one 8x8 block, so the result is
+/// `ceil(width / 8)` x `ceil(height / 8)`. Sequential files are entropy-decoded
+/// without keeping their AC coefficients; progressive files are read up to the end of
+/// their DC first pass, as in [`first_scan_preview`].
+///
+/// # Errors
+///
+/// Returns `JpegError::NotJpeg` if the SOI marker is missing, `JpegError::Unsupported`
+/// for lossless, hierarchical and arithmetic-coded files, anything but gray or YCbCr,
+/// and progressive files whose DC first pass leaves out a component, and
+/// `JpegError::Corrupt` for malformed segments or entropy-coded data.
+pub fn decode_dc(input: &[u8]) -> Result<RgbaImage, JpegError> {
+    let (image, quant_tables, planes) = dc_planes(input, true)?;
+    if planes.iter().any(Option::is_none) {
+        return Err(JpegError::Unsupported(
+            "DC first pass doesn't cover every component".to_string(),
+        ));
+    }
+    render_dc(&image, &quant_tables, &planes)
+}
+
+/// One DC coefficient per block of each component's MCU-padded grid, for the
+/// components the scans read so far have covered.
+type DcPlanes = Vec<Option<Vec<i32>>>;
+
+/// The frame, quantization tables and per-component DC planes (see [`decode_dc_scan`])
+/// of a progressive JPEG's DC first pass, or with `sequential`, of every scan of a
+/// sequential one.
+fn dc_planes(
+    input: &[u8],
+    sequential: bool,
+) -> Result<(JpegImage, QuantTables, DcPlanes), JpegError> {
+    if input.get(..2) != Some(&[0xFF, MARKER_SOI]) {
+        return Err(JpegError::NotJpeg);
+    }
+
+    let mut frame: Option<JpegImage> = None;
+    let mut quant_tables: QuantTables = [None; 4];
+    let mut dc_tables: [Option<HuffDecoder>; 4] = Default::default();
+    let mut ac_tables: [Option<HuffDecoder>; 4] = Default::default();
+    let mut restart_interval = 0u16;
+    let mut planes: DcPlanes = Vec::new();
+    let mut progressive = false;
+    let mut pos = 2;
+
+    while let Some((marker, marker_end)) = next_marker(input, pos) {
+        if marker == MARKER_EOI {
+            break;
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            pos = marker_end;
+            continue;
+        }
+
+        let length = read_u16(input, marker_end)
+            .ok_or_else(|| JpegError::Corrupt("truncated segment length".to_string()))?;
+        let payload = usize::from(length)
+            .checked_sub(2)
+            .and_then(|len| input.get(marker_end + 2..marker_end + 2 + len))
+            .ok_or_else(|| JpegError::Corrupt("truncated segment".to_string()))?;
+        pos = marker_end + 2 + payload.len();
+
+        match marker {
+            MARKER_SOF0 | MARKER_SOF1 if !sequential => {
+                return Err(JpegError::Unsupported(
+                    "sequential JPEG has no first-pass preview".to_string(),
+                ))
+            }
+            MARKER_SOF0 | MARKER_SOF1 | MARKER_SOF2 => {
+                if frame.is_some() {
+                    return Err(JpegError::Corrupt("multiple frame headers".to_string()));
+                }
+                progressive = marker == MARKER_SOF2;
+                let image = parse_frame_header(payload)?;
+                if !matches!(image.components.len(), 1 | 3) {
+                    return Err(JpegError::Unsupported(format!(
+                        "{} components in a preview",
+                        image.components.len()
+                    )));
+                }
+                planes = vec![None; image.components.len()];
+                frame = Some(image);
+            }
+            0xC3 | 0xC5..=0xC7 | 0xC9..=0xCB | 0xCD..=0xCF | MARKER_DAC => {
+                return Err(JpegError::Unsupported(
+                    "lossless, hierarchical or arithmetic-coded JPEG".to_string(),
+                ))
+            }
+            MARKER_DHT => parse_huffman_tables(payload, &mut dc_tables, &mut ac_tables)?,
+            MARKER_DQT => parse_quant_tables(payload, &mut quant_tables)?,
+            MARKER_DRI => {
+                restart_interval = read_u16(payload, 0)
+                    .ok_or_else(|| JpegError::Corrupt("truncated DRI".to_string()))?;
+            }
+            MARKER_SOS => {
+                let image = frame
+                    .as_ref()
+                    .ok_or_else(|| JpegError::Corrupt("scan before frame header".to_string()))?;
+                let scan = parse_scan_header(payload, image)?;
+                let end = entropy_end(input, pos);
+                let data = input.get(pos..end).unwrap_or_default();
+                if !progressive {
+                    if scan.spectral != (0, 63) || scan.approximation != 0 {
+                        return Err(JpegError::Unsupported(
+                            "spectral selection or successive approximation".to_string(),
+                        ));
+                    }
+                    let tables = (&dc_tables, &ac_tables);
+                    decode_sequential_dc(
+                        image,
+                        &scan,
+                        data,
+                        tables,
+                        restart_interval,
+                        &mut planes,
+                    )?;
+                } else if scan.spectral.0 != 0 || scan.approximation >> 4 != 0 {
+                    break;
+                } else {
+                    decode_dc_scan(
+                        image,
+                        &scan,
+                        data,
+                        &dc_tables,
+                        restart_interval,
+                        &mut planes,
+                    )?;
+                }
+                pos = end;
+            }
+            _ => {}
+        }
+    }
+
+    let image = frame.ok_or_else(|| JpegError::Corrupt("no frame header".to_string()))?;
+    Ok((image, quant_tables, planes))
+}
+
+/// Draws the DC planes of [`first_scan_preview`] and [`decode_dc`] one pixel per luma
+/// block.
+fn render_dc(
+    image: &JpegImage,
+    quant_tables: &QuantTables,
+    planes: &[Option<Vec<i32>>],
+) -> Result<RgbaImage, JpegError> {
+    let (mcus_x, _) = image.mcu_grid();
+    let (max_h, max_v) = image.max_sampling();
+    let mut levels = Vec::with_capacity(image.components.len());
+    for (component, plane) in image.components.iter().zip(planes) {
+        let quant = quant_tables
+            .get(usize::from(component.quant_table))
+            .and_then(Option::as_ref)
+            .map(|table| i32::from(table[0]));
+        if plane.is_some() && quant.is_none() {
+            return Err(JpegError::Corrupt(
+                "component references an undefined quantization table".to_string(),
+            ));
+        }
+        levels.push((component, plane.as_deref(), quant.unwrap_or(0)));
+    }
+
+    // A dequantized DC coefficient is 8 times the block's mean, less the level shift.
+    let sample = |slot: usize, x: u32, y: u32| {
+        let Some(&(component, Some(plane), quant)) = levels.get(slot) else {
+            return 128;
+        };
+        let (h, v) = (usize::from(component.h), usize::from(component.v));
+        let block_x = usize::try_from(x).unwrap_or(0) * h / usize::from(max_h);
+        let block_y = usize::try_from(y).unwrap_or(0) * v / usize::from(max_v);
+        let dc = plane
+            .get(block_y * mcus_x * h + block_x)
+            .copied()
+            .unwrap_or(0);
+        clamp_sample((dc * quant + 4).div_euclid(8) + 128)
+    };
+    let width = u32::from(image.width).div_ceil(8);
+    let height = u32::from(image.height).div_ceil(8);
+    Ok(RgbaImage::from_fn(width, height, |x, y| {
+        let luma = sample(0, x, y);
+        let [r, g, b] = if levels.len() == 3 {
+            ycbcr_to_rgb(luma, sample(1, x, y), sample(2, x, y))
+        } else {
+            [luma; 3]
+        };
+        Rgba([r, g, b, 255])
+    }))
+}
+
+/// JFIF YCbCr to RGB, in 16.16 fixed point.
+fn ycbcr_to_rgb(y: u8, cb: u8, cr: u8) -> [u8; 3] {
+    let y = i32::from(y) << 16;
+    let (cb, cr) = (i32::from(cb) - 128, i32::from(cr) - 128);
+    let channel = |value: i32| clamp_sample((value + 0x8000) >> 16);
+    [
+        channel(y + 91_881 * cr),
+        channel(y - 22_554 * cb - 46_802 * cr),
+        channel(y + 116_130 * cb),
+    ]
+}
+
+fn clamp_sample(value: i32) -> u8 {
+    u8::try_from(value.clamp(0, 255)).unwrap_or(u8::MAX)
+}
+
+/// Replaces every COM segment with a single `comment`, or removes them all when
+/// `comment` is `None`.
+///
+/// The new segment goes right after the leading APPn segments (so JFIF/EXIF stay
+/// first); everything else, including the entropy-coded data, is copied byte for byte.
+///
+/// # Errors
+///
+/// Returns `JpegError::CommentTooLong` if the comment exceeds 65533 bytes, plus the
+/// header errors of [`comments`].
+pub fn set_comment(input: &[u8], comment: Option<&str>) -> Result<Vec<u8>, JpegError> {
+    if let Some(text) = comment {
+        if text.len() > MAX_SEGMENT_PAYLOAD {
+            return Err(JpegError::CommentTooLong(text.len()));
+        }
+    }
+    let (segments, body_start) = header_segments(input)?;
+
+    let mut out = Vec::with_capacity(input.len() + comment.map_or(0, |c| c.len() + 4));
+    out.extend_from_slice(&[0xFF, MARKER_SOI]);
+    let mut pending = comment;
+    for segment in &segments {
+        if segment.marker == MARKER_COM {
+            continue;
+        }
+        if !(0xE0..=0xEF).contains(&segment.marker) {
+            if let Some(text) = pending.take() {
+                push_segment(&mut out, MARKER_COM, text.as_bytes())?;
+            }
+        }
+        out.extend_from_slice(input.get(segment.start..segment.end).unwrap_or_default());
+    }
+    if let Some(text) = pending {
+        push_segment(&mut out, MARKER_COM, text.as_bytes())?;
+    }
+    out.extend_from_slice(input.get(body_start..).unwrap_or_default());
+    Ok(out)
+}
+
+/// The EXIF orientation tag (1-8), or `None` if the file has no EXIF data or the tag
+/// is missing.
+///
+/// Only the headers are read.
+///
+/// # Errors
+///
+/// Returns `JpegError::NotJpeg` if the SOI marker is missing and `JpegError::Corrupt`
+/// if a header segment or the EXIF structure is truncated.
+pub fn orientation(input: &[u8]) -> Result<Option<u16>, JpegError> {
+    let (segments, _) = header_segments(input)?;
+    let Some(tiff) = segments.iter().find_map(exif_tiff) else {
+        return Ok(None);
+    };
+    let ifd = Ifd::read(tiff)?;
+    Ok(ifd
+        .entry(tiff, TAG_ORIENTATION)
+        .and_then(|entry| ifd.order.u16(tiff, entry + 8)))
+}
+
+/// Sets the EXIF orientation tag to `orientation` (1-8) without touching the pixel
+/// data, so viewers that honor the tag show the image rotated or mirrored.
+///
+/// An existing tag is overwritten in place. If the EXIF data has no orientation tag,
+/// its first IFD is copied to the end with the tag added; if the file has no EXIF data,
+/// a minimal EXIF segment is added after any JFIF header. Everything else is copied
+/// byte for byte.
+///
+/// # Errors
+///
+/// Returns `JpegError::InvalidOrientation` if `orientation` is outside 1-8,
+/// `JpegError::Corrupt` if the EXIF structure is malformed or would outgrow its
+/// segment, plus the header errors of [`comments`].
+pub fn set_orientation(input: &[u8], orientation: u16) -> Result<Vec<u8>, JpegError> {
+    if !(1..=8).contains(&orientation) {
+        return Err(JpegError::InvalidOrientation(orientation));
+    }
+    let tiff = match exif(input)? {
+        Some(tiff) => tiff_with_orientation(&tiff, orientation)?,
+        None => {
+            let mut tiff = b"MM\0\x2a\0\0\0\x08\0\x01".to_vec();
+            tiff.extend(orientation_entry(ByteOrder::Big, orientation));
+            tiff.extend_from_slice(&[0; 4]);
+            tiff
+        }
+    };
+    replace_exif(input, Some(&tiff))
+}
+
+/// The TIFF structure of the file's EXIF data, or `None` if it has none.
+///
+/// # Errors
+///
+/// Returns the header errors of [`comments`].
+pub fn exif(input: &[u8]) -> Result<Option<Vec<u8>>, JpegError> {
+    let (segments, _) = header_segments(input)?;
+    Ok(segments.iter().find_map(exif_tiff).map(<[u8]>::to_vec))
+}
+
+/// Replaces the file's EXIF data with `tiff`, a TIFF structure as [`exif`] returns
+/// it, or removes the EXIF segment when `tiff` is `None`. New EXIF data is added after
+/// any JFIF header. Everything else is copied byte for byte.
+///
+/// # Errors
+///
+/// Returns `JpegError::Corrupt` if `tiff` doesn't fit in one segment, plus the header
+/// errors of [`comments`].
+pub fn replace_exif(input: &[u8], tiff: Option<&[u8]>) -> Result<Vec<u8>, JpegError> {
+    let (segments, body_start) = header_segments(input)?;
+    let existing = segments
+        .iter()
+        .position(|segment| exif_tiff(segment).is_some());
+    let payload = tiff.map(|tiff| [EXIF_HEADER, tiff].concat());
+    if payload
+        .as_ref()
+        .is_some_and(|payload| payload.len() > MAX_SEGMENT_PAYLOAD)
+    {
+        return Err(JpegError::Corrupt(format!(
+            "EXIF data would exceed {MAX_SEGMENT_PAYLOAD} bytes"
+        )));
+    }
+
+    let extra = payload.as_ref().map_or(0, |payload| payload.len() + 4);
+    let mut out = Vec::with_capacity(input.len() + extra);
+    out.extend_from_slice(&[0xFF, MARKER_SOI]);
+    let mut pending = payload.as_ref().filter(|_| existing.is_none());
+    for (index, segment) in segments.iter().enumerate() {
+        if Some(index) == existing {
+            if let Some(payload) = &payload {
+                push_segment(&mut out, MARKER_APP1, payload)?;
+            }
+            continue;
+        }
+        if segment.marker != MARKER_APP0 {
+            if let Some(payload) = pending.take() {
+                push_segment(&mut out, MARKER_APP1, payload)?;
+            }
+        }
+        out.extend_from_slice(input.get(segment.start..segment.end).unwrap_or_default());
+    }
+    if let Some(payload) = pending {
+        push_segment(&mut out, MARKER_APP1, payload)?;
+    }
+    out.extend_from_slice(input.get(body_start..).unwrap_or_default());
+    Ok(out)
+}
+
+/// The TIFF structure inside an EXIF APP1 segment.
+fn exif_tiff<'a>(segment: &HeaderSegment<'a>) -> Option<&'a [u8]> {
+    if segment.marker != MARKER_APP1 {
+        return None;
+    }
+    segment.payload.strip_prefix(EXIF_HEADER)
+}
+
+/// `tiff` with its first IFD's orientation set, adding the tag if it is missing.
+fn tiff_with_orientation(tiff: &[u8], orientation: u16) -> Result<Vec<u8>, JpegError> {
+    let ifd = Ifd::read(tiff)?;
+    let mut out = tiff.to_vec();
+    if let Some(entry) = ifd.entry(tiff, TAG_ORIENTATION) {
+        if let Some(slot) = out.get_mut(entry..entry + 12) {
+            slot.copy_from_slice(&orientation_entry(ifd.order, orientation));
+        }
+        return Ok(out);
+    }
+
+    // Entry values and offsets are relative to the start of the TIFF structure, so the
+    // IFD can move to the end as long as its entries stay sorted by tag.
+    if out.len() % 2 == 1 {
+        out.push(0);
+    }
+    let new_offset = u32::try_from(out.len()).map_err(|_| exif_corrupt())?;
+    let count = ifd.count.checked_add(1).ok_or_else(exif_corrupt)?;
+    out.extend(ifd.order.bytes_u16(count));
+    let mut added = false;
+    for index in 0..usize::from(ifd.count) {
+        let entry = ifd.start + 2 + index * 12;
+        let tag = ifd.order.u16(tiff, entry).ok_or_else(exif_corrupt)?;
+        if !added && tag > TAG_ORIENTATION {
+            out.extend(orientation_entry(ifd.order, orientation));
+            added = true;
+        }
+        out.extend_from_slice(tiff.get(entry..entry + 12).ok_or_else(exif_corrupt)?);
+    }
+    if !added {
+        out.extend(orientation_entry(ifd.order, orientation));
+    }
+    let next = ifd.start + 2 + usize::from(ifd.count) * 12;
+    out.extend_from_slice(tiff.get(next..next + 4).ok_or_else(exif_corrupt)?);
+    if let Some(slot) = out.get_mut(4..8) {
+        slot.copy_from_slice(&ifd.order.bytes_u32(new_offset));
+    }
+    if out.len() + EXIF_HEADER.len() > MAX_SEGMENT_PAYLOAD {
+        return Err(JpegError::Corrupt(format!(
+            "EXIF data would exceed {MAX_SEGMENT_PAYLOAD} bytes"
+        )));
+    }
+    Ok(out)
+}
+
+/// A 12-byte IFD entry holding the orientation as one SHORT.
+fn orientation_entry(order: ByteOrder, orientation: u16) -> Vec<u8> {
+    let mut entry = Vec::with_capacity(12);
+    entry.extend(order.bytes_u16(TAG_ORIENTATION));
+    entry.extend(order.bytes_u16(TIFF_SHORT));
+    entry.extend(order.bytes_u32(1));
+    // Values shorter than four bytes are left-aligned in the value field.
+    entry.extend(order.bytes_u16(orientation));
+    entry.extend_from_slice(&[0; 2]);
+    entry
+}
+
+fn exif_corrupt() -> JpegError {
+    JpegError::Corrupt("truncated EXIF data".to_string())
+}
+
+/// Byte order of a TIFF structure.
+#[derive(Debug, Clone, Copy)]
+enum ByteOrder {
+    Little,
+    Big,
+}
+
+impl ByteOrder {
+    fn u16(self, data: &[u8], pos: usize) -> Option<u16> {
+        let bytes = [*data.get(pos)?, *data.get(pos.checked_add(1)?)?];
+        Some(match self {
+            Self::Little => u16::from_le_bytes(bytes),
+            Self::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(self, data: &[u8], pos: usize) -> Option<u32> {
+        let bytes: [u8; 4] = data.get(pos..pos.checked_add(4)?)?.try_into().ok()?;
+        Some(match self {
+            Self::Little => u32::from_le_bytes(bytes),
+            Self::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn bytes_u16(self, value: u16) -> [u8; 2] {
+        match self {
+            Self::Little => value.to_le_bytes(),
+            Self::Big => value.to_be_bytes(),
+        }
+    }
+
+    fn bytes_u32(self, value: u32) -> [u8; 4] {
+        match self {
+            Self::Little => value.to_le_bytes(),
+            Self::Big => value.to_be_bytes(),
+        }
+    }
+}
+
+/// The first IFD (the primary image's tags) of a TIFF structure.
+struct Ifd {
+    order: ByteOrder,
+    /// Offset of the entry count.
+    start: usize,
+    count: u16,
+}
+
+impl Ifd {
+    fn read(tiff: &[u8]) -> Result<Self, JpegError> {
+        let order = match tiff.get(..4) {
+            Some(b"II\x2a\0") => ByteOrder::Little,
+            Some(b"MM\0\x2a") => ByteOrder::Big,
+            _ => return Err(JpegError::Corrupt("invalid EXIF header".to_string())),
+        };
+        let start = order
+            .u32(tiff, 4)
+            .and_then(|offset| usize::try_from(offset).ok())
+            .ok_or_else(exif_corrupt)?;
+        let count = order.u16(tiff, start).ok_or_else(exif_corrupt)?;
+        if tiff.len() < start + 2 + usize::from(count) * 12 + 4 {
+            return Err(exif_corrupt());
+        }
+        Ok(Self {
+            order,
+            start,
+            count,
+        })
+    }
+
+    /// Offset of the entry for `tag`, if present.
+    fn entry(&self, tiff: &[u8], tag: u16) -> Option<usize> {
+        (0..usize::from(self.count))
+            .map(|index| self.start + 2 + index * 12)
+            .find(|&entry| self.order.u16(tiff, entry) == Some(tag))
+    }
+}
+
+/// A header segment located in the original file.
+struct HeaderSegment<'a> {
+    marker: u8,
+    /// Offset of the segment's `FF` marker byte.
+    start: usize,
+    /// Offset just past the segment.
+    end: usize,
+    payload: &'a [u8],
+}
+
+/// Splits the file into the header segments before the first scan and the offset
+/// where the rest (first SOS, or EOI for a headers-only file) begins.
+fn header_segments(input: &[u8]) -> Result<(Vec<HeaderSegment<'_>>, usize), JpegError> {
+    if input.get(..2) != Some(&[0xFF, MARKER_SOI]) {
+        return Err(JpegError::NotJpeg);
+    }
+    let mut segments = Vec::new();
+    let mut pos = 2;
+    while let Some((marker, marker_end)) = next_marker(input, pos) {
+        let start = marker_end - 2;
+        if matches!(marker, MARKER_SOS | MARKER_EOI) {
+            return Ok((segments, start));
+        }
+        if marker == 0x01 || (0xD0..=0xD7).contains(&marker) {
+            segments.push(HeaderSegment {
+                marker,
+                start,
+                end: marker_end,
+                payload: &[],
+            });
+            pos = marker_end;
+            continue;
+        }
+        let length = read_u16(input, marker_end)
+            .ok_or_else(|| JpegError::Corrupt("truncated segment length".to_string()))?;
+        let payload = usize::from(length)
+            .checked_sub(2)
+            .and_then(|len| input.get(marker_end + 2..marker_end + 2 + len))
+            .ok_or_else(|| JpegError::Corrupt("truncated segment".to_string()))?;
+        pos = marker_end + 2 + payload.len();
+        segments.push(HeaderSegment {
+            marker,
+            start,
+            end: pos,
+            payload,
+        });
+    }
+    Ok((segments, input.len()))
+}
+
+/// Errors from coefficient-level JPEG parsing and writing.
+#[derive(Debug)]
+pub enum JpegError {
+    /// The input does not start with a JPEG SOI marker.
+    NotJpeg,
+    /// The file uses a JPEG coding process this module does not handle.
+    Unsupported(String),
+    /// The file is malformed or truncated.
+    Corrupt(String),
+    /// A comment does not fit in one COM segment (65533 bytes).
+    CommentTooLong(usize),
+    /// An EXIF orientation outside 1-8.
+    InvalidOrientation(u16),
+}
+
+impl std::fmt::Display for JpegError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotJpeg => write!(f, "Input is not a JPEG file"),
+            Self::Unsupported(what) => write!(f, "Unsupported JPEG: {what}"),
+            Self::Corrupt(msg) => write!(f, "Invalid JPEG data: {msg}"),
+            Self::CommentTooLong(len) => write!(
+                f,
+                "Comment is {len} bytes; a JPEG comment holds at most {MAX_SEGMENT_PAYLOAD}"
+            ),
+            Self::InvalidOrientation(value) => {
+                write!(f, "EXIF orientation must be 1-8, got {value}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for JpegError {}
+
+// ===== Marker Parsing =====
+
+fn read_u16(data: &[u8], pos: usize) -> Option<u16> {
+    let bytes = data.get(pos..pos.checked_add(2)?)?;
+    Some(u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]))
+}
+
+/// Finds the next marker at or after `pos`, skipping fill bytes.
+/// Returns the marker code and the position just past it.
+fn next_marker(data: &[u8], mut pos: usize) -> Option<(u8, usize)> {
+    while *data.get(pos)? != 0xFF {
+        pos += 1;
+    }
+    while *data.get(pos)? == 0xFF {
+        pos += 1;
+    }
+    Some((*data.get(pos)?, pos + 1))
+}
+
+/// Returns the offset of the first marker that ends the entropy-coded data starting
+/// at `start` (stuffed `FF 00` bytes and restart markers belong to the scan).
+fn entropy_end(data: &[u8], start: usize) -> usize {
+    let mut pos = start;
+    while let Some(&byte) = data.get(pos) {
+        if byte == 0xFF {
+            match data.get(pos + 1) {
+                Some(0x00 | 0xD0..=0xD7) => pos += 2,
+                Some(0xFF) => pos += 1,
+                _ => return pos,
+            }
+        } else {
+            pos += 1;
+        }
+    }
+    pos
+}
+
+fn push_segment(out: &mut Vec<u8>, marker: u8, payload: &[u8]) -> Result<(), JpegError> {
+    let length = payload
+        .len()
+        .checked_add(2)
+        .and_then(|len| u16::try_from(len).ok())
+        .ok_or_else(|| {
+            JpegError::Corrupt(format!("segment exceeds {MAX_SEGMENT_PAYLOAD} bytes"))
+        })?;
+    out.extend_from_slice(&[0xFF, marker]);
+    out.extend_from_slice(&length.to_be_bytes());
+    out.extend_from_slice(payload);
+    Ok(())
+}
+
+fn component_count(image: &JpegImage) -> Result<u8, JpegError> {
+    u8::try_from(image.components.len())
+        .map_err(|_| JpegError::Unsupported("too many components".to_string()))
+}
+
+fn parse_frame_header(payload: &[u8]) -> Result<JpegImage, JpegError> {
+    let truncated = || JpegError::Corrupt("truncated frame header".to_string());
+    let precision = *payload.first().ok_or_else(truncated)?;
+    if precision != 8 {
+        return Err(JpegError::Unsupported(format!("{precision}-bit samples")));
+    }
+    let height = read_u16(payload, 1).ok_or_else(truncated)?;
+    let width = read_u16(payload, 3).ok_or_else(truncated)?;
+    if width == 0 || height == 0 {
+        return Err(JpegError::Unsupported(
+            "zero or deferred (DNL) dimensions".to_string(),
+        ));
+    }
+    let count = usize::from(*payload.get(5).ok_or_else(truncated)?);
+    if count == 0 || count > 4 {
+        return Err(JpegError::Unsupported(format!("{count} components")));
+    }
+
+    let mut components = Vec::with_capacity(count);
+    for spec in payload
+        .get(6..6 + count * 3)
+        .ok_or_else(truncated)?
+        .chunks_exact(3)
+    {
+        let &[id, sampling, quant_table] = spec else {
+            return Err(truncated());
+        };
+        let (h, v) = (sampling >> 4, sampling & 0x0F);
+        if !(1..=4).contains(&h) || !(1..=4).contains(&v) || quant_table > 3 {
+            return Err(JpegError::Corrupt(
+                "invalid component specification".to_string(),
+            ));
+        }
+        components.push(Component {
+            id,
+            h,
+            v,
+            quant_table,
+            blocks_w: 0,
+            blocks_h: 0,
+            blocks: Vec::new(),
+        });
+    }
+    if count == 1 {
+        // Sampling factors are meaningless for a single component; normalize them so
+        // the block layout matches what every decoder assumes.
+        for component in &mut components {
+            component.h = 1;
+            component.v = 1;
+        }
+    }
+    let blocks_per_mcu: u32 = components
+        .iter()
+        .map(|c| u32::from(c.h) * u32::from(c.v))
+        .sum();
+    if count > 1 && blocks_per_mcu > 10 {
+        return Err(JpegError::Corrupt(
+            "more than 10 blocks per MCU".to_string(),
+        ));
+    }
+
+    // Blocks are left unallocated: a preview of a progressive file only needs its DC.
+    Ok(JpegImage {
+        width,
+        height,
+        components,
+        quant_tables: [None; 4],
+        segments: Vec::new(),
+    })
+}
+
+fn parse_quant_tables(mut payload: &[u8], tables: &mut QuantTables) -> Result<(), JpegError> {
+    let corrupt = || JpegError::Corrupt("invalid quantization table".to_string());
+    while let Some((&spec, rest)) = payload.split_first() {
+        let wide = spec >> 4 != 0;
+        let slot = tables
+            .get_mut(usize::from(spec & 0x0F))
+            .ok_or_else(corrupt)?;
+        let size = if wide { 128 } else { 64 };
+        let values = rest.get(..size).ok_or_else(corrupt)?;
+        let mut table = [0u16; 64];
+        for (position, &index) in UNZIGZAG.iter().enumerate() {
+            let value = if wide {
+                read_u16(values, position * 2).ok_or_else(corrupt)?
+            } else {
+                u16::from(*values.get(position).ok_or_else(corrupt)?)
+            };
+            *table.get_mut(index).ok_or_else(corrupt)? = value;
+        }
+        *slot = Some(table);
+        payload = rest.get(size..).unwrap_or_default();
+    }
+    Ok(())
+}
+
+fn parse_huffman_tables(
+    mut payload: &[u8],
+    dc_tables: &mut [Option<HuffDecoder>; 4],
+    ac_tables: &mut [Option<HuffDecoder>; 4],
+) -> Result<(), JpegError> {
+    let corrupt = || JpegError::Corrupt("invalid Huffman table".to_string());
+    while let Some((&spec, rest)) = payload.split_first() {
+        let counts: [u8; 16] = rest
+            .get(..16)
+            .and_then(|bytes| bytes.try_into().ok())
+            .ok_or_else(corrupt)?;
+        let total: usize = counts.iter().map(|&n| usize::from(n)).sum();
+        let values = rest.get(16..16 + total).ok_or_else(corrupt)?;
+        let table = HuffTable::new(&counts, values);
+        let tables = if spec >> 4 == 0 {
+            &mut *dc_tables
+        } else {
+            &mut *ac_tables
+        };
+        *tables
+            .get_mut(usize::from(spec & 0x0F))
+            .ok_or_else(corrupt)? = Some(table.decoder()?);
+        payload = rest.get(16 + total..).unwrap_or_default();
+    }
+    Ok(())
+}
+
+// ===== Scans =====
+
+/// A scan's component list (component index plus DC and AC table slots), the range of
+/// zig-zag coefficients it codes and its successive approximation bit positions
+/// (high nibble Ah, low nibble Al).
+struct ScanHeader {
+    components: Vec<(usize, usize, usize)>,
+    spectral: (u8, u8),
+    approximation: u8,
+}
+
+fn parse_scan_header(payload: &[u8], image: &JpegImage) -> Result<ScanHeader, JpegError> {
+    let corrupt = |msg: &str| JpegError::Corrupt(msg.to_string());
+    let count = usize::from(
+        *payload
+            .first()
+            .ok_or_else(|| corrupt("truncated scan header"))?,
+    );
+    if count == 0 || count > 4 {
+        return Err(corrupt("invalid scan component count"));
+    }
+    let specs = payload
+        .get(1..1 + count * 2)
+        .ok_or_else(|| corrupt("truncated scan header"))?;
+    let mut components = Vec::with_capacity(count);
+    for spec in specs.chunks_exact(2) {
+        let &[id, tables] = spec else {
+            return Err(corrupt("truncated scan header"));
+        };
+        let index = image
+            .components
+            .iter()
+            .position(|c| c.id == id)
+            .ok_or_else(|| corrupt("scan references an unknown component"))?;
+        components.push((index, usize::from(tables >> 4), usize::from(tables & 0x0F)));
+    }
+
+    let &[start, end, approximation] = payload
+        .get(1 + count * 2..1 + count * 2 + 3)
+        .ok_or_else(|| corrupt("truncated scan header"))?
+    else {
+        return Err(corrupt("truncated scan header"));
+    };
+    Ok(ScanHeader {
+        components,
+        spectral: (start, end),
+        approximation,
+    })
+}
+
+/// Per-component layout needed to walk a scan's blocks in bitstream order.
+struct ScanComponent {
+    slot: usize,
+    h: usize,
+    v: usize,
+    visible_w: usize,
+    visible_h: usize,
+}
+
+struct ScanGeometry {
+    mcus_x: u32,
+    mcus_y: u32,
+    components: Vec<ScanComponent>,
+}
+
+impl ScanGeometry {
+    fn new(image: &JpegImage, slots: &[usize]) -> Result<Self, JpegError> {
+        let (mcu_w, mcu_h) = image.mcu_size();
+        let mut components = Vec::with_capacity(slots.len());
+        for &slot in slots {
+            let component = image
+                .components
+                .get(slot)
+                .ok_or_else(|| JpegError::Corrupt("component index out of range".to_string()))?;
+            let (visible_w, visible_h) = image.visible_blocks(component);
+            components.push(ScanComponent {
+                slot,
+                h: usize::from(component.h),
+                v: usize::from(component.v),
+                visible_w,
+                visible_h,
+            });
+        }
+        Ok(Self {
+            mcus_x: u32::from(image.width).div_ceil(mcu_w),
+            mcus_y: u32::from(image.height).div_ceil(mcu_h),
+            components,
+        })
+    }
+
+    /// Calls `visit(mcu_index, component_slot, block_x, block_y)` for every block of the
+    /// scan in bitstream order. Single-component scans are non-interleaved: each block is
+    /// its own MCU and only blocks covering real samples are coded.
+    fn walk<F>(&self, mut visit: F) -> Result<(), JpegError>
+    where
+        F: FnMut(usize, usize, usize, usize) -> Result<(), JpegError>,
+    {
+        if let [only] = self.components.as_slice() {
+            for y in 0..only.visible_h {
+                for x in 0..only.visible_w {
+                    visit(y * only.visible_w + x, only.slot, x, y)?;
+                }
+            }
+            return Ok(());
+        }
+
+        let mcus_x = usize::try_from(self.mcus_x).unwrap_or(usize::MAX);
+        let mcus_y = usize::try_from(self.mcus_y).unwrap_or(0);
+        for mcu_y in 0..mcus_y {
+            for mcu_x in 0..mcus_x {
+                let mcu = mcu_y * mcus_x + mcu_x;
+                for component in &self.components {
+                    for y in 0..component.v {
+                        for x in 0..component.h {
+                            visit(
+                                mcu,
+                                component.slot,
+                                mcu_x * component.h + x,
+                                mcu_y * component.v + y,
+                            )?;
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+fn decode_scan(
+    image: &mut JpegImage,
+    scan: &ScanHeader,
+    data: &[u8],
+    dc_tables: &[Option<HuffDecoder>; 4],
+    ac_tables: &[Option<HuffDecoder>; 4],
+    restart_interval: u16,
+) -> Result<(), JpegError> {
+    let missing = || JpegError::Corrupt("scan references an undefined Huffman table".to_string());
+    let mut decoders = Vec::with_capacity(scan.components.len());
+    for &(slot, dc, ac) in &scan.components {
+        let dc = dc_tables
+            .get(dc)
+            .and_then(Option::as_ref)
+            .ok_or_else(missing)?;
+        let ac = ac_tables
+            .get(ac)
+            .and_then(Option::as_ref)
+            .ok_or_else(missing)?;
+        decoders.push((slot, dc, ac));
+    }
+
+    let slots: Vec<usize> = scan.components.iter().map(|&(slot, _, _)| slot).collect();
+    let geometry = ScanGeometry::new(image, &slots)?;
+    let restart_interval = usize::from(restart_interval);
+    let mut reader = BitReader::new(data);
+    let mut predictions = vec![0i32; image.components.len()];
+    let mut current_mcu = 0;
+
+    geometry.walk(|mcu, slot, x, y| {
+        if mcu != current_mcu {
+            current_mcu = mcu;
+            if restart_interval > 0 && mcu % restart_interval == 0 {
+                reader.restart();
+                predictions.iter_mut().for_each(|p| *p = 0);
+            }
+        }
+        let &(_, dc, ac) = decoders
+            .iter()
+            .find(|(s, _, _)| *s == slot)
+            .ok_or_else(missing)?;
+        let prediction = predictions
+            .get_mut(slot)
+            .ok_or_else(|| JpegError::Corrupt("component index out of range".to_string()))?;
+        let block = image
+            .components
+            .get_mut(slot)
+            .and_then(|component| component.block_mut(x, y))
+            .ok_or_else(|| JpegError::Corrupt("block outside component grid".to_string()))?;
+        decode_block(&mut reader, block, prediction, dc, ac)
+    })
+}
+
+/// Decodes a progressive DC first-pass scan into `planes`, one DC value per block of
+/// each component's MCU-padded grid, allocating the planes of components it covers.
+fn decode_dc_scan(
+    image: &JpegImage,
+    scan: &ScanHeader,
+    data: &[u8],
+    dc_tables: &[Option<HuffDecoder>; 4],
+    restart_interval: u16,
+    planes: &mut [Option<Vec<i32>>],
+) -> Result<(), JpegError> {
+    let missing = || JpegError::Corrupt("scan references an undefined Huffman table".to_string());
+    let out_of_range = || JpegError::Corrupt("component index out of range".to_string());
+    let (mcus_x, mcus_y) = image.mcu_grid();
+    let mut decoders = Vec::with_capacity(scan.components.len());
+    for &(slot, dc, _) in &scan.components {
+        let dc = dc_tables
+            .get(dc)
+            .and_then(Option::as_ref)
+            .ok_or_else(missing)?;
+        let component = image.components.get(slot).ok_or_else(out_of_range)?;
+        let stride = mcus_x * usize::from(component.h);
+        planes
+            .get_mut(slot)
+            .ok_or_else(out_of_range)?
+            .get_or_insert_with(|| vec![0; stride * mcus_y * usize::from(component.v)]);
+        decoders.push((slot, dc, stride));
+    }
+
+    let shift = scan.approximation & 0x0F;
+    let slots: Vec<usize> = scan.components.iter().map(|&(slot, _, _)| slot).collect();
+    let geometry = ScanGeometry::new(image, &slots)?;
+    let restart_interval = usize::from(restart_interval);
+    let mut reader = BitReader::new(data);
+    let mut predictions = vec![0i32; image.components.len()];
+    let mut current_mcu = 0;
+
+    geometry.walk(|mcu, slot, x, y| {
+        if mcu != current_mcu {
+            current_mcu = mcu;
+            if restart_interval > 0 && mcu % restart_interval == 0 {
+                reader.restart();
+                predictions.iter_mut().for_each(|p| *p = 0);
+            }
+        }
+        let &(_, dc, stride) = decoders
+            .iter()
+            .find(|(s, _, _)| *s == slot)
+            .ok_or_else(missing)?;
+        let prediction = predictions.get_mut(slot).ok_or_else(out_of_range)?;
+        let size = dc.decode(&mut reader)?;
+        if size > 16 {
+            return Err(JpegError::Corrupt(
+                "invalid DC magnitude category".to_string(),
+            ));
+        }
+        *prediction += extend(reader.receive(size), size);
+        let value = planes
+            .get_mut(slot)
+            .and_then(Option::as_mut)
+            .and_then(|plane| plane.get_mut(y * stride + x))
+            .ok_or_else(|| JpegError::Corrupt("block outside component grid".to_string()))?;
+        *value = *prediction << shift;
+        Ok(())
+    })
+}
+
+/// Decodes a sequential scan into `planes` like [`decode_dc_scan`], keeping each
+/// block's DC coefficient and discarding the AC coefficients that follow it.
+fn decode_sequential_dc(
+    image: &JpegImage,
+    scan: &ScanHeader,
+    data: &[u8],
+    (dc_tables, ac_tables): (&[Option<HuffDecoder>; 4], &[Option<HuffDecoder>; 4]),
+    restart_interval: u16,
+    planes: &mut [Option<Vec<i32>>],
+) -> Result<(), JpegError> {
+    let missing = || JpegError::Corrupt("scan references an undefined Huffman table".to_string());
+    let out_of_range = || JpegError::Corrupt("component index out of range".to_string());
+    let (mcus_x, mcus_y) = image.mcu_grid();
+    let mut decoders = Vec::with_capacity(scan.components.len());
+    for &(slot, dc, ac) in &scan.components {
+        let dc = dc_tables
+            .get(dc)
+            .and_then(Option::as_ref)
+            .ok_or_else(missing)?;
+        let ac = ac_tables
+            .get(ac)
+            .and_then(Option::as_ref)
+            .ok_or_else(missing)?;
+        let component = image.components.get(slot).ok_or_else(out_of_range)?;
+        let stride = mcus_x * usize::from(component.h);
+        planes
+            .get_mut(slot)
+            .ok_or_else(out_of_range)?
+            .get_or_insert_with(|| vec![0; stride * mcus_y * usize::from(component.v)]);
+        decoders.push((slot, dc, ac, stride));
+    }
+
+    let slots: Vec<usize> = scan.components.iter().map(|&(slot, _, _)| slot).collect();
+    let geometry = ScanGeometry::new(image, &slots)?;
+    let restart_interval = usize::from(restart_interval);
+    let mut reader = BitReader::new(data);
+    let mut predictions = vec![0i32; image.components.len()];
+    let mut block = [0; 64];
+    let mut current_mcu = 0;
+
+    geometry.walk(|mcu, slot, x, y| {
+        if mcu != current_mcu {
+            current_mcu = mcu;
+            if restart_interval > 0 && mcu % restart_interval == 0 {
+                reader.restart();
+                predictions.iter_mut().for_each(|p| *p = 0);
+            }
+        }
+        let &(_, dc, ac, stride) = decoders
+            .iter()
+            .find(|(s, _, _, _)| *s == slot)
+            .ok_or_else(missing)?;
+        let prediction = predictions.get_mut(slot).ok_or_else(out_of_range)?;
+        decode_block(&mut reader, &mut block, prediction, dc, ac)?;
+        let value = planes
+            .get_mut(slot)
+            .and_then(Option::as_mut)
+            .and_then(|plane| plane.get_mut(y * stride + x))
+            .ok_or_else(|| JpegError::Corrupt("block outside component grid".to_string()))?;
+        *value = i32::from(block[0]);
+        Ok(())
+    })
+}
+
+fn decode_block(
+    reader: &mut BitReader<'_>,
+    block: &mut Block,
+    prediction: &mut i32,
+    dc: &HuffDecoder,
+    ac: &HuffDecoder,
+) -> Result<(), JpegError> {
+    let overflow = || JpegError::Corrupt("coefficient out of range".to_string());
+
+    let size = dc.decode(reader)?;
+    if size > 16 {
+        return Err(JpegError::Corrupt(
+            "invalid DC magnitude category".to_string(),
+        ));
+    }
+    *prediction += extend(reader.receive(size), size);
+    *block = [0; 64];
+    block[0] = i16::try_from(*prediction).map_err(|_| overflow())?;
+
+    let mut k = 1;
+    while k < 64 {
+        let symbol = ac.decode(reader)?;
+        let (run, size) = (symbol >> 4, symbol & 0x0F);
+        if size == 0 {
+            if run == 15 {
+                k += 16;
+                continue;
+            }
+            break;
+        }
+        k += usize::from(run);
+        let index = *UNZIGZAG
+            .get(k)
+            .ok_or_else(|| JpegError::Corrupt("AC coefficients run past the block".to_string()))?;
+        let value = extend(reader.receive(size), size);
+        *block.get_mut(index).ok_or_else(overflow)? =
+            i16::try_from(value).map_err(|_| overflow())?;
+        k += 1;
+    }
+    Ok(())
+}
+
+fn encode_block<S: EntropySink>(
+    sink: &mut S,
+    block: &Block,
+    prediction: &mut i32,
+    table: usize,
+) -> Result<(), JpegError> {
+    let (dc, ac) = (TableClass::Dc(table), TableClass::Ac(table));
+    encode_dc(sink, i32::from(block[0]), prediction, dc)?;
+
+    let mut run = 0u8;
+    for &index in UNZIGZAG.iter().skip(1) {
+        let coefficient = i32::from(block.get(index).copied().unwrap_or(0));
+        if coefficient == 0 {
+            run += 1;
+            continue;
+        }
+        while run > 15 {
+            sink.put_symbol(ac, 0xF0)?;
+            run -= 16;
+        }
+        let size = magnitude_category(coefficient);
+        if size > 15 {
+            return Err(JpegError::Corrupt(
+                "AC coefficient out of range".to_string(),
+            ));
+        }
+        sink.put_symbol(ac, (run << 4) | size)?;
+        sink.put_bits(magnitude_bits(coefficient, size), size);
+        run = 0;
+    }
+    if run > 0 {
+        sink.put_symbol(ac, 0x00)?;
+    }
+    Ok(())
+}
+
+/// Codes a DC coefficient as its difference from the previous block's.
+fn encode_dc<S: EntropySink>(
+    sink: &mut S,
+    value: i32,
+    prediction: &mut i32,
+    table: TableClass,
+) -> Result<(), JpegError> {
+    let diff = value - *prediction;
+    *prediction = value;
+    let size = magnitude_category(diff);
+    sink.put_symbol(table, size)?;
+    sink.put_bits(magnitude_bits(diff, size), size);
+    Ok(())
+}
+
+/// Codes the AC coefficients `start..=end` (zigzag order) of `block` for the first
+/// and only pass of a progressive AC scan (T.81 G.1.2.2). A block with nothing left
+/// in the band only extends `eob_run`, which is written once the next block with a
+/// nonzero coefficient comes along, or the run reaches its 32767-block limit.
+fn encode_ac_band<S: EntropySink>(
+    sink: &mut S,
+    block: &Block,
+    start: u8,
+    end: u8,
+    eob_run: &mut u32,
+) -> Result<(), JpegError> {
+    let mut run = 0u8;
+    for &index in UNZIGZAG
+        .iter()
+        .take(usize::from(end) + 1)
+        .skip(usize::from(start))
+    {
+        let coefficient = i32::from(block.get(index).copied().unwrap_or(0));
+        if coefficient == 0 {
+            run += 1;
+            continue;
+        }
+        flush_eob_run(sink, eob_run)?;
+        while run > 15 {
+            sink.put_symbol(TableClass::Ac(0), 0xF0)?;
+            run -= 16;
+        }
+        let size = magnitude_category(coefficient);
+        if size > 15 {
+            return Err(JpegError::Corrupt(
+                "AC coefficient out of range".to_string(),
+            ));
+        }
+        sink.put_symbol(TableClass::Ac(0), (run << 4) | size)?;
+        sink.put_bits(magnitude_bits(coefficient, size), size);
+        run = 0;
+    }
+    if run > 0 {
+        *eob_run += 1;
+        if *eob_run == 0x7FFF {
+            flush_eob_run(sink, eob_run)?;
+        }
+    }
+    Ok(())
+}
+
+/// Writes a pending run of empty blocks as an `EOBn` symbol plus its extra bits.
+fn flush_eob_run<S: EntropySink>(sink: &mut S, eob_run: &mut u32) -> Result<(), JpegError> {
+    if *eob_run == 0 {
+        return Ok(());
+    }
+    let bits = u8::try_from(31 - eob_run.leading_zeros()).unwrap_or(14);
+    sink.put_symbol(TableClass::Ac(0), bits << 4)?;
+    sink.put_bits(*eob_run - (1 << bits), bits);
+    *eob_run = 0;
+    Ok(())
+}
+
+/// Which Huffman table a symbol is coded with: DC or AC, luma (0) or chroma (1).
+#[derive(Debug, Clone, Copy)]
+enum TableClass {
+    Dc(usize),
+    Ac(usize),
+}
+
+impl TableClass {
+    fn index(self) -> usize {
+        match self {
+            Self::Dc(table) => table * 2,
+            Self::Ac(table) => table * 2 + 1,
+        }
+    }
+}
+
+/// Receives the symbols and raw bits of an entropy-coded scan, so the same block
+/// coder can either write a scan or just tally symbol frequencies.
+trait EntropySink {
+    fn put_symbol(&mut self, table: TableClass, symbol: u8) -> Result<(), JpegError>;
+    fn put_bits(&mut self, value: u32, size: u8);
+}
+
+struct EntropyWriter {
+    writer: BitWriter,
+    /// DC luma, AC luma, DC chroma, AC chroma.
+    encoders: [HuffEncoder; 4],
+}
+
+impl EntropySink for EntropyWriter {
+    fn put_symbol(&mut self, table: TableClass, symbol: u8) -> Result<(), JpegError> {
+        let encoder = self
+            .encoders
+            .get(table.index())
+            .ok_or_else(|| JpegError::Corrupt("Huffman table out of range".to_string()))?;
+        self.writer.put_symbol(encoder, symbol)
+    }
+
+    fn put_bits(&mut self, value: u32, size: u8) {
+        self.writer.put_bits(value, size);
+    }
+}
+
+struct SymbolCounter {
+    /// DC luma, AC luma, DC chroma, AC chroma.
+    counts: [[u32; 256]; 4],
+}
+
+impl EntropySink for SymbolCounter {
+    fn put_symbol(&mut self, table: TableClass, symbol: u8) -> Result<(), JpegError> {
+        if let Some(count) = self
+            .counts
+            .get_mut(table.index())
+            .and_then(|counts| counts.get_mut(usize::from(symbol)))
+        {
+            *count = count.saturating_add(1);
+        }
+        Ok(())
+    }
+
+    fn put_bits(&mut self, _value: u32, _size: u8) {}
+}
+
+/// Number of bits needed to represent `|value|` (the JPEG magnitude category).
+fn magnitude_category(value: i32) -> u8 {
+    let bits = 32 - value.unsigned_abs().leading_zeros();
+    u8::try_from(bits).unwrap_or(u8::MAX)
+}
+
+/// Low `size` bits used to code `value`; negatives are stored as `value - 1` (one's complement).
+fn magnitude_bits(value: i32, size: u8) -> u32 {
+    let adjusted = if value < 0 { value - 1 } else { value };
+    let mask = (1u32 << size) - 1;
+    u32::from_ne_bytes(adjusted.to_ne_bytes()) & mask
+}
+
+/// Inverse of [`magnitude_bits`] (the `EXTEND` procedure in T.81 F.2.2.1).
+fn extend(bits: u16, size: u8) -> i32 {
+    if size == 0 {
+        return 0;
+    }
+    let value = i32::from(bits);
+    if value < 1 << (size - 1) {
+        value - (1 << size) + 1
+    } else {
+        value
+    }
+}
+
+// ===== Huffman Coding =====
+
+/// A Huffman table as stored in a DHT segment.
+#[derive(Debug, Clone)]
+struct HuffTable {
+    counts: [u8; 16],
+    values: Vec<u8>,
+}
+
+struct HuffPair {
+    dc: HuffTable,
+    ac: HuffTable,
+}
+
+impl HuffTable {
+    fn new(counts: &[u8; 16], values: &[u8]) -> Self {
+        Self {
+            counts: *counts,
+            values: values.to_vec(),
+        }
+    }
+
+    /// Builds an optimal table for the given symbol frequencies, limited to 16-bit
+    /// codes and never assigning the all-ones code (T.81 Annex K.2).
+    fn optimal(frequencies: &[u32; 256]) -> Self {
+        const RESERVED: usize = 256;
+
+        // Plain Huffman tree over the used symbols plus one reserved pseudo-symbol;
+        // the reserved leaf ends up with the longest code, which is later discarded.
+        let mut heap = BinaryHeap::new();
+        for (symbol, &frequency) in frequencies.iter().enumerate() {
+            if frequency > 0 {
+                heap.push(Reverse((u64::from(frequency), symbol)));
+            }
+        }
+        if heap.is_empty() {
+            heap.push(Reverse((1, 0)));
+        }
+        heap.push(Reverse((0, RESERVED)));
+
+        let mut parents: Vec<Option<usize>> = vec![None; RESERVED + 1];
+        while let (Some(Reverse((fa, a))), Some(Reverse((fb, b)))) = (heap.pop(), heap.pop()) {
+            let node = parents.len();
+            parents.push(None);
+            for child in [a, b] {
+                if let Some(parent) = parents.get_mut(child) {
+                    *parent = Some(node);
+                }
+            }
+            heap.push(Reverse((fa + fb, node)));
+        }
+        let depth = |mut node: usize| {
+            let mut depth = 0usize;
+            while let Some(&Some(parent)) = parents.get(node) {
+                depth += 1;
+                node = parent;
+            }
+            depth
+        };
+
+        let mut leaves: Vec<(usize, usize)> = (0..RESERVED)
+            .filter(|&symbol| frequencies.get(symbol).is_some_and(|&f| f > 0))
+            .map(|symbol| (depth(symbol), symbol))
+            .collect();
+        if leaves.is_empty() {
+            leaves.push((1, 0));
+        }
+        leaves.sort_unstable();
+
+        let max_depth = leaves
+            .iter()
+            .map(|&(d, _)| d)
+            .chain([depth(RESERVED)])
+            .max()
+            .unwrap_or(1);
+        let mut bits = vec![0u32; max_depth.max(16) + 1];
+        for length in leaves.iter().map(|&(d, _)| d).chain([depth(RESERVED)]) {
+            if let Some(count) = bits.get_mut(length) {
+                *count += 1;
+            }
+        }
+
+        // Shorten codes longer than 16 bits: move pairs of the deepest leaves up one
+        // level and push a shorter leaf down to take the freed slot.
+        for length in (17..bits.len()).rev() {
+            while bits.get(length).copied().unwrap_or(0) > 0 {
+                let Some(shorter) = (1..length - 1)
+                    .rev()
+                    .find(|&j| bits.get(j).copied().unwrap_or(0) > 0)
+                else {
+                    break;
+                };
+                for (index, delta) in [
+                    (length, -2),
+                    (length - 1, 1),
+                    (shorter + 1, 2),
+                    (shorter, -1),
+                ] {
+                    if let Some(count) = bits.get_mut(index) {
+                        *count = count.saturating_add_signed(delta);
+                    }
+                }
+            }
+        }
+        // Drop the reserved code, which is one of the longest.
+        if let Some(count) = bits.iter_mut().take(17).rev().find(|count| **count > 0) {
+            *count -= 1;
+        }
+
+        let mut counts = [0u8; 16];
+        for (count, &bits) in counts.iter_mut().zip(bits.iter().skip(1)) {
+            *count = u8::try_from(bits).unwrap_or(u8::MAX);
+        }
+        let values = leaves
+            .iter()
+            .filter_map(|&(_, symbol)| u8::try_from(symbol).ok())
+            .collect();
+        Self { counts, values }
+    }
+
+    fn write_dht(&self, out: &mut Vec<u8>, class_and_slot: u8) {
+        out.push(class_and_slot);
+        out.extend_from_slice(&self.counts);
+        out.extend_from_slice(&self.values);
+    }
+
+    /// Canonical code assignment (T.81 Annex C), yielding `(symbol, code, length)`.
+    fn codes(&self) -> impl Iterator<Item = (u8, u16, u8)> + '_ {
+        let mut symbols = self.values.iter().copied();
+        let mut code = 0u32;
+        let mut assigned = Vec::with_capacity(self.values.len());
+        for (length, &count) in (1u8..).zip(&self.counts) {
+            for _ in 0..count {
+                if let Some(symbol) = symbols.next() {
+                    assigned.push((symbol, u16::try_from(code).unwrap_or(u16::MAX), length));
+                }
+                code += 1;
+            }
+            code <<= 1;
+        }
+        assigned.into_iter()
+    }
+
+    fn decoder(&self) -> Result<HuffDecoder, JpegError> {
+        let mut lengths = [LengthEntry::default(); 16];
+        let mut code = 0i32;
+        let mut offset = 0i32;
+        for (entry, &count) in lengths.iter_mut().zip(&self.counts) {
+            let count = i32::from(count);
+            *entry = LengthEntry {
+                min_code: code,
+                max_code: code + count - 1,
+                offset,
+            };
+            code += count;
+            offset += count;
+            code <<= 1;
+        }
+        if usize::try_from(offset).ok() != Some(self.values.len()) || code > 1 << 17 {
+            return Err(JpegError::Corrupt("invalid Huffman table".to_string()));
+        }
+        Ok(HuffDecoder {
+            lengths,
+            values: self.values.clone(),
+        })
+    }
+
+    fn encoder(&self) -> HuffEncoder {
+        let mut codes = [(0u16, 0u8); 256];
+        for (symbol, code, length) in self.codes() {
+            if let Some(slot) = codes.get_mut(usize::from(symbol)) {
+                *slot = (code, length);
+            }
+        }
+        HuffEncoder { codes }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct LengthEntry {
+    min_code: i32,
+    max_code: i32,
+    offset: i32,
+}
+
+#[derive(Debug, Clone)]
+struct HuffDecoder {
+    lengths: [LengthEntry; 16],
+    values: Vec<u8>,
+}
+
+impl HuffDecoder {
+    fn decode(&self, reader: &mut BitReader<'_>) -> Result<u8, JpegError> {
+        let mut code = 0i32;
+        for entry in &self.lengths {
+            code = (code << 1) | i32::from(reader.bit());
+            if code <= entry.max_code {
+                let index = usize::try_from(entry.offset + code - entry.min_code).ok();
+                return index
+                    .and_then(|i| self.values.get(i).copied())
+                    .ok_or_else(|| JpegError::Corrupt("invalid Huffman code".to_string()));
+            }
+        }
+        Err(JpegError::Corrupt("invalid Huffman code".to_string()))
+    }
+}
+
+struct HuffEncoder {
+    codes: [(u16, u8); 256],
+}
+
+// ===== Bit I/O =====
+
+/// MSB-first bit reader over entropy-coded data. Stuffed `FF 00` pairs are unescaped;
+/// at a marker or the end of data it keeps returning zero bits.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u32,
+    bits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn new(data: &'a [u8]) -> Self {
+        Self {
+            data,
+            pos: 0,
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    fn fill(&mut self) {
+        while self.bits <= 24 {
+            let byte = match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+                (Some(0xFF), Some(0x00)) => {
+                    self.pos += 2;
+                    0xFF
+                }
+                (Some(0xFF) | None, _) => 0,
+                (Some(&byte), _) => {
+                    self.pos += 1;
+                    byte
+                }
+            };
+            self.acc |= u32::from(byte) << (24 - self.bits);
+            self.bits += 8;
+        }
+    }
+
+    fn bit(&mut self) -> u8 {
+        self.fill();
+        let bit = u8::from(self.acc & 0x8000_0000 != 0);
+        self.acc <<= 1;
+        self.bits -= 1;
+        bit
+    }
+
+    /// Reads `size` (0-16) bits as an unsigned value.
+    fn receive(&mut self, size: u8) -> u16 {
+        if size == 0 {
+            return 0;
+        }
+        self.fill();
+        let size = u32::from(size);
+        let value = self.acc >> (32 - size);
+        self.acc <<= size;
+        self.bits -= size;
+        u16::try_from(value).unwrap_or(u16::MAX)
+    }
+
+    /// Discards buffered bits and skips past the next RSTn marker.
+    fn restart(&mut self) {
+        self.acc = 0;
+        self.bits = 0;
+        while let Some(&byte) = self.data.get(self.pos) {
+            if byte == 0xFF && matches!(self.data.get(self.pos + 1), Some(0xD0..=0xD7)) {
+                self.pos += 2;
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+/// MSB-first bit writer that byte-stuffs `FF` and pads the final byte with ones.
+struct BitWriter {
+    out: Vec<u8>,
+    acc: u64,
+    bits: u32,
+}
+
+impl BitWriter {
+    fn new(out: Vec<u8>) -> Self {
+        Self {
+            out,
+            acc: 0,
+            bits: 0,
+        }
+    }
+
+    fn put_bits(&mut self, value: u32, size: u8) {
+        if size == 0 {
+            return;
+        }
+        let size = u32::from(size);
+        self.acc = (self.acc << size) | u64::from(value & ((1 << size) - 1));
+        self.bits += size;
+        while self.bits >= 8 {
+            self.bits -= 8;
+            let byte = (self.acc >> self.bits).to_le_bytes()[0];
+            self.out.push(byte);
+            if byte == 0xFF {
+                self.out.push(0x00);
+            }
+        }
+        self.acc &= (1 << self.bits) - 1;
+    }
+
+    fn put_symbol(&mut self, encoder: &HuffEncoder, symbol: u8) -> Result<(), JpegError> {
+        let &(code, length) = encoder
+            .codes
+            .get(usize::from(symbol))
+            .ok_or_else(|| JpegError::Corrupt("symbol out of range".to_string()))?;
+        if length == 0 {
+            return Err(JpegError::Corrupt(format!(
+                "no Huffman code for symbol {symbol:#04x}"
+            )));
+        }
+        self.put_bits(u32::from(code), length);
+        Ok(())
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.bits > 0 {
+            let pad = u8::try_from(8 - self.bits).unwrap_or(0);
+            self.put_bits(u32::MAX, pad);
+        }
+        self.out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // ===== Fixture Helpers =====
+
+    fn make_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img = image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([
+                u8::try_from((x * 7) % 256).unwrap(),
+                u8::try_from((y * 11) % 256).unwrap(),
+                u8::try_from(((x + y) * 5) % 256).unwrap(),
+            ])
+        });
+        let mut buf = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut buf), 90)
+            .encode_image(&img)
+            .unwrap();
+        buf
+    }
+
+    /// Builds a three-component image with the given luma sampling and deterministic
+    /// low-frequency coefficients, so subsampled layouts can be tested without an
+    /// external encoder.
+    fn make_subsampled(width: u16, height: u16, h: u8, v: u8) -> JpegImage {
+        let component = |id, h, v, quant_table| Component {
+            id,
+            h,
+            v,
+            quant_table,
+            blocks_w: 0,
+            blocks_h: 0,
+            blocks: Vec::new(),
+        };
+        let mut image = JpegImage {
+            width,
+            height,
+            components: vec![
+                component(1, h, v, 0),
+                component(2, 1, 1, 1),
+                component(3, 1, 1, 1),
+            ],
+            quant_tables: [Some([4; 64]), Some([6; 64]), None, None],
+            segments: Vec::new(),
+        };
+        image.allocate_blocks().unwrap();
+        for (c, component) in (0i16..).zip(&mut image.components) {
+            for (i, block) in (0i16..).zip(&mut component.blocks) {
+                block[0] = (i * 3 + c * 20) % 64 - 32;
+                block[1] = (i % 7) - 3;
+                block[8] = (i % 5) - 2;
+                block[9] = c - 1;
+            }
+        }
+        image
+    }
+
+    /// Writes `image` as a progressive JPEG holding an interleaved DC first pass with
+    /// point transform `al`, then an AC scan that would fail to decode as DC.
+    fn make_progressive(image: &JpegImage, al: u8) -> Vec<u8> {
+        let baseline = image.encode().unwrap();
+        let sos = baseline
+            .windows(2)
+            .position(|pair| pair == [0xFF, MARKER_SOS])
+            .unwrap();
+        let mut out = baseline[..sos].to_vec();
+        let sof = out
+            .windows(2)
+            .position(|pair| pair == [0xFF, MARKER_SOF0])
+            .unwrap();
+        out[sof + 1] = MARKER_SOF2;
+
+        let mut header = vec![u8::try_from(image.components.len()).unwrap()];
+        for (index, component) in image.components.iter().enumerate() {
+            header.extend_from_slice(&[component.id, u8::from(index > 0) << 4]);
+        }
+        header.extend_from_slice(&[0, 0, al]);
+        push_segment(&mut out, MARKER_SOS, &header).unwrap();
+
+        let table = |counts, values| HuffTable::new(counts, values).encoder();
+        let mut entropy = EntropyWriter {
+            writer: BitWriter::new(out),
+            encoders: [
+                table(&STD_DC_LUMA_COUNTS, &STD_DC_VALUES),
+                table(&STD_AC_LUMA_COUNTS, &STD_AC_LUMA_VALUES),
+                table(&STD_DC_CHROMA_COUNTS, &STD_DC_VALUES),
+                table(&STD_AC_CHROMA_COUNTS, &STD_AC_CHROMA_VALUES),
+            ],
+        };
+        let slots: Vec<usize> = (0..image.components.len()).collect();
+        let mut predictions = vec![0; slots.len()];
+        ScanGeometry::new(image, &slots)
+            .unwrap()
+            .walk(|_, slot, x, y| {
+                let dc = i32::from(image.components[slot].block(x, y).unwrap()[0]) >> al;
+                let table = TableClass::Dc(usize::from(slot > 0));
+                encode_dc(&mut entropy, dc, &mut predictions[slot], table)
+            })
+            .unwrap();
+
+        let mut out = entropy.writer.finish();
+        let id = image.components[0].id;
+        push_segment(&mut out, MARKER_SOS, &[1, id, 0, 1, 63, 0]).unwrap();
+        out.extend_from_slice(&[0xFF, 0x00, 0x12, 0xFF, MARKER_EOI]);
+        out
+    }
+
+    fn assert_same_coefficients(a: &JpegImage, b: &JpegImage) {
+        assert_eq!((a.width, a.height), (b.width, b.height));
+        assert_eq!(a.components.len(), b.components.len());
+        for (ca, cb) in a.components.iter().zip(&b.components) {
+            assert_eq!((ca.h, ca.v), (cb.h, cb.v));
+            let (w, h) = a.visible_blocks(ca);
+            for y in 0..h {
+                for x in 0..w {
+                    assert_eq!(ca.block(x, y), cb.block(x, y), "block ({x}, {y})");
+                }
+            }
+        }
+    }
+
+    // ===== Round Trip Tests =====
+
+    #[test]
+    fn reencode_is_pixel_identical() {
+        let original = make_jpeg(37, 21);
+        let reencoded = JpegImage::decode(&original).unwrap().encode().unwrap();
+
+        let a = image::load_from_memory(&original).unwrap().to_rgb8();
+        let b = image::load_from_memory(&reencoded).unwrap().to_rgb8();
+        assert_eq!(a.dimensions(), b.dimensions());
+        assert_eq!(a.as_raw(), b.as_raw());
+    }
+
+    #[test]
+    fn reencode_preserves_coefficients() {
+        let first = JpegImage::decode(&make_jpeg(40, 24)).unwrap();
+        let second = JpegImage::decode(&first.encode().unwrap()).unwrap();
+        assert_same_coefficients(&first, &second);
+        assert_eq!(first.quant_tables, second.quant_tables);
+    }
+
+    #[test]
+    fn subsampled_round_trip() {
+        for (h, v) in [(2, 2), (2, 1), (1, 2)] {
+            let image = make_subsampled(45, 27, h, v);
+            let encoded = image.encode().unwrap();
+            let decoded = JpegImage::decode(&encoded).unwrap();
+            assert_same_coefficients(&image, &decoded);
+
+            let pixels = image::load_from_memory(&encoded).unwrap();
+            assert_eq!((pixels.width(), pixels.height()), (45, 27));
+        }
+    }
+
+    #[test]
+    fn grayscale_round_trip() {
+        let gray = image::GrayImage::from_fn(19, 13, |x, y| {
+            image::Luma([u8::try_from((x * 13 + y * 3) % 256).unwrap()])
+        });
+        let mut original = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut original), 85)
+            .encode_image(&gray)
+            .unwrap();
+
+        let parsed = JpegImage::decode(&original).unwrap();
+        assert_eq!(parsed.components.len(), 1);
+        let reencoded = parsed.encode().unwrap();
+        let a = image::load_from_memory(&original).unwrap().to_luma8();
+        let b = image::load_from_memory(&reencoded).unwrap().to_luma8();
+        assert_eq!(a.as_raw(), b.as_raw());
+    }
+
+    #[test]
+    fn metadata_segments_preserved() {
+        let mut jpeg = make_jpeg(16, 16);
+        let comment = [0xFF, MARKER_COM, 0x00, 0x07, b'h', b'e', b'l', b'l', b'o'];
+        jpeg.splice(2..2, comment);
+
+        let parsed = JpegImage::decode(&jpeg).unwrap();
+        assert!(parsed.segments.contains(&Segment {
+            marker: MARKER_COM,
+            data: b"hello".to_vec(),
+        }));
+
+        let reencoded = parsed.encode().unwrap();
+        assert!(reencoded.windows(comment.len()).any(|w| w == comment));
+    }
+
+    // ===== Error Case Tests =====
+
+    #[test]
+    fn decode_rejects_non_jpeg() {
+        let png = {
+            let mut buf = Vec::new();
+            image::DynamicImage::ImageRgb8(image::RgbImage::new(4, 4))
+                .write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+                .unwrap();
+            buf
+        };
+        assert!(matches!(JpegImage::decode(&png), Err(JpegError::NotJpeg)));
+        assert!(matches!(JpegImage::decode(&[]), Err(JpegError::NotJpeg)));
+    }
+
+    #[test]
+    fn decode_rejects_progressive() {
+        let mut jpeg = vec![
+            0xFF,
+            MARKER_SOI,
+            0xFF,
+            MARKER_SOF2,
+            0x00,
+            0x0B,
+            8,
+            0,
+            8,
+            0,
+            8,
+            1,
+        ];
+        jpeg.extend_from_slice(&[1, 0x11, 0, 0xFF, MARKER_EOI]);
+        assert!(matches!(
+            JpegImage::decode(&jpeg),
+            Err(JpegError::Unsupported(_))
+        ));
+    }
+
+    #[test]
+    fn decode_truncated_segment() {
+        let jpeg = make_jpeg(16, 16);
+        let result = JpegImage::decode(jpeg.get(..40).unwrap());
+        assert!(matches!(result, Err(JpegError::Corrupt(_))));
+    }
+
+    // ===== Quality Estimation Tests =====
+
+    fn make_jpeg_at(quality: u8) -> Vec<u8> {
+        let img = image::RgbImage::from_fn(16, 16, |x, y| {
+            image::Rgb([
+                u8::try_from(x * 16).unwrap(),
+                u8::try_from(y * 16).unwrap(),
+                128,
+            ])
+        });
+        let mut buf = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut buf), quality)
+            .encode_image(&img)
+            .unwrap();
+        buf
+    }
+
+    #[test]
+    fn estimate_matches_encoder_quality() {
+        for quality in [10, 35, 50, 60, 75, 90, 95, 100] {
+            let estimate = estimate_quality(&make_jpeg_at(quality)).unwrap();
+            assert_eq!(estimate.quality, quality);
+            assert!(estimate.exact, "quality {quality} should match exactly");
+        }
+    }
+
+    #[test]
+    fn estimate_custom_tables_is_approximate() {
+        let mut image = JpegImage::decode(&make_jpeg_at(75)).unwrap();
+        for table in image.quant_tables.iter_mut().flatten() {
+            table[0] += 3;
+            table[63] -= 2;
+        }
+        let estimate = estimate_quality(&image.encode().unwrap()).unwrap();
+        assert!(!estimate.exact);
+        assert!(
+            (73..=77).contains(&estimate.quality),
+            "got {}",
+            estimate.quality
+        );
+    }
+
+    #[test]
+    fn estimate_grayscale() {
+        let gray = image::GrayImage::from_pixel(8, 8, image::Luma([90]));
+        let mut buf = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut buf), 42)
+            .encode_image(&gray)
+            .unwrap();
+        assert_eq!(estimate_quality(&buf).unwrap().quality, 42);
+    }
+
+    #[test]
+    fn estimate_rejects_non_jpeg() {
+        assert!(matches!(
+            estimate_quality(b"GIF89a"),
+            Err(JpegError::NotJpeg)
+        ));
+        let headerless = [0xFF, MARKER_SOI, 0xFF, MARKER_EOI];
+        assert!(matches!(
+            estimate_quality(&headerless),
+            Err(JpegError::Corrupt(_))
+        ));
+    }
+
+    // ===== Comment Tests =====
+
+    #[test]
+    fn set_and_read_comment() {
+        let original = make_jpeg(16, 16);
+        assert!(comments(&original).unwrap().is_empty());
+
+        let tagged = set_comment(&original, Some("pipeline v2.3.1")).unwrap();
+        assert_eq!(
+            comments(&tagged).unwrap(),
+            vec!["pipeline v2.3.1".to_string()]
+        );
+
+        // Pixels untouched: everything from the first scan on is byte-identical.
+        let scan = |data: &[u8]| {
+            data.get(header_segments(data).unwrap().1..)
+                .unwrap()
+                .to_vec()
+        };
+        assert_eq!(scan(&original), scan(&tagged));
+    }
+
+    #[test]
+    fn set_comment_replaces_existing() {
+        let once = set_comment(&make_jpeg(16, 16), Some("first")).unwrap();
+        let twice = set_comment(&once, Some("second")).unwrap();
+        assert_eq!(comments(&twice).unwrap(), vec!["second".to_string()]);
+
+        let cleared = set_comment(&twice, None).unwrap();
+        assert!(comments(&cleared).unwrap().is_empty());
+    }
+
+    #[test]
+    fn comment_follows_app_segments() {
+        let original = make_jpeg(16, 16);
+        let tagged = set_comment(&original, Some("hi")).unwrap();
+        let (segments, _) = header_segments(&tagged).unwrap();
+        let markers: Vec<u8> = segments.iter().map(|s| s.marker).collect();
+        let com = markers.iter().position(|&m| m == MARKER_COM).unwrap();
+        assert!(markers.iter().take(com).all(|m| (0xE0..=0xEF).contains(m)));
+        assert!(markers
+            .iter()
+            .skip(com + 1)
+            .all(|m| !(0xE0..=0xEF).contains(m)));
+        image::load_from_memory(&tagged).unwrap();
+    }
+
+    #[test]
+    fn comment_too_long_rejected() {
+        let long = "x".repeat(70_000);
+        assert!(matches!(
+            set_comment(&make_jpeg(8, 8), Some(&long)),
+            Err(JpegError::CommentTooLong(70_000))
+        ));
+    }
+
+    // ===== Orientation Tests =====
+
+    fn exif_orientation(data: &[u8]) -> Option<u32> {
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(data))
+            .ok()?;
+        exif.get_field(exif::Tag::Orientation, exif::In::PRIMARY)?
+            .value
+            .get_uint(0)
+    }
+
+    #[test]
+    fn orientation_added_when_missing() {
+        let original = make_jpeg(16, 8);
+        assert_eq!(orientation(&original).unwrap(), None);
+
+        let tagged = set_orientation(&original, 6).unwrap();
+        assert_eq!(orientation(&tagged).unwrap(), Some(6));
+        assert_eq!(exif_orientation(&tagged), Some(6));
+        // The EXIF segment follows the JFIF header.
+        let (segments, _) = header_segments(&tagged).unwrap();
+        let markers: Vec<u8> = segments.iter().map(|s| s.marker).take(2).collect();
+        assert_eq!(markers, [MARKER_APP0, MARKER_APP1]);
+
+        let scan = |data: &[u8]| {
+            data.get(header_segments(data).unwrap().1..)
+                .unwrap()
+                .to_vec()
+        };
+        assert_eq!(scan(&original), scan(&tagged));
+        assert_eq!(
+            image::load_from_memory(&tagged).unwrap().to_rgb8(),
+            image::load_from_memory(&original).unwrap().to_rgb8()
+        );
+    }
+
+    #[test]
+    fn orientation_overwritten_in_place() {
+        let tagged = set_orientation(&make_jpeg(8, 8), 3).unwrap();
+        let retagged = set_orientation(&tagged, 8).unwrap();
+        assert_eq!(retagged.len(), tagged.len());
+        assert_eq!(orientation(&retagged).unwrap(), Some(8));
+        assert_eq!(exif_orientation(&retagged), Some(8));
+    }
+
+    #[test]
+    fn orientation_joins_existing_exif() {
+        // Little-endian EXIF with Make (0x010F, stored past the IFD) and Software (0x0131).
+        let mut tiff = b"II\x2a\0\x08\0\0\0\x02\0".to_vec();
+        tiff.extend_from_slice(&[0x0F, 0x01, 2, 0, 5, 0, 0, 0, 38, 0, 0, 0]);
+        tiff.extend_from_slice(&[0x31, 0x01, 2, 0, 3, 0, 0, 0, b'v', b'2', 0, 0]);
+        tiff.extend_from_slice(&[0; 4]);
+        tiff.extend_from_slice(b"Acme\0\0");
+        let mut payload = EXIF_HEADER.to_vec();
+        payload.extend(tiff);
+        let original = make_jpeg(8, 8);
+        let mut with_exif = original.get(..2).unwrap().to_vec();
+        push_segment(&mut with_exif, MARKER_APP1, &payload).unwrap();
+        with_exif.extend_from_slice(original.get(2..).unwrap());
+
+        let tagged = set_orientation(&with_exif, 5).unwrap();
+        assert_eq!(orientation(&tagged).unwrap(), Some(5));
+        let exif = exif::Reader::new()
+            .read_from_container(&mut Cursor::new(&tagged))
+            .unwrap();
+        let text = |tag| {
+            exif.get_field(tag, exif::In::PRIMARY)
+                .unwrap()
+                .display_value()
+                .to_string()
+        };
+        assert_eq!(text(exif::Tag::Make), "\"Acme\"");
+        assert_eq!(text(exif::Tag::Software), "\"v2\"");
+        assert_eq!(exif_orientation(&tagged), Some(5));
+    }
+
+    #[test]
+    fn invalid_orientation_rejected() {
+        assert!(matches!(
+            set_orientation(&make_jpeg(8, 8), 9),
+            Err(JpegError::InvalidOrientation(9))
+        ));
+    }
+
+    // ===== Coding Mode Tests =====
+
+    #[test]
+    fn detects_progressive_frames() {
+        let mut jpeg = make_jpeg(8, 8);
+        assert!(!is_progressive(&jpeg).unwrap());
+
+        let sof = jpeg
+            .windows(2)
+            .position(|pair| pair == [0xFF, MARKER_SOF0])
+            .unwrap();
+        jpeg[sof + 1] = MARKER_SOF2;
+        assert!(is_progressive(&jpeg).unwrap());
+        assert!(matches!(is_progressive(&[0, 1]), Err(JpegError::NotJpeg)));
+    }
+
+    // ===== Preview Tests =====
+
+    #[test]
+    fn preview_draws_gray_dc_per_block() {
+        let mut image = JpegImage {
+            width: 20,
+            height: 12,
+            components: vec![Component {
+                id: 1,
+                h: 1,
+                v: 1,
+                quant_table: 0,
+                blocks_w: 0,
+                blocks_h: 0,
+                blocks: Vec::new(),
+            }],
+            quant_tables: [Some([4; 64]), None, None, None],
+            segments: Vec::new(),
+        };
+        image.allocate_blocks().unwrap();
+        for (i, block) in (0i16..).zip(&mut image.components[0].blocks) {
+            block[0] = i * 40 - 100;
+            block[1] = 7;
+        }
+
+        let preview = first_scan_preview(&make_progressive(&image, 1)).unwrap();
+        assert_eq!(preview.dimensions(), (3, 2));
+        for (x, y, pixel) in preview.enumerate_pixels() {
+            let dc = i32::from(
+                image.components[0]
+                    .block(usize::try_from(x).unwrap(), usize::try_from(y).unwrap())
+                    .unwrap()[0],
+            );
+            let gray = clamp_sample((dc * 4 + 4).div_euclid(8) + 128);
+            assert_eq!(pixel.0, [gray, gray, gray, 255], "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn preview_upsamples_subsampled_chroma() {
+        let image = make_subsampled(40, 24, 2, 2);
+        let preview = first_scan_preview(&make_progressive(&image, 0)).unwrap();
+        assert_eq!(preview.dimensions(), (5, 3));
+
+        let level = |slot: usize, x: u32, y: u32| {
+            let block = image.components[slot]
+                .block(usize::try_from(x).unwrap(), usize::try_from(y).unwrap())
+                .unwrap();
+            let quant = i32::from(
+                image.quant_tables[usize::from(image.components[slot].quant_table)].unwrap()[0],
+            );
+            clamp_sample((i32::from(block[0]) * quant + 4).div_euclid(8) + 128)
+        };
+        for (x, y, pixel) in preview.enumerate_pixels() {
+            let rgb = ycbcr_to_rgb(
+                level(0, x, y),
+                level(1, x / 2, y / 2),
+                level(2, x / 2, y / 2),
+            );
+            assert_eq!(pixel.0[..3], rgb, "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn preview_matches_block_averages() {
+        let solid = image::RgbImage::from_pixel(32, 16, image::Rgb([200, 100, 50]));
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut jpeg), 90)
+            .encode_image(&solid)
+            .unwrap();
+        let image = JpegImage::decode(&jpeg).unwrap();
+
+        let preview = first_scan_preview(&make_progressive(&image, 0)).unwrap();
+        assert_eq!(preview.dimensions(), (4, 2));
+        for pixel in preview.pixels() {
+            for (&got, want) in pixel.0.iter().zip([200u8, 100, 50, 255]) {
+                assert!(got.abs_diff(want) <= 3, "{:?}", pixel.0);
+            }
+        }
+    }
+
+    #[test]
+    fn dc_decode_matches_progressive_preview() {
+        let image = make_subsampled(40, 24, 2, 2);
+        let sequential = decode_dc(&image.encode().unwrap()).unwrap();
+        let progressive = make_progressive(&image, 0);
+        assert_eq!(sequential.dimensions(), (5, 3));
+        assert_eq!(sequential, first_scan_preview(&progressive).unwrap());
+        assert_eq!(sequential, decode_dc(&progressive).unwrap());
+
+        let solid = image::RgbImage::from_pixel(30, 17, image::Rgb([200, 100, 50]));
+        let mut jpeg = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut jpeg), 90)
+            .encode_image(&solid)
+            .unwrap();
+        let decoded = decode_dc(&jpeg).unwrap();
+        assert_eq!(decoded.dimensions(), (4, 3));
+        for pixel in decoded.pixels() {
+            for (&got, want) in pixel.0.iter().zip([200u8, 100, 50, 255]) {
+                assert!(got.abs_diff(want) <= 3, "{:?}", pixel.0);
+            }
+        }
+        assert!(matches!(decode_dc(&[0, 1]), Err(JpegError::NotJpeg)));
+    }
+
+    #[test]
+    fn progressive_encode_starts_with_the_dc_scan() {
+        let image = make_subsampled(40, 24, 2, 1);
+        let progressive = image.encode_progressive().unwrap();
+        assert!(is_progressive(&progressive).unwrap());
+        assert_eq!(
+            first_scan_preview(&progressive).unwrap(),
+            decode_dc(&image.encode().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn preview_rejects_sequential_files() {
+        assert!(matches!(
+            first_scan_preview(&make_jpeg(8, 8)),
+            Err(JpegError::Unsupported(_))
+        ));
+        assert!(matches!(
+            first_scan_preview(&[0, 1]),
+            Err(JpegError::NotJpeg)
+        ));
+    }
+
+    // ===== Entropy Coding Tests =====
+
+    #[test]
+    fn optimized_encode_round_trips() {
+        let image = JpegImage::decode(&make_jpeg(64, 40)).unwrap();
+        let optimized = image.encode_optimized().unwrap();
+        assert!(optimized.len() < image.encode().unwrap().len());
+        assert_same_coefficients(&image, &JpegImage::decode(&optimized).unwrap());
+    }
+
+    #[test]
+    fn optimal_table_limits_code_length() {
+        // Fibonacci-like frequencies produce a maximally skewed tree (depth > 16).
+        let mut frequencies = [0u32; 256];
+        let (mut a, mut b) = (1u32, 1u32);
+        for frequency in frequencies.iter_mut().take(30) {
+            *frequency = a;
+            (a, b) = (b, a.saturating_add(b));
+        }
+        let table = HuffTable::optimal(&frequencies);
+        assert_eq!(table.values.len(), 30);
+        let total: usize = table.counts.iter().map(|&n| usize::from(n)).sum();
+        assert_eq!(total, 30);
+
+        // Kraft sum must leave room (no all-ones code): sum(2^-len) < 1.
+        let kraft: u32 = (0..16u32)
+            .zip(&table.counts)
+            .map(|(i, &n)| u32::from(n) << (15 - i))
+            .sum();
+        assert!(kraft < 1 << 16);
+        table.decoder().unwrap();
+    }
+
+    #[test]
+    fn magnitude_bits_round_trip() {
+        for value in [-2047, -256, -3, -1, 1, 2, 255, 1024] {
+            let size = magnitude_category(value);
+            let bits = u16::try_from(magnitude_bits(value, size)).unwrap();
+            assert_eq!(extend(bits, size), value, "value {value}");
+        }
+        assert_eq!(magnitude_category(0), 0);
+    }
+}
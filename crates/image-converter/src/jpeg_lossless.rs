@@ -0,0 +1,788 @@
+use serde::Serialize;
+
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::jpeg::{self, Block, JpegError, JpegImage};
+
+/// Rotations that can be applied to JPEG coefficients without re-quantizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    Rotate90,
+    Rotate180,
+    Rotate270,
+}
+
+impl Rotation {
+    /// Parses a clockwise rotation angle in degrees (90, 180 or 270).
+    ///
+    /// # Errors
+    ///
+    /// Returns `LosslessError::InvalidRotation` for any other angle.
+    pub fn from_degrees(degrees: u32) -> Result<Self, LosslessError> {
+        match degrees {
+            90 => Ok(Self::Rotate90),
+            180 => Ok(Self::Rotate180),
+            270 => Ok(Self::Rotate270),
+            other => Err(LosslessError::InvalidRotation(other)),
+        }
+    }
+
+    /// Whether the rotation swaps width and height.
+    fn transposes(self) -> bool {
+        matches!(self, Self::Rotate90 | Self::Rotate270)
+    }
+
+    /// Clockwise quarter turns.
+    fn quarter_turns(self) -> u8 {
+        match self {
+            Self::Rotate90 => 1,
+            Self::Rotate180 => 2,
+            Self::Rotate270 => 3,
+        }
+    }
+}
+
+/// Each EXIF orientation (1-8, by index) as whether the stored image is mirrored
+/// left-to-right for display, then the clockwise quarter turns applied after that.
+const ORIENTATIONS: [(bool, u8); 8] = [
+    (false, 0),
+    (true, 0),
+    (false, 2),
+    (true, 2),
+    (true, 3),
+    (false, 1),
+    (true, 1),
+    (false, 3),
+];
+
+/// Losslessly shrinks a JPEG by rewriting its entropy coding with optimized Huffman
+/// tables (like `jpegtran -optimize`), or with `progressive`, as a progressive JPEG
+/// (like `jpegtran -progressive`, see [`JpegImage::encode_progressive`]).
+///
+/// The DCT coefficients are untouched, so decoded pixels are identical to the input.
+/// Metadata segments are kept and restart markers are dropped. If the rewritten file
+/// would not be smaller, the input is returned unchanged.
+///
+/// # Errors
+///
+/// Returns `LosslessError::Jpeg` if the input is not a baseline or extended
+/// sequential JPEG.
+pub fn optimize_jpeg(input: &[u8], progressive: bool) -> Result<Vec<u8>, LosslessError> {
+    let image = JpegImage::decode(input).map_err(LosslessError::Jpeg)?;
+    let optimized = if progressive {
+        image.encode_progressive()
+    } else {
+        image.encode_optimized()
+    }
+    .map_err(LosslessError::Jpeg)?;
+    if optimized.len() < input.len() {
+        Ok(optimized)
+    } else {
+        Ok(input.to_vec())
+    }
+}
+
+/// Rotates a JPEG clockwise by 90, 180 or 270 degrees without decoding to pixels.
+///
+/// Works like `jpegtran -rotate N -trim -optimize`: blocks are rearranged and their DCT
+/// coefficients transposed/negated, so the image content suffers no generation loss.
+/// Any partial MCU on an edge that would end up on the top or left after rotation is
+/// trimmed (at most 15 pixels), since it cannot be moved losslessly.
+///
+/// APPn and COM segments (EXIF, ICC, comments) are kept unchanged, including the
+/// EXIF orientation tag.
+///
+/// # Errors
+///
+/// Returns `LosslessError::InvalidRotation` for angles other than 90/180/270,
+/// `LosslessError::Jpeg` if the input is not a supported sequential JPEG, and
+/// `LosslessError::TooSmall` if trimming would leave no pixels.
+pub fn rotate_jpeg_lossless(input: &[u8], degrees: u32) -> Result<Vec<u8>, LosslessError> {
+    let rotation = Rotation::from_degrees(degrees)?;
+    let image = JpegImage::decode(input).map_err(LosslessError::Jpeg)?;
+    rotate(&image, rotation)?
+        .encode_optimized()
+        .map_err(LosslessError::Jpeg)
+}
+
+/// Rotates a JPEG clockwise by 90, 180 or 270 degrees by updating its EXIF orientation
+/// tag, without touching the image data.
+///
+/// This is instant at any size, for "rotate" buttons on large photos whose rotation is
+/// baked in later. The new orientation combines the rotation with the existing tag, so
+/// a mirrored image stays mirrored. Viewers that ignore EXIF orientation, and decoders
+/// in this crate, still see the stored pixels unrotated.
+///
+/// # Errors
+///
+/// Returns `LosslessError::InvalidRotation` for angles other than 90/180/270 and
+/// `LosslessError::Jpeg` if the input is not a JPEG or its headers are malformed.
+pub fn rotate_jpeg_orientation(input: &[u8], degrees: u32) -> Result<Vec<u8>, LosslessError> {
+    let rotation = Rotation::from_degrees(degrees)?;
+    let current = jpeg::orientation(input).map_err(LosslessError::Jpeg)?;
+    jpeg::set_orientation(input, rotate_orientation(current.unwrap_or(1), rotation))
+        .map_err(LosslessError::Jpeg)
+}
+
+/// The EXIF orientation that shows an image stored with `orientation` rotated by
+/// `rotation`. Unknown orientations are treated as 1 (upright).
+fn rotate_orientation(orientation: u16, rotation: Rotation) -> u16 {
+    let (mirrored, turns) = usize::from(orientation)
+        .checked_sub(1)
+        .and_then(|index| ORIENTATIONS.get(index))
+        .copied()
+        .unwrap_or((false, 0));
+    let rotated = (mirrored, (turns + rotation.quarter_turns()) % 4);
+    (1u16..)
+        .zip(ORIENTATIONS)
+        .find_map(|(value, entry)| (entry == rotated).then_some(value))
+        .unwrap_or(1)
+}
+
+/// Rotates an already-parsed JPEG at the coefficient level.
+///
+/// # Errors
+///
+/// Returns `LosslessError::TooSmall` if trimming partial MCUs would leave no pixels.
+pub fn rotate(image: &JpegImage, rotation: Rotation) -> Result<JpegImage, LosslessError> {
+    let (mcu_w, mcu_h) = image.mcu_size();
+    let trim_width = matches!(rotation, Rotation::Rotate180 | Rotation::Rotate270);
+    let trim_height = matches!(rotation, Rotation::Rotate90 | Rotation::Rotate180);
+    let width = if trim_width {
+        trim_to_mcu(image.width, mcu_w)?
+    } else {
+        image.width
+    };
+    let height = if trim_height {
+        trim_to_mcu(image.height, mcu_h)?
+    } else {
+        image.height
+    };
+
+    let mut output = JpegImage {
+        width,
+        height,
+        components: image.components.clone(),
+        quant_tables: image.quant_tables,
+        segments: image.segments.clone(),
+    };
+    if rotation.transposes() {
+        output.width = height;
+        output.height = width;
+        for component in &mut output.components {
+            std::mem::swap(&mut component.h, &mut component.v);
+        }
+        for table in output.quant_tables.iter_mut().flatten() {
+            *table = transpose_table(table);
+        }
+    }
+    output.allocate_blocks().map_err(LosslessError::Jpeg)?;
+
+    let visible: Vec<(usize, usize)> = output
+        .components
+        .iter()
+        .map(|component| output.visible_blocks(component))
+        .collect();
+    for ((source, target), &(visible_w, visible_h)) in image
+        .components
+        .iter()
+        .zip(output.components.iter_mut())
+        .zip(&visible)
+    {
+        for y in 0..visible_h {
+            for x in 0..visible_w {
+                // Map each output block back to the input block it came from; the
+                // trimmed input extents equal the output extents (swapped if transposed).
+                let (source_x, source_y) = match rotation {
+                    Rotation::Rotate90 => (y, visible_w - 1 - x),
+                    Rotation::Rotate180 => (visible_w - 1 - x, visible_h - 1 - y),
+                    Rotation::Rotate270 => (visible_h - 1 - y, x),
+                };
+                let block = source.block(source_x, source_y).ok_or_else(|| {
+                    LosslessError::Jpeg(JpegError::Corrupt(
+                        "block outside component grid".to_string(),
+                    ))
+                })?;
+                if let Some(slot) = target.block_mut(x, y) {
+                    *slot = rotate_block(block, rotation);
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// A rectangle in pixel coordinates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct CropRegion {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Result of [`crop_jpeg`].
+#[derive(Debug, Clone)]
+pub struct CroppedJpeg {
+    pub data: Vec<u8>,
+    /// The region actually cropped, after snapping to MCU boundaries.
+    pub region: CropRegion,
+    /// Whether the crop kept the original DCT coefficients (no re-encoding).
+    pub lossless: bool,
+}
+
+/// Crops a JPEG, dropping whole blocks instead of re-encoding where possible.
+///
+/// Like `jpegtran -crop`, the top-left corner is snapped up/left to the nearest MCU
+/// boundary (8 or 16 px depending on chroma subsampling) and the size grows to keep
+/// the requested area covered; the right/bottom edges can fall anywhere. The returned
+/// `region` reports what was actually cropped.
+///
+/// When `exact` is set and the corner is not already MCU-aligned, the image is instead
+/// decoded, cropped to exactly the requested pixels, and re-encoded at `quality`
+/// (default 80) — `lossless` is `false` in that case.
+///
+/// # Errors
+///
+/// Returns `LosslessError::InvalidCrop` if the region is empty or extends past the
+/// image, `LosslessError::Jpeg` if the input is not a supported sequential JPEG, and
+/// `LosslessError::Fallback` if the lossy path fails.
+pub fn crop_jpeg(
+    input: &[u8],
+    region: CropRegion,
+    exact: bool,
+    quality: Option<u8>,
+) -> Result<CroppedJpeg, LosslessError> {
+    let image = JpegImage::decode(input).map_err(LosslessError::Jpeg)?;
+    let in_bounds = region
+        .x
+        .checked_add(region.width)
+        .is_some_and(|right| right <= u32::from(image.width))
+        && region
+            .y
+            .checked_add(region.height)
+            .is_some_and(|bottom| bottom <= u32::from(image.height));
+    if region.width == 0 || region.height == 0 || !in_bounds {
+        return Err(LosslessError::InvalidCrop(region));
+    }
+
+    let snapped = snap_to_mcu(&image, region);
+    if exact && snapped != region {
+        let decoded = image::load_from_memory(input)
+            .map_err(|e| LosslessError::Fallback(ConvertError::Decode(e)))?;
+        let cropped = decoded.crop_imm(region.x, region.y, region.width, region.height);
+        let data = convert::encode(&cropped, ImageFormat::Jpeg, quality)
+            .map_err(LosslessError::Fallback)?;
+        return Ok(CroppedJpeg {
+            data,
+            region,
+            lossless: false,
+        });
+    }
+
+    let data = crop(&image, snapped)?
+        .encode_optimized()
+        .map_err(LosslessError::Jpeg)?;
+    Ok(CroppedJpeg {
+        data,
+        region: snapped,
+        lossless: true,
+    })
+}
+
+/// Moves the region's top-left corner up/left to an MCU boundary, growing the size so
+/// the original area stays covered.
+pub fn snap_to_mcu(image: &JpegImage, region: CropRegion) -> CropRegion {
+    let (mcu_w, mcu_h) = image.mcu_size();
+    let x = region.x - region.x % mcu_w;
+    let y = region.y - region.y % mcu_h;
+    CropRegion {
+        x,
+        y,
+        width: region.width + (region.x - x),
+        height: region.height + (region.y - y),
+    }
+}
+
+/// Crops an already-parsed JPEG at the coefficient level.
+///
+/// # Errors
+///
+/// Returns `LosslessError::InvalidCrop` if the region's corner is not MCU-aligned or
+/// the region does not fit inside the image.
+pub fn crop(image: &JpegImage, region: CropRegion) -> Result<JpegImage, LosslessError> {
+    let (mcu_w, mcu_h) = image.mcu_size();
+    let invalid = || LosslessError::InvalidCrop(region);
+    if !region.x.is_multiple_of(mcu_w) || !region.y.is_multiple_of(mcu_h) {
+        return Err(invalid());
+    }
+    let width = u16::try_from(region.width).map_err(|_| invalid())?;
+    let height = u16::try_from(region.height).map_err(|_| invalid())?;
+    let fits = region.x + region.width <= u32::from(image.width)
+        && region.y + region.height <= u32::from(image.height);
+    if width == 0 || height == 0 || !fits {
+        return Err(invalid());
+    }
+
+    let mut output = JpegImage {
+        width,
+        height,
+        components: image.components.clone(),
+        quant_tables: image.quant_tables,
+        segments: image.segments.clone(),
+    };
+    output.allocate_blocks().map_err(LosslessError::Jpeg)?;
+
+    let mcu_x = usize::try_from(region.x / mcu_w).map_err(|_| invalid())?;
+    let mcu_y = usize::try_from(region.y / mcu_h).map_err(|_| invalid())?;
+    let single = image.components.len() == 1;
+    let visible: Vec<(usize, usize)> = output
+        .components
+        .iter()
+        .map(|component| output.visible_blocks(component))
+        .collect();
+    for ((source, target), &(visible_w, visible_h)) in image
+        .components
+        .iter()
+        .zip(output.components.iter_mut())
+        .zip(&visible)
+    {
+        let (offset_x, offset_y) = if single {
+            (mcu_x, mcu_y)
+        } else {
+            (mcu_x * usize::from(source.h), mcu_y * usize::from(source.v))
+        };
+        for y in 0..visible_h {
+            for x in 0..visible_w {
+                let block = source.block(x + offset_x, y + offset_y).ok_or_else(|| {
+                    LosslessError::Jpeg(JpegError::Corrupt(
+                        "block outside component grid".to_string(),
+                    ))
+                })?;
+                if let Some(slot) = target.block_mut(x, y) {
+                    *slot = *block;
+                }
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Rounds a dimension down to a whole number of MCUs.
+fn trim_to_mcu(size: u16, mcu: u32) -> Result<u16, LosslessError> {
+    let mcu = u16::try_from(mcu).map_err(|_| LosslessError::TooSmall)?;
+    let trimmed = size - size % mcu.max(1);
+    if trimmed == 0 {
+        return Err(LosslessError::TooSmall);
+    }
+    Ok(trimmed)
+}
+
+/// Rotates one block of coefficients.
+///
+/// A transpose swaps the horizontal and vertical frequencies; a mirror negates every
+/// odd frequency along the mirrored axis. 90° is transpose + horizontal mirror,
+/// 270° is transpose + vertical mirror, and 180° mirrors both axes.
+fn rotate_block(block: &Block, rotation: Rotation) -> Block {
+    let mut out = [0i16; 64];
+    for (index, value) in out.iter_mut().enumerate() {
+        let (row, col) = (index / 8, index % 8);
+        let (source, negate) = match rotation {
+            Rotation::Rotate90 => (col * 8 + row, col % 2 == 1),
+            Rotation::Rotate180 => (index, (row + col) % 2 == 1),
+            Rotation::Rotate270 => (col * 8 + row, row % 2 == 1),
+        };
+        let coefficient = block.get(source).copied().unwrap_or(0);
+        *value = if negate {
+            coefficient.saturating_neg()
+        } else {
+            coefficient
+        };
+    }
+    out
+}
+
+fn transpose_table(table: &[u16; 64]) -> [u16; 64] {
+    let mut out = [0u16; 64];
+    for (index, value) in out.iter_mut().enumerate() {
+        let (row, col) = (index / 8, index % 8);
+        *value = table.get(col * 8 + row).copied().unwrap_or(1);
+    }
+    out
+}
+
+/// Errors that can occur during lossless JPEG transforms.
+#[derive(Debug)]
+pub enum LosslessError {
+    /// The input could not be parsed or written as a sequential JPEG.
+    Jpeg(JpegError),
+    /// Rotation angle other than 90, 180 or 270 degrees.
+    InvalidRotation(u32),
+    /// The image is smaller than one MCU along an edge that has to be trimmed.
+    TooSmall,
+    /// Crop region is empty, outside the image, or (for coefficient crops) not MCU-aligned.
+    InvalidCrop(CropRegion),
+    /// The lossy fallback for an exact crop failed to decode or encode.
+    Fallback(ConvertError),
+}
+
+impl std::fmt::Display for LosslessError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Jpeg(e) => write!(f, "{e}"),
+            Self::InvalidRotation(degrees) => {
+                write!(f, "Rotation must be 90, 180 or 270 degrees, got {degrees}")
+            }
+            Self::TooSmall => write!(
+                f,
+                "Image is smaller than one MCU along an edge that must be trimmed"
+            ),
+            Self::InvalidCrop(region) => write!(
+                f,
+                "Invalid crop region {}x{} at ({}, {})",
+                region.width, region.height, region.x, region.y
+            ),
+            Self::Fallback(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for LosslessError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::jpeg::Component;
+    use std::io::Cursor;
+
+    // ===== Fixture Helpers =====
+
+    fn make_jpeg(width: u32, height: u32) -> Vec<u8> {
+        let img = image::RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([
+                u8::try_from((x * 9) % 256).unwrap(),
+                u8::try_from((y * 13) % 256).unwrap(),
+                u8::try_from(((x * y) / 3) % 256).unwrap(),
+            ])
+        });
+        let mut buf = Vec::new();
+        image::codecs::jpeg::JpegEncoder::new_with_quality(Cursor::new(&mut buf), 95)
+            .encode_image(&img)
+            .unwrap();
+        buf
+    }
+
+    /// Builds a three-component image with the given luma sampling and deterministic
+    /// low-frequency coefficients, so subsampled layouts can be tested without an
+    /// external encoder.
+    fn make_subsampled(width: u16, height: u16, h: u8, v: u8) -> JpegImage {
+        let component = |id, h, v, quant_table| Component {
+            id,
+            h,
+            v,
+            quant_table,
+            blocks_w: 0,
+            blocks_h: 0,
+            blocks: Vec::new(),
+        };
+        let mut image = JpegImage {
+            width,
+            height,
+            components: vec![
+                component(1, h, v, 0),
+                component(2, 1, 1, 1),
+                component(3, 1, 1, 1),
+            ],
+            quant_tables: [Some([4; 64]), Some([6; 64]), None, None],
+            segments: Vec::new(),
+        };
+        image.allocate_blocks().unwrap();
+        for (c, component) in (0i16..).zip(&mut image.components) {
+            for (i, block) in (0i16..).zip(&mut component.blocks) {
+                block[0] = (i * 3 + c * 20) % 64 - 32;
+                block[1] = (i % 7) - 3;
+                block[8] = (i % 5) - 2;
+                block[9] = c - 1;
+            }
+        }
+        image
+    }
+
+    fn max_difference(a: &image::RgbImage, b: &image::RgbImage) -> u8 {
+        assert_eq!(a.dimensions(), b.dimensions());
+        a.as_raw()
+            .iter()
+            .zip(b.as_raw())
+            .map(|(x, y)| x.abs_diff(*y))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn decode_rgb(data: &[u8]) -> image::RgbImage {
+        image::load_from_memory(data).unwrap().to_rgb8()
+    }
+
+    // ===== Rotation Tests =====
+
+    #[test]
+    fn rotate_matches_pixel_rotation() {
+        let original = make_jpeg(32, 24);
+        let pixels = decode_rgb(&original);
+        let expected = [
+            (90, image::imageops::rotate90(&pixels)),
+            (180, image::imageops::rotate180(&pixels)),
+            (270, image::imageops::rotate270(&pixels)),
+        ];
+        for (degrees, expected) in expected {
+            let rotated = decode_rgb(&rotate_jpeg_lossless(&original, degrees).unwrap());
+            let diff = max_difference(&rotated, &expected);
+            assert!(diff <= 2, "{degrees}°: max pixel difference {diff}");
+        }
+    }
+
+    #[test]
+    fn four_quarter_turns_are_identity() {
+        let original = make_jpeg(40, 16);
+        let mut data = original.clone();
+        for _ in 0..4 {
+            data = rotate_jpeg_lossless(&data, 90).unwrap();
+        }
+        assert_eq!(decode_rgb(&original).as_raw(), decode_rgb(&data).as_raw());
+    }
+
+    #[test]
+    fn rotate_trims_partial_mcus() {
+        let original = make_jpeg(21, 13);
+
+        let rotated = decode_rgb(&rotate_jpeg_lossless(&original, 90).unwrap());
+        assert_eq!(rotated.dimensions(), (8, 21));
+
+        let rotated = decode_rgb(&rotate_jpeg_lossless(&original, 180).unwrap());
+        assert_eq!(rotated.dimensions(), (16, 8));
+
+        let rotated = decode_rgb(&rotate_jpeg_lossless(&original, 270).unwrap());
+        assert_eq!(rotated.dimensions(), (13, 16));
+    }
+
+    #[test]
+    fn rotate_subsampled_swaps_sampling() {
+        let image = make_subsampled(48, 32, 2, 1);
+        let rotated = rotate(&image, Rotation::Rotate90).unwrap();
+        assert_eq!((rotated.width, rotated.height), (32, 48));
+        let luma = rotated.components.first().unwrap();
+        assert_eq!((luma.h, luma.v), (1, 2));
+
+        let back = rotate(&rotated, Rotation::Rotate270).unwrap();
+        for (a, b) in image.components.iter().zip(&back.components) {
+            assert_eq!(a.blocks, b.blocks);
+        }
+
+        let encoded = rotated.encode().unwrap();
+        let decoded = image::load_from_memory(&encoded).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (32, 48));
+    }
+
+    #[test]
+    fn rotate_transposes_quant_tables() {
+        let mut image = make_subsampled(16, 16, 1, 1);
+        let mut table = [1u16; 64];
+        table[1] = 50; // row 0, col 1
+        image.quant_tables[0] = Some(table);
+
+        let rotated = rotate(&image, Rotation::Rotate90).unwrap();
+        let rotated_table = rotated.quant_tables[0].unwrap();
+        assert_eq!(rotated_table[8], 50); // row 1, col 0
+        assert_eq!(rotated_table[1], 1);
+    }
+
+    #[test]
+    fn orientation_rotation_composes() {
+        assert_eq!(rotate_orientation(1, Rotation::Rotate90), 6);
+        assert_eq!(rotate_orientation(6, Rotation::Rotate90), 3);
+        assert_eq!(rotate_orientation(3, Rotation::Rotate90), 8);
+        assert_eq!(rotate_orientation(8, Rotation::Rotate90), 1);
+        assert_eq!(rotate_orientation(2, Rotation::Rotate90), 7);
+        assert_eq!(rotate_orientation(4, Rotation::Rotate270), 7);
+        assert_eq!(rotate_orientation(5, Rotation::Rotate180), 7);
+        assert_eq!(rotate_orientation(0, Rotation::Rotate180), 3);
+    }
+
+    #[test]
+    fn metadata_rotation_keeps_image_data() {
+        let original = make_jpeg(24, 16);
+        let rotated = rotate_jpeg_orientation(&original, 270).unwrap();
+        assert_eq!(jpeg::orientation(&rotated).unwrap(), Some(8));
+        let again = rotate_jpeg_orientation(&rotated, 180).unwrap();
+        assert_eq!(jpeg::orientation(&again).unwrap(), Some(6));
+        assert_eq!(decode_rgb(&again).as_raw(), decode_rgb(&original).as_raw());
+        assert!(matches!(
+            rotate_jpeg_orientation(&original, 45),
+            Err(LosslessError::InvalidRotation(45))
+        ));
+    }
+
+    // ===== Crop Tests =====
+
+    fn region(x: u32, y: u32, width: u32, height: u32) -> CropRegion {
+        CropRegion {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    #[test]
+    fn aligned_crop_is_pixel_identical() {
+        let original = make_jpeg(48, 40);
+        let result = crop_jpeg(&original, region(16, 8, 21, 19), false, None).unwrap();
+        assert!(result.lossless);
+        assert_eq!(result.region, region(16, 8, 21, 19));
+
+        let expected = image::imageops::crop_imm(&decode_rgb(&original), 16, 8, 21, 19).to_image();
+        assert_eq!(decode_rgb(&result.data).as_raw(), expected.as_raw());
+    }
+
+    #[test]
+    fn unaligned_crop_snaps_to_mcu() {
+        let original = make_jpeg(48, 40);
+        let result = crop_jpeg(&original, region(13, 10, 20, 20), false, None).unwrap();
+        assert!(result.lossless);
+        assert_eq!(result.region, region(8, 8, 25, 22));
+        assert_eq!(decode_rgb(&result.data).dimensions(), (25, 22));
+    }
+
+    #[test]
+    fn subsampled_crop_snaps_to_16() {
+        let image = make_subsampled(64, 48, 2, 2);
+        let snapped = snap_to_mcu(&image, region(20, 17, 10, 10));
+        assert_eq!(snapped, region(16, 16, 14, 11));
+
+        let cropped = crop(&image, snapped).unwrap();
+        let luma = cropped.components.first().unwrap();
+        let source = image.components.first().unwrap();
+        assert_eq!(luma.block(0, 0), source.block(2, 2));
+        let chroma = cropped.components.get(1).unwrap();
+        let source_chroma = image.components.get(1).unwrap();
+        assert_eq!(chroma.block(0, 0), source_chroma.block(1, 1));
+    }
+
+    #[test]
+    fn exact_unaligned_crop_falls_back_to_lossy() {
+        let original = make_jpeg(48, 40);
+        let result = crop_jpeg(&original, region(13, 10, 20, 20), true, Some(95)).unwrap();
+        assert!(!result.lossless);
+        assert_eq!(result.region, region(13, 10, 20, 20));
+        assert_eq!(decode_rgb(&result.data).dimensions(), (20, 20));
+    }
+
+    #[test]
+    fn exact_aligned_crop_stays_lossless() {
+        let original = make_jpeg(48, 40);
+        let result = crop_jpeg(&original, region(8, 16, 20, 20), true, None).unwrap();
+        assert!(result.lossless);
+    }
+
+    #[test]
+    fn crop_out_of_bounds_rejected() {
+        let original = make_jpeg(32, 32);
+        for bad in [
+            region(0, 0, 0, 10),
+            region(24, 0, 16, 8),
+            region(0, 30, 8, 8),
+        ] {
+            assert!(matches!(
+                crop_jpeg(&original, bad, false, None),
+                Err(LosslessError::InvalidCrop(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn coefficient_crop_requires_alignment() {
+        let image = make_subsampled(32, 32, 1, 1);
+        assert!(matches!(
+            crop(&image, region(4, 0, 8, 8)),
+            Err(LosslessError::InvalidCrop(_))
+        ));
+    }
+
+    // ===== Optimization Tests =====
+
+    #[test]
+    fn optimize_shrinks_without_changing_pixels() {
+        let original = make_jpeg(96, 64);
+        let optimized = optimize_jpeg(&original, false).unwrap();
+        assert!(
+            optimized.len() < original.len(),
+            "optimized {} bytes vs original {}",
+            optimized.len(),
+            original.len()
+        );
+        assert_eq!(
+            decode_rgb(&original).as_raw(),
+            decode_rgb(&optimized).as_raw()
+        );
+    }
+
+    #[test]
+    fn optimize_is_idempotent_in_size() {
+        let once = optimize_jpeg(&make_jpeg(64, 64), false).unwrap();
+        let twice = optimize_jpeg(&once, false).unwrap();
+        assert_eq!(once.len(), twice.len());
+    }
+
+    #[test]
+    fn optimize_subsampled() {
+        let image = make_subsampled(50, 30, 2, 2);
+        let standard = image.encode().unwrap();
+        let optimized = optimize_jpeg(&standard, false).unwrap();
+        assert!(optimized.len() <= standard.len());
+        let decoded = image::load_from_memory(&optimized).unwrap();
+        assert_eq!((decoded.width(), decoded.height()), (50, 30));
+    }
+
+    #[test]
+    fn progressive_output_decodes_to_the_same_pixels() {
+        let original = make_jpeg(96, 64);
+        let progressive = optimize_jpeg(&original, true).unwrap();
+        assert!(jpeg::is_progressive(&progressive).unwrap());
+        assert!(progressive.len() < original.len());
+        assert_eq!(
+            decode_rgb(&original).as_raw(),
+            decode_rgb(&progressive).as_raw()
+        );
+
+        let subsampled = make_subsampled(50, 30, 2, 2).encode().unwrap();
+        let progressive = optimize_jpeg(&subsampled, true).unwrap();
+        assert_eq!(
+            decode_rgb(&subsampled).as_raw(),
+            decode_rgb(&progressive).as_raw()
+        );
+    }
+
+    // ===== Error Case Tests =====
+
+    #[test]
+    fn invalid_rotation_rejected() {
+        let result = rotate_jpeg_lossless(&make_jpeg(16, 16), 45);
+        assert!(matches!(result, Err(LosslessError::InvalidRotation(45))));
+    }
+
+    #[test]
+    fn non_jpeg_rejected() {
+        let result = rotate_jpeg_lossless(b"not a jpeg", 90);
+        assert!(matches!(
+            result,
+            Err(LosslessError::Jpeg(JpegError::NotJpeg))
+        ));
+    }
+
+    #[test]
+    fn too_small_to_trim() {
+        let result = rotate_jpeg_lossless(&make_jpeg(20, 5), 90);
+        assert!(matches!(result, Err(LosslessError::TooSmall)));
+    }
+}
@@ -1,25 +1,227 @@
+pub mod adjust;
+pub mod animation;
+pub mod ascii;
+pub mod attribution;
+pub mod batch;
+pub mod bilevel;
+pub mod canvas;
+pub mod capabilities;
+pub mod channels;
+pub mod codecs;
+pub mod codes;
+pub mod color;
+pub mod composite;
 pub mod convert;
+pub mod deskew;
+pub mod dither;
+pub mod edges;
+pub mod effects;
+pub mod embedded;
+pub mod events;
+pub mod exif_write;
+#[cfg(feature = "faces")]
+pub mod faces;
+pub mod fonts;
 pub mod formats;
+pub mod generate;
+pub mod hash;
+pub mod ico;
+pub mod jpeg;
+pub mod jpeg_lossless;
+#[cfg(feature = "logging")]
+pub mod logging;
 pub mod metadata;
+pub mod montage;
+pub mod operations;
+pub mod palette;
+pub mod png_chunks;
+pub mod png_optimize;
+pub mod policy;
+pub mod presets;
+pub mod preview;
+pub mod proof;
+pub mod psd;
+pub mod qr;
+pub mod quantize;
+#[cfg(feature = "raw-preview")]
+pub mod raw;
+#[cfg(feature = "raw-decode")]
+pub mod raw_decode;
+pub mod region;
+pub mod resize;
+pub mod sample;
+pub mod scale;
+pub mod scan;
+pub mod session;
+pub mod smart_crop;
+pub mod sprite;
+pub mod stats;
+pub mod text;
+pub mod tiles;
+pub mod timing;
 pub mod transforms;
+pub mod typescript;
+pub mod warp;
+pub mod webp_anim;
 
 use wasm_bindgen::prelude::*;
 
 use formats::ImageFormat;
+#[cfg(feature = "faces")]
+use typescript::TsFaces;
+use typescript::{
+    TsAttributionOptions, TsBackground, TsBatchPlan, TsCapabilities, TsContactSheetOptions,
+    TsContours, TsConversionPlan, TsConversionPolicy, TsConvertOptions, TsCropBox, TsCropBoxes,
+    TsDecodeMemory, TsDecodedRegion, TsDeskewed, TsDetectedCodes, TsDimensions, TsEmbeddedImages,
+    TsEnhancedImage, TsEnhancement, TsExifFields, TsExposureStats, TsFillLayer, TsFontInfo,
+    TsFontInfos, TsGenerateSpec, TsImageInspection, TsImageMetadata, TsPolicyViolations,
+    TsPresetInfos, TsPsdInfo, TsQrCodeLocations, TsQrCodes, TsQuickPreview, TsRegionSamples,
+    TsReportedConversion, TsResizeGeometry, TsRgba, TsSessionStats, TsTileLayout, TsTilePyramid,
+    TsTiltShiftOptions, TsTrimmed,
+};
 
 /// Detect the format of an image from its raw bytes.
 ///
-/// Returns a lowercase format name string (e.g. `"png"`, `"jpeg"`, `"webp"`, `"gif"`, `"bmp"`).
+/// Returns a lowercase format name string (e.g. `"png"`, `"jpeg"`, `"webp"`, `"gif"`, `"bmp"`),
+/// `"cr2"`, `"nef"` or `"arw"` for camera RAW files in builds with the `raw-preview` feature,
+/// `"psd"` for Photoshop documents, or the name of a codec registered from Rust that recognizes the input.
 ///
 /// # Errors
 ///
 /// Returns a `JsError` if the input is empty or the format is unrecognized.
 #[wasm_bindgen]
 pub fn detect_format(input: &[u8]) -> Result<String, JsError> {
-    let format = ImageFormat::detect_from_bytes(input)
-        .map_err(|e| JsError::new(&format!("Failed to detect image format: {e}")))?;
+    // Camera RAW files are TIFF containers, so they are checked first.
+    #[cfg(feature = "raw-preview")]
+    if let Some(format) = raw::camera_format(input) {
+        return Ok(format.to_owned());
+    }
+    if psd::is_psd(input) {
+        return Ok(psd::NAME.to_owned());
+    }
+    let format = match ImageFormat::detect_from_bytes(input) {
+        Ok(format) => format.to_string(),
+        Err(e) => codecs::detect(input)
+            .map(|decoder| decoder.name().to_owned())
+            .ok_or_else(|| JsError::new(&format!("Failed to detect image format: {e}")))?,
+    };
+
+    Ok(format)
+}
+
+/// Detect the format of an image from its raw bytes, as an `ImageFormat` value.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input is empty or the format is unrecognized.
+#[wasm_bindgen]
+pub fn detect_image_format(input: &[u8]) -> Result<ImageFormat, JsError> {
+    ImageFormat::detect_from_bytes(input)
+        .map_err(|e| JsError::new(&format!("Failed to detect image format: {e}")))
+}
+
+/// The lowercase name of `format` (e.g. `"jpeg"`), as the string-based functions take
+/// it.
+#[wasm_bindgen]
+pub fn format_name(format: ImageFormat) -> String {
+    format.as_str().to_owned()
+}
+
+/// Detect an image's format along with its container details, without decoding pixels.
+///
+/// Returns `{ format, width, height, frame_count, animated, progressive, has_alpha,
+/// has_icc_profile, has_exif, icon_sizes, decoded_bytes }`. `progressive` covers
+/// progressive JPEGs and interlaced PNGs and GIFs; `decoded_bytes` estimates the memory
+/// decoding takes. `icon_sizes` lists the `{ width, height, bits_per_pixel, png }`
+/// sizes of an ICO file (empty for other formats), and the other fields describe the
+/// largest, which conversions decode unless the `icon_size` option picks another.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the format is unrecognized or the headers cannot be read.
+#[wasm_bindgen]
+pub fn inspect_image(input: &[u8]) -> Result<TsImageInspection, JsError> {
+    let info = metadata::inspect(input)
+        .map_err(|e| JsError::new(&format!("Failed to inspect image: {e}")))?;
+    serde_wasm_bindgen::to_value(&info)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize inspection: {e}")))
+}
+
+/// Read a Photoshop document's headers without decoding it.
+///
+/// Returns `{ width, height, channels, depth, color_mode, layers, has_alpha,
+/// large_document }`, where `layers` is 0 for flat files. Converting a PSD decodes
+/// its flattened composite, not the individual layers.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input isn't a PSD or PSB file or a section is truncated.
+#[wasm_bindgen]
+pub fn inspect_psd(input: &[u8]) -> Result<TsPsdInfo, JsError> {
+    let info =
+        psd::inspect(input).map_err(|e| JsError::new(&format!("Failed to inspect PSD: {e}")))?;
+    serde_wasm_bindgen::to_value(&info)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize PSD info: {e}")))
+}
+
+/// Extract the images a file carries: its EXIF thumbnail (JPEG, PNG, WebP), every size
+/// of an ICO file, every page of a TIFF, or with the `raw-preview` feature every JPEG
+/// preview of a camera RAW file.
+///
+/// Returns an array of `{ kind, index, format, width, height, data }`, where `kind` is
+/// `"exif_thumbnail"`, `"icon_image"`, `"tiff_page"` or `"raw_preview"`, `index`
+/// counts images of that kind, and `data` is an encoded image in `format`. TIFF pages
+/// are re-encoded as PNG and non-PNG icon sizes are returned as single-image ICO files;
+/// everything else is returned byte for byte. Images that can't be read are left out.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input's format can't be detected.
+#[wasm_bindgen]
+pub fn extract_embedded(input: &[u8]) -> Result<TsEmbeddedImages, JsError> {
+    let images = embedded::extract_embedded(input)
+        .map_err(|e| JsError::new(&format!("Failed to extract embedded images: {e}")))?;
+
+    let array = js_sys::Array::new();
+    for image in &images {
+        let obj = js_sys::Object::new();
+        let data = js_sys::Uint8Array::from(image.data.as_slice());
+        for (key, value) in [
+            ("kind", JsValue::from(image.kind.as_str())),
+            ("index", image.index.into()),
+            ("format", image.format.as_str().into()),
+            ("width", image.width.into()),
+            ("height", image.height.into()),
+            ("data", data.into()),
+        ] {
+            js_sys::Reflect::set(&obj, &key.into(), &value).map_err(|_| {
+                JsError::new(&format!("Failed to set embedded image {key} property"))
+            })?;
+        }
+        array.push(&obj);
+    }
+    Ok(array.unchecked_into())
+}
 
-    Ok(format.to_string())
+/// Estimate the memory decoding an image takes, from its headers alone, so hosts can
+/// send files too large for the browser to a server instead of attempting them here.
+///
+/// Returns `{ width, height, frames, channels, bits_per_channel, bytes }`, where `bytes`
+/// is the size of the decoded pixels: every frame, for animations, which decode to
+/// 8-bit RGBA. Converting takes more; see `plan_conversion`'s `approx_memory_bytes`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the format is unrecognized or the headers cannot be read.
+#[wasm_bindgen]
+pub fn estimate_decode_memory(input: &[u8]) -> Result<TsDecodeMemory, JsError> {
+    let memory = metadata::estimate_decode_memory(input)
+        .map_err(|e| JsError::new(&format!("Failed to read image headers: {e}")))?;
+    serde_wasm_bindgen::to_value(&memory)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize memory estimate: {e}")))
 }
 
 /// Convert an image from one format to another.
@@ -28,6 +230,9 @@ pub fn detect_format(input: &[u8]) -> Result<String, JsError> {
 /// and an optional quality value (1-100) for formats that support it.
 /// Returns the re-encoded image as a byte vector.
 ///
+/// Inputs and targets may also be in formats whose codecs were registered from Rust
+/// (see the `codecs` module).
+///
 /// # Errors
 ///
 /// Returns a `JsError` if:
@@ -50,6 +255,17 @@ pub fn convert_image(
         }
     }
 
+    if ImageFormat::from_name(target_format).is_err()
+        && codecs::find_encoder(target_format).is_some()
+    {
+        let options = convert::ConvertOptions {
+            quality,
+            ..convert::ConvertOptions::default()
+        };
+        return convert::convert_with_encoder(input.to_vec(), target_format, &options, &[])
+            .map_err(|e| JsError::new(&e.to_string()));
+    }
+
     let target = ImageFormat::from_name(target_format)
         .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
 
@@ -101,6 +317,734 @@ pub fn convert_image_with_transforms(
     Ok(result)
 }
 
+/// Options accepted by [`convert_image_with_options`], read from a plain JS object.
+/// Every field is optional.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct JsConvertOptions {
+    /// Output quality, 1-100.
+    quality: Option<u8>,
+    /// Comma-separated transform names, as for `convert_image_with_transforms`, or an
+    /// array of `Transform` values or names.
+    transforms: TransformsOption,
+    /// PNG ancillary chunk policy, e.g. `"safe,+tEXt,-pHYs"`.
+    png_chunks: String,
+    /// PNG color-space tagging: `"auto"`, `"srgb"` or `"none"`.
+    png_color_tag: EnumOption,
+    /// Indexed PNG output mode: `"never"`, `"prefer"` or `"require"`.
+    png_indexed: EnumOption,
+    /// Dither 16-bit sources when the output is 8-bit.
+    dither_16bit: bool,
+    /// Palette builder for GIF output, e.g. `"wu"` or `"neuquant:5"`.
+    gif_quantizer: String,
+    /// Dithering for `gif_quantizer`: `"none"`, `"floyd_steinberg"` or `"ordered"`.
+    gif_dither: EnumOption,
+    /// Byte-identical output for identical input and options.
+    deterministic: bool,
+    /// Format to decode the input as when its bytes don't identify one (e.g. `"tga"`).
+    source_format: EnumOption,
+    /// Which size of an ICO input to decode, in pixels.
+    icon_size: Option<u32>,
+    /// Memory budget for the conversion, in bytes.
+    max_memory_bytes: Option<u64>,
+    /// Crop to `{ x, y, width, height }` after the transforms.
+    crop: Option<smart_crop::CropBox>,
+    /// Registered operations to run after the crop, as `{ op, ...params }`.
+    operations: Vec<serde_json::Map<String, serde_json::Value>>,
+    /// Resize after the transforms and operations.
+    resize: Option<JsResizeStep>,
+    /// Tile a logo across the result.
+    watermark: Option<JsWatermarkStep>,
+    /// EXIF fields to write into JPEG and PNG output.
+    exif: Option<JsExifFields>,
+    /// Remove GPS fields from the output's EXIF data.
+    strip_gps: bool,
+}
+
+/// The EXIF fields of `set_exif` and the `exif` option.
+#[derive(Debug, Default, Clone, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct JsExifFields {
+    artist: Option<String>,
+    copyright: Option<String>,
+    date_time: Option<String>,
+    remove_gps: bool,
+}
+
+impl JsExifFields {
+    fn resolve(&self) -> Result<exif_write::ExifEdits, JsError> {
+        let edits = exif_write::ExifEdits {
+            artist: self.artist.clone(),
+            copyright: self.copyright.clone(),
+            date_time: self.date_time.clone(),
+            remove_gps: self.remove_gps,
+        };
+        edits
+            .validate()
+            .map_err(|e| JsError::new(&format!("Invalid EXIF fields: {e}")))?;
+        Ok(edits)
+    }
+}
+
+/// The `resize` option.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsResizeStep {
+    mode: String,
+    width: u32,
+    height: Option<u32>,
+    no_upscale: Option<bool>,
+    progressive: Option<bool>,
+    fill: Option<String>,
+}
+
+/// The `watermark` option.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsWatermarkStep {
+    logo: Vec<u8>,
+    opacity: Option<f64>,
+}
+
+/// The padding fill of a resize: `"transparent"` (the default), `"auto"` or a color.
+fn pad_fill(fill: Option<String>) -> Result<resize::PadFill, JsError> {
+    fill.map_or(Ok(resize::PadFill::default()), |name| {
+        resize::PadFill::from_name(&name)
+            .map_err(|e| JsError::new(&format!("Invalid padding fill: {e}")))
+    })
+}
+
+/// An enum-valued option, given by name or as a value of the exported enum (e.g.
+/// `IndexedPng.Prefer` from TypeScript).
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum EnumOption {
+    Name(String),
+    Value(usize),
+}
+
+impl Default for EnumOption {
+    fn default() -> Self {
+        Self::Name(String::new())
+    }
+}
+
+impl EnumOption {
+    /// Resolves the option against `all`, the enum's variants in declaration order,
+    /// which is also the order of their JS values.
+    fn resolve<T: Copy, E: std::fmt::Display>(
+        &self,
+        all: &[T],
+        from_name: impl Fn(&str) -> Result<T, E>,
+    ) -> Result<T, String> {
+        match self {
+            Self::Name(name) => from_name(name).map_err(|e| e.to_string()),
+            Self::Value(value) => all
+                .get(*value)
+                .copied()
+                .ok_or_else(|| format!("No option has the value {value}")),
+        }
+    }
+}
+
+/// The `transforms` option.
+#[derive(Debug, serde::Deserialize)]
+#[serde(untagged)]
+enum TransformsOption {
+    Csv(String),
+    List(Vec<EnumOption>),
+}
+
+impl Default for TransformsOption {
+    fn default() -> Self {
+        Self::Csv(String::new())
+    }
+}
+
+/// Convert an image with an options object. As with `convert_image`, the input and
+/// target may be in formats whose codecs were registered from Rust.
+///
+/// `options` is `{ quality?, transforms?, png_chunks?, png_color_tag?, png_indexed?,
+/// dither_16bit?, gif_quantizer?, gif_dither?, deterministic? }` (or `undefined`):
+/// - `quality`: 1-100, as for `convert_image`; animated output is encoded at it too
+/// - `transforms`: comma-separated transform names, as for `convert_image_with_transforms`,
+///   or an array of `Transform` values
+/// - `png_chunks`: which ancillary chunks PNG output keeps from the source. A preset
+///   (`"keep"`, `"safe"` for color management and physical size, or `"strip"`) followed
+///   by `+name` / `-name` overrides, e.g. `"safe,+tEXt,-pHYs"`. Defaults to `"strip"`.
+///   Non-PNG sources contribute their ICC profile (`iCCP`) and EXIF (`eXIf`).
+/// - `png_color_tag`: how PNG output is color tagged. `"auto"` (default) keeps the
+///   source's color chunks or ICC profile even under `"strip"`, and tags untagged
+///   sources as sRGB (`sRGB` plus `gAMA`/`cHRM` fallbacks); `"srgb"` always tags as
+///   sRGB (pixels are not converted); `"none"` writes no color chunks.
+/// - `png_indexed`: `"never"` (default), `"prefer"` to write an indexed PNG (PNG8, alpha
+///   in `tRNS`) when the image has at most 256 colors, or `"require"` to fail instead of
+///   falling back to truecolor when it has more.
+/// - `dither_16bit`: when a 16-bit source is written to an 8-bit output (anything but
+///   PNG/TIFF, or indexed PNG), use error-diffusion dithering instead of rounding, which
+///   avoids banding in smooth gradients. Defaults to `false`.
+/// - `gif_quantizer`: builds the palette of GIF output with `"median_cut"`,
+///   `"wu"` or `"neuquant"`, optionally followed by `:parameter` (k-means passes for
+///   median cut and Wu, sampling factor 1–30 for NeuQuant). Animated output shares
+///   one palette across all frames. Empty (default) leaves the palette to the GIF
+///   encoder.
+/// - `gif_dither`: how pixels are mapped onto the `gif_quantizer` palette: `"none"`,
+///   `"floyd_steinberg"` (default) or `"ordered"` (8×8 Bayer pattern).
+/// - `deterministic`: guarantee byte-identical output for identical input and options
+///   across runs and builds, for CDN caching and content-addressed storage. PNG output
+///   never carries `tIME`, and JPEG always uses the built-in encoder (never mozjpeg).
+///   Defaults to `false`.
+/// - `source_format`: the format to decode the input as when its bytes don't identify
+///   one, e.g. `"tga"`, which has no magic bytes. Inputs that are recognized are decoded
+///   as what they are.
+/// - `icon_size`: which size of a multi-size ICO input to decode: the smallest at
+///   least this many pixels on its longer side, or the largest when none is. Defaults
+///   to the largest (`inspect_image` lists the sizes).
+/// - `max_memory_bytes`: a memory budget, checked against `plan_conversion`'s
+///   `approx_memory_bytes` before anything is decoded, so a tab on a memory-constrained
+///   device fails cleanly instead of being killed. Over budget, tiled and striped TIFFs
+///   are decoded at the smallest reduced resolution that fits (`report.applied.reduced_by`
+///   says by what factor), with `crop`, `resize` and the watermark scaled down to match;
+///   anything else throws an `Error` named `"LimitExceeded"` with
+///   `required_bytes` and `limit_bytes` properties. No limit by default.
+/// - `crop`: `{ x, y, width, height }`, cropping to that rectangle after the transforms.
+///   It must be non-empty and inside the transformed image; animations are cropped frame
+///   by frame.
+/// - `operations`: `{ op, ...params }` objects naming operations the host registered
+///   from Rust (see the `operations` module), run in order after the crop. Builds
+///   without registered operations reject any.
+/// - `resize`: `{ mode, width, height?, no_upscale?, progressive?, fill? }`, resizing after the
+///   transforms and operations as `resize` does (`height` defaults to `width`). The image is 8-bit
+///   RGBA from then on; animations are resized frame by frame. Without a `crop` or
+///   operations, large TIFF and JPEG downscales decode at reduced resolution, as `resize` does.
+/// - `watermark`: `{ logo, opacity? }`, tiling `logo` (encoded image bytes) across the
+///   result at its own size after every other step, as `client_proof_batch` does, with
+///   `opacity` from 0 to 1 (default 1). Animations are watermarked frame by frame.
+/// - `exif`: `{ artist?, copyright?, date_time?, remove_gps? }` fields written into
+///   JPEG and PNG output as `set_exif` writes them, amending any EXIF data the output
+///   carries (see `png_chunks` for what PNG output keeps). Ignored for other targets.
+/// - `strip_gps`: remove the location fields from EXIF data the output carries, e.g.
+///   with `png_chunks: "keep"`, while keeping camera settings and orientation. Defaults
+///   to `false`.
+///
+/// `png_color_tag`, `png_indexed` and `gif_dither` also accept values of the exported
+/// `ColorTag`, `IndexedPng` and `Dither` enums, and `source_format` an `ImageFormat`.
+/// The generated `.d.ts` types the object as the `ConvertOptions` interface, so
+/// misspelled keys fail type checking; at runtime they are rejected as unknown fields.
+///
+/// # Errors
+///
+/// Throws an `Error` if an option is malformed or out of range, the memory limit is
+/// exceeded, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn convert_image_with_options(
+    input: &[u8],
+    target_format: &str,
+    options: Option<TsConvertOptions>,
+) -> Result<Vec<u8>, JsValue> {
+    if ImageFormat::from_name(target_format).is_err()
+        && codecs::find_encoder(target_format).is_some()
+    {
+        let (convert_options, transform_list) = parse_convert_options(options)?;
+        return convert::convert_with_encoder(
+            input.to_vec(),
+            target_format,
+            &convert_options,
+            &transform_list,
+        )
+        .map_err(|e| convert_error(&e));
+    }
+
+    let (target, convert_options, transform_list) = convert_request(target_format, options)?;
+    let result =
+        convert::convert_with_options(input.to_vec(), target, &convert_options, &transform_list)
+            .map_err(|e| convert_error(&e))?;
+
+    Ok(result)
+}
+
+/// Convert an image like `convert_image_with_options`, with the target given as an
+/// `ImageFormat` value instead of a name.
+///
+/// # Errors
+///
+/// Throws an `Error` if an option is malformed or out of range, the memory limit is
+/// exceeded, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn convert_to_format(
+    input: &[u8],
+    target: ImageFormat,
+    options: Option<TsConvertOptions>,
+) -> Result<Vec<u8>, JsValue> {
+    let (convert_options, transform_list) = parse_convert_options(options)?;
+    let result =
+        convert::convert_with_options(input.to_vec(), target, &convert_options, &transform_list)
+            .map_err(|e| convert_error(&e))?;
+
+    Ok(result)
+}
+
+/// Register a named preset, e.g. `"avatar"` or `"email-thumbnail"`, for
+/// `convert_with_preset`.
+///
+/// `ops_json` is a JSON object with a `format` (the target format name) and any of the
+/// `convert_image_with_options` options, e.g.
+/// `{"format": "jpeg", "quality": 80, "resize": {"mode": "cover", "width": 256}}`. It is
+/// validated now, so mistakes surface at registration rather than on first use: the
+/// format must be one conversions can encode, so not `"webp"`.
+/// Registering a name again (compared case-insensitively) replaces that preset.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the name is blank, `ops_json` isn't a JSON object, or its
+/// format is invalid or decode-only, or an option is invalid.
+#[wasm_bindgen]
+pub fn register_preset(name: &str, ops_json: &str) -> Result<(), JsError> {
+    let ops: serde_json::Value = serde_json::from_str(ops_json)
+        .map_err(|e| JsError::new(&format!("Invalid preset JSON: {e}")))?;
+    let mut options = ops
+        .as_object()
+        .cloned()
+        .ok_or_else(|| JsError::new("A preset must be a JSON object"))?;
+    let format: EnumOption = options
+        .remove("format")
+        .map(serde_json::from_value)
+        .transpose()
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?
+        .ok_or_else(|| JsError::new("A preset needs a \"format\""))?;
+    let target = format
+        .resolve(&ImageFormat::ALL, ImageFormat::from_name)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let options: JsConvertOptions = serde_json::from_value(options.into())
+        .map_err(|e| JsError::new(&format!("Invalid options: {e}")))?;
+    let (options, transforms) = resolve_convert_options(&options)?;
+
+    let preset = presets::Preset {
+        target,
+        options,
+        transforms,
+        ops,
+    };
+    presets::register_preset(name, preset)
+        .map_err(|e| JsError::new(&format!("Failed to register preset: {e}")))
+}
+
+/// Remove a preset registered with `register_preset`. Returns whether there was one.
+#[wasm_bindgen]
+pub fn unregister_preset(name: &str) -> bool {
+    presets::unregister_preset(name)
+}
+
+/// List the registered presets, in registration order, as `{ name, ops }` with `ops`
+/// the object each was registered with, for debugging.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the list cannot be serialized.
+#[wasm_bindgen]
+pub fn list_presets() -> Result<TsPresetInfos, JsError> {
+    // Through JSON, so `ops` objects come out as plain objects rather than Maps.
+    let json = serde_json::to_string(&presets::list_presets())
+        .map_err(|e| JsError::new(&format!("Failed to serialize presets: {e}")))?;
+    js_sys::JSON::parse(&json)
+        .map(JsCast::unchecked_into)
+        .map_err(|_| JsError::new("Failed to serialize presets"))
+}
+
+/// Convert an image with a preset registered with `register_preset`.
+///
+/// # Errors
+///
+/// Throws an `Error` if no preset has that name, the memory limit is exceeded, or
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn convert_with_preset(input: &[u8], name: &str) -> Result<Vec<u8>, JsValue> {
+    presets::convert_with_preset(input.to_vec(), name).map_err(|e| match e {
+        presets::PresetError::Convert(e) => convert_error(&e),
+        e => JsError::new(&e.to_string()).into(),
+    })
+}
+
+/// A conversion policy, read from a plain JS object. Every field is optional.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct JsConversionPolicy {
+    max_width: Option<u32>,
+    max_height: Option<u32>,
+    allowed_input_formats: Vec<EnumOption>,
+    output_format: Option<EnumOption>,
+    strip_metadata: bool,
+    min_quality: Option<u8>,
+    max_quality: Option<u8>,
+}
+
+/// Parses the policy object of `check_policy` and `convert_image_with_policy`.
+fn parse_policy(policy: TsConversionPolicy) -> Result<policy::ConversionPolicy, JsError> {
+    let policy: JsConversionPolicy = serde_wasm_bindgen::from_value(policy.into())
+        .map_err(|e| JsError::new(&format!("Invalid policy: {e}")))?;
+    let format = |option: &EnumOption| {
+        option
+            .resolve(&ImageFormat::ALL, ImageFormat::from_name)
+            .map_err(|e| JsError::new(&format!("Invalid policy format: {e}")))
+    };
+    Ok(policy::ConversionPolicy {
+        max_width: policy.max_width,
+        max_height: policy.max_height,
+        allowed_inputs: policy
+            .allowed_input_formats
+            .iter()
+            .map(format)
+            .collect::<Result<_, _>>()?,
+        output_format: policy.output_format.as_ref().map(format).transpose()?,
+        strip_metadata: policy.strip_metadata,
+        min_quality: policy.min_quality,
+        max_quality: policy.max_quality,
+    })
+}
+
+/// List the rules of `policy` that `convert_image_with_options(input, target_format,
+/// options)` would break, reading only the input's headers.
+///
+/// `policy` is `{ max_width?, max_height?, allowed_input_formats?, output_format?,
+/// strip_metadata?, min_quality?, max_quality? }`:
+/// - `max_width`, `max_height`: the largest output, after transforms and `resize`
+/// - `allowed_input_formats`: the formats the input may be in; any by default
+/// - `output_format`: the only format the output may be written in
+/// - `strip_metadata`: `convert_image_with_policy` writes no chunks carried over from
+///   the source, whatever `png_chunks` says
+/// - `min_quality`, `max_quality`: bounds on JPEG quality (80 when unset)
+///
+/// Returns `{ rule, message }` objects, with `rule` one of `"input_format"`,
+/// `"output_format"`, `"max_width"`, `"max_height"` and `"quality"`; empty when the
+/// conversion complies.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the policy or an option is malformed, or the conversion
+/// can't be planned (see `plan_conversion`).
+#[wasm_bindgen]
+pub fn check_policy(
+    input: &[u8],
+    target_format: &str,
+    options: Option<TsConvertOptions>,
+    policy: TsConversionPolicy,
+) -> Result<TsPolicyViolations, JsError> {
+    let (target, convert_options, transform_list) = convert_request(target_format, options)?;
+    let policy = parse_policy(policy)?;
+    let violations = policy::check(input, target, &convert_options, &transform_list, &policy)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+    serde_wasm_bindgen::to_value(&violations)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize violations: {e}")))
+}
+
+/// Convert an image like `convert_image_with_options`, unless the conversion breaks a
+/// rule of `policy` (see `check_policy`). `target_format` may be left out when the
+/// policy has an `output_format`.
+///
+/// # Errors
+///
+/// Throws an `Error` named `"PolicyViolation"`, with a `violations` property listing
+/// every broken rule as `check_policy` does, if the conversion breaks the policy.
+/// Otherwise throws an `Error` if the policy or an option is malformed, no target
+/// format is known, the memory limit is exceeded, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn convert_image_with_policy(
+    input: &[u8],
+    target_format: Option<String>,
+    options: Option<TsConvertOptions>,
+    policy: TsConversionPolicy,
+) -> Result<Vec<u8>, JsValue> {
+    let target = target_format
+        .map(|name| ImageFormat::from_name(&name))
+        .transpose()
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let (convert_options, transform_list) = parse_convert_options(options)?;
+    let policy = parse_policy(policy)?;
+    policy::convert_with_policy(
+        input.to_vec(),
+        target,
+        &convert_options,
+        &transform_list,
+        &policy,
+    )
+    .map_err(|e| policy_error(&e))
+}
+
+/// Turns a policy failure into a JS `Error`. Violations are named `"PolicyViolation"`
+/// and carry the broken rules as `violations`; conversion failures are reported as by
+/// [`convert_error`].
+fn policy_error(error: &policy::PolicyError) -> JsValue {
+    match error {
+        policy::PolicyError::Violations(violations) => {
+            let js_error = js_sys::Error::new(&error.to_string());
+            js_error.set_name("PolicyViolation");
+            let violations = serde_wasm_bindgen::to_value(violations).unwrap_or(JsValue::UNDEFINED);
+            // Setting a property on a fresh, unfrozen Error cannot fail.
+            let _ = js_sys::Reflect::set(&js_error, &"violations".into(), &violations);
+            js_error.into()
+        }
+        policy::PolicyError::Convert(e) => convert_error(e),
+        e => JsError::new(&e.to_string()).into(),
+    }
+}
+
+/// Convert an image like `convert_image_with_options`, also reporting how it went.
+///
+/// Returns `{ data: Uint8Array, report }`, where `report` has `decode_ms`, `ops` (one
+/// `{ name, ms }` per transform, plus `"dither_16bit"` when it runs), `encode_ms`,
+/// `input_bytes`, `output_bytes`, output `width`/`height`, `frames`, a rough
+/// `peak_memory_bytes` estimate, and `applied`: the target, effective quality,
+/// transforms, `dithered_16bit`, `indexed_png`, `quantized_gif`, the `png_chunks`
+/// written, `deterministic` and `reduced_by`. Timings have millisecond resolution in
+/// browsers.
+///
+/// # Errors
+///
+/// Throws an `Error` if an option is malformed or out of range, the memory limit is
+/// exceeded, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn convert_image_with_report(
+    input: &[u8],
+    target_format: &str,
+    options: Option<TsConvertOptions>,
+) -> Result<TsReportedConversion, JsValue> {
+    let (target, convert_options, transform_list) = convert_request(target_format, options)?;
+    let result =
+        convert::convert_with_report(input.to_vec(), target, &convert_options, &transform_list)
+            .map_err(|e| convert_error(&e))?;
+
+    let report = serde_wasm_bindgen::to_value(&result.report)
+        .map_err(|e| JsError::new(&format!("Failed to serialize conversion report: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let data = js_sys::Uint8Array::from(result.data.as_slice());
+    js_sys::Reflect::set(&obj, &"data".into(), &data)
+        .map_err(|_| JsError::new("Failed to set data property"))?;
+    js_sys::Reflect::set(&obj, &"report".into(), &report)
+        .map_err(|_| JsError::new("Failed to set report property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Turns a conversion failure into a JS `Error`. Memory limit failures are named
+/// `"LimitExceeded"` and carry `required_bytes` and `limit_bytes`, so callers can tell
+/// them apart and retry with a smaller image.
+fn convert_error(error: &convert::ConvertError) -> JsValue {
+    let js_error = js_sys::Error::new(&error.to_string());
+    if let convert::ConvertError::LimitExceeded {
+        required_bytes,
+        limit_bytes,
+    } = *error
+    {
+        js_error.set_name("LimitExceeded");
+        for (key, bytes) in [
+            ("required_bytes", required_bytes),
+            ("limit_bytes", limit_bytes),
+        ] {
+            let bytes = serde_wasm_bindgen::to_value(&bytes).unwrap_or(JsValue::UNDEFINED);
+            // Setting a property on a fresh, unfrozen Error cannot fail.
+            let _ = js_sys::Reflect::set(&js_error, &key.into(), &bytes);
+        }
+    }
+    js_error.into()
+}
+
+/// Work out what `convert_image_with_options` would produce, without decoding pixels,
+/// so a UI can show a summary and warnings before converting.
+///
+/// Returns `{ input_format, target, input_width, input_height, width, height, frames,
+/// approx_bytes, approx_memory_bytes, lossy_steps }`. `approx_bytes` and
+/// `approx_memory_bytes` (peak memory of the conversion) are rough estimates. `lossy_steps`
+/// lists, in pipeline order, any of `"animation_flattened"`, `"grayscale"`,
+/// `"bit_depth_reduced"`, `"alpha_dropped"`, `"palette_reduced"` (GIF output) and
+/// `"jpeg_compression"`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if an option is malformed or out of range, the target cannot be
+/// encoded, the input's headers cannot be read, or the output would be too large for
+/// the target.
+#[wasm_bindgen]
+pub fn plan_conversion(
+    input: &[u8],
+    target_format: &str,
+    options: Option<TsConvertOptions>,
+) -> Result<TsConversionPlan, JsError> {
+    let (target, convert_options, transform_list) = convert_request(target_format, options)?;
+    let plan = convert::plan(input, target, &convert_options, &transform_list)
+        .map_err(|e| JsError::new(&e.to_string()))?;
+
+    serde_wasm_bindgen::to_value(&plan)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize conversion plan: {e}")))
+}
+
+/// Parses the target format and options object shared by `convert_image_with_options`,
+/// `convert_image_with_report` and `plan_conversion`.
+fn convert_request(
+    target_format: &str,
+    options: Option<TsConvertOptions>,
+) -> Result<
+    (
+        ImageFormat,
+        convert::ConvertOptions,
+        Vec<transforms::Transform>,
+    ),
+    JsError,
+> {
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let (convert_options, transform_list) = parse_convert_options(options)?;
+    Ok((target, convert_options, transform_list))
+}
+
+/// Parses the options object of `convert_image_with_options` and `convert_to_format`.
+fn parse_convert_options(
+    options: Option<TsConvertOptions>,
+) -> Result<(convert::ConvertOptions, Vec<transforms::Transform>), JsError> {
+    let options: JsConvertOptions = match options.map(JsValue::from) {
+        Some(options) if !options.is_null() => serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsError::new(&format!("Invalid options: {e}")))?,
+        _ => JsConvertOptions::default(),
+    };
+    resolve_convert_options(&options)
+}
+
+/// Validates conversion options read from JS or from a preset's JSON.
+fn resolve_convert_options(
+    options: &JsConvertOptions,
+) -> Result<(convert::ConvertOptions, Vec<transforms::Transform>), JsError> {
+    if let Some(q) = options.quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let transform_list = match &options.transforms {
+        TransformsOption::Csv(csv) => transforms::parse_transforms(csv).map_err(|e| e.to_string()),
+        TransformsOption::List(list) => list
+            .iter()
+            .map(|transform| {
+                transform.resolve(
+                    &transforms::Transform::ALL,
+                    transforms::Transform::from_name,
+                )
+            })
+            .collect(),
+    }
+    .map_err(|e| JsError::new(&format!("Invalid transform: {e}")))?;
+
+    let png_chunks = png_chunks::PngChunkPolicy::parse(&options.png_chunks)
+        .map_err(|e| JsError::new(&format!("Invalid PNG chunk policy: {e}")))?;
+
+    let png_color_tag = options
+        .png_color_tag
+        .resolve(&png_chunks::ColorTag::ALL, png_chunks::ColorTag::from_name)
+        .map_err(|e| JsError::new(&format!("Invalid PNG color tag: {e}")))?;
+
+    let png_indexed = options
+        .png_indexed
+        .resolve(&quantize::IndexedPng::ALL, quantize::IndexedPng::from_name)
+        .map_err(|e| JsError::new(&format!("Invalid PNG indexed mode: {e}")))?;
+
+    let gif_quantize = if options.gif_quantizer.trim().is_empty() {
+        None
+    } else {
+        let quantizer = quantize::Quantizer::from_name(&options.gif_quantizer)
+            .map_err(|e| JsError::new(&format!("Invalid GIF quantizer: {e}")))?;
+        let dither = options
+            .gif_dither
+            .resolve(&quantize::Dither::ALL, quantize::Dither::from_name)
+            .map_err(|e| JsError::new(&format!("Invalid GIF dithering mode: {e}")))?;
+        Some(quantize::QuantizeOptions {
+            dither,
+            quantizer,
+            ..quantize::QuantizeOptions::default()
+        })
+    };
+
+    let source_format = match &options.source_format {
+        EnumOption::Name(name) if name.trim().is_empty() => None,
+        format => Some(
+            format
+                .resolve(&ImageFormat::ALL, ImageFormat::from_name)
+                .map_err(|e| JsError::new(&format!("Invalid source format: {e}")))?,
+        ),
+    };
+
+    let resize = match &options.resize {
+        Some(step) => {
+            let mode = resize::ResizeMode::from_name(&step.mode)
+                .map_err(|e| JsError::new(&format!("Invalid resize mode: {e}")))?;
+            let defaults = resize::ResizeOptions::default();
+            Some(convert::ResizeStep {
+                mode,
+                width: step.width,
+                height: step.height.unwrap_or(step.width),
+                options: resize::ResizeOptions {
+                    no_upscale: step.no_upscale.unwrap_or(defaults.no_upscale),
+                    progressive: step.progressive.unwrap_or(defaults.progressive),
+                    fill: pad_fill(step.fill.clone())?,
+                },
+            })
+        }
+        None => None,
+    };
+
+    let watermark = match &options.watermark {
+        Some(step) => Some(convert::WatermarkStep {
+            logo: image::load_from_memory(&step.logo)
+                .map_err(|e| JsError::new(&format!("Invalid watermark logo: {e}")))?
+                .into_rgba8(),
+            opacity: step.opacity.unwrap_or(1.0),
+        }),
+        None => None,
+    };
+
+    let operation_steps = options
+        .operations
+        .iter()
+        .map(|step| {
+            let mut params = step.clone();
+            let Some(serde_json::Value::String(name)) = params.remove("op") else {
+                return Err("each operation needs an \"op\" name".to_owned());
+            };
+            let step = operations::OperationStep {
+                name,
+                params: params.into(),
+            };
+            step.resolve().map_err(|e| e.to_string())?;
+            Ok(step)
+        })
+        .collect::<Result<_, _>>()
+        .map_err(|e| JsError::new(&format!("Invalid operation: {e}")))?;
+
+    let convert_options = convert::ConvertOptions {
+        quality: options.quality,
+        png_chunks,
+        png_color_tag,
+        png_indexed,
+        dither_16bit: options.dither_16bit,
+        gif_quantize,
+        deterministic: options.deterministic,
+        source_format,
+        icon_size: options.icon_size,
+        max_memory_bytes: options.max_memory_bytes,
+        crop: options.crop,
+        operations: operation_steps,
+        resize,
+        watermark,
+        exif: options
+            .exif
+            .as_ref()
+            .map(JsExifFields::resolve)
+            .transpose()?,
+        strip_gps: options.strip_gps,
+    };
+    Ok((convert_options, transform_list))
+}
+
 /// Decode an image from any supported format to raw RGBA8 pixel bytes.
 ///
 /// Returns a flat `Vec<u8>` of pixels in RGBA order (4 bytes per pixel, row-major).
@@ -148,33 +1092,3417 @@ pub fn decode_to_rgba_with_transforms(
     Ok(obj.into())
 }
 
-/// Read the dimensions of an image without fully decoding its pixel data.
+/// Decode the `width` x `height` rectangle at (`x`, `y`) of an image to RGBA8 pixels,
+/// e.g. one tile of a gigapixel scan.
 ///
-/// Returns a JavaScript object with `width` and `height` properties (both `u32`).
+/// Returns `{ rgba: Uint8Array, width, height, method }`. `method` is `"chunks"` when
+/// only the overlapping tiles or strips of a TIFF (8-bit gray or RGB) were decoded, and
+/// `"full_decode"` when the whole image had to be decoded and cropped, as for JPEG.
 ///
 /// # Errors
 ///
-/// Returns a `JsError` if the image format cannot be guessed or the dimensions cannot be read.
+/// Returns a `JsError` if the rectangle is empty or extends past the image, or the
+/// input cannot be decoded.
 #[wasm_bindgen]
-pub fn get_dimensions(input: &[u8]) -> Result<JsValue, JsError> {
-    let dims = convert::dimensions(input).map_err(|e| JsError::new(&e.to_string()))?;
+pub fn decode_region(
+    input: &[u8],
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<TsDecodedRegion, JsError> {
+    let region = region::decode_region(input, x, y, width, height)
+        .map_err(|e| JsError::new(&format!("Failed to decode region: {e}")))?;
+    let method = serde_wasm_bindgen::to_value(&region.method)
+        .map_err(|e| JsError::new(&format!("Failed to serialize region method: {e}")))?;
 
-    serde_wasm_bindgen::to_value(&dims)
-        .map_err(|e| JsError::new(&format!("Failed to serialize dimensions: {e}")))
+    let obj = js_sys::Object::new();
+    let rgba = js_sys::Uint8Array::from(region.rgba.as_slice());
+    js_sys::Reflect::set(&obj, &"rgba".into(), &rgba)
+        .map_err(|_| JsError::new("Failed to set rgba property"))?;
+    js_sys::Reflect::set(&obj, &"width".into(), &region.width.into())
+        .map_err(|_| JsError::new("Failed to set width property"))?;
+    js_sys::Reflect::set(&obj, &"height".into(), &region.height.into())
+        .map_err(|_| JsError::new("Failed to set height property"))?;
+    js_sys::Reflect::set(&obj, &"method".into(), &method)
+        .map_err(|_| JsError::new("Failed to set method property"))?;
+
+    Ok(obj.unchecked_into())
 }
 
-/// Extract metadata from an image without fully decoding pixel data.
+/// Decode an image to RGBA8 pixels with its longer side at most `max_edge`, keeping the
+/// aspect ratio, e.g. for thumbnails and previews. Images already that small keep
+/// their own size.
 ///
-/// Returns a JavaScript object containing image dimensions, format, color info,
-/// EXIF data (if present), and PNG text chunks (if applicable).
+/// Returns `{ rgba: Uint8Array, width, height, method }` like `decode_region`.
+/// `method` is `"chunks"` when a TIFF (8-bit gray or RGB, tiled or striped) was reduced
+/// as its chunks were read, without holding the full-size pixels, `"jpeg_dc"` when a
+/// JPEG at least 8 times larger than `max_edge` was decoded at 1/8 scale from its DC
+/// coefficients alone, and `"full_decode"` when the whole image was decoded and then
+/// downscaled, as for WebP.
 ///
 /// # Errors
 ///
-/// Returns a `JsError` if the image format cannot be detected or metadata extraction fails.
+/// Returns a `JsError` if `max_edge` is zero or the input cannot be decoded.
 #[wasm_bindgen]
-pub fn get_image_metadata(input: &[u8]) -> Result<JsValue, JsError> {
-    let meta =
-        metadata::extract(input).map_err(|e| JsError::new(&format!("Metadata error: {e}")))?;
-    serde_wasm_bindgen::to_value(&meta)
-        .map_err(|e| JsError::new(&format!("Failed to serialize metadata: {e}")))
+pub fn decode_scaled(input: &[u8], max_edge: u32) -> Result<TsDecodedRegion, JsError> {
+    let scaled = region::decode_scaled(input, max_edge)
+        .map_err(|e| JsError::new(&format!("Failed to decode scaled image: {e}")))?;
+    let method = serde_wasm_bindgen::to_value(&scaled.method)
+        .map_err(|e| JsError::new(&format!("Failed to serialize decode method: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let rgba = js_sys::Uint8Array::from(scaled.rgba.as_slice());
+    js_sys::Reflect::set(&obj, &"rgba".into(), &rgba)
+        .map_err(|_| JsError::new("Failed to set rgba property"))?;
+    js_sys::Reflect::set(&obj, &"width".into(), &scaled.width.into())
+        .map_err(|_| JsError::new("Failed to set width property"))?;
+    js_sys::Reflect::set(&obj, &"height".into(), &scaled.height.into())
+        .map_err(|_| JsError::new("Failed to set height property"))?;
+    js_sys::Reflect::set(&obj, &"method".into(), &method)
+        .map_err(|_| JsError::new("Failed to set method property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Read the color of one pixel as `[r, g, b, a]`, e.g. for an eyedropper, without
+/// copying the decoded image into JS.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the pixel is outside the image or the input cannot be
+/// decoded.
+#[wasm_bindgen]
+pub fn get_pixel(input: &[u8], x: u32, y: u32) -> Result<TsRgba, JsError> {
+    let rgba = sample::get_pixel(input, x, y)
+        .map_err(|e| JsError::new(&format!("Failed to read pixel: {e}")))?;
+    serde_wasm_bindgen::to_value(&rgba)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize pixel: {e}")))
+}
+
+/// Sample at most `max_samples` pixels of `rect` (`{ x, y, width, height }`) on an
+/// even grid, e.g. to detect a background color, without copying the decoded image
+/// into JS. Rectangles with no more pixels than that are sampled in full.
+///
+/// Returns `{ samples, mean, most_common, most_common_share }`: each sample's
+/// position and `[r, g, b, a]` color row by row, the mean color, and the color seen
+/// most often with the share of samples that have it (near 1 on a flat background).
+///
+/// # Errors
+///
+/// Returns a `JsError` if `rect` isn't a rectangle, is empty or extends past the
+/// image, `max_samples` is zero, or the input cannot be decoded.
+#[wasm_bindgen]
+pub fn sample_region(
+    input: &[u8],
+    rect: TsCropBox,
+    max_samples: u32,
+) -> Result<TsRegionSamples, JsError> {
+    let rect: smart_crop::CropBox = serde_wasm_bindgen::from_value(rect.into())
+        .map_err(|e| JsError::new(&format!("Invalid rectangle: {e}")))?;
+    let sampled = sample::sample_region(input, rect, max_samples)
+        .map_err(|e| JsError::new(&format!("Failed to sample region: {e}")))?;
+    serde_wasm_bindgen::to_value(&sampled)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize samples: {e}")))
+}
+
+/// Clone-stamp `source` (`{ x, y, width, height }`, inside the image) so its top-left
+/// corner lands at (`x`, `y`), e.g. to remove a small blemish or cover sensitive
+/// details with surrounding texture rather than an obvious black box.
+///
+/// The copy fades out over its outer `feather` pixels (default 0, hard edges) so it
+/// blends into what's around it. Parts landing past the image edge are dropped.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `source` isn't a rectangle, is empty or extends past the
+/// image, the target format or quality is invalid, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn patch(
+    input: &[u8],
+    source: TsCropBox,
+    x: u32,
+    y: u32,
+    feather: Option<u32>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let source: smart_crop::CropBox = serde_wasm_bindgen::from_value(source.into())
+        .map_err(|e| JsError::new(&format!("Invalid rectangle: {e}")))?;
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    composite::patch_image(input, source, x, y, feather.unwrap_or(0), target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to patch image: {e}")))
+}
+
+/// Detect an image's background color from the pixels along its edges, e.g. to pick
+/// the color that letterboxes it (see `resize`'s `fill: "auto"`).
+///
+/// Returns `{ rgba, confidence }`: the color as `[r, g, b, a]`, and the share of the
+/// border close to it, from 0 to 1. A plain backdrop scores around 0.9 or more; a
+/// photo that runs off the edges scores low, and its `rgba` is just the most common
+/// edge color.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded.
+#[wasm_bindgen]
+pub fn detect_background(input: &[u8]) -> Result<TsBackground, JsError> {
+    let background = sample::detect_background(input)
+        .map_err(|e| JsError::new(&format!("Failed to detect background: {e}")))?;
+    serde_wasm_bindgen::to_value(&background)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize background: {e}")))
+}
+
+/// Decode only the first scan of a progressive JPEG or the first pass of an interlaced
+/// PNG, for an instant low-detail preview while the full conversion runs.
+///
+/// Returns `{ rgba: Uint8Array, width, height, source_width, source_height }`: the
+/// preview is 1/8 of the source size on each side (rounded up), so stretch it to
+/// `source_width` x `source_height` to show it in place of the image.
+///
+/// # Errors
+///
+/// Returns a `JsError` for baseline JPEGs, non-interlaced PNGs and other formats, which
+/// have no early pass to show, or if the input cannot be read.
+#[wasm_bindgen]
+pub fn quick_preview(input: &[u8]) -> Result<TsQuickPreview, JsError> {
+    let preview = preview::quick_preview(input)
+        .map_err(|e| JsError::new(&format!("Failed to build preview: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let rgba = js_sys::Uint8Array::from(preview.image.as_raw().as_slice());
+    js_sys::Reflect::set(&obj, &"rgba".into(), &rgba)
+        .map_err(|_| JsError::new("Failed to set rgba property"))?;
+    js_sys::Reflect::set(&obj, &"width".into(), &preview.image.width().into())
+        .map_err(|_| JsError::new("Failed to set width property"))?;
+    js_sys::Reflect::set(&obj, &"height".into(), &preview.image.height().into())
+        .map_err(|_| JsError::new("Failed to set height property"))?;
+    js_sys::Reflect::set(&obj, &"source_width".into(), &preview.source_width.into())
+        .map_err(|_| JsError::new("Failed to set source_width property"))?;
+    js_sys::Reflect::set(&obj, &"source_height".into(), &preview.source_height.into())
+        .map_err(|_| JsError::new("Failed to set source_height property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Cut an image into a Deep Zoom tile pyramid for viewers such as OpenSeadragon.
+///
+/// Level 0 is 1x1 and each level doubles in size up to the full image; every level is
+/// cut into `tile_size` square tiles (smaller at the right and bottom edges) encoded as
+/// `target_format`. Returns `{ descriptor, dzi, tiles }`: `descriptor` is `{ width,
+/// height, tile_size, overlap, format, levels }` with `{ level, width, height, columns,
+/// rows }` per level, `dzi` is the `.dzi` XML, and `tiles` holds `{ name, level,
+/// column, row, data }` from level 0 up, where `name` is the tile's DZI path such as
+/// `"12/3_4.jpg"` under the `_files` directory.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, `tile_size` is
+/// zero, or the image cannot be decoded or a tile encoded.
+#[wasm_bindgen]
+pub fn generate_tiles(
+    input: &[u8],
+    tile_size: u32,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<TsTilePyramid, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let pyramid = tiles::generate_tiles(input, tile_size, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to generate tiles: {e}")))?;
+
+    let descriptor = serde_wasm_bindgen::to_value(&pyramid.descriptor)
+        .map_err(|e| JsError::new(&format!("Failed to serialize tile descriptor: {e}")))?;
+
+    let tiles = js_sys::Array::new();
+    for tile in &pyramid.tiles {
+        let obj = js_sys::Object::new();
+        let data = js_sys::Uint8Array::from(tile.data.as_slice());
+        for (key, value) in [
+            ("name", JsValue::from(tile.name.as_str())),
+            ("level", tile.level.into()),
+            ("column", tile.column.into()),
+            ("row", tile.row.into()),
+            ("data", data.into()),
+        ] {
+            js_sys::Reflect::set(&obj, &key.into(), &value)
+                .map_err(|_| JsError::new(&format!("Failed to set tile {key} property")))?;
+        }
+        tiles.push(&obj);
+    }
+
+    let obj = js_sys::Object::new();
+    js_sys::Reflect::set(&obj, &"descriptor".into(), &descriptor)
+        .map_err(|_| JsError::new("Failed to set descriptor property"))?;
+    js_sys::Reflect::set(&obj, &"dzi".into(), &pyramid.descriptor.to_dzi().into())
+        .map_err(|_| JsError::new("Failed to set dzi property"))?;
+    js_sys::Reflect::set(&obj, &"tiles".into(), &tiles)
+        .map_err(|_| JsError::new("Failed to set tiles property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Compose a grid of encoded tiles into one image, e.g. to export edited map or scan
+/// tiles.
+///
+/// `layout` is `{ width, height, tile_width, tile_height }`: `tiles` are in row-major
+/// order, `tile_width` x `tile_height` except the last column and row, which are cut to
+/// fit `width` x `height` (one level of a `generate_tiles` pyramid, for example). PNG
+/// output is written one row of tiles at a time to keep memory bounded.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the layout, target format or quality is invalid, the tiles
+/// don't match the layout, or a tile cannot be decoded or the result encoded.
+#[wasm_bindgen]
+pub fn stitch_tiles(
+    tiles: Vec<js_sys::Uint8Array>,
+    layout: TsTileLayout,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let layout: tiles::TileLayout = serde_wasm_bindgen::from_value(layout.into())
+        .map_err(|e| JsError::new(&format!("Invalid tile layout: {e}")))?;
+
+    let tiles: Vec<Vec<u8>> = tiles.into_iter().map(|tile| tile.to_vec()).collect();
+    tiles::stitch_tiles(&tiles, layout, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to stitch tiles: {e}")))
+}
+
+/// Options accepted by [`contact_sheet`], read from a plain JS object. Every field is
+/// optional.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct JsContactSheetOptions {
+    columns: Option<u32>,
+    cell_size: Option<u32>,
+    gap: Option<u32>,
+    labels: Vec<String>,
+    header: Option<String>,
+    /// Hex color of the sheet.
+    background: Option<String>,
+}
+
+/// Lay a batch of images out as a labeled contact sheet, e.g. to deliver proofs.
+///
+/// `options` is `{ columns?, cell_size?, gap?, labels?, header?, background? }` (or
+/// `undefined`):
+/// - `columns`: cells per row (default 4)
+/// - `cell_size`: side of the square each thumbnail is fitted into (default 200);
+///   smaller images aren't enlarged
+/// - `gap`: pixels around and between cells (default 8)
+/// - `labels`: a caption under each cell, such as its filename, in image order
+/// - `header`: a line of text above the grid
+/// - `background`: hex color of the sheet (default white)
+///
+/// Text is drawn in a built-in pixel font and cut short with `...` when it doesn't
+/// fit. The sheet may be up to 16384 pixels on a side.
+///
+/// # Errors
+///
+/// Returns a `JsError` if there are no images, an option is invalid, the sheet would
+/// be too large, or an image cannot be decoded or the sheet encoded.
+#[wasm_bindgen]
+pub fn contact_sheet(
+    images: Vec<js_sys::Uint8Array>,
+    options: Option<TsContactSheetOptions>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let js_options: JsContactSheetOptions = match options.map(JsValue::from) {
+        Some(options) if !options.is_null() => serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsError::new(&format!("Invalid options: {e}")))?,
+        _ => JsContactSheetOptions::default(),
+    };
+    let defaults = montage::ContactSheetOptions::default();
+    let background = match js_options.background.as_deref() {
+        Some(text) => color::parse_color(text)
+            .map_err(|e| JsError::new(&format!("Invalid background: {e}")))?,
+        None => defaults.background,
+    };
+    let options = montage::ContactSheetOptions {
+        columns: js_options.columns.unwrap_or(defaults.columns),
+        cell_size: js_options.cell_size.unwrap_or(defaults.cell_size),
+        gap: js_options.gap.unwrap_or(defaults.gap),
+        labels: js_options.labels,
+        header: js_options.header,
+        background,
+    };
+
+    let images: Vec<Vec<u8>> = images.into_iter().map(|image| image.to_vec()).collect();
+    montage::encode_contact_sheet(&images, &options, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to build contact sheet: {e}")))
+}
+
+/// Join images edge to edge at their own size, e.g. a before/after comparison or a
+/// receipt photographed in parts.
+///
+/// `direction` is `"horizontal"` (side by side, the default for `""`) or `"vertical"`
+/// (stacked). `align` places images smaller across the strip: `"start"` (top or
+/// left), `"center"` (the default for `""`) or `"end"` (bottom or right). `gap` is the
+/// space between images in pixels, and `background` the hex color of gaps and empty
+/// space (default transparent). The result may be up to 16384 pixels on a side.
+///
+/// # Errors
+///
+/// Returns a `JsError` if there are no images, an option is invalid, the result would
+/// be too large, or an image cannot be decoded or the result encoded.
+#[wasm_bindgen]
+pub fn concat(
+    images: Vec<js_sys::Uint8Array>,
+    direction: &str,
+    align: &str,
+    gap: u32,
+    background: Option<String>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let direction = montage::Direction::from_name(direction)
+        .map_err(|e| JsError::new(&format!("Invalid direction: {e}")))?;
+    let align = montage::Align::from_name(align)
+        .map_err(|e| JsError::new(&format!("Invalid alignment: {e}")))?;
+    let background = match background {
+        Some(text) => color::parse_color(&text)
+            .map_err(|e| JsError::new(&format!("Invalid background: {e}")))?,
+        None => [0; 4],
+    };
+    let options = montage::ConcatOptions {
+        direction,
+        align,
+        gap,
+        background,
+    };
+
+    let images: Vec<Vec<u8>> = images.into_iter().map(|image| image.to_vec()).collect();
+    montage::encode_concat(&images, &options, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to join images: {e}")))
+}
+
+/// Options accepted by [`export_with_attribution`], read from a plain JS object. Every
+/// field is optional.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct JsAttributionOptions {
+    corner: Option<String>,
+    text_height: Option<u32>,
+    margin: Option<u32>,
+    /// Hex color of the text.
+    color: Option<String>,
+    /// Hex color of the box behind the text.
+    background: Option<String>,
+    fallback: Option<String>,
+}
+
+/// Caption an image with the artist and copyright from its EXIF data and encode it,
+/// for a one-call "export with attribution".
+///
+/// The caption is the copyright notice, marked with `(c)` if it doesn't say so
+/// already and preceded by the artist unless it names them, e.g.
+/// `"Jane Doe - (c) 2026 Example Agency"`. The image is turned upright by its EXIF
+/// orientation first, and JPEG and PNG output keeps the artist and copyright in its
+/// EXIF data.
+///
+/// `options` is `{ corner?, text_height?, margin?, color?, background?, fallback? }`
+/// (or `undefined`):
+/// - `corner`: `"top_left"`, `"top_right"`, `"bottom_left"` or `"bottom_right"`
+///   (default)
+/// - `text_height`: pixels; by default 1/30 of the shorter side, at least 7
+/// - `margin`: pixels from the edges; by default the text height
+/// - `color`: hex color of the text (default white)
+/// - `background`: hex color of a box behind the text (default half-transparent
+///   black); a fully transparent color such as `"#0000"` draws bare text
+/// - `fallback`: caption for files without an artist or copyright
+///
+/// Text is drawn in a built-in pixel font; characters outside ASCII show as `?`, and
+/// captions too wide for the image are cut short with `...`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if an option is invalid, the file has no artist or copyright
+/// and no `fallback` is given, or the image cannot be decoded or encoded.
+#[wasm_bindgen]
+pub fn export_with_attribution(
+    input: &[u8],
+    options: Option<TsAttributionOptions>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let js_options: JsAttributionOptions = match options.map(JsValue::from) {
+        Some(options) if !options.is_null() => serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsError::new(&format!("Invalid options: {e}")))?,
+        _ => JsAttributionOptions::default(),
+    };
+    let defaults = attribution::CaptionOptions::default();
+    let corner = match js_options.corner.as_deref() {
+        Some(name) => attribution::Corner::from_name(name)
+            .map_err(|e| JsError::new(&format!("Invalid corner: {e}")))?,
+        None => defaults.corner,
+    };
+    let color = match js_options.color.as_deref() {
+        Some(text) => {
+            color::parse_color(text).map_err(|e| JsError::new(&format!("Invalid color: {e}")))?
+        }
+        None => defaults.color,
+    };
+    let background = match js_options.background.as_deref() {
+        Some(text) => Some(
+            color::parse_color(text)
+                .map_err(|e| JsError::new(&format!("Invalid background: {e}")))?,
+        )
+        .filter(|background| background[3] > 0),
+        None => defaults.background,
+    };
+    let options = attribution::CaptionOptions {
+        corner,
+        text_height: js_options.text_height,
+        margin: js_options.margin,
+        color,
+        background,
+        fallback: js_options.fallback,
+    };
+
+    attribution::export_with_attribution(input, &options, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to export with attribution: {e}")))
+}
+
+/// Cut an image into a `rows` x `columns` grid and encode each piece, e.g. for
+/// Instagram-style grid posts or chunked uploads of large scans.
+///
+/// Pieces are returned as an array of `Uint8Array`s in row-major order. When the image
+/// doesn't divide evenly, piece sizes differ by at most one pixel.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, `rows` or `columns`
+/// is zero or exceeds the image size, or the image cannot be decoded or a piece
+/// encoded.
+#[wasm_bindgen]
+pub fn split_image(
+    input: &[u8],
+    rows: u32,
+    columns: u32,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<js_sys::Array, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let pieces = tiles::split_image(input, rows, columns, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to split image: {e}")))?;
+
+    Ok(pieces
+        .iter()
+        .map(|piece| js_sys::Uint8Array::from(piece.as_slice()))
+        .collect())
+}
+
+/// Add margins around an image, e.g. print bleed or room for a caption.
+///
+/// `mode` fills the margins: a hex color such as `"#ffffff"`, `"mirror"` to reflect the
+/// image at its edges, or `"repeat"` to stretch the edge pixels outward.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `mode`, the target format or quality is invalid, the result
+/// would be too large, or decoding or encoding fails.
+// One argument per side keeps the JS call flat, like the other encode-and-return
+// exports.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn extend_canvas(
+    input: &[u8],
+    left: u32,
+    right: u32,
+    top: u32,
+    bottom: u32,
+    mode: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let mode = canvas::FillMode::from_name(mode)
+        .map_err(|e| JsError::new(&format!("Invalid fill mode: {e}")))?;
+    let margins = canvas::Margins {
+        left,
+        right,
+        top,
+        bottom,
+    };
+
+    canvas::extend_canvas(input, margins, mode, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to extend canvas: {e}")))
+}
+
+/// Rectify a photographed document or whiteboard: map the quadrilateral `corners` onto
+/// an upright rectangle with bilinear sampling.
+///
+/// `corners` holds eight numbers, the `x, y` pixel positions of the top-left, top-right,
+/// bottom-right and bottom-left corners. `width` and `height` set the output size; when
+/// either is missing the output keeps the quadrilateral's longer edges at their length.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `corners` isn't eight numbers, the corners are degenerate,
+/// the target format or quality is invalid, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn rectify_perspective(
+    input: &[u8],
+    corners: &[f64],
+    width: Option<u32>,
+    height: Option<u32>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let [x0, y0, x1, y1, x2, y2, x3, y3] = <[f64; 8]>::try_from(corners).map_err(|_| {
+        JsError::new(&format!(
+            "Corners must be 8 numbers (x, y for each corner), got {}",
+            corners.len()
+        ))
+    })?;
+    let corners = [[x0, y0], [x1, y1], [x2, y2], [x3, y3]];
+
+    warp::rectify(input, corners, width.zip(height), target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to warp image: {e}")))
+}
+
+/// Apply an affine transform (any mix of rotation, scaling, shear and translation) with
+/// bilinear sampling.
+///
+/// `matrix` holds six numbers `[a, b, c, d, e, f]` moving source point `(x, y)` to
+/// `(a x + b y + c, d x + e y + f)`. `width` and `height` set the output size; when
+/// either is missing the output matches the input.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `matrix` isn't six numbers or can't be inverted, the target
+/// format or quality is invalid, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn warp_affine(
+    input: &[u8],
+    matrix: &[f64],
+    width: Option<u32>,
+    height: Option<u32>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let matrix = <[f64; 6]>::try_from(matrix)
+        .map_err(|_| JsError::new(&format!("Matrix must be 6 numbers, got {}", matrix.len())))?;
+
+    warp::warp_affine(input, matrix, width.zip(height), target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to warp image: {e}")))
+}
+
+/// Level a scanned or photographed document: estimate the skew of its text lines or
+/// edges (up to 15 degrees either way) and rotate it back about its center.
+///
+/// Returns `{ data, angle }`, where `data` is the leveled image encoded as
+/// `target_format` at the input's size, with uncovered corners filled with the page's
+/// background color, and `angle` is the detected skew in degrees, positive when the
+/// content was turned clockwise.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or decoding,
+/// rotating or encoding fails.
+#[wasm_bindgen]
+pub fn deskew(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<TsDeskewed, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let deskewed = deskew::deskew(input, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to deskew image: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let data = js_sys::Uint8Array::from(deskewed.data.as_slice());
+    js_sys::Reflect::set(&obj, &"data".into(), &data)
+        .map_err(|_| JsError::new("Failed to set data property"))?;
+    js_sys::Reflect::set(&obj, &"angle".into(), &deskewed.angle.into())
+        .map_err(|_| JsError::new("Failed to set angle property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Remove the uniform-color or transparent border around an image, e.g. the padding
+/// around a screenshot.
+///
+/// The border color is taken from the top-left pixel; edge rows and columns are removed
+/// while every pixel matches it within `tolerance` per channel (any fully transparent
+/// pixel matches a transparent corner). Returns `{ data, margins }`, where `margins` is
+/// `{ left, right, top, bottom }` in pixels removed. An image that is all border comes
+/// back uncropped.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn trim(
+    input: &[u8],
+    tolerance: u8,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<TsTrimmed, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let trimmed = canvas::trim_borders(input, tolerance, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to trim image: {e}")))?;
+    let margins = serde_wasm_bindgen::to_value(&trimmed.margins)
+        .map_err(|e| JsError::new(&format!("Failed to serialize margins: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let data = js_sys::Uint8Array::from(trimmed.data.as_slice());
+    js_sys::Reflect::set(&obj, &"data".into(), &data)
+        .map_err(|_| JsError::new("Failed to set data property"))?;
+    js_sys::Reflect::set(&obj, &"margins".into(), &margins)
+        .map_err(|_| JsError::new("Failed to set margins property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Detect edges and return them as an image: `"sobel"` for the gradient magnitude
+/// (brighter where edges are stronger) or `"canny"` (default) for thin white edges on
+/// black.
+///
+/// `low` and `high` are Canny's hysteresis thresholds on the same 0–255 scale as the
+/// Sobel map (20 and 50 by default): gradients at or above `high` are edges, and those
+/// at or above `low` are kept where they connect to one. Sobel ignores them.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the method, thresholds, target format or quality are
+/// invalid, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn detect_edges(
+    input: &[u8],
+    method: &str,
+    low: Option<f64>,
+    high: Option<f64>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let method = edges::EdgeMethod::from_name(method)
+        .map_err(|e| JsError::new(&format!("Invalid edge method: {e}")))?;
+    let options = edges::EdgeOptions {
+        method,
+        low: low.unwrap_or(edges::DEFAULT_LOW),
+        high: high.unwrap_or(edges::DEFAULT_HIGH),
+    };
+
+    edges::detect_edges(input, &options, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to detect edges: {e}")))
+}
+
+/// Trace an image's Canny edges into simplified polylines, e.g. to find a subject's
+/// outline for cropping.
+///
+/// Returns an array of `{ points, closed }`, where `points` holds `[x, y]` pixel
+/// coordinates and `closed` marks outlines that loop back to their start. `low` and
+/// `high` are as for `detect_edges`; `epsilon` is how far, in pixels, the simplified
+/// polyline may stray from the traced edge (1.5 by default).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the thresholds or `epsilon` are invalid, or the input cannot
+/// be decoded.
+#[wasm_bindgen]
+pub fn find_contours(
+    input: &[u8],
+    low: Option<f64>,
+    high: Option<f64>,
+    epsilon: Option<f64>,
+) -> Result<TsContours, JsError> {
+    let contours = edges::find_contours(
+        input,
+        low.unwrap_or(edges::DEFAULT_LOW),
+        high.unwrap_or(edges::DEFAULT_HIGH),
+        epsilon.unwrap_or(edges::DEFAULT_EPSILON),
+    )
+    .map_err(|e| JsError::new(&format!("Failed to find contours: {e}")))?;
+
+    serde_wasm_bindgen::to_value(&contours)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize contours: {e}")))
+}
+
+/// Clean up a phone photo of a document so it looks scanned: level it, trim the
+/// uniform border around the page, balance the paper to white, stretch contrast, and
+/// with `binarize` reduce it to 1-bit black and white for text-only pages.
+///
+/// Always returns a PNG.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded, is too large to level, or the
+/// output cannot be encoded.
+#[wasm_bindgen]
+pub fn scan_cleanup(input: &[u8], binarize: Option<bool>) -> Result<Vec<u8>, JsError> {
+    let options = scan::ScanOptions {
+        binarize: binarize.unwrap_or(false),
+        ..scan::ScanOptions::default()
+    };
+    scan::scan_cleanup(input, &options)
+        .map_err(|e| JsError::new(&format!("Failed to clean up scan: {e}")))
+}
+
+/// Crop an image to the `width`:`height` aspect ratio around its most interesting
+/// part, then resize it to `width` x `height`, e.g. for avatars and thumbnails where a
+/// center crop would cut off the subject.
+///
+/// The crop is as large as the aspect ratio allows and slides to keep the most detail
+/// (edges and saturated color). `focus` is an optional array of `{ x, y, width, height }`
+/// boxes that the crop keeps in frame ahead of anything else, such as faces from
+/// `detect_faces` in builds with the `faces` feature, or from a detector in the page
+/// like the Shape Detection API's `FaceDetector`. `smart_crop_faces` detects them in
+/// one call.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the size is zero, `focus` isn't an array of boxes, the
+/// target format or quality is invalid, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn smart_crop(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    focus: Option<TsCropBoxes>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let focus: Vec<smart_crop::CropBox> = match focus.map(JsValue::from) {
+        Some(focus) if !focus.is_null() => serde_wasm_bindgen::from_value(focus)
+            .map_err(|e| JsError::new(&format!("Invalid focus regions: {e}")))?,
+        _ => Vec::new(),
+    };
+
+    smart_crop::smart_crop(input, width, height, &focus, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to smart crop image: {e}")))
+}
+
+/// Find the faces in an image with a pico cascade, such as pico's `facefinder`, which
+/// the host loads and passes as `cascade` since no model is compiled in.
+///
+/// Returns an array of `{ x, y, width, height, score }`, highest score first, that can
+/// be passed straight to `smart_crop` as focus regions. Faces scoring at or below
+/// `min_score` (default 50, which suits `facefinder`) are left out.
+///
+/// Only available in builds with the `faces` feature.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the cascade can't be read or the input cannot be decoded.
+#[cfg(feature = "faces")]
+#[wasm_bindgen]
+pub fn detect_faces(
+    input: &[u8],
+    cascade: &[u8],
+    min_score: Option<f32>,
+) -> Result<TsFaces, JsError> {
+    let mut options = faces::FaceOptions::default();
+    if let Some(min_score) = min_score {
+        options.min_score = min_score;
+    }
+    let faces = faces::detect_faces(input, cascade, &options)
+        .map_err(|e| JsError::new(&format!("Failed to detect faces: {e}")))?;
+
+    serde_wasm_bindgen::to_value(&faces)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize faces: {e}")))
+}
+
+/// Like `smart_crop`, but also keeps the faces `detect_faces` finds with `cascade` in
+/// frame.
+///
+/// Only available in builds with the `faces` feature.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the size is zero, the cascade can't be read, `focus` isn't an
+/// array of boxes, the target format or quality is invalid, or decoding or encoding
+/// fails.
+#[cfg(feature = "faces")]
+#[wasm_bindgen]
+pub fn smart_crop_faces(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    focus: Option<TsCropBoxes>,
+    cascade: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let focus: Vec<smart_crop::CropBox> = match focus.map(JsValue::from) {
+        Some(focus) if !focus.is_null() => serde_wasm_bindgen::from_value(focus)
+            .map_err(|e| JsError::new(&format!("Invalid focus regions: {e}")))?,
+        _ => Vec::new(),
+    };
+
+    faces::smart_crop_faces(input, width, height, &focus, cascade, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to smart crop image: {e}")))
+}
+
+/// Locate the QR codes in an image, e.g. on a receipt or ticket before it is shared.
+///
+/// Returns an array of `{ x, y, width, height, module_size }`, largest first, where
+/// the box covers the whole code in image pixels. Codes are located by their finder
+/// patterns only, so damaged codes are found too; `read_qr_codes` decodes payloads.
+/// Barcodes and other symbologies aren't found.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded.
+#[wasm_bindgen]
+pub fn locate_qr_codes(input: &[u8]) -> Result<TsQrCodeLocations, JsError> {
+    let codes = codes::locate_qr_codes(input)
+        .map_err(|e| JsError::new(&format!("Failed to locate QR codes: {e}")))?;
+
+    serde_wasm_bindgen::to_value(&codes)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize codes: {e}")))
+}
+
+/// Read the QR codes in an image, e.g. to show what a ticket's code links to before
+/// deciding whether to redact it.
+///
+/// Returns an array of `{ payload, version, error_correction, location }`, largest
+/// first, where `location` is what `locate_qr_codes` returns for the code and
+/// `error_correction` is `"L"`, `"M"`, `"Q"` or `"H"`. Payloads are text: byte segments
+/// are read as UTF-8, or ISO-8859-1 when the code says so or they aren't valid UTF-8.
+/// Codes that are too damaged to read, use Kanji segments, or are mirrored or light on
+/// dark are left out.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded.
+#[wasm_bindgen]
+pub fn read_qr_codes(input: &[u8]) -> Result<TsQrCodes, JsError> {
+    let codes = codes::read_qr_codes(input)
+        .map_err(|e| JsError::new(&format!("Failed to read QR codes: {e}")))?;
+
+    serde_wasm_bindgen::to_value(&codes)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize codes: {e}")))
+}
+
+/// Find and read the QR codes in an image in one call, optionally redacting them, e.g.
+/// to list what a receipt's codes link to while blurring them for sharing.
+///
+/// Returns `{ codes, data }`. `codes` is an array of `{ payload, location }`, largest
+/// first, where `location` is what `locate_qr_codes` returns and `payload` is the text
+/// `read_qr_codes` would read, or `undefined` for a code that is found but can't be
+/// read. When `redact_format` is given, `data` is the image with every code found
+/// blurred as `redact_qr_codes` does, encoded in that format; otherwise it is
+/// `undefined`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the redaction format or quality is invalid, or decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn detect_codes(
+    input: &[u8],
+    redact_format: Option<String>,
+    quality: Option<u8>,
+) -> Result<TsDetectedCodes, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = redact_format
+        .map(|name| ImageFormat::from_name(&name))
+        .transpose()
+        .map_err(|e| JsError::new(&format!("Invalid redaction format: {e}")))?;
+
+    let detected = codes::detect_codes(input, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to detect codes: {e}")))?;
+    let found = serde_wasm_bindgen::to_value(&detected.codes)
+        .map_err(|e| JsError::new(&format!("Failed to serialize codes: {e}")))?;
+    let data = detected.redacted.map_or(JsValue::UNDEFINED, |data| {
+        js_sys::Uint8Array::from(data.as_slice()).into()
+    });
+
+    let obj = js_sys::Object::new();
+    js_sys::Reflect::set(&obj, &"codes".into(), &found)
+        .map_err(|_| JsError::new("Failed to set codes property"))?;
+    js_sys::Reflect::set(&obj, &"data".into(), &data)
+        .map_err(|_| JsError::new("Failed to set data property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Blur every QR code `locate_qr_codes` finds in an image so it can no longer be
+/// scanned.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn redact_qr_codes(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    codes::redact_qr_codes(input, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to redact QR codes: {e}")))
+}
+
+/// Resize an image with CSS `object-fit` / ImageMagick semantics.
+///
+/// `mode` is one of:
+/// - `"fit"` (default): fit inside `width` x `height`, keeping the aspect ratio; the
+///   output is the scaled image (ImageMagick `WxH`)
+/// - `"fill"`: stretch to exactly `width` x `height` (CSS `fill`, ImageMagick `WxH!`)
+/// - `"cover"`: cover `width` x `height` and crop the overflow from both sides equally
+///   (CSS `cover`)
+/// - `"contain"`: fit inside and center on a `width` x `height` canvas (CSS
+///   `contain`), padded with `fill`
+/// - `"long_edge"` / `"short_edge"`: scale so the longer / shorter side is `width`
+///   pixels; `height` is ignored
+///
+/// The constrained side gets exactly the requested size; the other is scaled in
+/// proportion, rounded half up, and at least 1. Odd pixels of crop or padding go to
+/// the right or bottom. `resize_geometry` returns the same sizes without decoding.
+///
+/// With `no_upscale`, sources the mode would enlarge keep their own size, so thumbnail
+/// pipelines don't need to check dimensions first: `"cover"` only crops the sides that
+/// overflow the box, `"contain"` pads the unscaled source, and `"fill"` stops
+/// stretching each side at the source's size. A warning is logged when this happens.
+///
+/// Downscales of more than 3x halve the image progressively before the final pass,
+/// which avoids moire on fine detail; pass `progressive: false` for a single Lanczos3
+/// pass. Large TIFF and JPEG downscales first decode at reduced resolution, as
+/// `decode_scaled` does, so the full-size pixels are never held.
+///
+/// `fill` is what pads `"contain"` output: `"transparent"` (default; formats without
+/// alpha show it as black), a hex color, or `"auto"` for the background color
+/// `detect_background` finds on the source's border, so letterboxed product shots and
+/// scans blend in.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the mode, size, fill, target format or quality is invalid,
+/// or decoding or encoding fails.
+// Flat optional flags keep the JS call in line with the other encode-and-return
+// exports.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn resize(
+    input: &[u8],
+    mode: &str,
+    width: u32,
+    height: Option<u32>,
+    no_upscale: Option<bool>,
+    progressive: Option<bool>,
+    target_format: &str,
+    quality: Option<u8>,
+    fill: Option<String>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let mode = resize::ResizeMode::from_name(mode)
+        .map_err(|e| JsError::new(&format!("Invalid resize mode: {e}")))?;
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let defaults = resize::ResizeOptions::default();
+    let options = resize::ResizeOptions {
+        no_upscale: no_upscale.unwrap_or(defaults.no_upscale),
+        progressive: progressive.unwrap_or(defaults.progressive),
+        fill: pad_fill(fill)?,
+    };
+
+    resize::resize(
+        input,
+        mode,
+        width,
+        height.unwrap_or(width),
+        options,
+        target,
+        quality,
+    )
+    .map_err(|e| JsError::new(&format!("Failed to resize image: {e}")))
+}
+
+/// Compute the sizes `resize` would produce for a `source_width` x `source_height`
+/// image, e.g. to lay out a page before the image is processed.
+///
+/// Returns `{ scaled_width, scaled_height, width, height, x, y, upscale_prevented }`:
+/// the source is scaled to `scaled_width` x `scaled_height` and placed at (`x`, `y`) on
+/// a `width` x `height` output. `x` and `y` are negative where `"cover"` crops.
+/// `upscale_prevented` is true when `no_upscale` kept the source at its own size.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the mode or a size is invalid.
+#[wasm_bindgen]
+pub fn resize_geometry(
+    source_width: u32,
+    source_height: u32,
+    mode: &str,
+    width: u32,
+    height: Option<u32>,
+    no_upscale: Option<bool>,
+) -> Result<TsResizeGeometry, JsError> {
+    let mode = resize::ResizeMode::from_name(mode)
+        .map_err(|e| JsError::new(&format!("Invalid resize mode: {e}")))?;
+    let geometry = resize::geometry(
+        source_width,
+        source_height,
+        mode,
+        width,
+        height.unwrap_or(width),
+        no_upscale.unwrap_or(false),
+    )
+    .map_err(|e| JsError::new(&format!("Failed to compute resize: {e}")))?;
+
+    serde_wasm_bindgen::to_value(&geometry)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize geometry: {e}")))
+}
+
+/// Generate a placeholder or test image, e.g. for skeleton loaders and fixtures.
+///
+/// `spec` is `{ width, height, fill }`, where `fill` is one of:
+/// - `{ kind: "solid", color }`
+/// - `{ kind: "linear_gradient", from, to, angle? }`: `angle` in degrees clockwise from
+///   left-to-right (default 0), so 90 runs top to bottom
+/// - `{ kind: "radial_gradient", inner, outer }`: from the center to the farthest corner
+/// - `{ kind: "checkerboard", a, b, cell_size? }`: `cell_size` in pixels (default 8)
+/// - `{ kind: "noise", seed?, from?, to? }`: random blends between `from` (default
+///   black) and `to` (default white); the same seed always gives the same image
+///
+/// Colors are hex strings such as `"#ff8000"` or `"#0008"`. Sides may be up to 16384.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the spec is malformed, the size is out of range, the target
+/// format or quality is invalid, or encoding fails.
+#[wasm_bindgen]
+pub fn generate(
+    spec: TsGenerateSpec,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let spec: generate::GenerateSpec = serde_wasm_bindgen::from_value(spec.into())
+        .map_err(|e| JsError::new(&format!("Invalid image spec: {e}")))?;
+
+    generate::generate(&spec, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to generate image: {e}")))
+}
+
+/// Generate a square avatar showing a user's initials, for users without a profile
+/// photo.
+///
+/// Up to three characters of `initials` are drawn in white, uppercased and centered,
+/// in a built-in pixel font. The background is picked from a fixed palette by hashing
+/// `palette_seed` (e.g. a user ID), so each user keeps the same color.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `size` is 0 or above 16384, the target format or quality is
+/// invalid, or encoding fails.
+#[wasm_bindgen]
+pub fn generate_avatar(
+    initials: &str,
+    size: u32,
+    palette_seed: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    generate::generate_avatar(initials, size, palette_seed, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to generate avatar: {e}")))
+}
+
+/// Draw one image over another, e.g. a logo or badge.
+///
+/// `overlay`'s top-left corner goes at (`x`, `y`) on `input`, which may be negative or
+/// past the edge; only the overlapping part is drawn. `opacity` (0 to 1, default 1)
+/// scales the overlay's own alpha.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn overlay_image(
+    input: &[u8],
+    overlay: &[u8],
+    x: i32,
+    y: i32,
+    opacity: Option<f64>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let layer = image::load_from_memory(overlay)
+        .map_err(|e| JsError::new(&format!("Failed to decode overlay: {e}")))?
+        .into_rgba8();
+
+    composite::composite(
+        input,
+        &composite::Source::Image(layer),
+        i64::from(x),
+        i64::from(y),
+        opacity.unwrap_or(1.0),
+        target,
+        quality,
+    )
+    .map_err(|e| JsError::new(&format!("Failed to composite image: {e}")))
+}
+
+/// A fill layer for [`overlay_fill`], read from a plain JS object.
+#[derive(Debug, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct JsFillLayer {
+    fill: generate::Fill,
+    #[serde(default)]
+    x: i32,
+    #[serde(default)]
+    y: i32,
+    width: Option<u32>,
+    height: Option<u32>,
+    opacity: Option<f64>,
+}
+
+/// Draw a gradient or pattern over an image, e.g. a fade-to-black under captions, a
+/// badge background or a watermark ribbon, without shipping a bitmap for it.
+///
+/// `layer` is `{ fill, x?, y?, width?, height?, opacity? }`, where `fill` is as for
+/// `generate`. The fill is rendered at `width` x `height` (by default the image's
+/// size) with its top-left corner at (`x`, `y`), and `opacity` (0 to 1, default 1)
+/// scales its alpha.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the layer is malformed, the target format or quality is
+/// invalid, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn overlay_fill(
+    input: &[u8],
+    layer: TsFillLayer,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let layer: JsFillLayer = serde_wasm_bindgen::from_value(layer.into())
+        .map_err(|e| JsError::new(&format!("Invalid fill layer: {e}")))?;
+    let source = composite::Source::Fill {
+        fill: layer.fill,
+        width: layer.width,
+        height: layer.height,
+    };
+
+    composite::composite(
+        input,
+        &source,
+        i64::from(layer.x),
+        i64::from(layer.y),
+        layer.opacity.unwrap_or(1.0),
+        target,
+        quality,
+    )
+    .map_err(|e| JsError::new(&format!("Failed to composite image: {e}")))
+}
+
+/// Register a TrueType or OpenType font for `draw_text` and `measure_text`.
+///
+/// The font is parsed once, here, and kept until `unregister_font` or the page is
+/// unloaded, so drawing many captions doesn't re-read it. Registering a name again
+/// (compared case-insensitively) replaces that font. Returns
+/// `{ name, glyph_count, scripts }`, where `scripts` lists which of `"latin"` and
+/// `"cyrillic"` the font fully covers.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `name` is blank or contains a comma, or `data` isn't a font.
+#[wasm_bindgen]
+pub fn register_font(name: &str, data: Vec<u8>) -> Result<TsFontInfo, JsError> {
+    let info = fonts::register_font(name, data)
+        .map_err(|e| JsError::new(&format!("Failed to register font: {e}")))?;
+    serde_wasm_bindgen::to_value(&info)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize font info: {e}")))
+}
+
+/// Remove a font registered with `register_font`. Returns whether there was one.
+#[wasm_bindgen]
+pub fn unregister_font(name: &str) -> bool {
+    fonts::unregister_font(name)
+}
+
+/// List the registered fonts, in registration order, as for `register_font`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the list cannot be serialized.
+#[wasm_bindgen]
+pub fn list_fonts() -> Result<TsFontInfos, JsError> {
+    serde_wasm_bindgen::to_value(&fonts::list_fonts())
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize font list: {e}")))
+}
+
+/// Draw text on an image in registered fonts, e.g. a caption or watermark.
+///
+/// `fonts` is a comma-separated fallback list of registered font names (`""` for all of
+/// them, in registration order): each character is drawn in the first font that has
+/// it. The top-left corner of the first line is at (`x`, `y`), `size` is in pixels per
+/// em, and `color` is a hex color (`#rrggbbaa` for translucent text). Lines break at
+/// `\n`. Pairs of letters are kerned and combining accents are placed over the letter
+/// before them, which covers Latin and Cyrillic text; there are no ligatures.
+///
+/// # Errors
+///
+/// Returns a `JsError` if a font isn't registered, the size, color, target format or
+/// quality is invalid, or decoding or encoding fails.
+// Flat arguments keep the JS call in line with the other encode-and-return exports.
+#[allow(clippy::too_many_arguments)]
+#[wasm_bindgen]
+pub fn draw_text(
+    input: &[u8],
+    text: &str,
+    fonts: &str,
+    x: i32,
+    y: i32,
+    size: u32,
+    color: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let color =
+        color::parse_color(color).map_err(|e| JsError::new(&format!("Invalid color: {e}")))?;
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let names = font_names(fonts);
+
+    fonts::render_text(
+        input,
+        text,
+        &names,
+        i64::from(x),
+        i64::from(y),
+        size,
+        color,
+        target,
+        quality,
+    )
+    .map_err(|e| JsError::new(&format!("Failed to draw text: {e}")))
+}
+
+/// Measure text as `draw_text` would lay it out, e.g. to right-align a watermark.
+///
+/// Returns `{ width, height }` in pixels, rounded up.
+///
+/// # Errors
+///
+/// Returns a `JsError` if a font isn't registered or `size` is zero or above 16384.
+#[wasm_bindgen]
+pub fn measure_text(text: &str, fonts: &str, size: u32) -> Result<TsDimensions, JsError> {
+    let dims = fonts::measure_text(text, &font_names(fonts), size)
+        .map_err(|e| JsError::new(&format!("Failed to measure text: {e}")))?;
+    serde_wasm_bindgen::to_value(&dims)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize dimensions: {e}")))
+}
+
+/// The names in a comma-separated font list, without blanks.
+fn font_names(list: &str) -> Vec<&str> {
+    list.split(',')
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+        .collect()
+}
+
+/// Read the dimensions of an image without fully decoding its pixel data.
+///
+/// Returns a JavaScript object with `width` and `height` properties (both `u32`).
+/// For animated GIF, WebP and APNG files these are the canvas (a GIF's logical
+/// screen), which every decoded frame fills; [`inspect_image`] also reports where the
+/// first frame sits on it.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the image format cannot be guessed or the dimensions cannot be read.
+#[wasm_bindgen]
+pub fn get_dimensions(input: &[u8]) -> Result<TsDimensions, JsError> {
+    let dims = convert::dimensions(input).map_err(|e| JsError::new(&e.to_string()))?;
+
+    serde_wasm_bindgen::to_value(&dims)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize dimensions: {e}")))
+}
+
+/// Extract metadata from an image without fully decoding pixel data.
+///
+/// Returns a JavaScript object containing image dimensions, format, color info,
+/// EXIF data (if present), and PNG text chunks (if applicable).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the image format cannot be detected or metadata extraction fails.
+#[wasm_bindgen]
+pub fn get_image_metadata(input: &[u8]) -> Result<TsImageMetadata, JsError> {
+    let meta =
+        metadata::extract(input).map_err(|e| JsError::new(&format!("Metadata error: {e}")))?;
+    serde_wasm_bindgen::to_value(&meta)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize metadata: {e}")))
+}
+
+/// Extract a single frame from an animated GIF, WebP, or APNG as a static image.
+///
+/// `frame` selects which frame to use: `"first"` (or an empty string), `"middle"`,
+/// or a zero-based index such as `"3"`. Static inputs are treated as a one-frame
+/// animation. Frames after the selected one are not decoded.
+///
+/// # Errors
+///
+/// Returns a `JsError` if:
+/// - The target format name is not recognized or not encodable
+/// - The quality value is outside the 1-100 range
+/// - The frame selector is invalid or past the last frame
+/// - The input cannot be decoded
+#[wasm_bindgen]
+pub fn poster_frame(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+    frame: &str,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let selection = animation::FrameSelection::from_name(frame)
+        .map_err(|e| JsError::new(&format!("Invalid frame selection: {e}")))?;
+
+    animation::poster_frame(input, selection, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to extract poster frame: {e}")))
+}
+
+/// Lay the frames of an animated image out in a grid PNG sprite sheet.
+///
+/// Returns a `JsValue` object with `sheet` (Uint8Array, PNG bytes) and `metadata`
+/// (`frame_width`, `frame_height`, `frame_count`, `columns`, `rows`, `delays_ms`).
+/// `columns` is clamped to the number of frames.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `columns` is zero, the input cannot be decoded, the sheet
+/// would be larger than 16384 pixels on a side, or the sheet cannot be encoded.
+#[wasm_bindgen]
+pub fn to_sprite_sheet(input: &[u8], columns: u32) -> Result<JsValue, JsError> {
+    let result = sprite::to_sprite_sheet(input, columns)
+        .map_err(|e| JsError::new(&format!("Failed to build sprite sheet: {e}")))?;
+
+    let metadata = serde_wasm_bindgen::to_value(&result.metadata)
+        .map_err(|e| JsError::new(&format!("Failed to serialize sprite metadata: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let sheet_array = js_sys::Uint8Array::from(result.sheet.as_slice());
+    js_sys::Reflect::set(&obj, &"sheet".into(), &sheet_array)
+        .map_err(|_| JsError::new("Failed to set sheet property"))?;
+    js_sys::Reflect::set(&obj, &"metadata".into(), &metadata)
+        .map_err(|_| JsError::new("Failed to set metadata property"))?;
+
+    Ok(obj.into())
+}
+
+/// Cut a sprite sheet into equally sized tiles and encode each one.
+///
+/// Tiles are returned as an array of `Uint8Array`s in row-major order. Partial tiles
+/// at the right/bottom edge are skipped, and `frame_count` (if given) limits how many
+/// tiles are returned.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, a tile dimension
+/// is zero or larger than the sheet, or the sheet cannot be decoded.
+#[wasm_bindgen]
+pub fn slice_sprite_sheet(
+    input: &[u8],
+    tile_width: u32,
+    tile_height: u32,
+    frame_count: Option<u32>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<js_sys::Array, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let tiles =
+        sprite::slice_to_images(input, tile_width, tile_height, frame_count, target, quality)
+            .map_err(|e| JsError::new(&format!("Failed to slice sprite sheet: {e}")))?;
+
+    Ok(tiles
+        .iter()
+        .map(|tile| js_sys::Uint8Array::from(tile.as_slice()))
+        .collect())
+}
+
+/// Cut a sprite sheet into tiles and assemble them into a looping animated GIF.
+///
+/// Every frame is shown for `delay_ms` milliseconds. See `slice_sprite_sheet` for
+/// how tiles are selected.
+///
+/// # Errors
+///
+/// Returns a `JsError` if a tile dimension is zero or larger than the sheet,
+/// the sheet cannot be decoded, or the GIF cannot be encoded.
+#[wasm_bindgen]
+pub fn sprite_sheet_to_gif(
+    input: &[u8],
+    tile_width: u32,
+    tile_height: u32,
+    frame_count: Option<u32>,
+    delay_ms: u32,
+) -> Result<Vec<u8>, JsError> {
+    sprite::slice_to_gif(input, tile_width, tile_height, frame_count, delay_ms)
+        .map_err(|e| JsError::new(&format!("Failed to build GIF from sprite sheet: {e}")))
+}
+
+/// Re-encode an animated GIF/WebP/APNG as a lossless animated WebP.
+///
+/// Consecutive duplicate frames are merged. If `max_bytes` is given and the output is
+/// too large, the frames are quantized to fewer colors (one palette shared by all of
+/// them) and frames are dropped (with their delays merged into the kept frames) until
+/// it fits.
+///
+/// Returns a `JsValue` object with `webp` (Uint8Array) and `report` describing the
+/// reductions applied (`input_frames`, `output_frames`, `duplicate_frames_merged`,
+/// `frame_step`, `max_colors` (`undefined` when colors were kept), `output_bytes`,
+/// `met_target`).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded, the canvas is larger than
+/// 16384 px, or a frame fails to encode.
+#[wasm_bindgen]
+pub fn to_animated_webp(input: &[u8], max_bytes: Option<u32>) -> Result<JsValue, JsError> {
+    let max_bytes = max_bytes
+        .map(usize::try_from)
+        .transpose()
+        .map_err(|_| JsError::new("max_bytes is too large"))?;
+
+    let result = webp_anim::to_animated_webp(input, max_bytes)
+        .map_err(|e| JsError::new(&format!("Failed to encode animated WebP: {e}")))?;
+
+    let report = serde_wasm_bindgen::to_value(&result.report)
+        .map_err(|e| JsError::new(&format!("Failed to serialize WebP report: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let webp_array = js_sys::Uint8Array::from(result.data.as_slice());
+    js_sys::Reflect::set(&obj, &"webp".into(), &webp_array)
+        .map_err(|_| JsError::new("Failed to set webp property"))?;
+    js_sys::Reflect::set(&obj, &"report".into(), &report)
+        .map_err(|_| JsError::new("Failed to set report property"))?;
+
+    Ok(obj.into())
+}
+
+/// Re-encode an animated GIF/WebP/APNG as an APNG.
+///
+/// APNG keeps full 24-bit color and 8-bit alpha per frame, which GIF cannot represent.
+/// Frame delays are preserved. Static inputs produce a one-frame APNG.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded or the APNG cannot be encoded.
+#[wasm_bindgen]
+pub fn to_apng(input: &[u8]) -> Result<Vec<u8>, JsError> {
+    let frames = animation::decode_frames(input)
+        .map_err(|e| JsError::new(&format!("Failed to decode animation: {e}")))?;
+    animation::encode_apng(&frames, None).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Build an APNG from a list of encoded still images.
+///
+/// `frames` is an array of `Uint8Array`s in any supported input format, all with the
+/// same dimensions. `delays_ms` gives each frame's display time in milliseconds and
+/// must have the same length as `frames`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the lists are empty or differ in length, a frame cannot be
+/// decoded, frame sizes differ, or the APNG cannot be encoded.
+#[wasm_bindgen]
+pub fn encode_apng_from_frames(
+    frames: Vec<js_sys::Uint8Array>,
+    delays_ms: &[u32],
+) -> Result<Vec<u8>, JsError> {
+    let images: Vec<Vec<u8>> = frames.into_iter().map(|frame| frame.to_vec()).collect();
+    let frames = animation::frames_from_images(&images, delays_ms)
+        .map_err(|e| JsError::new(&format!("Invalid frames: {e}")))?;
+    animation::encode_apng(&frames, None).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Resample an animated GIF/WebP/APNG to a constant frame rate.
+///
+/// Frames are merged (lower `fps`) or repeated (higher `fps`) so that every output
+/// frame lasts `1000 / fps` ms while the total duration stays the same. `fps` must be
+/// between 1 and 100. `target_format` selects the output container: `"gif"`, `"png"`
+/// (APNG), or `"webp"` (lossless animated WebP).
+///
+/// `max_memory_bytes`, if given, caps the pixels of all output frames together at 4
+/// bytes per pixel, so a long animation resampled to a high rate fails cleanly
+/// instead of producing an enormous file.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target cannot hold an animation, `fps` is out of range,
+/// the output would exceed `max_memory_bytes`, or the input cannot be decoded.
+#[wasm_bindgen]
+pub fn resample_animation(
+    input: &[u8],
+    fps: u32,
+    target_format: &str,
+    max_memory_bytes: Option<u32>,
+) -> Result<Vec<u8>, JsError> {
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let frames = animation::decode_frames(input)
+        .map_err(|e| JsError::new(&format!("Failed to decode animation: {e}")))?;
+    let resampled = animation::resample_frames(&frames, fps, max_memory_bytes.map(u64::from))
+        .map_err(|e| JsError::new(&format!("Failed to resample animation: {e}")))?;
+
+    animation::encode_frame_refs(&resampled, target, None).map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Rotate a JPEG clockwise by 90, 180 or 270 degrees without re-encoding pixels.
+///
+/// The DCT coefficients are rearranged directly (like `jpegtran -rotate -trim`), so the
+/// result has no generation loss. The output uses optimized Huffman tables. Partial
+/// MCUs on edges that move to the top/left are trimmed. Metadata segments are
+/// preserved unchanged.
+///
+/// With `metadata_only`, only the EXIF orientation tag is updated, combined with any
+/// existing orientation. That is instant even on very large files and leaves the image
+/// data byte for byte; the rotation can be baked in later. Viewers that ignore EXIF
+/// orientation show the image unrotated.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the angle is invalid or the input is not a baseline or
+/// extended sequential JPEG (any JPEG with `metadata_only`).
+#[wasm_bindgen]
+pub fn rotate_jpeg_lossless(
+    input: &[u8],
+    degrees: u32,
+    metadata_only: Option<bool>,
+) -> Result<Vec<u8>, JsError> {
+    let rotated = if metadata_only.unwrap_or(false) {
+        jpeg_lossless::rotate_jpeg_orientation(input, degrees)
+    } else {
+        jpeg_lossless::rotate_jpeg_lossless(input, degrees)
+    };
+    rotated.map_err(|e| JsError::new(&e.to_string()))
+}
+
+/// Set a JPEG's EXIF orientation tag (1-8) without touching the image data, e.g. to
+/// reset it to 1 after baking a rotation into the pixels.
+///
+/// An EXIF segment is added if the file has none. Everything else is copied byte for
+/// byte.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `orientation` is outside 1-8, the input is not a JPEG, or its
+/// headers or EXIF data are malformed.
+#[wasm_bindgen]
+pub fn set_jpeg_orientation(input: &[u8], orientation: u16) -> Result<Vec<u8>, JsError> {
+    jpeg::set_orientation(input, orientation)
+        .map_err(|e| JsError::new(&format!("Failed to set JPEG orientation: {e}")))
+}
+
+/// Remove the GPS fields from a JPEG's or PNG's EXIF data, keeping everything else
+/// (camera settings, orientation, dates), without re-encoding the image. Files
+/// without GPS fields are returned unchanged.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input is not a JPEG or PNG or its EXIF data can't be
+/// re-encoded.
+#[wasm_bindgen]
+pub fn strip_gps(input: &[u8]) -> Result<Vec<u8>, JsError> {
+    exif_write::strip_gps(input)
+        .map_err(|e| JsError::new(&format!("Failed to strip GPS data: {e}")))
+}
+
+/// Write EXIF fields into a JPEG or PNG (as an `eXIf` chunk) without re-encoding it,
+/// e.g. to stamp a copyright notice on exported images.
+///
+/// `fields` is `{ artist?, copyright?, date_time?, remove_gps? }`. Given fields replace
+/// the file's own, `date_time` (`"YYYY:MM:DD HH:MM:SS"` or ISO 8601) sets the
+/// modification date, and `remove_gps` drops every GPS field; everything else in the
+/// file's EXIF data, including the thumbnail, is kept. EXIF data is added if the file
+/// has none. To stamp images while converting them, use the `exif` option of
+/// `convert_image_with_options`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input is not a JPEG or PNG, a field is malformed, or the
+/// file's EXIF data can't be re-encoded.
+#[wasm_bindgen]
+pub fn set_exif(input: &[u8], fields: TsExifFields) -> Result<Vec<u8>, JsError> {
+    let fields: JsExifFields = serde_wasm_bindgen::from_value(fields.into())
+        .map_err(|e| JsError::new(&format!("Invalid EXIF fields: {e}")))?;
+    exif_write::set_exif(input, &fields.resolve()?)
+        .map_err(|e| JsError::new(&format!("Failed to write EXIF: {e}")))
+}
+
+/// Crop a JPEG by dropping whole blocks, without recompressing.
+///
+/// The top-left corner snaps up/left to the nearest MCU boundary (8 or 16 px) and the
+/// crop grows to keep the requested area covered. With `exact` set, an unaligned crop
+/// instead falls back to decode → crop → re-encode at `quality` (default 80).
+///
+/// Returns `{ jpeg: Uint8Array, region: { x, y, width, height }, lossless: boolean }`
+/// where `region` is the area actually cropped.
+///
+/// # Errors
+///
+/// Returns a `JsError` if quality is outside 1-100, the region is empty or out of
+/// bounds, or the input is not a baseline or extended sequential JPEG.
+#[wasm_bindgen]
+pub fn crop_jpeg_lossless(
+    input: &[u8],
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+    exact: bool,
+    quality: Option<u8>,
+) -> Result<JsValue, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let region = jpeg_lossless::CropRegion {
+        x,
+        y,
+        width,
+        height,
+    };
+    let result = jpeg_lossless::crop_jpeg(input, region, exact, quality)
+        .map_err(|e| JsError::new(&format!("Failed to crop JPEG: {e}")))?;
+
+    let region = serde_wasm_bindgen::to_value(&result.region)
+        .map_err(|e| JsError::new(&format!("Failed to serialize crop region: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let jpeg_array = js_sys::Uint8Array::from(result.data.as_slice());
+    js_sys::Reflect::set(&obj, &"jpeg".into(), &jpeg_array)
+        .map_err(|_| JsError::new("Failed to set jpeg property"))?;
+    js_sys::Reflect::set(&obj, &"region".into(), &region)
+        .map_err(|_| JsError::new("Failed to set region property"))?;
+    js_sys::Reflect::set(&obj, &"lossless".into(), &result.lossless.into())
+        .map_err(|_| JsError::new("Failed to set lossless property"))?;
+
+    Ok(obj.into())
+}
+
+/// Estimate the quality a JPEG was saved with from its quantization tables.
+///
+/// Returns `{ quality: number, exact: boolean }`. `quality` is on the usual 1-100
+/// (libjpeg) scale; `exact` is false when the encoder used custom tables and the
+/// value is only the nearest equivalent. Useful to avoid re-encoding a q60 source at
+/// q90, or to warn that an input is already heavily compressed.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input is not a JPEG or its headers are malformed.
+#[wasm_bindgen]
+pub fn estimate_jpeg_quality(input: &[u8]) -> Result<JsValue, JsError> {
+    let estimate = jpeg::estimate_quality(input)
+        .map_err(|e| JsError::new(&format!("Failed to estimate JPEG quality: {e}")))?;
+    serde_wasm_bindgen::to_value(&estimate)
+        .map_err(|e| JsError::new(&format!("Failed to serialize quality estimate: {e}")))
+}
+
+/// Losslessly shrink a JPEG by re-encoding its entropy coding with optimized Huffman
+/// tables. DCT coefficients are untouched, so the decoded pixels are identical.
+///
+/// With `progressive` (default `false`), the output is a progressive JPEG: a coarse
+/// version shows while it downloads, and it is usually a few percent smaller still.
+/// Returns the input unchanged if the optimized file would not be smaller.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input is not a baseline or extended sequential JPEG.
+#[wasm_bindgen]
+pub fn optimize_jpeg(input: &[u8], progressive: Option<bool>) -> Result<Vec<u8>, JsError> {
+    jpeg_lossless::optimize_jpeg(input, progressive.unwrap_or(false))
+        .map_err(|e| JsError::new(&format!("Failed to optimize JPEG: {e}")))
+}
+
+/// Read every comment (COM segment) in a JPEG, in file order.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input is not a JPEG or its headers are malformed.
+#[wasm_bindgen]
+pub fn get_jpeg_comments(input: &[u8]) -> Result<Vec<String>, JsError> {
+    jpeg::comments(input).map_err(|e| JsError::new(&format!("Failed to read JPEG comments: {e}")))
+}
+
+/// Replace a JPEG's comments with a single COM segment without re-encoding pixels.
+///
+/// Passing an empty string removes all comments. Everything except the COM segments
+/// is copied byte for byte.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input is not a JPEG, its headers are malformed, or the
+/// comment is longer than 65533 bytes.
+#[wasm_bindgen]
+pub fn set_jpeg_comment(input: &[u8], comment: &str) -> Result<Vec<u8>, JsError> {
+    let comment = (!comment.is_empty()).then_some(comment);
+    jpeg::set_comment(input, comment)
+        .map_err(|e| JsError::new(&format!("Failed to set JPEG comment: {e}")))
+}
+
+/// Losslessly shrink a PNG by trying color/bit-depth reductions and every row filter,
+/// keeping the smallest encoding. Decoded pixels are identical to the input.
+///
+/// `level` is 1 (fast), 2 (all filters) or 3 (all filters plus Zopfli, much slower).
+/// `strip` selects which ancillary chunks are kept: `"keep"` (all), `"safe"` (color
+/// management and physical size only; also used for an empty string) or `"strip"`
+/// (none). `tRNS` is always kept.
+///
+/// Returns `{ png: Uint8Array, report }`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the level or policy is invalid, or the input is not a
+/// decodable, non-animated PNG.
+#[wasm_bindgen]
+pub fn optimize_png(input: &[u8], level: u8, strip: &str) -> Result<JsValue, JsError> {
+    let policy =
+        png_optimize::ChunkPolicy::from_name(strip).map_err(|e| JsError::new(&format!("{e}")))?;
+    let result = png_optimize::optimize_png(input, level, policy)
+        .map_err(|e| JsError::new(&format!("Failed to optimize PNG: {e}")))?;
+
+    let report = serde_wasm_bindgen::to_value(&result.report)
+        .map_err(|e| JsError::new(&format!("Failed to serialize PNG report: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let png_array = js_sys::Uint8Array::from(result.data.as_slice());
+    js_sys::Reflect::set(&obj, &"png".into(), &png_array)
+        .map_err(|_| JsError::new("Failed to set png property"))?;
+    js_sys::Reflect::set(&obj, &"report".into(), &report)
+        .map_err(|_| JsError::new("Failed to set report property"))?;
+
+    Ok(obj.into())
+}
+
+/// Reduce an image to an indexed PNG with an optimized palette (pngquant-style).
+///
+/// `max_colors` is the palette size (2–256). `min_quality` (0–100) is the lowest
+/// acceptable palette quality; conversion fails rather than produce a worse result.
+/// `dither` is `"none"`, `"floyd_steinberg"` (default when empty) or `"ordered"` for
+/// an 8×8 Bayer pattern, which is faster and gives the fixed, regular texture of
+/// pixel art; images whose colors all fit are never dithered.
+///
+/// `quantizer` picks the palette builder: `"median_cut"` (default when empty), `"wu"`
+/// or `"neuquant"`, optionally followed by `:parameter` — k-means passes for median
+/// cut (default 3) and Wu (default 0), or the sampling factor 1–30 for NeuQuant
+/// (default 10), e.g. `"neuquant:1"` for its best quality. Alpha is preserved.
+///
+/// Returns `{ png: Uint8Array, report }`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the options are out of range, the input cannot be decoded,
+/// or the palette quality is below `min_quality`.
+#[wasm_bindgen]
+pub fn quantize_png(
+    input: &[u8],
+    max_colors: u16,
+    min_quality: u8,
+    dither: &str,
+    quantizer: &str,
+) -> Result<JsValue, JsError> {
+    let options = quantize_options(max_colors, min_quality, dither, quantizer)?;
+    let result = quantize::quantize_png(input, &options)
+        .map_err(|e| JsError::new(&format!("Failed to quantize PNG: {e}")))?;
+    quantized_png_object(&result)
+}
+
+/// Split a batch conversion into shards that separate WASM worker instances can convert
+/// at the same time, e.g. one per core from `navigator.hardwareConcurrency`.
+///
+/// `names` are the input file names, one per input. Returns `{ shards }`, at most
+/// `workers` of them, each `{ units, cost }` with units `{ index, name, cost }` in input
+/// order: `index` is the input's position, `name` its unique file name for
+/// `target_format`, and `cost` a relative estimate of the work, read from the headers
+/// only. Shards are balanced by cost. Post each shard to a worker, convert
+/// `inputs[unit.index]` there (e.g. with `convert_image_with_options`), and put each
+/// result at `outputs[unit.index]` for `merge_batch_zip`. The plan is plain data, so it
+/// survives `postMessage` and storage.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format is invalid, there are no inputs, `names`
+/// doesn't have one name per input, or `workers` is zero.
+#[wasm_bindgen]
+// wasm-bindgen can't take `&[String]`.
+#[allow(clippy::needless_pass_by_value)]
+pub fn plan_batch(
+    inputs: Vec<js_sys::Uint8Array>,
+    names: Vec<String>,
+    target_format: &str,
+    workers: u32,
+) -> Result<TsBatchPlan, JsError> {
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let inputs: Vec<Vec<u8>> = inputs.into_iter().map(|input| input.to_vec()).collect();
+    let plan = batch::plan_batch(&inputs, &names, target, workers)
+        .map_err(|e| JsError::new(&format!("Failed to plan batch: {e}")))?;
+    serde_wasm_bindgen::to_value(&plan)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize batch plan: {e}")))
+}
+
+/// Prepare a gallery for a client in one call: every input is turned upright, shrunk
+/// to fit `max_edge` x `max_edge` (never enlarged), watermarked with `logo` tiled
+/// across it, and saved as a JPEG (quality 85), all stored in one ZIP archive. Large
+/// TIFFs and JPEGs are decoded at reduced resolution, as `decode_scaled` does.
+///
+/// Proofs keep only the EXIF artist and copyright, so GPS locations and other
+/// metadata never leave. `names` (optional, one per input) name the entries, with a
+/// `.jpg` extension; without them they are `proof-001.jpg`, `proof-002.jpg`, and so on.
+///
+/// # Errors
+///
+/// Returns a `JsError` if there are no inputs, `names` doesn't have one name per
+/// input, the logo or an input (named by its index) cannot be decoded, `max_edge` is
+/// zero, or the archive would exceed 65535 files or 4 GiB.
+#[wasm_bindgen]
+// wasm-bindgen can't take `&[String]`.
+#[allow(clippy::needless_pass_by_value)]
+pub fn client_proof_batch(
+    inputs: Vec<js_sys::Uint8Array>,
+    logo: &[u8],
+    max_edge: u32,
+    names: Option<Vec<String>>,
+) -> Result<Vec<u8>, JsError> {
+    let inputs: Vec<Vec<u8>> = inputs.into_iter().map(|input| input.to_vec()).collect();
+    proof::client_proof_batch(&inputs, names.as_deref(), logo, max_edge)
+        .map_err(|e| JsError::new(&format!("Failed to make proofs: {e}")))
+}
+
+/// Merge the converted files of a `plan_batch` plan into one ZIP archive.
+///
+/// `outputs[i]` is the converted file of the unit with `index` `i`, stored under the
+/// unit's `name`. Entries are uncompressed (the images already are) and in input order
+/// with a fixed timestamp, so identical outputs give an identical archive.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the plan is malformed, `outputs` doesn't have one file per
+/// unit, or the archive would exceed 65535 files or 4 GiB.
+#[wasm_bindgen]
+pub fn merge_batch_zip(
+    plan: TsBatchPlan,
+    outputs: Vec<js_sys::Uint8Array>,
+) -> Result<Vec<u8>, JsError> {
+    let plan: batch::BatchPlan = serde_wasm_bindgen::from_value(plan.into())
+        .map_err(|e| JsError::new(&format!("Invalid batch plan: {e}")))?;
+    let outputs: Vec<Vec<u8>> = outputs.into_iter().map(|output| output.to_vec()).collect();
+    batch::merge_zip(&plan, &outputs)
+        .map_err(|e| JsError::new(&format!("Failed to merge batch: {e}")))
+}
+
+/// Reduce a batch of related images to indexed PNGs that share one palette.
+///
+/// The palette is built from the colors of all `inputs` together, so a color comes out
+/// identical in every file. Options are as for `quantize_png`; `min_quality` applies
+/// to the batch as a whole, and each report's `quality` is for that image.
+///
+/// Returns an array of `{ png: Uint8Array, report }`, in input order.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `inputs` is empty, the options are out of range, an input
+/// cannot be decoded, or the shared palette quality is below `min_quality`.
+#[wasm_bindgen]
+pub fn quantize_png_batch(
+    inputs: Vec<js_sys::Uint8Array>,
+    max_colors: u16,
+    min_quality: u8,
+    dither: &str,
+    quantizer: &str,
+) -> Result<js_sys::Array, JsError> {
+    let options = quantize_options(max_colors, min_quality, dither, quantizer)?;
+    let inputs: Vec<Vec<u8>> = inputs.into_iter().map(|input| input.to_vec()).collect();
+    let results = quantize::quantize_png_batch(&inputs, &options)
+        .map_err(|e| JsError::new(&format!("Failed to quantize PNG batch: {e}")))?;
+
+    let array = js_sys::Array::new();
+    for result in &results {
+        array.push(&quantized_png_object(result)?);
+    }
+    Ok(array)
+}
+
+/// Build a looping animated GIF from a list of encoded still images, with one palette
+/// shared by every frame so colors don't flicker.
+///
+/// `frames` and `delays_ms` are as for `encode_apng_from_frames`. `dither` and
+/// `quantizer` are as for `quantize_png`; the palette has up to 256 colors, one of which
+/// is reserved for transparency when a frame has fully transparent pixels.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the lists are empty or differ in length, a frame cannot be
+/// decoded, frame sizes differ, an option is invalid, or the GIF cannot be encoded.
+#[wasm_bindgen]
+pub fn encode_gif_from_frames(
+    frames: Vec<js_sys::Uint8Array>,
+    delays_ms: &[u32],
+    dither: &str,
+    quantizer: &str,
+) -> Result<Vec<u8>, JsError> {
+    let options = quantize_options(quantize::MAX_COLORS, 0, dither, quantizer)?;
+    let images: Vec<Vec<u8>> = frames.into_iter().map(|frame| frame.to_vec()).collect();
+    let frames = animation::frames_from_images(&images, delays_ms)
+        .map_err(|e| JsError::new(&format!("Invalid frames: {e}")))?;
+    quantize::quantize_gif_frames(&frames, &options)
+        .map_err(|e| JsError::new(&format!("Failed to encode GIF: {e}")))
+}
+
+/// Parses the palette options shared by the quantizing exports.
+fn quantize_options(
+    max_colors: u16,
+    min_quality: u8,
+    dither: &str,
+    quantizer: &str,
+) -> Result<quantize::QuantizeOptions, JsError> {
+    let dither = quantize::Dither::from_name(dither)
+        .map_err(|e| JsError::new(&format!("Invalid dithering mode: {e}")))?;
+    let quantizer = quantize::Quantizer::from_name(quantizer)
+        .map_err(|e| JsError::new(&format!("Invalid quantizer: {e}")))?;
+    Ok(quantize::QuantizeOptions {
+        max_colors,
+        min_quality,
+        dither,
+        quantizer,
+    })
+}
+
+/// Wraps a quantized PNG as `{ png: Uint8Array, report }`.
+fn quantized_png_object(result: &quantize::QuantizedPng) -> Result<JsValue, JsError> {
+    let report = serde_wasm_bindgen::to_value(&result.report)
+        .map_err(|e| JsError::new(&format!("Failed to serialize quantize report: {e}")))?;
+
+    let obj = js_sys::Object::new();
+    let png_array = js_sys::Uint8Array::from(result.data.as_slice());
+    js_sys::Reflect::set(&obj, &"png".into(), &png_array)
+        .map_err(|_| JsError::new("Failed to set png property"))?;
+    js_sys::Reflect::set(&obj, &"report".into(), &report)
+        .map_err(|_| JsError::new("Failed to set report property"))?;
+
+    Ok(obj.into())
+}
+
+/// Extract one channel of an image as a grayscale image, e.g. alpha as a mask.
+///
+/// `channel` is `"red"`, `"green"`, `"blue"` or `"alpha"` (or `r`/`g`/`b`/`a`).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the channel, target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn extract_channel(
+    input: &[u8],
+    channel: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let channel = channels::Channel::from_name(channel)
+        .map_err(|e| JsError::new(&format!("Invalid channel: {e}")))?;
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    channels::extract_channel(input, channel, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to extract channel: {e}")))
+}
+
+/// Rearrange the channels of an image.
+///
+/// `order` has one character per output channel (red, green, blue, alpha), each naming
+/// the source channel (`r`, `g`, `b`, `a`) or a constant (`0`, `1` for full): `"bgra"`
+/// swaps red and blue, `"rgb1"` drops alpha.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the order, target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn swap_channels(
+    input: &[u8],
+    order: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let order = channels::parse_order(order)
+        .map_err(|e| JsError::new(&format!("Invalid channel order: {e}")))?;
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    channels::swap_channels(input, order, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to swap channels: {e}")))
+}
+
+/// Merge grayscale images into the red, green, blue and alpha channels of one image.
+///
+/// Each input is converted to grayscale; all given inputs must have the same size.
+/// Missing color channels are filled with 0 and a missing alpha channel with 255.
+///
+/// # Errors
+///
+/// Returns a `JsError` if no channel is given, the channels differ in size, the target
+/// format or quality is invalid, or if decoding or encoding fails.
+#[wasm_bindgen]
+pub fn merge_channels(
+    red: Option<Vec<u8>>,
+    green: Option<Vec<u8>>,
+    blue: Option<Vec<u8>>,
+    alpha: Option<Vec<u8>>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let inputs = [red, green, blue, alpha];
+    channels::merge_channels(inputs.each_ref().map(Option::as_deref), target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to merge channels: {e}")))
+}
+
+/// Apply a duotone: map each pixel's luminance onto a ramp from `shadow` to
+/// `highlight`.
+///
+/// Colors are hex strings (`#rgb`, `#rrggbb`, or with alpha `#rrggbbaa`).
+///
+/// # Errors
+///
+/// Returns a `JsError` if a color, the target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn duotone(
+    input: &[u8],
+    shadow: &str,
+    highlight: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let shadow = color::parse_color(shadow)
+        .map_err(|e| JsError::new(&format!("Invalid shadow color: {e}")))?;
+    let highlight = color::parse_color(highlight)
+        .map_err(|e| JsError::new(&format!("Invalid highlight color: {e}")))?;
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let map = effects::GradientMap::duotone(shadow, highlight);
+    effects::apply_gradient_map(input, &map, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to apply duotone: {e}")))
+}
+
+/// Apply a gradient map: map each pixel's luminance onto a ramp of color stops.
+///
+/// `stops` is a comma-separated list of hex colors, each optionally followed by a
+/// position as a fraction or percentage, e.g. `"#1a0033, #ff0080 40%, #ffe600"`.
+/// Without positions the stops are spaced evenly.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the stops, target format or quality are invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn gradient_map(
+    input: &[u8],
+    stops: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let map = effects::parse_stops(stops)
+        .and_then(|stops| effects::GradientMap::new(&stops))
+        .map_err(|e| JsError::new(&format!("Invalid gradient: {e}")))?;
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    effects::apply_gradient_map(input, &map, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to apply gradient map: {e}")))
+}
+
+/// Turn a solid background color transparent, e.g. a white or green backdrop behind a
+/// product photo.
+///
+/// `color` is a hex string (`#rgb` or `#rrggbb`). `tolerance` and `softness` are
+/// percentages (0-100) of the largest RGB distance: pixels within `tolerance` of the
+/// color are removed, and the next `softness` fades back to opaque to feather edges.
+/// `target_format` must be `"png"` or `"webp"` (lossless).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the color, a percentage or the target format is invalid, or
+/// if decoding or encoding fails.
+#[wasm_bindgen]
+pub fn chroma_key(
+    input: &[u8],
+    color: &str,
+    tolerance: f64,
+    softness: f64,
+    target_format: &str,
+) -> Result<Vec<u8>, JsError> {
+    let color =
+        color::parse_color(color).map_err(|e| JsError::new(&format!("Invalid key color: {e}")))?;
+
+    let key = effects::ChromaKey::new(color, tolerance, softness)
+        .map_err(|e| JsError::new(&format!("Invalid chroma key: {e}")))?;
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    effects::chroma_key(input, &key, target)
+        .map_err(|e| JsError::new(&format!("Failed to apply chroma key: {e}")))
+}
+
+/// Replace one color with another, e.g. to make color variants of a product photo.
+///
+/// Colors are hex strings (`#rgb` or `#rrggbb`). Pixels within `tolerance` (a
+/// percentage, 0-100, of the largest RGB distance) of `from` are recolored to `to`.
+/// With `preserve_luminance`, each pixel keeps how much lighter or darker it was than
+/// `from`, so shading carries over.
+///
+/// # Errors
+///
+/// Returns a `JsError` if a color, the tolerance, target format or quality is invalid,
+/// or if decoding or encoding fails.
+#[wasm_bindgen]
+pub fn replace_color(
+    input: &[u8],
+    from: &str,
+    to: &str,
+    tolerance: f64,
+    preserve_luminance: bool,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let from = color::parse_color(from)
+        .map_err(|e| JsError::new(&format!("Invalid source color: {e}")))?;
+    let to = color::parse_color(to)
+        .map_err(|e| JsError::new(&format!("Invalid replacement color: {e}")))?;
+
+    let replace = effects::ColorReplace::new(from, to, tolerance, preserve_luminance)
+        .map_err(|e| JsError::new(&format!("Invalid color replacement: {e}")))?;
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    effects::replace_color(input, &replace, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to replace color: {e}")))
+}
+
+/// Simulate how an image looks with a form of color blindness, e.g. to check that a
+/// chart's colors stay distinguishable.
+///
+/// `vision` is `"protanopia"` (no red cones), `"deuteranopia"` (no green cones, the
+/// most common) or `"tritanopia"` (no blue cones). Alpha is kept.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `vision`, the target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn simulate_color_vision(
+    input: &[u8],
+    vision: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let vision = effects::ColorVision::from_name(vision)
+        .map_err(|e| JsError::new(&format!("Invalid color vision: {e}")))?;
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    effects::simulate_color_vision(input, vision, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to simulate color vision: {e}")))
+}
+
+/// Add film grain: seeded noise for a filmic look, or to mask the banding heavy
+/// quantization leaves in gradients (add grain before reducing colors).
+///
+/// `intensity` is a percentage (0-100); `size` is the grain size in pixels (1-16,
+/// default 1). `monochrome` (default true) moves the color channels together; pass
+/// `false` for colored chroma noise. The same `seed` (default 0) always gives the same
+/// grain. Alpha is kept.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `intensity`, `size`, the target format or quality is
+/// invalid, or if decoding or encoding fails.
+#[wasm_bindgen]
+pub fn add_grain(
+    input: &[u8],
+    intensity: f64,
+    size: Option<u32>,
+    monochrome: Option<bool>,
+    seed: Option<u32>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let grain = effects::Grain::new(
+        intensity,
+        size.unwrap_or(1),
+        monochrome.unwrap_or(true),
+        u64::from(seed.unwrap_or(0)),
+    )
+    .map_err(|e| JsError::new(&format!("Invalid grain: {e}")))?;
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    effects::add_grain(input, &grain, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to add grain: {e}")))
+}
+
+/// Options for `tilt_shift`, deserialized from a JS object. Every field is optional.
+#[derive(Debug, Default, serde::Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct JsTiltShiftOptions {
+    shape: Option<String>,
+    center_x: Option<f64>,
+    center_y: Option<f64>,
+    band: Option<f64>,
+    transition: Option<f64>,
+    radius: Option<f64>,
+}
+
+/// Apply a tilt-shift blur: a sharp focus area with blur fading in around it, which
+/// makes full-size scenes look like miniatures.
+///
+/// `options` is `{ shape?, center_x?, center_y?, band?, transition?, radius? }` (or
+/// `undefined`):
+/// - `shape`: `"linear"` (default), a horizontal band, or `"radial"`, a circle
+/// - `center_x`, `center_y`: the center of the focus area as percentages of the width
+///   and height (default 50); a linear band only uses `center_y`
+/// - `band`: the size of the sharp area, as a percentage of the height for a band or of
+///   the shorter side for the circle's diameter (default 30)
+/// - `transition`: how far the blur takes to reach full strength, in the same units
+///   (default 25)
+/// - `radius`: the full blur's radius in pixels, up to 100 (default 8)
+///
+/// # Errors
+///
+/// Returns a `JsError` if an option, the target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn tilt_shift(
+    input: &[u8],
+    options: Option<TsTiltShiftOptions>,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let js_options: JsTiltShiftOptions = match options.map(JsValue::from) {
+        Some(options) if !options.is_null() => serde_wasm_bindgen::from_value(options)
+            .map_err(|e| JsError::new(&format!("Invalid options: {e}")))?,
+        _ => JsTiltShiftOptions::default(),
+    };
+    let shape = match js_options.shape.as_deref() {
+        Some(name) => effects::FocusShape::from_name(name)
+            .map_err(|e| JsError::new(&format!("Invalid focus shape: {e}")))?,
+        None => effects::FocusShape::default(),
+    };
+    let tilt_shift = effects::TiltShift::new(
+        shape,
+        [
+            js_options.center_x.unwrap_or(50.0),
+            js_options.center_y.unwrap_or(50.0),
+        ],
+        js_options.band.unwrap_or(30.0),
+        js_options.transition.unwrap_or(25.0),
+        js_options.radius.unwrap_or(8.0),
+    )
+    .map_err(|e| JsError::new(&format!("Invalid tilt-shift: {e}")))?;
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    effects::tilt_shift(input, &tilt_shift, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to apply tilt-shift: {e}")))
+}
+
+/// Adjust exposure by `stops` EV (-10 to 10), applied in linear light so +1 doubles
+/// the light in the scene rather than the encoded pixel values.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `stops`, the target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn adjust_exposure(
+    input: &[u8],
+    stops: f64,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    let adjustment = adjust::Adjustment::exposure(stops)
+        .map_err(|e| JsError::new(&format!("Invalid exposure: {e}")))?;
+    adjust_image(input, adjustment, target_format, quality)
+}
+
+/// Adjust vibrance by `amount` percent (-100 to 100): a saturation change weighted
+/// toward muted colors, so already-vivid colors don't clip.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `amount`, the target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn adjust_vibrance(
+    input: &[u8],
+    amount: f64,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    let adjustment = adjust::Adjustment::vibrance(amount)
+        .map_err(|e| JsError::new(&format!("Invalid vibrance: {e}")))?;
+    adjust_image(input, adjustment, target_format, quality)
+}
+
+/// Balance colors automatically so the brightest areas (paper, a white wall) come out
+/// neutral, e.g. to remove the warm cast of indoor lighting from a document photo.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or if decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn auto_white_balance(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    adjust_image(
+        input,
+        adjust::Adjustment::WhiteBalance,
+        target_format,
+        quality,
+    )
+}
+
+/// Stretch contrast automatically so the darkest pixels become black and the brightest
+/// white (ignoring the extreme 0.5% at each end), keeping hues unchanged.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or if decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn auto_contrast(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    adjust_image(
+        input,
+        adjust::Adjustment::ContrastStretch,
+        target_format,
+        quality,
+    )
+}
+
+/// Turn a scanned or camera-photographed color negative into a positive.
+///
+/// The film base, including the orange mask of color negative film, is measured from
+/// the brightest pixels and divided out; the result is inverted to density and each
+/// channel is leveled separately (ignoring the extreme 0.5% at each end), which
+/// balances the dyes' differing contrast. Crop away the unexposed film borders and
+/// sprocket holes first for the best color.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or if decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn invert_negative(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    adjust_image(
+        input,
+        adjust::Adjustment::InvertNegative,
+        target_format,
+        quality,
+    )
+}
+
+/// Enhance a photo automatically with conservative settings: white balance from the
+/// brightest areas (each channel scaled by at most 25%), a contrast stretch ignoring
+/// the extreme 0.5% at each end, a 15% vibrance boost and light sharpening.
+///
+/// Returns `{ data: Uint8Array, enhancement }`, where `enhancement` is
+/// `{ white_balance, black_point, white_point, vibrance, sharpen }`: the red, green
+/// and blue gains, the levels mapped to black and white, the vibrance percentage and
+/// the unsharp mask strength. Pass it, edited, to `enhance` to adjust the result.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, or if decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn auto_enhance(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<TsEnhancedImage, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let (data, enhancement) = adjust::auto_enhance(input, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to enhance image: {e}")))?;
+
+    let enhancement = serde_wasm_bindgen::to_value(&enhancement)
+        .map_err(|e| JsError::new(&format!("Failed to serialize enhancement: {e}")))?;
+    let obj = js_sys::Object::new();
+    let data = js_sys::Uint8Array::from(data.as_slice());
+    js_sys::Reflect::set(&obj, &"data".into(), &data)
+        .map_err(|_| JsError::new("Failed to set data property"))?;
+    js_sys::Reflect::set(&obj, &"enhancement".into(), &enhancement)
+        .map_err(|_| JsError::new("Failed to set enhancement property"))?;
+
+    Ok(obj.unchecked_into())
+}
+
+/// Apply enhancement settings, typically those `auto_enhance` returned after tweaking
+/// them: `{ white_balance, black_point, white_point, vibrance, sharpen }`.
+///
+/// `white_balance` holds red, green and blue gains (above 0, at most 4);
+/// `black_point` and `white_point` are the levels (0-255) mapped to black and white;
+/// `vibrance` is a percentage (-100 to 100); `sharpen` is the unsharp mask strength
+/// (0 to 3).
+///
+/// # Errors
+///
+/// Returns a `JsError` if a setting is missing or out of range, the target format or
+/// quality is invalid, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn enhance(
+    input: &[u8],
+    enhancement: TsEnhancement,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let enhancement: adjust::Enhancement = serde_wasm_bindgen::from_value(enhancement.into())
+        .map_err(|e| JsError::new(&format!("Invalid enhancement: {e}")))?;
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    adjust::enhance(input, &enhancement, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to enhance image: {e}")))
+}
+
+/// Remap each color channel of `source` so its histogram matches `reference`'s, e.g.
+/// to make a batch of product photos look consistent with one approved shot.
+///
+/// Only visible pixels are counted, and alpha is kept. Matching works best when the
+/// two images show similar content; a reference with very different subject matter
+/// will shift colors accordingly.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format or quality is invalid, the reference has
+/// no visible pixels, or decoding or encoding fails.
+#[wasm_bindgen]
+pub fn match_histogram(
+    source: &[u8],
+    reference: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    adjust::match_histogram(source, reference, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to match histogram: {e}")))
+}
+
+fn adjust_image(
+    input: &[u8],
+    adjustment: adjust::Adjustment,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    adjust::adjust(input, &[adjustment], target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to adjust image: {e}")))
+}
+
+/// Analyze which colors an image uses.
+///
+/// Returns an object with `unique_colors` (counted up to 65536, with `colors_capped`
+/// set when the cap is hit), `grayscale`, `opaque`, `needs_16bit`, `palette_size`
+/// (entries an indexed PNG would need, or `null`), and the smallest lossless PNG
+/// layout: `color_type` (`"gray"`, `"gray_alpha"`, `"indexed"`, `"rgb"` or `"rgba"`),
+/// `bit_depth` and `bits_per_pixel`. Useful for choosing between PNG8 and PNG32.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded.
+#[wasm_bindgen]
+pub fn color_stats(input: &[u8]) -> Result<JsValue, JsError> {
+    let stats = stats::color_stats(input)
+        .map_err(|e| JsError::new(&format!("Failed to analyze colors: {e}")))?;
+    serde_wasm_bindgen::to_value(&stats)
+        .map_err(|e| JsError::new(&format!("Failed to serialize color stats: {e}")))
+}
+
+/// Score how sharp an image is, e.g. to warn about a blurry photo before a document
+/// or ID capture is submitted.
+///
+/// Returns the variance of the Laplacian of the image's luma: higher is sharper, and
+/// flat or heavily blurred images score near 0. The score depends on resolution and
+/// content, so tune the rejection threshold for the capture flow (around 100 is a
+/// common starting point).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded.
+#[wasm_bindgen]
+pub fn sharpness_score(input: &[u8]) -> Result<f64, JsError> {
+    stats::sharpness_score(input)
+        .map_err(|e| JsError::new(&format!("Failed to score sharpness: {e}")))
+}
+
+/// Report how well exposed an image is, so capture UIs can prompt "photo too dark"
+/// or "too bright" without decoding in JS.
+///
+/// Returns `{ mean_luminance, clipped_highlights, crushed_shadows }`: the average luma
+/// (0–255) and the percentages of pixels at or above 250 and at or below 5. Fully
+/// transparent pixels are ignored.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded.
+#[wasm_bindgen]
+pub fn exposure_score(input: &[u8]) -> Result<TsExposureStats, JsError> {
+    let stats = stats::exposure_score(input)
+        .map_err(|e| JsError::new(&format!("Failed to score exposure: {e}")))?;
+    serde_wasm_bindgen::to_value(&stats)
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize exposure stats: {e}")))
+}
+
+/// Constrain an image to a fixed palette, e.g. for pixel art or e-ink displays.
+///
+/// `palette` is a comma-separated list of 1 to 256 hex colors (`"#000, #fff, #f00"`;
+/// `#rrggbbaa` adds alpha). `dither` is `"none"`, `"floyd_steinberg"` (default) or
+/// `"ordered"` (8×8 Bayer). PNG output is an indexed PNG; other formats get the
+/// remapped pixels.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the palette, dithering mode, target format or quality is
+/// invalid, or if decoding or encoding fails.
+#[wasm_bindgen]
+pub fn apply_palette(
+    input: &[u8],
+    palette: &str,
+    dither: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let colors = color::parse_palette(palette)
+        .map_err(|e| JsError::new(&format!("Invalid palette: {e}")))?;
+
+    let dither = quantize::Dither::from_name(dither)
+        .map_err(|e| JsError::new(&format!("Invalid dithering mode: {e}")))?;
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    palette::apply_palette(input, &colors, dither, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to apply palette: {e}")))
+}
+
+/// Reduce an image to 1-bit black and white for thermal printers and e-paper displays.
+///
+/// Transparent areas become white (paper). `dither` is `"none"` for a hard threshold,
+/// `"floyd_steinberg"` (default) or `"ordered"` (8×8 Bayer); `threshold` is the gray
+/// level (0–255) at or above which pixels turn white, 128 by default. `format` is
+/// `"png"` (default, a 1-bit grayscale PNG) or `"pbm"` (binary `P4` bitmap).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the dithering mode or format is invalid, or if decoding or
+/// encoding fails.
+#[wasm_bindgen]
+pub fn to_bilevel(
+    input: &[u8],
+    dither: &str,
+    threshold: Option<u8>,
+    format: &str,
+) -> Result<Vec<u8>, JsError> {
+    let dither = quantize::Dither::from_name(dither)
+        .map_err(|e| JsError::new(&format!("Invalid dithering mode: {e}")))?;
+
+    let format = bilevel::BilevelFormat::from_name(format)
+        .map_err(|e| JsError::new(&format!("Invalid 1-bit format: {e}")))?;
+
+    let options = bilevel::BilevelOptions {
+        dither,
+        threshold: threshold.unwrap_or(bilevel::DEFAULT_THRESHOLD),
+        format,
+    };
+    bilevel::to_bilevel(input, &options)
+        .map_err(|e| JsError::new(&format!("Failed to create 1-bit image: {e}")))
+}
+
+/// Render an image as text for terminal previews.
+///
+/// The image is downsampled to `columns` characters wide (80 by default), with rows
+/// halved to allow for tall terminal cells, and each cell's luminance picks a
+/// character from `charset`, ordered darkest to brightest (`" .:-=+*#%@"` when
+/// empty). With `ansi`, each character is colored with 24-bit ANSI escapes.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the options are invalid or the input cannot be decoded.
+#[wasm_bindgen]
+pub fn to_ascii(
+    input: &[u8],
+    columns: Option<u32>,
+    charset: &str,
+    ansi: bool,
+) -> Result<String, JsError> {
+    let options = ascii::AsciiOptions {
+        columns: columns.unwrap_or(ascii::AsciiOptions::default().columns),
+        charset: charset.to_owned(),
+        ansi,
+    };
+    ascii::to_ascii(input, &options)
+        .map_err(|e| JsError::new(&format!("Failed to render text preview: {e}")))
+}
+
+/// Upscale pixel art by a whole-number `factor` without blending colors.
+///
+/// `filter` is `"nearest"` (default; solid blocks, factors 1–8) or `"scalex"`
+/// (Scale2x/Scale3x edge smoothing that only reuses existing colors; factors 2, 3, 4,
+/// 6 or 8).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the factor, filter, target format or quality is invalid, or
+/// if decoding or encoding fails.
+#[wasm_bindgen]
+pub fn upscale_pixel_art(
+    input: &[u8],
+    factor: u32,
+    filter: &str,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    let filter = scale::PixelArtFilter::from_name(filter)
+        .map_err(|e| JsError::new(&format!("Invalid pixel-art filter: {e}")))?;
+
+    scale::upscale(input, factor, filter, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to upscale image: {e}")))
+}
+
+/// Shrink artwork into a small icon whose longer side is `size` pixels.
+///
+/// Uses exact area averaging in linear light followed by a light sharpen, which keeps
+/// 16–64 px icons crisper than general-purpose resampling.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `size`, the target format or quality is invalid, or if
+/// decoding or encoding fails.
+#[wasm_bindgen]
+pub fn downscale_icon(
+    input: &[u8],
+    size: u32,
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+
+    scale::to_icon(input, size, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to create icon: {e}")))
+}
+
+/// A converter session that keeps work between calls, so generating several variants
+/// of one image decodes it once.
+///
+/// Decoded images are keyed by their bytes: passing the same `Uint8Array` contents
+/// again reuses the decoded pixels. The least recently used images are dropped once
+/// they exceed the session's limit (`max_image_bytes`, 256 MiB by default). Gradient
+/// maps and palettes are kept by their text until cleared, and pixel buffers are reused
+/// between operations. Call `free()` when done with the session to release everything.
+#[wasm_bindgen]
+pub struct ConverterSession(session::Session);
+
+#[wasm_bindgen]
+impl ConverterSession {
+    /// Start a session keeping up to `max_image_bytes` of decoded pixels (default
+    /// 256 MiB); 0 keeps no images.
+    #[wasm_bindgen(constructor)]
+    pub fn new(max_image_bytes: Option<u32>) -> Self {
+        Self(max_image_bytes.map_or_else(session::Session::new, |bytes| {
+            session::Session::with_max_image_bytes(u64::from(bytes))
+        }))
+    }
+
+    /// Encode an image as `target_format`, like `convert_image` without transforms.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `JsError` if the target format or quality is invalid, or decoding or
+    /// encoding fails.
+    pub fn encode(
+        &mut self,
+        input: &[u8],
+        target_format: &str,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, JsError> {
+        if let Some(q) = quality {
+            if q == 0 || q > 100 {
+                return Err(JsError::new("Quality must be between 1 and 100"));
+            }
+        }
+        let target = ImageFormat::from_name(target_format)
+            .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+        self.0
+            .encode(input, target, quality)
+            .map_err(|e| JsError::new(&format!("Failed to encode image: {e}")))
+    }
+
+    /// Resize an image; arguments are as for `resize`.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `JsError` if the mode, size, fill, target format or quality is
+    /// invalid, or decoding or encoding fails.
+    // Same arguments as the `resize` export.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resize(
+        &mut self,
+        input: &[u8],
+        mode: &str,
+        width: u32,
+        height: Option<u32>,
+        no_upscale: Option<bool>,
+        progressive: Option<bool>,
+        target_format: &str,
+        quality: Option<u8>,
+        fill: Option<String>,
+    ) -> Result<Vec<u8>, JsError> {
+        if let Some(q) = quality {
+            if q == 0 || q > 100 {
+                return Err(JsError::new("Quality must be between 1 and 100"));
+            }
+        }
+        let mode = resize::ResizeMode::from_name(mode)
+            .map_err(|e| JsError::new(&format!("Invalid resize mode: {e}")))?;
+        let target = ImageFormat::from_name(target_format)
+            .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+        let defaults = resize::ResizeOptions::default();
+        let options = resize::ResizeOptions {
+            no_upscale: no_upscale.unwrap_or(defaults.no_upscale),
+            progressive: progressive.unwrap_or(defaults.progressive),
+            fill: pad_fill(fill)?,
+        };
+        self.0
+            .resize(
+                input,
+                mode,
+                width,
+                height.unwrap_or(width),
+                options,
+                target,
+                quality,
+            )
+            .map_err(|e| JsError::new(&format!("Failed to resize image: {e}")))
+    }
+
+    /// Apply a gradient map; arguments are as for `gradient_map`. The lookup table
+    /// built from `stops` is kept for the next call with the same stops.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `JsError` if the stops, target format or quality are invalid, or if
+    /// decoding or encoding fails.
+    pub fn gradient_map(
+        &mut self,
+        input: &[u8],
+        stops: &str,
+        target_format: &str,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, JsError> {
+        if let Some(q) = quality {
+            if q == 0 || q > 100 {
+                return Err(JsError::new("Quality must be between 1 and 100"));
+            }
+        }
+        let target = ImageFormat::from_name(target_format)
+            .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+        self.0
+            .gradient_map(input, stops, target, quality)
+            .map_err(|e| JsError::new(&format!("Failed to apply gradient map: {e}")))
+    }
+
+    /// Constrain an image to a fixed palette; arguments are as for `apply_palette`. The
+    /// parsed palette is kept for the next call with the same palette.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `JsError` if the palette, dithering mode, target format or quality is
+    /// invalid, or if decoding or encoding fails.
+    pub fn apply_palette(
+        &mut self,
+        input: &[u8],
+        palette: &str,
+        dither: &str,
+        target_format: &str,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, JsError> {
+        if let Some(q) = quality {
+            if q == 0 || q > 100 {
+                return Err(JsError::new("Quality must be between 1 and 100"));
+            }
+        }
+        let dither = quantize::Dither::from_name(dither)
+            .map_err(|e| JsError::new(&format!("Invalid dithering mode: {e}")))?;
+        let target = ImageFormat::from_name(target_format)
+            .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+        self.0
+            .apply_palette(input, palette, dither, target, quality)
+            .map_err(|e| JsError::new(&format!("Failed to apply palette: {e}")))
+    }
+
+    /// Drop the decoded copy of `input`. Returns whether the session held one.
+    pub fn evict_image(&mut self, input: &[u8]) -> bool {
+        self.0.evict_image(input)
+    }
+
+    /// Drop every decoded image.
+    pub fn clear_images(&mut self) {
+        self.0.clear_images();
+    }
+
+    /// Drop every gradient map lookup table.
+    pub fn clear_luts(&mut self) {
+        self.0.clear_luts();
+    }
+
+    /// Drop every parsed palette.
+    pub fn clear_palettes(&mut self) {
+        self.0.clear_palettes();
+    }
+
+    /// Free the pooled pixel buffers.
+    pub fn clear_buffers(&mut self) {
+        self.0.clear_buffers();
+    }
+
+    /// Drop everything the session holds. The image limit stays.
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Change the limit on decoded images, dropping the least recently used ones until
+    /// the rest fit.
+    pub fn set_max_image_bytes(&mut self, max_image_bytes: u32) {
+        self.0.set_max_image_bytes(u64::from(max_image_bytes));
+    }
+
+    /// What the session holds: `{ images, image_bytes, max_image_bytes, luts, palettes,
+    /// pooled_buffers, pooled_bytes }`, with sizes in bytes.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `JsError` if the stats cannot be serialized.
+    pub fn stats(&self) -> Result<TsSessionStats, JsError> {
+        serde_wasm_bindgen::to_value(&self.0.stats())
+            .map(JsCast::unchecked_into)
+            .map_err(|e| JsError::new(&format!("Failed to serialize session stats: {e}")))
+    }
+}
+
+/// Hash an image's decoded pixels rather than its file bytes.
+///
+/// Returns 64 lowercase hex digits (SHA-256 of the dimensions and RGBA8 pixels). The
+/// same picture saved losslessly in different formats, or with different metadata,
+/// hashes identically, which makes it usable for deduplication.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input cannot be decoded.
+#[wasm_bindgen]
+pub fn pixel_hash(input: &[u8]) -> Result<String, JsError> {
+    hash::pixel_hash(input).map_err(|e| JsError::new(&format!("Failed to hash pixels: {e}")))
+}
+
+/// Build a stable cache key for a derived image, for CDN or object-store keys.
+///
+/// `input_hash` identifies the source (e.g. from `pixel_hash`); `ops_json` is a JSON
+/// description of the operations applied, in order. Object key order and whitespace in
+/// `ops_json` don't affect the key, and the crate version is included so upgrades
+/// produce fresh keys. Returns 64 lowercase hex digits.
+///
+/// # Errors
+///
+/// Returns a `JsError` if `input_hash` is empty or `ops_json` is not valid JSON.
+#[wasm_bindgen]
+pub fn derivation_key(input_hash: &str, ops_json: &str) -> Result<String, JsError> {
+    hash::derivation_key(input_hash, ops_json)
+        .map_err(|e| JsError::new(&format!("Failed to build derivation key: {e}")))
+}
+
+/// Forwards instrumentation events to a JavaScript callback.
+struct JsEventSink(js_sys::Function);
+
+impl events::EventSink for JsEventSink {
+    fn event(&self, event: &events::Event) {
+        if let Ok(value) = serde_wasm_bindgen::to_value(event) {
+            // A throwing listener must not break the conversion it is observing.
+            let _ = self.0.call1(&JsValue::NULL, &value);
+        }
+    }
+}
+
+/// Register a callback that receives structured events from conversions, or pass
+/// `undefined` to remove it.
+///
+/// Each event is `{ type: "started", operation }` or `{ type: "finished", operation,
+/// ms, bytes }`. Operations are `"convert"` for the whole call, `"decode"`,
+/// `"encode"`, and one per processing step (a transform name or `"dither_16bit"`);
+/// `bytes` is the pixel data produced by decoding and processing steps, and the
+/// encoded size otherwise. Errors thrown by the callback are ignored.
+#[wasm_bindgen]
+pub fn set_event_listener(listener: Option<js_sys::Function>) {
+    let sink = listener.map(|f| -> Box<dyn events::EventSink> { Box::new(JsEventSink(f)) });
+    events::set_event_sink(sink);
+}
+
+/// Send this library's log output (chosen encoders, fallback decisions) to the browser
+/// console at `level` and above: `"off"`, `"error"`, `"warn"`, `"info"`, `"debug"` or
+/// `"trace"`. Logging is off until this is called.
+///
+/// Only available in builds with the `logging` feature.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the level is not recognized.
+#[cfg(feature = "logging")]
+#[wasm_bindgen]
+pub fn set_log_level(level: &str) -> Result<(), JsError> {
+    let level = logging::level_from_name(level)
+        .map_err(|e| JsError::new(&format!("Invalid log level: {e}")))?;
+    logging::set_log_level(level);
+    Ok(())
+}
+
+/// Extract the largest JPEG preview embedded in a camera RAW file (CR2, NEF or ARW),
+/// byte for byte. Much faster than converting the file, which decodes the same
+/// preview and turns it upright by the file's orientation.
+///
+/// Only available in builds with the `raw-preview` feature.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input isn't a supported RAW file or embeds no preview.
+#[cfg(feature = "raw-preview")]
+#[wasm_bindgen]
+pub fn extract_raw_preview(input: &[u8]) -> Result<Vec<u8>, JsError> {
+    raw::extract_preview(input)
+        .map_err(|e| JsError::new(&format!("Failed to extract RAW preview: {e}")))
+}
+
+/// Develop the sensor data of a camera RAW file (CR2, NEF or ARW) instead of reading
+/// its embedded preview, and encode the result as `target_format`.
+///
+/// Uncompressed and lossless-JPEG sensor data is read (CR2, and uncompressed NEF and
+/// ARW), then developed with black and white levels, the camera's as-shot white
+/// balance (gray world when the file doesn't record it in a form read here), bilinear
+/// demosaicing and the file's DNG color matrix if it has one. There are no per-camera
+/// color tables. Much slower than `extract_raw_preview`; conversions only develop files
+/// that embed no preview.
+///
+/// Only available in builds with the `raw-decode` feature.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the input isn't a supported RAW file, its sensor data uses
+/// another compression (such as Nikon's or Sony's own) or is corrupt, or the target
+/// format or quality is invalid.
+#[cfg(feature = "raw-decode")]
+#[wasm_bindgen]
+pub fn develop_raw(
+    input: &[u8],
+    target_format: &str,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, JsError> {
+    if let Some(q) = quality {
+        if q == 0 || q > 100 {
+            return Err(JsError::new("Quality must be between 1 and 100"));
+        }
+    }
+
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    let image = raw_decode::develop(input)
+        .map_err(|e| JsError::new(&format!("Failed to develop RAW file: {e}")))?;
+    convert::encode(&image, target, quality)
+        .map_err(|e| JsError::new(&format!("Failed to encode image: {e}")))
+}
+
+/// Describe what this build supports, so front-ends can build their format and
+/// operation menus at runtime.
+///
+/// Returns `{ version, input_formats, hint_required, output_formats, operations,
+/// features }`, where the format lists hold names `convert_image` accepts,
+/// `hint_required` lists input formats that must be passed as the `source_format`
+/// option, `operations` holds transform names followed by any operations registered
+/// from Rust, and `features` is `{ threads, simd, mozjpeg, logging, tracing,
+/// raw_preview, raw_decode, faces }`. `input_formats` includes `"psd"`, and with
+/// the `raw-preview` feature `"cr2"`, `"nef"` and `"arw"`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the result cannot be serialized.
+#[wasm_bindgen]
+pub fn capabilities() -> Result<TsCapabilities, JsError> {
+    serde_wasm_bindgen::to_value(&capabilities::capabilities())
+        .map(JsCast::unchecked_into)
+        .map_err(|e| JsError::new(&format!("Failed to serialize capabilities: {e}")))
+}
+
+/// Whether this build can convert `source_name` images to `target_name`, by format name
+/// (e.g. `"webp"`, `"jpg"`). Unknown names return `false`.
+///
+/// Cheap enough to call for every cell of a format matrix.
+#[wasm_bindgen]
+pub fn can_convert(source_name: &str, target_name: &str) -> bool {
+    capabilities::can_convert(source_name, target_name)
+}
+
+/// The file extension, without a dot, that files in `format` usually carry (e.g.
+/// `"jpg"` for `"jpeg"`).
+///
+/// # Errors
+///
+/// Returns a `JsError` if the format name is not recognized.
+#[wasm_bindgen]
+pub fn preferred_extension(format: &str) -> Result<String, JsError> {
+    let format = ImageFormat::from_name(format)
+        .map_err(|e| JsError::new(&format!("Invalid format: {e}")))?;
+    Ok(format.preferred_extension().to_owned())
+}
+
+/// Suggest a download name for `original_name` converted to `target_format`, e.g.
+/// `"photo.jpg"` for `"photo.png"`. Only extensions that name an image format are
+/// replaced; an empty name becomes `"image"`.
+///
+/// # Errors
+///
+/// Returns a `JsError` if the target format name is not recognized.
+#[wasm_bindgen]
+pub fn suggest_filename(original_name: &str, target_format: &str) -> Result<String, JsError> {
+    let target = ImageFormat::from_name(target_format)
+        .map_err(|e| JsError::new(&format!("Invalid target format: {e}")))?;
+    Ok(formats::suggest_filename(original_name, target))
 }
@@ -0,0 +1,94 @@
+use std::fmt;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+/// Writes log records to the browser console, each level to its matching console
+/// method so devtools can filter them.
+struct ConsoleLogger;
+
+impl Log for ConsoleLogger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let message = format!("[{}] {}", record.target(), record.args()).into();
+        match record.level() {
+            Level::Error => web_sys::console::error_1(&message),
+            Level::Warn => web_sys::console::warn_1(&message),
+            Level::Info => web_sys::console::info_1(&message),
+            Level::Debug | Level::Trace => web_sys::console::debug_1(&message),
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: ConsoleLogger = ConsoleLogger;
+
+/// Parses a log level name.
+///
+/// Accepts `"off"`, `"error"`, `"warn"` (or `"warning"`), `"info"`, `"debug"` or
+/// `"trace"`.
+///
+/// Returns an error if the string is not a recognized level.
+pub fn level_from_name(name: &str) -> Result<LevelFilter, LoggingError> {
+    match name.trim().to_ascii_lowercase().as_str() {
+        "off" => Ok(LevelFilter::Off),
+        "error" => Ok(LevelFilter::Error),
+        "warn" | "warning" => Ok(LevelFilter::Warn),
+        "info" => Ok(LevelFilter::Info),
+        "debug" => Ok(LevelFilter::Debug),
+        "trace" => Ok(LevelFilter::Trace),
+        _ => Err(LoggingError::UnknownLevel(name.to_owned())),
+    }
+}
+
+/// Sends this crate's log output to the browser console at `level` and above.
+///
+/// The console logger is installed on first use. If the host application already
+/// installed its own `log` logger, that one stays and only the level changes.
+pub fn set_log_level(level: LevelFilter) {
+    // Fails only if a logger is already installed, which is fine either way.
+    let _ = log::set_logger(&LOGGER);
+    log::set_max_level(level);
+}
+
+/// Errors from configuring logging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LoggingError {
+    /// The level name was not recognized.
+    UnknownLevel(String),
+}
+
+impl fmt::Display for LoggingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownLevel(name) => write!(
+                f,
+                "Unknown log level \"{name}\" (expected off, error, warn, info, debug or trace)"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for LoggingError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_names() {
+        assert_eq!(level_from_name("off").unwrap(), LevelFilter::Off);
+        assert_eq!(level_from_name(" Warning").unwrap(), LevelFilter::Warn);
+        assert_eq!(level_from_name("DEBUG").unwrap(), LevelFilter::Debug);
+        assert!(matches!(
+            level_from_name("verbose"),
+            Err(LoggingError::UnknownLevel(_))
+        ));
+    }
+}
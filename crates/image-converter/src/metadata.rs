@@ -4,6 +4,11 @@ use image::ImageDecoder;
 use image::ImageReader;
 use serde::Serialize;
 
+use crate::animation::{self, FrameRect};
+use crate::formats::{FormatError, ImageFormat};
+use crate::ico::{self, IconSize};
+use crate::jpeg;
+
 /// Metadata extracted from an image file.
 #[derive(Debug, Clone, Serialize, Default)]
 pub struct ImageMetadata {
@@ -44,6 +49,47 @@ pub struct ExifField {
     pub group: String,
 }
 
+/// What a file holds and what decoding it takes, read from its headers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Inspection {
+    pub format: &'static str,
+    pub width: u32,
+    pub height: u32,
+    /// Frames in the file; 1 for still images.
+    pub frame_count: u32,
+    pub animated: bool,
+    /// Where the first frame of an animation is drawn. `width` and `height` are the
+    /// canvas (a GIF's logical screen), which a frame may cover only part of. `None`
+    /// for still images.
+    pub first_frame: Option<FrameRect>,
+    /// A progressive JPEG, or an interlaced PNG or GIF.
+    pub progressive: bool,
+    pub has_alpha: bool,
+    pub has_icc_profile: bool,
+    pub has_exif: bool,
+    /// The sizes of an ICO file, in directory order; empty for other formats. The
+    /// other fields describe the largest, which conversions decode by default.
+    pub icon_sizes: Vec<IconSize>,
+    /// Estimated size of the decoded pixels: one buffer in the decoded color type, or
+    /// every frame as 8-bit RGBA for animations.
+    pub decoded_bytes: u64,
+}
+
+/// How much memory the decoded pixels of a file take, worked out from its headers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct DecodeMemory {
+    pub width: u32,
+    pub height: u32,
+    /// Frames decoded; 1 for still images.
+    pub frames: u32,
+    /// Channels and bits per channel of the decoded pixels. Animations are decoded to
+    /// 8-bit RGBA frames whatever they store.
+    pub channels: u8,
+    pub bits_per_channel: u8,
+    /// `width * height * frames * channels * bits_per_channel / 8`.
+    pub bytes: u64,
+}
+
 /// A PNG text chunk (tEXt, zTXt, or iTXt).
 #[derive(Debug, Clone, Serialize)]
 pub struct TextChunk {
@@ -62,6 +108,8 @@ pub enum MetadataError {
     ExifParse(String),
     /// Failed to parse PNG data.
     PngParse(String),
+    /// The format could not be detected.
+    Format(FormatError),
 }
 
 impl std::fmt::Display for MetadataError {
@@ -71,6 +119,7 @@ impl std::fmt::Display for MetadataError {
             Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
             Self::ExifParse(msg) => write!(f, "Failed to parse EXIF data: {msg}"),
             Self::PngParse(msg) => write!(f, "Failed to parse PNG data: {msg}"),
+            Self::Format(e) => write!(f, "{e}"),
         }
     }
 }
@@ -134,6 +183,114 @@ pub fn extract(input: &[u8]) -> Result<ImageMetadata, MetadataError> {
     })
 }
 
+/// Inspects a file's headers: its format, dimensions, animation, coding mode, what it
+/// embeds, and how much memory decoding it takes. No pixel data is decoded.
+///
+/// # Errors
+///
+/// Returns a `MetadataError` if the format cannot be detected or the headers cannot be
+/// read.
+pub fn inspect(input: &[u8]) -> Result<Inspection, MetadataError> {
+    let format = ImageFormat::detect_from_bytes(input).map_err(MetadataError::Format)?;
+    let icon_sizes = ico::sizes(input);
+    let icon = ico::select_entry(input, None);
+    let input = icon.as_deref().unwrap_or(input);
+    let mut decoder = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(MetadataError::Io)?
+        .into_decoder()
+        .map_err(MetadataError::Decode)?;
+
+    let (width, height) = decoder.dimensions();
+    let memory = decode_memory(input, &decoder);
+    let frame_count = memory.frames;
+    let animated = frame_count > 1;
+    let progressive = match format {
+        ImageFormat::Jpeg => jpeg::is_progressive(input).unwrap_or(false),
+        // The interlace method is the last byte of IHDR, which always comes first.
+        ImageFormat::Png => input.get(28) == Some(&1),
+        ImageFormat::Gif => gif_interlaced(input),
+        ImageFormat::WebP
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => false,
+    };
+
+    Ok(Inspection {
+        format: format.as_str(),
+        width,
+        height,
+        frame_count,
+        animated,
+        first_frame: animated
+            .then(|| animation::first_frame_rect(input))
+            .flatten(),
+        progressive,
+        has_alpha: decoder.color_type().has_alpha(),
+        has_icc_profile: decoder.icc_profile().ok().flatten().is_some(),
+        has_exif: decoder.exif_metadata().ok().flatten().is_some(),
+        icon_sizes,
+        decoded_bytes: memory.bytes,
+    })
+}
+
+/// Estimates the memory decoding `input` takes from its header dimensions, channels and
+/// bit depth, and for animations its frame count, without decoding any pixels. Hosts
+/// can use it to send files too large for the browser to a server instead.
+///
+/// # Errors
+///
+/// Returns a `MetadataError` if the format cannot be detected or the headers cannot be
+/// read.
+pub fn estimate_decode_memory(input: &[u8]) -> Result<DecodeMemory, MetadataError> {
+    let decoder = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(MetadataError::Io)?
+        .into_decoder()
+        .map_err(MetadataError::Decode)?;
+    Ok(decode_memory(input, &decoder))
+}
+
+/// [`estimate_decode_memory`] for an already-opened decoder.
+fn decode_memory(input: &[u8], decoder: &impl ImageDecoder) -> DecodeMemory {
+    let (width, height) = decoder.dimensions();
+    let frames = u32::try_from(animation::frame_count(input).unwrap_or(1)).unwrap_or(u32::MAX);
+    let color = decoder.color_type();
+    // Animations are composited into full-size RGBA8 frames.
+    let (channels, bits_per_channel) = if frames > 1 {
+        (4, 8)
+    } else {
+        let channels = color.channel_count();
+        (channels, color.bytes_per_pixel() / channels.max(1) * 8)
+    };
+    let bytes = u64::from(width)
+        * u64::from(height)
+        * u64::from(frames.max(1))
+        * u64::from(channels)
+        * u64::from(bits_per_channel)
+        / 8;
+    DecodeMemory {
+        width,
+        height,
+        frames: frames.max(1),
+        channels,
+        bits_per_channel,
+        bytes,
+    }
+}
+
+/// Whether the first frame of a GIF is interlaced, read from its image descriptor.
+fn gif_interlaced(input: &[u8]) -> bool {
+    let mut options = gif::DecodeOptions::new();
+    options.set_color_output(gif::ColorOutput::Indexed);
+    let Ok(mut decoder) = options.read_info(input) else {
+        return false;
+    };
+    matches!(decoder.next_frame_info(), Ok(Some(frame)) if frame.interlaced)
+}
+
 /// Convert an `image::ImageFormat` to a lowercase string name.
 fn format_to_string(format: image::ImageFormat) -> String {
     match format {
@@ -352,7 +509,7 @@ mod tests {
                 .add_text_chunk(text_chunk.keyword.clone(), text_chunk.text.clone())
                 .unwrap();
             let mut writer = encoder.write_header().unwrap();
-            let data = vec![0u8; (width * height * 4) as usize];
+            let data = vec![0u8; usize::try_from(width * height * 4).unwrap()];
             writer.write_image_data(&data).unwrap();
         }
         buf
@@ -514,4 +671,108 @@ mod tests {
         assert_eq!(format_to_string(image::ImageFormat::Bmp), "bmp");
         assert_eq!(format_to_string(image::ImageFormat::Tiff), "tiff");
     }
+
+    // ===== Inspection Tests =====
+
+    #[test]
+    fn inspect_still_png() {
+        let info = inspect(&make_png(6, 5)).unwrap();
+        assert_eq!(info.format, "png");
+        assert_eq!((info.width, info.height), (6, 5));
+        assert_eq!(info.frame_count, 1);
+        assert!(!info.animated && !info.progressive);
+        assert!(info.has_alpha);
+        assert!(!info.has_icc_profile && !info.has_exif);
+        assert_eq!(info.decoded_bytes, 6 * 5 * 4);
+    }
+
+    #[test]
+    fn inspect_jpeg() {
+        let info = inspect(&make_jpeg(8, 4)).unwrap();
+        assert_eq!(info.format, "jpeg");
+        assert!(!info.has_alpha && !info.progressive);
+        assert_eq!(info.decoded_bytes, 8 * 4 * 3);
+    }
+
+    #[test]
+    fn inspect_animated_interlaced_gif() {
+        let mut buf = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut buf, 4, 3, &[0, 0, 0, 255, 255, 255]).unwrap();
+            for index in 0..2 {
+                let mut frame = gif::Frame::from_indexed_pixels(4, 3, vec![index; 12], None);
+                frame.interlaced = true;
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        let info = inspect(&buf).unwrap();
+        assert_eq!(info.format, "gif");
+        assert_eq!(info.frame_count, 2);
+        assert!(info.animated && info.progressive);
+        assert_eq!(info.decoded_bytes, 2 * 4 * 3 * 4);
+    }
+
+    #[test]
+    fn inspect_reports_canvas_and_first_frame() {
+        let mut buf = Vec::new();
+        {
+            let mut encoder =
+                gif::Encoder::new(&mut buf, 20, 10, &[0, 0, 0, 255, 255, 255]).unwrap();
+            for index in 0..2 {
+                let mut frame = gif::Frame::from_indexed_pixels(4, 3, vec![index; 12], None);
+                (frame.left, frame.top) = (6, 5);
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        let info = inspect(&buf).unwrap();
+        assert_eq!((info.width, info.height), (20, 10));
+        assert_eq!(
+            info.first_frame,
+            Some(FrameRect {
+                left: 6,
+                top: 5,
+                width: 4,
+                height: 3
+            })
+        );
+        let dims = crate::convert::dimensions(&buf).unwrap();
+        assert_eq!((dims.width, dims.height), (20, 10));
+        assert_eq!(inspect(&make_jpeg(8, 4)).unwrap().first_frame, None);
+    }
+
+    #[test]
+    fn decode_memory_counts_channels_depth_and_frames() {
+        let memory = estimate_decode_memory(&make_jpeg(8, 4)).unwrap();
+        assert_eq!(
+            (memory.channels, memory.bits_per_channel, memory.bytes),
+            (3, 8, 8 * 4 * 3)
+        );
+
+        let wide = image::ImageBuffer::<image::Rgba<u16>, _>::new(5, 2);
+        let mut png = Vec::new();
+        image::DynamicImage::ImageRgba16(wide)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let memory = estimate_decode_memory(&png).unwrap();
+        assert_eq!(
+            (memory.channels, memory.bits_per_channel, memory.bytes),
+            (4, 16, 5 * 2 * 8)
+        );
+
+        let mut gif = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut gif, 4, 3, &[0, 0, 0, 255, 255, 255]).unwrap();
+            for index in 0..3 {
+                let frame = gif::Frame::from_indexed_pixels(4, 3, vec![index % 2; 12], None);
+                encoder.write_frame(&frame).unwrap();
+            }
+        }
+        let memory = estimate_decode_memory(&gif).unwrap();
+        assert_eq!((memory.frames, memory.bytes), (3, 3 * 4 * 3 * 4));
+    }
+
+    #[test]
+    fn inspect_rejects_unknown_input() {
+        assert!(matches!(inspect(&[1, 2, 3]), Err(MetadataError::Format(_))));
+    }
 }
@@ -0,0 +1,533 @@
+use std::fmt;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::color;
+use crate::composite;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::MAX_SIDE;
+use crate::region::{self, RegionError};
+use crate::text;
+
+/// Default number of cells per row.
+pub const DEFAULT_COLUMNS: u32 = 4;
+
+/// Default side of the square each thumbnail is fitted into, in pixels.
+pub const DEFAULT_CELL_SIZE: u32 = 200;
+
+/// Default space around and between cells, in pixels.
+pub const DEFAULT_GAP: u32 = 8;
+
+/// Height of cell labels, in pixels.
+const LABEL_HEIGHT: u32 = 9;
+
+/// Height of the header line, in pixels.
+const HEADER_HEIGHT: u32 = 14;
+
+/// Settings for [`contact_sheet`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ContactSheetOptions {
+    /// Cells per row.
+    pub columns: u32,
+    /// Side of the square each thumbnail is fitted into.
+    pub cell_size: u32,
+    /// Space around and between cells.
+    pub gap: u32,
+    /// A caption under each cell, such as its filename, in image order. Cells past the
+    /// end of the list, and empty strings, get no caption.
+    pub labels: Vec<String>,
+    /// A line of text above the grid, such as the shoot and delivery date.
+    pub header: Option<String>,
+    /// Color of the sheet behind the cells.
+    pub background: [u8; 4],
+}
+
+impl Default for ContactSheetOptions {
+    fn default() -> Self {
+        Self {
+            columns: DEFAULT_COLUMNS,
+            cell_size: DEFAULT_CELL_SIZE,
+            gap: DEFAULT_GAP,
+            labels: Vec::new(),
+            header: None,
+            background: [u8::MAX; 4],
+        }
+    }
+}
+
+/// Lays `images` out as thumbnails in a grid, with optional captions under each cell
+/// and a header line above the grid, e.g. to deliver proofs from a shoot. Thumbnails
+/// keep their aspect ratio and are centered in their cells; images smaller than a cell
+/// aren't enlarged. Text longer than its space is cut short with `...`.
+///
+/// # Errors
+///
+/// Returns a `MontageError` if there are no images, `columns` or `cell_size` is zero,
+/// the sheet would be larger than [`MAX_SIDE`], or an image cannot be decoded.
+pub fn contact_sheet(
+    images: &[Vec<u8>],
+    options: &ContactSheetOptions,
+) -> Result<RgbaImage, MontageError> {
+    if images.is_empty() {
+        return Err(MontageError::NoImages);
+    }
+    if options.columns == 0 || options.cell_size == 0 {
+        return Err(MontageError::InvalidLayout);
+    }
+
+    let count = u32::try_from(images.len()).map_err(|_| MontageError::TooLarge)?;
+    let columns = options.columns.min(count);
+    let rows = count.div_ceil(columns);
+    let gap = options.gap;
+    let labeled = options.labels.iter().any(|label| !label.is_empty());
+    let label_band = if labeled { gap + LABEL_HEIGHT } else { 0 };
+    let header_band = match &options.header {
+        Some(header) if !header.is_empty() => HEADER_HEIGHT + gap,
+        _ => 0,
+    };
+    let cell_width = options.cell_size;
+    let cell_height = options.cell_size + label_band;
+    let side = |cells: u32, cell: u32, extra: u32| {
+        cells
+            .checked_mul(cell.checked_add(gap)?)?
+            .checked_add(gap)?
+            .checked_add(extra)
+            .filter(|&side| side <= MAX_SIDE)
+    };
+    let width = side(columns, cell_width, 0).ok_or(MontageError::TooLarge)?;
+    let height = side(rows, cell_height, header_band).ok_or(MontageError::TooLarge)?;
+
+    let mut sheet = RgbaImage::from_pixel(width, height, Rgba(options.background));
+    let ink = if color::luminance(options.background) > 128 {
+        [0x20, 0x20, 0x20, 0xff]
+    } else {
+        [0xf0, 0xf0, 0xf0, 0xff]
+    };
+    if let Some(header) = options.header.as_deref().filter(|h| !h.is_empty()) {
+        let header = text::fit(header, HEADER_HEIGHT, width - 2 * gap);
+        text::draw_text(
+            &mut sheet,
+            &header,
+            i64::from(gap),
+            i64::from(gap),
+            HEADER_HEIGHT,
+            ink,
+        );
+    }
+
+    for ((index, input), cell) in images.iter().enumerate().zip(0u32..) {
+        let decoded = region::decode_scaled(input, cell_width)
+            .map_err(|source| MontageError::Decode { index, source })?;
+        let thumb =
+            RgbaImage::from_raw(decoded.width, decoded.height, decoded.rgba).unwrap_or_default();
+
+        let left = gap + (cell % columns) * (cell_width + gap);
+        let top = header_band + gap + (cell / columns) * (cell_height + gap);
+        let x = left + (cell_width - thumb.width()) / 2;
+        let y = top + (cell_width - thumb.height()) / 2;
+        composite::overlay(&mut sheet, &thumb, i64::from(x), i64::from(y), 1.0);
+
+        if let Some(label) = options.labels.get(index).filter(|l| !l.is_empty()) {
+            let label = text::fit(label, LABEL_HEIGHT, cell_width);
+            let (label_width, _) = text::measure(&label, LABEL_HEIGHT);
+            let label_x = left + (cell_width - label_width.min(cell_width)) / 2;
+            let label_y = top + cell_width + gap;
+            text::draw_text(
+                &mut sheet,
+                &label,
+                i64::from(label_x),
+                i64::from(label_y),
+                LABEL_HEIGHT,
+                ink,
+            );
+        }
+    }
+    Ok(sheet)
+}
+
+/// Which way [`concat`] joins images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Direction {
+    /// Side by side, left to right.
+    #[default]
+    Horizontal,
+    /// Stacked, top to bottom.
+    Vertical,
+}
+
+impl Direction {
+    /// Parses a direction: `"horizontal"` (or `"row"`, or an empty string) or
+    /// `"vertical"` (or `"column"`).
+    ///
+    /// Returns an error if the string is not a recognized direction.
+    pub fn from_name(name: &str) -> Result<Self, MontageError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "horizontal" | "row" => Ok(Self::Horizontal),
+            "vertical" | "column" => Ok(Self::Vertical),
+            _ => Err(MontageError::UnknownDirection(name.to_owned())),
+        }
+    }
+}
+
+/// Where [`concat`] places images narrower than the strip across it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Align {
+    /// The top of a horizontal strip, the left of a vertical one.
+    Start,
+    /// Centered.
+    #[default]
+    Center,
+    /// The bottom of a horizontal strip, the right of a vertical one.
+    End,
+}
+
+impl Align {
+    /// Parses an alignment: `"start"` (or `"top"`, `"left"`), `"center"` (or
+    /// `"middle"`, or an empty string) or `"end"` (or `"bottom"`, `"right"`).
+    ///
+    /// Returns an error if the string is not a recognized alignment.
+    pub fn from_name(name: &str) -> Result<Self, MontageError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "start" | "top" | "left" => Ok(Self::Start),
+            "" | "center" | "middle" => Ok(Self::Center),
+            "end" | "bottom" | "right" => Ok(Self::End),
+            _ => Err(MontageError::UnknownAlign(name.to_owned())),
+        }
+    }
+
+    /// Offset of something `size` long within `space`.
+    fn offset(self, size: u32, space: u32) -> u32 {
+        let slack = space.saturating_sub(size);
+        match self {
+            Self::Start => 0,
+            Self::Center => slack / 2,
+            Self::End => slack,
+        }
+    }
+}
+
+/// Settings for [`concat`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct ConcatOptions {
+    pub direction: Direction,
+    pub align: Align,
+    /// Space between images, in pixels.
+    pub gap: u32,
+    /// Color of the gaps and of the space beside smaller images (default transparent).
+    pub background: [u8; 4],
+}
+
+/// Joins `images` edge to edge at their own size, e.g. a before/after comparison or a
+/// long receipt photographed in parts. The strip is as wide (or, stacked vertically,
+/// as tall) as the largest image across it; smaller images are placed by `align`.
+///
+/// # Errors
+///
+/// Returns a `MontageError` if there are no images, the result would be larger than
+/// [`MAX_SIDE`], or an image cannot be decoded.
+pub fn concat(images: &[Vec<u8>], options: &ConcatOptions) -> Result<RgbaImage, MontageError> {
+    if images.is_empty() {
+        return Err(MontageError::NoImages);
+    }
+    let decoded = images
+        .iter()
+        .enumerate()
+        .map(|(index, input)| {
+            image::load_from_memory(input)
+                .map(DynamicImage::into_rgba8)
+                .map_err(|e| MontageError::Decode {
+                    index,
+                    source: RegionError::Decode(e),
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let horizontal = options.direction == Direction::Horizontal;
+    // Lengths along the strip and across it.
+    let along = |img: &RgbaImage| {
+        if horizontal {
+            img.width()
+        } else {
+            img.height()
+        }
+    };
+    let across = |img: &RgbaImage| {
+        if horizontal {
+            img.height()
+        } else {
+            img.width()
+        }
+    };
+    // Where each image starts along the strip, and where the last one ends.
+    let mut starts = Vec::with_capacity(decoded.len());
+    let mut length: u32 = 0;
+    for (index, img) in decoded.iter().enumerate() {
+        let start = if index == 0 {
+            0
+        } else {
+            length
+                .checked_add(options.gap)
+                .ok_or(MontageError::TooLarge)?
+        };
+        length = start
+            .checked_add(along(img))
+            .filter(|&end| end <= MAX_SIDE)
+            .ok_or(MontageError::TooLarge)?;
+        starts.push(start);
+    }
+    let breadth = decoded.iter().map(across).max().unwrap_or(0);
+    if breadth > MAX_SIDE {
+        return Err(MontageError::TooLarge);
+    }
+
+    let (width, height) = if horizontal {
+        (length, breadth)
+    } else {
+        (breadth, length)
+    };
+    let mut strip = RgbaImage::from_pixel(width, height, Rgba(options.background));
+    for (img, start) in decoded.iter().zip(starts) {
+        let offset = options.align.offset(across(img), breadth);
+        let (x, y) = if horizontal {
+            (start, offset)
+        } else {
+            (offset, start)
+        };
+        image::imageops::replace(&mut strip, img, i64::from(x), i64::from(y));
+    }
+    Ok(strip)
+}
+
+/// Joins images with [`concat`] and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns a `MontageError` if the images cannot be joined or the result encoded.
+pub fn encode_concat(
+    images: &[Vec<u8>],
+    options: &ConcatOptions,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, MontageError> {
+    let strip = concat(images, options)?;
+    convert::encode(&DynamicImage::ImageRgba8(strip), target, quality)
+        .map_err(MontageError::Convert)
+}
+
+/// Builds a [`contact_sheet`] and encodes it as `target`.
+///
+/// # Errors
+///
+/// Returns a `MontageError` if the sheet cannot be built or encoded.
+pub fn encode_contact_sheet(
+    images: &[Vec<u8>],
+    options: &ContactSheetOptions,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, MontageError> {
+    let sheet = contact_sheet(images, options)?;
+    convert::encode(&DynamicImage::ImageRgba8(sheet), target, quality)
+        .map_err(MontageError::Convert)
+}
+
+/// Errors that can occur while building a montage.
+#[derive(Debug)]
+pub enum MontageError {
+    /// No images were given.
+    NoImages,
+    /// The number of columns or the cell size was zero.
+    InvalidLayout,
+    /// The sheet or strip would be larger than [`MAX_SIDE`] on a side.
+    TooLarge,
+    /// The concatenation direction was not recognized.
+    UnknownDirection(String),
+    /// The concatenation alignment was not recognized.
+    UnknownAlign(String),
+    /// Failed to decode the image at `index`.
+    Decode { index: usize, source: RegionError },
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for MontageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoImages => write!(f, "At least one image is required"),
+            Self::InvalidLayout => write!(f, "Columns and cell size must be at least 1"),
+            Self::TooLarge => write!(
+                f,
+                "The result would be larger than {MAX_SIDE}x{MAX_SIDE}; use fewer or smaller images"
+            ),
+            Self::UnknownDirection(name) => write!(
+                f,
+                "Unknown direction \"{name}\" (expected horizontal or vertical)"
+            ),
+            Self::UnknownAlign(name) => write!(
+                f,
+                "Unknown alignment \"{name}\" (expected start, center or end)"
+            ),
+            Self::Decode { index, source } => {
+                write!(f, "Image {index}: {source}")
+            }
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for MontageError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn png(width: u32, height: u32, color: [u8; 4]) -> Vec<u8> {
+        let mut out = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(width, height, Rgba(color)))
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn lays_out_fitted_thumbnails() {
+        let images = [
+            png(100, 50, [255, 0, 0, 255]),
+            png(10, 10, [0, 0, 255, 255]),
+            png(20, 40, [0, 255, 0, 255]),
+        ];
+        let options = ContactSheetOptions {
+            columns: 2,
+            cell_size: 40,
+            gap: 4,
+            ..ContactSheetOptions::default()
+        };
+        let sheet = contact_sheet(&images, &options).unwrap();
+        assert_eq!(sheet.dimensions(), (2 * 44 + 4, 2 * 44 + 4));
+        // The wide image is scaled to 40x20 and centered vertically.
+        assert_eq!(sheet.get_pixel(4, 13).0, [255; 4]);
+        assert_eq!(sheet.get_pixel(4, 14).0, [255, 0, 0, 255]);
+        // The small image keeps its size.
+        assert_eq!(sheet.get_pixel(48 + 15, 4 + 15).0, [0, 0, 255, 255]);
+        assert_eq!(sheet.get_pixel(48 + 14, 4 + 15).0, [255; 4]);
+        // The empty last cell is background.
+        assert_eq!(sheet.get_pixel(70, 70).0, [255; 4]);
+    }
+
+    #[test]
+    fn labels_and_header_add_bands() {
+        let images = [png(8, 8, [0, 0, 0, 255]), png(8, 8, [0, 0, 0, 255])];
+        let options = ContactSheetOptions {
+            columns: 2,
+            cell_size: 60,
+            gap: 4,
+            labels: vec!["IMG_0001.jpg".into(), String::new()],
+            header: Some("Proofs".into()),
+            ..ContactSheetOptions::default()
+        };
+        let sheet = contact_sheet(&images, &options).unwrap();
+        let label_band = 4 + LABEL_HEIGHT;
+        let header_band = HEADER_HEIGHT + 4;
+        assert_eq!(
+            sheet.dimensions(),
+            (2 * 64 + 4, 64 + label_band + 4 + header_band)
+        );
+
+        let dark_in = |x0: u32, y0: u32, x1: u32, y1: u32| {
+            (y0..y1).any(|y| (x0..x1).any(|x| sheet.get_pixel(x, y).0[0] < 128))
+        };
+        assert!(dark_in(4, 4, 128, 4 + HEADER_HEIGHT));
+        let label_top = header_band + 4 + 60 + 4;
+        assert!(dark_in(4, label_top, 64, label_top + LABEL_HEIGHT));
+        assert!(!dark_in(68, label_top, 128, label_top + LABEL_HEIGHT));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        let options = ContactSheetOptions::default();
+        assert!(matches!(
+            contact_sheet(&[], &options),
+            Err(MontageError::NoImages)
+        ));
+        assert!(matches!(
+            contact_sheet(&[vec![1, 2, 3]], &options),
+            Err(MontageError::Decode { index: 0, .. })
+        ));
+        let huge = ContactSheetOptions {
+            cell_size: MAX_SIDE,
+            ..ContactSheetOptions::default()
+        };
+        assert!(matches!(
+            contact_sheet(&[png(1, 1, [0; 4]), png(1, 1, [0; 4])], &huge),
+            Err(MontageError::TooLarge)
+        ));
+    }
+
+    #[test]
+    fn concat_joins_images_with_gaps_and_alignment() {
+        let images = [png(4, 6, [255, 0, 0, 255]), png(3, 2, [0, 0, 255, 255])];
+        let options = ConcatOptions {
+            gap: 2,
+            background: [255; 4],
+            ..ConcatOptions::default()
+        };
+        let strip = concat(&images, &options).unwrap();
+        assert_eq!(strip.dimensions(), (4 + 2 + 3, 6));
+        assert_eq!(strip.get_pixel(3, 5).0, [255, 0, 0, 255]);
+        assert_eq!(strip.get_pixel(4, 0).0, [255; 4]);
+        // The short image is centered across the strip.
+        assert_eq!(strip.get_pixel(6, 1).0, [255; 4]);
+        assert_eq!(strip.get_pixel(6, 2).0, [0, 0, 255, 255]);
+        assert_eq!(strip.get_pixel(8, 3).0, [0, 0, 255, 255]);
+
+        let stacked = concat(
+            &images,
+            &ConcatOptions {
+                direction: Direction::Vertical,
+                align: Align::End,
+                ..ConcatOptions::default()
+            },
+        )
+        .unwrap();
+        assert_eq!(stacked.dimensions(), (4, 8));
+        assert_eq!(stacked.get_pixel(0, 6).0, [0; 4]);
+        assert_eq!(stacked.get_pixel(1, 6).0, [0, 0, 255, 255]);
+
+        assert_eq!(
+            Direction::from_name(" Column ").unwrap(),
+            Direction::Vertical
+        );
+        assert_eq!(Align::from_name("top").unwrap(), Align::Start);
+        assert!(matches!(
+            Direction::from_name("diagonal"),
+            Err(MontageError::UnknownDirection(_))
+        ));
+        assert!(matches!(
+            Align::from_name("justify"),
+            Err(MontageError::UnknownAlign(_))
+        ));
+        assert!(matches!(concat(&[], &options), Err(MontageError::NoImages)));
+        assert!(matches!(
+            concat(&[images[0].clone(), b"junk".to_vec()], &options),
+            Err(MontageError::Decode { index: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn concat_gaps_cannot_overflow() {
+        let image = png(4, 6, [255, 0, 0, 255]);
+        let huge_gap = ConcatOptions {
+            gap: u32::MAX - 1,
+            ..ConcatOptions::default()
+        };
+        // A single image has no gaps, so the gap can't matter.
+        let strip = concat(std::slice::from_ref(&image), &huge_gap).unwrap();
+        assert_eq!(strip.dimensions(), (4, 6));
+        assert!(matches!(
+            concat(&[image.clone(), image], &huge_gap),
+            Err(MontageError::TooLarge)
+        ));
+    }
+}
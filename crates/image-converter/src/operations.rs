@@ -0,0 +1,289 @@
+//! Custom pixel operations, registered by Rust code that embeds this crate and then
+//! named in conversion options like the built-in steps.
+//!
+//! JS can't implement [`Operation`]; a host that needs its own operations from JS
+//! builds its own module around this crate and registers them at startup.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use image::DynamicImage;
+
+use crate::transforms::Transform;
+
+/// A pixel operation the conversion pipeline can run by name.
+pub trait Operation {
+    /// The name conversion options refer to the operation by, e.g. `"sepia"`.
+    fn name(&self) -> &'static str;
+
+    /// Checks `params` before anything is decoded, so bad parameters fail early (and
+    /// presets fail at registration). Accepts anything by default.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OperationError::InvalidParams` describing what is wrong.
+    fn validate(&self, params: &serde_json::Value) -> Result<(), OperationError> {
+        let _ = params;
+        Ok(())
+    }
+
+    /// The size of the output for a `width`×`height` input, for
+    /// [`convert::plan`](crate::convert::plan). The size is kept by default.
+    fn dimensions(&self, width: u32, height: u32, params: &serde_json::Value) -> (u32, u32) {
+        let _ = params;
+        (width, height)
+    }
+
+    /// Applies the operation. Animations call this once per frame.
+    ///
+    /// # Errors
+    ///
+    /// Returns an `OperationError` if the operation can't be applied.
+    fn apply(
+        &self,
+        image: DynamicImage,
+        params: &serde_json::Value,
+    ) -> Result<DynamicImage, OperationError>;
+}
+
+/// A registered operation to run, with its parameters: the JSON object of the step
+/// without its `"op"` key, or `Null`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationStep {
+    pub name: String,
+    pub params: serde_json::Value,
+}
+
+impl OperationStep {
+    /// The registered operation this step names, with its parameters validated.
+    ///
+    /// # Errors
+    ///
+    /// Returns `OperationError::UnknownOperation` if nothing is registered under the
+    /// name, or the operation's validation error.
+    pub fn resolve(&self) -> Result<Rc<dyn Operation>, OperationError> {
+        let operation =
+            find(&self.name).ok_or_else(|| OperationError::UnknownOperation(self.name.clone()))?;
+        operation.validate(&self.params)?;
+        Ok(operation)
+    }
+}
+
+/// Step names the pipeline uses itself; see [`OpTiming`](crate::convert::OpTiming).
+const RESERVED: [&str; 2] = ["resize", "dither_16bit"];
+
+thread_local! {
+    /// Registered operations in registration order, per thread like the font registry.
+    static OPERATIONS: RefCell<Vec<Rc<dyn Operation>>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Registers `operation` under its [`Operation::name`], replacing any operation
+/// already registered under that name (compared case-insensitively).
+///
+/// # Errors
+///
+/// Returns `OperationError::InvalidName` if the name is blank, contains a comma, or is
+/// taken by a built-in transform or pipeline step.
+pub fn register_operation(operation: impl Operation + 'static) -> Result<(), OperationError> {
+    let name = operation.name();
+    let builtin = Transform::from_name(name).is_ok()
+        || RESERVED
+            .iter()
+            .any(|reserved| reserved.eq_ignore_ascii_case(name.trim()));
+    if name.trim().is_empty() || name.contains(',') || builtin {
+        return Err(OperationError::InvalidName(name.to_owned()));
+    }
+    OPERATIONS.with(|operations| {
+        let mut operations = operations.borrow_mut();
+        let operation: Rc<dyn Operation> = Rc::new(operation);
+        match operations
+            .iter_mut()
+            .find(|registered| registered.name().eq_ignore_ascii_case(name))
+        {
+            Some(existing) => *existing = operation,
+            None => operations.push(operation),
+        }
+    });
+    Ok(())
+}
+
+/// Removes the operation registered under `name`. Returns whether there was one.
+pub fn unregister_operation(name: &str) -> bool {
+    let name = name.trim();
+    OPERATIONS.with(|operations| {
+        let mut operations = operations.borrow_mut();
+        let before = operations.len();
+        operations.retain(|registered| !registered.name().eq_ignore_ascii_case(name));
+        operations.len() != before
+    })
+}
+
+/// The names of the registered operations, in registration order.
+pub fn registered_operations() -> Vec<&'static str> {
+    OPERATIONS.with(|operations| {
+        operations
+            .borrow()
+            .iter()
+            .map(|operation| operation.name())
+            .collect()
+    })
+}
+
+fn find(name: &str) -> Option<Rc<dyn Operation>> {
+    let name = name.trim();
+    OPERATIONS.with(|operations| {
+        operations
+            .borrow()
+            .iter()
+            .find(|registered| registered.name().eq_ignore_ascii_case(name))
+            .map(Rc::clone)
+    })
+}
+
+/// Errors that can occur while registering or running operations.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum OperationError {
+    /// The name was blank, contained a comma, or belongs to a built-in step.
+    InvalidName(String),
+    /// No operation is registered under the name.
+    UnknownOperation(String),
+    /// The step's parameters were rejected.
+    InvalidParams(String),
+    /// The operation failed.
+    Failed(String),
+}
+
+impl fmt::Display for OperationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName(name) => write!(f, "{name:?} can't be used as an operation name"),
+            Self::UnknownOperation(name) => write!(f, "No operation is registered as {name:?}"),
+            Self::InvalidParams(msg) => write!(f, "Invalid operation parameters: {msg}"),
+            Self::Failed(msg) => write!(f, "Operation failed: {msg}"),
+        }
+    }
+}
+
+impl std::error::Error for OperationError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::convert::{self, ConvertOptions};
+    use crate::formats::ImageFormat;
+
+    /// Pads the image with `"size"` transparent pixels on every side.
+    struct Pad;
+
+    impl Pad {
+        fn size(params: &serde_json::Value) -> Result<u32, OperationError> {
+            params["size"]
+                .as_u64()
+                .and_then(|size| u32::try_from(size).ok())
+                .ok_or_else(|| OperationError::InvalidParams("size must be a number".into()))
+        }
+    }
+
+    impl Operation for Pad {
+        fn name(&self) -> &'static str {
+            "pad"
+        }
+
+        fn validate(&self, params: &serde_json::Value) -> Result<(), OperationError> {
+            Self::size(params).map(|_| ())
+        }
+
+        fn dimensions(&self, width: u32, height: u32, params: &serde_json::Value) -> (u32, u32) {
+            let size = Self::size(params).unwrap_or(0);
+            (width + 2 * size, height + 2 * size)
+        }
+
+        fn apply(
+            &self,
+            image: DynamicImage,
+            params: &serde_json::Value,
+        ) -> Result<DynamicImage, OperationError> {
+            let size = Self::size(params)?;
+            let mut padded =
+                image::RgbaImage::new(image.width() + 2 * size, image.height() + 2 * size);
+            image::imageops::overlay(
+                &mut padded,
+                &image.to_rgba8(),
+                i64::from(size),
+                i64::from(size),
+            );
+            Ok(DynamicImage::ImageRgba8(padded))
+        }
+    }
+
+    struct Named(&'static str);
+
+    impl Operation for Named {
+        fn name(&self) -> &'static str {
+            self.0
+        }
+
+        fn apply(
+            &self,
+            image: DynamicImage,
+            _params: &serde_json::Value,
+        ) -> Result<DynamicImage, OperationError> {
+            Ok(image)
+        }
+    }
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        image::RgbaImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn registered_operations_run_in_conversions() {
+        register_operation(Pad).unwrap();
+        let options = ConvertOptions {
+            operations: vec![OperationStep {
+                name: "Pad".to_owned(),
+                params: serde_json::json!({ "size": 3 }),
+            }],
+            ..ConvertOptions::default()
+        };
+        let plan = convert::plan(&png(4, 2), ImageFormat::Png, &options, &[]).unwrap();
+        assert_eq!((plan.width, plan.height), (10, 8));
+        let output =
+            convert::convert_with_options(png(4, 2), ImageFormat::Png, &options, &[]).unwrap();
+        let img = image::load_from_memory(&output).unwrap();
+        assert_eq!((img.width(), img.height()), (10, 8));
+
+        let bad = ConvertOptions {
+            operations: vec![OperationStep {
+                name: "pad".to_owned(),
+                params: serde_json::Value::Null,
+            }],
+            ..ConvertOptions::default()
+        };
+        assert!(convert::convert_with_options(png(4, 2), ImageFormat::Png, &bad, &[]).is_err());
+        assert!(unregister_operation("pad"));
+        assert!(convert::plan(&png(4, 2), ImageFormat::Png, &options, &[]).is_err());
+    }
+
+    #[test]
+    fn registry_rejects_builtin_names_and_replaces_by_name() {
+        for name in ["invert", "Resize", " ", "a,b"] {
+            assert_eq!(
+                register_operation(Named(name)),
+                Err(OperationError::InvalidName(name.to_owned()))
+            );
+        }
+        register_operation(Named("noop")).unwrap();
+        register_operation(Named("identity")).unwrap();
+        register_operation(Named("NOOP")).unwrap();
+        assert_eq!(registered_operations(), ["NOOP", "identity"]);
+        assert!(!unregister_operation("sepia"));
+    }
+}
@@ -0,0 +1,136 @@
+use std::fmt;
+
+use image::DynamicImage;
+
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::quantize::{self, Dither, QuantizeError};
+
+/// Decodes `input`, constrains it to `colors` (see [`quantize::remap_to_palette`]), and
+/// encodes it as `target`.
+///
+/// PNG output is written as an indexed PNG holding just the palette colors the image
+/// uses; other formats get the remapped RGBA pixels.
+///
+/// # Errors
+///
+/// Returns a `PaletteError` if the palette is empty or larger than 256 colors, the
+/// input cannot be decoded, or the output cannot be encoded.
+pub fn apply_palette(
+    input: &[u8],
+    colors: &[[u8; 4]],
+    dither: Dither,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, PaletteError> {
+    let img = image::load_from_memory(input)
+        .map_err(PaletteError::Decode)?
+        .into_rgba8();
+    let indexed =
+        quantize::remap_to_palette(&img, colors, dither).map_err(PaletteError::Quantize)?;
+    drop(img);
+
+    if target == ImageFormat::Png {
+        return indexed.to_png().map_err(PaletteError::Quantize);
+    }
+    convert::encode(
+        &DynamicImage::ImageRgba8(indexed.to_rgba()),
+        target,
+        quality,
+    )
+    .map_err(PaletteError::Convert)
+}
+
+/// Errors that can occur while applying a palette.
+#[derive(Debug)]
+pub enum PaletteError {
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// The palette was invalid or the indexed PNG could not be written.
+    Quantize(QuantizeError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for PaletteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Quantize(e) => write!(f, "{e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for PaletteError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    const BLACK_WHITE: [[u8; 4]; 2] = [[0, 0, 0, 255], [255, 255, 255, 255]];
+
+    fn gray_ramp_png() -> Vec<u8> {
+        let img = RgbaImage::from_fn(64, 16, |x, _| {
+            let v = u8::try_from(x * 4).unwrap();
+            Rgba([v, v, v, 255])
+        });
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn png_output_is_indexed_with_palette_colors_only() {
+        for dither in [Dither::None, Dither::FloydSteinberg, Dither::Ordered] {
+            let output = apply_palette(
+                &gray_ramp_png(),
+                &BLACK_WHITE,
+                dither,
+                ImageFormat::Png,
+                None,
+            )
+            .unwrap();
+            let decoder = png::Decoder::new(Cursor::new(&output));
+            let reader = decoder.read_info().unwrap();
+            assert_eq!(reader.info().color_type, png::ColorType::Indexed);
+
+            let img = image::load_from_memory(&output).unwrap().into_rgba8();
+            let colors: HashSet<[u8; 4]> = img.pixels().map(|p| p.0).collect();
+            assert!(colors.iter().all(|c| BLACK_WHITE.contains(c)), "{dither:?}");
+        }
+    }
+
+    #[test]
+    fn other_targets_get_remapped_pixels() {
+        let output = apply_palette(
+            &gray_ramp_png(),
+            &BLACK_WHITE,
+            Dither::Ordered,
+            ImageFormat::Bmp,
+            None,
+        )
+        .unwrap();
+        let img = image::load_from_memory(&output).unwrap().into_rgba8();
+        assert!(img.pixels().all(|p| BLACK_WHITE.contains(&p.0)));
+    }
+
+    #[test]
+    fn invalid_palettes_are_rejected() {
+        assert!(matches!(
+            apply_palette(&gray_ramp_png(), &[], Dither::None, ImageFormat::Png, None),
+            Err(PaletteError::Quantize(QuantizeError::InvalidPaletteSize(0)))
+        ));
+        assert!(matches!(
+            apply_palette(&[0], &BLACK_WHITE, Dither::None, ImageFormat::Png, None),
+            Err(PaletteError::Decode(_))
+        ));
+    }
+}
@@ -0,0 +1,792 @@
+use std::io::Cursor;
+
+use image::{ImageDecoder, ImageReader};
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::png_optimize::{self, ChunkPolicy, PngOptimizeError, PNG_SIGNATURE};
+use crate::transforms::Transform;
+
+/// Chunks tied to the source's pixel layout or animation. The encoder writes its own
+/// (or none), so copying them would describe pixels that no longer exist.
+const LAYOUT_CHUNKS: [[u8; 4]; 7] = [
+    *b"tRNS", *b"sBIT", *b"bKGD", *b"hIST", *b"acTL", *b"fcTL", *b"fdAT",
+];
+
+/// Offset just past `IHDR`, which is always the first chunk: signature (8),
+/// length and type (8), 13-byte payload, CRC (4).
+const AFTER_IHDR: usize = 33;
+
+/// Keyword written in `iCCP` chunks built from non-PNG sources.
+const ICC_PROFILE_NAME: &[u8] = b"ICC Profile";
+
+/// Chunks that describe the color space of the pixels.
+const COLOR_CHUNKS: [[u8; 4]; 4] = [*b"iCCP", *b"sRGB", *b"gAMA", *b"cHRM"];
+
+/// `gAMA` payload for sRGB: 1/2.2 scaled by 100000.
+const SRGB_GAMA: [u8; 4] = 45455u32.to_be_bytes();
+
+/// `cHRM` payload for sRGB (D65 white point and Rec. 709 primaries, scaled by 100000).
+const SRGB_CHRM: [u32; 8] = [31270, 32900, 64000, 33000, 30000, 60000, 15000, 6000];
+
+/// How PNG output is tagged with color-space information.
+///
+/// Exported to JS as a numeric enum whose values follow declaration order.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ColorTag {
+    /// Keep the source's color chunks (`sRGB`, `iCCP`, `gAMA`, `cHRM`, or an embedded
+    /// ICC profile from other formats), and tag untagged sources as sRGB.
+    #[default]
+    Auto,
+    /// Tag the output as sRGB, replacing any source color chunks. Pixels are not
+    /// converted.
+    Srgb,
+    /// Write no color chunks at all.
+    Untagged,
+}
+
+impl ColorTag {
+    /// Every mode, in declaration order.
+    pub const ALL: [Self; 3] = [Self::Auto, Self::Srgb, Self::Untagged];
+
+    /// Parses a color tag mode.
+    ///
+    /// Accepts `"auto"`, `"srgb"` or `"none"`. An empty string selects `"auto"`.
+    ///
+    /// Returns an error if the string is not a recognized mode.
+    pub fn from_name(name: &str) -> Result<Self, PngChunkError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "auto" => Ok(Self::Auto),
+            "srgb" => Ok(Self::Srgb),
+            "none" => Ok(Self::Untagged),
+            _ => Err(PngChunkError::UnknownColorTag(name.to_owned())),
+        }
+    }
+}
+
+/// The color model of PNG pixels. An `iCCP` profile is only valid for the model it
+/// was written for: a `GRAY` profile for grayscale images, an `RGB` profile for
+/// truecolor and palette images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorModel {
+    Gray,
+    Rgb,
+}
+
+impl ColorModel {
+    /// The color model of an encoded PNG, from the color type in its `IHDR`. Returns
+    /// `None` if `png` is not a PNG.
+    pub fn of_png(png: &[u8]) -> Option<Self> {
+        if !png.starts_with(&PNG_SIGNATURE) {
+            return None;
+        }
+        // Color type is the tenth byte of the IHDR payload, which starts at offset 16.
+        match png.get(25)? {
+            0 | 4 => Some(Self::Gray),
+            2 | 3 | 6 => Some(Self::Rgb),
+            _ => None,
+        }
+    }
+
+    /// The data color space an `iCCP` chunk's profile declares, if it is one a PNG
+    /// can use. CMYK, Lab and other profiles, and unreadable ones, give `None`.
+    fn of_iccp(data: &[u8]) -> Option<Self> {
+        // Keyword, null separator, compression method, then the zlib stream. Only the
+        // header up to the color space field (bytes 16..20) is inflated.
+        let keyword_end = data.iter().position(|&b| b == 0)?;
+        let compressed = data.get(keyword_end + 2..)?;
+        let header = miniz_oxide::inflate::decompress_to_vec_zlib_with_limit(compressed, 20)
+            .unwrap_or_else(|partial| partial.output);
+        match header.get(16..20)? {
+            b"GRAY" => Some(Self::Gray),
+            b"RGB " => Some(Self::Rgb),
+            _ => None,
+        }
+    }
+}
+
+/// Which ancillary chunks a conversion to PNG carries over from the source.
+///
+/// Starts from a preset and applies per-chunk overrides. The default strips
+/// everything, which is what the encoder alone produces.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PngChunkPolicy {
+    preset: ChunkPolicy,
+    keep: Vec<[u8; 4]>,
+    strip: Vec<[u8; 4]>,
+}
+
+impl Default for PngChunkPolicy {
+    fn default() -> Self {
+        Self {
+            preset: ChunkPolicy::StripAll,
+            keep: Vec::new(),
+            strip: Vec::new(),
+        }
+    }
+}
+
+impl PngChunkPolicy {
+    /// Parses a comma-separated policy such as `"safe,+tEXt,-pHYs"`.
+    ///
+    /// A bare word picks the preset: `"keep"` (all chunks), `"safe"` (color management
+    /// and physical size) or `"strip"` (none). `+name` keeps and `-name` strips one
+    /// chunk type, overriding the preset. Chunk names are case-sensitive. An empty
+    /// string strips everything.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for an unknown preset, a malformed chunk name, or a critical
+    /// chunk (these are always written by the encoder).
+    pub fn parse(spec: &str) -> Result<Self, PngChunkError> {
+        let mut policy = Self::default();
+        for token in spec.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            if let Some(name) = token.strip_prefix('+') {
+                policy.keep.push(parse_chunk_name(name)?);
+            } else if let Some(name) = token.strip_prefix('-') {
+                policy.strip.push(parse_chunk_name(name)?);
+            } else {
+                policy.preset = ChunkPolicy::from_name(token)
+                    .map_err(|_| PngChunkError::UnknownPreset(token.to_owned()))?;
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Whether a source chunk of this type is written to the output.
+    pub fn keeps(&self, kind: [u8; 4]) -> bool {
+        if LAYOUT_CHUNKS.contains(&kind) || self.strip.contains(&kind) {
+            false
+        } else {
+            self.keep.contains(&kind) || self.preset.keeps(kind)
+        }
+    }
+
+    fn strips_explicitly(&self, kind: [u8; 4]) -> bool {
+        self.strip.contains(&kind)
+    }
+
+    fn keeps_nothing(&self) -> bool {
+        self.preset == ChunkPolicy::StripAll && self.keep.is_empty()
+    }
+}
+
+/// An ancillary chunk to write into the converted PNG.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CarriedChunk {
+    pub kind: [u8; 4],
+    pub data: Vec<u8>,
+}
+
+/// Collects the source chunks the policy keeps, rewritten for the output.
+///
+/// PNG sources contribute their ancillary chunks as-is, except `pHYs`, whose axes
+/// are swapped when the transforms rotate by 90 or 270 degrees, and `iCCP`, which
+/// is dropped when a grayscale transform leaves its RGB profile behind. Other formats
+/// contribute an `iCCP` chunk for an embedded ICC profile and an `eXIf` chunk for
+/// EXIF data, when the decoder exposes them.
+///
+/// Metadata is best effort: anything that can't be read is skipped, and a source
+/// that fails to decode is reported by the conversion itself.
+pub fn carried_chunks(
+    input: &[u8],
+    policy: &PngChunkPolicy,
+    transforms_list: &[Transform],
+) -> Vec<CarriedChunk> {
+    if policy.keeps_nothing() {
+        return Vec::new();
+    }
+
+    let chunks = if input.starts_with(&PNG_SIGNATURE) {
+        png_source_chunks(input)
+    } else {
+        decoder_chunks(input)
+    };
+    let transposed = transforms_list
+        .iter()
+        .filter(|t| matches!(t, Transform::Rotate90 | Transform::Rotate270))
+        .count()
+        % 2
+        == 1;
+    let grayscale = transforms_list.contains(&Transform::Grayscale);
+    let fits_output = |chunk: &CarriedChunk| {
+        !grayscale
+            || &chunk.kind != b"iCCP"
+            || ColorModel::of_iccp(&chunk.data) == Some(ColorModel::Gray)
+    };
+
+    chunks
+        .into_iter()
+        .filter(|chunk| policy.keeps(chunk.kind) && fits_output(chunk))
+        .map(|chunk| {
+            if transposed && &chunk.kind == b"pHYs" {
+                swap_phys_axes(chunk)
+            } else {
+                chunk
+            }
+        })
+        .collect()
+}
+
+/// Makes the carried chunks tag the output's color space as `tag` asks.
+///
+/// With [`ColorTag::Auto`], chunks the policy already carried are left alone.
+/// Otherwise the source's own color information is added even though the preset
+/// dropped it, because it changes how the pixels render: an `sRGB` chunk (written
+/// with the recommended `gAMA`/`cHRM` fallbacks), else `iCCP`/`gAMA`/`cHRM` as the
+/// source has them, else an embedded ICC profile from a non-PNG source. Untagged
+/// sources are tagged as sRGB, which is what browsers assume for them anyway. Chunk
+/// types the policy strips explicitly (`-name`) are never added.
+///
+/// The source's profile may not fit the encoded output; [`match_color_model`] checks
+/// it once the output's color type is known.
+pub fn apply_color_tag(
+    carried: &mut Vec<CarriedChunk>,
+    tag: ColorTag,
+    policy: &PngChunkPolicy,
+    input: &[u8],
+) {
+    let is_color = |chunk: &CarriedChunk| COLOR_CHUNKS.contains(&chunk.kind);
+    let added = match tag {
+        ColorTag::Untagged => {
+            carried.retain(|chunk| !is_color(chunk));
+            return;
+        }
+        ColorTag::Srgb => {
+            carried.retain(|chunk| !is_color(chunk));
+            srgb_chunks(0)
+        }
+        ColorTag::Auto => {
+            if carried.iter().any(is_color) {
+                return;
+            }
+            source_color_chunks(input)
+        }
+    };
+
+    let added = added
+        .into_iter()
+        .filter(|chunk| !policy.strips_explicitly(chunk.kind));
+    carried.splice(0..0, added);
+}
+
+/// Drops an `iCCP` chunk whose profile doesn't describe `output`, such as an RGB or
+/// CMYK profile on a grayscale PNG, since decoders reject or misapply it. When that
+/// leaves no `iCCP`, `sRGB` or `gAMA` chunk, the output is tagged as sRGB instead (leaving out
+/// chunk types the policy strips explicitly), as for an untagged source.
+pub fn match_color_model(
+    carried: &mut Vec<CarriedChunk>,
+    output: ColorModel,
+    policy: &PngChunkPolicy,
+) {
+    let before = carried.len();
+    carried
+        .retain(|chunk| &chunk.kind != b"iCCP" || ColorModel::of_iccp(&chunk.data) == Some(output));
+    if carried.len() == before
+        || carried
+            .iter()
+            .any(|chunk| [*b"iCCP", *b"sRGB", *b"gAMA"].contains(&chunk.kind))
+    {
+        return;
+    }
+
+    carried.retain(|chunk| !COLOR_CHUNKS.contains(&chunk.kind));
+    let fallback = srgb_chunks(0)
+        .into_iter()
+        .filter(|chunk| !policy.strips_explicitly(chunk.kind));
+    carried.splice(0..0, fallback);
+}
+
+/// The `sRGB` chunk with the given rendering intent, plus its `gAMA` and `cHRM`
+/// fallbacks for decoders that don't understand `sRGB`.
+fn srgb_chunks(intent: u8) -> Vec<CarriedChunk> {
+    vec![
+        CarriedChunk {
+            kind: *b"sRGB",
+            data: vec![intent],
+        },
+        CarriedChunk {
+            kind: *b"gAMA",
+            data: SRGB_GAMA.to_vec(),
+        },
+        CarriedChunk {
+            kind: *b"cHRM",
+            data: SRGB_CHRM.iter().flat_map(|v| v.to_be_bytes()).collect(),
+        },
+    ]
+}
+
+/// The source's color information as PNG chunks, or an sRGB tag if it has none.
+fn source_color_chunks(input: &[u8]) -> Vec<CarriedChunk> {
+    let source = if input.starts_with(&PNG_SIGNATURE) {
+        png_source_chunks(input)
+    } else {
+        decoder_chunks(input)
+    };
+    let mut color: Vec<CarriedChunk> = source
+        .into_iter()
+        .filter(|chunk| COLOR_CHUNKS.contains(&chunk.kind))
+        .collect();
+
+    if let Some(srgb) = color.iter().find(|chunk| &chunk.kind == b"sRGB") {
+        let intent = srgb.data.first().copied().unwrap_or(0);
+        return srgb_chunks(intent);
+    }
+    if color.is_empty() {
+        color = srgb_chunks(0);
+    }
+    color
+}
+
+/// Writes `chunks` into an encoded PNG, directly after `IHDR`. That position is valid
+/// for every ancillary chunk type, including those that must precede `PLTE`.
+///
+/// # Errors
+///
+/// Returns an error if `png` is not a PNG or a chunk is too large.
+pub fn insert_chunks(png: &mut Vec<u8>, chunks: &[CarriedChunk]) -> Result<(), PngChunkError> {
+    if chunks.is_empty() {
+        return Ok(());
+    }
+    if !png.starts_with(&PNG_SIGNATURE) || png.len() < AFTER_IHDR {
+        return Err(PngChunkError::Write(PngOptimizeError::NotPng));
+    }
+
+    let mut bytes = Vec::new();
+    for chunk in chunks {
+        png_optimize::write_chunk(&mut bytes, chunk.kind, &chunk.data)
+            .map_err(PngChunkError::Write)?;
+    }
+    png.splice(AFTER_IHDR..AFTER_IHDR, bytes);
+    Ok(())
+}
+
+fn png_source_chunks(input: &[u8]) -> Vec<CarriedChunk> {
+    png_optimize::read_chunks(input)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|chunk| chunk.kind[0].is_ascii_lowercase())
+        .map(|chunk| CarriedChunk {
+            kind: chunk.kind,
+            data: chunk.data.to_vec(),
+        })
+        .collect()
+}
+
+fn decoder_chunks(input: &[u8]) -> Vec<CarriedChunk> {
+    let Some(mut decoder) = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .ok()
+        .and_then(|reader| reader.into_decoder().ok())
+    else {
+        return Vec::new();
+    };
+
+    let mut chunks = Vec::new();
+    if let Some(profile) = decoder.icc_profile().ok().flatten() {
+        // Keyword, null separator, compression method 0 (zlib), compressed profile.
+        let mut data = ICC_PROFILE_NAME.to_vec();
+        data.extend_from_slice(&[0, 0]);
+        data.extend(miniz_oxide::deflate::compress_to_vec_zlib(&profile, 9));
+        chunks.push(CarriedChunk {
+            kind: *b"iCCP",
+            data,
+        });
+    }
+    if let Some(exif) = decoder.exif_metadata().ok().flatten() {
+        // eXIf holds the bare TIFF structure, without JPEG's "Exif\0\0" prefix.
+        let data = exif.strip_prefix(b"Exif\0\0").unwrap_or(&exif).to_vec();
+        chunks.push(CarriedChunk {
+            kind: *b"eXIf",
+            data,
+        });
+    }
+    chunks
+}
+
+/// Swaps the X and Y pixels-per-unit fields of a `pHYs` chunk.
+fn swap_phys_axes(chunk: CarriedChunk) -> CarriedChunk {
+    let (x, rest) = chunk.data.split_at(chunk.data.len().min(4));
+    let (y, unit) = rest.split_at(rest.len().min(4));
+    if x.len() != 4 || y.len() != 4 {
+        return chunk;
+    }
+    let data = [y, x, unit].concat();
+    CarriedChunk { data, ..chunk }
+}
+
+fn parse_chunk_name(name: &str) -> Result<[u8; 4], PngChunkError> {
+    let kind: [u8; 4] = name
+        .as_bytes()
+        .try_into()
+        .map_err(|_| PngChunkError::InvalidChunkName(name.to_owned()))?;
+    if !kind.iter().all(u8::is_ascii_alphabetic) {
+        return Err(PngChunkError::InvalidChunkName(name.to_owned()));
+    }
+    if kind[0].is_ascii_uppercase() {
+        return Err(PngChunkError::CriticalChunk(name.to_owned()));
+    }
+    Ok(kind)
+}
+
+/// Errors that can occur while parsing a chunk policy or writing chunks.
+#[derive(Debug)]
+pub enum PngChunkError {
+    /// The preset name was not `keep`, `safe` or `strip`.
+    UnknownPreset(String),
+    /// A chunk name is not four ASCII letters.
+    InvalidChunkName(String),
+    /// Critical chunks are always written by the encoder and can't be configured.
+    CriticalChunk(String),
+    /// The color tag mode was not `auto`, `srgb` or `none`.
+    UnknownColorTag(String),
+    /// The chunks could not be written into the output.
+    Write(PngOptimizeError),
+}
+
+impl std::fmt::Display for PngChunkError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UnknownPreset(name) => write!(
+                f,
+                "Unknown PNG chunk preset \"{name}\" (expected keep, safe or strip)"
+            ),
+            Self::InvalidChunkName(name) => {
+                write!(f, "\"{name}\" is not a valid PNG chunk name")
+            }
+            Self::CriticalChunk(name) => {
+                write!(f, "Critical PNG chunk {name} cannot be kept or stripped")
+            }
+            Self::UnknownColorTag(name) => write!(
+                f,
+                "Unknown color tag \"{name}\" (expected auto, srgb or none)"
+            ),
+            Self::Write(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for PngChunkError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ===== Fixture Helpers =====
+
+    fn png_with_chunks(extra: &[(&[u8; 4], &[u8])]) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, 2, 1);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().unwrap();
+        for (kind, payload) in extra {
+            writer
+                .write_chunk(png::chunk::ChunkType(**kind), payload)
+                .unwrap();
+        }
+        writer.write_image_data(&[1, 2, 3, 4, 5, 6]).unwrap();
+        writer.finish().unwrap();
+        buf
+    }
+
+    fn kinds(chunks: &[CarriedChunk]) -> Vec<[u8; 4]> {
+        chunks.iter().map(|c| c.kind).collect()
+    }
+
+    const PHYS: &[u8] = &[0, 0, 0x0B, 0x13, 0, 0, 0x16, 0x26, 1];
+
+    // ===== Policy Parsing Tests =====
+
+    #[test]
+    fn parse_presets_and_overrides() {
+        let policy = PngChunkPolicy::parse("safe, +tEXt, -pHYs").unwrap();
+        assert!(policy.keeps(*b"gAMA"));
+        assert!(policy.keeps(*b"tEXt"));
+        assert!(!policy.keeps(*b"pHYs"));
+        assert!(!policy.keeps(*b"zTXt"));
+
+        let policy = PngChunkPolicy::parse("+iCCP").unwrap();
+        assert!(policy.keeps(*b"iCCP"));
+        assert!(!policy.keeps(*b"gAMA"));
+
+        assert_eq!(
+            PngChunkPolicy::parse("").unwrap(),
+            PngChunkPolicy::default()
+        );
+        assert!(PngChunkPolicy::parse("keep").unwrap().keeps(*b"tIME"));
+    }
+
+    #[test]
+    fn layout_chunks_are_never_kept() {
+        let policy = PngChunkPolicy::parse("keep,+bKGD,+tRNS").unwrap();
+        assert!(!policy.keeps(*b"bKGD"));
+        assert!(!policy.keeps(*b"tRNS"));
+        assert!(!policy.keeps(*b"acTL"));
+    }
+
+    #[test]
+    fn parse_rejects_bad_names() {
+        assert!(matches!(
+            PngChunkPolicy::parse("everything"),
+            Err(PngChunkError::UnknownPreset(_))
+        ));
+        assert!(matches!(
+            PngChunkPolicy::parse("+text!"),
+            Err(PngChunkError::InvalidChunkName(_))
+        ));
+        assert!(matches!(
+            PngChunkPolicy::parse("-PLTE"),
+            Err(PngChunkError::CriticalChunk(_))
+        ));
+    }
+
+    // ===== Carry-Over Tests =====
+
+    #[test]
+    fn carries_png_chunks_per_policy() {
+        let input = png_with_chunks(&[
+            (b"gAMA", &[0, 0, 0xB1, 0x8F]),
+            (b"tEXt", b"Title\0Hello"),
+            (b"pHYs", PHYS),
+        ]);
+        let safe = carried_chunks(&input, &PngChunkPolicy::parse("safe").unwrap(), &[]);
+        assert_eq!(kinds(&safe), vec![*b"gAMA", *b"pHYs"]);
+
+        let all = carried_chunks(&input, &PngChunkPolicy::parse("keep").unwrap(), &[]);
+        assert_eq!(kinds(&all), vec![*b"gAMA", *b"tEXt", *b"pHYs"]);
+        assert_eq!(all[1].data, b"Title\0Hello");
+
+        assert!(carried_chunks(&input, &PngChunkPolicy::default(), &[]).is_empty());
+    }
+
+    #[test]
+    fn rotation_swaps_phys_axes() {
+        let input = png_with_chunks(&[(b"pHYs", PHYS)]);
+        let policy = PngChunkPolicy::parse("+pHYs").unwrap();
+
+        let rotated = carried_chunks(&input, &policy, &[Transform::Rotate90]);
+        assert_eq!(rotated[0].data, [0, 0, 0x16, 0x26, 0, 0, 0x0B, 0x13, 1]);
+
+        let twice = carried_chunks(
+            &input,
+            &policy,
+            &[Transform::Rotate90, Transform::Rotate270],
+        );
+        assert_eq!(twice[0].data, PHYS);
+
+        let flipped = carried_chunks(&input, &policy, &[Transform::Rotate180]);
+        assert_eq!(flipped[0].data, PHYS);
+    }
+
+    #[test]
+    fn grayscale_transform_drops_rgb_profiles() {
+        let policy = PngChunkPolicy::parse("safe").unwrap();
+        for (space, kept) in [(*b"RGB ", false), (*b"GRAY", true)] {
+            let profile = iccp(space);
+            let input = png_with_chunks(&[(b"iCCP", &profile.data), (b"pHYs", PHYS)]);
+            assert_eq!(kinds(&carried_chunks(&input, &policy, &[]))[0], *b"iCCP");
+
+            let gray = carried_chunks(&input, &policy, &[Transform::Grayscale]);
+            assert_eq!(gray.contains(&profile), kept);
+            assert!(kinds(&gray).contains(b"pHYs"));
+        }
+    }
+
+    #[test]
+    fn jpeg_sources_carry_nothing_without_metadata() {
+        let img = image::RgbImage::new(4, 4);
+        let mut jpeg = Vec::new();
+        img.write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        let policy = PngChunkPolicy::parse("keep").unwrap();
+        assert!(carried_chunks(&jpeg, &policy, &[]).is_empty());
+    }
+
+    // ===== Color Tag Tests =====
+
+    fn tagged(input: &[u8], policy: &str, tag: ColorTag) -> Vec<[u8; 4]> {
+        let policy = PngChunkPolicy::parse(policy).unwrap();
+        let mut carried = carried_chunks(input, &policy, &[]);
+        apply_color_tag(&mut carried, tag, &policy, input);
+        kinds(&carried)
+    }
+
+    #[test]
+    fn auto_tags_untagged_sources_as_srgb() {
+        let input = png_with_chunks(&[(b"tEXt", b"a\0b")]);
+        assert_eq!(
+            tagged(&input, "", ColorTag::Auto),
+            vec![*b"sRGB", *b"gAMA", *b"cHRM"]
+        );
+    }
+
+    #[test]
+    fn auto_keeps_source_color_chunks_despite_strip_preset() {
+        let gamma_only = png_with_chunks(&[(b"gAMA", &[0, 1, 0x86, 0xA0])]);
+        assert_eq!(tagged(&gamma_only, "strip", ColorTag::Auto), vec![*b"gAMA"]);
+
+        let srgb = png_with_chunks(&[(b"sRGB", &[1])]);
+        let policy = PngChunkPolicy::default();
+        let mut carried = Vec::new();
+        apply_color_tag(&mut carried, ColorTag::Auto, &policy, &srgb);
+        assert_eq!(carried[0].data, vec![1], "rendering intent is preserved");
+        assert_eq!(kinds(&carried), vec![*b"sRGB", *b"gAMA", *b"cHRM"]);
+    }
+
+    #[test]
+    fn auto_leaves_policy_carried_color_chunks() {
+        let input = png_with_chunks(&[(b"gAMA", &[0, 1, 0x86, 0xA0]), (b"pHYs", PHYS)]);
+        assert_eq!(
+            tagged(&input, "safe", ColorTag::Auto),
+            vec![*b"gAMA", *b"pHYs"]
+        );
+    }
+
+    fn iccp(space: [u8; 4]) -> CarriedChunk {
+        let mut profile = vec![0; 128];
+        profile[16..20].copy_from_slice(&space);
+        let mut data = b"icc\0\0".to_vec();
+        data.extend(miniz_oxide::deflate::compress_to_vec_zlib(&profile, 6));
+        CarriedChunk {
+            kind: *b"iCCP",
+            data,
+        }
+    }
+
+    #[test]
+    fn color_model_of_png_follows_color_type() {
+        let rgb = png_with_chunks(&[]);
+        assert_eq!(ColorModel::of_png(&rgb), Some(ColorModel::Rgb));
+
+        let mut gray = Vec::new();
+        image::GrayImage::new(2, 2)
+            .write_to(&mut Cursor::new(&mut gray), image::ImageFormat::Png)
+            .unwrap();
+        assert_eq!(ColorModel::of_png(&gray), Some(ColorModel::Gray));
+        assert_eq!(ColorModel::of_png(b"GIF89a"), None);
+    }
+
+    #[test]
+    fn mismatched_profiles_fall_back_to_srgb() {
+        let policy = PngChunkPolicy::default();
+        let mut carried = vec![iccp(*b"RGB ")];
+        match_color_model(&mut carried, ColorModel::Rgb, &policy);
+        assert_eq!(kinds(&carried), vec![*b"iCCP"]);
+
+        match_color_model(&mut carried, ColorModel::Gray, &policy);
+        assert_eq!(kinds(&carried), vec![*b"sRGB", *b"gAMA", *b"cHRM"]);
+
+        let mut carried = vec![iccp(*b"CMYK"), iccp(*b"GRAY")];
+        match_color_model(&mut carried, ColorModel::Gray, &policy);
+        assert_eq!(carried, vec![iccp(*b"GRAY")]);
+
+        let mut carried = vec![
+            iccp(*b"CMYK"),
+            CarriedChunk {
+                kind: *b"gAMA",
+                data: SRGB_GAMA.to_vec(),
+            },
+        ];
+        match_color_model(&mut carried, ColorModel::Rgb, &policy);
+        assert_eq!(kinds(&carried), vec![*b"gAMA"]);
+    }
+
+    #[test]
+    fn explicit_strips_are_respected() {
+        let input = png_with_chunks(&[]);
+        assert_eq!(
+            tagged(&input, "-cHRM", ColorTag::Auto),
+            vec![*b"sRGB", *b"gAMA"]
+        );
+        assert_eq!(
+            tagged(&input, "-sRGB", ColorTag::Srgb),
+            vec![*b"gAMA", *b"cHRM"]
+        );
+    }
+
+    #[test]
+    fn srgb_and_untagged_replace_source_tags() {
+        let input = png_with_chunks(&[(b"gAMA", &[0, 1, 0x86, 0xA0]), (b"tEXt", b"a\0b")]);
+        assert_eq!(
+            tagged(&input, "keep", ColorTag::Srgb),
+            vec![*b"sRGB", *b"gAMA", *b"cHRM", *b"tEXt"]
+        );
+        assert_eq!(tagged(&input, "keep", ColorTag::Untagged), vec![*b"tEXt"]);
+    }
+
+    #[test]
+    fn srgb_chunks_decode_as_srgb() {
+        let mut output = png_with_chunks(&[]);
+        insert_chunks(&mut output, &srgb_chunks(0)).unwrap();
+        let reader = png::Decoder::new(Cursor::new(&output)).read_info().unwrap();
+        let info = reader.info();
+        assert!(info.srgb.is_some());
+        assert_eq!(info.gama_chunk.unwrap().into_scaled(), 45455);
+        assert_eq!(info.chrm_chunk.unwrap().white.0.into_scaled(), 31270);
+    }
+
+    #[test]
+    fn color_tags_follow_js_values() {
+        for (index, value) in ColorTag::ALL.into_iter().enumerate() {
+            // Safe: reading a fieldless enum's discriminant, which is its JS value.
+            #[allow(clippy::as_conversions)]
+            let discriminant = value as usize;
+            assert_eq!(discriminant, index);
+        }
+    }
+
+    #[test]
+    fn color_tag_from_name() {
+        assert_eq!(ColorTag::from_name("").unwrap(), ColorTag::Auto);
+        assert_eq!(ColorTag::from_name("sRGB").unwrap(), ColorTag::Srgb);
+        assert_eq!(ColorTag::from_name("none").unwrap(), ColorTag::Untagged);
+        assert!(matches!(
+            ColorTag::from_name("p3"),
+            Err(PngChunkError::UnknownColorTag(_))
+        ));
+    }
+
+    // ===== Insertion Tests =====
+
+    #[test]
+    fn inserted_chunks_follow_ihdr_and_decode() {
+        let mut output = png_with_chunks(&[]);
+        let chunks = vec![
+            CarriedChunk {
+                kind: *b"tEXt",
+                data: b"Author\0Someone".to_vec(),
+            },
+            CarriedChunk {
+                kind: *b"pHYs",
+                data: PHYS.to_vec(),
+            },
+        ];
+        insert_chunks(&mut output, &chunks).unwrap();
+
+        let order: Vec<[u8; 4]> = png_optimize::read_chunks(&output)
+            .unwrap()
+            .iter()
+            .map(|c| c.kind)
+            .collect();
+        assert_eq!(
+            order,
+            vec![*b"IHDR", *b"tEXt", *b"pHYs", *b"IDAT", *b"IEND"]
+        );
+
+        let reader = png::Decoder::new(Cursor::new(&output)).read_info().unwrap();
+        let info = reader.info();
+        assert_eq!(info.uncompressed_latin1_text[0].keyword, "Author");
+        assert_eq!(info.pixel_dims.unwrap().xppu, 0x0B13);
+    }
+
+    #[test]
+    fn insert_rejects_non_png() {
+        let mut output = b"not a png".to_vec();
+        let chunk = CarriedChunk {
+            kind: *b"tEXt",
+            data: Vec::new(),
+        };
+        assert!(insert_chunks(&mut output, &[chunk]).is_err());
+    }
+}
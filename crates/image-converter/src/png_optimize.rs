@@ -0,0 +1,841 @@
+use std::io::Cursor;
+
+use serde::Serialize;
+
+/// The eight-byte signature every PNG file starts with.
+pub const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1A, b'\n'];
+
+/// Highest optimization level accepted by [`optimize_png`].
+pub const MAX_LEVEL: u8 = 3;
+
+/// Row filter strategy tried at level 1.
+const FAST_FILTERS: [(png::Filter, &str); 1] = [(png::Filter::Adaptive, "adaptive")];
+
+/// Row filter strategies tried at level 2 and above, with their report names.
+const ALL_FILTERS: [(png::Filter, &str); 7] = [
+    (png::Filter::NoFilter, "none"),
+    (png::Filter::Sub, "sub"),
+    (png::Filter::Up, "up"),
+    (png::Filter::Avg, "average"),
+    (png::Filter::Paeth, "paeth"),
+    (png::Filter::Adaptive, "adaptive"),
+    (png::Filter::MinEntropy, "min-entropy"),
+];
+
+/// Ancillary chunks that change how pixels are displayed (color space, gamma,
+/// physical size) and are kept by [`ChunkPolicy::KeepSafe`].
+const RENDERING_CHUNKS: [[u8; 4]; 9] = [
+    *b"gAMA", *b"cHRM", *b"sRGB", *b"iCCP", *b"sBIT", *b"pHYs", *b"cICP", *b"mDCV", *b"cLLI",
+];
+
+/// Which ancillary chunks survive optimization.
+///
+/// `tRNS` is always kept because it defines transparency, not metadata.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkPolicy {
+    /// Keep every chunk.
+    KeepAll,
+    /// Keep color-management and physical-size chunks; drop text, time, EXIF and
+    /// anything unknown.
+    KeepSafe,
+    /// Drop every ancillary chunk except `tRNS`.
+    StripAll,
+}
+
+impl ChunkPolicy {
+    /// Parses a chunk policy name.
+    ///
+    /// Accepts `"keep"`, `"safe"` or `"strip"`. An empty string selects `"safe"`.
+    ///
+    /// Returns an error if the string is not a recognized policy.
+    pub fn from_name(name: &str) -> Result<Self, PngOptimizeError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "keep" => Ok(Self::KeepAll),
+            "" | "safe" => Ok(Self::KeepSafe),
+            "strip" => Ok(Self::StripAll),
+            _ => Err(PngOptimizeError::UnknownChunkPolicy(name.to_owned())),
+        }
+    }
+
+    /// Whether an ancillary chunk of this type is kept.
+    pub fn keeps(self, kind: [u8; 4]) -> bool {
+        match self {
+            Self::KeepAll => true,
+            Self::KeepSafe => &kind == b"tRNS" || RENDERING_CHUNKS.contains(&kind),
+            Self::StripAll => &kind == b"tRNS",
+        }
+    }
+}
+
+/// What [`optimize_png`] did to the file.
+#[derive(Debug, Clone, Serialize)]
+pub struct PngOptimizeReport {
+    pub original_size: usize,
+    pub optimized_size: usize,
+    /// Winning row filter strategy, or `"original"` if the input's image data was kept.
+    pub filter: &'static str,
+    /// Whether the image data was recompressed with Zopfli.
+    pub zopfli: bool,
+    /// Whether the color type or bit depth was reduced.
+    pub reduced: bool,
+    /// Types of the ancillary chunks that were dropped, in file order.
+    pub removed_chunks: Vec<String>,
+}
+
+/// Result of [`optimize_png`].
+#[derive(Debug, Clone)]
+pub struct OptimizedPng {
+    pub data: Vec<u8>,
+    pub report: PngOptimizeReport,
+}
+
+/// Losslessly shrinks a PNG, in the spirit of `oxipng`.
+///
+/// Every level first tries pixel-preserving reductions (16-bit samples that fit in
+/// 8 bits, a fully opaque alpha channel, RGB where every pixel is gray), then
+/// re-deflates the image data and keeps whichever encoding is smallest:
+///
+/// - level 1: one adaptive-filter pass at deflate level 9,
+/// - level 2: every row filter strategy at deflate level 9,
+/// - level 3: level 2, then the winning filter recompressed with Zopfli (slow).
+///
+/// The original image data is always a candidate, so the result is never larger
+/// than the input apart from chunks the policy removes. Interlaced images are written
+/// non-interlaced unless the original data wins. Decoded pixels are bit-identical.
+///
+/// `sBIT` and `bKGD` are dropped whenever the color type or bit depth is reduced, and
+/// `iCCP` when RGB becomes grayscale, since its RGB profile no longer applies.
+///
+/// # Errors
+///
+/// Returns an error if the level is out of range, the input is not a PNG, it is
+/// animated, or it fails to decode.
+pub fn optimize_png(
+    input: &[u8],
+    level: u8,
+    policy: ChunkPolicy,
+) -> Result<OptimizedPng, PngOptimizeError> {
+    if level == 0 || level > MAX_LEVEL {
+        return Err(PngOptimizeError::InvalidLevel(level));
+    }
+
+    let chunks = read_chunks(input)?;
+    if chunks.iter().any(|chunk| &chunk.kind == b"acTL") {
+        return Err(PngOptimizeError::Animated);
+    }
+    let original_ihdr = chunks
+        .iter()
+        .find(|chunk| &chunk.kind == b"IHDR")
+        .map(|chunk| chunk.data.to_vec())
+        .ok_or_else(|| PngOptimizeError::Corrupt("missing IHDR chunk".to_owned()))?;
+    let original_idat: Vec<u8> = chunks
+        .iter()
+        .filter(|chunk| &chunk.kind == b"IDAT")
+        .flat_map(|chunk| chunk.data.iter().copied())
+        .collect();
+
+    let source_gray = is_gray(&original_ihdr);
+
+    let mut raw = RawImage::decode(input)?;
+    let reduced = raw.trns.is_none() && raw.reduce();
+
+    let filters: &[(png::Filter, &str)] = if level == 1 {
+        &FAST_FILTERS
+    } else {
+        &ALL_FILTERS
+    };
+    let mut best = Candidate {
+        ihdr: original_ihdr,
+        idat: original_idat,
+        filter: "original",
+        reduced: false,
+    };
+    for &(filter, name) in filters {
+        let idat = raw.deflate(filter)?;
+        if idat.len() < best.idat.len() {
+            best = Candidate {
+                ihdr: raw.ihdr(),
+                idat,
+                filter: name,
+                reduced,
+            };
+        }
+    }
+
+    let mut zopfli = false;
+    if level == MAX_LEVEL {
+        let recompressed = zopfli_recompress(&best.idat)?;
+        if recompressed.len() < best.idat.len() {
+            best.idat = recompressed;
+            zopfli = true;
+        }
+    }
+
+    let became_gray = is_gray(&best.ihdr) && !source_gray;
+
+    let mut removed_chunks = Vec::new();
+    let mut data = PNG_SIGNATURE.to_vec();
+    let mut idat_written = false;
+    for chunk in &chunks {
+        match &chunk.kind {
+            b"IHDR" => write_chunk(&mut data, *b"IHDR", &best.ihdr)?,
+            b"IDAT" => {
+                if !idat_written {
+                    write_chunk(&mut data, *b"IDAT", &best.idat)?;
+                    idat_written = true;
+                }
+            }
+            kind if is_critical(*kind) => write_chunk(&mut data, *kind, chunk.data)?,
+            // sBIT and bKGD are laid out per color type and bit depth, so they no
+            // longer apply once either changes.
+            kind if best.reduced && (kind == b"sBIT" || kind == b"bKGD") => {
+                removed_chunks.push(String::from_utf8_lossy(kind).into_owned());
+            }
+            // The source's profile is an RGB one, which grayscale PNGs can't use.
+            kind if became_gray && kind == b"iCCP" => {
+                removed_chunks.push(String::from_utf8_lossy(kind).into_owned());
+            }
+            kind if policy.keeps(*kind) => write_chunk(&mut data, *kind, chunk.data)?,
+            kind => removed_chunks.push(String::from_utf8_lossy(kind).into_owned()),
+        }
+    }
+
+    Ok(OptimizedPng {
+        report: PngOptimizeReport {
+            original_size: input.len(),
+            optimized_size: data.len(),
+            filter: best.filter,
+            zopfli,
+            reduced: best.reduced,
+            removed_chunks,
+        },
+        data,
+    })
+}
+
+/// One chunk of a PNG file, borrowed from the input.
+pub struct Chunk<'a> {
+    pub kind: [u8; 4],
+    pub data: &'a [u8],
+}
+
+/// Splits a PNG into its chunks, up to and including `IEND`. CRCs are not checked;
+/// the decoder does that.
+///
+/// # Errors
+///
+/// Returns `PngOptimizeError::NotPng` without the PNG signature and
+/// `PngOptimizeError::Corrupt` if a chunk runs past the end of the input.
+pub fn read_chunks(input: &[u8]) -> Result<Vec<Chunk<'_>>, PngOptimizeError> {
+    if !input.starts_with(&PNG_SIGNATURE) {
+        return Err(PngOptimizeError::NotPng);
+    }
+
+    let truncated = || PngOptimizeError::Corrupt("truncated chunk".to_owned());
+    let mut chunks = Vec::new();
+    let mut pos = PNG_SIGNATURE.len();
+    loop {
+        let header = input.get(pos..pos + 8).ok_or_else(truncated)?;
+        let (len_bytes, kind_bytes) = header.split_at(4);
+        let len = u32::from_be_bytes(len_bytes.try_into().map_err(|_| truncated())?);
+        let len = usize::try_from(len).map_err(|_| truncated())?;
+        let kind: [u8; 4] = kind_bytes.try_into().map_err(|_| truncated())?;
+        let data_start = pos + 8;
+        let data_end = data_start.checked_add(len).ok_or_else(truncated)?;
+        let data = input.get(data_start..data_end).ok_or_else(truncated)?;
+        chunks.push(Chunk { kind, data });
+        if &kind == b"IEND" {
+            return Ok(chunks);
+        }
+        pos = data_end + 4;
+    }
+}
+
+/// Appends a chunk with its length and CRC.
+///
+/// # Errors
+///
+/// Returns `PngOptimizeError::ChunkTooLarge` if `data` exceeds 4 GiB.
+pub fn write_chunk(out: &mut Vec<u8>, kind: [u8; 4], data: &[u8]) -> Result<(), PngOptimizeError> {
+    let len = u32::try_from(data.len()).map_err(|_| PngOptimizeError::ChunkTooLarge)?;
+    let mut crc = crc32fast::Hasher::new();
+    crc.update(&kind);
+    crc.update(data);
+    out.extend_from_slice(&len.to_be_bytes());
+    out.extend_from_slice(&kind);
+    out.extend_from_slice(data);
+    out.extend_from_slice(&crc.finalize().to_be_bytes());
+    Ok(())
+}
+
+/// Critical chunks have an uppercase first letter and are never dropped.
+/// Whether an `IHDR` payload's color type (byte 9) is grayscale, with or without alpha.
+fn is_gray(ihdr: &[u8]) -> bool {
+    matches!(ihdr.get(9), Some(0 | 4))
+}
+
+fn is_critical(kind: [u8; 4]) -> bool {
+    kind[0].is_ascii_uppercase()
+}
+
+/// Inflates a zlib stream and deflates it again with Zopfli.
+fn zopfli_recompress(idat: &[u8]) -> Result<Vec<u8>, PngOptimizeError> {
+    let filtered = miniz_oxide::inflate::decompress_to_vec_zlib(idat)
+        .map_err(|e| PngOptimizeError::Corrupt(format!("invalid image data: {e}")))?;
+    let mut out = Vec::new();
+    zopfli::compress(
+        zopfli::Options::default(),
+        zopfli::Format::Zlib,
+        filtered.as_slice(),
+        &mut out,
+    )
+    .map_err(PngOptimizeError::Zopfli)?;
+    Ok(out)
+}
+
+/// A candidate encoding: the header it needs and its zlib-compressed image data.
+struct Candidate {
+    ihdr: Vec<u8>,
+    idat: Vec<u8>,
+    filter: &'static str,
+    reduced: bool,
+}
+
+/// Decoded, unfiltered scanlines in the file's own color type and bit depth.
+struct RawImage {
+    width: u32,
+    height: u32,
+    color: png::ColorType,
+    depth: png::BitDepth,
+    palette: Option<Vec<u8>>,
+    trns: Option<Vec<u8>>,
+    /// Packed rows, big-endian for 16-bit samples, no filter bytes.
+    data: Vec<u8>,
+}
+
+impl RawImage {
+    fn decode(input: &[u8]) -> Result<Self, PngOptimizeError> {
+        let mut decoder = png::Decoder::new(Cursor::new(input));
+        decoder.set_transformations(png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info().map_err(PngOptimizeError::Decode)?;
+        let size = reader
+            .output_buffer_size()
+            .ok_or_else(|| PngOptimizeError::Corrupt("image is too large".to_owned()))?;
+        let mut data = vec![0; size];
+        let frame = reader
+            .next_frame(&mut data)
+            .map_err(PngOptimizeError::Decode)?;
+        data.truncate(frame.buffer_size());
+
+        let info = reader.info();
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            color: info.color_type,
+            depth: info.bit_depth,
+            palette: info.palette.as_ref().map(|p| p.to_vec()),
+            trns: info.trns.as_ref().map(|t| t.to_vec()),
+            data,
+        })
+    }
+
+    /// Applies every reduction that keeps pixels identical. Returns whether anything
+    /// changed. Callers skip this for images with `tRNS`, whose layout depends on
+    /// the color type and bit depth.
+    fn reduce(&mut self) -> bool {
+        if self.color == png::ColorType::Indexed
+            || matches!(
+                self.depth,
+                png::BitDepth::One | png::BitDepth::Two | png::BitDepth::Four
+            )
+        {
+            return false;
+        }
+        let mut changed = false;
+
+        if self.depth == png::BitDepth::Sixteen
+            && self
+                .data
+                .chunks_exact(2)
+                .all(|sample| matches!(sample, [hi, lo] if hi == lo))
+        {
+            self.data = self.data.iter().copied().step_by(2).collect();
+            self.depth = png::BitDepth::Eight;
+            changed = true;
+        }
+
+        let sample_bytes = if self.depth == png::BitDepth::Sixteen {
+            2
+        } else {
+            1
+        };
+        let pixel_bytes = sample_bytes * self.color.samples();
+
+        if matches!(
+            self.color,
+            png::ColorType::Rgba | png::ColorType::GrayscaleAlpha
+        ) && self
+            .data
+            .chunks_exact(pixel_bytes)
+            .all(|px| px.iter().rev().take(sample_bytes).all(|&b| b == 0xFF))
+        {
+            self.data = self
+                .data
+                .chunks_exact(pixel_bytes)
+                .flat_map(|px| px.iter().take(pixel_bytes - sample_bytes).copied())
+                .collect();
+            self.color = match self.color {
+                png::ColorType::Rgba => png::ColorType::Rgb,
+                _ => png::ColorType::Grayscale,
+            };
+            changed = true;
+        }
+
+        let pixel_bytes = sample_bytes * self.color.samples();
+        let is_gray = |px: &[u8]| {
+            let (r, rest) = px.split_at(sample_bytes);
+            let (g, rest) = rest.split_at(sample_bytes);
+            r == g && rest.starts_with(r)
+        };
+        if matches!(self.color, png::ColorType::Rgb | png::ColorType::Rgba)
+            && self.data.chunks_exact(pixel_bytes).all(is_gray)
+        {
+            self.data = self
+                .data
+                .chunks_exact(pixel_bytes)
+                .flat_map(|px| {
+                    let (r, rest) = px.split_at(sample_bytes);
+                    let (_, alpha) = rest.split_at(2 * sample_bytes);
+                    r.iter().chain(alpha).copied()
+                })
+                .collect();
+            self.color = match self.color {
+                png::ColorType::Rgba => png::ColorType::GrayscaleAlpha,
+                _ => png::ColorType::Grayscale,
+            };
+            changed = true;
+        }
+
+        changed
+    }
+
+    /// The 13-byte `IHDR` payload for this image, non-interlaced.
+    fn ihdr(&self) -> Vec<u8> {
+        let mut ihdr = Vec::with_capacity(13);
+        ihdr.extend_from_slice(&self.width.to_be_bytes());
+        ihdr.extend_from_slice(&self.height.to_be_bytes());
+        // Safe: both enums are `repr(u8)` with the PNG header codes as discriminants.
+        #[allow(clippy::as_conversions)]
+        ihdr.extend_from_slice(&[self.depth as u8, self.color as u8, 0, 0, 0]);
+        ihdr
+    }
+
+    /// Filters and compresses the scanlines, returning the zlib stream that goes in
+    /// `IDAT`.
+    fn deflate(&self, filter: png::Filter) -> Result<Vec<u8>, PngOptimizeError> {
+        let mut buf = Vec::new();
+        {
+            let mut encoder = png::Encoder::new(&mut buf, self.width, self.height);
+            encoder.set_color(self.color);
+            encoder.set_depth(self.depth);
+            if let Some(palette) = &self.palette {
+                encoder.set_palette(palette.as_slice());
+            }
+            if let Some(trns) = &self.trns {
+                encoder.set_trns(trns.as_slice());
+            }
+            encoder.set_deflate_compression(png::DeflateCompression::Level(9));
+            encoder.set_filter(filter);
+            let mut writer = encoder.write_header().map_err(PngOptimizeError::Encode)?;
+            writer
+                .write_image_data(&self.data)
+                .map_err(PngOptimizeError::Encode)?;
+            writer.finish().map_err(PngOptimizeError::Encode)?;
+        }
+
+        Ok(read_chunks(&buf)?
+            .iter()
+            .filter(|chunk| &chunk.kind == b"IDAT")
+            .flat_map(|chunk| chunk.data.iter().copied())
+            .collect())
+    }
+}
+
+/// Errors that can occur while optimizing a PNG.
+#[derive(Debug)]
+pub enum PngOptimizeError {
+    /// The input does not start with the PNG signature.
+    NotPng,
+    /// The chunk structure or image data is malformed.
+    Corrupt(String),
+    /// Animated PNGs are not supported.
+    Animated,
+    /// The optimization level is outside `1..=MAX_LEVEL`.
+    InvalidLevel(u8),
+    /// The chunk policy name was not recognized.
+    UnknownChunkPolicy(String),
+    /// A chunk is larger than the format allows.
+    ChunkTooLarge,
+    /// The PNG decoder rejected the input.
+    Decode(png::DecodingError),
+    /// The PNG encoder failed while trying a filter strategy.
+    Encode(png::EncodingError),
+    /// Zopfli failed to compress the image data.
+    Zopfli(std::io::Error),
+}
+
+impl std::fmt::Display for PngOptimizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotPng => write!(f, "Input is not a PNG file"),
+            Self::Corrupt(reason) => write!(f, "Corrupt PNG: {reason}"),
+            Self::Animated => write!(f, "Animated PNGs cannot be optimized"),
+            Self::InvalidLevel(level) => write!(
+                f,
+                "Optimization level must be between 1 and {MAX_LEVEL}, got {level}"
+            ),
+            Self::UnknownChunkPolicy(name) => write!(
+                f,
+                "Unknown chunk policy \"{name}\" (expected keep, safe or strip)"
+            ),
+            Self::ChunkTooLarge => write!(f, "PNG chunk exceeds the maximum size"),
+            Self::Decode(e) => write!(f, "Failed to decode PNG: {e}"),
+            Self::Encode(e) => write!(f, "Failed to encode PNG: {e}"),
+            Self::Zopfli(e) => write!(f, "Zopfli compression failed: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PngOptimizeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // ===== Fixture Helpers =====
+
+    fn encode_png(
+        width: u32,
+        height: u32,
+        color: png::ColorType,
+        depth: png::BitDepth,
+        data: &[u8],
+        extra: &[(&[u8; 4], &[u8])],
+    ) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, width, height);
+        encoder.set_color(color);
+        encoder.set_depth(depth);
+        encoder.set_deflate_compression(png::DeflateCompression::Level(1));
+        encoder.set_filter(png::Filter::NoFilter);
+        let mut writer = encoder.write_header().unwrap();
+        for (kind, payload) in extra {
+            writer
+                .write_chunk(png::chunk::ChunkType(**kind), payload)
+                .unwrap();
+        }
+        writer.write_image_data(data).unwrap();
+        writer.finish().unwrap();
+        buf
+    }
+
+    fn gradient_rgba(width: u32, height: u32) -> Vec<u8> {
+        let img = image::RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([
+                u8::try_from((x * 7) % 256).unwrap(),
+                u8::try_from((y * 5) % 256).unwrap(),
+                u8::try_from((x + y) % 256).unwrap(),
+                u8::try_from((x * y) % 256).unwrap(),
+            ])
+        });
+        encode_png(
+            width,
+            height,
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            img.as_raw(),
+            &[],
+        )
+    }
+
+    fn pixels(png_data: &[u8]) -> image::RgbaImage {
+        image::load_from_memory_with_format(png_data, image::ImageFormat::Png)
+            .unwrap()
+            .to_rgba8()
+    }
+
+    fn chunk_types(png_data: &[u8]) -> Vec<[u8; 4]> {
+        read_chunks(png_data)
+            .unwrap()
+            .iter()
+            .map(|chunk| chunk.kind)
+            .collect()
+    }
+
+    // ===== Optimization Tests =====
+
+    #[test]
+    fn every_level_preserves_pixels_and_shrinks() {
+        let input = gradient_rgba(48, 40);
+        for level in 1..=MAX_LEVEL {
+            let result = optimize_png(&input, level, ChunkPolicy::KeepAll).unwrap();
+            assert_eq!(pixels(&result.data), pixels(&input), "level {level}");
+            assert!(result.data.len() < input.len(), "level {level}");
+            assert_eq!(result.report.original_size, input.len());
+            assert_eq!(result.report.optimized_size, result.data.len());
+        }
+    }
+
+    #[test]
+    fn max_level_uses_zopfli() {
+        let input = gradient_rgba(32, 32);
+        let level2 = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        let level3 = optimize_png(&input, 3, ChunkPolicy::KeepAll).unwrap();
+        assert!(level3.report.zopfli);
+        assert!(level3.data.len() <= level2.data.len());
+        assert_eq!(pixels(&level3.data), pixels(&input));
+    }
+
+    #[test]
+    fn already_optimized_input_does_not_grow() {
+        let input = gradient_rgba(24, 24);
+        let once = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        let twice = optimize_png(&once.data, 2, ChunkPolicy::KeepAll).unwrap();
+        assert!(twice.data.len() <= once.data.len());
+        assert_eq!(twice.report.filter, "original");
+    }
+
+    #[test]
+    fn interlaced_input_is_deinterlaced() {
+        // A 2x2 Adam7 image: pass 1 holds (0,0), pass 6 holds (1,0), pass 7 holds row 1.
+        let filtered = [0, 10, 0, 20, 0, 30, 40];
+        let mut input = PNG_SIGNATURE.to_vec();
+        write_chunk(
+            &mut input,
+            *b"IHDR",
+            &[0, 0, 0, 2, 0, 0, 0, 2, 8, 0, 0, 0, 1],
+        )
+        .unwrap();
+        let idat = miniz_oxide::deflate::compress_to_vec_zlib(&filtered, 0);
+        write_chunk(&mut input, *b"IDAT", &idat).unwrap();
+        write_chunk(&mut input, *b"IEND", &[]).unwrap();
+
+        let result = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        assert_ne!(result.report.filter, "original");
+        assert_eq!(read_chunks(&result.data).unwrap()[0].data[12], 0);
+        assert_eq!(
+            RawImage::decode(&result.data).unwrap().data,
+            [10, 20, 30, 40]
+        );
+        assert_eq!(pixels(&result.data), pixels(&input));
+    }
+
+    // ===== Reduction Tests =====
+
+    #[test]
+    fn opaque_gray_rgba_reduces_to_grayscale() {
+        let data: Vec<u8> = (0..=255u8).flat_map(|i| [i, i, i, 0xFF]).collect();
+        let input = encode_png(
+            16,
+            16,
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            &data,
+            &[],
+        );
+        let result = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        assert!(result.report.reduced);
+        let raw = RawImage::decode(&result.data).unwrap();
+        assert_eq!(raw.color, png::ColorType::Grayscale);
+        assert_eq!(raw.depth, png::BitDepth::Eight);
+        assert_eq!(pixels(&result.data), pixels(&input));
+    }
+
+    #[test]
+    fn sixteen_bit_with_repeated_bytes_reduces_to_eight() {
+        let data: Vec<u8> = (0..8u8 * 8)
+            .flat_map(|i| [i, i, i.wrapping_mul(3), i.wrapping_mul(3), 9, 9])
+            .collect();
+        let input = encode_png(
+            8,
+            8,
+            png::ColorType::Rgb,
+            png::BitDepth::Sixteen,
+            &data,
+            &[],
+        );
+        let result = optimize_png(&input, 1, ChunkPolicy::KeepAll).unwrap();
+        let raw = RawImage::decode(&result.data).unwrap();
+        assert_eq!(raw.color, png::ColorType::Rgb);
+        assert_eq!(raw.depth, png::BitDepth::Eight);
+        assert_eq!(pixels(&result.data), pixels(&input));
+    }
+
+    #[test]
+    fn translucent_alpha_is_kept() {
+        let input = gradient_rgba(16, 16);
+        let result = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        assert!(!result.report.reduced);
+        assert_eq!(
+            RawImage::decode(&result.data).unwrap().color,
+            png::ColorType::Rgba
+        );
+    }
+
+    #[test]
+    fn reduction_drops_color_dependent_chunks() {
+        let data: Vec<u8> = (0..64u8).flat_map(|i| [i, i, i]).collect();
+        let input = encode_png(
+            8,
+            8,
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            &data,
+            &[(b"bKGD", &[0, 1, 0, 1, 0, 1])],
+        );
+        let result = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        assert!(result.report.reduced);
+        assert_eq!(result.report.removed_chunks, vec!["bKGD"]);
+        assert_eq!(pixels(&result.data), pixels(&input));
+    }
+
+    #[test]
+    fn gray_reduction_drops_rgb_icc_profile() {
+        let data: Vec<u8> = (0..64u8).flat_map(|i| [i, i, i]).collect();
+        let profile = miniz_oxide::deflate::compress_to_vec_zlib(&[0; 128], 6);
+        let iccp = [b"icc\0\0".as_slice(), &profile].concat();
+        let input = encode_png(
+            8,
+            8,
+            png::ColorType::Rgb,
+            png::BitDepth::Eight,
+            &data,
+            &[(b"iCCP", &iccp), (b"gAMA", &[0, 0, 0xB1, 0x8F])],
+        );
+        let result = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        assert!(result.report.reduced);
+        assert_eq!(result.report.removed_chunks, vec!["iCCP"]);
+        assert_eq!(chunk_types(&result.data)[1], *b"gAMA");
+        assert_eq!(pixels(&result.data), pixels(&input));
+
+        let opaque: Vec<u8> = (0..64u8).flat_map(|i| [i, 0, 0, 255]).collect();
+        let input = encode_png(
+            8,
+            8,
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            &opaque,
+            &[(b"iCCP", &iccp)],
+        );
+        let result = optimize_png(&input, 2, ChunkPolicy::KeepAll).unwrap();
+        assert!(result.report.reduced);
+        assert!(result.report.removed_chunks.is_empty());
+    }
+
+    // ===== Chunk Policy Tests =====
+
+    #[test]
+    fn chunk_policy_from_name() {
+        assert_eq!(
+            ChunkPolicy::from_name("keep").unwrap(),
+            ChunkPolicy::KeepAll
+        );
+        assert_eq!(ChunkPolicy::from_name("").unwrap(), ChunkPolicy::KeepSafe);
+        assert_eq!(
+            ChunkPolicy::from_name("SAFE").unwrap(),
+            ChunkPolicy::KeepSafe
+        );
+        assert_eq!(
+            ChunkPolicy::from_name("strip").unwrap(),
+            ChunkPolicy::StripAll
+        );
+        assert!(matches!(
+            ChunkPolicy::from_name("all"),
+            Err(PngOptimizeError::UnknownChunkPolicy(_))
+        ));
+    }
+
+    #[test]
+    fn chunk_policies_filter_ancillary_chunks() {
+        let img = image::RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 40]));
+        let input = encode_png(
+            4,
+            4,
+            png::ColorType::Rgba,
+            png::BitDepth::Eight,
+            img.as_raw(),
+            &[
+                (b"gAMA", &[0, 0, 0xB1, 0x8F]),
+                (b"tEXt", b"Comment\0hello"),
+                (b"pHYs", &[0, 0, 0x0B, 0x13, 0, 0, 0x0B, 0x13, 1]),
+            ],
+        );
+
+        let keep = optimize_png(&input, 1, ChunkPolicy::KeepAll).unwrap();
+        let kinds = chunk_types(&keep.data);
+        assert!(kinds.contains(b"gAMA") && kinds.contains(b"tEXt") && kinds.contains(b"pHYs"));
+
+        let safe = optimize_png(&input, 1, ChunkPolicy::KeepSafe).unwrap();
+        let kinds = chunk_types(&safe.data);
+        assert!(kinds.contains(b"gAMA") && kinds.contains(b"pHYs"));
+        assert!(!kinds.contains(b"tEXt"));
+        assert_eq!(safe.report.removed_chunks, vec!["tEXt"]);
+
+        let strip = optimize_png(&input, 1, ChunkPolicy::StripAll).unwrap();
+        assert_eq!(chunk_types(&strip.data), vec![*b"IHDR", *b"IDAT", *b"IEND"]);
+        assert_eq!(pixels(&strip.data), pixels(&input));
+    }
+
+    #[test]
+    fn palette_and_transparency_are_kept() {
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, 4, 4);
+        encoder.set_color(png::ColorType::Indexed);
+        encoder.set_depth(png::BitDepth::Two);
+        encoder.set_palette(vec![0, 0, 0, 255, 0, 0, 0, 255, 0, 0, 0, 255]);
+        encoder.set_trns(vec![0, 128, 255]);
+        let mut writer = encoder.write_header().unwrap();
+        writer
+            .write_image_data(&[0b0001_1011, 0b1110_0100, 0b0101_0101, 0b1010_1010])
+            .unwrap();
+        writer.finish().unwrap();
+
+        let result = optimize_png(&buf, 3, ChunkPolicy::StripAll).unwrap();
+        let kinds = chunk_types(&result.data);
+        assert!(kinds.contains(b"PLTE") && kinds.contains(b"tRNS"));
+        assert_eq!(pixels(&result.data), pixels(&buf));
+    }
+
+    // ===== Error Tests =====
+
+    #[test]
+    fn rejects_invalid_level() {
+        let input = gradient_rgba(4, 4);
+        assert!(matches!(
+            optimize_png(&input, 0, ChunkPolicy::KeepAll),
+            Err(PngOptimizeError::InvalidLevel(0))
+        ));
+        assert!(matches!(
+            optimize_png(&input, 4, ChunkPolicy::KeepAll),
+            Err(PngOptimizeError::InvalidLevel(4))
+        ));
+    }
+
+    #[test]
+    fn rejects_non_png() {
+        assert!(matches!(
+            optimize_png(b"GIF89a", 1, ChunkPolicy::KeepAll),
+            Err(PngOptimizeError::NotPng)
+        ));
+    }
+
+    #[test]
+    fn rejects_truncated_png() {
+        let input = gradient_rgba(8, 8);
+        let truncated = &input[..input.len() - 20];
+        assert!(matches!(
+            optimize_png(truncated, 1, ChunkPolicy::KeepAll),
+            Err(PngOptimizeError::Corrupt(_))
+        ));
+    }
+}
@@ -0,0 +1,345 @@
+//! Conversion policies: constraints on what may be converted and how, checked before
+//! anything is decoded.
+
+use std::fmt;
+
+use serde::Serialize;
+
+use crate::convert::{self, ConvertError, ConvertOptions, DEFAULT_JPEG_QUALITY};
+use crate::formats::ImageFormat;
+use crate::png_chunks::PngChunkPolicy;
+use crate::transforms::Transform;
+
+/// Constraints a conversion must satisfy. The default allows everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConversionPolicy {
+    /// Largest output width, after transforms and resizing.
+    pub max_width: Option<u32>,
+    /// Largest output height, after transforms and resizing.
+    pub max_height: Option<u32>,
+    /// Input formats that may be converted. Empty allows any.
+    pub allowed_inputs: Vec<ImageFormat>,
+    /// The only format output may be written in, used when no target is given.
+    pub output_format: Option<ImageFormat>,
+    /// Write no ancillary chunks carried over from the source, whatever
+    /// [`ConvertOptions::png_chunks`] asks for. PNG output is the only output that
+    /// carries any.
+    pub strip_metadata: bool,
+    /// Lowest JPEG quality, 1-100. JPEG is the only lossy target.
+    pub min_quality: Option<u8>,
+    /// Highest JPEG quality, 1-100.
+    pub max_quality: Option<u8>,
+}
+
+/// A policy rule a conversion breaks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    InputFormat,
+    OutputFormat,
+    MaxWidth,
+    MaxHeight,
+    Quality,
+}
+
+/// One broken rule, as returned by [`check`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Violation {
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl Violation {
+    fn new(rule: Rule, message: String) -> Self {
+        Self { rule, message }
+    }
+}
+
+/// Every rule of `policy` that converting `input` to `target` with `options` and
+/// `transforms_list` would break, in the order of [`Rule`]. Only headers are read.
+///
+/// # Errors
+///
+/// Returns `PolicyError::InvalidQualityBounds` if the policy's quality bounds are out
+/// of range or reversed, or `PolicyError::Convert` if the conversion couldn't be
+/// planned (see [`convert::plan`]).
+pub fn check(
+    input: &[u8],
+    target: ImageFormat,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+    policy: &ConversionPolicy,
+) -> Result<Vec<Violation>, PolicyError> {
+    let min_quality = policy.min_quality.unwrap_or(1);
+    let max_quality = policy.max_quality.unwrap_or(100);
+    if min_quality == 0 || max_quality > 100 || min_quality > max_quality {
+        return Err(PolicyError::InvalidQualityBounds {
+            min: min_quality,
+            max: max_quality,
+        });
+    }
+
+    let mut violations = Vec::new();
+    if !policy.allowed_inputs.is_empty() {
+        let input_format = ImageFormat::detect_from_bytes(input)
+            .ok()
+            .or(options.source_format);
+        if !input_format.is_some_and(|format| policy.allowed_inputs.contains(&format)) {
+            let name = input_format.map_or("unknown", |format| format.as_str());
+            violations.push(Violation::new(
+                Rule::InputFormat,
+                format!("{name} input is not allowed"),
+            ));
+        }
+    }
+    if let Some(format) = policy.output_format {
+        if format != target {
+            violations.push(Violation::new(
+                Rule::OutputFormat,
+                format!("Output must be {format}, not {target}"),
+            ));
+        }
+    }
+
+    let plan =
+        convert::plan(input, target, options, transforms_list).map_err(PolicyError::Convert)?;
+    if let Some(max) = policy.max_width {
+        if plan.width > max {
+            violations.push(Violation::new(
+                Rule::MaxWidth,
+                format!("Output is {} pixels wide, more than {max}", plan.width),
+            ));
+        }
+    }
+    if let Some(max) = policy.max_height {
+        if plan.height > max {
+            violations.push(Violation::new(
+                Rule::MaxHeight,
+                format!("Output is {} pixels high, more than {max}", plan.height),
+            ));
+        }
+    }
+    if target == ImageFormat::Jpeg {
+        let quality = options.quality.unwrap_or(DEFAULT_JPEG_QUALITY);
+        if !(min_quality..=max_quality).contains(&quality) {
+            violations.push(Violation::new(
+                Rule::Quality,
+                format!("Quality {quality} is outside {min_quality}-{max_quality}"),
+            ));
+        }
+    }
+    Ok(violations)
+}
+
+/// Converts `input` as [`convert::convert_with_options`] would, unless it breaks a
+/// rule of `policy`. `target` defaults to the policy's output format.
+///
+/// # Errors
+///
+/// Returns `PolicyError::NoTarget` if neither `target` nor the policy names an output
+/// format, `PolicyError::Violations` with every broken rule, or the errors of
+/// [`check`] and the conversion.
+pub fn convert_with_policy(
+    input: Vec<u8>,
+    target: Option<ImageFormat>,
+    options: &ConvertOptions,
+    transforms_list: &[Transform],
+    policy: &ConversionPolicy,
+) -> Result<Vec<u8>, PolicyError> {
+    let target = target
+        .or(policy.output_format)
+        .ok_or(PolicyError::NoTarget)?;
+    let violations = check(&input, target, options, transforms_list, policy)?;
+    if !violations.is_empty() {
+        return Err(PolicyError::Violations(violations));
+    }
+    let stripped;
+    let options = if policy.strip_metadata {
+        stripped = ConvertOptions {
+            png_chunks: PngChunkPolicy::default(),
+            ..options.clone()
+        };
+        &stripped
+    } else {
+        options
+    };
+    convert::convert_with_options(input, target, options, transforms_list)
+        .map_err(PolicyError::Convert)
+}
+
+/// Errors that can occur while checking or converting under a policy.
+#[derive(Debug)]
+pub enum PolicyError {
+    /// The policy's quality bounds are outside 1-100 or `min` is above `max`.
+    InvalidQualityBounds { min: u8, max: u8 },
+    /// No target format was given and the policy doesn't name one.
+    NoTarget,
+    /// The conversion breaks these rules.
+    Violations(Vec<Violation>),
+    /// The conversion couldn't be planned or failed.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for PolicyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidQualityBounds { min, max } => {
+                write!(f, "Invalid quality bounds {min}-{max}")
+            }
+            Self::NoTarget => write!(f, "No target format given and the policy has none"),
+            Self::Violations(violations) => {
+                write!(f, "The conversion breaks the policy: ")?;
+                for (i, violation) in violations.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, "; ")?;
+                    }
+                    write!(f, "{}", violation.message)?;
+                }
+                Ok(())
+            }
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for PolicyError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn encoded(width: u32, height: u32, format: image::ImageFormat) -> Vec<u8> {
+        let mut out = Vec::new();
+        image::DynamicImage::new_rgb8(width, height)
+            .write_to(&mut Cursor::new(&mut out), format)
+            .unwrap();
+        out
+    }
+
+    fn png_with_text(width: u32, height: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, width, height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .add_text_chunk("Author".to_owned(), "Someone".to_owned())
+            .unwrap();
+        let mut writer = encoder.write_header().unwrap();
+        let pixels = vec![0; usize::try_from(width * height * 3).unwrap()];
+        writer.write_image_data(&pixels).unwrap();
+        writer.finish().unwrap();
+        buf
+    }
+
+    fn rules(violations: &[Violation]) -> Vec<Rule> {
+        violations.iter().map(|violation| violation.rule).collect()
+    }
+
+    #[test]
+    fn check_reports_every_broken_rule() {
+        let policy = ConversionPolicy {
+            max_width: Some(16),
+            max_height: Some(16),
+            allowed_inputs: vec![ImageFormat::Png],
+            output_format: Some(ImageFormat::Png),
+            min_quality: Some(85),
+            ..ConversionPolicy::default()
+        };
+        let input = encoded(32, 8, image::ImageFormat::Bmp);
+        let violations = check(
+            &input,
+            ImageFormat::Jpeg,
+            &ConvertOptions::default(),
+            &[],
+            &policy,
+        )
+        .unwrap();
+        assert_eq!(
+            rules(&violations),
+            [
+                Rule::InputFormat,
+                Rule::OutputFormat,
+                Rule::MaxWidth,
+                Rule::Quality
+            ]
+        );
+
+        // Rotating swaps which side is too long.
+        let violations = check(
+            &input,
+            ImageFormat::Jpeg,
+            &ConvertOptions {
+                quality: Some(90),
+                ..ConvertOptions::default()
+            },
+            &[Transform::Rotate90],
+            &ConversionPolicy {
+                output_format: None,
+                allowed_inputs: Vec::new(),
+                ..policy
+            },
+        )
+        .unwrap();
+        assert_eq!(rules(&violations), [Rule::MaxHeight]);
+    }
+
+    #[test]
+    fn convert_with_policy_enforces_the_policy() {
+        let policy = ConversionPolicy {
+            max_width: Some(16),
+            output_format: Some(ImageFormat::Png),
+            strip_metadata: true,
+            ..ConversionPolicy::default()
+        };
+        let options = ConvertOptions {
+            png_chunks: PngChunkPolicy::parse("keep").unwrap(),
+            ..ConvertOptions::default()
+        };
+        let output =
+            convert_with_policy(png_with_text(8, 8), None, &options, &[], &policy).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&output).unwrap(),
+            ImageFormat::Png
+        );
+        assert!(!output.windows(4).any(|kind| kind == b"tEXt"));
+
+        match convert_with_policy(
+            encoded(32, 8, image::ImageFormat::Png),
+            Some(ImageFormat::Png),
+            &options,
+            &[],
+            &policy,
+        ) {
+            Err(PolicyError::Violations(violations)) => {
+                assert_eq!(rules(&violations), [Rule::MaxWidth]);
+            }
+            other => panic!("expected violations, got {other:?}"),
+        }
+        assert!(matches!(
+            convert_with_policy(
+                encoded(8, 8, image::ImageFormat::Png),
+                None,
+                &options,
+                &[],
+                &ConversionPolicy::default(),
+            ),
+            Err(PolicyError::NoTarget)
+        ));
+        assert!(matches!(
+            check(
+                &encoded(8, 8, image::ImageFormat::Png),
+                ImageFormat::Png,
+                &options,
+                &[],
+                &ConversionPolicy {
+                    min_quality: Some(90),
+                    max_quality: Some(80),
+                    ..ConversionPolicy::default()
+                },
+            ),
+            Err(PolicyError::InvalidQualityBounds { min: 90, max: 80 })
+        ));
+    }
+}
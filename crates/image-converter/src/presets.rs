@@ -0,0 +1,220 @@
+//! Named conversion presets, defined once (say, at app start) and referenced by name.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+
+use serde::Serialize;
+
+use crate::convert::{self, ConvertError, ConvertOptions};
+use crate::formats::ImageFormat;
+use crate::transforms::Transform;
+
+/// A validated preset: everything a conversion needs besides the input.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Preset {
+    pub target: ImageFormat,
+    pub options: ConvertOptions,
+    pub transforms: Vec<Transform>,
+    /// The definition the preset was built from, kept for [`list_presets`].
+    pub ops: serde_json::Value,
+}
+
+/// A registered preset, as listed by [`list_presets`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PresetInfo {
+    pub name: String,
+    pub ops: serde_json::Value,
+}
+
+thread_local! {
+    /// Registered presets in registration order, per thread like the font registry.
+    static PRESETS: RefCell<Vec<(String, Rc<Preset>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Registers `preset` under `name`, replacing any preset already registered under
+/// that name (compared case-insensitively).
+///
+/// # Errors
+///
+/// Returns `PresetError::InvalidName` if the name is blank, and
+/// `PresetError::UnsupportedTarget` if the target is decode-only (see
+/// [`ImageFormat::can_encode`]), so the preset would fail on every use. Registered
+/// encoders can't take a built-in format's name, so none can stand in for it.
+pub fn register_preset(name: &str, preset: Preset) -> Result<(), PresetError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(PresetError::InvalidName);
+    }
+    if !preset.target.can_encode() {
+        return Err(PresetError::UnsupportedTarget(preset.target));
+    }
+    PRESETS.with(|presets| {
+        let mut presets = presets.borrow_mut();
+        let entry = (name.to_owned(), Rc::new(preset));
+        match presets
+            .iter_mut()
+            .find(|(registered, _)| registered.eq_ignore_ascii_case(name))
+        {
+            Some(existing) => *existing = entry,
+            None => presets.push(entry),
+        }
+    });
+    Ok(())
+}
+
+/// Removes the preset registered under `name`. Returns whether there was one.
+pub fn unregister_preset(name: &str) -> bool {
+    let name = name.trim();
+    PRESETS.with(|presets| {
+        let mut presets = presets.borrow_mut();
+        let before = presets.len();
+        presets.retain(|(registered, _)| !registered.eq_ignore_ascii_case(name));
+        presets.len() != before
+    })
+}
+
+/// The registered presets with their definitions, in registration order.
+pub fn list_presets() -> Vec<PresetInfo> {
+    PRESETS.with(|presets| {
+        presets
+            .borrow()
+            .iter()
+            .map(|(name, preset)| PresetInfo {
+                name: name.clone(),
+                ops: preset.ops.clone(),
+            })
+            .collect()
+    })
+}
+
+/// Converts `input` with the preset registered under `name`, as
+/// [`convert::convert_with_options`] would with the preset's settings.
+///
+/// # Errors
+///
+/// Returns `PresetError::UnknownPreset` if no preset has that name, or
+/// `PresetError::Convert` if the conversion fails.
+pub fn convert_with_preset(input: Vec<u8>, name: &str) -> Result<Vec<u8>, PresetError> {
+    let preset = find(name).ok_or_else(|| PresetError::UnknownPreset(name.to_owned()))?;
+    convert::convert_with_options(input, preset.target, &preset.options, &preset.transforms)
+        .map_err(PresetError::Convert)
+}
+
+fn find(name: &str) -> Option<Rc<Preset>> {
+    let name = name.trim();
+    PRESETS.with(|presets| {
+        presets
+            .borrow()
+            .iter()
+            .find(|(registered, _)| registered.eq_ignore_ascii_case(name))
+            .map(|(_, preset)| Rc::clone(preset))
+    })
+}
+
+/// Errors that can occur while registering or applying presets.
+#[derive(Debug)]
+pub enum PresetError {
+    /// The preset name was blank.
+    InvalidName,
+    /// The preset's target format can't be encoded.
+    UnsupportedTarget(ImageFormat),
+    /// No preset is registered under the name.
+    UnknownPreset(String),
+    /// The conversion failed.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for PresetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidName => write!(f, "Preset names must not be empty"),
+            Self::UnsupportedTarget(format) => write!(
+                f,
+                "Presets can't target \"{format}\", which is not supported as an output format"
+            ),
+            Self::UnknownPreset(name) => write!(f, "No preset is registered as {name:?}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for PresetError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::convert::ResizeStep;
+    use crate::resize::{ResizeMode, ResizeOptions};
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        image::RgbaImage::new(width, height)
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    fn thumbnail(side: u32) -> Preset {
+        Preset {
+            target: ImageFormat::Bmp,
+            options: ConvertOptions {
+                resize: Some(ResizeStep {
+                    mode: ResizeMode::Cover,
+                    width: side,
+                    height: side,
+                    options: ResizeOptions::default(),
+                }),
+                ..ConvertOptions::default()
+            },
+            transforms: vec![Transform::Grayscale],
+            ops: serde_json::json!({ "format": "bmp", "resize": { "width": side } }),
+        }
+    }
+
+    #[test]
+    fn presets_convert_by_name() {
+        register_preset("email-thumbnail", thumbnail(8)).unwrap();
+        let output = convert_with_preset(png(20, 10), " Email-Thumbnail").unwrap();
+        let img = image::load_from_memory(&output).unwrap();
+        assert_eq!((img.width(), img.height()), (8, 8));
+        assert!(matches!(
+            convert_with_preset(png(4, 4), "avatar"),
+            Err(PresetError::UnknownPreset(_))
+        ));
+    }
+
+    #[test]
+    fn registry_replaces_lists_and_removes_presets() {
+        register_preset("avatar", thumbnail(64)).unwrap();
+        register_preset("listing", thumbnail(800)).unwrap();
+        register_preset("Avatar", thumbnail(128)).unwrap();
+        let listed = list_presets();
+        let names: Vec<&str> = listed.iter().map(|info| info.name.as_str()).collect();
+        assert_eq!(names, ["Avatar", "listing"]);
+        assert_eq!(listed[0].ops["resize"]["width"], 128);
+
+        assert!(unregister_preset("AVATAR"));
+        assert!(!unregister_preset("avatar"));
+        assert_eq!(list_presets().len(), 1);
+        assert!(matches!(
+            register_preset("  ", thumbnail(1)),
+            Err(PresetError::InvalidName)
+        ));
+    }
+
+    #[test]
+    fn rejects_presets_that_cannot_encode() {
+        let webp = Preset {
+            target: ImageFormat::WebP,
+            ..thumbnail(256)
+        };
+        assert!(matches!(
+            register_preset("webp-avatar", webp),
+            Err(PresetError::UnsupportedTarget(ImageFormat::WebP))
+        ));
+        assert!(list_presets().iter().all(|info| info.name != "webp-avatar"));
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+use std::io::Cursor;
+
+use image::RgbaImage;
+
+use crate::formats::{FormatError, ImageFormat};
+use crate::jpeg::{self, JpegError};
+use crate::region;
+
+/// A low-detail first look at an image, 1/8 of its size on each side (rounded up).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Preview {
+    pub image: RgbaImage,
+    /// Size of the full image, for drawing the preview scaled up in its place.
+    pub source_width: u32,
+    pub source_height: u32,
+}
+
+/// Decodes only the first scan or pass of a progressive JPEG or interlaced PNG, for an
+/// instant preview while the full image is still being converted.
+///
+/// JPEGs come from the DC coefficients of their first scans, so each pixel is the
+/// average of an 8x8 block. PNGs come from the first Adam7 pass, which holds the
+/// top-left pixel of every 8x8 block; the rest of the compressed data isn't inflated.
+///
+/// # Errors
+///
+/// Returns `PreviewError::NotProgressive` for baseline JPEGs, non-interlaced PNGs and
+/// other formats, which have no early pass to stop after, or an error if the input
+/// cannot be read.
+pub fn quick_preview(input: &[u8]) -> Result<Preview, PreviewError> {
+    match ImageFormat::detect_from_bytes(input).map_err(PreviewError::Format)? {
+        ImageFormat::Jpeg => jpeg_preview(input),
+        ImageFormat::Png => png_preview(input),
+        ImageFormat::Gif
+        | ImageFormat::WebP
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => Err(PreviewError::NotProgressive),
+    }
+}
+
+fn jpeg_preview(input: &[u8]) -> Result<Preview, PreviewError> {
+    if !jpeg::is_progressive(input).map_err(PreviewError::Jpeg)? {
+        return Err(PreviewError::NotProgressive);
+    }
+    let (source_width, source_height) = image::ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|e| PreviewError::Decode(image::ImageError::IoError(e)))?
+        .into_dimensions()
+        .map_err(PreviewError::Decode)?;
+    Ok(Preview {
+        image: jpeg::first_scan_preview(input).map_err(PreviewError::Jpeg)?,
+        source_width,
+        source_height,
+    })
+}
+
+fn png_preview(input: &[u8]) -> Result<Preview, PreviewError> {
+    let mut decoder = png::Decoder::new(Cursor::new(input));
+    decoder.set_transformations(png::Transformations::normalize_to_color8());
+    let mut reader = decoder.read_info().map_err(PreviewError::Png)?;
+    let info = reader.info();
+    if !info.interlaced {
+        return Err(PreviewError::NotProgressive);
+    }
+    let (source_width, source_height) = (info.width, info.height);
+    let channels = reader.output_color_type().0.samples();
+
+    // Adam7's first pass is every eighth pixel of every eighth row, and comes first.
+    let (width, height) = (source_width.div_ceil(8), source_height.div_ceil(8));
+    let mut image = RgbaImage::new(width, height);
+    for line in image.chunks_exact_mut(usize::try_from(width).unwrap_or(0) * 4) {
+        let Some(row) = reader.next_interlaced_row().map_err(PreviewError::Png)? else {
+            break;
+        };
+        region::expand_to_rgba(row.data(), line, channels);
+    }
+    Ok(Preview {
+        image,
+        source_width,
+        source_height,
+    })
+}
+
+/// Errors that can occur while building a preview.
+#[derive(Debug)]
+pub enum PreviewError {
+    /// The input format could not be detected.
+    Format(FormatError),
+    /// The input has no early scan or pass to preview.
+    NotProgressive,
+    /// Failed to read the JPEG's scans.
+    Jpeg(JpegError),
+    /// Failed to read the PNG's first pass.
+    Png(png::DecodingError),
+    /// Failed to read the image's dimensions.
+    Decode(image::ImageError),
+}
+
+impl fmt::Display for PreviewError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(e) => write!(f, "{e}"),
+            Self::NotProgressive => write!(
+                f,
+                "Only progressive JPEGs and interlaced PNGs have a first pass to preview"
+            ),
+            Self::Jpeg(e) => write!(f, "{e}"),
+            Self::Png(e) => write!(f, "Failed to read PNG: {e}"),
+            Self::Decode(e) => write!(f, "Failed to read image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for PreviewError {}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+    use crate::png_optimize::{write_chunk, PNG_SIGNATURE};
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([
+                u8::try_from(x * 9 % 256).unwrap(),
+                u8::try_from(y * 13 % 256).unwrap(),
+                100,
+                u8::try_from(255 - (x + y) % 128).unwrap(),
+            ])
+        })
+    }
+
+    /// An RGBA8 PNG, Adam7-interlaced if `interlaced`, with unfiltered scanlines (the
+    /// `png` encoder only writes non-interlaced images).
+    fn png(image: &RgbaImage, interlaced: bool) -> Vec<u8> {
+        // Starting column and row, then column and row step, of each pass.
+        let passes: &[(u32, u32, u32, u32)] = if interlaced {
+            &[
+                (0, 0, 8, 8),
+                (4, 0, 8, 8),
+                (0, 4, 4, 8),
+                (2, 0, 4, 4),
+                (0, 2, 2, 4),
+                (1, 0, 2, 2),
+                (0, 1, 1, 2),
+            ]
+        } else {
+            &[(0, 0, 1, 1)]
+        };
+        let mut raw = Vec::new();
+        for &(x0, y0, dx, dy) in passes {
+            if x0 >= image.width() {
+                continue;
+            }
+            for y in (y0..image.height()).step_by(usize::try_from(dy).unwrap()) {
+                raw.push(0);
+                for x in (x0..image.width()).step_by(usize::try_from(dx).unwrap()) {
+                    raw.extend_from_slice(&image.get_pixel(x, y).0);
+                }
+            }
+        }
+
+        let mut ihdr = Vec::new();
+        ihdr.extend_from_slice(&image.width().to_be_bytes());
+        ihdr.extend_from_slice(&image.height().to_be_bytes());
+        ihdr.extend_from_slice(&[8, 6, 0, 0, u8::from(interlaced)]);
+        let mut out = PNG_SIGNATURE.to_vec();
+        write_chunk(&mut out, *b"IHDR", &ihdr).unwrap();
+        let idat = miniz_oxide::deflate::compress_to_vec_zlib(&raw, 6);
+        write_chunk(&mut out, *b"IDAT", &idat).unwrap();
+        write_chunk(&mut out, *b"IEND", &[]).unwrap();
+        out
+    }
+
+    #[test]
+    fn interlaced_png_previews_first_pass() {
+        let image = gradient(21, 10);
+        let preview = quick_preview(&png(&image, true)).unwrap();
+        assert_eq!((preview.source_width, preview.source_height), (21, 10));
+        assert_eq!(preview.image.dimensions(), (3, 2));
+        for (x, y, pixel) in preview.image.enumerate_pixels() {
+            assert_eq!(pixel, image.get_pixel(x * 8, y * 8), "({x}, {y})");
+        }
+    }
+
+    #[test]
+    fn sequential_inputs_have_no_preview() {
+        let image = gradient(16, 16);
+        assert!(matches!(
+            quick_preview(&png(&image, false)),
+            Err(PreviewError::NotProgressive)
+        ));
+
+        let mut jpeg = Vec::new();
+        image::DynamicImage::ImageRgba8(image)
+            .into_rgb8()
+            .write_to(&mut Cursor::new(&mut jpeg), image::ImageFormat::Jpeg)
+            .unwrap();
+        assert!(matches!(
+            quick_preview(&jpeg),
+            Err(PreviewError::NotProgressive)
+        ));
+    }
+}
@@ -0,0 +1,323 @@
+//! Client proofs: the resize, watermark and metadata clean-up photographers run on a
+//! gallery before sharing it, done in one call that returns a ZIP archive.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::io::Cursor;
+
+use image::metadata::Orientation;
+use image::{DynamicImage, ImageDecoder, ImageReader, RgbaImage};
+
+use crate::attribution::{self, AttributionError};
+use crate::batch::{self, BatchError};
+use crate::composite;
+use crate::convert::{self, ConvertError};
+use crate::exif_write::{self, ExifEdits, ExifWriteError};
+use crate::formats::{self, ImageFormat};
+use crate::region;
+use crate::resize::{self, ResizeError, ResizeMode, ResizeOptions};
+
+/// JPEG quality of the proofs: plenty to judge a shot by on screen.
+pub const PROOF_QUALITY: u8 = 85;
+
+/// The watermark logo's longer side is the proof's shorter side divided by this.
+const LOGO_DIVISOR: u32 = 5;
+
+/// Opacity of the tiled watermark: visible everywhere without hiding the photo.
+const WATERMARK_OPACITY: f64 = 0.3;
+
+/// Repeats `logo` across `img` at `opacity` (clamped to 0.0..=1.0), one logo's width
+/// and height apart, with every other row shifted by a logo's width so the pattern
+/// can't be cropped out.
+pub fn tile_watermark(img: &mut RgbaImage, logo: &RgbaImage, opacity: f64) {
+    let (logo_width, logo_height) = (i64::from(logo.width()), i64::from(logo.height()));
+    if logo_width == 0 || logo_height == 0 {
+        return;
+    }
+    let (width, height) = (i64::from(img.width()), i64::from(img.height()));
+    let step = |size: i64| usize::try_from(size * 2).unwrap_or(usize::MAX);
+    for (row, y) in (logo_height / 2..height)
+        .step_by(step(logo_height))
+        .enumerate()
+    {
+        let start = if row % 2 == 0 { 0 } else { logo_width } - logo_width / 2;
+        for x in (start..width).step_by(step(logo_width)) {
+            composite::overlay(img, logo, x, y, opacity);
+        }
+    }
+}
+
+/// Makes one proof: turns `input` upright by its EXIF orientation, shrinks it to fit
+/// `max_edge` x `max_edge` (never enlarging it), tiles `logo` across it at a fifth of
+/// the proof's shorter side, and encodes a JPEG at [`PROOF_QUALITY`]. Large TIFFs and
+/// JPEGs are decoded at reduced resolution (see [`region::decode_at_least`]).
+///
+/// Re-encoding drops the source's metadata, GPS location included; only the EXIF
+/// artist and copyright are carried over.
+///
+/// # Errors
+///
+/// Returns a `ProofError` if the input cannot be decoded, `max_edge` is zero, or the
+/// proof cannot be encoded.
+pub fn make_proof(input: &[u8], logo: &RgbaImage, max_edge: u32) -> Result<Vec<u8>, ProofError> {
+    let mut decoder = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|e| ProofError::Decode(image::ImageError::IoError(e)))?
+        .into_decoder()
+        .map_err(ProofError::Decode)?;
+    let orientation = decoder.orientation().map_err(ProofError::Decode)?;
+    let (width, height) = decoder.dimensions();
+    let options = ResizeOptions {
+        no_upscale: true,
+        ..ResizeOptions::default()
+    };
+    // Fitting a square is the same either way up, so the plan holds before orienting.
+    let plan = resize::geometry(
+        width,
+        height,
+        ResizeMode::Fit,
+        max_edge,
+        max_edge,
+        options.no_upscale,
+    )
+    .map_err(ProofError::Resize)?;
+    let mut img = match region::try_decode_at_least(input, plan.scaled_width, plan.scaled_height) {
+        Some(reduced) => DynamicImage::ImageRgba8(reduced),
+        None => DynamicImage::from_decoder(decoder).map_err(ProofError::Decode)?,
+    };
+    img.apply_orientation(orientation);
+    let (width, height) = match orientation {
+        Orientation::NoTransforms
+        | Orientation::Rotate180
+        | Orientation::FlipHorizontal
+        | Orientation::FlipVertical => (width, height),
+        Orientation::Rotate90
+        | Orientation::Rotate270
+        | Orientation::Rotate90FlipH
+        | Orientation::Rotate270FlipH => (height, width),
+    };
+
+    let mut proof = resize::resize_reduced(
+        &img.into_rgba8(),
+        width,
+        height,
+        ResizeMode::Fit,
+        max_edge,
+        max_edge,
+        options,
+    )
+    .map_err(ProofError::Resize)?;
+    let logo_size = (proof.width().min(proof.height()) / LOGO_DIVISOR).max(1);
+    let logo = resize::resize_image(
+        logo,
+        ResizeMode::LongEdge,
+        logo_size,
+        logo_size,
+        ResizeOptions::default(),
+    )
+    .map_err(ProofError::Resize)?;
+    tile_watermark(&mut proof, &logo, WATERMARK_OPACITY);
+    let output = convert::encode(
+        &DynamicImage::ImageRgba8(proof),
+        ImageFormat::Jpeg,
+        Some(PROOF_QUALITY),
+    )
+    .map_err(ProofError::Convert)?;
+
+    let credit = attribution::read(input).map_err(ProofError::Attribution)?;
+    let edits = ExifEdits {
+        artist: credit.artist,
+        copyright: credit.copyright,
+        ..ExifEdits::default()
+    };
+    if edits.is_empty() {
+        return Ok(output);
+    }
+    exif_write::set_exif(&output, &edits).map_err(ProofError::Exif)
+}
+
+/// Makes a proof of every input with [`make_proof`] and stores them in a ZIP archive
+/// (see [`batch::write_zip`]), in input order.
+///
+/// Entries are named after `names` with a `.jpg` extension, or `proof-001.jpg`,
+/// `proof-002.jpg`, ... without names; repeated names get ` (2)`, ` (3)`, ... added.
+///
+/// # Errors
+///
+/// Returns a `ProofError` if there are no inputs, `names` doesn't have one name per
+/// input, the logo or an input cannot be decoded, `max_edge` is zero, or the archive
+/// would exceed 65535 files or 4 GiB.
+pub fn client_proof_batch(
+    inputs: &[Vec<u8>],
+    names: Option<&[String]>,
+    logo: &[u8],
+    max_edge: u32,
+) -> Result<Vec<u8>, ProofError> {
+    if inputs.is_empty() {
+        return Err(ProofError::Batch(BatchError::NoInputs));
+    }
+    if let Some(names) = names.filter(|names| names.len() != inputs.len()) {
+        return Err(ProofError::Batch(BatchError::NamesMismatch {
+            inputs: inputs.len(),
+            names: names.len(),
+        }));
+    }
+    let logo = image::load_from_memory(logo)
+        .map_err(ProofError::Logo)?
+        .into_rgba8();
+
+    let mut taken = HashSet::new();
+    let mut files = Vec::with_capacity(inputs.len());
+    for (index, input) in inputs.iter().enumerate() {
+        let name = match names.and_then(|names| names.get(index)) {
+            Some(name) => formats::suggest_filename(name, ImageFormat::Jpeg),
+            None => format!("proof-{:03}.jpg", index + 1),
+        };
+        let proof = make_proof(input, &logo, max_edge).map_err(|e| ProofError::Input {
+            index,
+            source: Box::new(e),
+        })?;
+        files.push((batch::unique_name(&name, &mut taken), proof));
+    }
+
+    let entries: Vec<(&str, &[u8])> = files
+        .iter()
+        .map(|(name, data)| (name.as_str(), data.as_slice()))
+        .collect();
+    batch::write_zip(&entries).map_err(ProofError::Batch)
+}
+
+/// Errors that can occur while making client proofs.
+#[derive(Debug)]
+pub enum ProofError {
+    /// Failed to decode the watermark logo.
+    Logo(image::ImageError),
+    /// Failed to decode an input image.
+    Decode(image::ImageError),
+    /// The proof size was invalid.
+    Resize(ResizeError),
+    /// Failed to encode a proof.
+    Convert(ConvertError),
+    /// Failed to read an input's artist and copyright.
+    Attribution(AttributionError),
+    /// Failed to write the artist and copyright into a proof.
+    Exif(ExifWriteError),
+    /// The batch was empty or malformed, or too large to archive.
+    Batch(BatchError),
+    /// Making the proof of the input at `index` failed.
+    Input {
+        index: usize,
+        source: Box<ProofError>,
+    },
+}
+
+impl fmt::Display for ProofError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Logo(e) => write!(f, "Failed to decode logo: {e}"),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Resize(e) => write!(f, "{e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+            Self::Attribution(e) => write!(f, "{e}"),
+            Self::Exif(e) => write!(f, "{e}"),
+            Self::Batch(e) => write!(f, "{e}"),
+            Self::Input { index, source } => write!(f, "Input {index}: {source}"),
+        }
+    }
+}
+
+impl std::error::Error for ProofError {}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    fn encoded(img: &RgbaImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(img.clone())
+            .write_to(&mut buf, format)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn watermark_tiles_cover_the_whole_image() {
+        let mut img = RgbaImage::from_pixel(100, 60, Rgba([255, 255, 255, 255]));
+        let logo = RgbaImage::from_pixel(10, 6, Rgba([0, 0, 0, 255]));
+        tile_watermark(&mut img, &logo, 0.5);
+
+        let marked = |x0: u32, y0: u32| {
+            (x0..x0 + 20)
+                .flat_map(|x| (y0..y0 + 12).map(move |y| (x, y)))
+                .any(|(x, y)| img.get_pixel(x, y).0 == [128, 128, 128, 255])
+        };
+        for (x, y) in [(0, 0), (80, 0), (40, 24), (0, 48), (80, 48)] {
+            assert!(marked(x, y), "no watermark near ({x}, {y})");
+        }
+        assert_eq!(img.get_pixel(0, 0).0, [255, 255, 255, 255]);
+    }
+
+    #[test]
+    fn batch_zips_shrunk_watermarked_jpegs() {
+        let photo = encoded(
+            &RgbaImage::from_pixel(400, 200, Rgba([200, 180, 160, 255])),
+            image::ImageFormat::Png,
+        );
+        let small = encoded(
+            &RgbaImage::from_pixel(50, 80, Rgba([20, 40, 60, 255])),
+            image::ImageFormat::Png,
+        );
+        let logo = encoded(
+            &RgbaImage::from_pixel(8, 8, Rgba([255, 255, 255, 255])),
+            image::ImageFormat::Png,
+        );
+        let inputs = [photo.clone(), small, photo.clone()];
+
+        let zip = client_proof_batch(&inputs, None, &logo, 100).unwrap();
+        for name in ["proof-001.jpg", "proof-002.jpg", "proof-003.jpg"] {
+            assert!(zip
+                .windows(name.len())
+                .any(|window| window == name.as_bytes()));
+        }
+
+        let proof = make_proof(
+            &photo,
+            &image::load_from_memory(&logo).unwrap().into_rgba8(),
+            100,
+        )
+        .unwrap();
+        let decoded = image::load_from_memory(&proof).unwrap();
+        assert_eq!(
+            ImageFormat::detect_from_bytes(&proof).unwrap(),
+            ImageFormat::Jpeg
+        );
+        assert_eq!((decoded.width(), decoded.height()), (100, 50));
+
+        let names = ["a.png".to_owned(), "b.png".to_owned(), "A.PNG".to_owned()];
+        let zip = client_proof_batch(&inputs, Some(&names), &logo, 100).unwrap();
+        for name in ["a.jpg", "b.jpg", "A (2).jpg"] {
+            assert!(zip
+                .windows(name.len())
+                .any(|window| window == name.as_bytes()));
+        }
+
+        assert!(matches!(
+            client_proof_batch(&inputs, Some(&names[..2]), &logo, 100),
+            Err(ProofError::Batch(BatchError::NamesMismatch { .. }))
+        ));
+        assert!(matches!(
+            client_proof_batch(&[], None, &logo, 100),
+            Err(ProofError::Batch(BatchError::NoInputs))
+        ));
+        assert!(matches!(
+            client_proof_batch(&[b"junk".to_vec()], None, &logo, 100),
+            Err(ProofError::Input { index: 0, .. })
+        ));
+        assert!(matches!(
+            client_proof_batch(&inputs, None, b"junk", 100),
+            Err(ProofError::Logo(_))
+        ));
+    }
+}
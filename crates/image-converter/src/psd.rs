@@ -0,0 +1,591 @@
+//! Photoshop documents (PSD, and large-document PSB), read through the flattened
+//! composite Photoshop stores after the layers.
+//!
+//! Layers aren't composited here: the composite is what Photoshop rendered when the
+//! file was saved. Files saved without "Maximize compatibility" still have one, but it
+//! may be blank.
+
+use std::fmt;
+
+use image::{DynamicImage, ImageBuffer, Luma, LumaA, Rgb, Rgba};
+use serde::Serialize;
+
+use crate::codecs::{CodecError, Decoder};
+
+/// The format name PSD inputs are detected and listed as.
+pub const NAME: &str = "psd";
+
+/// Largest composite decoded, in pixels, so a corrupt header can't ask for gigabytes.
+const MAX_PIXELS: u64 = 1 << 28;
+
+/// What a PSD's headers say about it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PsdInfo {
+    pub width: u32,
+    pub height: u32,
+    /// Channels of the composite, including alpha and spot channels.
+    pub channels: u16,
+    /// Bits per channel: 1, 8, 16 or 32.
+    pub depth: u16,
+    /// `"bitmap"`, `"grayscale"`, `"indexed"`, `"rgb"`, `"cmyk"`, `"multichannel"`,
+    /// `"duotone"` or `"lab"`.
+    pub color_mode: &'static str,
+    /// Layers in the document; 0 for a flat file.
+    pub layers: u32,
+    /// Whether the composite has transparency.
+    pub has_alpha: bool,
+    /// Whether the file is a large-document (PSB) file.
+    pub large_document: bool,
+}
+
+/// Whether `input` starts like a PSD or PSB file.
+pub fn is_psd(input: &[u8]) -> bool {
+    input.starts_with(b"8BPS") && matches!(input.get(4..6), Some([0, 1 | 2]))
+}
+
+/// Reads a PSD's headers and layer count without decoding the composite.
+///
+/// # Errors
+///
+/// Returns `PsdError::NotPsd` if the input isn't a PSD or PSB file, or
+/// `PsdError::Corrupt` if a section is truncated.
+pub fn inspect(input: &[u8]) -> Result<PsdInfo, PsdError> {
+    Ok(Document::read(input)?.info)
+}
+
+/// Decodes the flattened composite. 16-bit documents stay 16-bit; 32-bit ones are
+/// clamped to 16 bits. CMYK is converted to RGB without color management.
+///
+/// # Errors
+///
+/// Returns the errors of [`inspect`], `PsdError::Unsupported` for Lab and
+/// multichannel documents or an unknown compression, or `PsdError::Corrupt` if the
+/// image data is truncated.
+pub fn decode(input: &[u8]) -> Result<DynamicImage, PsdError> {
+    let document = Document::read(input)?;
+    let info = document.info;
+    let base = match info.color_mode {
+        "bitmap" | "grayscale" | "indexed" | "duotone" => 1,
+        "rgb" => 3,
+        "cmyk" => 4,
+        mode => return Err(PsdError::Unsupported(format!("{mode} color"))),
+    };
+    if !matches!(info.depth, 1 | 8 | 16 | 32) || (info.depth == 1 && info.color_mode != "bitmap") {
+        return Err(PsdError::Unsupported(format!(
+            "{}-bit channels",
+            info.depth
+        )));
+    }
+    if u64::from(info.width) * u64::from(info.height) > MAX_PIXELS {
+        return Err(PsdError::Unsupported(format!(
+            "{}×{} is too large",
+            info.width, info.height
+        )));
+    }
+    let needed = base + usize::from(info.has_alpha);
+    if usize::from(info.channels) < needed {
+        return Err(PsdError::Corrupt("too few channels"));
+    }
+
+    let planes = document.planes(needed)?;
+    let (width, height) = (info.width, info.height);
+    let samples = |index: usize| planes.get(index).map_or(&[][..], Vec::as_slice);
+    let alpha = info.has_alpha.then(|| samples(base));
+    let pixels = planes.first().map_or(0, Vec::len);
+    let mut out = Vec::with_capacity(pixels * 4);
+    let layout = match info.color_mode {
+        "indexed" => {
+            let palette = document.color_data;
+            let entry = |channel: usize, index: u16| {
+                let at = channel * 256 + usize::from(index >> 8);
+                palette.get(at).map_or(0, |&v| u16::from(v) * 257)
+            };
+            for (i, &index) in samples(0).iter().enumerate() {
+                out.extend([entry(0, index), entry(1, index), entry(2, index)]);
+                out.extend(alpha.map(|alpha| alpha.get(i).copied().unwrap_or(u16::MAX)));
+            }
+            if info.has_alpha {
+                Layout::Rgba
+            } else {
+                Layout::Rgb
+            }
+        }
+        "rgb" => {
+            for i in 0..pixels {
+                out.extend((0..3).map(|c| samples(c).get(i).copied().unwrap_or(0)));
+                out.extend(alpha.map(|alpha| alpha.get(i).copied().unwrap_or(u16::MAX)));
+            }
+            if info.has_alpha {
+                Layout::Rgba
+            } else {
+                Layout::Rgb
+            }
+        }
+        "cmyk" => {
+            // PSD stores CMYK inverted (0 is full ink), so each stored value already is
+            // the light that channel lets through.
+            let black = samples(3);
+            for i in 0..pixels {
+                let k = u32::from(black.get(i).copied().unwrap_or(0));
+                out.extend((0..3).map(|c| {
+                    let v = u32::from(samples(c).get(i).copied().unwrap_or(0));
+                    u16::try_from(v * k / 65535).unwrap_or(u16::MAX)
+                }));
+                out.extend(alpha.map(|alpha| alpha.get(i).copied().unwrap_or(u16::MAX)));
+            }
+            if info.has_alpha {
+                Layout::Rgba
+            } else {
+                Layout::Rgb
+            }
+        }
+        _ => {
+            for (i, &v) in samples(0).iter().enumerate() {
+                out.push(v);
+                out.extend(alpha.map(|alpha| alpha.get(i).copied().unwrap_or(u16::MAX)));
+            }
+            if info.has_alpha {
+                Layout::LumaA
+            } else {
+                Layout::Luma
+            }
+        }
+    };
+    image(width, height, layout, out, info.depth > 8)
+        .ok_or(PsdError::Corrupt("image data doesn't match the dimensions"))
+}
+
+/// The decoder the conversion pipeline uses for PSD inputs.
+#[derive(Debug, Clone, Copy)]
+pub struct PsdDecoder;
+
+impl Decoder for PsdDecoder {
+    fn name(&self) -> &'static str {
+        NAME
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        is_psd(input)
+    }
+
+    fn decode(&self, input: &[u8]) -> Result<DynamicImage, CodecError> {
+        decode(input).map_err(|e| CodecError::Failed(e.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    Luma,
+    LumaA,
+    Rgb,
+    Rgba,
+}
+
+/// Builds an image from interleaved 16-bit samples, at 16 bits or narrowed to 8.
+fn image(
+    width: u32,
+    height: u32,
+    layout: Layout,
+    samples: Vec<u16>,
+    wide: bool,
+) -> Option<DynamicImage> {
+    if wide {
+        return match layout {
+            Layout::Luma => ImageBuffer::<Luma<u16>, _>::from_raw(width, height, samples)
+                .map(DynamicImage::ImageLuma16),
+            Layout::LumaA => ImageBuffer::<LumaA<u16>, _>::from_raw(width, height, samples)
+                .map(DynamicImage::ImageLumaA16),
+            Layout::Rgb => ImageBuffer::<Rgb<u16>, _>::from_raw(width, height, samples)
+                .map(DynamicImage::ImageRgb16),
+            Layout::Rgba => ImageBuffer::<Rgba<u16>, _>::from_raw(width, height, samples)
+                .map(DynamicImage::ImageRgba16),
+        };
+    }
+    let samples: Vec<u8> = samples
+        .into_iter()
+        .map(|v| u8::try_from(v >> 8).unwrap_or(u8::MAX))
+        .collect();
+    match layout {
+        Layout::Luma => ImageBuffer::<Luma<u8>, _>::from_raw(width, height, samples)
+            .map(DynamicImage::ImageLuma8),
+        Layout::LumaA => ImageBuffer::<LumaA<u8>, _>::from_raw(width, height, samples)
+            .map(DynamicImage::ImageLumaA8),
+        Layout::Rgb => {
+            ImageBuffer::<Rgb<u8>, _>::from_raw(width, height, samples).map(DynamicImage::ImageRgb8)
+        }
+        Layout::Rgba => ImageBuffer::<Rgba<u8>, _>::from_raw(width, height, samples)
+            .map(DynamicImage::ImageRgba8),
+    }
+}
+
+/// The sections of a PSD file this module reads.
+struct Document<'a> {
+    info: PsdInfo,
+    /// The color mode data section: the palette of indexed documents.
+    color_data: &'a [u8],
+    /// The image data section: compression, then the composite's channels.
+    image_data: &'a [u8],
+}
+
+impl<'a> Document<'a> {
+    fn read(input: &'a [u8]) -> Result<Self, PsdError> {
+        if !is_psd(input) {
+            return Err(PsdError::NotPsd);
+        }
+        let mut reader = Reader {
+            data: input,
+            pos: 4,
+        };
+        let large_document = reader.u16()? == 2;
+        reader.skip(6)?;
+        let channels = reader.u16()?;
+        let height = reader.u32()?;
+        let width = reader.u32()?;
+        let depth = reader.u16()?;
+        let color_mode = match reader.u16()? {
+            0 => "bitmap",
+            1 => "grayscale",
+            2 => "indexed",
+            3 => "rgb",
+            4 => "cmyk",
+            7 => "multichannel",
+            8 => "duotone",
+            9 => "lab",
+            _ => return Err(PsdError::Corrupt("unknown color mode")),
+        };
+        let length = reader.u32()?;
+        let color_data = reader.take(u64::from(length))?;
+        let length = reader.u32()?;
+        reader.skip(u64::from(length))?;
+
+        // The layer and mask section; lengths are 8 bytes in large documents.
+        let length = reader.length(large_document)?;
+        let mut layers_section = Reader {
+            data: reader.take(length)?,
+            pos: 0,
+        };
+        let layer_count = if length == 0 || layers_section.length(large_document)? == 0 {
+            0
+        } else {
+            i16::from_be_bytes(layers_section.array()?)
+        };
+
+        Ok(Self {
+            info: PsdInfo {
+                width,
+                height,
+                channels,
+                depth,
+                color_mode,
+                layers: u32::from(layer_count.unsigned_abs()),
+                // A negative layer count says the first extra channel is the
+                // composite's transparency rather than a saved selection.
+                has_alpha: layer_count < 0,
+                large_document,
+            },
+            color_data,
+            image_data: reader.data.get(reader.pos..).unwrap_or_default(),
+        })
+    }
+
+    /// The first `count` channels of the composite, as 16-bit samples.
+    fn planes(&self, count: usize) -> Result<Vec<Vec<u16>>, PsdError> {
+        let info = self.info;
+        let width = usize::try_from(info.width).map_err(|_| PsdError::Corrupt("width"))?;
+        let height = usize::try_from(info.height).map_err(|_| PsdError::Corrupt("height"))?;
+        let row_bytes = (width * usize::from(info.depth)).div_ceil(8);
+        let mut reader = Reader {
+            data: self.image_data,
+            pos: 0,
+        };
+        let compression = reader.u16()?;
+        let rows: Vec<Vec<u8>> = match compression {
+            0 => (0..count * height)
+                .map(|_| {
+                    reader
+                        .take(u64::try_from(row_bytes).unwrap_or(u64::MAX))
+                        .map(<[u8]>::to_vec)
+                })
+                .collect::<Result<_, _>>()?,
+            1 => {
+                // Byte counts for every row of every channel come first.
+                let all_rows = usize::from(info.channels) * height;
+                let counts = (0..all_rows)
+                    .map(|_| {
+                        if info.large_document {
+                            reader.u32().map(u64::from)
+                        } else {
+                            reader.u16().map(u64::from)
+                        }
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                counts
+                    .iter()
+                    .take(count * height)
+                    .map(|&length| unpack_bits(reader.take(length)?, row_bytes))
+                    .collect::<Result<_, _>>()?
+            }
+            other => {
+                return Err(PsdError::Unsupported(format!(
+                    "composite compression {other}"
+                )))
+            }
+        };
+
+        Ok(rows
+            .chunks(height.max(1))
+            .map(|channel| {
+                channel
+                    .iter()
+                    .flat_map(|row| samples(row, width, info.depth))
+                    .collect()
+            })
+            .collect())
+    }
+}
+
+/// The `width` samples of one row, scaled to 16 bits. Bitmap rows have one bit per
+/// pixel with 1 meaning black.
+fn samples(row: &[u8], width: usize, depth: u16) -> Vec<u16> {
+    match depth {
+        1 => (0..width)
+            .map(|x| {
+                let byte = row.get(x / 8).copied().unwrap_or(0);
+                if byte & (0x80 >> (x % 8)) == 0 {
+                    u16::MAX
+                } else {
+                    0
+                }
+            })
+            .collect(),
+        8 => row.iter().map(|&v| u16::from(v) * 257).collect(),
+        16 => row
+            .as_chunks()
+            .0
+            .iter()
+            .map(|&pair| u16::from_be_bytes(pair))
+            .collect(),
+        _ => row
+            .as_chunks()
+            .0
+            .iter()
+            .map(|&quad| float_to_u16(f32::from_be_bytes(quad)))
+            .collect(),
+    }
+}
+
+/// Clamps a linear 0-1 float sample to 16 bits.
+#[allow(clippy::as_conversions)]
+fn float_to_u16(value: f32) -> u16 {
+    // Safe: the value is clamped to 0..=65535 before the cast.
+    (f64::from(value) * 65535.0).round().clamp(0.0, 65535.0) as u16
+}
+
+/// Expands one PackBits-compressed row to `row_bytes` bytes.
+fn unpack_bits(packed: &[u8], row_bytes: usize) -> Result<Vec<u8>, PsdError> {
+    let mut row = Vec::with_capacity(row_bytes);
+    let mut bytes = packed.iter();
+    while let Some(&header) = bytes.next() {
+        let header = i8::from_be_bytes([header]);
+        match header {
+            0..=127 => {
+                for _ in 0..=header {
+                    row.push(*bytes.next().ok_or(PsdError::Corrupt("truncated RLE run"))?);
+                }
+            }
+            -127..=-1 => {
+                let value = *bytes.next().ok_or(PsdError::Corrupt("truncated RLE run"))?;
+                let repeat = usize::from(header.unsigned_abs()) + 1;
+                row.extend(std::iter::repeat_n(value, repeat));
+            }
+            -128 => {}
+        }
+    }
+    if row.len() < row_bytes {
+        return Err(PsdError::Corrupt("short RLE row"));
+    }
+    row.truncate(row_bytes);
+    Ok(row)
+}
+
+/// A big-endian cursor over a section.
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: u64) -> Result<&'a [u8], PsdError> {
+        let end = usize::try_from(length)
+            .ok()
+            .and_then(|length| self.pos.checked_add(length))
+            .ok_or(PsdError::Corrupt("section length"))?;
+        let bytes = self
+            .data
+            .get(self.pos..end)
+            .ok_or(PsdError::Corrupt("truncated section"))?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn skip(&mut self, length: u64) -> Result<(), PsdError> {
+        self.take(length).map(|_| ())
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], PsdError> {
+        self.take(u64::try_from(N).unwrap_or(u64::MAX))?
+            .try_into()
+            .map_err(|_| PsdError::Corrupt("truncated section"))
+    }
+
+    fn u16(&mut self) -> Result<u16, PsdError> {
+        self.array().map(u16::from_be_bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, PsdError> {
+        self.array().map(u32::from_be_bytes)
+    }
+
+    /// A section length: 4 bytes, or 8 in large documents.
+    fn length(&mut self, large_document: bool) -> Result<u64, PsdError> {
+        if large_document {
+            self.array().map(u64::from_be_bytes)
+        } else {
+            self.u32().map(u64::from)
+        }
+    }
+}
+
+/// Errors that can occur while reading PSD files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PsdError {
+    /// The input isn't a PSD or PSB file.
+    NotPsd,
+    /// The document uses something this module doesn't decode.
+    Unsupported(String),
+    /// The file is malformed or truncated.
+    Corrupt(&'static str),
+}
+
+impl fmt::Display for PsdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotPsd => write!(f, "Input is not a PSD file"),
+            Self::Unsupported(what) => write!(f, "Unsupported PSD: {what}"),
+            Self::Corrupt(what) => write!(f, "Invalid PSD data: {what}"),
+        }
+    }
+}
+
+impl std::error::Error for PsdError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert;
+    use crate::formats::ImageFormat;
+
+    /// Packs each row as a single literal run.
+    fn pack(row: &[u8]) -> Vec<u8> {
+        let mut packed = vec![u8::try_from(row.len() - 1).unwrap()];
+        packed.extend(row);
+        packed
+    }
+
+    /// A PSD with the given header fields, layer count and channel rows (one `Vec` per
+    /// row, every row of channel 0 first), RLE-compressed.
+    fn psd(
+        mode: u16,
+        depth: u16,
+        size: (u32, u32),
+        layers: i16,
+        channels: &[Vec<Vec<u8>>],
+    ) -> Vec<u8> {
+        let mut file = b"8BPS\0\x01".to_vec();
+        file.extend([0; 6]);
+        file.extend(u16::try_from(channels.len()).unwrap().to_be_bytes());
+        file.extend(size.1.to_be_bytes());
+        file.extend(size.0.to_be_bytes());
+        file.extend(depth.to_be_bytes());
+        file.extend(mode.to_be_bytes());
+        file.extend(0u32.to_be_bytes());
+        file.extend(0u32.to_be_bytes());
+        // Layer info holding only the count; real files follow it with the layers.
+        file.extend(10u32.to_be_bytes());
+        file.extend(6u32.to_be_bytes());
+        file.extend(layers.to_be_bytes());
+        file.extend([0; 4]);
+        file.extend(1u16.to_be_bytes());
+        let packed: Vec<Vec<u8>> = channels.iter().flatten().map(|row| pack(row)).collect();
+        for row in &packed {
+            file.extend(u16::try_from(row.len()).unwrap().to_be_bytes());
+        }
+        for row in &packed {
+            file.extend(row);
+        }
+        file
+    }
+
+    #[test]
+    fn decodes_the_rgb_composite_with_alpha() {
+        let rows = |a: u8, b: u8| vec![vec![a, a, a], vec![b, b, b]];
+        let file = psd(
+            3,
+            8,
+            (3, 2),
+            -2,
+            &[rows(255, 0), rows(0, 255), rows(0, 0), rows(255, 128)],
+        );
+        let info = inspect(&file).unwrap();
+        assert_eq!(
+            (info.layers, info.has_alpha, info.color_mode),
+            (2, true, "rgb")
+        );
+
+        let img = decode(&file).unwrap().into_rgba8();
+        assert_eq!(img.get_pixel(0, 0).0, [255, 0, 0, 255]);
+        assert_eq!(img.get_pixel(2, 1).0, [0, 255, 0, 128]);
+
+        let png = convert::convert(file, ImageFormat::Png, None, &[]).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!((img.width(), img.height()), (3, 2));
+    }
+
+    #[test]
+    fn decodes_cmyk_and_16_bit_gray_and_keeps_extra_channels_out() {
+        // Stored inverted: 255 is no ink, so cyan ink with no black is (0, 255, 255).
+        let file = psd(
+            4,
+            8,
+            (1, 1),
+            0,
+            &[
+                vec![vec![0]],
+                vec![vec![255]],
+                vec![vec![255]],
+                vec![vec![255]],
+                vec![vec![0]],
+            ],
+        );
+        let img = decode(&file).unwrap();
+        assert_eq!(img.color(), image::ColorType::Rgb8);
+        assert_eq!(img.into_rgb8().get_pixel(0, 0).0, [0, 255, 255]);
+
+        let file = psd(1, 16, (2, 1), 0, &[vec![vec![0x12, 0x34, 0xFF, 0xFF]]]);
+        let img = decode(&file).unwrap().into_luma16();
+        assert_eq!(img.as_raw(), &[0x1234, 0xFFFF]);
+        assert_eq!(inspect(&file).unwrap().layers, 0);
+
+        assert_eq!(
+            decode(&psd(
+                9,
+                8,
+                (1, 1),
+                0,
+                &[vec![vec![0]], vec![vec![0]], vec![vec![0]]]
+            )),
+            Err(PsdError::Unsupported("lab color".into()))
+        );
+        assert_eq!(inspect(b"8BPS"), Err(PsdError::NotPsd));
+    }
+}
@@ -0,0 +1,1041 @@
+//! Reading the payloads of the QR codes [`codes::find`] locates.
+//!
+//! Each code's modules are sampled through the perspective its finder patterns and,
+//! from version 2 on, its bottom-right alignment pattern give. They are then read as
+//! ISO/IEC 18004 lays them out: format and version information, the data mask,
+//! Reed-Solomon error correction of each block, and numeric, alphanumeric, byte and ECI
+//! segments. Kanji segments, mirrored codes and light-on-dark codes aren't read.
+
+use image::{DynamicImage, GrayImage, Luma, RgbaImage};
+use serde::Serialize;
+
+use crate::codes::{self, Finder, QrCodeLocation};
+use crate::stats;
+
+/// A QR code read from an image.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct QrCode {
+    /// The decoded text. Byte segments are read as UTF-8, or as ISO-8859-1 (the
+    /// standard's default) when an ECI says so or they aren't valid UTF-8.
+    pub payload: String,
+    /// Version, 1 to 40: the code is `17 + 4 * version` modules on a side.
+    pub version: u8,
+    pub error_correction: ErrorCorrection,
+    pub location: QrCodeLocation,
+}
+
+/// How much of a code can be damaged and still be read: about 7% (`L`), 15% (`M`),
+/// 25% (`Q`) or 30% (`H`) of its codewords.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum ErrorCorrection {
+    L,
+    M,
+    Q,
+    H,
+}
+
+impl ErrorCorrection {
+    /// The level the two format information bits `bits` encode.
+    fn from_bits(bits: u32) -> Self {
+        match bits & 3 {
+            1 => Self::L,
+            0 => Self::M,
+            3 => Self::Q,
+            _ => Self::H,
+        }
+    }
+
+    /// This level's row in [`ECC_CODEWORDS_PER_BLOCK`] and [`ERROR_CORRECTION_BLOCKS`].
+    fn index(self) -> usize {
+        match self {
+            Self::L => 0,
+            Self::M => 1,
+            Self::Q => 2,
+            Self::H => 3,
+        }
+    }
+}
+
+/// Error correction codewords in each block, by level and then version 1 to 40.
+const ECC_CODEWORDS_PER_BLOCK: [[u8; 40]; 4] = [
+    [
+        7, 10, 15, 20, 26, 18, 20, 24, 30, 18, 20, 24, 26, 30, 22, 24, 28, 30, 28, 28, 28, 28, 30,
+        30, 26, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        10, 16, 26, 18, 24, 16, 18, 22, 22, 26, 30, 22, 22, 24, 24, 28, 28, 26, 26, 26, 26, 28, 28,
+        28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28, 28,
+    ],
+    [
+        13, 22, 18, 26, 18, 24, 18, 22, 20, 24, 28, 26, 24, 20, 30, 24, 28, 28, 26, 30, 28, 30, 30,
+        30, 30, 28, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+    [
+        17, 28, 22, 16, 22, 28, 26, 26, 24, 28, 24, 28, 22, 24, 24, 30, 28, 28, 26, 28, 30, 24, 30,
+        30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30, 30,
+    ],
+];
+
+/// Error correction blocks the codewords are split into, by level and then version
+/// 1 to 40.
+const ERROR_CORRECTION_BLOCKS: [[u8; 40]; 4] = [
+    [
+        1, 1, 1, 1, 1, 2, 2, 2, 2, 4, 4, 4, 4, 4, 6, 6, 6, 6, 7, 8, 8, 9, 9, 10, 12, 12, 12, 13,
+        14, 15, 16, 17, 18, 19, 19, 20, 21, 22, 24, 25,
+    ],
+    [
+        1, 1, 1, 2, 2, 4, 4, 4, 5, 5, 5, 8, 9, 9, 10, 10, 11, 13, 14, 16, 17, 17, 18, 20, 21, 23,
+        25, 26, 28, 29, 31, 33, 35, 37, 38, 40, 43, 45, 47, 49,
+    ],
+    [
+        1, 1, 2, 2, 4, 4, 6, 6, 8, 8, 8, 10, 12, 16, 12, 17, 16, 18, 21, 20, 23, 23, 25, 27, 29,
+        34, 34, 35, 38, 40, 43, 45, 48, 51, 53, 56, 59, 62, 65, 68,
+    ],
+    [
+        1, 1, 2, 4, 4, 4, 5, 6, 8, 8, 11, 11, 16, 16, 18, 16, 19, 21, 25, 25, 25, 34, 30, 32, 35,
+        37, 40, 42, 45, 48, 51, 54, 57, 60, 63, 66, 70, 74, 77, 81,
+    ],
+];
+
+/// The characters of alphanumeric segments, by value.
+const ALPHANUMERIC: &[u8; 45] = b"0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ $%*+-./:";
+
+/// Reads the QR codes in `img`, largest first. Codes that are located (see
+/// [`codes::locate`]) but can't be read, e.g. because they are too damaged for their
+/// error correction, are left out.
+pub fn read(img: &RgbaImage) -> Vec<QrCode> {
+    let gray = DynamicImage::ImageRgba8(img.clone()).into_luma8();
+    let threshold = stats::otsu_threshold(gray.as_raw());
+    let mut read: Vec<QrCode> = codes::find(&gray, threshold)
+        .into_iter()
+        .filter_map(|finders| read_at(&gray, threshold, finders))
+        .collect();
+    read.sort_by_key(|code| {
+        std::cmp::Reverse(u64::from(code.location.width) * u64::from(code.location.height))
+    });
+    read
+}
+
+/// Reads the code whose finder patterns [`codes::find`] found in `gray`, where pixels
+/// at or below `threshold` are dark, or `None` if it can't be read.
+pub(crate) fn read_at(gray: &GrayImage, threshold: u8, finders: [Finder; 3]) -> Option<QrCode> {
+    let [p, corner, q] = finders;
+    let (payload, version, error_correction) = Sampler { gray, threshold }.read(p, corner, q)?;
+    Some(QrCode {
+        payload,
+        version,
+        error_correction,
+        location: codes::bounds(p, corner, q, gray.width(), gray.height()),
+    })
+}
+
+/// Reads modules from a thresholded image.
+struct Sampler<'a> {
+    gray: &'a GrayImage,
+    /// Pixels at or below this are dark.
+    threshold: u8,
+}
+
+impl Sampler<'_> {
+    /// Reads the code whose finder patterns are `p`, `corner` and `q`, returning its
+    /// payload, version and error correction level.
+    fn read(&self, p: Finder, corner: Finder, q: Finder) -> Option<(String, u8, ErrorCorrection)> {
+        // Going clockwise from the top-right pattern reaches the bottom-left one.
+        let cross = (p.x - corner.x) * (q.y - corner.y) - (p.y - corner.y) * (q.x - corner.x);
+        let (top_right, bottom_left) = if cross > 0.0 { (p, q) } else { (q, p) };
+        let finders = [corner, top_right, bottom_left];
+
+        // Finder centers are `size - 7` modules apart, and `size` is `17 + 4 * version`.
+        let module = finders.iter().map(|finder| finder.module).sum::<f64>() / 3.0;
+        let distance = |a: Finder, b: Finder| (b.x - a.x).hypot(b.y - a.y);
+        let span = (distance(corner, top_right) + distance(corner, bottom_left)) / 2.0 / module;
+        let estimate =
+            u8::try_from(codes::to_u32(((span - 10.0) / 4.0).round()).clamp(1, 40)).unwrap_or(1);
+
+        for version in [estimate, estimate - 1, estimate + 1] {
+            if !(1..=40).contains(&version) {
+                continue;
+            }
+            let grid = self.sample(finders, version, module)?;
+            // Larger codes spell out their version, which beats the estimate.
+            let read_version = match grid.version() {
+                Some(read) if version >= 7 && read != version => read,
+                Some(_) | None => version,
+            };
+            let grid = if read_version == version {
+                grid
+            } else {
+                self.sample(finders, read_version, module)?
+            };
+            if let Some((payload, level)) = grid.decode(read_version) {
+                return Some((payload, read_version, level));
+            }
+        }
+        None
+    }
+
+    /// Samples the modules of a `version` code with the given finder patterns (top
+    /// left, top right, bottom left).
+    fn sample(&self, finders: [Finder; 3], version: u8, module: f64) -> Option<Grid> {
+        let size = 17 + 4 * u32::from(version);
+        let [top_left, top_right, bottom_left] = finders.map(|finder| (finder.x, finder.y));
+        // Finder centers sit 3.5 modules in from the code's edges.
+        let far = f64::from(size) - 3.5;
+        let bottom_right = (
+            top_right.0 + bottom_left.0 - top_left.0,
+            top_right.1 + bottom_left.1 - top_left.1,
+        );
+        let affine = Perspective::between(
+            [(3.5, 3.5), (far, 3.5), (far, far), (3.5, far)],
+            [top_left, top_right, bottom_right, bottom_left],
+        )?;
+        // The bottom-right alignment pattern's center is 3 modules further in, and
+        // pins down the perspective the finder patterns alone can't.
+        let transform = (version >= 2)
+            .then(|| self.alignment(affine, far - 3.0, module))
+            .flatten()
+            .and_then(|alignment| {
+                Perspective::between(
+                    [(3.5, 3.5), (far, 3.5), (far - 3.0, far - 3.0), (3.5, far)],
+                    [top_left, top_right, alignment, bottom_left],
+                )
+            })
+            .unwrap_or(affine);
+
+        let dark = (0..size)
+            .flat_map(|row| (0..size).map(move |col| (col, row)))
+            .map(|(col, row)| {
+                self.dark(transform.apply(f64::from(col) + 0.5, f64::from(row) + 0.5))
+            })
+            .collect();
+        Some(Grid { size, dark })
+    }
+
+    /// Finds the alignment pattern `affine` puts near module point (`center`,
+    /// `center`): the middle of the pixels within 4 modules whose 5x5 neighborhood
+    /// matches the pattern's dark ring and center exactly.
+    fn alignment(&self, affine: Perspective, center: f64, module: f64) -> Option<(f64, f64)> {
+        let (x, y) = affine.apply(center, center);
+        let right = affine.apply(center + 1.0, center);
+        let down = affine.apply(center, center + 1.0);
+        let (rx, ry) = (right.0 - x, right.1 - y);
+        let (dx, dy) = (down.0 - x, down.1 - y);
+        let matches = |cx: f64, cy: f64| {
+            (-2i32..=2)
+                .flat_map(|j| (-2i32..=2).map(move |i| (i, j)))
+                .filter(|&(i, j)| {
+                    let (u, v) = (f64::from(i), f64::from(j));
+                    let point = (cx + u * rx + v * dx, cy + u * ry + v * dy);
+                    self.dark(point) == (i.abs().max(j.abs()) != 1)
+                })
+                .count()
+        };
+
+        let radius = i32::try_from(codes::to_u32((module * 4.0).ceil())).unwrap_or(0);
+        let (mut found, mut sum_x, mut sum_y) = (0u32, 0.0, 0.0);
+        for j in -radius..=radius {
+            for i in -radius..=radius {
+                let (cx, cy) = (x + f64::from(i), y + f64::from(j));
+                if matches(cx, cy) == 25 {
+                    found += 1;
+                    sum_x += cx;
+                    sum_y += cy;
+                }
+            }
+        }
+        (found > 0).then(|| (sum_x / f64::from(found), sum_y / f64::from(found)))
+    }
+
+    /// Whether the pixel at `point` is dark; points outside the image are light.
+    fn dark(&self, (x, y): (f64, f64)) -> bool {
+        if !(x >= 0.0 && y >= 0.0) {
+            return false;
+        }
+        self.gray
+            .get_pixel_checked(codes::to_u32(x), codes::to_u32(y))
+            .is_some_and(|&Luma([value])| value <= self.threshold)
+    }
+}
+
+/// A perspective transform from module coordinates (a code's modules span `0..size`
+/// on each axis) to image pixels, as a 3x3 matrix in row-major order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Perspective([f64; 9]);
+
+impl Perspective {
+    /// The transform taking each of the four points `from` to the matching point in
+    /// `to`, or `None` if either four are degenerate (three in a line).
+    fn between(from: [(f64, f64); 4], to: [(f64, f64); 4]) -> Option<Self> {
+        let Self(to_square) = Self::square_to_quad(from)?.adjugate();
+        let Self(to_quad) = Self::square_to_quad(to)?;
+        let [a, b, c, d, e, f, g, h, i] = to_quad;
+        let [j, k, l, m, n, o, p, q, r] = to_square;
+        Some(Self([
+            a * j + b * m + c * p,
+            a * k + b * n + c * q,
+            a * l + b * o + c * r,
+            d * j + e * m + f * p,
+            d * k + e * n + f * q,
+            d * l + e * o + f * r,
+            g * j + h * m + i * p,
+            g * k + h * n + i * q,
+            g * l + h * o + i * r,
+        ]))
+    }
+
+    /// The transform taking the unit square's corners (0, 0), (1, 0), (1, 1) and
+    /// (0, 1) onto `corners`, after Heckbert's square-to-quad construction.
+    fn square_to_quad(corners: [(f64, f64); 4]) -> Option<Self> {
+        let [(x0, y0), (x1, y1), (x2, y2), (x3, y3)] = corners;
+        let sx = x0 - x1 + x2 - x3;
+        let sy = y0 - y1 + y2 - y3;
+        let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+        let den = dx1 * dy2 - dx2 * dy1;
+        if den.abs() < f64::EPSILON {
+            return None;
+        }
+        let g = (sx * dy2 - dx2 * sy) / den;
+        let h = (dx1 * sy - sx * dy1) / den;
+        Some(Self([
+            x1 - x0 + g * x1,
+            x3 - x0 + h * x3,
+            x0,
+            y1 - y0 + g * y1,
+            y3 - y0 + h * y3,
+            y0,
+            g,
+            h,
+            1.0,
+        ]))
+    }
+
+    /// The adjugate, which inverts the transform (up to the scale a perspective
+    /// divides out anyway).
+    fn adjugate(self) -> Self {
+        let [a, b, c, d, e, f, g, h, i] = self.0;
+        Self([
+            e * i - f * h,
+            c * h - b * i,
+            b * f - c * e,
+            f * g - d * i,
+            a * i - c * g,
+            c * d - a * f,
+            d * h - e * g,
+            b * g - a * h,
+            a * e - b * d,
+        ])
+    }
+
+    /// The pixel for module point (`u`, `v`).
+    fn apply(self, u: f64, v: f64) -> (f64, f64) {
+        let [a, b, c, d, e, f, g, h, i] = self.0;
+        let w = g * u + h * v + i;
+        ((a * u + b * v + c) / w, (d * u + e * v + f) / w)
+    }
+}
+
+/// A code's modules, `size` x `size` in row-major order, dark as `true`.
+struct Grid {
+    size: u32,
+    dark: Vec<bool>,
+}
+
+impl Grid {
+    /// Whether the module at column `col` and row `row` is dark.
+    fn get(&self, col: u32, row: u32) -> bool {
+        let index = u64::from(row) * u64::from(self.size) + u64::from(col);
+        usize::try_from(index)
+            .ok()
+            .and_then(|index| self.dark.get(index).copied())
+            .unwrap_or(false)
+    }
+
+    /// Decodes the payload of this `version` code, returning it with the error
+    /// correction level.
+    fn decode(&self, version: u8) -> Option<(String, ErrorCorrection)> {
+        let (level, mask) = self.format()?;
+        let codewords = self.codewords(version, mask);
+        let data = correct_blocks(&codewords, version, level)?;
+        Some((decode_segments(&data, version)?, level))
+    }
+
+    /// The error correction level and data mask, from whichever copy of the format
+    /// information is closer to a valid one, if either is within 3 bits.
+    fn format(&self) -> Option<(ErrorCorrection, u32)> {
+        let size = self.size;
+        let first = bits(15, |i| match i {
+            0..=5 => self.get(8, i),
+            6 => self.get(8, 7),
+            7 => self.get(8, 8),
+            8 => self.get(7, 8),
+            _ => self.get(14 - i, 8),
+        });
+        let second = bits(15, |i| {
+            if i < 8 {
+                self.get(size - 1 - i, 8)
+            } else {
+                self.get(8, size - 15 + i)
+            }
+        });
+        let data = nearest(0..32, format_bits, [first, second])?;
+        Some((ErrorCorrection::from_bits(data >> 3), data & 7))
+    }
+
+    /// The version spelled out in the version information of codes from version 7,
+    /// from whichever copy is closer to a valid one, if either is within 3 bits.
+    fn version(&self) -> Option<u8> {
+        let corner = self.size.checked_sub(11)?;
+        let top_right = bits(18, |i| self.get(corner + i % 3, i / 3));
+        let bottom_left = bits(18, |i| self.get(i / 3, corner + i % 3));
+        let version = nearest(7..41, version_bits, [top_right, bottom_left])?;
+        u8::try_from(version).ok()
+    }
+
+    /// The codewords in the data area of this `version` code, unmasked with `mask`,
+    /// read in the standard's two-column zigzag from the bottom right.
+    fn codewords(&self, version: u8, mask: u32) -> Vec<u8> {
+        let size = self.size;
+        let alignment = alignment_positions(version);
+        let mut bits = Vec::new();
+        let mut right = size - 1;
+        loop {
+            // The vertical timing pattern's column is skipped.
+            if right == 6 {
+                right = 5;
+            }
+            let upward = (right + 1) & 2 == 0;
+            for vertical in 0..size {
+                let row = if upward {
+                    size - 1 - vertical
+                } else {
+                    vertical
+                };
+                for col in [right, right - 1] {
+                    if !is_function(version, size, &alignment, col, row) {
+                        bits.push(self.get(col, row) ^ masked(mask, col, row));
+                    }
+                }
+            }
+            match right.checked_sub(2) {
+                Some(next) if next >= 1 => right = next,
+                _ => break,
+            }
+        }
+        // Leftover remainder bits don't make a whole codeword.
+        bits.chunks_exact(8)
+            .map(|byte| byte.iter().fold(0u8, |acc, &bit| acc << 1 | u8::from(bit)))
+            .collect()
+    }
+}
+
+/// A `count`-bit number whose bit `i` is `bit(i)`.
+fn bits(count: u32, bit: impl Fn(u32) -> bool) -> u32 {
+    (0..count)
+        .filter(|&i| bit(i))
+        .fold(0, |acc, i| acc | 1 << i)
+}
+
+/// The value in `values` whose codeword is nearest to either of `read`, if that is
+/// within 3 bits.
+fn nearest(values: std::ops::Range<u32>, codeword: fn(u32) -> u32, read: [u32; 2]) -> Option<u32> {
+    values
+        .flat_map(|value| read.map(|bits| (value, (bits ^ codeword(value)).count_ones())))
+        .min_by_key(|&(_, distance)| distance)
+        .filter(|&(_, distance)| distance <= 3)
+        .map(|(value, _)| value)
+}
+
+/// The 15-bit format information for the 5 bits `data` (level and mask): a BCH code,
+/// masked so it is never all light.
+fn format_bits(data: u32) -> u32 {
+    let mut remainder = data;
+    for _ in 0..10 {
+        remainder = (remainder << 1) ^ ((remainder >> 9) * 0x537);
+    }
+    (data << 10 | remainder) ^ 0x5412
+}
+
+/// The 18-bit version information for `version`: a BCH code.
+fn version_bits(version: u32) -> u32 {
+    let mut remainder = version;
+    for _ in 0..12 {
+        remainder = (remainder << 1) ^ ((remainder >> 11) * 0x1F25);
+    }
+    version << 12 | remainder
+}
+
+/// The rows (and columns) of a `version` code's alignment pattern centers.
+fn alignment_positions(version: u8) -> Vec<u32> {
+    if version < 2 {
+        return Vec::new();
+    }
+    let version = u32::from(version);
+    let count = version / 7 + 2;
+    let step = if version == 32 {
+        26
+    } else {
+        (version * 4 + count * 2 + 1) / (count * 2 - 2) * 2
+    };
+    let size = 17 + 4 * version;
+    let mut positions: Vec<u32> = (0..count - 1).map(|i| size - 7 - i * step).collect();
+    positions.push(6);
+    positions.reverse();
+    positions
+}
+
+/// Whether module (`col`, `row`) of a `version` code of `size` modules is part of a
+/// function pattern (finders and their separators, timing, alignment, format and
+/// version information) rather than data.
+fn is_function(version: u8, size: u32, alignment: &[u32], col: u32, row: u32) -> bool {
+    let near_end = |value: u32| value + 8 >= size;
+    let finder = (row <= 8 && (col <= 8 || near_end(col))) || (col <= 8 && near_end(row));
+    let timing = col == 6 || row == 6;
+    let (first, last) = (alignment.first(), alignment.last());
+    let aligned = alignment.iter().any(|&y| {
+        alignment.iter().any(|&x| {
+            let overlaps_finder = (Some(&x) == first && (Some(&y) == first || Some(&y) == last))
+                || (Some(&x) == last && Some(&y) == first);
+            !overlaps_finder && col.abs_diff(x) <= 2 && row.abs_diff(y) <= 2
+        })
+    });
+    let version_info = version >= 7
+        && ((col + 11 >= size && col + 8 < size && row < 6)
+            || (row + 11 >= size && row + 8 < size && col < 6));
+    finder || timing || aligned || version_info
+}
+
+/// Whether data mask `mask` flips module (`col`, `row`).
+fn masked(mask: u32, col: u32, row: u32) -> bool {
+    let (x, y) = (col, row);
+    match mask {
+        0 => (x + y) % 2 == 0,
+        1 => y % 2 == 0,
+        2 => x % 3 == 0,
+        3 => (x + y) % 3 == 0,
+        4 => (x / 3 + y / 2) % 2 == 0,
+        5 => x * y % 2 + x * y % 3 == 0,
+        6 => (x * y % 2 + x * y % 3) % 2 == 0,
+        _ => ((x + y) % 2 + x * y % 3) % 2 == 0,
+    }
+}
+
+/// Splits the interleaved `codewords` of a `version` code into its blocks, corrects
+/// each, and returns their data codewords in order. `None` if a block has more errors
+/// than its error correction can fix.
+fn correct_blocks(codewords: &[u8], version: u8, level: ErrorCorrection) -> Option<Vec<u8>> {
+    let table = |table: &[[u8; 40]; 4]| {
+        table
+            .get(level.index())
+            .and_then(|row| row.get(usize::from(version).checked_sub(1)?))
+            .map(|&value| usize::from(value))
+    };
+    let (blocks, ecc) = (
+        table(&ERROR_CORRECTION_BLOCKS)?,
+        table(&ECC_CODEWORDS_PER_BLOCK)?,
+    );
+    // The last `codewords.len() % blocks` blocks hold one more data codeword.
+    let short_blocks = blocks - codewords.len() % blocks;
+    let short_data = (codewords.len() / blocks).checked_sub(ecc)?;
+    let data_len = |block: usize| short_data + usize::from(block >= short_blocks);
+
+    let mut split: Vec<Vec<u8>> = (0..blocks)
+        .map(|block| Vec::with_capacity(data_len(block) + ecc))
+        .collect();
+    let mut next = codewords.iter().copied();
+    for i in 0..=short_data {
+        for (index, block) in split.iter_mut().enumerate() {
+            if i < data_len(index) {
+                block.push(next.next()?);
+            }
+        }
+    }
+    for _ in 0..ecc {
+        for block in &mut split {
+            block.push(next.next()?);
+        }
+    }
+
+    let mut data = Vec::new();
+    for (index, mut block) in split.into_iter().enumerate() {
+        reed_solomon::correct(&mut block, ecc)?;
+        data.extend_from_slice(block.get(..data_len(index))?);
+    }
+    Some(data)
+}
+
+/// Reads the segments in `data`, the corrected data codewords of a `version` code,
+/// into text. `None` for Kanji and reserved modes, and for segments cut short.
+fn decode_segments(data: &[u8], version: u8) -> Option<String> {
+    let mut reader = BitReader { data, position: 0 };
+    // Character counts are longer in larger codes.
+    let count_bits = |short: u32, medium: u32, long: u32| match version {
+        0..=9 => short,
+        10..=26 => medium,
+        _ => long,
+    };
+    let mut bytes = Vec::new();
+    let mut latin1 = false;
+    // Fewer than 4 bits left ends the data as a terminator would.
+    while let Some(mode) = reader.read(4) {
+        match mode {
+            0 => break,
+            // Numeric: three digits in 10 bits, with 7 or 4 bits for the last one or two.
+            1 => {
+                let mut count = reader.read(count_bits(10, 12, 14))?;
+                while count > 0 {
+                    let (digits, width) = match count {
+                        1 => (1u8, 4),
+                        2 => (2, 7),
+                        _ => (3, 10),
+                    };
+                    let value = reader.read(width)?;
+                    let text = format!("{value:0digits$}", digits = usize::from(digits));
+                    if text.len() != usize::from(digits) {
+                        return None;
+                    }
+                    bytes.extend_from_slice(text.as_bytes());
+                    count -= u32::from(digits);
+                }
+            }
+            // Alphanumeric: two characters in 11 bits, with 6 bits for an odd last one.
+            2 => {
+                let mut count = reader.read(count_bits(9, 11, 13))?;
+                let character = |value: u32| {
+                    usize::try_from(value)
+                        .ok()
+                        .and_then(|index| ALPHANUMERIC.get(index).copied())
+                };
+                while count >= 2 {
+                    let value = reader.read(11)?;
+                    bytes.push(character(value / 45)?);
+                    bytes.push(character(value % 45)?);
+                    count -= 2;
+                }
+                if count == 1 {
+                    bytes.push(character(reader.read(6)?)?);
+                }
+            }
+            // Byte: eight bits each.
+            4 => {
+                let count = reader.read(count_bits(8, 16, 16))?;
+                for _ in 0..count {
+                    bytes.push(u8::try_from(reader.read(8)?).ok()?);
+                }
+            }
+            // ECI: a one- to three-byte designator; 1 and 3 are ISO-8859-1.
+            7 => {
+                let first = reader.read(8)?;
+                let designator = match first {
+                    0..=0x7F => first,
+                    0x80..=0xBF => (first & 0x3F) << 8 | reader.read(8)?,
+                    _ => (first & 0x1F) << 16 | reader.read(16)?,
+                };
+                latin1 = matches!(designator, 1 | 3);
+            }
+            // Structured append: this code's place in a sequence, and its parity.
+            3 => {
+                reader.read(16)?;
+            }
+            // FNC1 in first position, and in second with its application indicator.
+            5 => {}
+            9 => {
+                reader.read(8)?;
+            }
+            _ => return None,
+        }
+    }
+    if latin1 {
+        return Some(bytes.into_iter().map(char::from).collect());
+    }
+    Some(
+        String::from_utf8(bytes)
+            .unwrap_or_else(|e| e.into_bytes().into_iter().map(char::from).collect()),
+    )
+}
+
+/// Reads big-endian bit fields from bytes.
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl BitReader<'_> {
+    /// The next `count` bits, or `None` if fewer are left.
+    fn read(&mut self, count: u32) -> Option<u32> {
+        let mut value = 0;
+        for _ in 0..count {
+            let byte = self.data.get(self.position / 8)?;
+            let bit = (byte >> (7 - self.position % 8)) & 1;
+            value = value << 1 | u32::from(bit);
+            self.position += 1;
+        }
+        Some(value)
+    }
+}
+
+/// Reed-Solomon error correction over GF(2^8) with the QR code polynomial
+/// x^8 + x^4 + x^3 + x^2 + 1, whose generator's roots are 2^0, 2^1, ...
+mod reed_solomon {
+    /// Multiplies two field elements.
+    fn mul(a: u8, b: u8) -> u8 {
+        let (mut a, mut b) = (u16::from(a), b);
+        let mut product = 0u16;
+        while b != 0 {
+            if b & 1 != 0 {
+                product ^= a;
+            }
+            a <<= 1;
+            if a & 0x100 != 0 {
+                a ^= 0x11D;
+            }
+            b >>= 1;
+        }
+        u8::try_from(product).unwrap_or(0)
+    }
+
+    /// `base` to the power `exponent`.
+    fn pow(base: u8, exponent: usize) -> u8 {
+        let (mut base, mut exponent, mut result) = (base, exponent, 1);
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = mul(result, base);
+            }
+            base = mul(base, base);
+            exponent >>= 1;
+        }
+        result
+    }
+
+    /// The multiplicative inverse of a nonzero element.
+    fn inverse(value: u8) -> u8 {
+        pow(value, 254)
+    }
+
+    /// Evaluates `poly` (lowest degree first) at `x`.
+    fn eval(poly: &[u8], x: u8) -> u8 {
+        poly.iter().rev().fold(0, |acc, &c| mul(acc, x) ^ c)
+    }
+
+    /// Syndromes of `block` (highest degree first): the received polynomial at
+    /// 2^0 .. 2^(ecc - 1). All zero for a valid block.
+    fn syndromes(block: &[u8], ecc: usize) -> Vec<u8> {
+        (0..ecc)
+            .map(|i| {
+                let x = pow(2, i);
+                block.iter().fold(0, |acc, &c| mul(acc, x) ^ c)
+            })
+            .collect()
+    }
+
+    /// Corrects `block`, data codewords followed by `ecc` error correction codewords,
+    /// in place. `None` if it has more than `ecc / 2` errors.
+    pub(super) fn correct(block: &mut [u8], ecc: usize) -> Option<()> {
+        let syndromes = syndromes(block, ecc);
+        if syndromes.iter().all(|&s| s == 0) {
+            return Some(());
+        }
+
+        // Berlekamp-Massey finds the error locator, whose roots are the inverses of
+        // the error positions' powers.
+        let mut locator = vec![1u8];
+        let mut previous = vec![1u8];
+        let (mut errors, mut shift, mut previous_discrepancy) = (0usize, 1usize, 1u8);
+        for (n, &syndrome) in syndromes.iter().enumerate() {
+            let discrepancy = (1..=errors).fold(syndrome, |acc, i| {
+                let coefficient = locator.get(i).copied().unwrap_or(0);
+                let earlier = n
+                    .checked_sub(i)
+                    .and_then(|j| syndromes.get(j))
+                    .copied()
+                    .unwrap_or(0);
+                acc ^ mul(coefficient, earlier)
+            });
+            if discrepancy == 0 {
+                shift += 1;
+                continue;
+            }
+            let scale = mul(discrepancy, inverse(previous_discrepancy));
+            let mut next = locator.clone();
+            next.resize(next.len().max(previous.len() + shift), 0);
+            for (slot, &c) in next.iter_mut().skip(shift).zip(&previous) {
+                *slot ^= mul(scale, c);
+            }
+            if 2 * errors <= n {
+                previous = std::mem::replace(&mut locator, next);
+                errors = n + 1 - errors;
+                previous_discrepancy = discrepancy;
+                shift = 1;
+            } else {
+                locator = next;
+                shift += 1;
+            }
+        }
+        if 2 * errors > ecc {
+            return None;
+        }
+        locator.truncate(errors + 1);
+
+        // Codeword `k` of `n` is the coefficient of x^(n - 1 - k).
+        let n = block.len();
+        let power = |k: usize| (n - 1 - k) % 255;
+        let positions: Vec<usize> = (0..n)
+            .filter(|&k| eval(&locator, pow(2, 255 - power(k))) == 0)
+            .collect();
+        if positions.len() != errors {
+            return None;
+        }
+
+        // Forney: each error's magnitude from the evaluator and the locator's
+        // derivative, whose even terms vanish in characteristic 2.
+        let mut evaluator = vec![0u8; ecc];
+        for (i, &s) in syndromes.iter().enumerate() {
+            for (j, &l) in locator.iter().enumerate() {
+                if let Some(slot) = evaluator.get_mut(i + j) {
+                    *slot ^= mul(s, l);
+                }
+            }
+        }
+        let derivative: Vec<u8> = locator
+            .iter()
+            .enumerate()
+            .skip(1)
+            .map(|(i, &c)| if i % 2 == 1 { c } else { 0 })
+            .collect();
+        for k in positions {
+            let x = pow(2, power(k));
+            let x_inverse = inverse(x);
+            let denominator = eval(&derivative, x_inverse);
+            if denominator == 0 {
+                return None;
+            }
+            let magnitude = mul(x, mul(eval(&evaluator, x_inverse), inverse(denominator)));
+            *block.get_mut(k)? ^= magnitude;
+        }
+        self::syndromes(block, ecc)
+            .iter()
+            .all(|&s| s == 0)
+            .then_some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+    use qrcodegen::{QrCodeEcc, QrSegment};
+
+    use super::*;
+    use crate::warp;
+
+    /// `code` drawn with `module`-pixel modules and a 4-module quiet zone.
+    fn render(code: &qrcodegen::QrCode, module: u32) -> RgbaImage {
+        let quiet = 4;
+        let size = u32::try_from(code.size()).unwrap();
+        let side = (size + 2 * quiet) * module;
+        RgbaImage::from_fn(side, side, |x, y| {
+            let col = i32::try_from(x / module).unwrap() - 4;
+            let row = i32::try_from(y / module).unwrap() - 4;
+            if code.get_module(col, row) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255; 4])
+            }
+        })
+    }
+
+    fn encode(segments: &[QrSegment], level: QrCodeEcc, version: u8) -> qrcodegen::QrCode {
+        qrcodegen::QrCode::encode_segments_advanced(
+            segments,
+            level,
+            qrcodegen::Version::new(version),
+            qrcodegen::Version::new(40),
+            None,
+            false,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn reads_text_at_every_level() {
+        for (level, expected) in [
+            (QrCodeEcc::Low, ErrorCorrection::L),
+            (QrCodeEcc::Medium, ErrorCorrection::M),
+            (QrCodeEcc::Quartile, ErrorCorrection::Q),
+            (QrCodeEcc::High, ErrorCorrection::H),
+        ] {
+            let code = encode(&QrSegment::make_segments("Hello, world!"), level, 1);
+            let codes = read(&render(&code, 4));
+            assert_eq!(codes.len(), 1, "{level:?}");
+            assert_eq!(codes[0].payload, "Hello, world!");
+            assert_eq!(codes[0].error_correction, expected);
+            assert_eq!(codes[0].version, code.version().value());
+        }
+    }
+
+    #[test]
+    fn reads_numeric_alphanumeric_and_utf8_segments() {
+        let segments = [
+            QrSegment::make_numeric("0123456789"),
+            QrSegment::make_alphanumeric("TICKET-42 $ 10.50"),
+            QrSegment::make_bytes("/ünïcode ✓".as_bytes()),
+        ];
+        let code = encode(&segments, QrCodeEcc::Medium, 1);
+        let codes = read(&render(&code, 3));
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].payload, "0123456789TICKET-42 $ 10.50/ünïcode ✓");
+
+        let latin1 = [
+            QrSegment::make_eci(3),
+            QrSegment::make_bytes(&[0x63, 0x61, 0x66, 0xE9]),
+        ];
+        let codes = read(&render(&encode(&latin1, QrCodeEcc::Low, 1), 3));
+        assert_eq!(codes[0].payload, "café");
+    }
+
+    #[test]
+    fn reads_large_versions_from_their_version_information() {
+        let url = "https://example.com/t/".to_owned() + &"0123456789abcdef".repeat(3);
+        for version in [7, 12, 25] {
+            let code = encode(
+                &QrSegment::make_segments(&url),
+                QrCodeEcc::Quartile,
+                version,
+            );
+            assert_eq!(code.version().value(), version);
+            let codes = read(&render(&code, 3));
+            assert_eq!(codes.len(), 1, "version {version}");
+            assert_eq!(codes[0].payload, url);
+            assert_eq!(codes[0].version, version);
+        }
+    }
+
+    #[test]
+    fn reads_turned_and_tilted_codes() {
+        let segments = QrSegment::make_segments("ROW 12 SEAT 7");
+        let img = render(&encode(&segments, QrCodeEcc::Medium, 3), 5);
+        for turned in [
+            image::imageops::rotate90(&img),
+            image::imageops::rotate180(&img),
+            image::imageops::rotate270(&img),
+        ] {
+            assert_eq!(read(&turned)[0].payload, "ROW 12 SEAT 7");
+        }
+
+        // Photographed from below and to the left: the top edge is shorter.
+        let side = f64::from(img.width());
+        let tilted = warp::perspective(
+            &img,
+            [
+                [side * 0.06, side * 0.04],
+                [side * 0.96, -side * 0.02],
+                [side, side],
+                [0.0, side * 0.97],
+            ],
+            img.width(),
+            img.height(),
+        )
+        .unwrap();
+        let codes = read(&tilted);
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].payload, "ROW 12 SEAT 7");
+    }
+
+    #[test]
+    fn corrects_damaged_codewords() {
+        let code = encode(&QrSegment::make_segments("PAYLOAD"), QrCodeEcc::High, 2);
+        let module = 4;
+        let mut img = render(&code, module);
+        // Scribble over a few data modules in the bottom-right corner.
+        for (col, row) in [(24, 24), (23, 24), (24, 22), (21, 23), (20, 20)] {
+            let (x, y) = ((col + 4) * module, (row + 4) * module);
+            for dy in 0..module {
+                for dx in 0..module {
+                    let pixel = img.get_pixel_mut(x + dx, y + dy);
+                    pixel.0 = if pixel.0[0] == 0 {
+                        [255; 4]
+                    } else {
+                        [0, 0, 0, 255]
+                    };
+                }
+            }
+        }
+        let codes = read(&img);
+        assert_eq!(codes.len(), 1);
+        assert_eq!(codes[0].payload, "PAYLOAD");
+    }
+
+    /// The modules of `code`, as a reader would sample them from a perfect image.
+    fn grid(code: &qrcodegen::QrCode) -> Grid {
+        let size = code.size();
+        Grid {
+            size: u32::try_from(size).unwrap(),
+            dark: (0..size)
+                .flat_map(|row| (0..size).map(move |col| code.get_module(col, row)))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn data_area_matches_the_standard_capacity() {
+        for version in 1..=40u8 {
+            let size = 17 + 4 * u32::from(version);
+            let alignment = alignment_positions(version);
+            let data_modules = (0..size)
+                .flat_map(|row| (0..size).map(move |col| (col, row)))
+                .filter(|&(col, row)| !is_function(version, size, &alignment, col, row))
+                .count();
+            // ISO/IEC 18004's raw data modules, remainder bits included.
+            let v = usize::from(version);
+            let mut expected = (16 * v + 128) * v + 64;
+            if v >= 2 {
+                let count = v / 7 + 2;
+                expected -= (25 * count - 10) * count - 55;
+                if v >= 7 {
+                    expected -= 36;
+                }
+            }
+            assert_eq!(data_modules, expected, "version {version}");
+        }
+    }
+
+    #[test]
+    fn reed_solomon_corrects_up_to_half_its_check_codewords() {
+        // A version 1-M code is a single block of 16 data and 10 check codewords.
+        let code = encode(
+            &QrSegment::make_segments("HELLO WORLD"),
+            QrCodeEcc::Medium,
+            1,
+        );
+        let grid = grid(&code);
+        let (level, mask) = grid.format().unwrap();
+        assert_eq!(level, ErrorCorrection::M);
+        let block = grid.codewords(1, mask);
+        assert_eq!(block.len(), 26);
+
+        let mut damaged = block.clone();
+        for i in [0, 3, 9, 15, 20] {
+            damaged[i] ^= 0x5A;
+        }
+        reed_solomon::correct(&mut damaged, 10).unwrap();
+        assert_eq!(damaged, block);
+
+        for i in [0, 3, 9, 15, 20, 25] {
+            damaged[i] ^= 0x33;
+        }
+        assert!(reed_solomon::correct(&mut damaged, 10).is_none() || damaged != block);
+    }
+
+    #[test]
+    fn ignores_codes_it_cannot_read() {
+        assert!(read(&RgbaImage::from_pixel(60, 60, Rgba([255; 4]))).is_empty());
+        let code = qrcodegen::QrCode::encode_text("SECRET", QrCodeEcc::Low).unwrap();
+        let mut img = render(&code, 4);
+        // Wipe the data area between the finder patterns.
+        for y in 4 * 4 + 9 * 4..4 * 4 + 20 * 4 {
+            for x in 4 * 4 + 9 * 4..4 * 4 + 20 * 4 {
+                img.put_pixel(x, y, Rgba([255; 4]));
+            }
+        }
+        assert!(read(&img).is_empty());
+        assert_eq!(codes::locate(&img).len(), 1);
+    }
+}
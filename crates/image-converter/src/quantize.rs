@@ -0,0 +1,2060 @@
+use std::collections::{HashMap, HashSet};
+
+use image::{Frame, RgbaImage};
+use serde::Serialize;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+use crate::png_optimize::{self, ChunkPolicy, PngOptimizeError};
+
+/// Largest palette a PNG can hold.
+pub const MAX_COLORS: u16 = 256;
+
+/// Default k-means passes run over the median-cut palette.
+const REFINE_ITERATIONS: u8 = 3;
+
+/// Default NeuQuant sampling factor (1 is slowest and best, 30 fastest).
+const NEUQUANT_SAMPLE_FACTOR: u8 = 10;
+
+/// Bins per axis of the Wu histogram (5 bits per channel), plus a zero plane so the
+/// cumulative moments need no bounds checks at the low edge.
+const WU_SIDE: usize = 33;
+
+/// 8×8 Bayer threshold matrix (values 0–63) for ordered dithering.
+const BAYER_8X8: [[u8; 8]; 8] = [
+    [0, 32, 8, 40, 2, 34, 10, 42],
+    [48, 16, 56, 24, 50, 18, 58, 26],
+    [12, 44, 4, 36, 14, 46, 6, 38],
+    [60, 28, 52, 20, 62, 30, 54, 22],
+    [3, 35, 11, 43, 1, 33, 9, 41],
+    [51, 19, 59, 27, 49, 17, 57, 25],
+    [15, 47, 7, 39, 13, 45, 5, 37],
+    [63, 31, 55, 23, 61, 29, 53, 21],
+];
+
+/// Settings for [`quantize_png`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct QuantizeOptions {
+    /// Palette size, 2 to 256.
+    pub max_colors: u16,
+    /// Lowest acceptable quality (0–100, pngquant scale). 0 accepts anything.
+    pub min_quality: u8,
+    /// How pixels are mapped onto the palette. Ignored when the image's colors all
+    /// fit, so exact output is never dithered.
+    pub dither: Dither,
+    /// Algorithm that builds the palette when the image has more colors than fit.
+    pub quantizer: Quantizer,
+}
+
+impl Default for QuantizeOptions {
+    fn default() -> Self {
+        Self {
+            max_colors: MAX_COLORS,
+            min_quality: 0,
+            dither: Dither::default(),
+            quantizer: Quantizer::default(),
+        }
+    }
+}
+
+/// Palette-building algorithm for images with more colors than the palette holds.
+/// Images that already fit always get their exact colors.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Quantizer {
+    /// Median cut in premultiplied RGBA, followed by `refine` k-means passes. Slower
+    /// with more passes, but each pass pulls entries toward the colors they stand for.
+    MedianCut { refine: u8 },
+    /// Xiaolin Wu's variance-minimizing cuts over a 32×32×32 RGB histogram, followed
+    /// by `refine` k-means passes. Fast and good on smooth gradients.
+    Wu { refine: u8 },
+    /// NeuQuant neural-network quantization (as used by the `gif` crate). Every
+    /// `sample_factor`-th pixel is learned, from 1 (best) to 30 (fastest). Needs a
+    /// palette of 64 colors or more to give good results.
+    NeuQuant { sample_factor: u8 },
+}
+
+impl Default for Quantizer {
+    fn default() -> Self {
+        Self::MedianCut {
+            refine: REFINE_ITERATIONS,
+        }
+    }
+}
+
+impl Quantizer {
+    /// Parses a quantizer as `name` or `name:parameter`.
+    ///
+    /// Accepts `"median_cut"` (or `"median"`) and `"wu"`, whose parameter is the
+    /// number of k-means passes (default 3 for median cut, 0 for Wu), and
+    /// `"neuquant"` (or `"nq"`), whose parameter is the sampling factor 1–30
+    /// (default 10). An empty string selects `"median_cut"`.
+    ///
+    /// Returns an error if the name is not recognized or the parameter is invalid.
+    pub fn from_name(name: &str) -> Result<Self, QuantizeError> {
+        let unknown = || QuantizeError::UnknownQuantizer(name.to_owned());
+        let lower = name.trim().to_ascii_lowercase();
+        let (algorithm, parameter) = match lower.split_once(':') {
+            Some((algorithm, parameter)) => (
+                algorithm.trim(),
+                Some(parameter.trim().parse::<u8>().map_err(|_| unknown())?),
+            ),
+            None => (lower.as_str(), None),
+        };
+        let quantizer = match algorithm {
+            "" | "median_cut" | "median" => Self::MedianCut {
+                refine: parameter.unwrap_or(REFINE_ITERATIONS),
+            },
+            "wu" => Self::Wu {
+                refine: parameter.unwrap_or(0),
+            },
+            "neuquant" | "nq" => Self::NeuQuant {
+                sample_factor: parameter.unwrap_or(NEUQUANT_SAMPLE_FACTOR),
+            },
+            _ => return Err(unknown()),
+        };
+        quantizer.validate()?;
+        Ok(quantizer)
+    }
+
+    fn validate(self) -> Result<(), QuantizeError> {
+        match self {
+            Self::NeuQuant { sample_factor } if !(1..=30).contains(&sample_factor) => {
+                Err(QuantizeError::InvalidSampleFactor(sample_factor))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// What [`quantize_png`] produced.
+#[derive(Debug, Clone, Serialize)]
+pub struct QuantizeReport {
+    pub original_size: usize,
+    pub quantized_size: usize,
+    /// Number of palette entries in the output.
+    pub colors: usize,
+    /// Estimated quality of the palette (0–100, pngquant scale). 100 means every
+    /// source color is in the palette.
+    pub quality: u8,
+}
+
+/// Result of [`quantize_png`].
+#[derive(Debug, Clone)]
+pub struct QuantizedPng {
+    pub data: Vec<u8>,
+    pub report: QuantizeReport,
+}
+
+/// Reduces an image to an indexed PNG with an optimized palette of at most
+/// `max_colors` RGBA entries, in the spirit of `pngquant`.
+///
+/// The palette is built by `options.quantizer` (by default median cut refined with
+/// k-means), in premultiplied-alpha space so that transparent pixels don't pull
+/// colors toward their (invisible) RGB values. Images that already fit in the palette
+/// are written losslessly. Alpha is kept through a `tRNS` chunk.
+///
+/// # Errors
+///
+/// Returns an error if the options are out of range, the input cannot be decoded,
+/// or the best palette falls below `min_quality` (like `pngquant --quality`).
+pub fn quantize_png(
+    input: &[u8],
+    options: &QuantizeOptions,
+) -> Result<QuantizedPng, QuantizeError> {
+    validate_options(*options)?;
+
+    let img = image::load_from_memory(input)
+        .map_err(QuantizeError::Decode)?
+        .to_rgba8();
+    let histogram = histogram(&img);
+    let palette = build_palette(
+        &histogram,
+        usize::from(options.max_colors),
+        options.quantizer,
+    );
+    let quality = mse_to_quality(palette_mse(&palette, &histogram));
+    if quality < options.min_quality {
+        return Err(QuantizeError::QualityTooLow {
+            quality,
+            minimum: options.min_quality,
+        });
+    }
+
+    let dither = dither_for(&histogram, usize::from(options.max_colors), options.dither);
+    let indexed = IndexedImage {
+        width: img.width(),
+        height: img.height(),
+        indices: palette.remap(&img, dither),
+        palette: palette.colors,
+    };
+    let data = indexed.to_png()?;
+
+    Ok(QuantizedPng {
+        report: QuantizeReport {
+            original_size: input.len(),
+            quantized_size: data.len(),
+            colors: indexed.palette.len(),
+            quality,
+        },
+        data,
+    })
+}
+
+/// Encodes a still image as a GIF whose palette is built by `options.quantizer`,
+/// rather than by the GIF encoder's own NeuQuant pass.
+///
+/// GIF transparency is all-or-nothing: as with the `image` crate's encoder, pixels
+/// with zero alpha become transparent and every other pixel is made opaque. The
+/// transparent entry takes one of the `max_colors` palette slots when it is needed.
+///
+/// # Errors
+///
+/// Returns an error if the options are out of range, a side is longer than 65535
+/// pixels, the palette falls below `min_quality`, or the GIF encoder fails.
+pub fn quantize_gif(img: &RgbaImage, options: &QuantizeOptions) -> Result<Vec<u8>, QuantizeError> {
+    validate_options(*options)?;
+    let width = u16::try_from(img.width()).map_err(|_| QuantizeError::TooLarge)?;
+    let height = u16::try_from(img.height()).map_err(|_| QuantizeError::TooLarge)?;
+
+    let opaque = binary_alpha(img);
+    let palette = GifPalette::new(&[&opaque], *options)?;
+    let frame = gif::Frame::from_palette_pixels(
+        width,
+        height,
+        palette.indices(&opaque),
+        palette.rgb(),
+        palette.transparent,
+    );
+    let mut buf = Vec::new();
+    {
+        let mut encoder =
+            gif::Encoder::new(&mut buf, width, height, &[]).map_err(QuantizeError::GifEncode)?;
+        encoder
+            .write_frame(&frame)
+            .map_err(QuantizeError::GifEncode)?;
+    }
+    Ok(buf)
+}
+
+/// Encodes frames as a looping animated GIF with one palette shared by every frame,
+/// so colors don't flicker from frame to frame the way they do when each frame is
+/// quantized on its own.
+///
+/// The palette is built in a first pass over all frames' colors by
+/// `options.quantizer`, and written once as the global color table; the second pass
+/// maps each frame onto it. Transparency works as in [`quantize_gif`]. Frames keep
+/// their offsets and delays and are cleared to the background after display, which
+/// suits the full-canvas frames produced by [`crate::animation::decode_frames`].
+///
+/// # Errors
+///
+/// Returns an error if there are no frames, the options are out of range, the
+/// animation is larger than 65535 pixels on a side, the palette falls below
+/// `min_quality`, or the GIF encoder fails.
+pub fn quantize_gif_frames(
+    frames: &[Frame],
+    options: &QuantizeOptions,
+) -> Result<Vec<u8>, QuantizeError> {
+    validate_options(*options)?;
+    if frames.is_empty() {
+        return Err(QuantizeError::NoImages);
+    }
+    let extent = |offset: u32, size: u32| {
+        u16::try_from(offset.saturating_add(size)).map_err(|_| QuantizeError::TooLarge)
+    };
+    let mut width = 0;
+    let mut height = 0;
+    for frame in frames {
+        width = width.max(extent(frame.left(), frame.buffer().width())?);
+        height = height.max(extent(frame.top(), frame.buffer().height())?);
+    }
+
+    let opaque: Vec<RgbaImage> = frames.iter().map(|f| binary_alpha(f.buffer())).collect();
+    let palette = GifPalette::new(&opaque.iter().collect::<Vec<_>>(), *options)?;
+
+    let mut buf = Vec::new();
+    {
+        let mut encoder = gif::Encoder::new(&mut buf, width, height, &palette.rgb())
+            .map_err(QuantizeError::GifEncode)?;
+        encoder
+            .set_repeat(gif::Repeat::Infinite)
+            .map_err(QuantizeError::GifEncode)?;
+        for (frame, img) in frames.iter().zip(&opaque) {
+            let mut gif_frame = gif::Frame::from_indexed_pixels(
+                u16::try_from(img.width()).map_err(|_| QuantizeError::TooLarge)?,
+                u16::try_from(img.height()).map_err(|_| QuantizeError::TooLarge)?,
+                palette.indices(img),
+                palette.transparent,
+            );
+            gif_frame.left = u16::try_from(frame.left()).map_err(|_| QuantizeError::TooLarge)?;
+            gif_frame.top = u16::try_from(frame.top()).map_err(|_| QuantizeError::TooLarge)?;
+            let (numer, denom) = frame.delay().numer_denom_ms();
+            // GIF delays are in hundredths of a second.
+            let centis = numer / denom.max(1) / 10;
+            gif_frame.delay = u16::try_from(centis).unwrap_or(u16::MAX);
+            gif_frame.dispose = gif::DisposalMethod::Background;
+            encoder
+                .write_frame(&gif_frame)
+                .map_err(QuantizeError::GifEncode)?;
+        }
+    }
+    Ok(buf)
+}
+
+/// Reduces a batch of related images to indexed PNGs that all use one palette, built
+/// in a first pass over every image's colors, so the same color comes out the same in
+/// every file.
+///
+/// Each report's quality is measured for that image against the shared palette.
+///
+/// # Errors
+///
+/// Returns an error if there are no inputs, the options are out of range, an input
+/// cannot be decoded, or the shared palette falls below `min_quality` over the whole
+/// batch.
+pub fn quantize_png_batch(
+    inputs: &[Vec<u8>],
+    options: &QuantizeOptions,
+) -> Result<Vec<QuantizedPng>, QuantizeError> {
+    validate_options(*options)?;
+    if inputs.is_empty() {
+        return Err(QuantizeError::NoImages);
+    }
+    let images = inputs
+        .iter()
+        .map(|input| {
+            image::load_from_memory(input)
+                .map(|img| img.to_rgba8())
+                .map_err(QuantizeError::Decode)
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let histograms: Vec<_> = images.iter().map(histogram).collect();
+    let combined = merge_histograms(&histograms);
+    let max_colors = usize::from(options.max_colors);
+    let palette = build_palette(&combined, max_colors, options.quantizer);
+    let quality = mse_to_quality(palette_mse(&palette, &combined));
+    if quality < options.min_quality {
+        return Err(QuantizeError::QualityTooLow {
+            quality,
+            minimum: options.min_quality,
+        });
+    }
+    let dither = dither_for(&combined, max_colors, options.dither);
+
+    inputs
+        .iter()
+        .zip(&images)
+        .zip(&histograms)
+        .map(|((input, img), histogram)| {
+            let indexed = IndexedImage {
+                width: img.width(),
+                height: img.height(),
+                indices: palette.remap(img, dither),
+                palette: palette.colors.clone(),
+            };
+            let data = indexed.to_png()?;
+            Ok(QuantizedPng {
+                report: QuantizeReport {
+                    original_size: input.len(),
+                    quantized_size: data.len(),
+                    colors: indexed.palette.len(),
+                    quality: mse_to_quality(palette_mse(&palette, histogram)),
+                },
+                data,
+            })
+        })
+        .collect()
+}
+
+/// Reduces related images, such as an animation's frames, to at most
+/// `options.max_colors` colors shared by all of them, with the palette built in a
+/// first pass over every image's colors. The results stay RGBA, for encoders that
+/// don't take a palette but compress fewer colors better.
+///
+/// # Errors
+///
+/// Returns an error if there are no images, the options are out of range, or the
+/// shared palette falls below `min_quality`.
+pub fn reduce_colors(
+    images: &[&RgbaImage],
+    options: &QuantizeOptions,
+) -> Result<Vec<RgbaImage>, QuantizeError> {
+    validate_options(*options)?;
+    if images.is_empty() {
+        return Err(QuantizeError::NoImages);
+    }
+    let histograms: Vec<_> = images.iter().map(|img| histogram(img)).collect();
+    let combined = merge_histograms(&histograms);
+    let max_colors = usize::from(options.max_colors);
+    let palette = build_palette(&combined, max_colors, options.quantizer);
+    let quality = mse_to_quality(palette_mse(&palette, &combined));
+    if quality < options.min_quality {
+        return Err(QuantizeError::QualityTooLow {
+            quality,
+            minimum: options.min_quality,
+        });
+    }
+    let dither = dither_for(&combined, max_colors, options.dither);
+
+    Ok(images
+        .iter()
+        .map(|img| {
+            IndexedImage {
+                width: img.width(),
+                height: img.height(),
+                indices: palette.remap(img, dither),
+                palette: palette.colors.clone(),
+            }
+            .to_rgba()
+        })
+        .collect())
+}
+
+/// Copy of `img` with GIF's all-or-nothing alpha: zero-alpha pixels become `[0; 4]`
+/// and every other pixel becomes opaque.
+fn binary_alpha(img: &RgbaImage) -> RgbaImage {
+    let mut opaque = img.clone();
+    for px in opaque.pixels_mut() {
+        if px.0[3] == 0 {
+            px.0 = [0; 4];
+        } else {
+            px.0[3] = u8::MAX;
+        }
+    }
+    opaque
+}
+
+/// A GIF palette of opaque colors, plus a transparent entry after them when any
+/// image needs one.
+struct GifPalette {
+    palette: Palette,
+    transparent: Option<u8>,
+    dither: Dither,
+}
+
+impl GifPalette {
+    /// Builds the palette over the colors of every image, whose alpha must already be
+    /// binary (see [`binary_alpha`]).
+    fn new(images: &[&RgbaImage], options: QuantizeOptions) -> Result<Self, QuantizeError> {
+        let histograms: Vec<_> = images.iter().map(|img| histogram(img)).collect();
+        let mut combined = merge_histograms(&histograms);
+        let has_transparency = combined.remove(&[0; 4]).is_some();
+        let max_colors = usize::from(options.max_colors) - usize::from(has_transparency);
+        let palette = build_palette(&combined, max_colors, options.quantizer);
+        let quality = mse_to_quality(palette_mse(&palette, &combined));
+        if quality < options.min_quality {
+            return Err(QuantizeError::QualityTooLow {
+                quality,
+                minimum: options.min_quality,
+            });
+        }
+        let transparent = if has_transparency {
+            Some(u8::try_from(palette.colors.len()).map_err(|_| QuantizeError::TooLarge)?)
+        } else {
+            None
+        };
+        Ok(Self {
+            dither: dither_for(&combined, max_colors, options.dither),
+            palette,
+            transparent,
+        })
+    }
+
+    /// Maps an image's pixels to palette indices, with transparent pixels on the
+    /// transparent entry.
+    fn indices(&self, img: &RgbaImage) -> Vec<u8> {
+        let mut indices = self.palette.remap(img, self.dither);
+        if let Some(index) = self.transparent {
+            for (slot, px) in indices.iter_mut().zip(img.pixels()) {
+                if px.0[3] == 0 {
+                    *slot = index;
+                }
+            }
+        }
+        indices
+    }
+
+    /// The color table as packed RGB.
+    fn rgb(&self) -> Vec<u8> {
+        let mut rgb: Vec<u8> = self
+            .palette
+            .colors
+            .iter()
+            .flat_map(|c| [c[0], c[1], c[2]])
+            .collect();
+        if self.transparent.is_some() {
+            rgb.extend([0; 3]);
+        }
+        rgb
+    }
+}
+
+/// Sums per-image histograms into one.
+fn merge_histograms(histograms: &[HashMap<[u8; 4], u32>]) -> HashMap<[u8; 4], u32> {
+    let mut combined = HashMap::new();
+    for histogram in histograms {
+        for (&color, &count) in histogram {
+            let total = combined.entry(color).or_insert(0u32);
+            *total = total.saturating_add(count);
+        }
+    }
+    combined
+}
+
+/// The requested dithering, or none when the palette holds every color exactly.
+/// Ordered dithering would otherwise nudge exact pixels onto neighbouring entries.
+fn dither_for(histogram: &HashMap<[u8; 4], u32>, max_colors: usize, dither: Dither) -> Dither {
+    if histogram.len() <= max_colors {
+        Dither::None
+    } else {
+        dither
+    }
+}
+
+fn validate_options(options: QuantizeOptions) -> Result<(), QuantizeError> {
+    if options.max_colors < 2 || options.max_colors > MAX_COLORS {
+        return Err(QuantizeError::InvalidColorCount(options.max_colors));
+    }
+    if options.min_quality > 100 {
+        return Err(QuantizeError::InvalidQuality(options.min_quality));
+    }
+    options.quantizer.validate()
+}
+
+/// How pixels are mapped onto a palette.
+///
+/// Exported to JS as a numeric enum whose values follow declaration order.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Dither {
+    /// Each pixel takes its nearest palette color.
+    None,
+    /// Floyd–Steinberg error diffusion.
+    #[default]
+    FloydSteinberg,
+    /// Ordered dithering with an 8×8 Bayer matrix: a fixed, position-based pattern
+    /// that is faster than error diffusion and doesn't crawl between similar images.
+    Ordered,
+}
+
+impl Dither {
+    /// Every mode, in declaration order.
+    pub const ALL: [Self; 3] = [Self::None, Self::FloydSteinberg, Self::Ordered];
+
+    /// Parses a dithering mode.
+    ///
+    /// Accepts `"none"`, `"floyd_steinberg"` (or `"fs"`) and `"ordered"` (or
+    /// `"bayer"`). An empty string selects `"floyd_steinberg"`.
+    ///
+    /// Returns an error if the string is not a recognized mode.
+    pub fn from_name(name: &str) -> Result<Self, QuantizeError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "none" => Ok(Self::None),
+            "" | "floyd_steinberg" | "fs" => Ok(Self::FloydSteinberg),
+            "ordered" | "bayer" => Ok(Self::Ordered),
+            _ => Err(QuantizeError::UnknownDither(name.to_owned())),
+        }
+    }
+}
+
+/// An image as palette indices, row-major.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IndexedImage {
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<[u8; 4]>,
+    pub indices: Vec<u8>,
+}
+
+impl IndexedImage {
+    /// Expands the indices back to RGBA pixels.
+    pub fn to_rgba(&self) -> RgbaImage {
+        let pixels = self
+            .indices
+            .iter()
+            .flat_map(|&index| {
+                self.palette
+                    .get(usize::from(index))
+                    .copied()
+                    .unwrap_or_default()
+            })
+            .collect();
+        RgbaImage::from_raw(self.width, self.height, pixels).unwrap_or_default()
+    }
+
+    /// Encodes the image as an optimized indexed PNG at the smallest bit depth that
+    /// fits the palette, with alpha in `tRNS`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the PNG encoder fails.
+    pub fn to_png(&self) -> Result<Vec<u8>, QuantizeError> {
+        let encoded = encode_indexed(self.width, self.height, &self.palette, &self.indices)?;
+        Ok(
+            png_optimize::optimize_png(&encoded, 2, ChunkPolicy::StripAll)
+                .map_err(QuantizeError::Optimize)?
+                .data,
+        )
+    }
+}
+
+/// Maps every pixel of `img` onto the given palette (1 to 256 RGBA colors).
+///
+/// Colors are matched in premultiplied-alpha space, so a palette without a
+/// transparent entry maps transparent pixels to its darkest color. Duplicate entries
+/// are merged, and entries are reordered with translucent colors first.
+///
+/// # Errors
+///
+/// Returns `QuantizeError::InvalidPaletteSize` if the palette is empty or has more
+/// than 256 colors.
+pub fn remap_to_palette(
+    img: &RgbaImage,
+    colors: &[[u8; 4]],
+    dither: Dither,
+) -> Result<IndexedImage, QuantizeError> {
+    if colors.is_empty() || colors.len() > usize::from(MAX_COLORS) {
+        return Err(QuantizeError::InvalidPaletteSize(colors.len()));
+    }
+    let palette = Palette::new(colors.to_vec());
+    Ok(IndexedImage {
+        width: img.width(),
+        height: img.height(),
+        indices: palette.remap(img, dither),
+        palette: palette.colors,
+    })
+}
+
+/// How PNG output chooses between indexed (PNG8) and truecolor encoding.
+///
+/// Exported to JS as a numeric enum whose values follow declaration order.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum IndexedPng {
+    /// Always truecolor (RGB, RGBA or grayscale, as the encoder picks).
+    #[default]
+    Never,
+    /// Indexed when the image has at most 256 colors, truecolor otherwise.
+    Prefer,
+    /// Indexed, or an error if the image has more than 256 colors.
+    Require,
+}
+
+impl IndexedPng {
+    /// Every mode, in declaration order.
+    pub const ALL: [Self; 3] = [Self::Never, Self::Prefer, Self::Require];
+
+    /// Parses an indexed-output mode.
+    ///
+    /// Accepts `"never"`, `"prefer"` or `"require"`. An empty string selects `"never"`.
+    ///
+    /// Returns an error if the string is not a recognized mode.
+    pub fn from_name(name: &str) -> Result<Self, QuantizeError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "never" => Ok(Self::Never),
+            "prefer" => Ok(Self::Prefer),
+            "require" => Ok(Self::Require),
+            _ => Err(QuantizeError::UnknownIndexedMode(name.to_owned())),
+        }
+    }
+}
+
+/// Encodes an image as an indexed PNG whose palette holds exactly the image's colors,
+/// with alpha in `tRNS`, at the smallest bit depth that fits.
+///
+/// No visible pixel changes. Fully transparent pixels share one palette entry, so
+/// their hidden RGB values become zero.
+///
+/// Returns `Ok(None)` if the image has more than 256 distinct colors.
+///
+/// # Errors
+///
+/// Returns an error if the PNG encoder fails.
+pub fn exact_indexed_png(img: &RgbaImage) -> Result<Option<Vec<u8>>, QuantizeError> {
+    let mut colors = HashSet::new();
+    for px in img.pixels() {
+        colors.insert(visible_color(px.0));
+        if colors.len() > usize::from(MAX_COLORS) {
+            return Ok(None);
+        }
+    }
+
+    let palette = Palette::new(colors.into_iter().collect());
+    let indices = palette.remap(img, Dither::None);
+    encode_indexed(img.width(), img.height(), &palette.colors, &indices).map(Some)
+}
+
+/// A color in premultiplied-alpha space with channels in 0–1.
+type Premultiplied = [f64; 4];
+
+/// Output palette: straight-alpha entries as written to the file, plus their
+/// premultiplied form for nearest-color searches.
+struct Palette {
+    colors: Vec<[u8; 4]>,
+    premultiplied: Vec<Premultiplied>,
+}
+
+impl Palette {
+    /// Orders entries so translucent ones come first, keeping `tRNS` short. Ties are
+    /// broken by value so the output doesn't depend on hash iteration order.
+    fn new(mut colors: Vec<[u8; 4]>) -> Self {
+        colors.sort_by_key(|&c| (c[3] == u8::MAX, c));
+        colors.dedup();
+        let premultiplied = colors.iter().map(|&c| premultiply(c)).collect();
+        Self {
+            colors,
+            premultiplied,
+        }
+    }
+
+    /// Index and squared distance of the closest entry.
+    fn nearest(&self, color: &Premultiplied) -> (u8, f64) {
+        self.premultiplied
+            .iter()
+            .zip(0..=u8::MAX)
+            .map(|(entry, index)| (index, distance(entry, color)))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .unwrap_or((0, 0.0))
+    }
+
+    /// Maps every pixel to a palette index, row-major.
+    fn remap(&self, img: &RgbaImage, dither: Dither) -> Vec<u8> {
+        match dither {
+            Dither::FloydSteinberg => return self.remap_dithered(img),
+            Dither::Ordered => return self.remap_ordered(img),
+            Dither::None => {}
+        }
+        let mut cache: HashMap<[u8; 4], u8> = HashMap::new();
+        img.pixels()
+            .map(|px| {
+                *cache
+                    .entry(px.0)
+                    .or_insert_with(|| self.nearest(&premultiply(px.0)).0)
+            })
+            .collect()
+    }
+
+    /// Floyd–Steinberg remapping. Error is carried in premultiplied space and never
+    /// diffused into or out of fully transparent pixels, so clear areas stay clear
+    /// and don't bleed color into their edges.
+    fn remap_dithered(&self, img: &RgbaImage) -> Vec<u8> {
+        let width = usize::try_from(img.width()).unwrap_or(0);
+        // One slot of padding on each side so neighbours never fall off the row.
+        let mut current = vec![[0.0; 4]; width + 2];
+        let mut next = vec![[0.0; 4]; width + 2];
+        let mut indices = Vec::with_capacity(width * usize::try_from(img.height()).unwrap_or(0));
+
+        for row in img.rows() {
+            for (x, px) in row.enumerate() {
+                let source = premultiply(px.0);
+                if px.0[3] == 0 {
+                    indices.push(self.nearest(&source).0);
+                    continue;
+                }
+
+                let carried = current.get(x + 1).copied().unwrap_or_default();
+                let mut target = add(&source, &carried, 1.0);
+                let alpha = target[3].clamp(0.0, 1.0);
+                target[3] = alpha;
+                for channel in target.iter_mut().take(3) {
+                    *channel = channel.clamp(0.0, alpha);
+                }
+
+                let (index, _) = self.nearest(&target);
+                indices.push(index);
+                let chosen = self
+                    .premultiplied
+                    .get(usize::from(index))
+                    .copied()
+                    .unwrap_or_default();
+                let error = add(&target, &chosen, -1.0);
+
+                diffuse(&mut current, x + 2, &error, 7.0 / 16.0);
+                diffuse(&mut next, x, &error, 3.0 / 16.0);
+                diffuse(&mut next, x + 1, &error, 5.0 / 16.0);
+                diffuse(&mut next, x + 2, &error, 1.0 / 16.0);
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.fill([0.0; 4]);
+        }
+        indices
+    }
+
+    /// Ordered remapping: each pixel is nudged by its Bayer threshold before the
+    /// nearest-color search. The nudge is scaled by the palette's typical spacing
+    /// (mean distance from each entry to its nearest neighbour, per channel) so the
+    /// pattern spans one palette step. Alpha is never dithered.
+    fn remap_ordered(&self, img: &RgbaImage) -> Vec<u8> {
+        let spread = self.mean_spacing() / 3f64.sqrt();
+        img.enumerate_pixels()
+            .map(|(x, y, px)| {
+                let mut target = premultiply(px.0);
+                if px.0[3] == 0 {
+                    return self.nearest(&target).0;
+                }
+                let threshold = bayer_offset(x, y);
+                let alpha = target[3];
+                for channel in target.iter_mut().take(3) {
+                    *channel = (*channel + threshold * spread * alpha).clamp(0.0, alpha);
+                }
+                self.nearest(&target).0
+            })
+            .collect()
+    }
+
+    /// Mean RGB distance from each entry to its nearest other entry.
+    fn mean_spacing(&self) -> f64 {
+        let rgb = |c: &Premultiplied| [c[0], c[1], c[2], 0.0];
+        let spacings: Vec<f64> = self
+            .premultiplied
+            .iter()
+            .enumerate()
+            .filter_map(|(i, a)| {
+                self.premultiplied
+                    .iter()
+                    .enumerate()
+                    .filter(|&(j, _)| j != i)
+                    .map(|(_, b)| distance(&rgb(a), &rgb(b)))
+                    .min_by(f64::total_cmp)
+            })
+            .collect();
+        let count = u32::try_from(spacings.len()).unwrap_or(1).max(1);
+        spacings.iter().map(|d| d.sqrt()).sum::<f64>() / f64::from(count)
+    }
+}
+
+/// Ordered-dithering offset for a pixel position, from the 8×8 Bayer matrix: evenly
+/// spread over -0.5..0.5, to be scaled by one quantization step.
+pub fn bayer_offset(x: u32, y: u32) -> f64 {
+    let row = BAYER_8X8.get(usize::try_from(y % 8).unwrap_or(0));
+    let level = row
+        .and_then(|row| row.get(usize::try_from(x % 8).unwrap_or(0)))
+        .copied()
+        .unwrap_or(0);
+    (f64::from(level) + 0.5) / 64.0 - 0.5
+}
+
+/// Adds a share of the quantization error to one cell of an error row.
+fn diffuse(row: &mut [Premultiplied], slot: usize, error: &Premultiplied, weight: f64) {
+    if let Some(cell) = row.get_mut(slot) {
+        *cell = add(cell, error, weight);
+    }
+}
+
+/// Counts each distinct visible color.
+fn histogram(img: &RgbaImage) -> HashMap<[u8; 4], u32> {
+    let mut counts = HashMap::new();
+    for px in img.pixels() {
+        *counts.entry(visible_color(px.0)).or_insert(0) += 1;
+    }
+    counts
+}
+
+/// Histogram entries sorted by color. Palette building sums floats and breaks ties by
+/// position, so iterating in hash order would let the output vary from run to run.
+fn sorted_counts(histogram: &HashMap<[u8; 4], u32>) -> Vec<([u8; 4], u32)> {
+    let mut counts: Vec<([u8; 4], u32)> = histogram.iter().map(|(&c, &n)| (c, n)).collect();
+    counts.sort_unstable();
+    counts
+}
+
+/// Collapses every fully transparent pixel to one color, since their RGB is invisible.
+fn visible_color(color: [u8; 4]) -> [u8; 4] {
+    if color[3] == 0 {
+        [0; 4]
+    } else {
+        color
+    }
+}
+
+/// A histogram color with its pixel count as a weight.
+#[derive(Debug, Clone)]
+struct Entry {
+    color: Premultiplied,
+    weight: f64,
+}
+
+/// A median-cut box and its weighted squared error, cached so the worst box can be
+/// picked without rescanning.
+struct ColorBox {
+    entries: Vec<Entry>,
+    error: f64,
+}
+
+impl ColorBox {
+    fn new(entries: Vec<Entry>) -> Self {
+        let mean = weighted_mean(&entries);
+        let error = entries
+            .iter()
+            .map(|e| e.weight * distance(&e.color, &mean))
+            .sum();
+        Self { entries, error }
+    }
+
+    /// Splits at the weighted median of the channel with the largest variance.
+    fn split(mut self) -> (Self, Self) {
+        let mean = weighted_mean(&self.entries);
+        let mut variance = [0.0; 4];
+        for entry in &self.entries {
+            let diff = add(&entry.color, &mean, -1.0);
+            for (v, d) in variance.iter_mut().zip(diff) {
+                *v += entry.weight * d * d;
+            }
+        }
+        let channel = (0..4)
+            .max_by(|&a, &b| component(&variance, a).total_cmp(&component(&variance, b)))
+            .unwrap_or(0);
+
+        self.entries
+            .sort_by(|a, b| component(&a.color, channel).total_cmp(&component(&b.color, channel)));
+        let total: f64 = self.entries.iter().map(|e| e.weight).sum();
+        let mut running = 0.0;
+        let median = self
+            .entries
+            .iter()
+            .position(|e| {
+                running += e.weight;
+                running >= total / 2.0
+            })
+            .unwrap_or(0);
+        let at = (median + 1).clamp(1, self.entries.len().saturating_sub(1));
+        let upper = self.entries.split_off(at);
+        (Self::new(self.entries), Self::new(upper))
+    }
+}
+
+/// Builds a palette of at most `max_colors` entries for the histogram.
+fn build_palette(
+    histogram: &HashMap<[u8; 4], u32>,
+    max_colors: usize,
+    quantizer: Quantizer,
+) -> Palette {
+    if histogram.len() <= max_colors {
+        return Palette::new(histogram.keys().copied().collect());
+    }
+
+    let entries: Vec<Entry> = sorted_counts(histogram)
+        .into_iter()
+        .map(|(color, count)| Entry {
+            color: premultiply(color),
+            weight: f64::from(count),
+        })
+        .collect();
+
+    let centers = match quantizer {
+        Quantizer::MedianCut { refine } => {
+            refine_centers(median_cut(entries.clone(), max_colors), &entries, refine)
+        }
+        Quantizer::Wu { refine } => refine_centers(wu(histogram, max_colors), &entries, refine),
+        Quantizer::NeuQuant { sample_factor } => {
+            return Palette::new(neuquant(histogram, max_colors, sample_factor))
+        }
+    };
+    Palette::new(centers.into_iter().map(unpremultiply).collect())
+}
+
+/// Median cut: repeatedly splits the box with the largest error; returns box means.
+fn median_cut(entries: Vec<Entry>, max_colors: usize) -> Vec<Premultiplied> {
+    let mut boxes = vec![ColorBox::new(entries)];
+    while boxes.len() < max_colors {
+        let Some(worst) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.entries.len() > 1)
+            .max_by(|a, b| a.1.error.total_cmp(&b.1.error))
+            .map(|(i, _)| i)
+        else {
+            break;
+        };
+        let (low, high) = boxes.swap_remove(worst).split();
+        boxes.push(low);
+        boxes.push(high);
+    }
+    boxes.iter().map(|b| weighted_mean(&b.entries)).collect()
+}
+
+/// Runs `iterations` k-means passes, moving each center to the mean of the entries
+/// nearest to it.
+fn refine_centers(
+    mut centers: Vec<Premultiplied>,
+    entries: &[Entry],
+    iterations: u8,
+) -> Vec<Premultiplied> {
+    for _ in 0..iterations {
+        let mut sums = vec![([0.0; 4], 0.0); centers.len()];
+        for entry in entries {
+            let nearest = centers
+                .iter()
+                .enumerate()
+                .min_by(|a, b| distance(a.1, &entry.color).total_cmp(&distance(b.1, &entry.color)))
+                .map_or(0, |(i, _)| i);
+            if let Some((sum, weight)) = sums.get_mut(nearest) {
+                *sum = add(sum, &entry.color, entry.weight);
+                *weight += entry.weight;
+            }
+        }
+        for (center, (sum, weight)) in centers.iter_mut().zip(sums) {
+            if weight > 0.0 {
+                *center = sum.map(|c| c / weight);
+            }
+        }
+    }
+    centers
+}
+
+/// NeuQuant over the histogram expanded back into pixels, in sorted order so the
+/// network sees the same sequence on every run.
+fn neuquant(
+    histogram: &HashMap<[u8; 4], u32>,
+    max_colors: usize,
+    sample_factor: u8,
+) -> Vec<[u8; 4]> {
+    let pixels: Vec<u8> = sorted_counts(histogram)
+        .into_iter()
+        .flat_map(|(color, count)| {
+            std::iter::repeat_n(color, usize::try_from(count).unwrap_or(0)).flatten()
+        })
+        .collect();
+    let map =
+        color_quant::NeuQuant::new(i32::from(sample_factor), max_colors, &pixels).color_map_rgba();
+    map.as_chunks::<4>().0.to_vec()
+}
+
+/// Weight, sum and sum of squared magnitudes of a set of premultiplied colors.
+#[derive(Debug, Clone, Copy, Default)]
+struct Moments {
+    weight: f64,
+    sum: Premultiplied,
+    squares: f64,
+}
+
+impl Moments {
+    /// `self + other * sign`.
+    fn add(self, other: &Self, sign: f64) -> Self {
+        Self {
+            weight: self.weight + other.weight * sign,
+            sum: add(&self.sum, &other.sum, sign),
+            squares: self.squares + other.squares * sign,
+        }
+    }
+
+    /// `|sum|² / weight`: the part of the squared magnitudes explained by the mean.
+    fn explained(&self) -> f64 {
+        if self.weight > 0.0 {
+            self.sum.iter().map(|c| c * c).sum::<f64>() / self.weight
+        } else {
+            0.0
+        }
+    }
+
+    fn variance(&self) -> f64 {
+        self.squares - self.explained()
+    }
+}
+
+/// A box of Wu histogram bins: `lower` is exclusive and `upper` inclusive per axis.
+#[derive(Debug, Clone, Copy)]
+struct WuBox {
+    lower: [usize; 3],
+    upper: [usize; 3],
+    moments: Moments,
+    variance: f64,
+}
+
+/// Cumulative moments of a `WU_SIDE`³ histogram, so any box's moments take eight
+/// lookups.
+struct WuHistogram {
+    cumulative: Vec<Moments>,
+}
+
+impl WuHistogram {
+    /// Bins colors by the top five bits of each premultiplied RGB channel.
+    fn new(histogram: &HashMap<[u8; 4], u32>) -> Self {
+        let mut cumulative = vec![Moments::default(); WU_SIDE * WU_SIDE * WU_SIDE];
+        for (color, count) in sorted_counts(histogram) {
+            let premultiplied = premultiply(color);
+            let bin = [0, 1, 2]
+                .map(|c| usize::from(to_u8(component(&premultiplied, c) * 255.0) >> 3) + 1);
+            let weight = f64::from(count);
+            if let Some(moments) = cumulative.get_mut(Self::index(bin)) {
+                *moments = moments.add(
+                    &Moments {
+                        weight: 1.0,
+                        sum: premultiplied,
+                        squares: premultiplied.iter().map(|c| c * c).sum(),
+                    },
+                    weight,
+                );
+            }
+        }
+
+        // Inclusion–exclusion over the three lower neighbours turns the counts into
+        // prefix sums in one pass.
+        for r in 1..WU_SIDE {
+            for g in 1..WU_SIDE {
+                for b in 1..WU_SIDE {
+                    let at = |point: [usize; 3]| {
+                        cumulative
+                            .get(Self::index(point))
+                            .copied()
+                            .unwrap_or_default()
+                    };
+                    let total = at([r, g, b])
+                        .add(&at([r - 1, g, b]), 1.0)
+                        .add(&at([r, g - 1, b]), 1.0)
+                        .add(&at([r, g, b - 1]), 1.0)
+                        .add(&at([r - 1, g - 1, b]), -1.0)
+                        .add(&at([r - 1, g, b - 1]), -1.0)
+                        .add(&at([r, g - 1, b - 1]), -1.0)
+                        .add(&at([r - 1, g - 1, b - 1]), 1.0);
+                    if let Some(slot) = cumulative.get_mut(Self::index([r, g, b])) {
+                        *slot = total;
+                    }
+                }
+            }
+        }
+        Self { cumulative }
+    }
+
+    fn index([r, g, b]: [usize; 3]) -> usize {
+        (r * WU_SIDE + g) * WU_SIDE + b
+    }
+
+    /// Moments of the colors inside `lower` (exclusive) to `upper` (inclusive).
+    fn moments(&self, lower: [usize; 3], upper: [usize; 3]) -> Moments {
+        let mut total = Moments::default();
+        for corner in 0..8u8 {
+            let mut point = upper;
+            let mut sign = 1.0;
+            for (axis, (coordinate, low)) in point.iter_mut().zip(lower).enumerate() {
+                if corner >> axis & 1 == 1 {
+                    *coordinate = low;
+                    sign = -sign;
+                }
+            }
+            let moments = self
+                .cumulative
+                .get(Self::index(point))
+                .copied()
+                .unwrap_or_default();
+            total = total.add(&moments, sign);
+        }
+        total
+    }
+
+    fn make_box(&self, lower: [usize; 3], upper: [usize; 3]) -> WuBox {
+        let moments = self.moments(lower, upper);
+        WuBox {
+            lower,
+            upper,
+            moments,
+            variance: moments.variance(),
+        }
+    }
+
+    /// Splits a box at the plane that maximizes the summed `|sum|² / weight` of the
+    /// halves, which minimizes their combined variance. `None` if no plane leaves
+    /// colors on both sides.
+    fn split(&self, whole: &WuBox) -> Option<(WuBox, WuBox)> {
+        let mut best: Option<(f64, usize, usize)> = None;
+        for (axis, (&low, &high)) in whole.lower.iter().zip(&whole.upper).enumerate() {
+            for cut in low + 1..high {
+                let mut upper = whole.upper;
+                if let Some(bound) = upper.get_mut(axis) {
+                    *bound = cut;
+                }
+                let below = self.moments(whole.lower, upper);
+                let above = whole.moments.add(&below, -1.0);
+                if below.weight <= 0.0 || above.weight <= 0.0 {
+                    continue;
+                }
+                let score = below.explained() + above.explained();
+                if best.is_none_or(|(top, _, _)| score > top) {
+                    best = Some((score, axis, cut));
+                }
+            }
+        }
+
+        let (_, axis, cut) = best?;
+        let (mut below_upper, mut above_lower) = (whole.upper, whole.lower);
+        if let (Some(upper), Some(lower)) = (below_upper.get_mut(axis), above_lower.get_mut(axis)) {
+            *upper = cut;
+            *lower = cut;
+        }
+        Some((
+            self.make_box(whole.lower, below_upper),
+            self.make_box(above_lower, whole.upper),
+        ))
+    }
+}
+
+/// Xiaolin Wu's quantizer: repeatedly splits the box with the largest variance at
+/// its best plane; returns box means.
+///
+/// Boxes are cut along the RGB axes only, so fully transparent pixels get a reserved
+/// entry rather than sharing a bin with black.
+fn wu(histogram: &HashMap<[u8; 4], u32>, max_colors: usize) -> Vec<Premultiplied> {
+    let mut colors = histogram.clone();
+    let transparent = colors.remove(&[0; 4]).is_some();
+    let max_colors = max_colors.saturating_sub(usize::from(transparent)).max(1);
+
+    let bins = WuHistogram::new(&colors);
+    let mut boxes = vec![bins.make_box([0; 3], [WU_SIDE - 1; 3])];
+    while boxes.len() < max_colors {
+        let Some(worst) = boxes
+            .iter()
+            .enumerate()
+            .filter(|(_, b)| b.variance > 0.0)
+            .max_by(|a, b| a.1.variance.total_cmp(&b.1.variance))
+            .map(|(i, _)| i)
+        else {
+            break;
+        };
+        match boxes.get(worst).and_then(|b| bins.split(b)) {
+            Some((low, high)) => {
+                boxes.swap_remove(worst);
+                boxes.push(low);
+                boxes.push(high);
+            }
+            None => {
+                if let Some(b) = boxes.get_mut(worst) {
+                    b.variance = 0.0;
+                }
+            }
+        }
+    }
+
+    let mut centers: Vec<Premultiplied> = boxes
+        .iter()
+        .filter(|b| b.moments.weight > 0.0)
+        .map(|b| b.moments.sum.map(|c| c / b.moments.weight))
+        .collect();
+    if transparent {
+        centers.push([0.0; 4]);
+    }
+    centers
+}
+
+/// Mean squared error of mapping every histogram color to its nearest entry.
+fn palette_mse(palette: &Palette, histogram: &HashMap<[u8; 4], u32>) -> f64 {
+    let mut total = 0.0;
+    let mut pixels = 0.0;
+    for (color, count) in sorted_counts(histogram) {
+        let weight = f64::from(count);
+        total += weight * palette.nearest(&premultiply(color)).1;
+        pixels += weight;
+    }
+    if pixels > 0.0 {
+        total / pixels
+    } else {
+        0.0
+    }
+}
+
+/// Converts a mean squared error to pngquant's 0–100 quality scale (the inverse of
+/// libimagequant's `quality_to_mse`).
+fn mse_to_quality(mse: f64) -> u8 {
+    (1..=100u8)
+        .rev()
+        .find(|&q| mse <= quality_to_mse(q) + 1e-6)
+        .unwrap_or(0)
+}
+
+fn quality_to_mse(quality: u8) -> f64 {
+    if quality >= 100 {
+        return 0.0;
+    }
+    let q = f64::from(quality);
+    let low_quality_fudge = (0.016 / (0.001 + q) - 0.001).max(0.0);
+    0.45 * (low_quality_fudge + 2.5 / (210.0 + q).powf(1.2) * (100.1 - q) / 100.0)
+}
+
+/// Writes an indexed PNG at the smallest bit depth that fits the palette.
+fn encode_indexed(
+    width: u32,
+    height: u32,
+    palette: &[[u8; 4]],
+    indices: &[u8],
+) -> Result<Vec<u8>, QuantizeError> {
+    let (depth, bits, per_byte) = match palette.len() {
+        0..=2 => (png::BitDepth::One, 1, 8),
+        3..=4 => (png::BitDepth::Two, 2, 4),
+        5..=16 => (png::BitDepth::Four, 4, 2),
+        _ => (png::BitDepth::Eight, 8, 1),
+    };
+    let rgb: Vec<u8> = palette.iter().flat_map(|c| [c[0], c[1], c[2]]).collect();
+    let translucent = palette.iter().take_while(|c| c[3] < u8::MAX).count();
+    let trns: Vec<u8> = palette.iter().take(translucent).map(|c| c[3]).collect();
+
+    let row_len = usize::try_from(width).map_err(|_| QuantizeError::TooLarge)?;
+    let mut packed = Vec::with_capacity(indices.len() / per_byte + 1);
+    for row in indices.chunks(row_len.max(1)) {
+        for group in row.chunks(per_byte) {
+            let mut byte = 0u8;
+            for (slot, &index) in (1u32..).zip(group) {
+                byte |= index << (8 - slot * bits);
+            }
+            packed.push(byte);
+        }
+    }
+
+    let mut buf = Vec::new();
+    let mut encoder = png::Encoder::new(&mut buf, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(depth);
+    encoder.set_palette(rgb);
+    if !trns.is_empty() {
+        encoder.set_trns(trns);
+    }
+    let mut writer = encoder.write_header().map_err(QuantizeError::Encode)?;
+    writer
+        .write_image_data(&packed)
+        .map_err(QuantizeError::Encode)?;
+    writer.finish().map_err(QuantizeError::Encode)?;
+    Ok(buf)
+}
+
+fn premultiply(color: [u8; 4]) -> Premultiplied {
+    let alpha = f64::from(color[3]) / 255.0;
+    [
+        f64::from(color[0]) / 255.0 * alpha,
+        f64::from(color[1]) / 255.0 * alpha,
+        f64::from(color[2]) / 255.0 * alpha,
+        alpha,
+    ]
+}
+
+fn unpremultiply(color: Premultiplied) -> [u8; 4] {
+    let alpha = color[3];
+    if alpha <= 0.0 {
+        return [0; 4];
+    }
+    [
+        to_u8(color[0] / alpha * 255.0),
+        to_u8(color[1] / alpha * 255.0),
+        to_u8(color[2] / alpha * 255.0),
+        to_u8(alpha * 255.0),
+    ]
+}
+
+fn to_u8(value: f64) -> u8 {
+    // Safe: the value is rounded and clamped to 0..=255 first.
+    #[allow(clippy::as_conversions)]
+    let byte = value.round().clamp(0.0, 255.0) as u8;
+    byte
+}
+
+fn distance(a: &Premultiplied, b: &Premultiplied) -> f64 {
+    a.iter().zip(b).map(|(x, y)| (x - y) * (x - y)).sum()
+}
+
+/// `a + b * scale`, channel-wise.
+fn add(a: &Premultiplied, b: &Premultiplied, scale: f64) -> Premultiplied {
+    let mut out = *a;
+    for (o, v) in out.iter_mut().zip(b) {
+        *o += v * scale;
+    }
+    out
+}
+
+fn component(color: &Premultiplied, channel: usize) -> f64 {
+    color.get(channel).copied().unwrap_or(0.0)
+}
+
+fn weighted_mean(entries: &[Entry]) -> Premultiplied {
+    let mut sum = [0.0; 4];
+    let mut weight = 0.0;
+    for entry in entries {
+        sum = add(&sum, &entry.color, entry.weight);
+        weight += entry.weight;
+    }
+    if weight > 0.0 {
+        sum.map(|c| c / weight)
+    } else {
+        sum
+    }
+}
+
+/// Errors that can occur while quantizing an image.
+#[derive(Debug)]
+pub enum QuantizeError {
+    /// Palette size outside 2..=256.
+    InvalidColorCount(u16),
+    /// Quality floor above 100.
+    InvalidQuality(u8),
+    /// The indexed-output mode name was not recognized.
+    UnknownIndexedMode(String),
+    /// The dithering mode name was not recognized.
+    UnknownDither(String),
+    /// A caller-supplied palette was empty or had more than 256 colors.
+    InvalidPaletteSize(usize),
+    /// A batch or animation had no images.
+    NoImages,
+    /// The quantizer name or its parameter was not recognized.
+    UnknownQuantizer(String),
+    /// NeuQuant's sampling factor was outside 1..=30.
+    InvalidSampleFactor(u8),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// The best palette is worse than the requested floor.
+    QualityTooLow { quality: u8, minimum: u8 },
+    /// The image is too large for the output format or this platform.
+    TooLarge,
+    /// The PNG encoder failed.
+    Encode(png::EncodingError),
+    /// The GIF encoder failed.
+    GifEncode(gif::EncodingError),
+    /// The indexed PNG could not be recompressed.
+    Optimize(PngOptimizeError),
+}
+
+impl std::fmt::Display for QuantizeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidColorCount(count) => write!(
+                f,
+                "Color count must be between 2 and {MAX_COLORS}, got {count}"
+            ),
+            Self::InvalidQuality(quality) => {
+                write!(f, "Quality must be between 0 and 100, got {quality}")
+            }
+            Self::UnknownIndexedMode(name) => write!(
+                f,
+                "Unknown indexed PNG mode \"{name}\" (expected never, prefer or require)"
+            ),
+            Self::UnknownDither(name) => write!(
+                f,
+                "Unknown dithering mode \"{name}\" (expected none, floyd_steinberg or ordered)"
+            ),
+            Self::InvalidPaletteSize(size) => write!(
+                f,
+                "Palette must have between 1 and {MAX_COLORS} colors, got {size}"
+            ),
+            Self::UnknownQuantizer(name) => write!(
+                f,
+                "Unknown quantizer \"{name}\" (expected median_cut, wu or neuquant, optionally with :parameter)"
+            ),
+            Self::InvalidSampleFactor(factor) => write!(
+                f,
+                "NeuQuant sampling factor must be between 1 and 30, got {factor}"
+            ),
+            Self::NoImages => write!(f, "No images to quantize"),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::QualityTooLow { quality, minimum } => write!(
+                f,
+                "Palette quality {quality} is below the minimum of {minimum}"
+            ),
+            Self::TooLarge => write!(f, "Image is too large to quantize"),
+            Self::Encode(e) => write!(f, "Failed to encode PNG: {e}"),
+            Self::GifEncode(e) => write!(f, "Failed to encode GIF: {e}"),
+            Self::Optimize(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for QuantizeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    // ===== Fixture Helpers =====
+
+    fn encode(img: &RgbaImage) -> Vec<u8> {
+        let mut buf = Vec::new();
+        img.write_to(&mut Cursor::new(&mut buf), image::ImageFormat::Png)
+            .unwrap();
+        buf
+    }
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([
+                u8::try_from(x * 255 / (width - 1)).unwrap(),
+                u8::try_from(y * 255 / (height - 1)).unwrap(),
+                u8::try_from((x + y) * 255 / (width + height - 2)).unwrap(),
+                255,
+            ])
+        })
+    }
+
+    fn decode(data: &[u8]) -> RgbaImage {
+        image::load_from_memory(data).unwrap().to_rgba8()
+    }
+
+    fn palette_info(data: &[u8]) -> (png::ColorType, usize) {
+        let reader = png::Decoder::new(Cursor::new(data)).read_info().unwrap();
+        let info = reader.info();
+        (
+            info.color_type,
+            info.palette.as_ref().map_or(0, |p| p.len() / 3),
+        )
+    }
+
+    fn options(max_colors: u16, min_quality: u8, dither: bool) -> QuantizeOptions {
+        QuantizeOptions {
+            max_colors,
+            min_quality,
+            dither: if dither {
+                Dither::FloydSteinberg
+            } else {
+                Dither::None
+            },
+            ..QuantizeOptions::default()
+        }
+    }
+
+    // ===== Quantization Tests =====
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let img = RgbaImage::from_fn(20, 10, |x, _| match x % 3 {
+            0 => image::Rgba([255, 0, 0, 255]),
+            1 => image::Rgba([0, 0, 255, 128]),
+            _ => image::Rgba([0, 0, 0, 0]),
+        });
+        let result = quantize_png(&encode(&img), &QuantizeOptions::default()).unwrap();
+        assert_eq!(result.report.colors, 3);
+        assert_eq!(result.report.quality, 100);
+        assert_eq!(decode(&result.data), img);
+        assert_eq!(palette_info(&result.data), (png::ColorType::Indexed, 3));
+    }
+
+    #[test]
+    fn many_colors_fit_the_palette() {
+        let input = encode(&gradient(64, 64));
+        for max_colors in [2, 16, 64, 256] {
+            let result = quantize_png(&input, &options(max_colors, 0, true)).unwrap();
+            let (color, entries) = palette_info(&result.data);
+            assert_eq!(color, png::ColorType::Indexed);
+            assert!(entries <= usize::from(max_colors), "{max_colors}");
+            assert_eq!(result.report.colors, entries);
+            assert!(result.report.quality < 100);
+        }
+    }
+
+    #[test]
+    fn more_colors_give_higher_quality() {
+        let input = encode(&gradient(48, 48));
+        let small = quantize_png(&input, &options(8, 0, false)).unwrap();
+        let large = quantize_png(&input, &options(128, 0, false)).unwrap();
+        assert!(large.report.quality > small.report.quality);
+    }
+
+    #[test]
+    fn output_is_smaller_than_truecolor() {
+        let input = encode(&gradient(128, 96));
+        let result = quantize_png(&input, &QuantizeOptions::default()).unwrap();
+        assert!(result.data.len() < input.len());
+        assert_eq!(result.report.quantized_size, result.data.len());
+    }
+
+    #[test]
+    fn transparent_pixels_stay_transparent() {
+        let mut img = gradient(32, 32);
+        for (x, _, px) in img.enumerate_pixels_mut() {
+            if x < 8 {
+                px.0 = [200, 50, 10, 0];
+            } else if x < 16 {
+                px.0[3] = 100;
+            }
+        }
+        let result = quantize_png(&encode(&img), &options(16, 0, true)).unwrap();
+        let out = decode(&result.data);
+        for (x, _, px) in out.enumerate_pixels() {
+            if x < 8 {
+                assert_eq!(px.0[3], 0, "column {x}");
+            } else {
+                assert_ne!(px.0[3], 0, "column {x}");
+            }
+        }
+    }
+
+    #[test]
+    fn dithering_preserves_average_tone() {
+        let img = RgbaImage::from_fn(256, 8, |x, _| {
+            let v = u8::try_from(x).unwrap();
+            image::Rgba([v, v, v, 255])
+        });
+        let input = encode(&img);
+        let mean_error = |data: &[u8]| -> f64 {
+            let out = decode(data);
+            (0..256u32)
+                .step_by(16)
+                .map(|x0| {
+                    let band = |im: &RgbaImage| -> f64 {
+                        (x0..x0 + 16)
+                            .flat_map(|x| (0..8).map(move |y| (x, y)))
+                            .map(|(x, y)| f64::from(im.get_pixel(x, y).0[0]))
+                            .sum::<f64>()
+                    };
+                    (band(&out) - band(&img)).abs()
+                })
+                .sum()
+        };
+        let plain = quantize_png(&input, &options(4, 0, false)).unwrap();
+        let dithered = quantize_png(&input, &options(4, 0, true)).unwrap();
+        assert!(mean_error(&dithered.data) < mean_error(&plain.data));
+
+        let ordered = QuantizeOptions {
+            dither: Dither::Ordered,
+            ..options(4, 0, false)
+        };
+        let ordered = quantize_png(&input, &ordered).unwrap();
+        assert!(mean_error(&ordered.data) < mean_error(&plain.data));
+    }
+
+    #[test]
+    fn ordered_dithering_leaves_exact_palettes_alone() {
+        let img = RgbaImage::from_fn(16, 16, |x, y| {
+            let v = u8::try_from((x + y) % 4 * 60).unwrap();
+            image::Rgba([v, 255 - v, v / 2, 255])
+        });
+        let ordered = QuantizeOptions {
+            dither: Dither::Ordered,
+            ..QuantizeOptions::default()
+        };
+        let result = quantize_png(&encode(&img), &ordered).unwrap();
+        assert_eq!(decode(&result.data), img);
+        let gif = quantize_gif(&img, &ordered).unwrap();
+        assert_eq!(image::load_from_memory(&gif).unwrap().to_rgba8(), img);
+    }
+
+    #[test]
+    fn quality_floor_rejects_poor_palettes() {
+        let input = encode(&gradient(32, 32));
+        match quantize_png(&input, &options(2, 90, true)) {
+            Err(QuantizeError::QualityTooLow { quality, minimum }) => {
+                assert!(quality < 90);
+                assert_eq!(minimum, 90);
+            }
+            other => panic!("expected QualityTooLow, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn mse_quality_scale_is_monotonic() {
+        assert_eq!(mse_to_quality(0.0), 100);
+        assert_eq!(mse_to_quality(1.0), 0);
+        assert!(mse_to_quality(0.001) > mse_to_quality(0.01));
+    }
+
+    // ===== Exact Indexed Tests =====
+
+    #[test]
+    fn exact_indexed_keeps_every_pixel() {
+        let img = RgbaImage::from_fn(9, 5, |x, y| {
+            image::Rgba([
+                u8::try_from(x * 20).unwrap(),
+                0,
+                u8::try_from(y * 40).unwrap(),
+                200,
+            ])
+        });
+        let data = exact_indexed_png(&img).unwrap().unwrap();
+        assert_eq!(palette_info(&data), (png::ColorType::Indexed, 45));
+        assert_eq!(decode(&data), img);
+    }
+
+    #[test]
+    fn exact_indexed_gives_up_past_256_colors() {
+        assert!(exact_indexed_png(&gradient(64, 64)).unwrap().is_none());
+    }
+
+    #[test]
+    fn exact_indexed_output_is_deterministic() {
+        let img = RgbaImage::from_fn(16, 16, |x, y| {
+            image::Rgba([
+                u8::try_from(x * 16).unwrap(),
+                u8::try_from(y * 16).unwrap(),
+                0,
+                255,
+            ])
+        });
+        let first = exact_indexed_png(&img).unwrap();
+        for _ in 0..4 {
+            assert_eq!(exact_indexed_png(&img).unwrap(), first);
+        }
+    }
+
+    #[test]
+    fn indexed_modes_follow_js_values() {
+        for (index, value) in IndexedPng::ALL.into_iter().enumerate() {
+            // Safe: reading a fieldless enum's discriminant, which is its JS value.
+            #[allow(clippy::as_conversions)]
+            let discriminant = value as usize;
+            assert_eq!(discriminant, index);
+        }
+    }
+
+    #[test]
+    fn indexed_mode_from_name() {
+        assert_eq!(IndexedPng::from_name("").unwrap(), IndexedPng::Never);
+        assert_eq!(IndexedPng::from_name("Prefer").unwrap(), IndexedPng::Prefer);
+        assert_eq!(
+            IndexedPng::from_name("require").unwrap(),
+            IndexedPng::Require
+        );
+        assert!(matches!(
+            IndexedPng::from_name("always"),
+            Err(QuantizeError::UnknownIndexedMode(_))
+        ));
+    }
+
+    // ===== Error Tests =====
+
+    #[test]
+    fn rejects_invalid_options() {
+        let input = encode(&gradient(4, 4));
+        for count in [0, 1, 257] {
+            assert!(matches!(
+                quantize_png(&input, &options(count, 0, true)),
+                Err(QuantizeError::InvalidColorCount(c)) if c == count
+            ));
+        }
+        assert!(matches!(
+            quantize_png(&input, &options(16, 101, true)),
+            Err(QuantizeError::InvalidQuality(101))
+        ));
+    }
+
+    #[test]
+    fn rejects_undecodable_input() {
+        assert!(matches!(
+            quantize_png(b"not an image", &QuantizeOptions::default()),
+            Err(QuantizeError::Decode(_))
+        ));
+    }
+
+    // ===== Palette Remapping Tests =====
+
+    /// Fraction of white pixels after remapping a flat gray onto black and white.
+    fn white_fraction(level: u8, dither: Dither) -> f64 {
+        let img = RgbaImage::from_pixel(32, 32, image::Rgba([level, level, level, 255]));
+        let palette = [[0, 0, 0, 255], [255, 255, 255, 255]];
+        let indexed = remap_to_palette(&img, &palette, dither).unwrap();
+        let white = indexed.to_rgba().pixels().filter(|p| p.0[0] == 255).count();
+        f64::from(u32::try_from(white).unwrap()) / 1024.0
+    }
+
+    #[test]
+    fn ordered_dither_tracks_gray_level() {
+        assert_eq!(white_fraction(64, Dither::None), 0.0);
+        for level in [64u8, 128, 192] {
+            let fraction = white_fraction(level, Dither::Ordered);
+            let expected = f64::from(level) / 255.0;
+            assert!((fraction - expected).abs() < 0.05, "{level}: {fraction}");
+        }
+    }
+
+    #[test]
+    fn ordered_dither_is_a_fixed_pattern() {
+        let img = RgbaImage::from_pixel(16, 16, image::Rgba([128, 128, 128, 255]));
+        let palette = [[0, 0, 0, 255], [255, 255, 255, 255]];
+        let first = remap_to_palette(&img, &palette, Dither::Ordered).unwrap();
+        // The pattern repeats every 8 pixels in both directions.
+        let index = |x: usize, y: usize| first.indices[y * 16 + x];
+        for (x, y) in [(0, 0), (3, 5), (7, 2)] {
+            assert_eq!(index(x, y), index(x + 8, y + 8));
+        }
+        assert_ne!(index(0, 0), index(1, 0));
+    }
+
+    #[test]
+    fn remap_to_palette_keeps_transparency_with_clear_entry() {
+        let img = RgbaImage::from_fn(2, 1, |x, _| {
+            image::Rgba(if x == 0 {
+                [9, 9, 9, 0]
+            } else {
+                [250, 10, 10, 255]
+            })
+        });
+        let palette = [[255, 0, 0, 255], [0, 0, 0, 0], [0, 0, 255, 255]];
+        let indexed = remap_to_palette(&img, &palette, Dither::FloydSteinberg).unwrap();
+        assert_eq!(
+            indexed.palette[0],
+            [0, 0, 0, 0],
+            "translucent entries first"
+        );
+        let rgba = indexed.to_rgba();
+        assert_eq!(rgba.get_pixel(0, 0).0, [0, 0, 0, 0]);
+        assert_eq!(rgba.get_pixel(1, 0).0, [255, 0, 0, 255]);
+        assert!(matches!(
+            remap_to_palette(&img, &[[0; 4]; 257], Dither::None),
+            Err(QuantizeError::InvalidPaletteSize(257))
+        ));
+    }
+
+    #[test]
+    fn dither_modes_follow_js_values() {
+        for (index, value) in Dither::ALL.into_iter().enumerate() {
+            // Safe: reading a fieldless enum's discriminant, which is its JS value.
+            #[allow(clippy::as_conversions)]
+            let discriminant = value as usize;
+            assert_eq!(discriminant, index);
+        }
+    }
+
+    #[test]
+    fn dither_from_name() {
+        assert_eq!(Dither::from_name("").unwrap(), Dither::FloydSteinberg);
+        assert_eq!(Dither::from_name("Bayer").unwrap(), Dither::Ordered);
+        assert_eq!(Dither::from_name("none").unwrap(), Dither::None);
+        assert!(matches!(
+            Dither::from_name("atkinson"),
+            Err(QuantizeError::UnknownDither(_))
+        ));
+    }
+
+    // ===== Quantizer Tests =====
+
+    fn with_quantizer(max_colors: u16, quantizer: Quantizer) -> QuantizeOptions {
+        QuantizeOptions {
+            max_colors,
+            dither: Dither::None,
+            quantizer,
+            ..QuantizeOptions::default()
+        }
+    }
+
+    #[test]
+    fn every_quantizer_fills_the_palette() {
+        let histogram = histogram(&gradient(64, 64));
+        let baseline = palette_mse(
+            &build_palette(&histogram, 64, Quantizer::default()),
+            &histogram,
+        );
+        for quantizer in [
+            Quantizer::default(),
+            Quantizer::MedianCut { refine: 0 },
+            Quantizer::Wu { refine: 0 },
+            Quantizer::Wu { refine: 2 },
+            Quantizer::NeuQuant { sample_factor: 1 },
+            Quantizer::NeuQuant { sample_factor: 30 },
+        ] {
+            let palette = build_palette(&histogram, 64, quantizer);
+            assert!(palette.colors.len() > 48, "{quantizer:?}");
+            assert!(palette.colors.len() <= 64, "{quantizer:?}");
+            // Every algorithm lands in the same league as the default.
+            let mse = palette_mse(&palette, &histogram);
+            assert!(mse < baseline * 3.0, "{quantizer:?}: {mse} vs {baseline}");
+        }
+    }
+
+    #[test]
+    fn wu_refinement_does_not_hurt() {
+        let input = encode(&gradient(48, 48));
+        let plain = quantize_png(&input, &with_quantizer(16, Quantizer::Wu { refine: 0 })).unwrap();
+        let refined =
+            quantize_png(&input, &with_quantizer(16, Quantizer::Wu { refine: 4 })).unwrap();
+        assert!(refined.report.quality >= plain.report.quality);
+    }
+
+    #[test]
+    fn wu_keeps_transparent_apart_from_black() {
+        let mut img = gradient(32, 32);
+        for (x, y, px) in img.enumerate_pixels_mut() {
+            if x < 8 {
+                px.0 = [0; 4];
+            } else if y < 8 {
+                px.0 = [0, 0, 0, 255];
+            }
+        }
+        let result = quantize_png(
+            &encode(&img),
+            &with_quantizer(8, Quantizer::Wu { refine: 0 }),
+        )
+        .unwrap();
+        let out = decode(&result.data);
+        assert_eq!(out.get_pixel(0, 20).0[3], 0);
+        let [r, g, b, a] = out.get_pixel(20, 0).0;
+        assert_eq!(a, 255, "black must not share the transparent entry");
+        assert!(r.max(g).max(b) < 16, "{:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn neuquant_is_deterministic() {
+        let input = encode(&gradient(40, 40));
+        let options = with_quantizer(32, Quantizer::NeuQuant { sample_factor: 10 });
+        let first = quantize_png(&input, &options).unwrap();
+        let second = quantize_png(&input, &options).unwrap();
+        assert_eq!(first.data, second.data);
+    }
+
+    #[test]
+    fn quantizer_from_name() {
+        assert_eq!(Quantizer::from_name("").unwrap(), Quantizer::default());
+        assert_eq!(
+            Quantizer::from_name(" Median:0 ").unwrap(),
+            Quantizer::MedianCut { refine: 0 }
+        );
+        assert_eq!(
+            Quantizer::from_name("wu").unwrap(),
+            Quantizer::Wu { refine: 0 }
+        );
+        assert_eq!(
+            Quantizer::from_name("nq").unwrap(),
+            Quantizer::NeuQuant { sample_factor: 10 }
+        );
+        assert_eq!(
+            Quantizer::from_name("neuquant: 30").unwrap(),
+            Quantizer::NeuQuant { sample_factor: 30 }
+        );
+        assert!(matches!(
+            Quantizer::from_name("neuquant:31"),
+            Err(QuantizeError::InvalidSampleFactor(31))
+        ));
+        for bad in ["octree", "wu:", "wu:-1", "median:many"] {
+            assert!(
+                matches!(
+                    Quantizer::from_name(bad),
+                    Err(QuantizeError::UnknownQuantizer(_))
+                ),
+                "{bad}"
+            );
+        }
+    }
+
+    // ===== GIF Tests =====
+
+    fn decode_gif_frame(data: &[u8]) -> gif::Frame<'static> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(data).unwrap();
+        decoder.read_next_frame().unwrap().unwrap().clone()
+    }
+
+    #[test]
+    fn gif_uses_the_chosen_palette() {
+        let img = gradient(40, 30);
+        let options = with_quantizer(16, Quantizer::Wu { refine: 1 });
+        let gif = quantize_gif(&img, &options).unwrap();
+        let frame = decode_gif_frame(&gif);
+        assert_eq!((frame.width, frame.height), (40, 30));
+        assert_eq!(frame.transparent, None);
+        assert!(frame.palette.as_ref().unwrap().len() <= 16 * 3);
+
+        let out = image::load_from_memory(&gif).unwrap().to_rgba8();
+        let histogram = histogram(&img);
+        let palette = build_palette(&histogram, 16, options.quantizer);
+        assert_eq!(
+            out,
+            IndexedImage {
+                width: 40,
+                height: 30,
+                indices: palette.remap(&img, Dither::None),
+                palette: palette.colors,
+            }
+            .to_rgba()
+        );
+    }
+
+    #[test]
+    fn gif_reserves_an_entry_for_transparency() {
+        let mut img = gradient(32, 32);
+        for (x, _, px) in img.enumerate_pixels_mut() {
+            if x < 8 {
+                px.0 = [200, 50, 10, 0];
+            } else if x < 16 {
+                px.0[3] = 1;
+            }
+        }
+        let gif = quantize_gif(&img, &with_quantizer(4, Quantizer::default())).unwrap();
+        let frame = decode_gif_frame(&gif);
+        assert_eq!(frame.transparent, Some(3));
+        let out = image::load_from_memory(&gif).unwrap().to_rgba8();
+        for (x, _, px) in out.enumerate_pixels() {
+            assert_eq!(px.0[3], if x < 8 { 0 } else { 255 }, "column {x}");
+        }
+    }
+
+    #[test]
+    fn gif_rejects_oversized_images() {
+        let img = RgbaImage::new(70_000, 1);
+        assert!(matches!(
+            quantize_gif(&img, &QuantizeOptions::default()),
+            Err(QuantizeError::TooLarge)
+        ));
+    }
+
+    // ===== Shared Palette Tests =====
+
+    fn plte(data: &[u8]) -> Vec<u8> {
+        let reader = png::Decoder::new(Cursor::new(data)).read_info().unwrap();
+        reader.info().palette.as_ref().unwrap().to_vec()
+    }
+
+    #[test]
+    fn batch_shares_one_palette() {
+        let reds = RgbaImage::from_fn(32, 8, |x, _| {
+            image::Rgba([u8::try_from(x * 8).unwrap(), 0, 0, 255])
+        });
+        let blues = RgbaImage::from_fn(32, 8, |x, _| {
+            image::Rgba([0, 0, u8::try_from(x * 8).unwrap(), 255])
+        });
+        let inputs = [encode(&reds), encode(&blues)];
+        let results = quantize_png_batch(&inputs, &options(16, 0, false)).unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(plte(&results[0].data), plte(&results[1].data));
+        assert!(results[0].report.colors <= 16);
+
+        // On its own, each image would get all 16 entries for its own hue.
+        let alone = quantize_png(&inputs[0], &options(16, 0, false)).unwrap();
+        assert!(alone.report.quality >= results[0].report.quality);
+    }
+
+    #[test]
+    fn batch_of_few_colors_stays_exact() {
+        let a = RgbaImage::from_pixel(4, 4, image::Rgba([10, 20, 30, 255]));
+        let b = RgbaImage::from_pixel(4, 4, image::Rgba([200, 100, 0, 128]));
+        let results =
+            quantize_png_batch(&[encode(&a), encode(&b)], &QuantizeOptions::default()).unwrap();
+        assert_eq!(decode(&results[0].data), a);
+        assert_eq!(decode(&results[1].data), b);
+        assert!(results.iter().all(|r| r.report.quality == 100));
+    }
+
+    #[test]
+    fn batch_needs_images() {
+        assert!(matches!(
+            quantize_png_batch(&[], &QuantizeOptions::default()),
+            Err(QuantizeError::NoImages)
+        ));
+        assert!(matches!(
+            quantize_gif_frames(&[], &QuantizeOptions::default()),
+            Err(QuantizeError::NoImages)
+        ));
+    }
+
+    #[test]
+    fn gif_frames_use_a_global_palette() {
+        let delay = image::Delay::from_numer_denom_ms(70, 1);
+        let first = gradient(24, 16);
+        let mut second = first.clone();
+        image::imageops::flip_horizontal_in_place(&mut second);
+        second.put_pixel(0, 0, image::Rgba([1, 2, 3, 0]));
+        let frames = [
+            Frame::from_parts(first, 0, 0, delay),
+            Frame::from_parts(second, 0, 0, delay),
+        ];
+        let gif = quantize_gif_frames(&frames, &with_quantizer(32, Quantizer::default())).unwrap();
+
+        let mut decode_options = gif::DecodeOptions::new();
+        decode_options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = decode_options.read_info(gif.as_slice()).unwrap();
+        assert!(decoder.global_palette().unwrap().len() <= 32 * 3);
+        let mut count = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            assert!(frame.palette.is_none(), "frames use the global table");
+            assert_eq!(frame.delay, 7);
+            assert_eq!(frame.transparent, Some(31));
+            count += 1;
+        }
+        assert_eq!(count, 2);
+
+        let decoded = crate::animation::decode_frames(&gif).unwrap();
+        assert_eq!(decoded[1].buffer().get_pixel(0, 0).0[3], 0);
+        assert_eq!(decoded[1].buffer().get_pixel(1, 0).0[3], 255);
+    }
+}
@@ -0,0 +1,530 @@
+//! RAW preview extraction: camera RAW files (Canon CR2, Nikon NEF, Sony ARW), read
+//! through the JPEG previews cameras embed in them. The sensor data itself is only
+//! decoded with the `raw-decode` feature, by [`crate::raw_decode`].
+//!
+//! These formats are TIFF containers: besides the sensor data, they carry one or more
+//! JPEGs the camera rendered, usually one at or near full resolution. Extracting the
+//! largest one is instant and needs no demosaicing, color science or per-camera
+//! decompression, which is what a browser preview wants.
+
+use std::fmt;
+
+use image::metadata::Orientation;
+use image::DynamicImage;
+
+use crate::codecs::{CodecError, Decoder};
+
+/// The RAW formats [`camera_format`] recognizes.
+pub const RAW_FORMATS: [&str; 3] = ["cr2", "nef", "arw"];
+
+const TAG_MAKE: u16 = 0x010F;
+pub(crate) const TAG_STRIP_OFFSETS: u16 = 0x0111;
+const TAG_ORIENTATION: u16 = 0x0112;
+pub(crate) const TAG_STRIP_BYTE_COUNTS: u16 = 0x0117;
+const TAG_SUB_IFDS: u16 = 0x014A;
+const TAG_JPEG_OFFSET: u16 = 0x0201;
+const TAG_JPEG_LENGTH: u16 = 0x0202;
+const TAG_EXIF_IFD: u16 = 0x8769;
+
+/// IFDs visited at most, so a file with looping IFD offsets can't hang the walk.
+const MAX_IFDS: usize = 64;
+
+/// The RAW format of `input` (one of [`RAW_FORMATS`]), or `None` for anything else,
+/// including plain TIFFs.
+pub fn camera_format(input: &[u8]) -> Option<&'static str> {
+    let tiff = Tiff::read(input)?;
+    // CR2 marks itself right after the TIFF header.
+    if input.get(8..10) == Some(b"CR") {
+        return Some("cr2");
+    }
+    let make = tiff.ascii(tiff.first_ifd, TAG_MAKE)?.to_ascii_uppercase();
+    if make.starts_with("NIKON") {
+        Some("nef")
+    } else if make.starts_with("SONY") {
+        Some("arw")
+    } else {
+        None
+    }
+}
+
+/// The largest baseline or progressive JPEG embedded in a RAW file, byte for byte.
+///
+/// # Errors
+///
+/// Returns `RawError::NotRaw` if the input isn't a RAW format this module knows, or
+/// `RawError::NoPreview` if it embeds no JPEG the crate can decode.
+pub fn extract_preview(input: &[u8]) -> Result<Vec<u8>, RawError> {
+    let tiff = Tiff::read(input)
+        .filter(|_| camera_format(input).is_some())
+        .ok_or(RawError::NotRaw)?;
+    tiff.jpegs()
+        .into_iter()
+        .filter_map(|jpeg| Some((jpeg_area(jpeg)?, jpeg)))
+        .max_by_key(|&(area, _)| area)
+        .map(|(_, jpeg)| jpeg.to_vec())
+        .ok_or(RawError::NoPreview)
+}
+
+/// Every baseline or progressive JPEG embedded in a RAW file (thumbnails and previews),
+/// byte for byte.
+///
+/// # Errors
+///
+/// Returns `RawError::NotRaw` if the input isn't a RAW format this module knows.
+pub fn previews(input: &[u8]) -> Result<Vec<Vec<u8>>, RawError> {
+    let tiff = Tiff::read(input)
+        .filter(|_| camera_format(input).is_some())
+        .ok_or(RawError::NotRaw)?;
+    Ok(tiff
+        .jpegs()
+        .into_iter()
+        .filter(|jpeg| jpeg_area(jpeg).is_some())
+        .map(<[u8]>::to_vec)
+        .collect())
+}
+
+/// Decodes the embedded preview of a RAW file, turned upright by the file's
+/// orientation tag (previews don't carry their own). With the `raw-decode` feature,
+/// files without a preview are developed from their sensor data instead.
+///
+/// # Errors
+///
+/// Returns the errors of [`extract_preview`], or `RawError::Decode` if the preview
+/// can't be decoded. With `raw-decode`, files without a preview return the errors of
+/// [`crate::raw_decode::develop`] instead of `RawError::NoPreview`.
+pub fn decode(input: &[u8]) -> Result<DynamicImage, RawError> {
+    let preview = match extract_preview(input) {
+        Ok(preview) => preview,
+        #[cfg(feature = "raw-decode")]
+        Err(RawError::NoPreview) => return crate::raw_decode::develop(input),
+        Err(e) => return Err(e),
+    };
+    let mut image = image::load_from_memory_with_format(&preview, image::ImageFormat::Jpeg)
+        .map_err(RawError::Decode)?;
+    if let Some(orientation) = orientation(input) {
+        image.apply_orientation(orientation);
+    }
+    Ok(image)
+}
+
+/// The orientation tag of a RAW file's first IFD.
+pub(crate) fn orientation(input: &[u8]) -> Option<Orientation> {
+    Tiff::read(input)
+        .and_then(|tiff| tiff.short(tiff.first_ifd, TAG_ORIENTATION))
+        .and_then(|value| u8::try_from(value).ok())
+        .and_then(Orientation::from_exif)
+}
+
+/// The RAW decoder the conversion pipeline uses for inputs [`camera_format`]
+/// recognizes, named after the format.
+#[derive(Debug, Clone, Copy)]
+pub struct RawDecoder(pub &'static str);
+
+impl Decoder for RawDecoder {
+    fn name(&self) -> &'static str {
+        self.0
+    }
+
+    fn detect(&self, input: &[u8]) -> bool {
+        camera_format(input) == Some(self.0)
+    }
+
+    fn decode(&self, input: &[u8]) -> Result<DynamicImage, CodecError> {
+        decode(input).map_err(|e| CodecError::Failed(e.to_string()))
+    }
+}
+
+/// Pixel count of a JPEG the crate can decode, from its frame header. Lossless JPEG
+/// (the sensor data of CR2 and some NEFs) and other coding processes are skipped.
+fn jpeg_area(jpeg: &[u8]) -> Option<u64> {
+    if jpeg.get(..2) != Some(&[0xFF, 0xD8]) {
+        return None;
+    }
+    let mut pos = 2;
+    loop {
+        if *jpeg.get(pos)? != 0xFF {
+            return None;
+        }
+        let marker = *jpeg.get(pos + 1)?;
+        let length = usize::from(u16::from_be_bytes([
+            *jpeg.get(pos + 2)?,
+            *jpeg.get(pos + 3)?,
+        ]));
+        match marker {
+            // Baseline, extended sequential and progressive Huffman frames.
+            0xC0..=0xC2 => {
+                let height = u16::from_be_bytes([*jpeg.get(pos + 5)?, *jpeg.get(pos + 6)?]);
+                let width = u16::from_be_bytes([*jpeg.get(pos + 7)?, *jpeg.get(pos + 8)?]);
+                return Some(u64::from(width) * u64::from(height));
+            }
+            0xC3..=0xCF if marker != 0xC4 && marker != 0xC8 && marker != 0xCC => return None,
+            0xDA | 0xD9 => return None,
+            _ => pos += 2 + length,
+        }
+    }
+}
+
+/// Byte order of a TIFF structure.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum ByteOrder {
+    Little,
+    Big,
+}
+
+/// A TIFF container, read lazily from the input.
+pub(crate) struct Tiff<'a> {
+    pub(crate) data: &'a [u8],
+    pub(crate) order: ByteOrder,
+    pub(crate) first_ifd: usize,
+}
+
+impl<'a> Tiff<'a> {
+    pub(crate) fn read(data: &'a [u8]) -> Option<Self> {
+        let order = match data.get(..4)? {
+            b"II\x2a\0" => ByteOrder::Little,
+            b"MM\0\x2a" => ByteOrder::Big,
+            _ => return None,
+        };
+        let mut tiff = Self {
+            data,
+            order,
+            first_ifd: 0,
+        };
+        tiff.first_ifd = tiff.offset(4)?;
+        Some(tiff)
+    }
+
+    fn u16(&self, pos: usize) -> Option<u16> {
+        let bytes = [*self.data.get(pos)?, *self.data.get(pos.checked_add(1)?)?];
+        Some(match self.order {
+            ByteOrder::Little => u16::from_le_bytes(bytes),
+            ByteOrder::Big => u16::from_be_bytes(bytes),
+        })
+    }
+
+    fn u32(&self, pos: usize) -> Option<u32> {
+        let bytes: [u8; 4] = self.data.get(pos..pos.checked_add(4)?)?.try_into().ok()?;
+        Some(match self.order {
+            ByteOrder::Little => u32::from_le_bytes(bytes),
+            ByteOrder::Big => u32::from_be_bytes(bytes),
+        })
+    }
+
+    fn offset(&self, pos: usize) -> Option<usize> {
+        usize::try_from(self.u32(pos)?).ok()
+    }
+
+    /// Offsets of the 12-byte entries of the IFD at `ifd`.
+    fn entries(&self, ifd: usize) -> impl Iterator<Item = usize> {
+        let count = self.u16(ifd).map_or(0, usize::from);
+        (0..count).map(move |index| ifd + 2 + index * 12)
+    }
+
+    pub(crate) fn entry(&self, ifd: usize, tag: u16) -> Option<usize> {
+        self.entries(ifd)
+            .find(|&entry| self.u16(entry) == Some(tag))
+    }
+
+    /// The values of a SHORT or LONG entry, up to [`MAX_IFDS`] of them.
+    pub(crate) fn values(&self, entry: usize) -> Vec<u32> {
+        self.values_up_to(entry, MAX_IFDS)
+    }
+
+    /// The first `limit` values of a SHORT or LONG entry.
+    pub(crate) fn values_up_to(&self, entry: usize, limit: usize) -> Vec<u32> {
+        let (Some(kind), Some(count)) = (self.u16(entry + 2), self.offset(entry + 4)) else {
+            return Vec::new();
+        };
+        let size = match kind {
+            3 => 2,
+            4 | 13 => 4,
+            _ => return Vec::new(),
+        };
+        let start = if count.saturating_mul(size) <= 4 {
+            entry + 8
+        } else {
+            match self.offset(entry + 8) {
+                Some(start) => start,
+                None => return Vec::new(),
+            }
+        };
+        (0..count.min(limit))
+            .map_while(|index| {
+                let pos = start.checked_add(index * size)?;
+                match size {
+                    2 => self.u16(pos).map(u32::from),
+                    _ => self.u32(pos),
+                }
+            })
+            .collect()
+    }
+
+    pub(crate) fn short(&self, ifd: usize, tag: u16) -> Option<u32> {
+        self.values(self.entry(ifd, tag)?).first().copied()
+    }
+
+    fn ascii(&self, ifd: usize, tag: u16) -> Option<String> {
+        let entry = self.entry(ifd, tag)?;
+        let count = self.offset(entry + 4)?;
+        let start = if count <= 4 {
+            entry + 8
+        } else {
+            self.offset(entry + 8)?
+        };
+        let text = self.data.get(start..start.checked_add(count)?)?;
+        let text = text.split(|&byte| byte == 0).next().unwrap_or_default();
+        Some(String::from_utf8_lossy(text).into_owned())
+    }
+
+    /// The values of a BYTE or UNDEFINED entry.
+    #[cfg(feature = "raw-decode")]
+    pub(crate) fn bytes(&self, entry: usize) -> Option<&'a [u8]> {
+        self.data.get(self.byte_range(entry)?)
+    }
+
+    /// Where the values of a BYTE or UNDEFINED entry are in the data.
+    #[cfg(feature = "raw-decode")]
+    pub(crate) fn byte_range(&self, entry: usize) -> Option<std::ops::Range<usize>> {
+        if !matches!(self.u16(entry + 2)?, 1 | 7) {
+            return None;
+        }
+        let count = self.offset(entry + 4)?;
+        let start = if count <= 4 {
+            entry + 8
+        } else {
+            self.offset(entry + 8)?
+        };
+        Some(start..start.checked_add(count)?)
+    }
+
+    /// The values of a RATIONAL or SRATIONAL entry, up to [`MAX_IFDS`] of them, or
+    /// `None` if the entry has another type or a zero denominator.
+    #[cfg(feature = "raw-decode")]
+    pub(crate) fn reals(&self, entry: usize) -> Option<Vec<f64>> {
+        let signed = match self.u16(entry + 2)? {
+            5 => false,
+            10 => true,
+            _ => return None,
+        };
+        let count = self.offset(entry + 4)?.min(MAX_IFDS);
+        let start = self.offset(entry + 8)?;
+        (0..count)
+            .map(|index| {
+                let pos = start.checked_add(index * 8)?;
+                let (numerator, denominator) = (self.u32(pos)?, self.u32(pos + 4)?);
+                let real = if signed {
+                    let numerator = i32::from_ne_bytes(numerator.to_ne_bytes());
+                    let denominator = i32::from_ne_bytes(denominator.to_ne_bytes());
+                    f64::from(numerator) / f64::from(denominator)
+                } else {
+                    f64::from(numerator) / f64::from(denominator)
+                };
+                Some(real).filter(|real| real.is_finite())
+            })
+            .collect()
+    }
+
+    /// Every IFD reachable from the first: the IFD chain, SubIFDs and the EXIF IFD.
+    pub(crate) fn ifds(&self) -> Vec<usize> {
+        let mut seen = Vec::new();
+        let mut pending = vec![self.first_ifd];
+        while let Some(ifd) = pending.pop() {
+            if ifd == 0 || seen.contains(&ifd) || seen.len() == MAX_IFDS {
+                continue;
+            }
+            seen.push(ifd);
+            let count = self.u16(ifd).map_or(0, usize::from);
+            if let Some(next) = self.offset(ifd + 2 + count * 12) {
+                pending.push(next);
+            }
+            for tag in [TAG_SUB_IFDS, TAG_EXIF_IFD] {
+                if let Some(entry) = self.entry(ifd, tag) {
+                    pending.extend(
+                        self.values(entry)
+                            .into_iter()
+                            .filter_map(|offset| usize::try_from(offset).ok()),
+                    );
+                }
+            }
+        }
+        seen
+    }
+
+    /// Candidate embedded JPEGs: JPEG interchange-format data and single-strip images
+    /// that start with a JPEG SOI marker.
+    fn jpegs(&self) -> Vec<&'a [u8]> {
+        let slice = |start: u32, length: u32| {
+            let start = usize::try_from(start).ok()?;
+            let end = start.checked_add(usize::try_from(length).ok()?)?;
+            self.data.get(start..end)
+        };
+        let mut jpegs = Vec::new();
+        for ifd in self.ifds() {
+            for (offset_tag, length_tag) in [
+                (TAG_JPEG_OFFSET, TAG_JPEG_LENGTH),
+                (TAG_STRIP_OFFSETS, TAG_STRIP_BYTE_COUNTS),
+            ] {
+                let offsets = self.entry(ifd, offset_tag).map(|e| self.values(e));
+                let lengths = self.entry(ifd, length_tag).map(|e| self.values(e));
+                if let (Some([start]), Some([length])) = (offsets.as_deref(), lengths.as_deref()) {
+                    jpegs.extend(slice(*start, *length));
+                }
+            }
+        }
+        jpegs
+    }
+}
+
+/// Errors that can occur while reading RAW files.
+#[derive(Debug)]
+pub enum RawError {
+    /// The input isn't a CR2, NEF or ARW file.
+    NotRaw,
+    /// The file embeds no JPEG preview the crate can decode.
+    NoPreview,
+    /// The preview couldn't be decoded.
+    Decode(image::ImageError),
+    /// The sensor data is compressed in a way the crate can't read (such as Nikon's or
+    /// Sony's own compression), or doesn't use a 2x2 RGB filter pattern.
+    #[cfg(feature = "raw-decode")]
+    UnsupportedSensorData,
+    /// The sensor data is truncated or corrupt.
+    #[cfg(feature = "raw-decode")]
+    InvalidSensorData,
+}
+
+impl fmt::Display for RawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NotRaw => write!(f, "Input is not a CR2, NEF or ARW file"),
+            Self::NoPreview => write!(f, "The RAW file has no embedded JPEG preview"),
+            Self::Decode(e) => write!(f, "Failed to decode the RAW preview: {e}"),
+            #[cfg(feature = "raw-decode")]
+            Self::UnsupportedSensorData => {
+                write!(f, "The RAW file's sensor data uses an unsupported encoding")
+            }
+            #[cfg(feature = "raw-decode")]
+            Self::InvalidSensorData => write!(f, "The RAW file's sensor data is corrupt"),
+        }
+    }
+}
+
+impl std::error::Error for RawError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+    use crate::convert;
+    use crate::formats::ImageFormat;
+
+    fn jpeg(width: u32, height: u32) -> Vec<u8> {
+        let mut out = Vec::new();
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Jpeg)
+            .unwrap();
+        out
+    }
+
+    fn entry(tag: u16, kind: u16, count: u32, value: u32) -> Vec<u8> {
+        let mut entry = tag.to_le_bytes().to_vec();
+        entry.extend(kind.to_le_bytes());
+        entry.extend(count.to_le_bytes());
+        entry.extend(value.to_le_bytes());
+        entry
+    }
+
+    fn ifd(entries: &[Vec<u8>], next: u32) -> Vec<u8> {
+        let mut ifd = u16::try_from(entries.len()).unwrap().to_le_bytes().to_vec();
+        for entry in entries {
+            ifd.extend(entry);
+        }
+        ifd.extend(next.to_le_bytes());
+        ifd
+    }
+
+    /// A little-endian NEF-like file: IFD0 with the make, an orientation, a small
+    /// thumbnail and a SubIFD holding the large preview, plus a lossless-JPEG strip
+    /// standing in for the sensor data.
+    fn nef(orientation: u32) -> (Vec<u8>, Vec<u8>) {
+        let make = b"NIKON CORPORATION\0";
+        let thumbnail = jpeg(4, 2);
+        let preview = jpeg(16, 8);
+        let sensor = [
+            0xFF, 0xD8, 0xFF, 0xC3, 0x00, 0x0B, 0x0C, 0x01, 0x00, 0x01, 0x00,
+        ];
+
+        let ifd0_at = 8;
+        let ifd0_len = 2 + 5 * 12 + 4;
+        let sub_at = ifd0_at + ifd0_len;
+        let sub_len = 2 + 2 * 12 + 4;
+        let sensor_ifd_at = sub_at + sub_len;
+        let make_at = sensor_ifd_at + sub_len;
+        let thumbnail_at = make_at + make.len();
+        let preview_at = thumbnail_at + thumbnail.len();
+        let sensor_at = preview_at + preview.len();
+        let at = |offset: usize| u32::try_from(offset).unwrap();
+        let len = |bytes: &[u8]| u32::try_from(bytes.len()).unwrap();
+
+        let mut file = b"II\x2a\0".to_vec();
+        file.extend(at(ifd0_at).to_le_bytes());
+        file.extend(ifd(
+            &[
+                entry(TAG_MAKE, 2, len(make), at(make_at)),
+                entry(TAG_ORIENTATION, 3, 1, orientation),
+                entry(TAG_SUB_IFDS, 4, 1, at(sub_at)),
+                entry(TAG_JPEG_OFFSET, 4, 1, at(thumbnail_at)),
+                entry(TAG_JPEG_LENGTH, 4, 1, len(&thumbnail)),
+            ],
+            0,
+        ));
+        file.extend(ifd(
+            &[
+                entry(TAG_JPEG_OFFSET, 4, 1, at(preview_at)),
+                entry(TAG_JPEG_LENGTH, 4, 1, len(&preview)),
+            ],
+            at(sensor_ifd_at),
+        ));
+        file.extend(ifd(
+            &[
+                entry(TAG_STRIP_OFFSETS, 4, 1, at(sensor_at)),
+                entry(TAG_STRIP_BYTE_COUNTS, 4, 1, len(&sensor)),
+            ],
+            0,
+        ));
+        file.extend(make);
+        file.extend(&thumbnail);
+        file.extend(&preview);
+        file.extend(sensor);
+        (file, preview)
+    }
+
+    #[test]
+    fn extracts_the_largest_decodable_preview() {
+        let (file, preview) = nef(1);
+        assert_eq!(camera_format(&file), Some("nef"));
+        assert_eq!(extract_preview(&file).unwrap(), preview);
+        assert_eq!(previews(&file).unwrap().len(), 2);
+
+        let mut tiff = Vec::new();
+        DynamicImage::new_rgb8(2, 2)
+            .write_to(&mut Cursor::new(&mut tiff), image::ImageFormat::Tiff)
+            .unwrap();
+        assert_eq!(camera_format(&tiff), None);
+        assert!(matches!(extract_preview(&tiff), Err(RawError::NotRaw)));
+    }
+
+    #[test]
+    fn raw_files_convert_upright() {
+        // Orientation 6: the camera was turned 90° clockwise.
+        let (file, _) = nef(6);
+        let img = decode(&file).unwrap();
+        assert_eq!((img.width(), img.height()), (8, 16));
+
+        let png = convert::convert(file.clone(), ImageFormat::Png, None, &[]).unwrap();
+        let img = image::load_from_memory(&png).unwrap();
+        assert_eq!((img.width(), img.height()), (8, 16));
+        assert_eq!(convert::dimensions(&file).unwrap().height, 16);
+    }
+}
@@ -0,0 +1,1463 @@
+//! RAW sensor decoding (only in builds with the `raw-decode` feature): develops the
+//! sensor data of camera RAW files into RGB, for files without a usable embedded
+//! preview or when the sensor data itself is wanted.
+//!
+//! Uncompressed CFA data and lossless JPEG (the sensor data of CR2 files) are read,
+//! then developed simply: black and white levels, white balance, bilinear demosaicing,
+//! the file's color matrix and the sRGB curve. White balance is the camera's as-shot
+//! setting where the file records it in a form read here (the DNG AsShotNeutral tag,
+//! Canon's color data or Nikon's white balance levels), and gray world otherwise. The
+//! color matrix comes from the DNG ColorMatrix tags; there are no per-camera tables,
+//! so files without them keep the camera's own primaries. Masked border pixels that
+//! some sensors record are kept, and Nikon's and Sony's own compressions aren't read.
+
+use image::{DynamicImage, RgbImage};
+
+use crate::color;
+use crate::generate::MAX_SIDE;
+use crate::raw::{self, ByteOrder, RawError, Tiff, TAG_STRIP_BYTE_COUNTS, TAG_STRIP_OFFSETS};
+
+const TAG_IMAGE_WIDTH: u16 = 0x0100;
+const TAG_IMAGE_LENGTH: u16 = 0x0101;
+const TAG_BITS_PER_SAMPLE: u16 = 0x0102;
+const TAG_COMPRESSION: u16 = 0x0103;
+const TAG_PHOTOMETRIC: u16 = 0x0106;
+const TAG_CFA_REPEAT_PATTERN_DIM: u16 = 0x828D;
+const TAG_CFA_PATTERN: u16 = 0x828E;
+const TAG_BLACK_LEVEL: u16 = 0xC61A;
+const TAG_WHITE_LEVEL: u16 = 0xC61D;
+const TAG_CR2_SLICE: u16 = 0xC640;
+const TAG_MAKER_NOTE: u16 = 0x927C;
+const TAG_COLOR_MATRIX_1: u16 = 0xC621;
+const TAG_COLOR_MATRIX_2: u16 = 0xC622;
+const TAG_AS_SHOT_NEUTRAL: u16 = 0xC628;
+/// Canon's color data, in its maker note.
+const TAG_CANON_COLOR_DATA: u16 = 0x4001;
+/// Nikon's red and blue white balance levels, in its maker note.
+const TAG_NIKON_WB_LEVELS: u16 = 0x000C;
+
+const COMPRESSION_NONE: u32 = 1;
+const COMPRESSION_OLD_JPEG: u32 = 6;
+const COMPRESSION_JPEG: u32 = 7;
+const PHOTOMETRIC_CFA: u32 = 32803;
+
+/// Most strips read from one IFD.
+const MAX_STRIPS: usize = 1 << 16;
+
+/// Filter colors of a 2x2 pattern, row by row, when the file doesn't say: red, green,
+/// green, blue, as on most Canon sensors.
+const RGGB: [usize; 4] = [0, 1, 1, 2];
+
+/// Largest white balance gain, so a channel with almost no signal isn't blown up.
+const MAX_GAIN: f64 = 8.0;
+
+/// Most values read from Canon's color data, which is at most a few thousand long.
+const MAX_COLOR_DATA: usize = 1 << 13;
+
+/// Linear sRGB to CIE XYZ, row-major, for the D65 white point both use.
+const XYZ_FROM_SRGB: [f64; 9] = [
+    0.412_453, 0.357_580, 0.180_423, //
+    0.212_671, 0.715_160, 0.072_169, //
+    0.019_334, 0.119_193, 0.950_227,
+];
+
+/// Sensor values, one per photosite, before development.
+#[derive(Debug, Clone)]
+struct Sensor {
+    width: usize,
+    height: usize,
+    values: Vec<u16>,
+    /// The filter color (0 red, 1 green, 2 blue) of each photosite of the 2x2
+    /// pattern, row by row.
+    pattern: [usize; 4],
+    /// The value of no light, or `None` to estimate it.
+    black: Option<u16>,
+    /// The value at which photosites clip.
+    white: u16,
+    /// The camera's white balance gains for red, green and blue, or `None` to estimate
+    /// them.
+    as_shot: Option<[f64; 3]>,
+    /// White-balanced camera values to linear sRGB, row-major, or `None` to use the
+    /// camera's values as they are.
+    matrix: Option<[f64; 9]>,
+}
+
+/// Where the sensor data of one IFD is and how it's stored.
+#[derive(Debug, Clone, Copy)]
+enum Layout {
+    /// Uncompressed values of `bits` bits each.
+    Uncompressed {
+        width: usize,
+        height: usize,
+        bits: u32,
+    },
+    /// A lossless JPEG, possibly cut into CR2 slices.
+    LosslessJpeg(Frame),
+}
+
+impl Layout {
+    fn area(&self) -> usize {
+        match self {
+            Self::Uncompressed { width, height, .. } => width.saturating_mul(*height),
+            Self::LosslessJpeg(frame) => frame.line().saturating_mul(frame.height),
+        }
+    }
+}
+
+/// Develops the sensor data of a RAW file into an RGB image, turned upright by the
+/// file's orientation tag. Slower than [`raw::decode`], which reads the preview.
+///
+/// # Errors
+///
+/// Returns `RawError::NotRaw` if the input isn't a RAW format [`raw::camera_format`]
+/// knows, `RawError::UnsupportedSensorData` if its sensor data is stored in a way this
+/// module can't read, or `RawError::InvalidSensorData` if it is truncated or corrupt.
+pub fn develop(input: &[u8]) -> Result<DynamicImage, RawError> {
+    let (Some(tiff), Some(format)) = (Tiff::read(input), raw::camera_format(input)) else {
+        return Err(RawError::NotRaw);
+    };
+    let mut image = DynamicImage::ImageRgb8(read_sensor(&tiff, format)?.develop());
+    if let Some(orientation) = raw::orientation(input) {
+        image.apply_orientation(orientation);
+    }
+    Ok(image)
+}
+
+/// Reads the largest sensor image in the file, and how to color it from the metadata
+/// of its `format` (one of [`raw::RAW_FORMATS`]).
+fn read_sensor(tiff: &Tiff<'_>, format: &str) -> Result<Sensor, RawError> {
+    let mut best: Option<(Layout, Vec<u8>, usize)> = None;
+    for ifd in tiff.ifds() {
+        let compression = tiff.short(ifd, TAG_COMPRESSION).unwrap_or(COMPRESSION_NONE);
+        let cfa = tiff.short(ifd, TAG_PHOTOMETRIC) == Some(PHOTOMETRIC_CFA);
+        let jpeg = matches!(compression, COMPRESSION_OLD_JPEG | COMPRESSION_JPEG);
+        if !cfa && !jpeg {
+            continue;
+        }
+        let Some(data) = strips(tiff, ifd) else {
+            continue;
+        };
+        let layout = match compression {
+            COMPRESSION_NONE if cfa => {
+                let size = |tag| {
+                    tiff.short(ifd, tag)
+                        .and_then(|side| usize::try_from(side).ok())
+                };
+                let (Some(width), Some(height)) = (size(TAG_IMAGE_WIDTH), size(TAG_IMAGE_LENGTH))
+                else {
+                    continue;
+                };
+                Layout::Uncompressed {
+                    width,
+                    height,
+                    bits: tiff.short(ifd, TAG_BITS_PER_SAMPLE).unwrap_or(16),
+                }
+            }
+            COMPRESSION_OLD_JPEG | COMPRESSION_JPEG => match lossless_frame(&data) {
+                Some(frame) => Layout::LosslessJpeg(frame),
+                None => continue,
+            },
+            // Sensor data in a compression this module doesn't read.
+            _ if cfa => return Err(RawError::UnsupportedSensorData),
+            _ => continue,
+        };
+        if best
+            .as_ref()
+            .is_none_or(|(best, ..)| layout.area() > best.area())
+        {
+            best = Some((layout, data, ifd));
+        }
+    }
+    let (layout, data, ifd) = best.ok_or(RawError::UnsupportedSensorData)?;
+
+    let (width, height, values, bits) = match layout {
+        Layout::Uncompressed {
+            width,
+            height,
+            bits,
+        } => {
+            check_size(width, height)?;
+            let values = unpack(&data, width * height, bits, tiff.order)
+                .ok_or(RawError::InvalidSensorData)?;
+            (width, height, values, bits)
+        }
+        Layout::LosslessJpeg(frame) => {
+            let (width, height) = (frame.line(), frame.height);
+            check_size(width, height)?;
+            let values = decode_lossless(&data, &frame).ok_or(RawError::InvalidSensorData)?;
+            let values = match tiff.entry(ifd, TAG_CR2_SLICE).map(|e| tiff.values(e)) {
+                Some(slices) => {
+                    unslice(&values, width, height, &slices).ok_or(RawError::InvalidSensorData)?
+                }
+                None => values,
+            };
+            (width, height, values, u32::from(frame.precision))
+        }
+    };
+
+    let level = |tag| {
+        tiff.entry(ifd, tag)
+            .and_then(|entry| tiff.values(entry).first().copied())
+            .and_then(|level| u16::try_from(level).ok())
+    };
+    let max = u16::try_from((1u32 << bits.min(16)) - 1).unwrap_or(u16::MAX);
+    Ok(Sensor {
+        width,
+        height,
+        values,
+        pattern: pattern(tiff, ifd)?,
+        black: level(TAG_BLACK_LEVEL),
+        white: level(TAG_WHITE_LEVEL).unwrap_or(max),
+        as_shot: as_shot_gains(tiff, format),
+        matrix: camera_to_srgb(tiff),
+    })
+}
+
+fn check_size(width: usize, height: usize) -> Result<(), RawError> {
+    let side = usize::try_from(MAX_SIDE).unwrap_or(usize::MAX);
+    if width == 0 || height == 0 || width > side || height > side {
+        return Err(RawError::InvalidSensorData);
+    }
+    Ok(())
+}
+
+/// The concatenated strips of an IFD.
+fn strips(tiff: &Tiff<'_>, ifd: usize) -> Option<Vec<u8>> {
+    let offsets = tiff.values_up_to(tiff.entry(ifd, TAG_STRIP_OFFSETS)?, MAX_STRIPS);
+    let lengths = tiff.values_up_to(tiff.entry(ifd, TAG_STRIP_BYTE_COUNTS)?, MAX_STRIPS);
+    if offsets.is_empty() || offsets.len() != lengths.len() {
+        return None;
+    }
+    let mut data = Vec::new();
+    for (&offset, &length) in offsets.iter().zip(&lengths) {
+        let start = usize::try_from(offset).ok()?;
+        let end = start.checked_add(usize::try_from(length).ok()?)?;
+        data.extend_from_slice(tiff.data.get(start..end)?);
+    }
+    Some(data)
+}
+
+/// The 2x2 filter pattern of the sensor IFD, from its TIFF/EP CFA tags, or [`RGGB`].
+fn pattern(tiff: &Tiff<'_>, ifd: usize) -> Result<[usize; 4], RawError> {
+    let Some(entry) = tiff.entry(ifd, TAG_CFA_PATTERN) else {
+        return Ok(RGGB);
+    };
+    let dims = tiff
+        .entry(ifd, TAG_CFA_REPEAT_PATTERN_DIM)
+        .map(|entry| tiff.values(entry));
+    match (dims.as_deref(), tiff.bytes(entry)) {
+        (Some([2, 2]), Some(&[a, b, c, d])) if [a, b, c, d].iter().all(|&color| color <= 2) => {
+            Ok([a, b, c, d].map(usize::from))
+        }
+        _ => Err(RawError::UnsupportedSensorData),
+    }
+}
+
+/// The first value of `tag` in any IFD, as reals.
+fn reals(tiff: &Tiff<'_>, tag: u16) -> Option<Vec<f64>> {
+    tiff.ifds()
+        .into_iter()
+        .find_map(|ifd| tiff.reals(tiff.entry(ifd, tag)?))
+}
+
+/// The camera's white balance when the picture was taken, as gains for red, green and
+/// blue with green at 1, or `None` if the file doesn't record it in a form read here.
+fn as_shot_gains(tiff: &Tiff<'_>, format: &str) -> Option<[f64; 3]> {
+    let gains = match reals(tiff, TAG_AS_SHOT_NEUTRAL).as_deref() {
+        // The camera values of white, which the gains bring back to 1.
+        Some(&[red, green, blue]) => [1.0 / red, 1.0 / green, 1.0 / blue],
+        _ => {
+            let note = tiff
+                .ifds()
+                .into_iter()
+                .find_map(|ifd| tiff.byte_range(tiff.entry(ifd, TAG_MAKER_NOTE)?))?;
+            match format {
+                "cr2" => canon_levels(tiff, note.start)?,
+                "nef" => nikon_levels(tiff.data.get(note)?)?,
+                _ => return None,
+            }
+        }
+    };
+    let green = gains[1];
+    if !gains.iter().all(|gain| gain.is_finite() && *gain > 0.0) {
+        return None;
+    }
+    Some(gains.map(|gain| (gain / green).clamp(1.0 / MAX_GAIN, MAX_GAIN)))
+}
+
+/// The as-shot levels in Canon's color data, from the maker note IFD at `note`. Where
+/// they sit depends on the version of the data, which its length tells.
+fn canon_levels(tiff: &Tiff<'_>, note: usize) -> Option<[f64; 3]> {
+    let data = tiff.values_up_to(tiff.entry(note, TAG_CANON_COLOR_DATA)?, MAX_COLOR_DATA);
+    let start = match data.len() {
+        582 => 25,
+        653 => 34,
+        5120 => 71,
+        len if len > 500 => 63,
+        _ => return None,
+    };
+    // Red, green, green and blue.
+    let &[red, green1, green2, blue] = data.get(start..start + 4)? else {
+        return None;
+    };
+    let green = (f64::from(green1) + f64::from(green2)) / 2.0;
+    Some([f64::from(red), green, f64::from(blue)])
+}
+
+/// Nikon's red and blue white balance levels, from a maker note that embeds its own
+/// TIFF structure after a 10-byte header.
+fn nikon_levels(note: &[u8]) -> Option<[f64; 3]> {
+    let note = Tiff::read(note.strip_prefix(b"Nikon\0")?.get(4..)?)?;
+    let levels = note.reals(note.entry(note.first_ifd, TAG_NIKON_WB_LEVELS)?)?;
+    let [red, blue, ..] = *levels.as_slice() else {
+        return None;
+    };
+    Some([red, 1.0, blue])
+}
+
+/// The matrix from white-balanced camera values to linear sRGB, from the DNG color
+/// matrix for D65 (usually the second) or else the other one, each of which maps XYZ
+/// to camera values.
+fn camera_to_srgb(tiff: &Tiff<'_>) -> Option<[f64; 9]> {
+    let camera_from_xyz = [TAG_COLOR_MATRIX_2, TAG_COLOR_MATRIX_1]
+        .into_iter()
+        .find_map(|tag| <[f64; 9]>::try_from(reals(tiff, tag)?).ok())?;
+    let mut camera_from_srgb = multiply(camera_from_xyz, XYZ_FROM_SRGB);
+    // Scale each row so that white, balanced to 1 in every camera channel, maps to
+    // sRGB white.
+    for row in camera_from_srgb.chunks_exact_mut(3) {
+        let sum: f64 = row.iter().sum();
+        if sum.abs() < f64::EPSILON {
+            return None;
+        }
+        for value in row {
+            *value /= sum;
+        }
+    }
+    invert(camera_from_srgb)
+}
+
+/// The product of two row-major 3x3 matrices.
+fn multiply(left: [f64; 9], right: [f64; 9]) -> [f64; 9] {
+    let [a, b, c, d, e, f, g, h, i] = left;
+    let [j, k, l, m, n, o, p, q, r] = right;
+    [
+        a * j + b * m + c * p,
+        a * k + b * n + c * q,
+        a * l + b * o + c * r,
+        d * j + e * m + f * p,
+        d * k + e * n + f * q,
+        d * l + e * o + f * r,
+        g * j + h * m + i * p,
+        g * k + h * n + i * q,
+        g * l + h * o + i * r,
+    ]
+}
+
+/// The inverse of a row-major 3x3 matrix, or `None` if it is singular.
+fn invert(matrix: [f64; 9]) -> Option<[f64; 9]> {
+    let [a, b, c, d, e, f, g, h, i] = matrix;
+    let adjugate = [
+        e * i - f * h,
+        c * h - b * i,
+        b * f - c * e,
+        f * g - d * i,
+        a * i - c * g,
+        c * d - a * f,
+        d * h - e * g,
+        b * g - a * h,
+        a * e - b * d,
+    ];
+    let determinant = a * adjugate[0] + b * adjugate[3] + c * adjugate[6];
+    if determinant.abs() < f64::EPSILON {
+        return None;
+    }
+    Some(adjugate.map(|value| value / determinant))
+}
+
+/// `rgb` transformed by a row-major 3x3 matrix.
+fn transform(matrix: [f64; 9], rgb: [f64; 3]) -> [f64; 3] {
+    let [a, b, c, d, e, f, g, h, i] = matrix;
+    let [red, green, blue] = rgb;
+    [
+        a * red + b * green + c * blue,
+        d * red + e * green + f * blue,
+        g * red + h * green + i * blue,
+    ]
+}
+
+/// Reads `count` values of `bits` bits: one per byte at 8 bits, one per 16-bit word
+/// in the file's byte order when the data is that large, and packed most significant
+/// bit first otherwise.
+fn unpack(data: &[u8], count: usize, bits: u32, order: ByteOrder) -> Option<Vec<u16>> {
+    match bits {
+        8 => Some(data.get(..count)?.iter().copied().map(u16::from).collect()),
+        1..=16 if data.len() >= count.checked_mul(2)? => Some(
+            data.chunks_exact(2)
+                .take(count)
+                .map(|word| {
+                    let word = [word.first().copied()?, word.get(1).copied()?];
+                    Some(match order {
+                        ByteOrder::Little => u16::from_le_bytes(word),
+                        ByteOrder::Big => u16::from_be_bytes(word),
+                    })
+                })
+                .collect::<Option<_>>()?,
+        ),
+        1..=16 => {
+            let needed = count.checked_mul(usize::try_from(bits).ok()?)?.div_ceil(8);
+            let mut bytes = data.get(..needed)?.iter();
+            let (mut acc, mut held) = (0u32, 0u32);
+            let mut values = Vec::with_capacity(count);
+            for _ in 0..count {
+                while held < bits {
+                    acc = (acc << 8) | u32::from(*bytes.next()?);
+                    held += 8;
+                }
+                held -= bits;
+                values.push(u16::try_from((acc >> held) & ((1 << bits) - 1)).ok()?);
+            }
+            Some(values)
+        }
+        _ => None,
+    }
+}
+
+/// Puts the values of a CR2 sliced image back in raster order. Canon stores the sensor
+/// as `slices[0]` vertical strips `slices[1]` wide and a last one `slices[2]` wide,
+/// each top to bottom, one after the other.
+fn unslice(values: &[u16], width: usize, height: usize, slices: &[u32]) -> Option<Vec<u16>> {
+    let [count, slice_width, last_width] = slices else {
+        return None;
+    };
+    let [count, slice_width, last_width] =
+        [count, slice_width, last_width].map(|&value| usize::try_from(value).unwrap_or(0));
+    if count.checked_mul(slice_width)?.checked_add(last_width)? != width
+        || slice_width == 0
+        || last_width == 0
+    {
+        return None;
+    }
+    let mut out = vec![0; values.len()];
+    let mut source = values.iter();
+    for slice in 0..=count {
+        let (left, slice_width) = if slice < count {
+            (slice * slice_width, slice_width)
+        } else {
+            (count * slice_width, last_width)
+        };
+        for row in 0..height {
+            let start = row * width + left;
+            for value in out.get_mut(start..start + slice_width)? {
+                *value = *source.next()?;
+            }
+        }
+    }
+    Some(out)
+}
+
+impl Sensor {
+    /// The filter color of the photosite at `x`, `y`.
+    fn color(&self, x: usize, y: usize) -> usize {
+        self.pattern
+            .get((y % 2) * 2 + x % 2)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Develops the sensor values into an sRGB image.
+    fn develop(&self) -> RgbImage {
+        let black = self.black.unwrap_or_else(|| self.estimate_black());
+        let white = self.white.max(black.saturating_add(1));
+        let range = f64::from(white - black);
+        let gains = self
+            .as_shot
+            .unwrap_or_else(|| self.gray_world(black, white));
+
+        // Sensor value to linear light per color, then linear light to sRGB.
+        let linear: Vec<Vec<u16>> = gains
+            .iter()
+            .map(|gain| {
+                (0..=u16::MAX)
+                    .map(|value| {
+                        let level = f64::from(value.clamp(black, white) - black) / range;
+                        to_u16((level * gain).min(1.0) * f64::from(u16::MAX))
+                    })
+                    .collect()
+            })
+            .collect();
+        let srgb: Vec<u8> = (0..=u16::MAX)
+            .map(|value| color::linear_to_srgb(f64::from(value) / f64::from(u16::MAX)))
+            .collect();
+        let lookup = |table: &[u8], value: u32| {
+            usize::try_from(value)
+                .ok()
+                .and_then(|index| table.get(index))
+                .copied()
+                .unwrap_or_default()
+        };
+        let linear_of = |x: usize, y: usize| {
+            let color = self.color(x, y);
+            let value = usize::from(self.value(x, y));
+            linear
+                .get(color)
+                .and_then(|table| table.get(value))
+                .copied()
+                .map_or(0, u32::from)
+        };
+
+        // Bilinear demosaicing: each missing color is the average of the neighbors in the
+        // surrounding 3x3 block that have it.
+        let width = u32::try_from(self.width).unwrap_or(u32::MAX);
+        let height = u32::try_from(self.height).unwrap_or(u32::MAX);
+        RgbImage::from_fn(width, height, |x, y| {
+            let (x, y) = (usize_of(x), usize_of(y));
+            let own = self.color(x, y);
+            let (mut sums, mut counts) = ([0u32; 3], [0u32; 3]);
+            for ny in y.saturating_sub(1)..=(y + 1).min(self.height - 1) {
+                for nx in x.saturating_sub(1)..=(x + 1).min(self.width - 1) {
+                    let color = self.color(nx, ny);
+                    if let (Some(sum), Some(count)) = (sums.get_mut(color), counts.get_mut(color)) {
+                        *sum += linear_of(nx, ny);
+                        *count += 1;
+                    }
+                }
+            }
+            let levels = [0, 1, 2].map(|c| {
+                if c == own {
+                    linear_of(x, y)
+                } else {
+                    sums.get(c).copied().unwrap_or_default()
+                        / counts.get(c).copied().unwrap_or_default().max(1)
+                }
+            });
+            let levels = match self.matrix {
+                Some(matrix) => {
+                    transform(matrix, levels.map(f64::from)).map(|level| u32::from(to_u16(level)))
+                }
+                None => levels,
+            };
+            image::Rgb(levels.map(|level| lookup(&srgb, level)))
+        })
+    }
+
+    /// Gray-world white balance gains: red and blue scaled so that their unclipped
+    /// averages match green.
+    fn gray_world(&self, black: u16, white: u16) -> [f64; 3] {
+        let (mut sums, mut counts) = ([0u64; 3], [0u64; 3]);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let value = self.value(x, y);
+                if value < white {
+                    let color = self.color(x, y);
+                    if let (Some(sum), Some(count)) = (sums.get_mut(color), counts.get_mut(color)) {
+                        *sum += u64::from(value.saturating_sub(black));
+                        *count += 1;
+                    }
+                }
+            }
+        }
+        let means = [0, 1, 2].map(|c| {
+            let sum = sums.get(c).copied().unwrap_or_default();
+            let count = counts.get(c).copied().unwrap_or_default().max(1);
+            color::to_f64(sum) / color::to_f64(count)
+        });
+        means.map(|mean| {
+            if mean > 0.0 {
+                (means[1] / mean).clamp(1.0 / MAX_GAIN, MAX_GAIN)
+            } else {
+                1.0
+            }
+        })
+    }
+
+    fn value(&self, x: usize, y: usize) -> u16 {
+        self.values
+            .get(y * self.width + x)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// The black level of files that don't record it: the darkest values, which come
+    /// from masked border photosites or the deepest shadows. A thousandth of the values
+    /// is allowed to read lower, for noise.
+    fn estimate_black(&self) -> u16 {
+        let mut histogram = vec![0usize; usize::from(u16::MAX) + 1];
+        for &value in &self.values {
+            if let Some(count) = histogram.get_mut(usize::from(value)) {
+                *count += 1;
+            }
+        }
+        let allowed = self.values.len() / 1000;
+        let mut seen = 0;
+        for (value, count) in histogram.iter().enumerate() {
+            seen += count;
+            if seen > allowed {
+                return u16::try_from(value).unwrap_or_default();
+            }
+        }
+        0
+    }
+}
+
+fn usize_of(value: u32) -> usize {
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
+
+// Safe: the value is rounded and clamped to u16's range before the cast.
+#[allow(clippy::as_conversions)]
+fn to_u16(value: f64) -> u16 {
+    value.round().clamp(0.0, f64::from(u16::MAX)) as u16
+}
+
+/// The frame of a lossless JPEG (SOF3).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Frame {
+    /// Bits per sample.
+    precision: u8,
+    /// Lines.
+    height: usize,
+    /// Samples per line, per component.
+    width: usize,
+    /// Interleaved components, all sampled 1x1.
+    components: usize,
+}
+
+impl Frame {
+    /// Values per line, across components.
+    fn line(&self) -> usize {
+        self.width.saturating_mul(self.components)
+    }
+}
+
+/// The markers of a JPEG up to its first scan: each segment's marker and payload, and
+/// the position just past the segment.
+fn segments(data: &[u8]) -> impl Iterator<Item = (u8, &[u8], usize)> {
+    let mut pos = if data.get(..2) == Some(&[0xFF, 0xD8]) {
+        2
+    } else {
+        data.len()
+    };
+    std::iter::from_fn(move || {
+        while data.get(pos) == Some(&0xFF) && data.get(pos + 1) == Some(&0xFF) {
+            pos += 1;
+        }
+        if data.get(pos) != Some(&0xFF) {
+            return None;
+        }
+        let marker = *data.get(pos + 1)?;
+        let length = usize::from(u16::from_be_bytes([
+            *data.get(pos + 2)?,
+            *data.get(pos + 3)?,
+        ]));
+        let end = pos.checked_add(2 + length)?;
+        let payload = data.get(pos + 4..end)?;
+        pos = end;
+        Some((marker, payload, end))
+    })
+}
+
+/// The frame of a lossless JPEG whose components are all sampled 1x1, or `None` for
+/// any other JPEG.
+fn lossless_frame(data: &[u8]) -> Option<Frame> {
+    let (marker, header, _) = segments(data).find(|&(marker, ..)| {
+        (0xC0..=0xCF).contains(&marker) && ![0xC4, 0xC8, 0xCC].contains(&marker)
+    })?;
+    if marker != 0xC3 {
+        return None;
+    }
+    let [precision, h1, h0, w1, w0, components, ref specs @ ..] = *header else {
+        return None;
+    };
+    let components_len = usize::from(components);
+    let specs = specs.get(..components_len * 3)?;
+    if components == 0
+        || !(2..=16).contains(&precision)
+        || specs.chunks(3).any(|spec| spec.get(1) != Some(&0x11))
+    {
+        return None;
+    }
+    Some(Frame {
+        precision,
+        height: usize::from(u16::from_be_bytes([h1, h0])),
+        width: usize::from(u16::from_be_bytes([w1, w0])),
+        components: components_len,
+    })
+}
+
+/// A Huffman table of a lossless JPEG, decoded canonically as in T.81 Annex F.
+#[derive(Debug, Clone, Default)]
+struct Huffman {
+    /// Codes of each length, 1 to 16.
+    counts: [u16; 16],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn decode(&self, bits: &mut Bits<'_>) -> Option<u8> {
+        let (mut code, mut first, mut index) = (0u32, 0u32, 0usize);
+        for &count in &self.counts {
+            code |= bits.read(1);
+            let count = u32::from(count);
+            if code < first + count {
+                let offset = usize::try_from(code - first).ok()?;
+                return self.values.get(index + offset).copied();
+            }
+            index += usize::try_from(count).ok()?;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        None
+    }
+}
+
+/// Entropy-coded bits, with stuffed zero bytes removed. Reads past a marker or the end
+/// of the data return zeros.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u32,
+    held: u32,
+}
+
+impl Bits<'_> {
+    /// The next `count` bits, at most 16, most significant first.
+    fn read(&mut self, count: u32) -> u32 {
+        while self.held < count {
+            self.acc = (self.acc << 8) | u32::from(self.byte());
+            self.held += 8;
+        }
+        self.held -= count;
+        (self.acc >> self.held) & ((1 << count) - 1)
+    }
+
+    fn byte(&mut self) -> u8 {
+        match (self.data.get(self.pos), self.data.get(self.pos + 1)) {
+            (Some(0xFF), Some(0x00)) => {
+                self.pos += 2;
+                0xFF
+            }
+            (Some(0xFF) | None, _) => 0,
+            (Some(&byte), _) => {
+                self.pos += 1;
+                byte
+            }
+        }
+    }
+
+    /// Drops the bits left in the current byte and skips the restart marker after them.
+    fn restart(&mut self) {
+        self.acc = 0;
+        self.held = 0;
+        while self.data.get(self.pos) == Some(&0xFF) && self.data.get(self.pos + 1) == Some(&0xFF) {
+            self.pos += 1;
+        }
+        if self.data.get(self.pos) == Some(&0xFF)
+            && self
+                .data
+                .get(self.pos + 1)
+                .is_some_and(|marker| (0xD0..=0xD7).contains(marker))
+        {
+            self.pos += 2;
+        }
+    }
+}
+
+/// Decodes a lossless JPEG with the `frame` that [`lossless_frame`] read, into
+/// `frame.line()` values per line, components interleaved.
+fn decode_lossless(data: &[u8], frame: &Frame) -> Option<Vec<u16>> {
+    let mut tables: [Huffman; 4] = Default::default();
+    let mut restart_interval = 0;
+    let mut scan = None;
+    for (marker, payload, end) in segments(data) {
+        match marker {
+            0xC4 => {
+                let mut rest = payload;
+                while let [class_id, ref counts @ ..] = *rest {
+                    let counts: [u8; 16] = counts.get(..16)?.try_into().ok()?;
+                    let total = counts
+                        .iter()
+                        .map(|&count| usize::from(count))
+                        .sum::<usize>();
+                    let values = rest.get(17..17 + total)?.to_vec();
+                    *tables.get_mut(usize::from(class_id & 0x0F))? = Huffman {
+                        counts: counts.map(u16::from),
+                        values,
+                    };
+                    rest = rest.get(17 + total..)?;
+                }
+            }
+            0xDD => {
+                restart_interval =
+                    usize::from(u16::from_be_bytes([*payload.first()?, *payload.get(1)?]));
+            }
+            0xDA => {
+                scan = Some((payload, end));
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (header, start) = scan?;
+    let [count, ref rest @ ..] = *header else {
+        return None;
+    };
+    if usize::from(count) != frame.components {
+        return None;
+    }
+    let selectors = rest.get(..frame.components * 2)?;
+    let [predictor, _, point_transform] = *rest.get(frame.components * 2..)? else {
+        return None;
+    };
+    let point_transform = u32::from(point_transform & 0x0F);
+    if !(1..=7).contains(&predictor) || point_transform >= u32::from(frame.precision) {
+        return None;
+    }
+    let component_tables: Vec<&Huffman> = selectors
+        .chunks(2)
+        .map(|selector| tables.get(usize::from(selector.get(1)? >> 4)))
+        .collect::<Option<_>>()?;
+
+    let mut bits = Bits {
+        data: data.get(start..)?,
+        pos: 0,
+        acc: 0,
+        held: 0,
+    };
+    let line = frame.line();
+    let components = frame.components;
+    let initial = 1i32 << (u32::from(frame.precision) - point_transform - 1);
+    let mut out = vec![0u16; line.checked_mul(frame.height)?];
+    let at = |out: &[u16], index: usize| out.get(index).copied().map_or(0, i32::from);
+    // Samples on the first line of a scan or restart interval are predicted from the
+    // left, and the first of each from `initial`.
+    let (mut first_line, mut fresh) = (0, true);
+    let mut mcus = 0;
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            if restart_interval > 0 && mcus > 0 && mcus % restart_interval == 0 {
+                bits.restart();
+                (first_line, fresh) = (y, true);
+            }
+            for (c, table) in component_tables.iter().enumerate() {
+                let index = y * line + x * components + c;
+                let left = index.wrapping_sub(components);
+                let up = index.wrapping_sub(line);
+                let prediction = if fresh {
+                    initial
+                } else if y == first_line {
+                    at(&out, left)
+                } else if x == 0 {
+                    at(&out, up)
+                } else {
+                    let (a, b, c) = (
+                        at(&out, left),
+                        at(&out, up),
+                        at(&out, up.wrapping_sub(components)),
+                    );
+                    match predictor {
+                        1 => a,
+                        2 => b,
+                        3 => c,
+                        4 => a + b - c,
+                        5 => a + ((b - c) >> 1),
+                        6 => b + ((a - c) >> 1),
+                        _ => (a + b) >> 1,
+                    }
+                };
+                let diff = match table.decode(&mut bits)? {
+                    0 => 0,
+                    16 => 32768,
+                    size @ 1..=15 => {
+                        let value = i32::try_from(bits.read(u32::from(size))).ok()?;
+                        if value < 1 << (size - 1) {
+                            value - (1 << size) + 1
+                        } else {
+                            value
+                        }
+                    }
+                    _ => return None,
+                };
+                *out.get_mut(index)? = u16::try_from((prediction + diff) & 0xFFFF).ok()?;
+            }
+            fresh = false;
+            mcus += 1;
+        }
+    }
+    if point_transform > 0 {
+        for value in &mut out {
+            *value <<= point_transform;
+        }
+    }
+    Some(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::convert;
+    use crate::formats::ImageFormat;
+
+    const TAG_MAKE: u16 = 0x010F;
+    const TAG_ORIENTATION: u16 = 0x0112;
+
+    /// Appends `bits` bits of `value` to a JPEG entropy-coded segment, stuffing a zero
+    /// byte after every 0xFF.
+    struct BitWriter {
+        out: Vec<u8>,
+        acc: u32,
+        held: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, bits: u32) {
+            for bit in (0..bits).rev() {
+                self.acc = (self.acc << 1) | ((value >> bit) & 1);
+                self.held += 1;
+                if self.held == 8 {
+                    self.flush_byte();
+                }
+            }
+        }
+
+        fn flush_byte(&mut self) {
+            let byte = u8::try_from(self.acc & 0xFF).unwrap();
+            self.out.push(byte);
+            if byte == 0xFF {
+                self.out.push(0);
+            }
+            self.acc = 0;
+            self.held = 0;
+        }
+
+        /// Pads the last byte with ones.
+        fn align(&mut self) {
+            while self.held != 0 {
+                self.write(1, 1);
+            }
+        }
+    }
+
+    /// Encodes `values` (`width * components` per line) as a lossless JPEG with one
+    /// Huffman table that codes every difference size in 5 bits, and a restart marker
+    /// every `restart` samples if it isn't zero.
+    fn lossless_jpeg(
+        values: &[u16],
+        width: u16,
+        components: u8,
+        precision: u8,
+        predictor: u8,
+        restart: u16,
+    ) -> Vec<u8> {
+        let (width_, components_) = (usize::from(width), usize::from(components));
+        let line = width_ * components_;
+        let height = values.len() / line;
+        let mut out = vec![0xFF, 0xD8];
+        let mut segment = |marker: u8, payload: &[u8]| {
+            out.extend([0xFF, marker]);
+            out.extend(u16::try_from(payload.len() + 2).unwrap().to_be_bytes());
+            out.extend(payload);
+        };
+        let mut frame = vec![precision];
+        frame.extend(u16::try_from(height).unwrap().to_be_bytes());
+        frame.extend(width.to_be_bytes());
+        frame.push(components);
+        for c in 0..components {
+            frame.extend([c + 1, 0x11, 0]);
+        }
+        segment(0xC3, &frame);
+        let mut table = vec![0x00];
+        table.extend([0, 0, 0, 0, 17, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]);
+        table.extend(0..=16);
+        segment(0xC4, &table);
+        if restart > 0 {
+            segment(0xDD, &restart.to_be_bytes());
+        }
+        let mut scan = vec![components];
+        for c in 0..components {
+            scan.extend([c + 1, 0x00]);
+        }
+        scan.extend([predictor, 0, 0]);
+        segment(0xDA, &scan);
+
+        let mut bits = BitWriter {
+            out: Vec::new(),
+            acc: 0,
+            held: 0,
+        };
+        let at = |index: usize| i32::from(values[index]);
+        let (mut first_line, mut fresh, mut marker) = (0, true, 0);
+        for y in 0..height {
+            for x in 0..width_ {
+                let mcu = y * width_ + x;
+                if restart > 0 && mcu > 0 && mcu % usize::from(restart) == 0 {
+                    bits.align();
+                    bits.out.extend([0xFF, 0xD0 + marker]);
+                    marker = (marker + 1) % 8;
+                    (first_line, fresh) = (y, true);
+                }
+                for c in 0..components_ {
+                    let index = y * line + x * components_ + c;
+                    let prediction = if fresh {
+                        1 << (precision - 1)
+                    } else if y == first_line {
+                        at(index - components_)
+                    } else if x == 0 {
+                        at(index - line)
+                    } else {
+                        let (a, b, c) = (
+                            at(index - components_),
+                            at(index - line),
+                            at(index - line - components_),
+                        );
+                        match predictor {
+                            1 => a,
+                            2 => b,
+                            3 => c,
+                            4 => a + b - c,
+                            5 => a + ((b - c) >> 1),
+                            6 => b + ((a - c) >> 1),
+                            _ => (a + b) >> 1,
+                        }
+                    };
+                    let mut diff = (at(index) - prediction).rem_euclid(65536);
+                    if diff >= 32768 {
+                        diff -= 65536;
+                    }
+                    if diff == -32768 {
+                        bits.write(16, 5);
+                        continue;
+                    }
+                    let size = 32 - diff.unsigned_abs().leading_zeros();
+                    bits.write(size, 5);
+                    let extra = if diff < 0 { diff - 1 } else { diff };
+                    bits.write(u32::from_ne_bytes(extra.to_ne_bytes()), size);
+                }
+                fresh = false;
+            }
+        }
+        bits.align();
+        out.extend(bits.out);
+        out.extend([0xFF, 0xD9]);
+        out
+    }
+
+    /// Deterministic pseudo-random sensor values below `2^precision`.
+    fn noise(count: usize, precision: u8) -> Vec<u16> {
+        let mut state = 0x2545_F491_u32;
+        (0..count)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                u16::try_from(state % (1 << precision)).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn lossless_jpeg_round_trips_with_every_predictor() {
+        let values = noise(6 * 2 * 5, 14);
+        for predictor in 1..=7 {
+            for restart in [0, 6, 4] {
+                let jpeg = lossless_jpeg(&values, 6, 2, 14, predictor, restart);
+                let frame = lossless_frame(&jpeg).unwrap();
+                assert_eq!((frame.line(), frame.height), (12, 5));
+                assert_eq!(
+                    decode_lossless(&jpeg, &frame).unwrap(),
+                    values,
+                    "predictor {predictor}, restart {restart}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn lossless_jpeg_codes_large_differences() {
+        let values = [0, 65535, 0, 32768, 1, 65534, 12345, 54321];
+        let jpeg = lossless_jpeg(&values, 4, 1, 16, 1, 0);
+        let frame = lossless_frame(&jpeg).unwrap();
+        assert_eq!(decode_lossless(&jpeg, &frame).unwrap(), values);
+        // Lossy JPEGs aren't sensor data.
+        let mut baseline = Vec::new();
+        DynamicImage::new_rgb8(8, 8)
+            .write_to(
+                &mut std::io::Cursor::new(&mut baseline),
+                image::ImageFormat::Jpeg,
+            )
+            .unwrap();
+        assert_eq!(lossless_frame(&baseline), None);
+    }
+
+    #[test]
+    fn unslices_canon_sensor_data() {
+        // Two slices 2 wide and a last one 1 wide, over 2 lines.
+        let sliced = [1, 2, 6, 7, 3, 4, 8, 9, 5, 10];
+        let raster = unslice(&sliced, 5, 2, &[2, 2, 1]).unwrap();
+        assert_eq!(raster, [1, 2, 3, 4, 5, 6, 7, 8, 9, 10]);
+        assert_eq!(unslice(&sliced, 5, 2, &[2, 2, 2]), None);
+    }
+
+    #[test]
+    fn unpacks_bytes_words_and_packed_bits() {
+        let order = ByteOrder::Little;
+        assert_eq!(unpack(&[1, 2], 2, 8, order).unwrap(), [1, 2]);
+        assert_eq!(
+            unpack(&[0x34, 0x12, 0xFF, 0x0F], 2, 12, order).unwrap(),
+            [0x1234, 0x0FFF]
+        );
+        assert_eq!(
+            unpack(&[0x34, 0x12, 0xFF, 0x0F], 2, 12, ByteOrder::Big).unwrap(),
+            [0x3412, 0xFF0F]
+        );
+        // 12-bit values packed into 3 bytes per pair.
+        assert_eq!(
+            unpack(&[0xAB, 0xCD, 0xEF], 2, 12, order).unwrap(),
+            [0xABC, 0xDEF]
+        );
+        assert_eq!(unpack(&[0xAB, 0xCD], 2, 12, order), None);
+    }
+
+    /// Camera values equal to linear sRGB.
+    const IDENTITY: [f64; 9] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+    /// A scene that is neutral gray on the left half, red on the top right and cyan on
+    /// the bottom right (so that it averages to gray), as a 2x2 `pattern` sensor whose
+    /// colors are `camera` times linear sRGB would record it between `black` and
+    /// `white`.
+    fn mosaic(
+        width: usize,
+        height: usize,
+        pattern: [usize; 4],
+        black: u16,
+        white: u16,
+        camera: [f64; 9],
+    ) -> Vec<u16> {
+        let span = f64::from(white - black);
+        let mut values = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                let rgb = if x < width / 2 {
+                    [0.3, 0.3, 0.3]
+                } else if y < height / 2 {
+                    [0.5, 0.1, 0.1]
+                } else {
+                    [0.1, 0.5, 0.5]
+                };
+                let color = pattern[(y % 2) * 2 + x % 2];
+                values.push(black + to_u16(transform(camera, rgb)[color] * span));
+            }
+        }
+        values
+    }
+
+    fn entry(tag: u16, kind: u16, count: u32, value: u32) -> Vec<u8> {
+        let mut entry = tag.to_le_bytes().to_vec();
+        entry.extend(kind.to_le_bytes());
+        entry.extend(count.to_le_bytes());
+        entry.extend(value.to_le_bytes());
+        entry
+    }
+
+    /// A little-endian TIFF starting with `header`, whose IFD0 has `ifd0` entries and no
+    /// preview, and whose second IFD has the `sensor` entries for `data` placed at the
+    /// offset passed to it.
+    fn tiff(
+        header: &[u8],
+        ifd0: &[Vec<u8>],
+        sensor: impl Fn(u32) -> Vec<Vec<u8>>,
+        data: &[u8],
+    ) -> Vec<u8> {
+        let ifd_len = |count: usize| 2 + count * 12 + 4;
+        let ifd0_at = header.len();
+        let sensor_ifd_at = ifd0_at + ifd_len(ifd0.len());
+        let data_at = u32::try_from(sensor_ifd_at + ifd_len(sensor(0).len())).unwrap();
+        let mut file = header.to_vec();
+        file[4..8].copy_from_slice(&u32::try_from(ifd0_at).unwrap().to_le_bytes());
+        for (entries, next) in [
+            (ifd0.to_vec(), u32::try_from(sensor_ifd_at).unwrap()),
+            (sensor(data_at), 0),
+        ] {
+            file.extend(u16::try_from(entries.len()).unwrap().to_le_bytes());
+            for entry in entries {
+                file.extend(entry);
+            }
+            file.extend(next.to_le_bytes());
+        }
+        file.extend(data);
+        file
+    }
+
+    /// Checks that `img` shows the scene of [`mosaic`].
+    fn assert_developed(img: &RgbImage) {
+        let (width, height) = img.dimensions();
+        let gray = img.get_pixel(width / 4, height / 2).0;
+        let red = img.get_pixel(width * 3 / 4, height / 4).0;
+        let cyan = img.get_pixel(width * 3 / 4, height * 3 / 4).0;
+        let spread = gray.iter().max().unwrap() - gray.iter().min().unwrap();
+        assert!(spread <= 8 && gray[1] > 100, "gray {gray:?}");
+        assert!(red[0] > red[1].saturating_add(60), "red {red:?}");
+        assert!(red[0] > red[2].saturating_add(60), "red {red:?}");
+        assert!(cyan[1] > cyan[0].saturating_add(60), "cyan {cyan:?}");
+        assert!(cyan[2].abs_diff(cyan[1]) <= 8, "cyan {cyan:?}");
+    }
+
+    #[test]
+    fn develops_canon_lossless_jpeg_sensor_data() {
+        let (width, height) = (16, 8);
+        let black = 512;
+        let raster = mosaic(width, height, RGGB, black, 16383, IDENTITY);
+        // Stored as two slices, 8 and 8 wide, as a 2-component lossless JPEG.
+        let mut sliced = Vec::new();
+        for left in [0, 8] {
+            for row in 0..height {
+                sliced.extend(&raster[row * width + left..row * width + left + 8]);
+            }
+        }
+        let jpeg = lossless_jpeg(&sliced, 8, 2, 14, 1, 0);
+        let len = u32::try_from(jpeg.len()).unwrap();
+        let mut data = jpeg;
+        for value in [1u16, 8, 8] {
+            data.extend(value.to_le_bytes());
+        }
+        let file = tiff(
+            b"II\x2a\0\0\0\0\0CR\x02\0\0\0\0\0",
+            &[entry(TAG_ORIENTATION, 3, 1, 1)],
+            |data_at| {
+                vec![
+                    entry(TAG_COMPRESSION, 3, 1, 6),
+                    entry(TAG_STRIP_OFFSETS, 4, 1, data_at),
+                    entry(TAG_STRIP_BYTE_COUNTS, 4, 1, len),
+                    entry(TAG_CR2_SLICE, 3, 3, data_at + len),
+                ]
+            },
+            &data,
+        );
+
+        assert_eq!(raw::camera_format(&file), Some("cr2"));
+        let img = develop(&file).unwrap().into_rgb8();
+        assert_eq!(img.dimensions(), (16, 8));
+        assert_developed(&img);
+
+        // With no preview to read, conversions develop the sensor data too.
+        let png = convert::convert(file, ImageFormat::Png, None, &[]).unwrap();
+        let converted = image::load_from_memory(&png).unwrap().into_rgb8();
+        assert_eq!(converted, img);
+    }
+
+    /// An uncompressed NEF whose sensor IFD has a GRBG pattern and black and white
+    /// levels, with values of `bits` bits stored as `data`, and `extra` entries (tag,
+    /// type, count and more than 4 bytes of values) whose values follow the data.
+    fn uncompressed_nef(
+        width: u32,
+        height: u32,
+        bits: u32,
+        data: &[u8],
+        orientation: u32,
+        extra: &[(u16, u16, u32, Vec<u8>)],
+    ) -> Vec<u8> {
+        let mut header = b"II\x2a\0\0\0\0\0".to_vec();
+        header.extend(b"NIKON\0\0\0");
+        let len = u32::try_from(data.len()).unwrap();
+        let mut tail = data.to_vec();
+        let mut placed = Vec::new();
+        for (tag, kind, count, values) in extra {
+            placed.push((*tag, *kind, *count, u32::try_from(tail.len()).unwrap()));
+            tail.extend(values);
+        }
+        tiff(
+            &header,
+            &[
+                entry(TAG_MAKE, 2, 6, 8),
+                entry(TAG_ORIENTATION, 3, 1, orientation),
+            ],
+            |data_at| {
+                vec![
+                    entry(TAG_IMAGE_WIDTH, 4, 1, width),
+                    entry(TAG_IMAGE_LENGTH, 4, 1, height),
+                    entry(TAG_BITS_PER_SAMPLE, 3, 1, bits),
+                    entry(TAG_COMPRESSION, 3, 1, 1),
+                    entry(TAG_PHOTOMETRIC, 3, 1, PHOTOMETRIC_CFA),
+                    entry(TAG_STRIP_OFFSETS, 4, 1, data_at),
+                    entry(TAG_STRIP_BYTE_COUNTS, 4, 1, len),
+                    entry(TAG_CFA_REPEAT_PATTERN_DIM, 3, 2, 2 | (2 << 16)),
+                    entry(TAG_CFA_PATTERN, 1, 4, u32::from_le_bytes([1, 0, 2, 1])),
+                    entry(TAG_BLACK_LEVEL, 3, 1, 600),
+                    entry(TAG_WHITE_LEVEL, 3, 1, 4000),
+                ]
+                .into_iter()
+                .chain(
+                    placed
+                        .iter()
+                        .map(|&(tag, kind, count, at)| entry(tag, kind, count, data_at + at)),
+                )
+                .collect()
+            },
+            &tail,
+        )
+    }
+
+    /// Little-endian (S)RATIONAL values.
+    fn rationals(values: &[(i32, i32)]) -> Vec<u8> {
+        values
+            .iter()
+            .flat_map(|(numerator, denominator)| {
+                [numerator.to_le_bytes(), denominator.to_le_bytes()].concat()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn develops_uncompressed_cfa_data_with_its_pattern_and_levels() {
+        let (width, height) = (12, 8);
+        let values = mosaic(width, height, [1, 0, 2, 1], 600, 4000, IDENTITY);
+        let words: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let file = uncompressed_nef(12, 8, 12, &words, 1, &[]);
+        assert_eq!(raw::camera_format(&file), Some("nef"));
+        let img = develop(&file).unwrap().into_rgb8();
+        assert_eq!(img.dimensions(), (12, 8));
+        assert_developed(&img);
+
+        // The same values packed into 12 bits each develop the same way.
+        let mut packed = Vec::new();
+        for pair in values.chunks(2) {
+            let (a, b) = (u32::from(pair[0]), u32::from(pair[1]));
+            let bits = (a << 12) | b;
+            packed.extend(&bits.to_be_bytes()[1..]);
+        }
+        let file = uncompressed_nef(12, 8, 12, &packed, 1, &[]);
+        assert_eq!(develop(&file).unwrap().into_rgb8(), img);
+
+        // Orientation 6: the camera was turned 90° clockwise.
+        let file = uncompressed_nef(12, 8, 12, &words, 6, &[]);
+        assert_eq!(develop(&file).unwrap().into_rgb8().dimensions(), (8, 12));
+    }
+
+    #[test]
+    fn develops_with_the_as_shot_white_balance() {
+        let values = mosaic(12, 8, [1, 0, 2, 1], 600, 4000, IDENTITY);
+        let words: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        // A Nikon maker note whose embedded TIFF has the red and blue levels right after
+        // its IFD: red at 1.5, blue at 1.
+        let mut note = b"Nikon\0\x02\x10\0\0II\x2a\0\x08\0\0\0\x01\0".to_vec();
+        note.extend(entry(TAG_NIKON_WB_LEVELS, 5, 4, 26));
+        note.extend(0u32.to_le_bytes());
+        note.extend(rationals(&[(3, 2), (1, 1), (1, 1), (1, 1)]));
+        let note_len = u32::try_from(note.len()).unwrap();
+        let file = uncompressed_nef(12, 8, 12, &words, 1, &[(TAG_MAKER_NOTE, 7, note_len, note)]);
+        let img = develop(&file).unwrap().into_rgb8();
+        // Gray world would make the gray half neutral; the camera's setting warms it.
+        let [red, green, blue] = img.get_pixel(3, 4).0;
+        assert!(
+            red > green.saturating_add(15),
+            "gray {:?}",
+            [red, green, blue]
+        );
+        assert!(blue.abs_diff(green) <= 8, "gray {:?}", [red, green, blue]);
+
+        // The DNG tag holds the camera values of white instead.
+        let neutral = rationals(&[(2, 3), (1, 1), (1, 1)]);
+        let file = uncompressed_nef(
+            12,
+            8,
+            12,
+            &words,
+            1,
+            &[(TAG_AS_SHOT_NEUTRAL, 5, 3, neutral)],
+        );
+        assert_eq!(develop(&file).unwrap().into_rgb8(), img);
+    }
+
+    #[test]
+    fn reads_canon_color_data_by_version() {
+        for (len, start) in [(582, 25), (653, 34), (5120, 71), (1273, 63)] {
+            let mut file = b"II\x2a\0\x08\0\0\0\x01\0".to_vec();
+            file.extend(entry(TAG_CANON_COLOR_DATA, 3, len, 26));
+            file.extend(0u32.to_le_bytes());
+            let mut data = vec![0u16; usize::try_from(len).unwrap()];
+            data[start..start + 4].copy_from_slice(&[2000, 1000, 1100, 1500]);
+            file.extend(data.iter().flat_map(|value| value.to_le_bytes()));
+            let tiff = Tiff::read(&file).unwrap();
+            assert_eq!(canon_levels(&tiff, 8), Some([2000.0, 1050.0, 1500.0]));
+        }
+
+        let file = b"II\x2a\0\x08\0\0\0\x01\0\x01\x40\x03\0\x02\0\0\0\0\0\0\0";
+        assert_eq!(canon_levels(&Tiff::read(file).unwrap(), 8), None);
+    }
+
+    #[test]
+    fn develops_through_the_dng_color_matrix() {
+        // A sensor whose colors each pick up some of their neighbors.
+        let camera = [0.8, 0.2, 0.0, 0.1, 0.8, 0.1, 0.0, 0.2, 0.8];
+        let values = mosaic(12, 8, [1, 0, 2, 1], 600, 4000, camera);
+        let words: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let camera_from_xyz = multiply(camera, invert(XYZ_FROM_SRGB).unwrap());
+        // In ten-thousandths, rounded by `to_u16` with an offset that keeps the negative
+        // entries in its range.
+        let matrix: Vec<(i32, i32)> = camera_from_xyz
+            .iter()
+            .map(|value| {
+                (
+                    i32::from(to_u16(value * 10_000.0 + 30_000.0)) - 30_000,
+                    10_000,
+                )
+            })
+            .collect();
+        let neutral = rationals(&[(1, 1), (1, 1), (1, 1)]);
+        let develop_with = |extra: &[(u16, u16, u32, Vec<u8>)]| {
+            let file = uncompressed_nef(12, 8, 12, &words, 1, extra);
+            develop(&file).unwrap().into_rgb8()
+        };
+
+        let img = develop_with(&[
+            (TAG_AS_SHOT_NEUTRAL, 5, 3, neutral.clone()),
+            (TAG_COLOR_MATRIX_2, 10, 9, rationals(&matrix)),
+        ]);
+        // The red quarter comes out as the scene's red, linear 0.5, 0.1, 0.1.
+        let expected = [0.5, 0.1, 0.1].map(color::linear_to_srgb);
+        let red = img.get_pixel(9, 2).0;
+        for (channel, expected) in red.iter().zip(expected) {
+            assert!(
+                channel.abs_diff(expected) <= 4,
+                "red {red:?}, not {expected:?}"
+            );
+        }
+
+        // Without the matrix, the camera's own primaries dull it.
+        let dull = develop_with(&[(TAG_AS_SHOT_NEUTRAL, 5, 3, neutral)]);
+        let dull = dull.get_pixel(9, 2).0;
+        assert!(dull[1] > expected[1].saturating_add(12), "dull {dull:?}");
+    }
+
+    #[test]
+    fn rejects_other_sensor_compressions_and_truncated_data() {
+        let values = mosaic(4, 4, RGGB, 0, 4095, IDENTITY);
+        let words: Vec<u8> = values
+            .iter()
+            .flat_map(|value| value.to_le_bytes())
+            .collect();
+        let mut file = uncompressed_nef(4, 4, 12, &words, 1, &[]);
+        let compression = file
+            .windows(4)
+            .position(|window| window == [0x03, 0x01, 3, 0])
+            .unwrap();
+        // Nikon's own compression.
+        file[compression + 8..compression + 10].copy_from_slice(&34713u16.to_le_bytes());
+        assert!(matches!(
+            develop(&file),
+            Err(RawError::UnsupportedSensorData)
+        ));
+
+        let file = uncompressed_nef(4, 4, 12, &words[..10], 1, &[]);
+        assert!(matches!(develop(&file), Err(RawError::InvalidSensorData)));
+    }
+}
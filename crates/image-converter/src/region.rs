@@ -0,0 +1,830 @@
+use std::fmt;
+use std::io::Cursor;
+
+use image::{imageops, ImageReader, Rgba, RgbaImage};
+use serde::Serialize;
+use tiff::decoder::{ChunkType, Decoder, DecodingResult};
+use tiff::tags::Tag;
+use tiff::{ColorType, TiffError};
+
+use crate::formats::{FormatError, ImageFormat};
+use crate::generate::MAX_SIDE;
+use crate::jpeg::{self, JpegError};
+use crate::resize::{self, ResizeError, ResizeMode, ResizeOptions};
+
+/// A rectangle of decoded pixels.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    /// RGBA8 pixels, row-major.
+    pub rgba: Vec<u8>,
+    pub width: u32,
+    pub height: u32,
+    pub method: RegionMethod,
+}
+
+/// How [`decode_region`] or [`decode_scaled`] got the pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RegionMethod {
+    /// Only the TIFF tiles or strips overlapping the region were decoded, or every chunk
+    /// was reduced as it was read.
+    Chunks,
+    /// Only the DC coefficients of the JPEG were decoded, giving it at 1/8 scale.
+    JpegDc,
+    /// The whole image was decoded and then cropped or downscaled.
+    FullDecode,
+}
+
+/// The scale a DC-only JPEG decode gives.
+const JPEG_DC_FACTOR: u32 = 8;
+
+/// The requested rectangle, already checked against the image bounds.
+#[derive(Debug, Clone, Copy)]
+struct Rect {
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+}
+
+/// Decodes the `width` x `height` rectangle whose top-left corner is at (`x`, `y`).
+///
+/// Tiled and striped TIFFs with 8-bit gray or RGB samples (with or without alpha)
+/// decode only the tiles or strips the rectangle overlaps, so a small view of a huge
+/// scan costs little more than the view itself. Everything else, including JPEG (whose
+/// decoder can't start at a restart marker), is decoded in full and cropped;
+/// [`Region::method`] says which happened.
+///
+/// # Errors
+///
+/// Returns a `RegionError` if the rectangle is empty or extends past the image, or the
+/// input cannot be decoded.
+pub fn decode_region(
+    input: &[u8],
+    x: u32,
+    y: u32,
+    width: u32,
+    height: u32,
+) -> Result<Region, RegionError> {
+    let format = ImageFormat::detect_from_bytes(input).map_err(RegionError::Format)?;
+    let (image_width, image_height) = ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(|e| RegionError::Decode(image::ImageError::IoError(e)))?
+        .into_dimensions()
+        .map_err(RegionError::Decode)?;
+
+    let fits = |start: u32, len: u32, limit: u32| {
+        len > 0 && start.checked_add(len).is_some_and(|end| end <= limit)
+    };
+    if !fits(x, width, image_width) || !fits(y, height, image_height) {
+        return Err(RegionError::OutOfBounds {
+            image_width,
+            image_height,
+        });
+    }
+    let rect = Rect {
+        x,
+        y,
+        width,
+        height,
+    };
+
+    if format == ImageFormat::Tiff {
+        if let Some(rgba) = tiff_region(input, rect).map_err(RegionError::Tiff)? {
+            log::debug!("decoded {width}x{height} region from TIFF chunks");
+            return Ok(Region {
+                rgba,
+                width,
+                height,
+                method: RegionMethod::Chunks,
+            });
+        }
+    }
+
+    let image = image::load_from_memory(input)
+        .map_err(RegionError::Decode)?
+        .into_rgba8();
+    Ok(Region {
+        rgba: imageops::crop_imm(&image, x, y, width, height)
+            .to_image()
+            .into_raw(),
+        width,
+        height,
+        method: RegionMethod::FullDecode,
+    })
+}
+
+/// Decodes a tiled or striped TIFF at 1/`factor` of its size on each side (rounded
+/// up), each output pixel the average of a `factor` x `factor` block.
+///
+/// Chunks are read one at a time and summed into a band of output rows that is
+/// written out as each row of chunks completes, so memory use is the reduced image
+/// plus one tile or strip and its band (see [`reduced_decode_bytes`]), and scans too
+/// large to decode in full can still be previewed or converted. Returns `None` for
+/// layouts that can't be read chunk by chunk, as for [`decode_region`].
+///
+/// # Errors
+///
+/// Returns a `TiffError` if the input is not a TIFF or its chunks cannot be read, and
+/// `TiffError::LimitsExceeded` if the reduced image would have more than
+/// [`MAX_SIDE`]² pixels.
+pub fn decode_reduced(input: &[u8], factor: u32) -> Result<Option<RgbaImage>, TiffError> {
+    let factor = factor.max(1);
+    let mut decoder = Decoder::new(Cursor::new(input))?;
+    let Some(layout) = ChunkLayout::read(&mut decoder)? else {
+        return Ok(None);
+    };
+    let (image_width, image_height) = decoder.dimensions()?;
+    let (width, height) = reduced_size(image_width, image_height, factor)?;
+
+    let mut reduced = RgbaImage::new(width, height);
+    // Sums for the output rows from `band_top` down, which a row of chunks can reach.
+    let band_rows = layout.band_rows(factor, image_height);
+    let mut sums = vec![[0u32; 4]; to_usize(width) * to_usize(band_rows)];
+    let mut band_top = 0;
+    let mut rgba = Vec::new();
+    for index in 0..layout.count {
+        let (data_width, data_height) = decoder.chunk_data_dimensions(index);
+        let DecodingResult::U8(data) = decoder.read_chunk(index)? else {
+            return Ok(None);
+        };
+        let left = index % layout.across * layout.width;
+        let top = index / layout.across * layout.height;
+        let stride = to_usize(data_width) * layout.channels;
+        rgba.resize(to_usize(data_width) * 4, 0);
+        for (y, line) in
+            (top..image_height).zip(data.chunks_exact(stride).take(to_usize(data_height)))
+        {
+            expand_to_rgba(line, &mut rgba, layout.channels);
+            let row = to_usize((y / factor).saturating_sub(band_top)) * to_usize(width);
+            for (x, pixel) in (left..image_width).zip(rgba.chunks_exact(4)) {
+                if let Some(sum) = sums.get_mut(row + to_usize(x / factor)) {
+                    for (total, &sample) in sum.iter_mut().zip(pixel) {
+                        *total += u32::from(sample);
+                    }
+                }
+            }
+        }
+
+        let last = index + 1 == layout.count;
+        if last || (index + 1) % layout.across == 0 {
+            // Output rows wholly above the next row of chunks are complete.
+            let next_top = top.saturating_add(layout.height);
+            let done = if last || next_top >= image_height {
+                height
+            } else {
+                next_top / factor
+            };
+            for y in band_top..done {
+                // Blocks on the right and bottom edges can be partial.
+                let rows = factor.min(image_height - y * factor);
+                let band = to_usize(y - band_top) * to_usize(width);
+                for x in 0..width {
+                    let count = factor.min(image_width - x * factor) * rows;
+                    let sum = sums.get(band + to_usize(x)).copied().unwrap_or_default();
+                    if let Some(pixel) = reduced.get_pixel_mut_checked(x, y) {
+                        *pixel = Rgba(sum.map(|total| {
+                            u8::try_from((total + count / 2) / count).unwrap_or(u8::MAX)
+                        }));
+                    }
+                }
+            }
+            // Move the rows still being summed to the top of the band.
+            let flushed = (to_usize(done - band_top) * to_usize(width)).min(sums.len());
+            sums.copy_within(flushed.., 0);
+            let kept = sums.len() - flushed;
+            sums.iter_mut().skip(kept).for_each(|sum| *sum = [0; 4]);
+            band_top = done;
+        }
+    }
+    Ok(Some(reduced))
+}
+
+/// Most memory [`decode_reduced`] holds at once for `input` at `factor`, in bytes: the
+/// reduced image, the sums for one band of it, and one decoded tile or strip. Returns
+/// `None` for layouts it can't read.
+///
+/// # Errors
+///
+/// Returns a `TiffError` under the same conditions as [`decode_reduced`], without
+/// reading any chunks.
+pub fn reduced_decode_bytes(input: &[u8], factor: u32) -> Result<Option<u64>, TiffError> {
+    let factor = factor.max(1);
+    let mut decoder = Decoder::new(Cursor::new(input))?;
+    let Some(layout) = ChunkLayout::read(&mut decoder)? else {
+        return Ok(None);
+    };
+    let (image_width, image_height) = decoder.dimensions()?;
+    let (width, height) = reduced_size(image_width, image_height, factor)?;
+    let band_rows = u64::from(layout.band_rows(factor, image_height));
+    let (width, height) = (u64::from(width), u64::from(height));
+    // Chunks are read without their padding past the image edges.
+    let chunk_width = u64::from(layout.width.min(image_width));
+    let chunk_pixels = chunk_width * u64::from(layout.height.min(image_height));
+    let channels = u64::try_from(layout.channels).unwrap_or(4);
+    Ok(Some(
+        width * height * 4 + width * band_rows * 16 + chunk_pixels * channels + chunk_width * 4,
+    ))
+}
+
+/// The size of an `image_width` x `image_height` image reduced by `factor`.
+///
+/// # Errors
+///
+/// Returns `TiffError::LimitsExceeded` if it would have more than [`MAX_SIDE`]²
+/// pixels, which only a corrupt or hostile header asks for.
+fn reduced_size(image_width: u32, image_height: u32, factor: u32) -> Result<(u32, u32), TiffError> {
+    let (width, height) = (image_width.div_ceil(factor), image_height.div_ceil(factor));
+    if u64::from(width) * u64::from(height) > u64::from(MAX_SIDE) * u64::from(MAX_SIDE) {
+        return Err(TiffError::LimitsExceeded);
+    }
+    Ok((width, height))
+}
+
+/// Decodes `input` with its longer side at most `max_edge` pixels, keeping the aspect
+/// ratio (see [`ResizeMode::Fit`]). Images already that small keep their own size.
+///
+/// Where the format can be decoded at reduced resolution (see [`decode_at_least`]), it
+/// is, and only resampled the rest of the way, so the full-size pixels are never held
+/// in memory. Everything else, including WebP and JPEGs that need less than 1/8
+/// reduction, is decoded in full and downscaled progressively; [`Region::method`] says
+/// which happened.
+///
+/// # Errors
+///
+/// Returns a `RegionError` if `max_edge` is zero or the input cannot be decoded.
+pub fn decode_scaled(input: &[u8], max_edge: u32) -> Result<Region, RegionError> {
+    let (image_width, image_height) = dimensions(input).map_err(RegionError::Decode)?;
+    let plan = resize::geometry(
+        image_width,
+        image_height,
+        ResizeMode::Fit,
+        max_edge,
+        max_edge,
+        true,
+    )
+    .map_err(RegionError::Resize)?;
+
+    let (image, method) = match decode_at_least(input, plan.width, plan.height)? {
+        Some(reduced) => reduced,
+        None => (
+            image::load_from_memory(input)
+                .map_err(RegionError::Decode)?
+                .into_rgba8(),
+            RegionMethod::FullDecode,
+        ),
+    };
+
+    // A reduced decode rounds up, so it is never smaller than the plan.
+    let image = if image.dimensions() == (plan.width, plan.height) {
+        image
+    } else {
+        resize::resize_image(
+            &image,
+            ResizeMode::Fill,
+            plan.width,
+            plan.height,
+            ResizeOptions::default(),
+        )
+        .map_err(RegionError::Resize)?
+    };
+    Ok(Region {
+        width: image.width(),
+        height: image.height(),
+        rgba: image.into_raw(),
+        method,
+    })
+}
+
+/// Decodes `input` reduced by the largest whole factor that keeps it at least
+/// `min_width` x `min_height`, if its format has a reduced-resolution decode, so
+/// thumbnail and resize paths can resample from that instead of the full image.
+///
+/// Tiled and striped TIFFs with 8-bit gray or RGB samples are reduced by the factor as
+/// their chunks are read (see [`decode_reduced`]). Gray and YCbCr JPEGs that can be
+/// reduced 8 times or more are decoded at 1/8 scale from their DC coefficients (see
+/// [`jpeg::decode_dc`]). Returns `None` for everything else, and when there is no
+/// reduction to make, so the caller decodes in full.
+///
+/// # Errors
+///
+/// Returns a `RegionError` if the input's format or dimensions cannot be read, or its
+/// TIFF chunks or JPEG coefficients cannot be decoded.
+pub fn decode_at_least(
+    input: &[u8],
+    min_width: u32,
+    min_height: u32,
+) -> Result<Option<(RgbaImage, RegionMethod)>, RegionError> {
+    let format = ImageFormat::detect_from_bytes(input).map_err(RegionError::Format)?;
+    let (image_width, image_height) = dimensions(input).map_err(RegionError::Decode)?;
+    // The largest factor with each side, rounded up, still at least the minimum.
+    let largest = |size: u32, min: u32| match min {
+        0 | 1 => size,
+        _ => size.saturating_sub(1) / (min - 1),
+    };
+    let factor = largest(image_width, min_width).min(largest(image_height, min_height));
+
+    match format {
+        ImageFormat::Tiff if factor >= 2 => {
+            let reduced = decode_reduced(input, factor).map_err(RegionError::Tiff)?;
+            if reduced.is_some() {
+                log::debug!("decoded TIFF chunks at 1/{factor} scale");
+            }
+            Ok(reduced.map(|image| (image, RegionMethod::Chunks)))
+        }
+        ImageFormat::Jpeg if factor >= JPEG_DC_FACTOR => match jpeg::decode_dc(input) {
+            Ok(image) => {
+                log::debug!("decoded JPEG DC coefficients at 1/{JPEG_DC_FACTOR} scale");
+                Ok(Some((image, RegionMethod::JpegDc)))
+            }
+            Err(JpegError::Unsupported(reason)) => {
+                log::debug!("no DC-only decode ({reason}), decoding in full");
+                Ok(None)
+            }
+            Err(e) => Err(RegionError::Jpeg(e)),
+        },
+        ImageFormat::Png
+        | ImageFormat::Jpeg
+        | ImageFormat::WebP
+        | ImageFormat::Gif
+        | ImageFormat::Bmp
+        | ImageFormat::Tiff
+        | ImageFormat::Ico
+        | ImageFormat::Tga
+        | ImageFormat::Qoi => Ok(None),
+    }
+}
+
+/// [`decode_at_least`] for callers that decode in full without it: `None` when the
+/// format has no reduced decode or the minimum leaves no reduction to make, and also,
+/// with a debug log, when the reduced decode fails.
+pub(crate) fn try_decode_at_least(
+    input: &[u8],
+    min_width: u32,
+    min_height: u32,
+) -> Option<RgbaImage> {
+    match decode_at_least(input, min_width, min_height) {
+        Ok(reduced) => reduced.map(|(image, _)| image),
+        Err(e) => {
+            log::debug!("reduced decode failed ({e}), decoding in full");
+            None
+        }
+    }
+}
+
+/// Reads the dimensions from `input`'s header.
+pub(crate) fn dimensions(input: &[u8]) -> Result<(u32, u32), image::ImageError> {
+    ImageReader::new(Cursor::new(input))
+        .with_guessed_format()
+        .map_err(image::ImageError::IoError)?
+        .into_dimensions()
+}
+
+/// How a TIFF's pixels are split into chunks that can be read one at a time.
+struct ChunkLayout {
+    /// Samples per pixel: 1 (gray) to 4 (RGBA).
+    channels: usize,
+    /// Size of a full tile or strip.
+    width: u32,
+    height: u32,
+    /// Chunks per row of the image.
+    across: u32,
+    count: u32,
+}
+
+impl ChunkLayout {
+    /// Reads the layout, or returns `None` for layouts this doesn't handle: anything
+    /// but contiguous 8-bit gray or RGB samples.
+    fn read(decoder: &mut Decoder<Cursor<&[u8]>>) -> Result<Option<Self>, TiffError> {
+        let channels: usize = match decoder.colortype()? {
+            ColorType::Gray(8) => 1,
+            ColorType::GrayA(8) => 2,
+            ColorType::RGB(8) => 3,
+            ColorType::RGBA(8) => 4,
+            _ => return Ok(None),
+        };
+        // Gray must be stored black-is-zero; anything else needs inverting or a palette.
+        let photometric = decoder.find_tag_unsigned::<u16>(Tag::PhotometricInterpretation)?;
+        if channels <= 2 && photometric != Some(1) {
+            return Ok(None);
+        }
+        let planar = decoder.find_tag_unsigned::<u16>(Tag::PlanarConfiguration)?;
+        if planar.is_some_and(|planar| planar != 1) {
+            return Ok(None);
+        }
+
+        let (image_width, _) = decoder.dimensions()?;
+        let (width, height) = decoder.chunk_dimensions();
+        if width == 0 || height == 0 {
+            return Ok(None);
+        }
+        let (across, count) = match decoder.get_chunk_type() {
+            ChunkType::Strip => (1, decoder.strip_count()?),
+            ChunkType::Tile => (image_width.div_ceil(width), decoder.tile_count()?),
+        };
+        Ok(Some(Self {
+            channels,
+            width,
+            height,
+            across,
+            count,
+        }))
+    }
+
+    /// Output rows a row of chunks can add to when reducing an image `image_height`
+    /// rows tall by `factor`: the rows it covers, plus one it shares with the row of
+    /// chunks above. A single strip can claim more rows than the image has.
+    fn band_rows(&self, factor: u32, image_height: u32) -> u32 {
+        self.height.min(image_height).div_ceil(factor) + 1
+    }
+}
+
+/// Copies `rect` out of the TIFF chunks it overlaps, or returns `None` for layouts
+/// [`ChunkLayout::read`] doesn't handle.
+fn tiff_region(input: &[u8], rect: Rect) -> Result<Option<Vec<u8>>, TiffError> {
+    let mut decoder = Decoder::new(Cursor::new(input))?;
+    let Some(ChunkLayout {
+        channels,
+        width: chunk_width,
+        height: chunk_height,
+        across,
+        count: chunk_count,
+    }) = ChunkLayout::read(&mut decoder)?
+    else {
+        return Ok(None);
+    };
+
+    let out_stride = to_usize(rect.width) * 4;
+    let mut rgba = vec![0; out_stride * to_usize(rect.height)];
+    let (right, bottom) = (rect.x + rect.width, rect.y + rect.height);
+    for row in rect.y / chunk_height..=(bottom - 1) / chunk_height {
+        for column in rect.x / chunk_width..=(right - 1) / chunk_width {
+            let index = row.saturating_mul(across).saturating_add(column);
+            if index >= chunk_count {
+                return Ok(None);
+            }
+            let (data_width, data_height) = decoder.chunk_data_dimensions(index);
+            let DecodingResult::U8(data) = decoder.read_chunk(index)? else {
+                return Ok(None);
+            };
+
+            // The overlap of the chunk and the rectangle, in image coordinates.
+            let (left, top) = (column * chunk_width, row * chunk_height);
+            let x0 = rect.x.max(left);
+            let x1 = right.min(left + data_width);
+            let y0 = rect.y.max(top);
+            let y1 = bottom.min(top + data_height);
+            if x0 >= x1 {
+                continue;
+            }
+            let span = to_usize(x1 - x0);
+            for y in y0..y1 {
+                let src_start =
+                    (to_usize(y - top) * to_usize(data_width) + to_usize(x0 - left)) * channels;
+                let dst_start = to_usize(y - rect.y) * out_stride + to_usize(x0 - rect.x) * 4;
+                let (Some(src), Some(dst)) = (
+                    data.get(src_start..src_start + span * channels),
+                    rgba.get_mut(dst_start..dst_start + span * 4),
+                ) else {
+                    return Ok(None);
+                };
+                expand_to_rgba(src, dst, channels);
+            }
+        }
+    }
+    Ok(Some(rgba))
+}
+
+/// Writes `src` pixels of `channels` samples each (gray, gray and alpha, RGB or RGBA)
+/// into `dst` as RGBA.
+pub fn expand_to_rgba(src: &[u8], dst: &mut [u8], channels: usize) {
+    for (pixel, out) in src.chunks_exact(channels).zip(dst.chunks_exact_mut(4)) {
+        let rgba = match *pixel {
+            [gray] => [gray, gray, gray, 255],
+            [gray, alpha] => [gray, gray, gray, alpha],
+            [r, g, b] => [r, g, b, 255],
+            [r, g, b, a] => [r, g, b, a],
+            _ => return,
+        };
+        out.copy_from_slice(&rgba);
+    }
+}
+
+fn to_usize(value: u32) -> usize {
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
+
+/// Errors that can occur while decoding a region.
+#[derive(Debug)]
+pub enum RegionError {
+    /// The input format could not be detected.
+    Format(FormatError),
+    /// Failed to read the image.
+    Decode(image::ImageError),
+    /// Failed to read the TIFF tiles or strips.
+    Tiff(TiffError),
+    /// Failed to read the JPEG's DC coefficients.
+    Jpeg(JpegError),
+    /// The rectangle is empty or extends past the image.
+    OutOfBounds { image_width: u32, image_height: u32 },
+    /// The requested size was empty, or resampling failed.
+    Resize(ResizeError),
+}
+
+impl fmt::Display for RegionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Format(e) => write!(f, "{e}"),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Tiff(e) => write!(f, "Failed to read TIFF chunks: {e}"),
+            Self::Jpeg(e) => write!(f, "Failed to read JPEG coefficients: {e}"),
+            Self::OutOfBounds {
+                image_width,
+                image_height,
+            } => write!(
+                f,
+                "Region must be non-empty and fit within the {image_width}x{image_height} image"
+            ),
+            Self::Resize(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for RegionError {}
+
+#[cfg(test)]
+mod tests {
+    use image::{DynamicImage, Rgb, RgbImage};
+
+    use super::*;
+
+    // Safe: modulo 256 guarantees values fit in u8.
+    #[allow(clippy::as_conversions)]
+    fn gradient(width: u32, height: u32) -> RgbImage {
+        RgbImage::from_fn(width, height, |x, y| {
+            Rgb([
+                (x * 7 % 256) as u8,
+                (y * 5 % 256) as u8,
+                ((x + y) * 3 % 256) as u8,
+            ])
+        })
+    }
+
+    fn encode(image: &RgbImage, format: image::ImageFormat) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        image.write_to(&mut buf, format).unwrap();
+        buf.into_inner()
+    }
+
+    fn expected(image: &RgbImage, x: u32, y: u32, width: u32, height: u32) -> Vec<u8> {
+        DynamicImage::ImageRgb8(imageops::crop_imm(image, x, y, width, height).to_image())
+            .into_rgba8()
+            .into_raw()
+    }
+
+    /// An uncompressed little-endian RGB TIFF stored as `tile` x `tile` tiles.
+    fn tiled_tiff(image: &RgbImage, tile: u32) -> Vec<u8> {
+        let across = image.width().div_ceil(tile);
+        let down = image.height().div_ceil(tile);
+        let tile_bytes = tile * tile * 3;
+        let count = across * down;
+
+        let mut tiles = Vec::new();
+        for row in 0..down {
+            for column in 0..across {
+                for y in row * tile..(row + 1) * tile {
+                    for x in column * tile..(column + 1) * tile {
+                        let pixel = image.get_pixel_checked(x, y).map_or([0; 3], |p| p.0);
+                        tiles.extend_from_slice(&pixel);
+                    }
+                }
+            }
+        }
+
+        let entries: u16 = 11;
+        let ifd_offset: u32 = 8;
+        let bits_offset = ifd_offset + 2 + u32::from(entries) * 12 + 4;
+        let offsets_offset = bits_offset + 6;
+        let counts_offset = offsets_offset + count * 4;
+        let data_offset = counts_offset + count * 4;
+
+        let mut out = b"II*\0".to_vec();
+        out.extend_from_slice(&ifd_offset.to_le_bytes());
+        out.extend_from_slice(&entries.to_le_bytes());
+        let mut entry = |tag: u16, kind: u16, count: u32, value: u32| {
+            out.extend_from_slice(&tag.to_le_bytes());
+            out.extend_from_slice(&kind.to_le_bytes());
+            out.extend_from_slice(&count.to_le_bytes());
+            out.extend_from_slice(&value.to_le_bytes());
+        };
+        const SHORT: u16 = 3;
+        const LONG: u16 = 4;
+        entry(256, LONG, 1, image.width());
+        entry(257, LONG, 1, image.height());
+        entry(258, SHORT, 3, bits_offset);
+        entry(259, SHORT, 1, 1);
+        entry(262, SHORT, 1, 2);
+        entry(277, SHORT, 1, 3);
+        entry(284, SHORT, 1, 1);
+        entry(322, LONG, 1, tile);
+        entry(323, LONG, 1, tile);
+        entry(324, LONG, count, offsets_offset);
+        entry(325, LONG, count, counts_offset);
+        out.extend_from_slice(&0u32.to_le_bytes());
+        for _ in 0..3 {
+            out.extend_from_slice(&8u16.to_le_bytes());
+        }
+        for index in 0..count {
+            out.extend_from_slice(&(data_offset + index * tile_bytes).to_le_bytes());
+        }
+        for _ in 0..count {
+            out.extend_from_slice(&tile_bytes.to_le_bytes());
+        }
+        out.extend_from_slice(&tiles);
+        out
+    }
+
+    #[test]
+    fn tiled_tiff_reads_overlapping_tiles() {
+        let image = gradient(50, 40);
+        let tiff = tiled_tiff(&image, 16);
+
+        for (x, y, width, height) in [(0, 0, 50, 40), (10, 12, 20, 9), (33, 30, 17, 10)] {
+            let region = decode_region(&tiff, x, y, width, height).unwrap();
+            assert_eq!(region.method, RegionMethod::Chunks);
+            assert_eq!((region.width, region.height), (width, height));
+            assert_eq!(region.rgba, expected(&image, x, y, width, height));
+        }
+    }
+
+    #[test]
+    fn striped_tiff_reads_overlapping_strips() {
+        // Large enough for the encoder to split the image into several strips.
+        let image = gradient(64, 300);
+        let tiff = encode(&image, image::ImageFormat::Tiff);
+
+        let region = decode_region(&tiff, 5, 150, 30, 100).unwrap();
+        assert_eq!(region.method, RegionMethod::Chunks);
+        assert_eq!(region.rgba, expected(&image, 5, 150, 30, 100));
+    }
+
+    #[test]
+    fn other_formats_decode_then_crop() {
+        let image = gradient(20, 20);
+        let png = encode(&image, image::ImageFormat::Png);
+
+        let region = decode_region(&png, 4, 6, 8, 3).unwrap();
+        assert_eq!(region.method, RegionMethod::FullDecode);
+        assert_eq!(region.rgba, expected(&image, 4, 6, 8, 3));
+    }
+
+    fn box_average(image: &RgbImage, factor: u32) -> Vec<u8> {
+        let (width, height) = image.dimensions();
+        let mut out = Vec::new();
+        for y in 0..height.div_ceil(factor) {
+            for x in 0..width.div_ceil(factor) {
+                let block =
+                    imageops::crop_imm(image, x * factor, y * factor, factor, factor).to_image();
+                let count = block.width() * block.height();
+                for channel in 0..3 {
+                    let total: u32 = block.pixels().map(|p| u32::from(p.0[channel])).sum();
+                    out.push(u8::try_from((total + count / 2) / count).unwrap());
+                }
+                out.push(255);
+            }
+        }
+        out
+    }
+
+    #[test]
+    fn reduced_decode_averages_blocks() {
+        let image = gradient(50, 40);
+        let tiled = decode_reduced(&tiled_tiff(&image, 16), 3).unwrap().unwrap();
+        assert_eq!(tiled.dimensions(), (17, 14));
+        assert_eq!(tiled.into_raw(), box_average(&image, 3));
+
+        let image = gradient(64, 300);
+        let striped = decode_reduced(&encode(&image, image::ImageFormat::Tiff), 8)
+            .unwrap()
+            .unwrap();
+        assert_eq!(striped.dimensions(), (8, 38));
+        assert_eq!(striped.into_raw(), box_average(&image, 8));
+
+        let png = encode(&image, image::ImageFormat::Png);
+        assert!(decode_reduced(&png, 2).is_err());
+    }
+
+    #[test]
+    fn reduced_decode_rejects_huge_headers_before_allocating() {
+        // One tile, claiming a 436207653x29 image: the header a corrupt scan can have.
+        let mut tiff = tiled_tiff(&gradient(16, 16), 16);
+        for (offset, value) in [
+            (18, 436_207_653u32),
+            (30, 29),
+            (102, 436_207_664),
+            (114, 32),
+        ] {
+            tiff[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        assert!(matches!(
+            decode_reduced(&tiff, 2),
+            Err(TiffError::LimitsExceeded)
+        ));
+        assert!(matches!(
+            reduced_decode_bytes(&tiff, 2),
+            Err(TiffError::LimitsExceeded)
+        ));
+    }
+
+    #[test]
+    fn reduced_decode_bytes_counts_the_band_and_a_chunk() {
+        let tiff = tiled_tiff(&gradient(50, 40), 16);
+        // 17x14 RGBA, sums for 7 rows of 17, one 16x16 RGB tile and one RGBA line.
+        let expected = 17 * 14 * 4 + 17 * 7 * 16 + 16 * 16 * 3 + 16 * 4;
+        assert_eq!(reduced_decode_bytes(&tiff, 3).unwrap(), Some(expected));
+
+        let png = encode(&gradient(50, 40), image::ImageFormat::Png);
+        assert!(reduced_decode_bytes(&png, 3).is_err());
+    }
+
+    #[test]
+    fn scaled_decode_is_never_larger_than_requested() {
+        let image = gradient(300, 200);
+        let tiff = tiled_tiff(&image, 16);
+        let scaled = decode_scaled(&tiff, 70).unwrap();
+        assert_eq!(scaled.method, RegionMethod::Chunks);
+        assert_eq!((scaled.width, scaled.height), (70, 47));
+        assert_eq!(scaled.rgba.len(), 70 * 47 * 4);
+
+        let png = encode(&image, image::ImageFormat::Png);
+        let scaled = decode_scaled(&png, 70).unwrap();
+        assert_eq!(scaled.method, RegionMethod::FullDecode);
+        assert_eq!((scaled.width, scaled.height), (70, 47));
+
+        // Exact factors skip resampling; small images keep their size.
+        let scaled = decode_scaled(&tiff, 100).unwrap();
+        assert_eq!((scaled.width, scaled.height), (100, 67));
+        let exact = decode_scaled(&tiled_tiff(&gradient(48, 32), 16), 16).unwrap();
+        assert_eq!(exact.rgba, box_average(&gradient(48, 32), 3));
+        let small = decode_scaled(&png, 500).unwrap();
+        assert_eq!((small.width, small.height), (300, 200));
+        assert!(matches!(
+            decode_scaled(&png, 0),
+            Err(RegionError::Resize(_))
+        ));
+    }
+
+    #[test]
+    fn large_jpeg_reductions_decode_dc_only() {
+        let jpeg = encode(&gradient(304, 200), image::ImageFormat::Jpeg);
+        let scaled = decode_scaled(&jpeg, 30).unwrap();
+        assert_eq!(scaled.method, RegionMethod::JpegDc);
+        assert_eq!((scaled.width, scaled.height), (30, 20));
+
+        let exact = decode_scaled(&jpeg, 38).unwrap();
+        assert_eq!(exact.method, RegionMethod::JpegDc);
+        assert_eq!(exact.rgba, jpeg::decode_dc(&jpeg).unwrap().into_raw());
+
+        let scaled = decode_scaled(&jpeg, 40).unwrap();
+        assert_eq!(scaled.method, RegionMethod::FullDecode);
+        assert_eq!((scaled.width, scaled.height), (40, 26));
+    }
+
+    #[test]
+    fn decodes_at_least_the_minimum() {
+        let tiff = tiled_tiff(&gradient(300, 200), 16);
+        // 300 / 4 rounds up to 75 and 200 / 4 to 50; a factor of 5 would give 60x40.
+        let (image, method) = decode_at_least(&tiff, 70, 41).unwrap().unwrap();
+        assert_eq!(method, RegionMethod::Chunks);
+        assert_eq!(image.dimensions(), (75, 50));
+        assert!(decode_at_least(&tiff, 200, 150).unwrap().is_none());
+
+        let jpeg = encode(&gradient(304, 200), image::ImageFormat::Jpeg);
+        let (image, method) = decode_at_least(&jpeg, 20, 25).unwrap().unwrap();
+        assert_eq!(method, RegionMethod::JpegDc);
+        assert_eq!(image.dimensions(), (38, 25));
+        assert!(decode_at_least(&jpeg, 20, 26).unwrap().is_none());
+
+        let png = encode(&gradient(300, 200), image::ImageFormat::Png);
+        assert!(decode_at_least(&png, 10, 10).unwrap().is_none());
+    }
+
+    #[test]
+    fn rejects_regions_outside_the_image() {
+        let png = encode(&gradient(20, 20), image::ImageFormat::Png);
+
+        for (x, y, width, height) in [(0, 0, 0, 5), (15, 0, 6, 5), (0, 19, 1, 2)] {
+            assert!(matches!(
+                decode_region(&png, x, y, width, height),
+                Err(RegionError::OutOfBounds {
+                    image_width: 20,
+                    image_height: 20
+                })
+            ));
+        }
+        assert!(matches!(
+            decode_region(&png, u32::MAX, 0, 1, 1),
+            Err(RegionError::OutOfBounds { .. })
+        ));
+    }
+}
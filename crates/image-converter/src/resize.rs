@@ -0,0 +1,704 @@
+use std::fmt;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, Rgba, RgbaImage};
+use serde::Serialize;
+
+use crate::color::{self, ColorError};
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::MAX_SIDE;
+use crate::region;
+use crate::sample;
+
+/// Downscales by more than this factor on either side are done progressively when
+/// [`ResizeOptions::progressive`] is set.
+pub const PROGRESSIVE_RATIO: u32 = 3;
+
+/// How an image is sized to a target, following CSS `object-fit` and ImageMagick
+/// geometry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ResizeMode {
+    /// Scale to fit inside `width` x `height`, keeping the aspect ratio; the output is
+    /// the scaled image (ImageMagick `WxH`).
+    Fit,
+    /// Stretch to exactly `width` x `height`, ignoring the aspect ratio (CSS `fill`,
+    /// ImageMagick `WxH!`).
+    Fill,
+    /// Scale to cover `width` x `height` and crop the overflow equally from both sides
+    /// (CSS `cover`, ImageMagick `WxH^` with centered `-extent`).
+    Cover,
+    /// Scale to fit inside `width` x `height` and center on a transparent canvas of
+    /// that size (CSS `contain`).
+    Contain,
+    /// Scale so the longer side is `width` pixels.
+    LongEdge,
+    /// Scale so the shorter side is `width` pixels.
+    ShortEdge,
+}
+
+impl ResizeMode {
+    /// Every mode, in declaration order.
+    pub const ALL: [Self; 6] = [
+        Self::Fit,
+        Self::Fill,
+        Self::Cover,
+        Self::Contain,
+        Self::LongEdge,
+        Self::ShortEdge,
+    ];
+
+    /// Parses a resize mode.
+    ///
+    /// Accepts `"fit"`, `"fill"`, `"cover"`, `"contain"`, `"long_edge"` and
+    /// `"short_edge"`. An empty string selects `"fit"`.
+    ///
+    /// Returns an error if the string is not a recognized mode.
+    pub fn from_name(name: &str) -> Result<Self, ResizeError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "fit" => Ok(Self::Fit),
+            "fill" => Ok(Self::Fill),
+            "cover" => Ok(Self::Cover),
+            "contain" => Ok(Self::Contain),
+            "long_edge" => Ok(Self::LongEdge),
+            "short_edge" => Ok(Self::ShortEdge),
+            _ => Err(ResizeError::UnknownMode(name.to_owned())),
+        }
+    }
+}
+
+/// What fills the padding [`ResizeMode::Contain`] adds around the scaled image.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PadFill {
+    /// Transparent pixels, which formats without alpha show as black.
+    #[default]
+    Transparent,
+    /// A solid RGBA color.
+    Color([u8; 4]),
+    /// The source's background color, detected from its border with
+    /// [`sample::background`], so product shots and scans letterbox seamlessly.
+    Auto,
+}
+
+impl PadFill {
+    /// Parses `"transparent"`, `"auto"` or a hex color such as `"#ffffff"`.
+    ///
+    /// # Errors
+    ///
+    /// Returns `ResizeError::InvalidFill` if the name is neither a fill nor a color.
+    pub fn from_name(name: &str) -> Result<Self, ResizeError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "transparent" => Ok(Self::Transparent),
+            "auto" => Ok(Self::Auto),
+            _ => color::parse_color(name)
+                .map(Self::Color)
+                .map_err(ResizeError::InvalidFill),
+        }
+    }
+}
+
+/// Settings for [`resize_image`] beyond the mode and size.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResizeOptions {
+    /// Keep sources the mode would enlarge at their own size (see [`geometry`]).
+    pub no_upscale: bool,
+    /// For downscales of more than [`PROGRESSIVE_RATIO`]x, repeatedly halve the image
+    /// by averaging 2x2 blocks before the final Lanczos3 pass. This avoids the moire a
+    /// single pass leaves on fine detail, such as fabric in a 24 MP photo shrunk to a
+    /// 300 px thumbnail, and is faster.
+    pub progressive: bool,
+    /// What fills [`ResizeMode::Contain`]'s padding.
+    pub fill: PadFill,
+}
+
+impl Default for ResizeOptions {
+    fn default() -> Self {
+        Self {
+            no_upscale: false,
+            progressive: true,
+            fill: PadFill::Transparent,
+        }
+    }
+}
+
+/// Where a resized image ends up: the source is resampled to `scaled_width` x
+/// `scaled_height` and placed with its top-left corner at (`x`, `y`) on a `width` x
+/// `height` output. Offsets are negative where [`ResizeMode::Cover`] crops and
+/// positive where [`ResizeMode::Contain`] pads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct ResizeGeometry {
+    pub scaled_width: u32,
+    pub scaled_height: u32,
+    pub width: u32,
+    pub height: u32,
+    pub x: i64,
+    pub y: i64,
+    /// Whether the mode would have enlarged the source and `no_upscale` kept it at its
+    /// own size instead.
+    pub upscale_prevented: bool,
+}
+
+/// Computes where a `source_width` x `source_height` image goes when resized with
+/// `mode`. `height` is ignored by the edge modes.
+///
+/// A side the mode constrains gets exactly the requested size. The other side is
+/// scaled in proportion and rounded half up, and is never less than 1. Crops and
+/// padding are split evenly, with any odd pixel going to the right or bottom.
+///
+/// With `no_upscale`, a source the mode would enlarge keeps its own size instead, so
+/// thumbnails of small images stay sharp: [`ResizeMode::Fill`] stops stretching each
+/// side at the source's size, [`ResizeMode::Cover`] only crops the sides that overflow
+/// the box, and [`ResizeMode::Contain`] pads the unscaled source out to the box.
+///
+/// # Errors
+///
+/// Returns `ResizeError::InvalidSize` if a requested size or the source is empty, and
+/// `ResizeError::TooLarge` if a side would exceed [`MAX_SIDE`].
+pub fn geometry(
+    source_width: u32,
+    source_height: u32,
+    mode: ResizeMode,
+    width: u32,
+    height: u32,
+    no_upscale: bool,
+) -> Result<ResizeGeometry, ResizeError> {
+    let height = match mode {
+        ResizeMode::LongEdge | ResizeMode::ShortEdge => width,
+        ResizeMode::Fit | ResizeMode::Fill | ResizeMode::Cover | ResizeMode::Contain => height,
+    };
+    if width == 0 || height == 0 || source_width == 0 || source_height == 0 {
+        return Err(ResizeError::InvalidSize { width, height });
+    }
+
+    let (sw, sh) = (u64::from(source_width), u64::from(source_height));
+    let (tw, th) = (u64::from(width), u64::from(height));
+    // Whether the width is the side that sets the scale when fitting inside the box.
+    let width_limits = sw * th >= sh * tw;
+    let by_width = || (tw, proportion(sh, tw, sw));
+    let by_height = || (proportion(sw, th, sh), th);
+    let scaled = match mode {
+        ResizeMode::Fill => (tw, th),
+        ResizeMode::Fit | ResizeMode::Contain if width_limits => by_width(),
+        ResizeMode::Fit | ResizeMode::Contain => by_height(),
+        ResizeMode::Cover if width_limits => by_height(),
+        ResizeMode::Cover => by_width(),
+        ResizeMode::LongEdge if sw >= sh => by_width(),
+        ResizeMode::LongEdge => by_height(),
+        ResizeMode::ShortEdge if sw <= sh => by_width(),
+        ResizeMode::ShortEdge => by_height(),
+    };
+    let upscale_prevented = no_upscale && (scaled.0 > sw || scaled.1 > sh);
+    let scaled = if upscale_prevented {
+        match mode {
+            ResizeMode::Fill => (scaled.0.min(sw), scaled.1.min(sh)),
+            ResizeMode::Fit
+            | ResizeMode::Cover
+            | ResizeMode::Contain
+            | ResizeMode::LongEdge
+            | ResizeMode::ShortEdge => (sw, sh),
+        }
+    } else {
+        scaled
+    };
+    let side = |value: u64| {
+        u32::try_from(value)
+            .ok()
+            .filter(|&side| side <= MAX_SIDE)
+            .ok_or(ResizeError::TooLarge)
+    };
+    let (scaled_width, scaled_height) = (side(scaled.0)?, side(scaled.1)?);
+    let (out_width, out_height) = match mode {
+        ResizeMode::Cover => (side(tw)?.min(scaled_width), side(th)?.min(scaled_height)),
+        ResizeMode::Contain => (side(tw)?, side(th)?),
+        ResizeMode::Fit | ResizeMode::Fill | ResizeMode::LongEdge | ResizeMode::ShortEdge => {
+            (scaled_width, scaled_height)
+        }
+    };
+
+    // Division truncates toward zero, so the odd pixel is always on the right or bottom.
+    let offset = |out: u32, scaled: u32| (i64::from(out) - i64::from(scaled)) / 2;
+    Ok(ResizeGeometry {
+        scaled_width,
+        scaled_height,
+        width: out_width,
+        height: out_height,
+        x: offset(out_width, scaled_width),
+        y: offset(out_height, scaled_height),
+        upscale_prevented,
+    })
+}
+
+/// `value * numerator / denominator`, rounded half up and at least 1.
+fn proportion(value: u64, numerator: u64, denominator: u64) -> u64 {
+    let (value, numerator, denominator) = (
+        u128::from(value),
+        u128::from(numerator),
+        u128::from(denominator),
+    );
+    let rounded = (2 * value * numerator + denominator) / (2 * denominator);
+    u64::try_from(rounded).unwrap_or(u64::MAX).max(1)
+}
+
+/// Resizes `img` with `mode` (see [`geometry`]), resampling with Lanczos3. Logs a
+/// warning when `no_upscale` keeps the source from being enlarged.
+///
+/// # Errors
+///
+/// Returns a `ResizeError` if the size is empty or too large.
+pub fn resize_image(
+    img: &RgbaImage,
+    mode: ResizeMode,
+    width: u32,
+    height: u32,
+    options: ResizeOptions,
+) -> Result<RgbaImage, ResizeError> {
+    resize_reduced(img, img.width(), img.height(), mode, width, height, options)
+}
+
+/// Like [`resize_image`] for `img` decoded at reduced resolution (see
+/// [`region::decode_at_least`]) from a `source_width` x `source_height` original. The
+/// geometry comes from the original's size, so the result is the same size as
+/// resizing the full image would give.
+///
+/// # Errors
+///
+/// Returns a `ResizeError` if the size is empty or too large.
+pub fn resize_reduced(
+    img: &RgbaImage,
+    source_width: u32,
+    source_height: u32,
+    mode: ResizeMode,
+    width: u32,
+    height: u32,
+    options: ResizeOptions,
+) -> Result<RgbaImage, ResizeError> {
+    let plan = geometry(
+        source_width,
+        source_height,
+        mode,
+        width,
+        height,
+        options.no_upscale,
+    )?;
+    if plan.upscale_prevented {
+        log::warn!(
+            "not upscaling the {source_width}x{source_height} source to {width}x{height}; keeping its own size"
+        );
+    }
+    let scaled = if (plan.scaled_width, plan.scaled_height) == img.dimensions() {
+        img.clone()
+    } else if options.progressive {
+        downscale_progressively(img, plan.scaled_width, plan.scaled_height)
+    } else {
+        imageops::resize(
+            img,
+            plan.scaled_width,
+            plan.scaled_height,
+            FilterType::Lanczos3,
+        )
+    };
+    if (plan.width, plan.height) == scaled.dimensions() {
+        return Ok(scaled);
+    }
+    let fill = match options.fill {
+        PadFill::Transparent => [0; 4],
+        PadFill::Color(color) => color,
+        PadFill::Auto => sample::background(img).map_or([0; 4], |found| found.rgba),
+    };
+    let mut out = RgbaImage::from_pixel(plan.width, plan.height, Rgba(fill));
+    imageops::replace(&mut out, &scaled, plan.x, plan.y);
+    Ok(out)
+}
+
+/// Resamples `img` to `width` x `height`, halving it first while it is at least twice
+/// the target on both sides, if it is more than [`PROGRESSIVE_RATIO`] times the target
+/// on either side.
+fn downscale_progressively(img: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let far = |from: u32, to: u32| u64::from(from) > u64::from(to) * u64::from(PROGRESSIVE_RATIO);
+    if !far(img.width(), width) && !far(img.height(), height) {
+        return imageops::resize(img, width, height, FilterType::Lanczos3);
+    }
+    let mut current = halve(img);
+    while current.width() / 2 >= width && current.height() / 2 >= height {
+        current = halve(&current);
+    }
+    log::debug!(
+        "downscaling {}x{} to {width}x{height} via {}x{}",
+        img.width(),
+        img.height(),
+        current.width(),
+        current.height()
+    );
+    imageops::resize(&current, width, height, FilterType::Lanczos3)
+}
+
+/// `img` at half size (rounded up), each pixel the average of a 2x2 block with color
+/// weighted by alpha so transparent pixels don't darken their neighbors. An odd last
+/// column or row averages with itself.
+fn halve(img: &RgbaImage) -> RgbaImage {
+    let (width, height) = img.dimensions();
+    RgbaImage::from_fn(width.div_ceil(2), height.div_ceil(2), |x, y| {
+        let (x0, y0) = (2 * x, 2 * y);
+        let (x1, y1) = ((x0 + 1).min(width - 1), (y0 + 1).min(height - 1));
+        let mut sums = [0u32; 4];
+        for (px, py) in [(x0, y0), (x1, y0), (x0, y1), (x1, y1)] {
+            let [r, g, b, a] = img.get_pixel(px, py).0;
+            let a = u32::from(a);
+            for (sum, channel) in sums.iter_mut().zip([r, g, b]) {
+                *sum += u32::from(channel) * a;
+            }
+            sums[3] += a;
+        }
+        let alpha = sums[3];
+        if alpha == 0 {
+            return Rgba([0; 4]);
+        }
+        let mut out = [0u8; 4];
+        for (slot, sum) in out.iter_mut().zip(sums) {
+            *slot = u8::try_from((sum + alpha / 2) / alpha).unwrap_or(u8::MAX);
+        }
+        out[3] = u8::try_from((alpha + 2) / 4).unwrap_or(u8::MAX);
+        Rgba(out)
+    })
+}
+
+/// Decodes `input`, resizes it with [`resize_image`], and encodes the result as
+/// `target`. Formats without alpha show [`ResizeMode::Contain`]'s transparent padding
+/// as black. Downscales decode at reduced resolution where the format allows (see
+/// [`region::decode_at_least`]).
+///
+/// # Errors
+///
+/// Returns a `ResizeError` if the size is invalid, the input cannot be decoded, or the
+/// output cannot be encoded.
+pub fn resize(
+    input: &[u8],
+    mode: ResizeMode,
+    width: u32,
+    height: u32,
+    options: ResizeOptions,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, ResizeError> {
+    let (source_width, source_height) = region::dimensions(input).map_err(ResizeError::Decode)?;
+    let plan = geometry(
+        source_width,
+        source_height,
+        mode,
+        width,
+        height,
+        options.no_upscale,
+    )?;
+    let img = match region::try_decode_at_least(input, plan.scaled_width, plan.scaled_height) {
+        Some(reduced) => reduced,
+        None => image::load_from_memory(input)
+            .map_err(ResizeError::Decode)?
+            .into_rgba8(),
+    };
+    let resized = resize_reduced(
+        &img,
+        source_width,
+        source_height,
+        mode,
+        width,
+        height,
+        options,
+    )?;
+    convert::encode(&DynamicImage::ImageRgba8(resized), target, quality)
+        .map_err(ResizeError::Convert)
+}
+
+/// Errors that can occur while resizing.
+#[derive(Debug)]
+pub enum ResizeError {
+    /// The resize mode was not recognized.
+    UnknownMode(String),
+    /// The padding fill was neither a fill name nor a color.
+    InvalidFill(ColorError),
+    /// A requested side or the source was empty.
+    InvalidSize { width: u32, height: u32 },
+    /// A side of the result would exceed [`MAX_SIDE`].
+    TooLarge,
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for ResizeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownMode(name) => write!(
+                f,
+                "Unknown resize mode \"{name}\" (expected fit, fill, cover, contain, long_edge or short_edge)"
+            ),
+            Self::InvalidFill(e) => write!(
+                f,
+                "Padding fill must be \"transparent\", \"auto\" or a hex color: {e}"
+            ),
+            Self::InvalidSize { width, height } => write!(
+                f,
+                "Resize size must be at least 1x1 on a non-empty image, got {width}x{height}"
+            ),
+            Self::TooLarge => write!(
+                f,
+                "The resized image would be larger than {MAX_SIDE}x{MAX_SIDE}"
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ResizeError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sizes(geometry: ResizeGeometry) -> (u32, u32, u32, u32, i64, i64) {
+        (
+            geometry.scaled_width,
+            geometry.scaled_height,
+            geometry.width,
+            geometry.height,
+            geometry.x,
+            geometry.y,
+        )
+    }
+
+    #[test]
+    fn box_modes_match_css_and_imagemagick() {
+        let plan = |mode| sizes(geometry(1000, 667, mode, 300, 300, false).unwrap());
+        // 667 * 0.3 = 200.1 rounds to 200.
+        assert_eq!(plan(ResizeMode::Fit), (300, 200, 300, 200, 0, 0));
+        assert_eq!(plan(ResizeMode::Fill), (300, 300, 300, 300, 0, 0));
+        assert_eq!(plan(ResizeMode::Contain), (300, 200, 300, 300, 0, 50));
+        // 1000 * 300 / 667 = 449.78 rounds to 450; 150 pixels of overflow, split evenly.
+        assert_eq!(plan(ResizeMode::Cover), (450, 300, 300, 300, -75, 0));
+    }
+
+    #[test]
+    fn rounding_is_half_up() {
+        // 3 * 5 / 2 = 7.5 rounds up to 8.
+        assert_eq!(
+            sizes(geometry(2, 3, ResizeMode::Fit, 5, 100, false).unwrap()).1,
+            8
+        );
+        // A sliver keeps at least one pixel.
+        assert_eq!(
+            sizes(geometry(1000, 1, ResizeMode::Fit, 10, 10, false).unwrap()),
+            (10, 1, 10, 1, 0, 0)
+        );
+        // An odd pixel of overflow is cropped from the right.
+        assert_eq!(
+            sizes(geometry(11, 10, ResizeMode::Cover, 10, 10, false).unwrap()),
+            (11, 10, 10, 10, 0, 0)
+        );
+    }
+
+    #[test]
+    fn edge_modes_set_one_side() {
+        let edge = |w, h, mode| sizes(geometry(w, h, mode, 100, 0, false).unwrap());
+        assert_eq!(
+            edge(400, 300, ResizeMode::LongEdge),
+            (100, 75, 100, 75, 0, 0)
+        );
+        assert_eq!(
+            edge(300, 400, ResizeMode::LongEdge),
+            (75, 100, 75, 100, 0, 0)
+        );
+        assert_eq!(
+            edge(400, 300, ResizeMode::ShortEdge),
+            (133, 100, 133, 100, 0, 0)
+        );
+        assert_eq!(
+            edge(300, 400, ResizeMode::ShortEdge),
+            (100, 133, 100, 133, 0, 0)
+        );
+    }
+
+    #[test]
+    fn rejects_empty_and_huge_sizes() {
+        assert!(matches!(
+            geometry(10, 10, ResizeMode::Fit, 0, 10, false),
+            Err(ResizeError::InvalidSize { .. })
+        ));
+        assert!(matches!(
+            geometry(1, 10_000, ResizeMode::Cover, 1000, 1000, false),
+            Err(ResizeError::TooLarge)
+        ));
+        assert!(matches!(
+            ResizeMode::from_name("stretch"),
+            Err(ResizeError::UnknownMode(_))
+        ));
+        for mode in ResizeMode::ALL {
+            assert!(geometry(5, 5, mode, 1, 1, false).is_ok());
+        }
+    }
+
+    #[test]
+    fn contain_pads_and_cover_crops() {
+        let img = RgbaImage::from_pixel(20, 10, Rgba([255, 0, 0, 255]));
+        let contained =
+            resize_image(&img, ResizeMode::Contain, 10, 10, ResizeOptions::default()).unwrap();
+        assert_eq!(contained.dimensions(), (10, 10));
+        assert_eq!(contained.get_pixel(5, 1).0, [0; 4]);
+        assert_eq!(contained.get_pixel(5, 5).0, [255, 0, 0, 255]);
+
+        let covered =
+            resize_image(&img, ResizeMode::Cover, 10, 10, ResizeOptions::default()).unwrap();
+        assert_eq!(covered.dimensions(), (10, 10));
+        assert!(covered.pixels().all(|p| p.0 == [255, 0, 0, 255]));
+    }
+
+    #[test]
+    fn contain_pads_with_the_chosen_fill() {
+        // A dark subject on a light backdrop, letterboxed into a square.
+        let img = RgbaImage::from_fn(20, 10, |x, _| {
+            if (8..12).contains(&x) {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([250, 240, 230, 255])
+            }
+        });
+        let padded = |fill| {
+            let options = ResizeOptions {
+                fill,
+                ..ResizeOptions::default()
+            };
+            resize_image(&img, ResizeMode::Contain, 20, 20, options).unwrap()
+        };
+        assert_eq!(
+            padded(PadFill::Auto).get_pixel(0, 0).0,
+            [250, 240, 230, 255]
+        );
+        assert_eq!(
+            padded(PadFill::from_name("#00f").unwrap())
+                .get_pixel(19, 19)
+                .0,
+            [0, 0, 255, 255]
+        );
+        assert_eq!(padded(PadFill::default()).get_pixel(0, 0).0, [0; 4]);
+        assert!(matches!(
+            PadFill::from_name("plaid"),
+            Err(ResizeError::InvalidFill(_))
+        ));
+    }
+
+    #[test]
+    fn no_upscale_keeps_small_sources() {
+        let plan = |mode, no_upscale| {
+            let geometry = geometry(200, 100, mode, 400, 400, no_upscale).unwrap();
+            (sizes(geometry), geometry.upscale_prevented)
+        };
+        assert_eq!(
+            plan(ResizeMode::Fit, false),
+            ((400, 200, 400, 200, 0, 0), false)
+        );
+        assert_eq!(
+            plan(ResizeMode::Fit, true),
+            ((200, 100, 200, 100, 0, 0), true)
+        );
+        assert_eq!(
+            plan(ResizeMode::LongEdge, true),
+            ((200, 100, 200, 100, 0, 0), true)
+        );
+        assert_eq!(
+            plan(ResizeMode::Contain, true),
+            ((200, 100, 400, 400, 100, 150), true)
+        );
+        // Only the overflowing side is cropped.
+        assert_eq!(
+            sizes(geometry(200, 100, ResizeMode::Cover, 150, 150, true).unwrap()),
+            (200, 100, 150, 100, -25, 0)
+        );
+        // Fill shrinks the side that's too large and leaves the other alone.
+        assert_eq!(
+            sizes(geometry(200, 100, ResizeMode::Fill, 150, 150, true).unwrap()),
+            (150, 100, 150, 100, 0, 0)
+        );
+
+        // Shrinking is unaffected.
+        let shrink = geometry(200, 100, ResizeMode::Fit, 100, 100, true).unwrap();
+        assert!(!shrink.upscale_prevented);
+        assert_eq!(sizes(shrink), (100, 50, 100, 50, 0, 0));
+
+        let img = RgbaImage::from_pixel(20, 10, Rgba([255, 0, 0, 255]));
+        let options = ResizeOptions {
+            no_upscale: true,
+            ..ResizeOptions::default()
+        };
+        let kept = resize_image(&img, ResizeMode::Fit, 100, 100, options).unwrap();
+        assert_eq!(kept, img);
+    }
+
+    #[test]
+    fn halving_averages_blocks_by_alpha() {
+        let mut img = RgbaImage::from_pixel(3, 2, Rgba([0, 0, 0, 0]));
+        img.put_pixel(0, 0, Rgba([200, 100, 0, 255]));
+        img.put_pixel(1, 1, Rgba([100, 100, 100, 255]));
+        img.put_pixel(2, 0, Rgba([10, 20, 30, 255]));
+        let half = halve(&img);
+        assert_eq!(half.dimensions(), (2, 1));
+        // Transparent pixels don't pull the color toward black.
+        assert_eq!(half.get_pixel(0, 0).0, [150, 100, 50, 128]);
+        // The odd last column averages with itself.
+        assert_eq!(half.get_pixel(1, 0).0, [10, 20, 30, 128]);
+    }
+
+    #[test]
+    fn progressive_downscaling_smooths_fine_detail() {
+        // One-pixel stripes, shrunk 16x, should become an even gray.
+        let img = RgbaImage::from_fn(256, 256, |x, _| {
+            let v = if x.is_multiple_of(2) { 0 } else { 255 };
+            Rgba([v, v, v, 255])
+        });
+        let options = ResizeOptions::default();
+        let small = resize_image(&img, ResizeMode::Fit, 16, 16, options).unwrap();
+        assert_eq!(small.dimensions(), (16, 16));
+        assert!(small.pixels().all(|p| p.0[0].abs_diff(128) <= 2));
+
+        // Small reductions are a single pass either way.
+        let single = ResizeOptions {
+            progressive: false,
+            ..options
+        };
+        assert_eq!(
+            resize_image(&img, ResizeMode::Fit, 100, 100, options).unwrap(),
+            resize_image(&img, ResizeMode::Fit, 100, 100, single).unwrap()
+        );
+    }
+
+    #[test]
+    fn large_downscales_decode_reduced_at_the_full_geometry() {
+        // A 304x200 JPEG covering 20x20 is decoded at 1/8 (38x25), yet sized from 304x200.
+        let img = RgbaImage::from_fn(304, 200, |x, y| {
+            Rgba([
+                u8::try_from(x % 256).unwrap(),
+                u8::try_from(y).unwrap(),
+                90,
+                255,
+            ])
+        });
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(img.clone())
+            .into_rgb8()
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = jpeg.into_inner();
+        assert!(region::decode_at_least(&jpeg, 30, 20).unwrap().is_some());
+
+        let options = ResizeOptions::default();
+        for (mode, width, height) in [
+            (ResizeMode::Cover, 20, 20),
+            (ResizeMode::Fit, 33, 33),
+            (ResizeMode::Contain, 30, 30),
+        ] {
+            let png = resize(&jpeg, mode, width, height, options, ImageFormat::Png, None).unwrap();
+            let resized = image::load_from_memory(&png).unwrap();
+            let full = resize_image(&img, mode, width, height, options).unwrap();
+            assert_eq!((resized.width(), resized.height()), full.dimensions());
+        }
+    }
+}
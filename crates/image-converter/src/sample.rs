@@ -0,0 +1,362 @@
+//! Reading a few colors out of an image without handing its whole RGBA buffer to JS,
+//! for eyedropper tools and background-color detection.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use image::RgbaImage;
+use serde::Serialize;
+
+use crate::color;
+use crate::region::{self, RegionError};
+use crate::smart_crop::CropBox;
+
+/// Border pixels within this [`color::distance`] (and alpha difference) of a detected
+/// background count toward its confidence, which absorbs JPEG noise and gentle
+/// vignetting.
+const BACKGROUND_TOLERANCE: f64 = 24.0;
+
+/// One sampled pixel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PixelSample {
+    pub x: u32,
+    pub y: u32,
+    pub rgba: [u8; 4],
+}
+
+/// Colors sampled from a rectangle by [`sample_region`].
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RegionSamples {
+    /// The sampled pixels, row by row.
+    pub samples: Vec<PixelSample>,
+    /// The per-channel mean of the samples.
+    pub mean: [u8; 4],
+    /// The color sampled most often; ties go to the one sampled first.
+    pub most_common: [u8; 4],
+    /// The share of samples that are exactly `most_common`, from 0.0 to 1.0. Near 1.0
+    /// on a flat background.
+    pub most_common_share: f64,
+}
+
+/// An image's background color, detected from its border by [`background`].
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct Background {
+    pub rgba: [u8; 4],
+    /// The share of border pixels close to `rgba`, from 0.0 to 1.0: around 0.9 or more
+    /// for a plain backdrop, low for a photo that runs off the edges.
+    pub confidence: f64,
+}
+
+/// The color of the pixel at (`x`, `y`).
+///
+/// # Errors
+///
+/// Returns `SampleError::Region` if the pixel is outside the image or the input
+/// cannot be decoded.
+pub fn get_pixel(input: &[u8], x: u32, y: u32) -> Result<[u8; 4], SampleError> {
+    let region = region::decode_region(input, x, y, 1, 1).map_err(SampleError::Region)?;
+    pixel(&region.rgba, 0).ok_or(SampleError::NoSamples)
+}
+
+/// Samples at most `max_samples` pixels of `rect` on an even grid, every pixel when
+/// the rectangle has no more than that. The grid keeps the rectangle's aspect ratio
+/// and samples the center of each cell.
+///
+/// Only the rectangle is decoded for tiled and striped TIFFs; other formats are
+/// decoded in full, as for [`region::decode_region`].
+///
+/// # Errors
+///
+/// Returns `SampleError::NoSamples` if `max_samples` is zero, or
+/// `SampleError::Region` if the rectangle is empty or extends past the image, or the
+/// input cannot be decoded.
+pub fn sample_region(
+    input: &[u8],
+    rect: CropBox,
+    max_samples: u32,
+) -> Result<RegionSamples, SampleError> {
+    if max_samples == 0 {
+        return Err(SampleError::NoSamples);
+    }
+    let region = region::decode_region(input, rect.x, rect.y, rect.width, rect.height)
+        .map_err(SampleError::Region)?;
+    let (width, height) = (u64::from(rect.width), u64::from(rect.height));
+    let max_samples = u64::from(max_samples);
+    let (columns, rows) = if width * height <= max_samples {
+        (width, height)
+    } else {
+        let columns = (max_samples * width / height).isqrt().clamp(1, width);
+        (columns, (max_samples / columns).clamp(1, height))
+    };
+
+    // The center of cell `index` of `cells` spanning `len` pixels.
+    let center = |index: u64, cells: u64, len: u64| (2 * index + 1) * len / (2 * cells);
+    let mut samples = Vec::new();
+    for row in 0..rows {
+        let y = center(row, rows, height);
+        for column in 0..columns {
+            let x = center(column, columns, width);
+            let offset = usize::try_from(y * width + x).unwrap_or(usize::MAX);
+            let (Some(rgba), Ok(x), Ok(y)) = (
+                pixel(&region.rgba, offset),
+                u32::try_from(x),
+                u32::try_from(y),
+            ) else {
+                continue;
+            };
+            samples.push(PixelSample {
+                x: rect.x + x,
+                y: rect.y + y,
+                rgba,
+            });
+        }
+    }
+    summarize(samples).ok_or(SampleError::NoSamples)
+}
+
+/// Detects the background of `img` from the pixels along its four edges: the mean of
+/// the most common group of similar colors there (fully transparent pixels form one
+/// group), and how much of the border it accounts for. `None` for empty images.
+pub fn background(img: &RgbaImage) -> Option<Background> {
+    let (width, height) = img.dimensions();
+    let rows = [0, height.saturating_sub(1)];
+    let columns = [0, width.saturating_sub(1)];
+    let mut border: Vec<[u8; 4]> = Vec::new();
+    for y in if height > 1 { &rows[..] } else { &rows[..1] } {
+        border.extend(
+            (0..width)
+                .filter_map(|x| img.get_pixel_checked(x, *y))
+                .map(|p| p.0),
+        );
+    }
+    for x in if width > 1 {
+        &columns[..]
+    } else {
+        &columns[..1]
+    } {
+        border.extend(
+            (1..height.saturating_sub(1))
+                .filter_map(|y| img.get_pixel_checked(*x, y))
+                .map(|p| p.0),
+        );
+    }
+    let total = u32::try_from(border.len()).ok().filter(|&n| n > 0)?;
+
+    // Group colors by their top four bits per channel.
+    let group = |rgba: [u8; 4]| {
+        if rgba[3] == 0 {
+            [0; 4]
+        } else {
+            rgba.map(|c| c >> 4)
+        }
+    };
+    let mut groups: HashMap<[u8; 4], (u32, [u64; 4])> = HashMap::new();
+    for &rgba in &border {
+        let (count, sums) = groups.entry(group(rgba)).or_default();
+        *count += 1;
+        for (sum, channel) in sums.iter_mut().zip(rgba) {
+            *sum += u64::from(channel);
+        }
+    }
+    let (count, sums) = groups
+        .into_values()
+        .max_by_key(|&(count, sums)| (count, sums))?;
+    let seed = mean(sums, count);
+
+    // The group's edges are arbitrary, so the result is the mean of every border pixel
+    // close to the group's mean rather than of the group alone.
+    let close: Vec<[u8; 4]> = border
+        .into_iter()
+        .filter(|&pixel| {
+            (seed[3] == 0 && pixel[3] == 0)
+                || (color::distance(seed, pixel) <= BACKGROUND_TOLERANCE
+                    && f64::from(seed[3].abs_diff(pixel[3])) <= BACKGROUND_TOLERANCE)
+        })
+        .collect();
+    let mut sums = [0u64; 4];
+    for pixel in &close {
+        for (sum, channel) in sums.iter_mut().zip(pixel) {
+            *sum += u64::from(*channel);
+        }
+    }
+    let close = u32::try_from(close.len()).unwrap_or(total);
+    Some(Background {
+        rgba: if close == 0 { seed } else { mean(sums, close) },
+        confidence: f64::from(close) / f64::from(total),
+    })
+}
+
+/// Per-channel means of `count` colors whose channels sum to `sums`.
+fn mean(sums: [u64; 4], count: u32) -> [u8; 4] {
+    sums.map(|sum| u8::try_from(sum / u64::from(count.max(1))).unwrap_or(u8::MAX))
+}
+
+/// Decodes `input` and detects its background with [`background`], e.g. to pick the
+/// color that letterboxes it.
+///
+/// # Errors
+///
+/// Returns `SampleError::Decode` if the input cannot be decoded.
+pub fn detect_background(input: &[u8]) -> Result<Background, SampleError> {
+    let img = image::load_from_memory(input)
+        .map_err(SampleError::Decode)?
+        .into_rgba8();
+    background(&img).ok_or(SampleError::NoSamples)
+}
+
+/// The RGBA pixel at `index` of a row-major RGBA8 buffer.
+fn pixel(rgba: &[u8], index: usize) -> Option<[u8; 4]> {
+    let start = index.checked_mul(4)?;
+    rgba.get(start..start.checked_add(4)?)?.try_into().ok()
+}
+
+fn summarize(samples: Vec<PixelSample>) -> Option<RegionSamples> {
+    let count = u32::try_from(samples.len()).ok().filter(|&n| n > 0)?;
+    let mut sums = [0u64; 4];
+    let mut counts: HashMap<[u8; 4], u32> = HashMap::new();
+    for sample in &samples {
+        for (sum, &channel) in sums.iter_mut().zip(&sample.rgba) {
+            *sum += u64::from(channel);
+        }
+        *counts.entry(sample.rgba).or_default() += 1;
+    }
+    let mean = mean(sums, count);
+    let (most_common, times) = samples
+        .iter()
+        .map(|sample| (sample.rgba, counts.get(&sample.rgba).copied().unwrap_or(0)))
+        .rev()
+        .max_by_key(|&(_, times)| times)?;
+    Some(RegionSamples {
+        samples,
+        mean,
+        most_common,
+        most_common_share: f64::from(times) / f64::from(count),
+    })
+}
+
+/// Errors that can occur while sampling pixels.
+#[derive(Debug)]
+pub enum SampleError {
+    /// `max_samples` was zero, or the image was empty.
+    NoSamples,
+    /// The pixel or rectangle is outside the image, or the image can't be decoded.
+    Region(RegionError),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+}
+
+impl fmt::Display for SampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoSamples => write!(f, "At least one sample is required"),
+            Self::Region(e) => write!(f, "{e}"),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for SampleError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    use super::*;
+
+    /// A white PNG with a red square at (10, 10)-(14, 14).
+    fn png() -> Vec<u8> {
+        let img = RgbaImage::from_fn(40, 20, |x, y| {
+            if (10..15).contains(&x) && (10..15).contains(&y) {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([255; 4])
+            }
+        });
+        let mut out = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn reads_single_pixels() {
+        let png = png();
+        assert_eq!(get_pixel(&png, 12, 12).unwrap(), [255, 0, 0, 255]);
+        assert_eq!(get_pixel(&png, 0, 0).unwrap(), [255; 4]);
+        assert!(matches!(
+            get_pixel(&png, 40, 0),
+            Err(SampleError::Region(RegionError::OutOfBounds { .. }))
+        ));
+    }
+
+    #[test]
+    fn samples_an_even_grid() {
+        let png = png();
+        let all = CropBox {
+            x: 0,
+            y: 0,
+            width: 40,
+            height: 20,
+        };
+        let sampled = sample_region(&png, all, 50).unwrap();
+        // 10 columns of 5 rows keep the 2:1 aspect ratio.
+        assert_eq!(sampled.samples.len(), 50);
+        assert_eq!(
+            (sampled.samples[0].x, sampled.samples[0].y),
+            (2, 2),
+            "cells are sampled at their centers"
+        );
+        assert_eq!(sampled.most_common, [255; 4]);
+        assert!(sampled.most_common_share > 0.9 && sampled.most_common_share < 1.0);
+
+        let square = CropBox {
+            x: 10,
+            y: 10,
+            width: 5,
+            height: 5,
+        };
+        let sampled = sample_region(&png, square, 100).unwrap();
+        assert_eq!(sampled.samples.len(), 25);
+        assert_eq!(sampled.samples[24].x, 14);
+        assert_eq!(
+            (sampled.mean, sampled.most_common_share),
+            ([255, 0, 0, 255], 1.0)
+        );
+        assert!(matches!(
+            sample_region(&png, square, 0),
+            Err(SampleError::NoSamples)
+        ));
+    }
+
+    #[test]
+    fn detects_the_border_color() {
+        // A product shot: an off-white backdrop with slight noise around a dark object.
+        let img = RgbaImage::from_fn(30, 20, |x, y| {
+            if (8..22).contains(&x) && (5..15).contains(&y) {
+                Rgba([20, 30, 40, 255])
+            } else {
+                let noise = u8::try_from((x + y) % 3).unwrap();
+                Rgba([240 + noise, 238 + noise, 235, 255])
+            }
+        });
+        let found = background(&img).unwrap();
+        assert!(color::distance(found.rgba, [241, 239, 235, 255]) < 2.0);
+        assert_eq!(found.confidence, 1.0);
+
+        // The object touching one edge lowers the confidence.
+        let mut touching = img.clone();
+        for y in 0..20 {
+            touching.put_pixel(0, y, Rgba([20, 30, 40, 255]));
+        }
+        let found = background(&touching).unwrap();
+        assert!(found.confidence < 0.85 && found.confidence > 0.7);
+
+        let transparent =
+            RgbaImage::from_fn(4, 4, |x, y| Rgba([9, 9, 9, u8::from(x == 2 && y == 2)]));
+        assert_eq!(background(&transparent).unwrap().rgba[3], 0);
+        assert_eq!(background(&RgbaImage::new(0, 0)), None);
+    }
+}
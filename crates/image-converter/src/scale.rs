@@ -0,0 +1,714 @@
+use std::fmt;
+
+use image::{DynamicImage, RgbaImage};
+
+use crate::color;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::MAX_SIDE;
+use crate::region;
+
+/// Largest pixel-art upscale factor.
+pub const MAX_UPSCALE_FACTOR: u32 = 8;
+
+/// How each source pixel is expanded when upscaling pixel art.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PixelArtFilter {
+    /// Every pixel becomes a solid `factor × factor` block.
+    #[default]
+    Nearest,
+    /// Scale2x/Scale3x (the EPX family): diagonal edges are rounded off using only
+    /// colors already in the image, so palettes and hard edges survive. Factors 4, 6
+    /// and 8 chain 2× and 3× passes.
+    ScaleX,
+}
+
+impl PixelArtFilter {
+    /// Parses a pixel-art filter name.
+    ///
+    /// Accepts `"nearest"` or `"scalex"` (also `"scale2x"`, `"epx"`). An empty string
+    /// selects `"nearest"`.
+    ///
+    /// Returns an error if the string is not a recognized filter.
+    pub fn from_name(name: &str) -> Result<Self, ScaleError> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "" | "nearest" => Ok(Self::Nearest),
+            "scalex" | "scale2x" | "epx" => Ok(Self::ScaleX),
+            _ => Err(ScaleError::UnknownFilter(name.to_owned())),
+        }
+    }
+}
+
+/// Decodes `input`, upscales it by a whole-number `factor` without blending colors,
+/// and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns a `ScaleError` if the factor is unsupported, the result would be too large,
+/// or decoding or encoding fails.
+pub fn upscale(
+    input: &[u8],
+    factor: u32,
+    filter: PixelArtFilter,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, ScaleError> {
+    let img = image::load_from_memory(input)
+        .map_err(ScaleError::Decode)?
+        .into_rgba8();
+    let scaled = upscale_pixel_art(&img, factor, filter)?;
+    convert::encode(&DynamicImage::ImageRgba8(scaled), target, quality).map_err(ScaleError::Convert)
+}
+
+/// Upscales an image by a whole-number `factor` (1 to [`MAX_UPSCALE_FACTOR`]) so pixel
+/// art stays crisp. Output only ever contains colors from the input.
+///
+/// # Errors
+///
+/// Returns `ScaleError::InvalidFactor` if the factor is out of range, or (for
+/// [`PixelArtFilter::ScaleX`]) not a product of 2s and 3s, and
+/// `ScaleError::TooLarge` if a side of the output would exceed [`MAX_SIDE`].
+pub fn upscale_pixel_art(
+    img: &RgbaImage,
+    factor: u32,
+    filter: PixelArtFilter,
+) -> Result<RgbaImage, ScaleError> {
+    if factor == 0 || factor > MAX_UPSCALE_FACTOR {
+        return Err(ScaleError::InvalidFactor(factor));
+    }
+    let fits = |side: u32| {
+        side.checked_mul(factor)
+            .is_some_and(|side| side <= MAX_SIDE)
+    };
+    if !fits(img.width()) || !fits(img.height()) {
+        return Err(ScaleError::TooLarge);
+    }
+
+    match filter {
+        PixelArtFilter::Nearest => Ok(nearest(img, factor)),
+        PixelArtFilter::ScaleX => {
+            let mut remaining = factor;
+            let mut out = img.clone();
+            while remaining > 1 {
+                if remaining.is_multiple_of(2) {
+                    out = scale2x(&out);
+                    remaining /= 2;
+                } else if remaining.is_multiple_of(3) {
+                    out = scale3x(&out);
+                    remaining /= 3;
+                } else {
+                    return Err(ScaleError::InvalidFactor(factor));
+                }
+            }
+            Ok(out)
+        }
+    }
+}
+
+fn nearest(img: &RgbaImage, factor: u32) -> RgbaImage {
+    RgbaImage::from_fn(img.width() * factor, img.height() * factor, |x, y| {
+        *img.get_pixel(x / factor, y / factor)
+    })
+}
+
+/// Reads pixels with coordinates clamped to the image, so edges repeat outward.
+struct Neighbourhood<'a> {
+    img: &'a RgbaImage,
+    x: u32,
+    y: u32,
+}
+
+impl Neighbourhood<'_> {
+    /// The pixel at offset `(dx, dy)` from the centre, each -1, 0 or 1.
+    fn at(&self, dx: i8, dy: i8) -> [u8; 4] {
+        let shift = |value: u32, delta: i8, len: u32| match delta {
+            -1 => value.saturating_sub(1),
+            1 => (value + 1).min(len - 1),
+            _ => value,
+        };
+        let x = shift(self.x, dx, self.img.width());
+        let y = shift(self.y, dy, self.img.height());
+        self.img.get_pixel(x, y).0
+    }
+}
+
+/// Scale2x: each pixel `e` becomes a 2×2 block, with a corner taking the color of
+/// the two neighbours it touches when they match and the edge isn't a straight line.
+fn scale2x(img: &RgbaImage) -> RgbaImage {
+    let mut out = RgbaImage::new(img.width() * 2, img.height() * 2);
+    for (x, y, px) in img.enumerate_pixels() {
+        let n = Neighbourhood { img, x, y };
+        let (b, d, f, h) = (n.at(0, -1), n.at(-1, 0), n.at(1, 0), n.at(0, 1));
+        let e = px.0;
+        let block = if b != h && d != f {
+            [
+                if d == b { d } else { e },
+                if b == f { f } else { e },
+                if d == h { d } else { e },
+                if h == f { f } else { e },
+            ]
+        } else {
+            [e; 4]
+        };
+        for ((dx, dy), color) in [(0, 0), (1, 0), (0, 1), (1, 1)].into_iter().zip(block) {
+            out.put_pixel(x * 2 + dx, y * 2 + dy, image::Rgba(color));
+        }
+    }
+    out
+}
+
+/// Scale3x: the 3×3 counterpart of [`scale2x`], which also decides the edge-centre
+/// pixels from the diagonal neighbours.
+fn scale3x(img: &RgbaImage) -> RgbaImage {
+    let mut out = RgbaImage::new(img.width() * 3, img.height() * 3);
+    for (x, y, px) in img.enumerate_pixels() {
+        let n = Neighbourhood { img, x, y };
+        let (a, b, c) = (n.at(-1, -1), n.at(0, -1), n.at(1, -1));
+        let (d, f) = (n.at(-1, 0), n.at(1, 0));
+        let (g, h, i) = (n.at(-1, 1), n.at(0, 1), n.at(1, 1));
+        let e = px.0;
+        let block = if b != h && d != f {
+            [
+                if d == b { d } else { e },
+                if (d == b && e != c) || (b == f && e != a) {
+                    b
+                } else {
+                    e
+                },
+                if b == f { f } else { e },
+                if (d == b && e != g) || (d == h && e != a) {
+                    d
+                } else {
+                    e
+                },
+                e,
+                if (b == f && e != i) || (h == f && e != c) {
+                    f
+                } else {
+                    e
+                },
+                if d == h { d } else { e },
+                if (d == h && e != i) || (h == f && e != g) {
+                    h
+                } else {
+                    e
+                },
+                if h == f { f } else { e },
+            ]
+        } else {
+            [e; 9]
+        };
+        for (offset, color) in (0..9u32).zip(block) {
+            out.put_pixel(x * 3 + offset % 3, y * 3 + offset / 3, image::Rgba(color));
+        }
+    }
+    out
+}
+
+/// Strength of the unsharp mask applied after icon downscaling. Enough to restore
+/// edges the box filter softens without visible halos.
+const ICON_SHARPEN: f64 = 0.35;
+
+/// Decodes `input`, shrinks it so its longer side is `size` pixels with
+/// [`downscale_icon`], and encodes the result as `target`.
+///
+/// Formats that support it are decoded at reduced resolution with
+/// [`region::decode_at_least`], never smaller than the icon, so a large photo's
+/// full-size pixels aren't held in memory.
+///
+/// # Errors
+///
+/// Returns a `ScaleError` if `size` is invalid or decoding or encoding fails.
+pub fn to_icon(
+    input: &[u8],
+    size: u32,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, ScaleError> {
+    let (width, height) = region::dimensions(input).map_err(ScaleError::Decode)?;
+    let (out_width, out_height) = icon_size(width, height, size)?;
+    let img = match region::try_decode_at_least(input, out_width, out_height) {
+        Some(reduced) => reduced,
+        None => image::load_from_memory(input)
+            .map_err(ScaleError::Decode)?
+            .into_rgba8(),
+    };
+    let icon = shrink_icon(&img, out_width, out_height);
+    convert::encode(&DynamicImage::ImageRgba8(icon), target, quality).map_err(ScaleError::Convert)
+}
+
+/// Shrinks artwork so its longer side is `size` pixels, keeping the aspect ratio,
+/// for small icons (typically 16–64 px).
+///
+/// Every output pixel is the exact area average of the source pixels it covers,
+/// taken in linear light with premultiplied alpha so thin strokes keep their weight
+/// and transparent edges don't pick up dark fringes. A light unsharp mask then
+/// restores the edge contrast the averaging softens.
+///
+/// # Errors
+///
+/// Returns `ScaleError::InvalidSize` if `size` is 0 or larger than the longer side.
+pub fn downscale_icon(img: &RgbaImage, size: u32) -> Result<RgbaImage, ScaleError> {
+    let (width, height) = img.dimensions();
+    let (out_width, out_height) = icon_size(width, height, size)?;
+    Ok(shrink_icon(img, out_width, out_height))
+}
+
+/// The size of an icon whose longer side is `size` pixels, for a `width` x `height`
+/// source.
+fn icon_size(width: u32, height: u32, size: u32) -> Result<(u32, u32), ScaleError> {
+    let longer = width.max(height);
+    if size == 0 || size > longer {
+        return Err(ScaleError::InvalidSize(size));
+    }
+    let fit = |side: u32| {
+        let scaled =
+            (u64::from(side) * u64::from(size) + u64::from(longer) / 2) / u64::from(longer);
+        u32::try_from(scaled).unwrap_or(size).max(1)
+    };
+    Ok((fit(width), fit(height)))
+}
+
+/// Area-averages `img` down to `width` x `height` and sharpens the result, as
+/// [`downscale_icon`] describes.
+fn shrink_icon(img: &RgbaImage, width: u32, height: u32) -> RgbaImage {
+    let linear = Plane::from_rgba(img);
+    let averaged = linear.shrink(width, height);
+    averaged.sharpen(ICON_SHARPEN).to_rgba()
+}
+
+/// Premultiplied linear-light RGBA samples, row-major.
+struct Plane {
+    width: u32,
+    height: u32,
+    samples: Vec<[f64; 4]>,
+}
+
+impl Plane {
+    fn from_rgba(img: &RgbaImage) -> Self {
+        let samples = img
+            .pixels()
+            .map(|p| {
+                let [r, g, b, a] = p.0;
+                let alpha = f64::from(a) / 255.0;
+                [
+                    color::srgb_to_linear(r) * alpha,
+                    color::srgb_to_linear(g) * alpha,
+                    color::srgb_to_linear(b) * alpha,
+                    alpha,
+                ]
+            })
+            .collect();
+        Self {
+            width: img.width(),
+            height: img.height(),
+            samples,
+        }
+    }
+
+    fn to_rgba(&self) -> RgbaImage {
+        RgbaImage::from_fn(self.width, self.height, |x, y| {
+            let [r, g, b, a] = self.get(x, y);
+            let alpha = a.clamp(0.0, 1.0);
+            let channel = |value: f64| {
+                if alpha > 0.0 {
+                    color::linear_to_srgb(value.clamp(0.0, alpha) / alpha)
+                } else {
+                    0
+                }
+            };
+            image::Rgba([
+                channel(r),
+                channel(g),
+                channel(b),
+                color::to_u8(alpha * 255.0),
+            ])
+        })
+    }
+
+    fn get(&self, x: u32, y: u32) -> [f64; 4] {
+        usize::try_from(u64::from(y) * u64::from(self.width) + u64::from(x))
+            .ok()
+            .and_then(|i| self.samples.get(i))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    /// Area-averages down to `width × height`, one axis at a time.
+    fn shrink(&self, width: u32, height: u32) -> Self {
+        let columns = coverage(self.width, width);
+        let horizontal = Self::build(width, self.height, |x, y| {
+            weighted_sum(axis_weights(&columns, x), |j| self.get(j, y))
+        });
+        let rows = coverage(self.height, height);
+        Self::build(width, height, |x, y| {
+            weighted_sum(axis_weights(&rows, y), |j| horizontal.get(x, j))
+        })
+    }
+
+    /// Unsharp mask against a 3×3 box blur, with edges clamped.
+    fn sharpen(&self, amount: f64) -> Self {
+        Self::build(self.width, self.height, |x, y| {
+            let mut blur = [0.0; 4];
+            for ny in [y.saturating_sub(1), y, (y + 1).min(self.height - 1)] {
+                for nx in [x.saturating_sub(1), x, (x + 1).min(self.width - 1)] {
+                    for (sum, value) in blur.iter_mut().zip(self.get(nx, ny)) {
+                        *sum += value / 9.0;
+                    }
+                }
+            }
+            let mut out = self.get(x, y);
+            for (value, blurred) in out.iter_mut().zip(blur) {
+                *value += amount * (*value - blurred);
+            }
+            out
+        })
+    }
+
+    fn build(width: u32, height: u32, sample: impl Fn(u32, u32) -> [f64; 4]) -> Self {
+        let samples = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| sample(x, y))
+            .collect();
+        Self {
+            width,
+            height,
+            samples,
+        }
+    }
+}
+
+/// For each of `dst` output positions along an axis of `src` pixels, the source
+/// indices it overlaps and the fraction of the output pixel each one covers.
+///
+/// Both are laid over a common length of `src * dst` units so overlaps are exact
+/// integers.
+fn coverage(src: u32, dst: u32) -> Vec<Vec<(u32, f64)>> {
+    let (src64, dst64) = (u64::from(src), u64::from(dst));
+    (0..dst64)
+        .map(|i| {
+            let (start, end) = (i * src64, (i + 1) * src64);
+            (start / dst64..end.div_ceil(dst64))
+                .filter_map(|j| {
+                    let overlap = end.min((j + 1) * dst64) - start.max(j * dst64);
+                    let index = u32::try_from(j).ok()?;
+                    Some((index, overlap_fraction(overlap, src64)))
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn axis_weights(coverage: &[Vec<(u32, f64)>], position: u32) -> &[(u32, f64)] {
+    usize::try_from(position)
+        .ok()
+        .and_then(|i| coverage.get(i))
+        .map_or(&[], Vec::as_slice)
+}
+
+fn overlap_fraction(overlap: u64, span: u64) -> f64 {
+    // Both are bounded by an image side, so they fit in u32.
+    let to_f64 = |value: u64| f64::from(u32::try_from(value).unwrap_or(u32::MAX));
+    to_f64(overlap) / to_f64(span)
+}
+
+fn weighted_sum(weights: &[(u32, f64)], sample: impl Fn(u32) -> [f64; 4]) -> [f64; 4] {
+    let mut out = [0.0; 4];
+    for &(index, weight) in weights {
+        for (sum, value) in out.iter_mut().zip(sample(index)) {
+            *sum += value * weight;
+        }
+    }
+    out
+}
+
+/// Errors that can occur while scaling images.
+#[derive(Debug)]
+pub enum ScaleError {
+    /// The filter name was not recognized.
+    UnknownFilter(String),
+    /// The upscale factor was out of range or unsupported by the filter.
+    InvalidFactor(u32),
+    /// A side of the scaled image would exceed [`MAX_SIDE`].
+    TooLarge,
+    /// The icon size was 0 or larger than the source.
+    InvalidSize(u32),
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for ScaleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnknownFilter(name) => write!(
+                f,
+                "Unknown pixel-art filter \"{name}\" (expected nearest or scalex)"
+            ),
+            Self::InvalidFactor(factor) => write!(
+                f,
+                "Unsupported upscale factor {factor} (nearest allows 1 to {MAX_UPSCALE_FACTOR}, scalex allows 1, 2, 3, 4, 6 or 8)"
+            ),
+            Self::TooLarge => write!(
+                f,
+                "The scaled image would be larger than {MAX_SIDE}x{MAX_SIDE}"
+            ),
+            Self::InvalidSize(size) => write!(
+                f,
+                "Icon size must be between 1 and the source's longer side, got {size}"
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ScaleError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::io::Cursor;
+
+    use image::Rgba;
+
+    use super::*;
+
+    const BLACK: Rgba<u8> = Rgba([0, 0, 0, 255]);
+    const WHITE: Rgba<u8> = Rgba([255; 4]);
+
+    /// A one-pixel diagonal line from top-left to bottom-right on white.
+    fn diagonal(size: u32) -> RgbaImage {
+        RgbaImage::from_fn(size, size, |x, y| if x == y { BLACK } else { WHITE })
+    }
+
+    fn colors(img: &RgbaImage) -> HashSet<[u8; 4]> {
+        img.pixels().map(|p| p.0).collect()
+    }
+
+    #[test]
+    fn nearest_repeats_each_pixel() {
+        let img = RgbaImage::from_fn(2, 1, |x, _| if x == 0 { BLACK } else { WHITE });
+        let out = upscale_pixel_art(&img, 3, PixelArtFilter::Nearest).unwrap();
+        assert_eq!(out.dimensions(), (6, 3));
+        for (x, _, px) in out.enumerate_pixels() {
+            assert_eq!(*px, if x < 3 { BLACK } else { WHITE });
+        }
+    }
+
+    #[test]
+    fn scale2x_rounds_diagonals() {
+        let out = upscale_pixel_art(&diagonal(4), 2, PixelArtFilter::ScaleX).unwrap();
+        assert_eq!(out.dimensions(), (8, 8));
+        // Nearest would leave a staircase; Scale2x fills the inner corners between
+        // the black blocks.
+        let nearest = upscale_pixel_art(&diagonal(4), 2, PixelArtFilter::Nearest).unwrap();
+        for (x, y) in [(2, 1), (1, 2), (4, 3), (3, 4)] {
+            assert_eq!(*nearest.get_pixel(x, y), WHITE);
+            assert_eq!(*out.get_pixel(x, y), BLACK, "({x}, {y})");
+        }
+        // Far from the line nothing changes.
+        assert_eq!(*out.get_pixel(7, 0), WHITE);
+        assert_eq!(colors(&out), colors(&diagonal(4)));
+    }
+
+    #[test]
+    fn scalex_keeps_flat_areas_and_palette() {
+        let img = RgbaImage::from_fn(5, 5, |_, y| {
+            if y < 2 {
+                Rgba([200, 40, 40, 255])
+            } else {
+                Rgba([20, 20, 90, 128])
+            }
+        });
+        for factor in [2, 3, 4, 6, 8] {
+            let out = upscale_pixel_art(&img, factor, PixelArtFilter::ScaleX).unwrap();
+            assert_eq!(out.dimensions(), (5 * factor, 5 * factor), "{factor}");
+            assert_eq!(colors(&out), colors(&img), "{factor}");
+            // Straight bands have no corners to round, so the result matches nearest.
+            assert_eq!(
+                out,
+                upscale_pixel_art(&img, factor, PixelArtFilter::Nearest).unwrap(),
+                "{factor}"
+            );
+        }
+    }
+
+    #[test]
+    fn scale3x_rounds_diagonals() {
+        let out = upscale_pixel_art(&diagonal(4), 3, PixelArtFilter::ScaleX).unwrap();
+        assert_eq!(out.dimensions(), (12, 12));
+        for (x, y) in [(3, 1), (3, 2), (1, 3), (2, 3)] {
+            assert_eq!(*out.get_pixel(x, y), BLACK, "({x}, {y})");
+        }
+        assert_eq!(*out.get_pixel(11, 0), WHITE);
+    }
+
+    #[test]
+    fn rejects_outputs_over_max_side() {
+        let wide = RgbaImage::new(MAX_SIDE / 8 + 1, 1);
+        for filter in [PixelArtFilter::Nearest, PixelArtFilter::ScaleX] {
+            assert!(matches!(
+                upscale_pixel_art(&wide, 8, filter),
+                Err(ScaleError::TooLarge)
+            ));
+        }
+        let tall = RgbaImage::new(1, MAX_SIDE / 2);
+        assert_eq!(
+            upscale_pixel_art(&tall, 2, PixelArtFilter::Nearest)
+                .unwrap()
+                .dimensions(),
+            (2, MAX_SIDE)
+        );
+    }
+
+    #[test]
+    fn rejects_unsupported_factors() {
+        let img = diagonal(2);
+        for (factor, filter) in [
+            (0, PixelArtFilter::Nearest),
+            (9, PixelArtFilter::Nearest),
+            (5, PixelArtFilter::ScaleX),
+            (7, PixelArtFilter::ScaleX),
+        ] {
+            assert!(
+                matches!(
+                    upscale_pixel_art(&img, factor, filter),
+                    Err(ScaleError::InvalidFactor(f)) if f == factor
+                ),
+                "{factor} {filter:?}"
+            );
+        }
+        assert!(upscale_pixel_art(&img, 7, PixelArtFilter::Nearest).is_ok());
+        assert_eq!(
+            upscale_pixel_art(&img, 1, PixelArtFilter::ScaleX).unwrap(),
+            img
+        );
+    }
+
+    #[test]
+    fn upscale_encodes_target() {
+        let mut input = Vec::new();
+        DynamicImage::ImageRgba8(diagonal(3))
+            .write_to(&mut Cursor::new(&mut input), image::ImageFormat::Png)
+            .unwrap();
+        let png = upscale(&input, 2, PixelArtFilter::ScaleX, ImageFormat::Png, None).unwrap();
+        let decoded = image::load_from_memory(&png).unwrap().into_rgba8();
+        assert_eq!(decoded.dimensions(), (6, 6));
+
+        assert_eq!(
+            PixelArtFilter::from_name(" Scale2x").unwrap(),
+            PixelArtFilter::ScaleX
+        );
+        assert!(matches!(
+            PixelArtFilter::from_name("lanczos"),
+            Err(ScaleError::UnknownFilter(_))
+        ));
+    }
+
+    #[test]
+    fn icon_keeps_flat_color() {
+        let img = RgbaImage::from_pixel(64, 64, Rgba([30, 140, 200, 255]));
+        let icon = downscale_icon(&img, 16).unwrap();
+        assert_eq!(icon.dimensions(), (16, 16));
+        assert!(icon.pixels().all(|p| *p == Rgba([30, 140, 200, 255])));
+    }
+
+    #[test]
+    fn icon_averages_in_linear_light() {
+        let img = RgbaImage::from_fn(64, 64, |x, y| if (x + y) % 2 == 0 { BLACK } else { WHITE });
+        let icon = downscale_icon(&img, 32).unwrap();
+        // Half the light of white is sRGB 188, not 128.
+        assert!(
+            icon.pixels().all(|p| p.0[0].abs_diff(188) <= 1),
+            "{:?}",
+            icon.get_pixel(0, 0)
+        );
+    }
+
+    #[test]
+    fn icon_keeps_aspect_ratio() {
+        let img = RgbaImage::from_pixel(100, 50, WHITE);
+        assert_eq!(downscale_icon(&img, 20).unwrap().dimensions(), (20, 10));
+        let img = RgbaImage::from_pixel(3, 300, WHITE);
+        assert_eq!(downscale_icon(&img, 16).unwrap().dimensions(), (1, 16));
+    }
+
+    #[test]
+    fn icon_edges_have_no_dark_fringe() {
+        // Transparent pixels hide black; premultiplied averaging must ignore it.
+        let img = RgbaImage::from_fn(9, 9, |x, _| {
+            if x < 4 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        });
+        let icon = downscale_icon(&img, 2).unwrap();
+        let [r, g, b, a] = icon.get_pixel(0, 0).0;
+        assert_eq!([r, g, b], [255, 0, 0]);
+        assert!(a > 0 && a < 255, "{a}");
+    }
+
+    #[test]
+    fn icon_sharpens_edges() {
+        let dark = Rgba([64, 64, 64, 255]);
+        let light = Rgba([192, 192, 192, 255]);
+        let img = RgbaImage::from_fn(8, 8, |x, _| if x < 4 { dark } else { light });
+        let icon = downscale_icon(&img, 4).unwrap();
+        assert_eq!(*icon.get_pixel(0, 0), dark);
+        assert!(icon.get_pixel(1, 0).0[0] < 64);
+        assert!(icon.get_pixel(2, 0).0[0] > 192);
+        assert_eq!(*icon.get_pixel(3, 0), light);
+    }
+
+    #[test]
+    fn icon_rejects_bad_sizes() {
+        let img = RgbaImage::from_pixel(10, 4, WHITE);
+        for size in [0, 11] {
+            assert!(matches!(
+                downscale_icon(&img, size),
+                Err(ScaleError::InvalidSize(s)) if s == size
+            ));
+        }
+        assert_eq!(downscale_icon(&img, 10).unwrap().dimensions(), (10, 4));
+    }
+
+    #[test]
+    fn icons_of_large_jpegs_decode_reduced() {
+        // A 400x240 JPEG shrunk to a 32 px icon only needs its 1/8-scale decode.
+        let img = RgbaImage::from_fn(400, 240, |x, y| {
+            Rgba([
+                u8::try_from(x * 255 / 399).unwrap(),
+                u8::try_from(y * 255 / 239).unwrap(),
+                128,
+                255,
+            ])
+        });
+        let mut jpeg = std::io::Cursor::new(Vec::new());
+        DynamicImage::ImageRgba8(img.clone())
+            .into_rgb8()
+            .write_to(&mut jpeg, image::ImageFormat::Jpeg)
+            .unwrap();
+        let jpeg = jpeg.into_inner();
+        assert!(region::decode_at_least(&jpeg, 32, 19).unwrap().is_some());
+
+        let png = to_icon(&jpeg, 32, ImageFormat::Png, None).unwrap();
+        let icon = image::load_from_memory(&png).unwrap().into_rgba8();
+        // Sized from the full image, and close to shrinking the full decode.
+        assert_eq!(icon.dimensions(), (32, 19));
+        let full = image::load_from_memory(&jpeg).unwrap().into_rgba8();
+        let expected = downscale_icon(&full, 32).unwrap();
+        for (a, b) in icon.pixels().zip(expected.pixels()) {
+            for (a, b) in a.0.iter().zip(b.0) {
+                assert!(a.abs_diff(b) <= 12, "{a} vs {b}");
+            }
+        }
+
+        assert!(matches!(
+            to_icon(&jpeg, 401, ImageFormat::Png, None),
+            Err(ScaleError::InvalidSize(401))
+        ));
+    }
+}
@@ -0,0 +1,144 @@
+use std::fmt;
+
+use image::DynamicImage;
+
+use crate::adjust::Adjustment;
+use crate::bilevel::{self, BilevelError};
+use crate::canvas;
+use crate::convert::{self, ConvertError};
+use crate::deskew::{self, DeskewError};
+use crate::formats::ImageFormat;
+use crate::quantize::Dither;
+
+/// Default per-channel tolerance for trimming the border left around the page.
+pub const DEFAULT_TRIM_TOLERANCE: u8 = 24;
+
+/// Settings for [`scan_cleanup`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanOptions {
+    /// Reduce the page to 1-bit black and white, for text-only documents.
+    pub binarize: bool,
+    /// Per-channel tolerance for the uniform border that is trimmed after leveling.
+    pub trim_tolerance: u8,
+}
+
+impl Default for ScanOptions {
+    fn default() -> Self {
+        Self {
+            binarize: false,
+            trim_tolerance: DEFAULT_TRIM_TOLERANCE,
+        }
+    }
+}
+
+/// Decodes a phone photo of a page and cleans it up to look scanned: levels it with
+/// [`deskew::deskew_image`], trims the uniform border with [`canvas::trim`], balances
+/// the paper to neutral white, stretches contrast, and optionally reduces it to black
+/// and white. The result is always a PNG (1-bit when binarized).
+///
+/// # Errors
+///
+/// Returns a `ScanError` if the input cannot be decoded, is too large to level, or the
+/// output cannot be encoded.
+pub fn scan_cleanup(input: &[u8], options: &ScanOptions) -> Result<Vec<u8>, ScanError> {
+    let img = image::load_from_memory(input)
+        .map_err(ScanError::Decode)?
+        .into_rgba8();
+    let (leveled, _) = deskew::deskew_image(&img).map_err(ScanError::Deskew)?;
+    let (mut page, _) = canvas::trim(&leveled, options.trim_tolerance);
+    Adjustment::WhiteBalance.apply(&mut page);
+    Adjustment::ContrastStretch.apply(&mut page);
+
+    if options.binarize {
+        let bits = bilevel::bilevel(&page, Dither::None, bilevel::DEFAULT_THRESHOLD);
+        bilevel::encode_png(&bits).map_err(ScanError::Bilevel)
+    } else {
+        convert::encode(&DynamicImage::ImageRgba8(page), ImageFormat::Png, None)
+            .map_err(ScanError::Convert)
+    }
+}
+
+/// Errors that can occur while cleaning up a document photo.
+#[derive(Debug)]
+pub enum ScanError {
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// The page could not be leveled.
+    Deskew(DeskewError),
+    /// Failed to encode the 1-bit output.
+    Bilevel(BilevelError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for ScanError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Deskew(e) => write!(f, "{e}"),
+            Self::Bilevel(e) => write!(f, "{e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for ScanError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+
+    /// A dim, warm-tinted page with two dark text lines, on a uniform gray desk.
+    fn photo() -> Vec<u8> {
+        let img = RgbaImage::from_fn(60, 40, |x, y| {
+            let on_page = (10..50).contains(&x) && (8..32).contains(&y);
+            let ink = on_page && (14..46).contains(&x) && (y == 14 || y == 22);
+            Rgba(match (on_page, ink) {
+                (_, true) => [40, 35, 30, 255],
+                (true, false) => [200, 180, 140, 255],
+                (false, _) => [90, 90, 90, 255],
+            })
+        });
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(img)
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        png
+    }
+
+    #[test]
+    fn cleans_up_a_page() {
+        let out = scan_cleanup(&photo(), &ScanOptions::default()).unwrap();
+        let page = image::load_from_memory(&out).unwrap().into_rgba8();
+        assert_eq!(page.dimensions(), (40, 24));
+        // The paper comes out white and the text black.
+        assert_eq!(page.get_pixel(1, 1).0, [255; 4]);
+        let [r, g, b, _] = page.get_pixel(10, 6).0;
+        assert!(r < 20 && g < 20 && b < 20, "{:?}", [r, g, b]);
+    }
+
+    #[test]
+    fn binarized_output_is_black_and_white() {
+        let options = ScanOptions {
+            binarize: true,
+            ..ScanOptions::default()
+        };
+        let out = scan_cleanup(&photo(), &options).unwrap();
+        let page = image::load_from_memory(&out).unwrap().into_luma8();
+        assert_eq!(page.dimensions(), (40, 24));
+        assert!(page.pixels().all(|p| p.0 == [0] || p.0 == [255]));
+        assert_eq!(page.get_pixel(10, 6).0, [0]);
+    }
+
+    #[test]
+    fn rejects_undecodable_input() {
+        assert!(matches!(
+            scan_cleanup(&[1, 2, 3], &ScanOptions::default()),
+            Err(ScanError::Decode(_))
+        ));
+    }
+}
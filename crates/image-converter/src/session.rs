@@ -0,0 +1,515 @@
+//! A converter session that keeps decoded images, gradient map lookup tables, parsed
+//! palettes and spare pixel buffers between calls, so generating several variants of
+//! one image decodes it once.
+//!
+//! Fonts aren't cached here: [`crate::fonts`] keeps registered fonts parsed for every
+//! caller, session or not.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use image::{DynamicImage, RgbaImage};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::color::{self, ColorError};
+use crate::convert::{self, ConvertError};
+use crate::effects::{self, EffectError, GradientMap};
+use crate::formats::ImageFormat;
+use crate::quantize::{self, Dither, QuantizeError};
+use crate::resize::{self, ResizeError, ResizeMode, ResizeOptions};
+
+/// Default limit on the decoded pixels a session keeps: 256 MiB, about eight 12 MP
+/// photos.
+pub const DEFAULT_MAX_IMAGE_BYTES: u64 = 256 * 1024 * 1024;
+
+/// Most spare pixel buffers a session keeps for reuse.
+pub const MAX_POOLED_BUFFERS: usize = 4;
+
+/// A decoded image and when it was last used.
+#[derive(Debug)]
+struct CachedImage {
+    image: Rc<RgbaImage>,
+    bytes: u64,
+    last_used: u64,
+}
+
+/// What a session is holding on to.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct SessionStats {
+    pub images: u32,
+    /// Decoded pixels held by the cached images.
+    pub image_bytes: u64,
+    pub max_image_bytes: u64,
+    pub luts: u32,
+    pub palettes: u32,
+    pub pooled_buffers: u32,
+    /// Capacity of the pooled buffers.
+    pub pooled_bytes: u64,
+}
+
+/// Caches shared by a run of operations on the same inputs.
+///
+/// Images are keyed by the SHA-256 of their encoded bytes and decoded to RGBA8, as the
+/// stateless operations do. The least recently used images are evicted once their
+/// decoded pixels would exceed the session's limit; an image larger than the limit on
+/// its own is decoded for the call and not kept. Gradient maps and palettes are keyed
+/// by the text they were parsed from and kept until cleared. Pixel buffers freed by an
+/// operation are pooled for the next one's working copy.
+#[derive(Debug)]
+pub struct Session {
+    images: HashMap<[u8; 32], CachedImage>,
+    luts: HashMap<String, GradientMap>,
+    palettes: HashMap<String, Vec<[u8; 4]>>,
+    buffers: Vec<Vec<u8>>,
+    max_image_bytes: u64,
+    /// Incremented on every image lookup, to order images by last use.
+    clock: u64,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::with_max_image_bytes(DEFAULT_MAX_IMAGE_BYTES)
+    }
+}
+
+impl Session {
+    /// An empty session keeping up to [`DEFAULT_MAX_IMAGE_BYTES`] of decoded images.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An empty session keeping up to `max_image_bytes` of decoded images. Zero keeps
+    /// none, leaving only the lookup table, palette and buffer caches.
+    pub fn with_max_image_bytes(max_image_bytes: u64) -> Self {
+        Self {
+            images: HashMap::new(),
+            luts: HashMap::new(),
+            palettes: HashMap::new(),
+            buffers: Vec::new(),
+            max_image_bytes,
+            clock: 0,
+        }
+    }
+
+    /// Encodes `input` as `target`, like [`convert::encode`] on its decoded pixels.
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SessionError` if the input cannot be decoded or the output cannot be
+    /// encoded.
+    pub fn encode(
+        &mut self,
+        input: &[u8],
+        target: ImageFormat,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, SessionError> {
+        let img = self.decode(input)?;
+        let copy = self.working_copy(&img);
+        self.encode_and_recycle(copy, target, quality)
+    }
+
+    /// Resizes `input` like [`resize::resize`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SessionError` if the size is invalid, the input cannot be decoded, or
+    /// the output cannot be encoded.
+    // Mirrors `resize::resize`, with the session in front.
+    #[allow(clippy::too_many_arguments)]
+    pub fn resize(
+        &mut self,
+        input: &[u8],
+        mode: ResizeMode,
+        width: u32,
+        height: u32,
+        options: ResizeOptions,
+        target: ImageFormat,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, SessionError> {
+        let img = self.decode(input)?;
+        let resized = resize::resize_image(&img, mode, width, height, options)
+            .map_err(SessionError::Resize)?;
+        self.encode_and_recycle(resized, target, quality)
+    }
+
+    /// Applies a gradient map given as color stops (see [`effects::parse_stops`]),
+    /// like [`effects::apply_gradient_map`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SessionError` if the stops are invalid, the input cannot be decoded,
+    /// or the output cannot be encoded.
+    pub fn gradient_map(
+        &mut self,
+        input: &[u8],
+        stops: &str,
+        target: ImageFormat,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, SessionError> {
+        let key = stops.trim();
+        if !self.luts.contains_key(key) {
+            let map = effects::parse_stops(key)
+                .and_then(|stops| GradientMap::new(&stops))
+                .map_err(SessionError::Effect)?;
+            self.luts.insert(key.to_owned(), map);
+        }
+        let img = self.decode(input)?;
+        let mut copy = self.working_copy(&img);
+        if let Some(map) = self.luts.get(key) {
+            map.apply(&mut copy);
+        }
+        self.encode_and_recycle(copy, target, quality)
+    }
+
+    /// Constrains `input` to a palette given as hex colors (see
+    /// [`color::parse_palette`]), like [`crate::palette::apply_palette`].
+    ///
+    /// # Errors
+    ///
+    /// Returns a `SessionError` if the palette is invalid, the input cannot be decoded,
+    /// or the output cannot be encoded.
+    pub fn apply_palette(
+        &mut self,
+        input: &[u8],
+        palette: &str,
+        dither: Dither,
+        target: ImageFormat,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, SessionError> {
+        let key = palette.trim();
+        if !self.palettes.contains_key(key) {
+            let colors = color::parse_palette(key).map_err(SessionError::Color)?;
+            self.palettes.insert(key.to_owned(), colors);
+        }
+        let img = self.decode(input)?;
+        let colors = self.palettes.get(key).map_or(&[][..], Vec::as_slice);
+        let indexed =
+            quantize::remap_to_palette(&img, colors, dither).map_err(SessionError::Quantize)?;
+
+        if target == ImageFormat::Png {
+            return indexed.to_png().map_err(SessionError::Quantize);
+        }
+        self.encode_and_recycle(indexed.to_rgba(), target, quality)
+    }
+
+    /// Drops the decoded copy of `input`, if the session holds one. Returns whether it
+    /// did.
+    pub fn evict_image(&mut self, input: &[u8]) -> bool {
+        self.images.remove(&image_key(input)).is_some()
+    }
+
+    /// Drops every decoded image.
+    pub fn clear_images(&mut self) {
+        self.images.clear();
+    }
+
+    /// Drops every gradient map lookup table.
+    pub fn clear_luts(&mut self) {
+        self.luts.clear();
+    }
+
+    /// Drops every parsed palette.
+    pub fn clear_palettes(&mut self) {
+        self.palettes.clear();
+    }
+
+    /// Frees the pooled pixel buffers.
+    pub fn clear_buffers(&mut self) {
+        self.buffers.clear();
+    }
+
+    /// Drops everything the session holds, as if it were new. The image limit stays.
+    pub fn clear(&mut self) {
+        self.clear_images();
+        self.clear_luts();
+        self.clear_palettes();
+        self.clear_buffers();
+    }
+
+    /// Changes the limit on decoded images, evicting the least recently used ones
+    /// until the rest fit.
+    pub fn set_max_image_bytes(&mut self, max_image_bytes: u64) {
+        self.max_image_bytes = max_image_bytes;
+        self.evict_to(max_image_bytes);
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        let count = |len: usize| u32::try_from(len).unwrap_or(u32::MAX);
+        SessionStats {
+            images: count(self.images.len()),
+            image_bytes: self.image_bytes(),
+            max_image_bytes: self.max_image_bytes,
+            luts: count(self.luts.len()),
+            palettes: count(self.palettes.len()),
+            pooled_buffers: count(self.buffers.len()),
+            pooled_bytes: self
+                .buffers
+                .iter()
+                .map(|buffer| u64::try_from(buffer.capacity()).unwrap_or(u64::MAX))
+                .fold(0, u64::saturating_add),
+        }
+    }
+
+    /// The decoded pixels of `input`, from the cache or freshly decoded and cached.
+    fn decode(&mut self, input: &[u8]) -> Result<Rc<RgbaImage>, SessionError> {
+        self.clock += 1;
+        let key = image_key(input);
+        if let Some(cached) = self.images.get_mut(&key) {
+            cached.last_used = self.clock;
+            return Ok(Rc::clone(&cached.image));
+        }
+
+        let image = Rc::new(
+            image::load_from_memory(input)
+                .map_err(SessionError::Decode)?
+                .into_rgba8(),
+        );
+        let bytes = u64::try_from(image.as_raw().len()).unwrap_or(u64::MAX);
+        if bytes <= self.max_image_bytes {
+            self.evict_to(self.max_image_bytes - bytes);
+            self.images.insert(
+                key,
+                CachedImage {
+                    image: Rc::clone(&image),
+                    bytes,
+                    last_used: self.clock,
+                },
+            );
+        }
+        Ok(image)
+    }
+
+    /// Evicts the least recently used images until the rest take at most `budget`.
+    fn evict_to(&mut self, budget: u64) {
+        while self.image_bytes() > budget {
+            let oldest = self
+                .images
+                .iter()
+                .min_by_key(|(_, cached)| cached.last_used)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => self.images.remove(&key),
+                None => break,
+            };
+        }
+    }
+
+    fn image_bytes(&self) -> u64 {
+        self.images
+            .values()
+            .map(|cached| cached.bytes)
+            .fold(0, u64::saturating_add)
+    }
+
+    /// A copy of `img` the caller may modify, in a pooled buffer when one is big
+    /// enough.
+    fn working_copy(&mut self, img: &RgbaImage) -> RgbaImage {
+        let pixels = img.as_raw();
+        // The smallest pooled buffer that fits, so big buffers stay for big images.
+        let pooled = self
+            .buffers
+            .iter()
+            .enumerate()
+            .filter(|(_, buffer)| buffer.capacity() >= pixels.len())
+            .min_by_key(|(_, buffer)| buffer.capacity())
+            .map(|(index, _)| index);
+        let mut buffer = match pooled {
+            Some(index) => self.buffers.swap_remove(index),
+            None => Vec::with_capacity(pixels.len()),
+        };
+        buffer.clear();
+        buffer.extend_from_slice(pixels);
+        RgbaImage::from_raw(img.width(), img.height(), buffer).unwrap_or_else(|| img.clone())
+    }
+
+    /// Encodes `img` and returns its buffer to the pool.
+    fn encode_and_recycle(
+        &mut self,
+        img: RgbaImage,
+        target: ImageFormat,
+        quality: Option<u8>,
+    ) -> Result<Vec<u8>, SessionError> {
+        let img = DynamicImage::ImageRgba8(img);
+        let encoded = convert::encode(&img, target, quality).map_err(SessionError::Convert);
+        self.recycle(img.into_rgba8().into_raw());
+        encoded
+    }
+
+    /// Keeps `buffer` for a later working copy, replacing the smallest pooled buffer
+    /// once the pool is full.
+    fn recycle(&mut self, buffer: Vec<u8>) {
+        if self.buffers.len() < MAX_POOLED_BUFFERS {
+            self.buffers.push(buffer);
+            return;
+        }
+        if let Some(smallest) = self
+            .buffers
+            .iter_mut()
+            .min_by_key(|pooled| pooled.capacity())
+            .filter(|pooled| pooled.capacity() < buffer.capacity())
+        {
+            *smallest = buffer;
+        }
+    }
+}
+
+/// Cache key of an encoded image.
+fn image_key(input: &[u8]) -> [u8; 32] {
+    Sha256::digest(input).into()
+}
+
+/// Errors that can occur in a session operation.
+#[derive(Debug)]
+pub enum SessionError {
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// The resize was invalid.
+    Resize(ResizeError),
+    /// The gradient stops were invalid.
+    Effect(EffectError),
+    /// The palette could not be parsed.
+    Color(ColorError),
+    /// The palette was invalid or the indexed PNG could not be written.
+    Quantize(QuantizeError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for SessionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Resize(e) => write!(f, "{e}"),
+            Self::Effect(e) => write!(f, "{e}"),
+            Self::Color(e) => write!(f, "{e}"),
+            Self::Quantize(e) => write!(f, "{e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SessionError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::Rgba;
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let img = RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([
+                u8::try_from(x * 17 % 256).unwrap(),
+                u8::try_from(y * 29 % 256).unwrap(),
+                90,
+                255,
+            ])
+        });
+        let mut out = Vec::new();
+        img.write_to(&mut Cursor::new(&mut out), image::ImageFormat::Png)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn variants_match_the_stateless_operations() {
+        let input = png(24, 16);
+        let mut session = Session::new();
+
+        let resized = session
+            .resize(
+                &input,
+                ResizeMode::Fit,
+                12,
+                12,
+                ResizeOptions::default(),
+                ImageFormat::Png,
+                None,
+            )
+            .unwrap();
+        let expected = resize::resize(
+            &input,
+            ResizeMode::Fit,
+            12,
+            12,
+            ResizeOptions::default(),
+            ImageFormat::Png,
+            None,
+        )
+        .unwrap();
+        assert_eq!(resized, expected);
+
+        let stops = "#000 0%, #f80 40%, #fff 100%";
+        let mapped = session
+            .gradient_map(&input, stops, ImageFormat::Png, None)
+            .unwrap();
+        let map = GradientMap::new(&effects::parse_stops(stops).unwrap()).unwrap();
+        let expected = effects::apply_gradient_map(&input, &map, ImageFormat::Png, None).unwrap();
+        assert_eq!(mapped, expected);
+
+        let palette = "#000, #fff, #f00";
+        let remapped = session
+            .apply_palette(&input, palette, Dither::Ordered, ImageFormat::Png, None)
+            .unwrap();
+        let colors = color::parse_palette(palette).unwrap();
+        let expected =
+            crate::palette::apply_palette(&input, &colors, Dither::Ordered, ImageFormat::Png, None)
+                .unwrap();
+        assert_eq!(remapped, expected);
+
+        let stats = session.stats();
+        assert_eq!((stats.images, stats.luts, stats.palettes), (1, 1, 1));
+        assert_eq!(stats.image_bytes, 24 * 16 * 4);
+        assert!(stats.pooled_buffers >= 1);
+    }
+
+    #[test]
+    fn least_recently_used_images_are_evicted() {
+        let (a, b, c) = (png(8, 8), png(9, 8), png(10, 8));
+        // Room for two of the three.
+        let mut session = Session::with_max_image_bytes(8 * 19 * 4);
+        session.encode(&a, ImageFormat::Bmp, None).unwrap();
+        session.encode(&b, ImageFormat::Bmp, None).unwrap();
+        session.encode(&a, ImageFormat::Bmp, None).unwrap();
+        session.encode(&c, ImageFormat::Bmp, None).unwrap();
+
+        assert_eq!(session.stats().images, 2);
+        assert!(!session.evict_image(&b));
+        assert!(session.evict_image(&a));
+        assert!(session.evict_image(&c));
+
+        session.set_max_image_bytes(0);
+        session.encode(&a, ImageFormat::Bmp, None).unwrap();
+        assert_eq!(session.stats().images, 0);
+    }
+
+    #[test]
+    fn clear_drops_every_cache() {
+        let input = png(4, 4);
+        let mut session = Session::new();
+        session
+            .gradient_map(&input, "#000, #fff", ImageFormat::Png, None)
+            .unwrap();
+        session
+            .apply_palette(&input, "#000, #fff", Dither::None, ImageFormat::Bmp, None)
+            .unwrap();
+        assert_ne!(session.stats().pooled_buffers, 0);
+
+        session.clear();
+        assert_eq!(
+            session.stats(),
+            SessionStats {
+                max_image_bytes: DEFAULT_MAX_IMAGE_BYTES,
+                ..SessionStats::default()
+            }
+        );
+        assert!(matches!(
+            session.gradient_map(&input, "#000", ImageFormat::Png, None),
+            Err(SessionError::Effect(EffectError::TooFewStops))
+        ));
+    }
+}
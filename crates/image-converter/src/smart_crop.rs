@@ -0,0 +1,372 @@
+//! Content-aware cropping for avatars and thumbnails.
+//!
+//! Crops are scored on detail and on caller-supplied focus regions. In builds with the
+//! `faces` feature, `faces::smart_crop_faces` adds the faces a cascade finds to those
+//! regions; otherwise hosts that want faces kept in frame pass the boxes from their own
+//! detector.
+
+use std::fmt;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::color;
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+
+/// Longest side of the thumbnail that crops are scored on.
+const ANALYSIS_SIDE: u32 = 256;
+
+/// Weight of keeping every focus region in frame, against 1.0 for keeping all of the
+/// image's detail: large enough that a face outranks any amount of busy background.
+const FOCUS_WEIGHT: f64 = 4.0;
+
+/// A rectangle in image pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropBox {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropBox {
+    /// Pixels of `self` that fall inside `other`.
+    fn overlap(&self, other: &Self) -> u64 {
+        let span = |start: u32, len: u32, other_start: u32, other_len: u32| {
+            let end = u64::from(start) + u64::from(len);
+            let other_end = u64::from(other_start) + u64::from(other_len);
+            end.min(other_end)
+                .saturating_sub(u64::from(start.max(other_start)))
+        };
+        span(self.x, self.width, other.x, other.width)
+            * span(self.y, self.height, other.y, other.height)
+    }
+
+    fn area(&self) -> u64 {
+        u64::from(self.width) * u64::from(self.height)
+    }
+}
+
+/// Picks the largest `width`:`height` crop of `img` that keeps the most detail (edges
+/// and saturated color) and, above all, the `focus` regions such as detected faces.
+/// Ties go to the crop nearest the center.
+///
+/// # Errors
+///
+/// Returns `SmartCropError::InvalidSize` if `width` or `height` is zero, or the image
+/// is empty.
+pub fn choose_crop(
+    img: &RgbaImage,
+    width: u32,
+    height: u32,
+    focus: &[CropBox],
+) -> Result<CropBox, SmartCropError> {
+    let (img_width, img_height) = img.dimensions();
+    if width == 0 || height == 0 || img_width == 0 || img_height == 0 {
+        return Err(SmartCropError::InvalidSize { width, height });
+    }
+
+    // The largest crop with the target aspect ratio; it only slides along one axis.
+    let wide = u64::from(img_width) * u64::from(height) > u64::from(img_height) * u64::from(width);
+    let (crop_width, crop_height) = if wide {
+        let scaled = u64::from(img_height) * u64::from(width) / u64::from(height);
+        (
+            u32::try_from(scaled)
+                .unwrap_or(img_width)
+                .clamp(1, img_width),
+            img_height,
+        )
+    } else {
+        let scaled = u64::from(img_width) * u64::from(height) / u64::from(width);
+        (
+            img_width,
+            u32::try_from(scaled)
+                .unwrap_or(img_height)
+                .clamp(1, img_height),
+        )
+    };
+    let range = if wide {
+        img_width - crop_width
+    } else {
+        img_height - crop_height
+    };
+
+    let detail = Detail::new(img);
+    let focus_area: u64 = focus.iter().map(CropBox::area).sum();
+    let step = (range / ANALYSIS_SIDE).max(1);
+    let center = range / 2;
+    let mut best = (f64::MIN, u32::MAX, 0);
+    for offset in (0..=range)
+        .step_by(usize::try_from(step).unwrap_or(1))
+        .chain([center, range])
+    {
+        let candidate = if wide {
+            CropBox {
+                x: offset,
+                y: 0,
+                width: crop_width,
+                height: crop_height,
+            }
+        } else {
+            CropBox {
+                x: 0,
+                y: offset,
+                width: crop_width,
+                height: crop_height,
+            }
+        };
+        let kept: u64 = focus.iter().map(|region| region.overlap(&candidate)).sum();
+        let focus_score = if focus_area == 0 {
+            0.0
+        } else {
+            ratio(kept, focus_area)
+        };
+        let score = detail.share(&candidate) + FOCUS_WEIGHT * focus_score;
+        let distance = offset.abs_diff(center);
+        if score > best.0 + 1e-9 || ((score - best.0).abs() <= 1e-9 && distance < best.1) {
+            best = (score, distance, offset);
+        }
+    }
+
+    let offset = best.2;
+    Ok(if wide {
+        CropBox {
+            x: offset,
+            y: 0,
+            width: crop_width,
+            height: crop_height,
+        }
+    } else {
+        CropBox {
+            x: 0,
+            y: offset,
+            width: crop_width,
+            height: crop_height,
+        }
+    })
+}
+
+/// A summed-area table of per-pixel detail over a thumbnail of the image.
+struct Detail {
+    /// Thumbnail pixels per image pixel.
+    scale: f64,
+    width: usize,
+    /// `(width + 1) * (height + 1)` running sums, with a zero first row and column.
+    sums: Vec<f64>,
+}
+
+impl Detail {
+    fn new(img: &RgbaImage) -> Self {
+        let (img_width, img_height) = img.dimensions();
+        let longest = img_width.max(img_height);
+        let thumb = if longest > ANALYSIS_SIDE {
+            let side = |value: u32| {
+                u32::try_from(u64::from(value) * u64::from(ANALYSIS_SIDE) / u64::from(longest))
+                    .unwrap_or(ANALYSIS_SIDE)
+                    .max(1)
+            };
+            imageops::thumbnail(img, side(img_width), side(img_height))
+        } else {
+            img.clone()
+        };
+        let (width, height) = thumb.dimensions();
+        let luma = |x: u32, y: u32| {
+            thumb
+                .get_pixel_checked(x.min(width - 1), y.min(height - 1))
+                .map_or(0.0, |pixel| f64::from(color::luminance(pixel.0)))
+        };
+
+        let columns = usize::try_from(width).unwrap_or(0) + 1;
+        let mut sums = vec![0.0; columns];
+        for y in 0..height {
+            let mut row = 0.0;
+            sums.push(0.0);
+            for x in 0..width {
+                let pixel = thumb.get_pixel(x, y).0;
+                let [r, g, b, a] = pixel.map(f64::from);
+                let here = luma(x, y);
+                let edge = (luma(x + 1, y) - here).abs() + (luma(x, y + 1) - here).abs();
+                let saturation = r.max(g).max(b) - r.min(g).min(b);
+                row += (edge + saturation * 0.5) * a / 255.0;
+                let above = sums.len().checked_sub(columns).and_then(|i| sums.get(i));
+                sums.push(row + above.copied().unwrap_or(0.0));
+            }
+        }
+        Self {
+            scale: f64::from(width) / f64::from(img_width),
+            width: columns - 1,
+            sums,
+        }
+    }
+
+    fn sum_to(&self, x: usize, y: usize) -> f64 {
+        self.sums
+            .get(y * (self.width + 1) + x.min(self.width))
+            .copied()
+            .unwrap_or(0.0)
+    }
+
+    /// The share of all detail that falls inside `crop`, from 0 to 1.
+    fn share(&self, crop: &CropBox) -> f64 {
+        let rows = self.sums.len() / (self.width + 1) - 1;
+        let to_cell = |value: u32| to_index(f64::from(value) * self.scale);
+        let (x0, y0) = (to_cell(crop.x), to_cell(crop.y));
+        let x1 = to_cell(crop.x.saturating_add(crop.width)).min(self.width);
+        let y1 = to_cell(crop.y.saturating_add(crop.height)).min(rows);
+        let total = self.sum_to(self.width, rows);
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let inside =
+            self.sum_to(x1, y1) - self.sum_to(x0, y1) - self.sum_to(x1, y0) + self.sum_to(x0, y0);
+        inside / total
+    }
+}
+
+// Safe: the value is rounded and clamped to be non-negative, and thumbnail coordinates
+// are at most ANALYSIS_SIDE.
+#[allow(clippy::as_conversions)]
+fn to_index(value: f64) -> usize {
+    value.round().max(0.0) as usize
+}
+
+// Safe: pixel areas stay far below f64's 53-bit mantissa.
+#[allow(clippy::as_conversions)]
+fn ratio(part: u64, whole: u64) -> f64 {
+    part as f64 / whole as f64
+}
+
+/// Decodes `input`, crops it with [`choose_crop`], resizes the crop to `width` x
+/// `height`, and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns a `SmartCropError` if the size is zero, the input cannot be decoded, or the
+/// output cannot be encoded.
+pub fn smart_crop(
+    input: &[u8],
+    width: u32,
+    height: u32,
+    focus: &[CropBox],
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, SmartCropError> {
+    let img = image::load_from_memory(input)
+        .map_err(SmartCropError::Decode)?
+        .into_rgba8();
+    let resized = crop_and_resize(&img, width, height, focus)?;
+    convert::encode(&resized, target, quality).map_err(SmartCropError::Convert)
+}
+
+/// Crops `img` with [`choose_crop`] and resizes the crop to `width` x `height`.
+pub(crate) fn crop_and_resize(
+    img: &RgbaImage,
+    width: u32,
+    height: u32,
+    focus: &[CropBox],
+) -> Result<DynamicImage, SmartCropError> {
+    let crop = choose_crop(img, width, height, focus)?;
+    let cropped = imageops::crop_imm(img, crop.x, crop.y, crop.width, crop.height).to_image();
+    Ok(DynamicImage::ImageRgba8(imageops::resize(
+        &cropped,
+        width,
+        height,
+        FilterType::Lanczos3,
+    )))
+}
+
+/// Errors that can occur while smart cropping.
+#[derive(Debug)]
+pub enum SmartCropError {
+    /// The output size or the image was empty.
+    InvalidSize { width: u32, height: u32 },
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+    /// The face detection cascade couldn't be read.
+    #[cfg(feature = "faces")]
+    Faces(crate::faces::FaceError),
+}
+
+impl fmt::Display for SmartCropError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidSize { width, height } => write!(
+                f,
+                "Crop size must be at least 1x1 on a non-empty image, got {width}x{height}"
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+            #[cfg(feature = "faces")]
+            Self::Faces(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for SmartCropError {}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    /// A flat gray image with a busy checkered patch at `x`..`x + 20`.
+    fn busy_patch_at(x: u32) -> RgbaImage {
+        RgbaImage::from_fn(200, 100, |px, py| {
+            if (x..x + 20).contains(&px) && (40..60).contains(&py) && (px + py) % 2 == 0 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([128, 128, 128, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn crop_follows_the_detail() {
+        let crop = choose_crop(&busy_patch_at(10), 100, 100, &[]).unwrap();
+        assert_eq!((crop.width, crop.height, crop.y), (100, 100, 0));
+        assert!(crop.x <= 10, "{crop:?}");
+
+        let crop = choose_crop(&busy_patch_at(170), 100, 100, &[]).unwrap();
+        assert!(crop.x + crop.width >= 190, "{crop:?}");
+    }
+
+    #[test]
+    fn flat_images_crop_to_the_center() {
+        let img = RgbaImage::from_pixel(100, 300, Rgba([9; 4]));
+        let crop = choose_crop(&img, 50, 50, &[]).unwrap();
+        assert_eq!(
+            crop,
+            CropBox {
+                x: 0,
+                y: 100,
+                width: 100,
+                height: 100,
+            }
+        );
+    }
+
+    #[test]
+    fn focus_regions_outrank_detail() {
+        let face = CropBox {
+            x: 160,
+            y: 30,
+            width: 30,
+            height: 30,
+        };
+        let crop = choose_crop(&busy_patch_at(10), 100, 100, &[face]).unwrap();
+        assert_eq!(face.overlap(&crop), face.area(), "{crop:?}");
+    }
+
+    #[test]
+    fn rejects_empty_sizes() {
+        assert!(matches!(
+            choose_crop(&busy_patch_at(0), 0, 10, &[]),
+            Err(SmartCropError::InvalidSize { .. })
+        ));
+    }
+}
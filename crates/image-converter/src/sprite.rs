@@ -0,0 +1,394 @@
+use std::fmt;
+
+use image::{imageops, Delay, DynamicImage, Frame, RgbaImage};
+use serde::Serialize;
+
+use crate::animation::{self, AnimationError};
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::MAX_SIDE;
+
+/// A sprite sheet produced from an animation, plus the layout needed to play it back.
+#[derive(Debug, Clone)]
+pub struct SpriteSheet {
+    /// The encoded PNG sheet.
+    pub sheet: Vec<u8>,
+    /// Grid layout and per-frame timing.
+    pub metadata: SpriteSheetMetadata,
+}
+
+/// Layout and timing information for a sprite sheet.
+///
+/// Frames are laid out in row-major order starting at the top-left cell.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SpriteSheetMetadata {
+    pub frame_width: u32,
+    pub frame_height: u32,
+    pub frame_count: u32,
+    pub columns: u32,
+    pub rows: u32,
+    pub delays_ms: Vec<u32>,
+}
+
+/// Lays the frames of an animated image out in a grid and encodes the result as PNG.
+///
+/// `columns` is the maximum number of frames per row; it is clamped to the frame count
+/// so a short animation never produces empty columns. Unused cells in the last row are
+/// left transparent. Static images produce a one-cell sheet.
+///
+/// # Errors
+///
+/// Returns a `SpriteError` if `columns` is zero, the input cannot be decoded,
+/// the sheet would be larger than [`MAX_SIDE`] on a side, or PNG encoding fails.
+pub fn to_sprite_sheet(input: &[u8], columns: u32) -> Result<SpriteSheet, SpriteError> {
+    if columns == 0 {
+        return Err(SpriteError::InvalidColumns);
+    }
+
+    let frames = animation::decode_frames(input).map_err(SpriteError::Animation)?;
+    let frame_count = u32::try_from(frames.len()).map_err(|_| SpriteError::TooLarge)?;
+    let (frame_width, frame_height) = frames
+        .first()
+        .map(|frame| frame.buffer().dimensions())
+        .unwrap_or_default();
+
+    let columns = columns.min(frame_count).max(1);
+    let rows = frame_count.div_ceil(columns);
+    let side = |cells: u32, cell: u32| cells.checked_mul(cell).filter(|&side| side <= MAX_SIDE);
+    let sheet_width = side(columns, frame_width).ok_or(SpriteError::TooLarge)?;
+    let sheet_height = side(rows, frame_height).ok_or(SpriteError::TooLarge)?;
+
+    let mut sheet = RgbaImage::new(sheet_width, sheet_height);
+    let mut delays_ms = Vec::with_capacity(frames.len());
+    for (index, frame) in (0..frame_count).zip(frames) {
+        delays_ms.push(animation::frame_delay_ms(&frame));
+        let x = i64::from(index % columns) * i64::from(frame_width);
+        let y = i64::from(index / columns) * i64::from(frame_height);
+        imageops::replace(&mut sheet, frame.buffer(), x, y);
+    }
+
+    let encoded = convert::encode(&DynamicImage::ImageRgba8(sheet), ImageFormat::Png, None)
+        .map_err(SpriteError::Convert)?;
+
+    Ok(SpriteSheet {
+        sheet: encoded,
+        metadata: SpriteSheetMetadata {
+            frame_width,
+            frame_height,
+            frame_count,
+            columns,
+            rows,
+            delays_ms,
+        },
+    })
+}
+
+/// Cuts a sprite sheet into `tile_width` x `tile_height` tiles in row-major order.
+///
+/// Only complete tiles are returned; a partial row or column at the right or bottom
+/// edge is ignored. `frame_count` limits the number of tiles, which trims the empty
+/// trailing cells of a sheet produced by [`to_sprite_sheet`].
+///
+/// # Errors
+///
+/// Returns a `SpriteError` if a tile dimension is zero, the tile is larger than the
+/// sheet, or the input cannot be decoded.
+pub fn slice_sprite_sheet(
+    input: &[u8],
+    tile_width: u32,
+    tile_height: u32,
+    frame_count: Option<u32>,
+) -> Result<Vec<RgbaImage>, SpriteError> {
+    if tile_width == 0 || tile_height == 0 {
+        return Err(SpriteError::InvalidTileSize);
+    }
+
+    let sheet = image::load_from_memory(input)
+        .map_err(SpriteError::Decode)?
+        .into_rgba8();
+    let columns = sheet.width() / tile_width;
+    let rows = sheet.height() / tile_height;
+    if columns == 0 || rows == 0 {
+        return Err(SpriteError::TileExceedsSheet);
+    }
+
+    let available = columns.saturating_mul(rows);
+    let count = frame_count.map_or(available, |limit| limit.min(available));
+
+    Ok((0..count)
+        .map(|index| {
+            let x = (index % columns) * tile_width;
+            let y = (index / columns) * tile_height;
+            imageops::crop_imm(&sheet, x, y, tile_width, tile_height).to_image()
+        })
+        .collect())
+}
+
+/// Slices a sprite sheet and encodes each tile in the target format.
+///
+/// See [`slice_sprite_sheet`] for how tiles are selected.
+///
+/// # Errors
+///
+/// Returns a `SpriteError` if slicing fails or a tile cannot be encoded.
+pub fn slice_to_images(
+    input: &[u8],
+    tile_width: u32,
+    tile_height: u32,
+    frame_count: Option<u32>,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<Vec<u8>>, SpriteError> {
+    slice_sprite_sheet(input, tile_width, tile_height, frame_count)?
+        .into_iter()
+        .map(|tile| {
+            convert::encode(&DynamicImage::ImageRgba8(tile), target, quality)
+                .map_err(SpriteError::Convert)
+        })
+        .collect()
+}
+
+/// Slices a sprite sheet and assembles the tiles into a looping animated GIF.
+///
+/// Every frame is shown for `delay_ms` milliseconds.
+///
+/// # Errors
+///
+/// Returns a `SpriteError` if slicing fails or the GIF cannot be encoded.
+pub fn slice_to_gif(
+    input: &[u8],
+    tile_width: u32,
+    tile_height: u32,
+    frame_count: Option<u32>,
+    delay_ms: u32,
+) -> Result<Vec<u8>, SpriteError> {
+    let delay = Delay::from_numer_denom_ms(delay_ms, 1);
+    let frames = slice_sprite_sheet(input, tile_width, tile_height, frame_count)?
+        .into_iter()
+        .map(|tile| Frame::from_parts(tile, 0, 0, delay))
+        .collect();
+    animation::encode_gif(frames, None).map_err(SpriteError::Animation)
+}
+
+/// Errors that can occur while building or slicing sprite sheets.
+#[derive(Debug)]
+pub enum SpriteError {
+    /// Failed to read frames from the input.
+    Animation(AnimationError),
+    /// Failed to decode a sprite sheet.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+    /// The column count was zero.
+    InvalidColumns,
+    /// A tile dimension was zero.
+    InvalidTileSize,
+    /// The tile is wider or taller than the sheet, so no complete tile fits.
+    TileExceedsSheet,
+    /// A side of the sheet would exceed [`MAX_SIDE`].
+    TooLarge,
+}
+
+impl fmt::Display for SpriteError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Animation(e) => write!(f, "{e}"),
+            Self::Decode(e) => write!(f, "Failed to decode sprite sheet: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+            Self::InvalidColumns => write!(f, "Column count must be at least 1"),
+            Self::InvalidTileSize => write!(f, "Tile width and height must be at least 1"),
+            Self::TileExceedsSheet => write!(f, "Tile size is larger than the sprite sheet"),
+            Self::TooLarge => write!(
+                f,
+                "The sprite sheet would be larger than {MAX_SIDE}x{MAX_SIDE}"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for SpriteError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::codecs::gif::GifEncoder;
+    use image::Rgba;
+
+    use super::*;
+
+    // ===== Fixture Helpers =====
+
+    fn make_animated_gif(colors: &[[u8; 4]], delay_ms: u32) -> Vec<u8> {
+        let mut buf = Vec::new();
+        {
+            let mut encoder = GifEncoder::new(&mut buf);
+            encoder
+                .encode_frames(colors.iter().map(|c| {
+                    let img = RgbaImage::from_pixel(4, 3, Rgba(*c));
+                    Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+                }))
+                .unwrap();
+        }
+        buf
+    }
+
+    const RED: [u8; 4] = [255, 0, 0, 255];
+    const GREEN: [u8; 4] = [0, 255, 0, 255];
+    const BLUE: [u8; 4] = [0, 0, 255, 255];
+
+    // ===== to_sprite_sheet Tests =====
+
+    #[test]
+    fn sprite_sheet_layout() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE], 50);
+        let result = to_sprite_sheet(&gif, 2).unwrap();
+        assert_eq!(
+            result.metadata,
+            SpriteSheetMetadata {
+                frame_width: 4,
+                frame_height: 3,
+                frame_count: 3,
+                columns: 2,
+                rows: 2,
+                delays_ms: vec![50, 50, 50],
+            }
+        );
+
+        let sheet = image::load_from_memory(&result.sheet).unwrap().into_rgba8();
+        assert_eq!(sheet.dimensions(), (8, 6));
+        assert_eq!(*sheet.get_pixel(0, 0), Rgba(RED));
+        assert_eq!(*sheet.get_pixel(4, 0), Rgba(GREEN));
+        assert_eq!(*sheet.get_pixel(0, 3), Rgba(BLUE));
+        assert_eq!(
+            sheet.get_pixel(4, 3).0[3],
+            0,
+            "Unused cell should be transparent"
+        );
+    }
+
+    #[test]
+    fn sprite_sheet_columns_clamped_to_frame_count() {
+        let gif = make_animated_gif(&[RED, GREEN], 10);
+        let result = to_sprite_sheet(&gif, 10).unwrap();
+        assert_eq!(result.metadata.columns, 2);
+        assert_eq!(result.metadata.rows, 1);
+        let dims = convert::dimensions(&result.sheet).unwrap();
+        assert_eq!((dims.width, dims.height), (8, 3));
+    }
+
+    #[test]
+    fn sprite_sheet_static_input() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(5, 5))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let result = to_sprite_sheet(&png, 4).unwrap();
+        assert_eq!(result.metadata.frame_count, 1);
+        assert_eq!(result.metadata.delays_ms, vec![0]);
+    }
+
+    #[test]
+    fn sprite_sheet_larger_than_max_side() {
+        let mut gif = Vec::new();
+        GifEncoder::new(&mut gif)
+            .encode_frames((0..5).map(|_| {
+                let img = RgbaImage::from_pixel(4000, 1, Rgba(RED));
+                Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(10, 1))
+            }))
+            .unwrap();
+        // Five 4000-pixel frames side by side are wider than MAX_SIDE; stacked, they fit.
+        assert!(matches!(
+            to_sprite_sheet(&gif, 5),
+            Err(SpriteError::TooLarge)
+        ));
+        let stacked = to_sprite_sheet(&gif, 1).unwrap();
+        assert_eq!((stacked.metadata.columns, stacked.metadata.rows), (1, 5));
+    }
+
+    #[test]
+    fn sprite_sheet_zero_columns() {
+        let gif = make_animated_gif(&[RED], 10);
+        assert!(matches!(
+            to_sprite_sheet(&gif, 0),
+            Err(SpriteError::InvalidColumns)
+        ));
+    }
+
+    #[test]
+    fn sprite_sheet_invalid_input() {
+        assert!(matches!(
+            to_sprite_sheet(&[0xDE, 0xAD], 2),
+            Err(SpriteError::Animation(_))
+        ));
+    }
+
+    // ===== slice_sprite_sheet Tests =====
+
+    #[test]
+    fn slice_round_trip_with_sprite_sheet() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE], 50);
+        let sheet = to_sprite_sheet(&gif, 2).unwrap();
+        let meta = &sheet.metadata;
+        let tiles = slice_sprite_sheet(
+            &sheet.sheet,
+            meta.frame_width,
+            meta.frame_height,
+            Some(meta.frame_count),
+        )
+        .unwrap();
+        assert_eq!(tiles.len(), 3);
+        assert_eq!(*tiles[0].get_pixel(0, 0), Rgba(RED));
+        assert_eq!(*tiles[1].get_pixel(0, 0), Rgba(GREEN));
+        assert_eq!(*tiles[2].get_pixel(0, 0), Rgba(BLUE));
+    }
+
+    #[test]
+    fn slice_ignores_partial_tiles() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::new(10, 7))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let tiles = slice_sprite_sheet(&png, 4, 3, None).unwrap();
+        assert_eq!(tiles.len(), 4, "2 full columns x 2 full rows");
+        assert!(tiles.iter().all(|t| t.dimensions() == (4, 3)));
+    }
+
+    #[test]
+    fn slice_invalid_tile_sizes() {
+        let gif = make_animated_gif(&[RED], 10);
+        assert!(matches!(
+            slice_sprite_sheet(&gif, 0, 3, None),
+            Err(SpriteError::InvalidTileSize)
+        ));
+        assert!(matches!(
+            slice_sprite_sheet(&gif, 100, 3, None),
+            Err(SpriteError::TileExceedsSheet)
+        ));
+    }
+
+    #[test]
+    fn slice_to_images_encodes_each_tile() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE], 50);
+        let sheet = to_sprite_sheet(&gif, 3).unwrap();
+        let tiles = slice_to_images(&sheet.sheet, 4, 3, None, ImageFormat::Bmp, None).unwrap();
+        assert_eq!(tiles.len(), 3);
+        for tile in &tiles {
+            assert_eq!(
+                ImageFormat::detect_from_bytes(tile).unwrap(),
+                ImageFormat::Bmp
+            );
+        }
+    }
+
+    #[test]
+    fn slice_to_gif_builds_animation() {
+        let gif = make_animated_gif(&[RED, GREEN, BLUE], 50);
+        let sheet = to_sprite_sheet(&gif, 2).unwrap();
+        let animated = slice_to_gif(&sheet.sheet, 4, 3, Some(3), 120).unwrap();
+        let frames = animation::decode_frames(&animated).unwrap();
+        assert_eq!(frames.len(), 3);
+        assert_eq!(animation::frame_delay_ms(&frames[0]), 120);
+        assert_eq!(*frames[2].buffer().get_pixel(0, 0), Rgba(BLUE));
+    }
+}
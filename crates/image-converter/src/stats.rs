@@ -0,0 +1,489 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use image::DynamicImage;
+use serde::Serialize;
+
+use crate::color;
+
+/// Distinct colors are counted up to this many; beyond it only the cap is reported.
+pub const COLOR_COUNT_CAP: u32 = 1 << 16;
+
+/// Largest palette a PNG can hold.
+const MAX_PALETTE: u32 = 256;
+
+/// Luma at or above which a pixel counts as a clipped highlight.
+pub const HIGHLIGHT_CLIP: u8 = 250;
+
+/// Luma at or below which a pixel counts as a crushed shadow.
+pub const SHADOW_CRUSH: u8 = 5;
+
+/// What an image's pixels actually use, and the smallest PNG layout that stores them
+/// without changing any visible pixel.
+///
+/// Fully transparent pixels count as one color whatever their hidden RGB values are,
+/// matching indexed PNG output (see [`crate::quantize::exact_indexed_png`]).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ColorStats {
+    /// Distinct colors, counted up to [`COLOR_COUNT_CAP`].
+    pub unique_colors: u32,
+    /// Whether counting stopped at the cap, i.e. the image has at least that many.
+    pub colors_capped: bool,
+    /// Every pixel has equal red, green and blue.
+    pub grayscale: bool,
+    /// Every pixel is fully opaque.
+    pub opaque: bool,
+    /// Whether any sample needs 16 bits (a 16-bit source that isn't just 8-bit data
+    /// stored wide).
+    pub needs_16bit: bool,
+    /// Palette entries needed for an indexed PNG, or `None` if it can't be indexed
+    /// losslessly (more than 256 colors, or 16-bit samples).
+    pub palette_size: Option<u16>,
+    /// The smallest lossless PNG color type: `"gray"`, `"gray_alpha"`, `"indexed"`,
+    /// `"rgb"` or `"rgba"`.
+    pub color_type: &'static str,
+    /// PNG bit depth for `color_type` (for `"indexed"`, the bits per palette index).
+    pub bit_depth: u8,
+    /// Bits per pixel of that layout, ignoring the palette itself.
+    pub bits_per_pixel: u8,
+}
+
+/// Decodes `input` and reports its color usage.
+///
+/// # Errors
+///
+/// Returns `StatsError::Decode` if the input cannot be decoded.
+pub fn color_stats(input: &[u8]) -> Result<ColorStats, StatsError> {
+    let decoded = image::load_from_memory(input).map_err(StatsError::Decode)?;
+    Ok(analyze(&decoded))
+}
+
+/// Reports the color usage of a decoded image.
+pub fn analyze(img: &DynamicImage) -> ColorStats {
+    let color = img.color();
+    let wide = color.bits_per_pixel() / u16::from(color.channel_count()) > 8;
+    if wide {
+        let wide_img = img.to_rgba16();
+        if wide_img.as_raw().iter().any(|&v| v % 257 != 0) {
+            return Scan::new(wide_img.pixels().map(|p| p.0), u16::MAX).into_stats(true);
+        }
+    }
+    let narrow = img.to_rgba8();
+    Scan::new(
+        narrow.pixels().map(|p| p.0.map(u16::from)),
+        u16::from(u8::MAX),
+    )
+    .into_stats(false)
+}
+
+/// One pass over the pixels, with samples widened to `u16`; `max` is the opaque alpha.
+struct Scan {
+    unique_colors: u32,
+    colors_capped: bool,
+    grayscale: bool,
+    opaque: bool,
+    /// Distinct levels of visible gray pixels, used for the gray bit depth.
+    gray_levels: HashSet<u16>,
+}
+
+impl Scan {
+    fn new(pixels: impl Iterator<Item = [u16; 4]>, max: u16) -> Self {
+        let mut colors = HashSet::new();
+        let mut scan = Self {
+            unique_colors: 0,
+            colors_capped: false,
+            grayscale: true,
+            opaque: true,
+            gray_levels: HashSet::new(),
+        };
+        for [r, g, b, a] in pixels {
+            if !scan.colors_capped {
+                colors.insert(if a == 0 { [0; 4] } else { [r, g, b, a] });
+                scan.colors_capped =
+                    colors.len() >= usize::try_from(COLOR_COUNT_CAP).unwrap_or(usize::MAX);
+            }
+
+            scan.opaque &= a == max;
+            if a != 0 {
+                scan.grayscale &= r == g && g == b;
+                if scan.grayscale {
+                    scan.gray_levels.insert(r);
+                }
+            }
+        }
+        scan.unique_colors = u32::try_from(colors.len()).unwrap_or(COLOR_COUNT_CAP);
+        scan
+    }
+
+    fn into_stats(self, needs_16bit: bool) -> ColorStats {
+        let palette_size = (!needs_16bit && self.unique_colors <= MAX_PALETTE)
+            .then(|| u16::try_from(self.unique_colors).ok())
+            .flatten();
+
+        // Candidate layouts as (color type, bit depth, bits per pixel), smallest first
+        // on ties in the order listed.
+        let sample_bits: u8 = if needs_16bit { 16 } else { 8 };
+        let mut candidates = Vec::new();
+        if self.grayscale && self.opaque {
+            let depth = if needs_16bit {
+                16
+            } else {
+                gray_depth(&self.gray_levels)
+            };
+            candidates.push(("gray", depth, depth));
+        }
+        if self.grayscale {
+            candidates.push(("gray_alpha", sample_bits, sample_bits * 2));
+        }
+        if let Some(size) = palette_size {
+            let depth = index_depth(size);
+            candidates.push(("indexed", depth, depth));
+        }
+        if self.opaque {
+            candidates.push(("rgb", sample_bits, sample_bits * 3));
+        }
+        candidates.push(("rgba", sample_bits, sample_bits * 4));
+
+        let (color_type, bit_depth, bits_per_pixel) = candidates
+            .into_iter()
+            .min_by_key(|&(_, _, bpp)| bpp)
+            .unwrap_or(("rgba", sample_bits, sample_bits * 4));
+
+        ColorStats {
+            unique_colors: self.unique_colors,
+            colors_capped: self.colors_capped,
+            grayscale: self.grayscale,
+            opaque: self.opaque,
+            needs_16bit,
+            palette_size,
+            color_type,
+            bit_depth,
+            bits_per_pixel,
+        }
+    }
+}
+
+/// How well exposed an image is, measured on the luma of its visible pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct ExposureStats {
+    /// Average luma, 0–255.
+    pub mean_luminance: f64,
+    /// Percentage of pixels at or above [`HIGHLIGHT_CLIP`].
+    pub clipped_highlights: f64,
+    /// Percentage of pixels at or below [`SHADOW_CRUSH`].
+    pub crushed_shadows: f64,
+}
+
+/// Decodes `input` and reports its exposure with [`exposure`].
+///
+/// # Errors
+///
+/// Returns `StatsError::Decode` if the input cannot be decoded.
+pub fn exposure_score(input: &[u8]) -> Result<ExposureStats, StatsError> {
+    let decoded = image::load_from_memory(input).map_err(StatsError::Decode)?;
+    Ok(exposure(&decoded))
+}
+
+/// Reports the mean luma and the share of clipped highlights and crushed shadows.
+/// Fully transparent pixels are ignored; an image with none visible reports zeros.
+pub fn exposure(img: &DynamicImage) -> ExposureStats {
+    let (mut count, mut sum, mut clipped, mut crushed) = (0u64, 0u64, 0u64, 0u64);
+    for pixel in img.to_rgba8().pixels() {
+        let [.., alpha] = pixel.0;
+        if alpha == 0 {
+            continue;
+        }
+        let luma = crate::color::luminance(pixel.0);
+        count += 1;
+        sum += u64::from(luma);
+        clipped += u64::from(luma >= HIGHLIGHT_CLIP);
+        crushed += u64::from(luma <= SHADOW_CRUSH);
+    }
+    if count == 0 {
+        return ExposureStats {
+            mean_luminance: 0.0,
+            clipped_highlights: 0.0,
+            crushed_shadows: 0.0,
+        };
+    }
+    let share = |n: u64| color::to_f64(n) / color::to_f64(count);
+    ExposureStats {
+        mean_luminance: share(sum),
+        clipped_highlights: share(clipped) * 100.0,
+        crushed_shadows: share(crushed) * 100.0,
+    }
+}
+
+/// Otsu's threshold: the luma level that best separates `luma` into a dark class (at
+/// or below it) and a light one, e.g. ink and paper.
+pub fn otsu_threshold(luma: &[u8]) -> u8 {
+    let mut histogram = [0u64; 256];
+    for &value in luma {
+        if let Some(bin) = histogram.get_mut(usize::from(value)) {
+            *bin += 1;
+        }
+    }
+    let total: u64 = histogram.iter().sum();
+    let weighted: u64 = (0u64..).zip(histogram).map(|(value, n)| value * n).sum();
+
+    let (mut below, mut below_weighted) = (0u64, 0u64);
+    let (mut best, mut best_variance) = (0u8, -1.0);
+    for (value, n) in (0u8..=255).zip(histogram) {
+        below += n;
+        below_weighted += u64::from(value) * n;
+        let above = total - below;
+        if below == 0 || above == 0 {
+            continue;
+        }
+        let (nb, na) = (color::to_f64(below), color::to_f64(above));
+        let mean_below = color::to_f64(below_weighted) / nb;
+        let mean_above = color::to_f64(weighted - below_weighted) / na;
+        let variance = nb * na * (mean_below - mean_above).powi(2);
+        if variance > best_variance {
+            (best, best_variance) = (value, variance);
+        }
+    }
+    best
+}
+
+/// Decodes `input` and scores how sharp it is with [`sharpness`].
+///
+/// # Errors
+///
+/// Returns `StatsError::Decode` if the input cannot be decoded.
+pub fn sharpness_score(input: &[u8]) -> Result<f64, StatsError> {
+    let decoded = image::load_from_memory(input).map_err(StatsError::Decode)?;
+    Ok(sharpness(&decoded))
+}
+
+/// The variance of the Laplacian of the image's luma: high when it has crisp edges,
+/// low when it is blurred or flat. The score depends on resolution and content, so
+/// compare it against a threshold tuned for one kind of capture (around 100 is a
+/// common starting point for documents). Images smaller than 3x3 score 0.
+pub fn sharpness(img: &DynamicImage) -> f64 {
+    let luma = img.to_luma8();
+    let (width, height) = luma.dimensions();
+    let at = |x: u32, y: u32| {
+        luma.get_pixel_checked(x, y)
+            .map_or(0.0, |&image::Luma([value])| f64::from(value))
+    };
+
+    let (mut count, mut sum, mut sum_squares) = (0u32, 0.0, 0.0);
+    for y in 1..height.saturating_sub(1) {
+        for x in 1..width.saturating_sub(1) {
+            let laplacian =
+                at(x - 1, y) + at(x + 1, y) + at(x, y - 1) + at(x, y + 1) - 4.0 * at(x, y);
+            count += 1;
+            sum += laplacian;
+            sum_squares += laplacian * laplacian;
+        }
+    }
+    if count == 0 {
+        return 0.0;
+    }
+    let n = f64::from(count);
+    let mean = sum / n;
+    (sum_squares / n - mean * mean).max(0.0)
+}
+
+/// Smallest PNG grayscale bit depth (1, 2, 4 or 8) that holds every 8-bit level
+/// exactly: a level fits depth `d` when it is a multiple of `255 / (2^d - 1)`.
+fn gray_depth(levels: &HashSet<u16>) -> u8 {
+    [(1, 255), (2, 85), (4, 17)]
+        .into_iter()
+        .find(|&(_, step)| levels.iter().all(|level| level % step == 0))
+        .map_or(8, |(depth, _)| depth)
+}
+
+/// Bits per index for a palette of `size` entries.
+fn index_depth(size: u16) -> u8 {
+    match size {
+        0..=2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    }
+}
+
+/// Errors that can occur while analyzing an image.
+#[derive(Debug)]
+pub enum StatsError {
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+}
+
+impl fmt::Display for StatsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for StatsError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use image::{ImageBuffer, Luma, Rgb, Rgba, RgbaImage};
+
+    use super::*;
+
+    fn stats_of(img: RgbaImage) -> ColorStats {
+        analyze(&DynamicImage::ImageRgba8(img))
+    }
+
+    #[test]
+    fn black_and_white_is_1bit_gray() {
+        let img = RgbaImage::from_fn(4, 4, |x, _| {
+            Rgba(if x % 2 == 0 { [0, 0, 0, 255] } else { [255; 4] })
+        });
+        let stats = stats_of(img);
+        assert_eq!(stats.unique_colors, 2);
+        assert!(stats.grayscale && stats.opaque);
+        assert_eq!(
+            (stats.color_type, stats.bit_depth, stats.bits_per_pixel),
+            ("gray", 1, 1)
+        );
+        assert_eq!(stats.palette_size, Some(2));
+    }
+
+    #[test]
+    fn gray_levels_pick_smallest_depth() {
+        let levels = |values: &[u16]| values.iter().copied().collect::<HashSet<_>>();
+        assert_eq!(gray_depth(&levels(&[0, 85, 170, 255])), 2);
+        assert_eq!(gray_depth(&levels(&[0, 17, 255])), 4);
+        assert_eq!(gray_depth(&levels(&[0, 16])), 8);
+    }
+
+    #[test]
+    fn few_colors_are_indexed() {
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 128]];
+        let img = RgbaImage::from_fn(6, 1, |x, _| Rgba(colors[usize::try_from(x % 3).unwrap()]));
+        let stats = stats_of(img);
+        assert_eq!(stats.unique_colors, 3);
+        assert!(!stats.grayscale && !stats.opaque);
+        assert_eq!(stats.palette_size, Some(3));
+        assert_eq!((stats.color_type, stats.bit_depth), ("indexed", 2));
+    }
+
+    #[test]
+    fn many_colors_need_truecolor() {
+        let img = RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([
+                u8::try_from(x * 4).unwrap(),
+                u8::try_from(y * 4).unwrap(),
+                7,
+                255,
+            ])
+        });
+        let stats = stats_of(img);
+        assert_eq!(stats.unique_colors, 4096);
+        assert!(!stats.colors_capped);
+        assert_eq!(stats.palette_size, None);
+        assert_eq!((stats.color_type, stats.bits_per_pixel), ("rgb", 24));
+    }
+
+    #[test]
+    fn count_is_capped() {
+        let img = RgbaImage::from_fn(512, 256, |x, y| {
+            let [_, _, hi, lo] = (y * 512 + x).to_be_bytes();
+            Rgba([hi, lo, 0, 255])
+        });
+        let stats = stats_of(img);
+        assert_eq!(stats.unique_colors, COLOR_COUNT_CAP);
+        assert!(stats.colors_capped);
+    }
+
+    #[test]
+    fn hidden_colors_under_full_transparency_count_once() {
+        let img = RgbaImage::from_fn(4, 1, |x, _| {
+            let x = u8::try_from(x).unwrap();
+            Rgba([x * 40, 200, 9, 0])
+        });
+        let stats = stats_of(img);
+        assert_eq!(stats.unique_colors, 1);
+        assert!(stats.grayscale, "invisible pixels don't break grayscale");
+        assert!(!stats.opaque);
+    }
+
+    #[test]
+    fn wide_sources_with_8bit_data_are_8bit() {
+        let narrow: ImageBuffer<Rgb<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(4, 4, |x, _| Rgb([257 * u16::try_from(x).unwrap(), 0, 0]));
+        let stats = analyze(&DynamicImage::ImageRgb16(narrow));
+        assert!(!stats.needs_16bit);
+        assert_eq!(stats.palette_size, Some(4));
+
+        let wide: ImageBuffer<Luma<u16>, Vec<u16>> =
+            ImageBuffer::from_fn(4, 4, |x, _| Luma([1000 + u16::try_from(x).unwrap()]));
+        let stats = analyze(&DynamicImage::ImageLuma16(wide));
+        assert!(stats.needs_16bit && stats.grayscale && stats.opaque);
+        assert_eq!(stats.palette_size, None);
+        assert_eq!((stats.color_type, stats.bit_depth), ("gray", 16));
+    }
+
+    #[test]
+    fn blurring_lowers_sharpness() {
+        let checker = DynamicImage::ImageRgba8(RgbaImage::from_fn(32, 32, |x, y| {
+            Rgba(if (x / 4 + y / 4) % 2 == 0 {
+                [0, 0, 0, 255]
+            } else {
+                [255; 4]
+            })
+        }));
+        let sharp = sharpness(&checker);
+        let blurred = sharpness(&checker.blur(2.0));
+        assert!(sharp > 1000.0, "{sharp}");
+        assert!(blurred < sharp / 10.0, "{blurred} vs {sharp}");
+
+        let flat = DynamicImage::ImageRgba8(RgbaImage::from_pixel(8, 8, Rgba([90; 4])));
+        assert_eq!(sharpness(&flat), 0.0);
+        let tiny = DynamicImage::ImageRgba8(RgbaImage::new(2, 5));
+        assert_eq!(sharpness(&tiny), 0.0);
+    }
+
+    #[test]
+    fn exposure_counts_clipped_and_crushed_pixels() {
+        let img = RgbaImage::from_fn(4, 2, |x, y| match (x, y) {
+            (0, 0) => Rgba([255; 4]),
+            (1, 0) => Rgba([0, 0, 0, 255]),
+            (2, 0) => Rgba([9, 9, 9, 0]),
+            _ => Rgba([100, 100, 100, 255]),
+        });
+        let stats = exposure(&DynamicImage::ImageRgba8(img));
+        // Seven visible pixels: one white, one black, five at 100.
+        assert!((stats.mean_luminance - 755.0 / 7.0).abs() < 1e-9);
+        assert!((stats.clipped_highlights - 100.0 / 7.0).abs() < 1e-9);
+        assert!((stats.crushed_shadows - 100.0 / 7.0).abs() < 1e-9);
+
+        let hidden = RgbaImage::from_pixel(2, 2, Rgba([0; 4]));
+        assert_eq!(
+            exposure(&DynamicImage::ImageRgba8(hidden)).mean_luminance,
+            0.0
+        );
+    }
+
+    #[test]
+    fn otsu_splits_two_levels() {
+        let luma: Vec<u8> = [30u8; 10].into_iter().chain([200; 30]).collect();
+        let threshold = otsu_threshold(&luma);
+        assert!((30..200).contains(&threshold), "{threshold}");
+        assert_eq!(otsu_threshold(&[7; 4]), 0);
+    }
+
+    #[test]
+    fn color_stats_decodes_input() {
+        let mut png = Vec::new();
+        DynamicImage::ImageRgba8(RgbaImage::from_pixel(3, 3, Rgba([10, 10, 10, 255])))
+            .write_to(&mut Cursor::new(&mut png), image::ImageFormat::Png)
+            .unwrap();
+        let stats = color_stats(&png).unwrap();
+        assert_eq!(stats.unique_colors, 1);
+        // Level 10 needs 8-bit gray, but one palette entry fits 1-bit indices.
+        assert_eq!((stats.color_type, stats.bit_depth), ("indexed", 1));
+
+        assert!(matches!(color_stats(&[1, 2]), Err(StatsError::Decode(_))));
+    }
+}
@@ -0,0 +1,518 @@
+//! Text drawn with a built-in 5x7 pixel font, so labels and initials need no font
+//! files in the `.wasm`.
+
+use image::RgbaImage;
+
+use crate::composite;
+
+/// Rows in a glyph of the built-in font.
+const GLYPH_ROWS: u32 = 7;
+
+/// Columns in a glyph of the built-in font.
+const GLYPH_COLUMNS: u32 = 5;
+
+/// Font columns from the start of one glyph to the next: the glyph and one of spacing.
+const ADVANCE: u32 = GLYPH_COLUMNS + 1;
+
+/// The printable ASCII characters, from space to `~`, one row of bits per glyph row
+/// with the leftmost column in the highest of the five bits.
+const FONT: [[u8; 7]; 95] = [
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // space
+    [
+        0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00000, 0b00100,
+    ], // !
+    [
+        0b01010, 0b01010, 0b01010, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // "
+    [
+        0b01010, 0b01010, 0b11111, 0b01010, 0b11111, 0b01010, 0b01010,
+    ], // #
+    [
+        0b00100, 0b01111, 0b10100, 0b01110, 0b00101, 0b11110, 0b00100,
+    ], // $
+    [
+        0b11000, 0b11001, 0b00010, 0b00100, 0b01000, 0b10011, 0b00011,
+    ], // %
+    [
+        0b01100, 0b10010, 0b10100, 0b01000, 0b10101, 0b10010, 0b01101,
+    ], // &
+    [
+        0b00100, 0b00100, 0b00100, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // '
+    [
+        0b00010, 0b00100, 0b01000, 0b01000, 0b01000, 0b00100, 0b00010,
+    ], // (
+    [
+        0b01000, 0b00100, 0b00010, 0b00010, 0b00010, 0b00100, 0b01000,
+    ], // )
+    [
+        0b00000, 0b00100, 0b10101, 0b01110, 0b10101, 0b00100, 0b00000,
+    ], // *
+    [
+        0b00000, 0b00100, 0b00100, 0b11111, 0b00100, 0b00100, 0b00000,
+    ], // +
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b00100, 0b01000,
+    ], // ,
+    [
+        0b00000, 0b00000, 0b00000, 0b11111, 0b00000, 0b00000, 0b00000,
+    ], // -
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b01100, 0b01100,
+    ], // .
+    [
+        0b00000, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b00000,
+    ], // /
+    [
+        0b01110, 0b10001, 0b10011, 0b10101, 0b11001, 0b10001, 0b01110,
+    ], // 0
+    [
+        0b00100, 0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // 1
+    [
+        0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b01000, 0b11111,
+    ], // 2
+    [
+        0b11111, 0b00010, 0b00100, 0b00010, 0b00001, 0b10001, 0b01110,
+    ], // 3
+    [
+        0b00010, 0b00110, 0b01010, 0b10010, 0b11111, 0b00010, 0b00010,
+    ], // 4
+    [
+        0b11111, 0b10000, 0b11110, 0b00001, 0b00001, 0b10001, 0b01110,
+    ], // 5
+    [
+        0b00110, 0b01000, 0b10000, 0b11110, 0b10001, 0b10001, 0b01110,
+    ], // 6
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b01000, 0b01000,
+    ], // 7
+    [
+        0b01110, 0b10001, 0b10001, 0b01110, 0b10001, 0b10001, 0b01110,
+    ], // 8
+    [
+        0b01110, 0b10001, 0b10001, 0b01111, 0b00001, 0b00010, 0b01100,
+    ], // 9
+    [
+        0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b01100, 0b00000,
+    ], // :
+    [
+        0b00000, 0b01100, 0b01100, 0b00000, 0b01100, 0b00100, 0b01000,
+    ], // ;
+    [
+        0b00010, 0b00100, 0b01000, 0b10000, 0b01000, 0b00100, 0b00010,
+    ], // <
+    [
+        0b00000, 0b00000, 0b11111, 0b00000, 0b11111, 0b00000, 0b00000,
+    ], // =
+    [
+        0b01000, 0b00100, 0b00010, 0b00001, 0b00010, 0b00100, 0b01000,
+    ], // >
+    [
+        0b01110, 0b10001, 0b00001, 0b00010, 0b00100, 0b00000, 0b00100,
+    ], // ?
+    [
+        0b01110, 0b10001, 0b00001, 0b01101, 0b10101, 0b10101, 0b01110,
+    ], // @
+    [
+        0b01110, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ], // A
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10001, 0b10001, 0b11110,
+    ], // B
+    [
+        0b01110, 0b10001, 0b10000, 0b10000, 0b10000, 0b10001, 0b01110,
+    ], // C
+    [
+        0b11100, 0b10010, 0b10001, 0b10001, 0b10001, 0b10010, 0b11100,
+    ], // D
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b11111,
+    ], // E
+    [
+        0b11111, 0b10000, 0b10000, 0b11110, 0b10000, 0b10000, 0b10000,
+    ], // F
+    [
+        0b01110, 0b10001, 0b10000, 0b10111, 0b10001, 0b10001, 0b01111,
+    ], // G
+    [
+        0b10001, 0b10001, 0b10001, 0b11111, 0b10001, 0b10001, 0b10001,
+    ], // H
+    [
+        0b01110, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // I
+    [
+        0b00111, 0b00010, 0b00010, 0b00010, 0b00010, 0b10010, 0b01100,
+    ], // J
+    [
+        0b10001, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010, 0b10001,
+    ], // K
+    [
+        0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b10000, 0b11111,
+    ], // L
+    [
+        0b10001, 0b11011, 0b10101, 0b10101, 0b10001, 0b10001, 0b10001,
+    ], // M
+    [
+        0b10001, 0b10001, 0b11001, 0b10101, 0b10011, 0b10001, 0b10001,
+    ], // N
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ], // O
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10000, 0b10000, 0b10000,
+    ], // P
+    [
+        0b01110, 0b10001, 0b10001, 0b10001, 0b10101, 0b10010, 0b01101,
+    ], // Q
+    [
+        0b11110, 0b10001, 0b10001, 0b11110, 0b10100, 0b10010, 0b10001,
+    ], // R
+    [
+        0b01111, 0b10000, 0b10000, 0b01110, 0b00001, 0b00001, 0b11110,
+    ], // S
+    [
+        0b11111, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+    ], // T
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01110,
+    ], // U
+    [
+        0b10001, 0b10001, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+    ], // V
+    [
+        0b10001, 0b10001, 0b10001, 0b10101, 0b10101, 0b10101, 0b01010,
+    ], // W
+    [
+        0b10001, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001, 0b10001,
+    ], // X
+    [
+        0b10001, 0b10001, 0b10001, 0b01010, 0b00100, 0b00100, 0b00100,
+    ], // Y
+    [
+        0b11111, 0b00001, 0b00010, 0b00100, 0b01000, 0b10000, 0b11111,
+    ], // Z
+    [
+        0b01110, 0b01000, 0b01000, 0b01000, 0b01000, 0b01000, 0b01110,
+    ], // [
+    [
+        0b00000, 0b10000, 0b01000, 0b00100, 0b00010, 0b00001, 0b00000,
+    ], // backslash
+    [
+        0b01110, 0b00010, 0b00010, 0b00010, 0b00010, 0b00010, 0b01110,
+    ], // ]
+    [
+        0b00100, 0b01010, 0b10001, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // ^
+    [
+        0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b00000, 0b11111,
+    ], // _
+    [
+        0b01000, 0b00100, 0b00010, 0b00000, 0b00000, 0b00000, 0b00000,
+    ], // `
+    [
+        0b00000, 0b00000, 0b01110, 0b00001, 0b01111, 0b10001, 0b01111,
+    ], // a
+    [
+        0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b11110,
+    ], // b
+    [
+        0b00000, 0b00000, 0b01110, 0b10000, 0b10000, 0b10001, 0b01110,
+    ], // c
+    [
+        0b00001, 0b00001, 0b01101, 0b10011, 0b10001, 0b10001, 0b01111,
+    ], // d
+    [
+        0b00000, 0b00000, 0b01110, 0b10001, 0b11111, 0b10000, 0b01110,
+    ], // e
+    [
+        0b00110, 0b01001, 0b01000, 0b11100, 0b01000, 0b01000, 0b01000,
+    ], // f
+    [
+        0b00000, 0b01111, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+    ], // g
+    [
+        0b10000, 0b10000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+    ], // h
+    [
+        0b00100, 0b00000, 0b01100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // i
+    [
+        0b00010, 0b00000, 0b00110, 0b00010, 0b00010, 0b10010, 0b01100,
+    ], // j
+    [
+        0b10000, 0b10000, 0b10010, 0b10100, 0b11000, 0b10100, 0b10010,
+    ], // k
+    [
+        0b01100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b01110,
+    ], // l
+    [
+        0b00000, 0b00000, 0b11010, 0b10101, 0b10101, 0b10001, 0b10001,
+    ], // m
+    [
+        0b00000, 0b00000, 0b10110, 0b11001, 0b10001, 0b10001, 0b10001,
+    ], // n
+    [
+        0b00000, 0b00000, 0b01110, 0b10001, 0b10001, 0b10001, 0b01110,
+    ], // o
+    [
+        0b00000, 0b00000, 0b11110, 0b10001, 0b11110, 0b10000, 0b10000,
+    ], // p
+    [
+        0b00000, 0b00000, 0b01101, 0b10011, 0b01111, 0b00001, 0b00001,
+    ], // q
+    [
+        0b00000, 0b00000, 0b10110, 0b11001, 0b10000, 0b10000, 0b10000,
+    ], // r
+    [
+        0b00000, 0b00000, 0b01110, 0b10000, 0b01110, 0b00001, 0b11110,
+    ], // s
+    [
+        0b01000, 0b01000, 0b11100, 0b01000, 0b01000, 0b01001, 0b00110,
+    ], // t
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b10011, 0b01101,
+    ], // u
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10001, 0b01010, 0b00100,
+    ], // v
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b10101, 0b10101, 0b01010,
+    ], // w
+    [
+        0b00000, 0b00000, 0b10001, 0b01010, 0b00100, 0b01010, 0b10001,
+    ], // x
+    [
+        0b00000, 0b00000, 0b10001, 0b10001, 0b01111, 0b00001, 0b01110,
+    ], // y
+    [
+        0b00000, 0b00000, 0b11111, 0b00010, 0b00100, 0b01000, 0b11111,
+    ], // z
+    [
+        0b00010, 0b00100, 0b00100, 0b01000, 0b00100, 0b00100, 0b00010,
+    ], // {
+    [
+        0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100, 0b00100,
+    ], // |
+    [
+        0b01000, 0b00100, 0b00100, 0b00010, 0b00100, 0b00100, 0b01000,
+    ], // }
+    [
+        0b00000, 0b00000, 0b01000, 0b10101, 0b00010, 0b00000, 0b00000,
+    ], // ~
+];
+
+/// The glyph for `c`. Characters outside printable ASCII are drawn as `?`.
+fn glyph(c: char) -> [u8; 7] {
+    let index = u32::from(c)
+        .checked_sub(u32::from(' '))
+        .and_then(|i| usize::try_from(i).ok())
+        .and_then(|i| FONT.get(i))
+        .or_else(|| FONT.get(usize::from(b'?' - b' ')));
+    index.copied().unwrap_or_default()
+}
+
+/// Whether the font cell at (`column`, `row`) of `text` is lit.
+fn lit(text: &[[u8; 7]], column: u32, row: u32) -> bool {
+    let glyph = usize::try_from(column / ADVANCE)
+        .ok()
+        .and_then(|i| text.get(i));
+    let bits = usize::try_from(row).ok().and_then(|r| glyph?.get(r));
+    let column = column % ADVANCE;
+    match bits {
+        Some(bits) if column < GLYPH_COLUMNS => (bits >> (GLYPH_COLUMNS - 1 - column)) & 1 == 1,
+        _ => false,
+    }
+}
+
+/// The size in pixels of `text` drawn `height` pixels tall, rounded up.
+pub fn measure(text: &str, height: u32) -> (u32, u32) {
+    (width_of(char_count(text), height), height)
+}
+
+/// The width of `count` characters at `height`.
+fn width_of(count: u32, height: u32) -> u32 {
+    let columns = (count.saturating_mul(ADVANCE)).saturating_sub(1);
+    let scale = f64::from(height) / f64::from(GLYPH_ROWS);
+    to_side(f64::from(columns) * scale)
+}
+
+fn char_count(text: &str) -> u32 {
+    u32::try_from(text.chars().count()).unwrap_or(u32::MAX)
+}
+
+/// `text`, cut short with `...` if it is wider than `max_width` at `height`.
+pub fn fit(text: &str, height: u32, max_width: u32) -> String {
+    let count = char_count(text);
+    if width_of(count, height) <= max_width {
+        return text.to_owned();
+    }
+    if width_of(3, height) > max_width {
+        return String::new();
+    }
+    // Width only depends on the character count, so the most characters that fit
+    // before the `...` are found by bisection rather than by measuring each shorter
+    // candidate. `kept` characters fit and `cut` don't.
+    let (mut kept, mut cut) = (0, count);
+    while cut - kept > 1 {
+        let mid = kept + (cut - kept) / 2;
+        if width_of(mid.saturating_add(3), height) <= max_width {
+            kept = mid;
+        } else {
+            cut = mid;
+        }
+    }
+    text.chars()
+        .take(usize::try_from(kept).unwrap_or(0))
+        .chain(['.'; 3])
+        .collect()
+}
+
+/// Draws `text` on `img` with its top-left corner at (`x`, `y`), `height` pixels tall
+/// (the height of a capital letter). Edges are antialiased by how much of each pixel
+/// the scaled font cells cover.
+pub fn draw_text(img: &mut RgbaImage, text: &str, x: i64, y: i64, height: u32, color: [u8; 4]) {
+    let glyphs: Vec<[u8; 7]> = text.chars().map(glyph).collect();
+    let (width, _) = measure(text, height);
+    if width == 0 || height == 0 {
+        return;
+    }
+    // Font cells per image pixel.
+    let step = f64::from(GLYPH_ROWS) / f64::from(height);
+    // The pixels of the text, `length` long from `offset`, that land on an image side.
+    let visible = |offset: i64, length: u32, side: u32| {
+        let start = offset.saturating_neg().clamp(0, i64::from(length));
+        let end = i64::from(side)
+            .saturating_sub(offset)
+            .clamp(start, i64::from(length));
+        u32::try_from(start).unwrap_or(0)..u32::try_from(end).unwrap_or(0)
+    };
+
+    for py in visible(y, height, img.height()) {
+        for px in visible(x, width, img.width()) {
+            let (Ok(ix), Ok(iy)) = (
+                u32::try_from(x + i64::from(px)),
+                u32::try_from(y + i64::from(py)),
+            ) else {
+                continue;
+            };
+            let Some(pixel) = img.get_pixel_mut_checked(ix, iy) else {
+                continue;
+            };
+
+            // The pixel's footprint in font cells, and how much of it lit cells cover.
+            let (left, top) = (f64::from(px) * step, f64::from(py) * step);
+            let (right, bottom) = (left + step, top + step);
+            let mut covered = 0.0;
+            for row in to_cell(top)..=to_cell(bottom).min(GLYPH_ROWS - 1) {
+                for column in to_cell(left)..=to_cell(right) {
+                    if !lit(&glyphs, column, row) {
+                        continue;
+                    }
+                    let (c, r) = (f64::from(column), f64::from(row));
+                    let across = right.min(c + 1.0) - left.max(c);
+                    let down = bottom.min(r + 1.0) - top.max(r);
+                    covered += across.max(0.0) * down.max(0.0);
+                }
+            }
+            let coverage = (covered / (step * step)).min(1.0);
+            if coverage > 0.0 {
+                pixel.0 = composite::over(pixel.0, color, coverage);
+            }
+        }
+    }
+}
+
+// Safe: the value is floored and clamped to be non-negative; font coordinates are
+// small.
+#[allow(clippy::as_conversions)]
+fn to_cell(value: f64) -> u32 {
+    value.floor().clamp(0.0, f64::from(u32::MAX)) as u32
+}
+
+// Safe: the value is rounded up and clamped to u32's range.
+#[allow(clippy::as_conversions)]
+fn to_side(value: f64) -> u32 {
+    value.ceil().clamp(0.0, f64::from(u32::MAX)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Rgba;
+
+    use super::*;
+
+    #[test]
+    fn glyphs_cover_printable_ascii() {
+        assert_eq!(glyph(' '), [0; 7]);
+        assert_eq!(glyph('~'), FONT[94]);
+        assert_eq!(glyph('\u{e9}'), glyph('?'));
+        assert_eq!(glyph('\n'), glyph('?'));
+    }
+
+    #[test]
+    fn measures_scaled_text() {
+        // Two glyphs and the gap between them: 11 font columns.
+        assert_eq!(measure("AB", 7), (11, 7));
+        assert_eq!(measure("AB", 14), (22, 14));
+        assert_eq!(measure("", 14), (0, 14));
+    }
+
+    #[test]
+    fn long_text_is_cut_short() {
+        assert_eq!(fit("short", 7, 100), "short");
+        let cut = fit("a_very_long_filename.jpg", 7, 60);
+        assert!(cut.ends_with("...") && measure(&cut, 7).0 <= 60, "{cut}");
+        assert_eq!(fit("abc", 7, 2), "");
+        // Cut as short as needed and no shorter: 6 characters are 35 columns.
+        assert_eq!(fit("abcdefgh", 7, 35), "abc...");
+        assert_eq!(fit("abcdefgh", 7, 34), "ab...");
+        // Untrusted captions can be long; fitting them is quick.
+        let long = "x".repeat(1_000_000);
+        assert_eq!(fit(&long, 7, 60), "xxxxxxx...");
+    }
+
+    #[test]
+    fn draws_glyph_cells() {
+        let mut img = RgbaImage::from_pixel(12, 7, Rgba([255; 4]));
+        draw_text(&mut img, "L", 1, 0, 7, [0, 0, 0, 255]);
+        // L: the left column and the bottom row.
+        assert_eq!(img.get_pixel(1, 0).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(2, 0).0, [255; 4]);
+        assert_eq!(img.get_pixel(5, 6).0, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(0, 6).0, [255; 4]);
+    }
+
+    #[test]
+    fn scaled_edges_are_antialiased() {
+        let mut img = RgbaImage::from_pixel(20, 20, Rgba([255; 4]));
+        // At 10px tall each font cell is 10/7 pixels, so cell edges fall mid-pixel.
+        draw_text(&mut img, "I", 0, 0, 10, [0, 0, 0, 255]);
+        let grays = img.pixels().filter(|p| p.0[0] > 0 && p.0[0] < 255).count();
+        assert!(grays > 0);
+        assert_eq!(img.get_pixel(3, 5).0, [0, 0, 0, 255]);
+        // Clipping to the image is fine.
+        draw_text(&mut img, "WWW", -5, 15, 10, [0, 0, 0, 255]);
+    }
+
+    #[test]
+    fn only_visible_pixels_are_visited() {
+        // Text billions of pixels tall or far off the image returns at once.
+        let mut img = RgbaImage::from_pixel(8, 8, Rgba([255; 4]));
+        draw_text(&mut img, "L", -3, -3, 2_000_000_000, [0, 0, 0, 255]);
+        assert_eq!(img.get_pixel(0, 0).0, [0, 0, 0, 255]);
+        let mut blank = RgbaImage::from_pixel(8, 8, Rgba([255; 4]));
+        draw_text(
+            &mut blank,
+            "WWW",
+            i64::MIN,
+            i64::MAX,
+            u32::MAX,
+            [0, 0, 0, 255],
+        );
+        draw_text(&mut blank, "WWW", 9, 0, 2_000_000_000, [0, 0, 0, 255]);
+        assert!(blank.pixels().all(|p| p.0 == [255; 4]));
+    }
+}
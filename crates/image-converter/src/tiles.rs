@@ -0,0 +1,636 @@
+use std::fmt;
+use std::io::Write;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+use crate::generate::MAX_SIDE;
+
+/// A Deep Zoom tile pyramid: every level of an image cut into encoded tiles.
+#[derive(Debug, Clone)]
+pub struct TilePyramid {
+    pub descriptor: PyramidDescriptor,
+    /// Tiles from the smallest level to the largest, each level in row-major order.
+    pub tiles: Vec<Tile>,
+}
+
+/// What a viewer needs to lay the tiles out, mirroring a `.dzi` file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PyramidDescriptor {
+    /// Full-resolution dimensions.
+    pub width: u32,
+    pub height: u32,
+    pub tile_size: u32,
+    /// Pixels each tile repeats from its neighbours. Always 0.
+    pub overlap: u32,
+    /// The tiles' file extension, as the DZI `Format` attribute (e.g. `"jpg"`).
+    pub format: &'static str,
+    /// Level 0 is 1x1; the last level is full resolution.
+    pub levels: Vec<PyramidLevel>,
+}
+
+/// One level of a pyramid, half the size of the next (rounded up).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct PyramidLevel {
+    pub level: u32,
+    pub width: u32,
+    pub height: u32,
+    pub columns: u32,
+    pub rows: u32,
+}
+
+/// An encoded tile.
+#[derive(Debug, Clone)]
+pub struct Tile {
+    /// Path within the pyramid's `_files` directory, DZI style: `"level/column_row.ext"`.
+    pub name: String,
+    pub level: u32,
+    pub column: u32,
+    pub row: u32,
+    pub data: Vec<u8>,
+}
+
+impl PyramidDescriptor {
+    /// The `.dzi` XML descriptor. Tiles go in a directory named after the `.dzi` file
+    /// with `_files` appended (`photo.dzi` and `photo_files/`).
+    pub fn to_dzi(&self) -> String {
+        format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                "\n",
+                r#"<Image xmlns="http://schemas.microsoft.com/deepzoom/2008" Format="{}" Overlap="{}" TileSize="{}">"#,
+                "\n",
+                r#"  <Size Width="{}" Height="{}"/>"#,
+                "\n</Image>\n"
+            ),
+            self.format, self.overlap, self.tile_size, self.width, self.height
+        )
+    }
+}
+
+/// Builds a Deep Zoom pyramid from `input`: the full image, then halvings of it down
+/// to 1x1, each cut into `tile_size` square tiles (smaller at the right and bottom
+/// edges) and encoded as `target`.
+///
+/// Each level is downscaled from the one above it, so the whole pyramid costs about a
+/// third more than tiling the full image alone. The top level is the full image, so
+/// unlike thumbnails it is always decoded at full resolution, never reduced (see
+/// [`crate::region::decode_at_least`]).
+///
+/// # Errors
+///
+/// Returns a `TilesError` if `tile_size` is zero, the input cannot be decoded, or a
+/// tile cannot be encoded (including targets that can't be encoded, like WebP).
+pub fn generate_tiles(
+    input: &[u8],
+    tile_size: u32,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<TilePyramid, TilesError> {
+    if tile_size == 0 {
+        return Err(TilesError::InvalidTileSize);
+    }
+    target
+        .to_image_format()
+        .map_err(|e| TilesError::Convert(ConvertError::UnsupportedTarget(e.to_string())))?;
+
+    let image = image::load_from_memory(input)
+        .map_err(TilesError::Decode)?
+        .into_rgba8();
+    let levels = levels(image.width(), image.height(), tile_size);
+    let extension = target.preferred_extension();
+
+    let mut by_level = Vec::with_capacity(levels.len());
+    let mut current = image;
+    for level in levels.iter().rev() {
+        if current.dimensions() != (level.width, level.height) {
+            current = imageops::resize(&current, level.width, level.height, FilterType::Triangle);
+        }
+        by_level.push(cut_level(&current, level, tile_size, target, quality)?);
+    }
+    let tiles: Vec<Tile> = by_level.into_iter().rev().flatten().collect();
+    log::debug!("generated {} tiles in {} levels", tiles.len(), levels.len());
+
+    Ok(TilePyramid {
+        descriptor: PyramidDescriptor {
+            width: levels.last().map_or(1, |level| level.width),
+            height: levels.last().map_or(1, |level| level.height),
+            tile_size,
+            overlap: 0,
+            format: extension,
+            levels,
+        },
+        tiles,
+    })
+}
+
+/// The levels of a `width` x `height` pyramid, smallest first. There are enough for
+/// the last to be full size: level `n` is at most `2^n` pixels on its longer side.
+fn levels(width: u32, height: u32, tile_size: u32) -> Vec<PyramidLevel> {
+    let longest = width.max(height).max(1);
+    let max_level = longest.next_power_of_two().trailing_zeros();
+    (0..=max_level)
+        .map(|level| {
+            let shift = max_level - level;
+            let scale = |size: u32| {
+                u32::try_from(u64::from(size).div_ceil(1 << shift))
+                    .unwrap_or(u32::MAX)
+                    .max(1)
+            };
+            let (width, height) = (scale(width), scale(height));
+            PyramidLevel {
+                level,
+                width,
+                height,
+                columns: width.div_ceil(tile_size),
+                rows: height.div_ceil(tile_size),
+            }
+        })
+        .collect()
+}
+
+/// Cuts one level's image into encoded tiles.
+fn cut_level(
+    image: &RgbaImage,
+    level: &PyramidLevel,
+    tile_size: u32,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<Tile>, TilesError> {
+    let mut tiles = Vec::new();
+    for row in 0..level.rows {
+        for column in 0..level.columns {
+            let (x, y) = (column * tile_size, row * tile_size);
+            let width = tile_size.min(level.width - x);
+            let height = tile_size.min(level.height - y);
+            let tile = imageops::crop_imm(image, x, y, width, height).to_image();
+            let data = convert::encode(&DynamicImage::ImageRgba8(tile), target, quality)
+                .map_err(TilesError::Convert)?;
+            tiles.push(Tile {
+                name: format!(
+                    "{}/{column}_{row}.{}",
+                    level.level,
+                    target.preferred_extension()
+                ),
+                level: level.level,
+                column,
+                row,
+                data,
+            });
+        }
+    }
+    Ok(tiles)
+}
+
+/// How a grid of tiles fits together: `tile_width` x `tile_height` tiles in row-major
+/// order, with the last column and row cut to fit `width` x `height`, as
+/// [`generate_tiles`] produces them for each level.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileLayout {
+    pub width: u32,
+    pub height: u32,
+    pub tile_width: u32,
+    pub tile_height: u32,
+}
+
+impl TileLayout {
+    pub fn columns(&self) -> u32 {
+        self.width.div_ceil(self.tile_width)
+    }
+
+    pub fn rows(&self) -> u32 {
+        self.height.div_ceil(self.tile_height)
+    }
+
+    /// The size the tile at `column`, `row` must have.
+    fn tile_size(&self, column: u32, row: u32) -> (u32, u32) {
+        (
+            self.tile_width.min(self.width - column * self.tile_width),
+            self.tile_height.min(self.height - row * self.tile_height),
+        )
+    }
+}
+
+/// Composes encoded tiles laid out as `layout` into one image encoded as `target`.
+///
+/// Tiles are decoded one row of the grid at a time. PNG output is written as each row
+/// is decoded, so it never holds more than one row of decoded tiles; other formats
+/// need the whole image for their encoder. `quality` applies as for [`convert::encode`],
+/// except that PNG output always uses default compression.
+///
+/// # Errors
+///
+/// Returns a `TilesError` if the layout is empty or larger than [`MAX_SIDE`] on a side,
+/// the number of tiles or the size of a tile doesn't match it, a tile cannot be
+/// decoded, or encoding fails.
+pub fn stitch_tiles(
+    tiles: &[impl AsRef<[u8]>],
+    layout: TileLayout,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, TilesError> {
+    if layout.tile_width == 0 || layout.tile_height == 0 {
+        return Err(TilesError::InvalidTileSize);
+    }
+    if layout.width == 0 || layout.height == 0 {
+        return Err(TilesError::EmptyLayout);
+    }
+    if layout.width > MAX_SIDE || layout.height > MAX_SIDE {
+        return Err(TilesError::TooLarge);
+    }
+    target
+        .to_image_format()
+        .map_err(|e| TilesError::Convert(ConvertError::UnsupportedTarget(e.to_string())))?;
+    let columns = to_usize(layout.columns());
+    let expected = columns.saturating_mul(to_usize(layout.rows()));
+    if tiles.len() != expected {
+        return Err(TilesError::TileCountMismatch {
+            expected,
+            actual: tiles.len(),
+        });
+    }
+    let bands = (0..layout.rows()).zip(tiles.chunks(columns));
+
+    if target == ImageFormat::Png {
+        let mut buf = Vec::new();
+        let mut encoder = png::Encoder::new(&mut buf, layout.width, layout.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header().map_err(TilesError::PngEncode)?;
+        let mut stream = writer.stream_writer().map_err(TilesError::PngEncode)?;
+        for (row, band) in bands {
+            let band = decode_band(band, layout, row)?;
+            let mut rows: Vec<_> = band
+                .iter()
+                .map(|tile| tile.as_raw().chunks_exact(to_usize(tile.width()) * 4))
+                .collect();
+            for _ in 0..layout.tile_size(0, row).1 {
+                for pixels in rows.iter_mut().filter_map(Iterator::next) {
+                    stream
+                        .write_all(pixels)
+                        .map_err(|e| TilesError::PngEncode(e.into()))?;
+                }
+            }
+        }
+        stream.finish().map_err(TilesError::PngEncode)?;
+        writer.finish().map_err(TilesError::PngEncode)?;
+        return Ok(buf);
+    }
+
+    // Decode and check every tile before allocating the canvas.
+    let decoded = bands
+        .map(|(row, band)| Ok((row, decode_band(band, layout, row)?)))
+        .collect::<Result<Vec<_>, TilesError>>()?;
+    let mut canvas = RgbaImage::new(layout.width, layout.height);
+    for (row, band) in decoded {
+        for (column, tile) in (0..).zip(band) {
+            let x = i64::from(column * layout.tile_width);
+            let y = i64::from(row * layout.tile_height);
+            imageops::replace(&mut canvas, &tile, x, y);
+        }
+    }
+    convert::encode(&DynamicImage::ImageRgba8(canvas), target, quality).map_err(TilesError::Convert)
+}
+
+/// Decodes the tiles of grid row `row`, checking each has the size `layout` gives it.
+fn decode_band(
+    band: &[impl AsRef<[u8]>],
+    layout: TileLayout,
+    row: u32,
+) -> Result<Vec<RgbaImage>, TilesError> {
+    (0..)
+        .zip(band)
+        .map(|(column, data)| {
+            let tile = image::load_from_memory(data.as_ref())
+                .map_err(TilesError::Decode)?
+                .into_rgba8();
+            if tile.dimensions() != layout.tile_size(column, row) {
+                return Err(TilesError::TileSizeMismatch { column, row });
+            }
+            Ok(tile)
+        })
+        .collect()
+}
+
+/// Cuts an image into a `rows` x `columns` grid of pieces, returned in row-major order
+/// and encoded as `target`, e.g. for grid posts on social media or chunked uploads.
+///
+/// Pieces split the image evenly; when it doesn't divide exactly, sizes differ by at
+/// most one pixel, with the extra pixels spread across the grid.
+///
+/// # Errors
+///
+/// Returns a `TilesError` if `rows` or `columns` is zero or larger than the image, the
+/// input cannot be decoded, or a piece cannot be encoded.
+pub fn split_image(
+    input: &[u8],
+    rows: u32,
+    columns: u32,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<Vec<u8>>, TilesError> {
+    if rows == 0 || columns == 0 {
+        return Err(TilesError::InvalidGrid);
+    }
+    target
+        .to_image_format()
+        .map_err(|e| TilesError::Convert(ConvertError::UnsupportedTarget(e.to_string())))?;
+    let image = image::load_from_memory(input)
+        .map_err(TilesError::Decode)?
+        .into_rgba8();
+    if columns > image.width() || rows > image.height() {
+        return Err(TilesError::GridExceedsImage);
+    }
+
+    let xs = split_points(image.width(), columns);
+    let ys = split_points(image.height(), rows);
+    let mut pieces = Vec::new();
+    for rows in ys.windows(2) {
+        let [top, bottom] = *rows else { continue };
+        for columns in xs.windows(2) {
+            let [left, right] = *columns else { continue };
+            let piece = imageops::crop_imm(&image, left, top, right - left, bottom - top);
+            let data =
+                convert::encode(&DynamicImage::ImageRgba8(piece.to_image()), target, quality)
+                    .map_err(TilesError::Convert)?;
+            pieces.push(data);
+        }
+    }
+    Ok(pieces)
+}
+
+/// The `parts + 1` boundaries that cut `size` into `parts` near-equal spans.
+fn split_points(size: u32, parts: u32) -> Vec<u32> {
+    (0..=parts)
+        .map(|part| {
+            u32::try_from(u64::from(size) * u64::from(part) / u64::from(parts)).unwrap_or(size)
+        })
+        .collect()
+}
+
+fn to_usize(value: u32) -> usize {
+    usize::try_from(value).unwrap_or(usize::MAX)
+}
+
+/// Errors that can occur while generating, stitching or splitting tiles.
+#[derive(Debug)]
+pub enum TilesError {
+    /// Failed to decode the input image or a tile.
+    Decode(image::ImageError),
+    /// Failed to encode a tile or the stitched image.
+    Convert(ConvertError),
+    /// Failed to write stitched PNG output.
+    PngEncode(png::EncodingError),
+    /// The tile size was zero.
+    InvalidTileSize,
+    /// The layout's width or height was zero.
+    EmptyLayout,
+    /// The layout's width or height was larger than [`MAX_SIDE`].
+    TooLarge,
+    /// A split grid had zero rows or columns.
+    InvalidGrid,
+    /// A split grid had more rows or columns than the image has pixels.
+    GridExceedsImage,
+    /// The number of tiles doesn't fill the layout's grid.
+    TileCountMismatch { expected: usize, actual: usize },
+    /// A tile's size doesn't match its place in the layout.
+    TileSizeMismatch { column: u32, row: u32 },
+}
+
+impl fmt::Display for TilesError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+            Self::PngEncode(e) => write!(f, "Failed to encode PNG: {e}"),
+            Self::InvalidTileSize => write!(f, "Tile size must be at least 1"),
+            Self::EmptyLayout => write!(f, "Layout width and height must be at least 1"),
+            Self::TooLarge => write!(
+                f,
+                "Layout width and height must be at most {MAX_SIDE}x{MAX_SIDE}"
+            ),
+            Self::InvalidGrid => write!(f, "Rows and columns must be at least 1"),
+            Self::GridExceedsImage => {
+                write!(f, "Grid has more rows or columns than the image has pixels")
+            }
+            Self::TileCountMismatch { expected, actual } => {
+                write!(f, "Layout needs {expected} tiles, got {actual}")
+            }
+            Self::TileSizeMismatch { column, row } => write!(
+                f,
+                "Tile at column {column}, row {row} doesn't match the layout's tile size"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TilesError {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn png(width: u32, height: u32) -> Vec<u8> {
+        let mut buf = Cursor::new(Vec::new());
+        RgbaImage::from_pixel(width, height, image::Rgba([10, 20, 30, 255]))
+            .write_to(&mut buf, image::ImageFormat::Png)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn levels_halve_down_to_one_pixel() {
+        let pyramid = levels(300, 200, 128);
+        assert_eq!(pyramid.len(), 10);
+        assert_eq!(
+            pyramid[9],
+            PyramidLevel {
+                level: 9,
+                width: 300,
+                height: 200,
+                columns: 3,
+                rows: 2
+            }
+        );
+        assert_eq!((pyramid[8].width, pyramid[8].height), (150, 100));
+        assert_eq!((pyramid[1].width, pyramid[1].height), (2, 1));
+        assert_eq!((pyramid[0].width, pyramid[0].height), (1, 1));
+
+        assert_eq!(levels(256, 1, 256).len(), 9);
+        assert_eq!(levels(1, 1, 256).len(), 1);
+    }
+
+    #[test]
+    fn generates_every_tile_of_every_level() {
+        let pyramid = generate_tiles(&png(300, 200), 128, ImageFormat::Png, None).unwrap();
+        let descriptor = &pyramid.descriptor;
+        assert_eq!((descriptor.width, descriptor.height), (300, 200));
+        assert_eq!(descriptor.format, "png");
+
+        let expected: u32 = descriptor
+            .levels
+            .iter()
+            .map(|level| level.columns * level.rows)
+            .sum();
+        assert_eq!(pyramid.tiles.len(), usize::try_from(expected).unwrap());
+        assert_eq!(pyramid.tiles[0].name, "0/0_0.png");
+
+        let corner = pyramid.tiles.last().unwrap();
+        assert_eq!(corner.name, "9/2_1.png");
+        let corner = image::load_from_memory(&corner.data).unwrap();
+        assert_eq!((corner.width(), corner.height()), (44, 72));
+    }
+
+    #[test]
+    fn dzi_descriptor_names_the_layout() {
+        let pyramid = generate_tiles(&png(10, 5), 254, ImageFormat::Jpeg, Some(80)).unwrap();
+        let dzi = pyramid.descriptor.to_dzi();
+        assert!(dzi.contains(r#"Format="jpg" Overlap="0" TileSize="254""#));
+        assert!(dzi.contains(r#"<Size Width="10" Height="5"/>"#));
+    }
+
+    // Safe: modulo 256 guarantees values fit in u8.
+    #[allow(clippy::as_conversions)]
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            image::Rgba([(x % 256) as u8, (y % 256) as u8, ((x ^ y) % 256) as u8, 255])
+        })
+    }
+
+    #[test]
+    fn stitching_undoes_tiling() {
+        let image = gradient(300, 200);
+        let mut buf = Cursor::new(Vec::new());
+        image.write_to(&mut buf, image::ImageFormat::Png).unwrap();
+        let pyramid = generate_tiles(buf.get_ref(), 128, ImageFormat::Png, None).unwrap();
+        let full = pyramid.descriptor.levels.last().unwrap();
+        let tiles: Vec<&[u8]> = pyramid
+            .tiles
+            .iter()
+            .filter(|tile| tile.level == full.level)
+            .map(|tile| tile.data.as_slice())
+            .collect();
+        let layout = TileLayout {
+            width: 300,
+            height: 200,
+            tile_width: 128,
+            tile_height: 128,
+        };
+
+        let png = stitch_tiles(&tiles, layout, ImageFormat::Png, None).unwrap();
+        assert_eq!(image::load_from_memory(&png).unwrap().into_rgba8(), image);
+
+        let bmp = stitch_tiles(&tiles, layout, ImageFormat::Bmp, None).unwrap();
+        assert_eq!(image::load_from_memory(&bmp).unwrap().into_rgba8(), image);
+    }
+
+    #[test]
+    fn stitching_checks_the_layout() {
+        let layout = TileLayout {
+            width: 6,
+            height: 4,
+            tile_width: 4,
+            tile_height: 4,
+        };
+        let tile = png(4, 4);
+        assert!(matches!(
+            stitch_tiles(&[&tile], layout, ImageFormat::Png, None),
+            Err(TilesError::TileCountMismatch {
+                expected: 2,
+                actual: 1
+            })
+        ));
+        assert!(matches!(
+            stitch_tiles(&[&tile, &tile], layout, ImageFormat::Png, None),
+            Err(TilesError::TileSizeMismatch { column: 1, row: 0 })
+        ));
+        assert!(stitch_tiles(&[&tile, &png(2, 4)], layout, ImageFormat::Png, None).is_ok());
+        assert!(matches!(
+            stitch_tiles(
+                &[&tile],
+                TileLayout {
+                    tile_width: 0,
+                    ..layout
+                },
+                ImageFormat::Png,
+                None
+            ),
+            Err(TilesError::InvalidTileSize)
+        ));
+        // Oversized layouts are rejected before any tile is decoded or the canvas is
+        // allocated.
+        for target in [ImageFormat::Png, ImageFormat::Bmp] {
+            assert!(matches!(
+                stitch_tiles(
+                    &[&png(2, 2)],
+                    TileLayout {
+                        width: 200_000,
+                        height: 200_000,
+                        tile_width: 200_000,
+                        tile_height: 200_000,
+                    },
+                    target,
+                    None
+                ),
+                Err(TilesError::TooLarge)
+            ));
+        }
+        // Other formats check every tile before composing them.
+        assert!(matches!(
+            stitch_tiles(&[&tile, &b"junk"[..]], layout, ImageFormat::Bmp, None),
+            Err(TilesError::Decode(_))
+        ));
+    }
+
+    #[test]
+    fn split_spreads_remainders_across_pieces() {
+        assert_eq!(split_points(10, 3), [0, 3, 6, 10]);
+        assert_eq!(split_points(9, 3), [0, 3, 6, 9]);
+
+        let image = gradient(10, 7);
+        let mut buf = Cursor::new(Vec::new());
+        image.write_to(&mut buf, image::ImageFormat::Png).unwrap();
+        let pieces = split_image(buf.get_ref(), 2, 3, ImageFormat::Png, None).unwrap();
+        assert_eq!(pieces.len(), 6);
+
+        let sizes: Vec<_> = pieces
+            .iter()
+            .map(|piece| image::load_from_memory(piece).unwrap().into_rgba8())
+            .map(|piece| piece.dimensions())
+            .collect();
+        assert_eq!(sizes, [(3, 3), (3, 3), (4, 3), (3, 4), (3, 4), (4, 4)]);
+
+        let last = image::load_from_memory(&pieces[5]).unwrap().into_rgba8();
+        assert_eq!(last, imageops::crop_imm(&image, 6, 3, 4, 4).to_image());
+    }
+
+    #[test]
+    fn split_rejects_bad_grids() {
+        let input = png(4, 2);
+        assert!(matches!(
+            split_image(&input, 0, 2, ImageFormat::Png, None),
+            Err(TilesError::InvalidGrid)
+        ));
+        assert!(matches!(
+            split_image(&input, 3, 1, ImageFormat::Png, None),
+            Err(TilesError::GridExceedsImage)
+        ));
+    }
+
+    #[test]
+    fn rejects_bad_arguments() {
+        assert!(matches!(
+            generate_tiles(&png(4, 4), 0, ImageFormat::Png, None),
+            Err(TilesError::InvalidTileSize)
+        ));
+        assert!(matches!(
+            generate_tiles(&png(4, 4), 256, ImageFormat::WebP, None),
+            Err(TilesError::Convert(ConvertError::UnsupportedTarget(_)))
+        ));
+    }
+}
@@ -0,0 +1,47 @@
+/// Measures elapsed wall-clock time.
+///
+/// `std::time::Instant` panics on `wasm32-unknown-unknown`, so WASM builds read the
+/// JavaScript clock instead, which has millisecond resolution.
+#[derive(Debug, Clone, Copy)]
+pub struct Stopwatch {
+    #[cfg(not(target_arch = "wasm32"))]
+    start: std::time::Instant,
+    #[cfg(target_arch = "wasm32")]
+    start_ms: f64,
+}
+
+impl Stopwatch {
+    /// Starts timing now.
+    pub fn start() -> Self {
+        Self {
+            #[cfg(not(target_arch = "wasm32"))]
+            start: std::time::Instant::now(),
+            #[cfg(target_arch = "wasm32")]
+            start_ms: js_sys::Date::now(),
+        }
+    }
+
+    /// Milliseconds since [`Stopwatch::start`].
+    pub fn elapsed_ms(&self) -> f64 {
+        #[cfg(not(target_arch = "wasm32"))]
+        let elapsed = self.start.elapsed().as_secs_f64() * 1000.0;
+        #[cfg(target_arch = "wasm32")]
+        let elapsed = js_sys::Date::now() - self.start_ms;
+        elapsed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elapsed_time_grows() {
+        let stopwatch = Stopwatch::start();
+        let first = stopwatch.elapsed_ms();
+        std::thread::sleep(std::time::Duration::from_millis(5));
+        let second = stopwatch.elapsed_ms();
+        assert!(first >= 0.0);
+        assert!(second >= first + 4.0, "{first} {second}");
+    }
+}
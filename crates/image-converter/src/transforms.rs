@@ -1,8 +1,13 @@
 use std::fmt;
 
 use image::DynamicImage;
+use wasm_bindgen::prelude::wasm_bindgen;
 
 /// Supported image transforms that can be applied before format conversion.
+///
+/// Exported to JS as a numeric enum whose values follow declaration order, so new
+/// transforms must be appended.
+#[wasm_bindgen]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Transform {
     /// Mirror the image along the vertical axis (left becomes right).
@@ -22,6 +27,17 @@ pub enum Transform {
 }
 
 impl Transform {
+    /// Every transform, in declaration order.
+    pub const ALL: [Self; 7] = [
+        Self::FlipHorizontal,
+        Self::FlipVertical,
+        Self::Rotate90,
+        Self::Rotate180,
+        Self::Rotate270,
+        Self::Grayscale,
+        Self::Invert,
+    ];
+
     /// Parses a transform name string into a `Transform`.
     ///
     /// Accepts: `"flip_horizontal"`, `"flip_vertical"`, `"rotate_90"`, `"rotate_180"`,
@@ -41,6 +57,19 @@ impl Transform {
         }
     }
 
+    /// The name [`Transform::from_name`] accepts for this transform.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::FlipHorizontal => "flip_horizontal",
+            Self::FlipVertical => "flip_vertical",
+            Self::Rotate90 => "rotate_90",
+            Self::Rotate180 => "rotate_180",
+            Self::Rotate270 => "rotate_270",
+            Self::Grayscale => "grayscale",
+            Self::Invert => "invert",
+        }
+    }
+
     /// Applies this transform to the given image, returning the transformed result.
     pub fn apply(self, img: DynamicImage) -> DynamicImage {
         match self {
@@ -179,6 +208,23 @@ mod tests {
         assert_eq!(Transform::from_name("invert").unwrap(), Transform::Invert);
     }
 
+    #[test]
+    fn name_round_trips() {
+        for transform in Transform::ALL {
+            assert_eq!(Transform::from_name(transform.name()).unwrap(), transform);
+        }
+    }
+
+    #[test]
+    fn all_follows_js_values() {
+        for (index, value) in Transform::ALL.into_iter().enumerate() {
+            // Safe: reading a fieldless enum's discriminant, which is its JS value.
+            #[allow(clippy::as_conversions)]
+            let discriminant = value as usize;
+            assert_eq!(discriminant, index);
+        }
+    }
+
     #[test]
     fn from_name_unknown() {
         let result = Transform::from_name("blur");
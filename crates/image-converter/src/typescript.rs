@@ -0,0 +1,987 @@
+//! TypeScript types for the objects the JS API takes and returns, so the generated
+//! `.d.ts` describes them instead of leaving them as `any`.
+//!
+//! The interfaces mirror the `Serialize` structs they describe; the tests check that
+//! their keys stay in step.
+
+use wasm_bindgen::prelude::wasm_bindgen;
+
+#[wasm_bindgen(typescript_custom_section)]
+const TS_SECTION: &str = TS_DEFINITIONS;
+
+/// The interfaces appended to the generated `.d.ts`, e.g. for hosts that bundle the
+/// typings themselves.
+pub const TS_DEFINITIONS: &str = r#"
+/** Format names the string-based functions accept, in any case and with or without a
+ * leading dot. */
+export type ImageFormatName =
+  | "png" | "jpeg" | "jpg" | "webp" | "gif" | "bmp" | "tiff" | "tif" | "ico" | "tga" | "qoi";
+
+/** Transform names, as `parse_transforms` and `capabilities().operations` use them. */
+export type TransformName =
+  | "flip_horizontal" | "flip_vertical" | "rotate_90" | "rotate_180" | "rotate_270"
+  | "grayscale" | "invert";
+
+/** The options object of `convert_image_with_options` and related functions. Unknown
+ * keys are rejected. */
+export interface ConvertOptions {
+  quality?: number;
+  transforms?: string | (Transform | TransformName)[];
+  png_chunks?: string;
+  png_color_tag?: ColorTag | "auto" | "srgb" | "none";
+  png_indexed?: IndexedPng | "never" | "prefer" | "require";
+  dither_16bit?: boolean;
+  gif_quantizer?: string;
+  gif_dither?: Dither | "none" | "floyd_steinberg" | "fs" | "ordered" | "bayer";
+  deterministic?: boolean;
+  source_format?: ImageFormat | ImageFormatName;
+  icon_size?: number;
+  max_memory_bytes?: number;
+  crop?: CropBox;
+  operations?: OperationStep[];
+  resize?: ResizeStep;
+  watermark?: WatermarkStep;
+  exif?: ExifFields;
+  strip_gps?: boolean;
+}
+
+/** EXIF fields for `set_exif` and the `exif` option. Unset fields are left alone. */
+export interface ExifFields {
+  artist?: string;
+  copyright?: string;
+  /** `"YYYY:MM:DD HH:MM:SS"` or ISO 8601 `"YYYY-MM-DDTHH:MM:SS"`. */
+  date_time?: string;
+  remove_gps?: boolean;
+}
+
+/** A step running an operation registered from Rust, with its parameters. */
+export interface OperationStep {
+  op: string;
+  [param: string]: unknown;
+}
+
+/** The `resize` option: resize the result after the transforms. */
+export interface ResizeStep {
+  mode: "fit" | "fill" | "cover" | "contain" | "long_edge" | "short_edge";
+  width: number;
+  height?: number;
+  no_upscale?: boolean;
+  progressive?: boolean;
+  /** `"transparent"` (default), `"auto"` or a hex color. */
+  fill?: string;
+}
+
+/** The `watermark` option: tile a logo across the result after every other step. */
+export interface WatermarkStep {
+  /** The logo, encoded in any supported input format. */
+  logo: Uint8Array;
+  /** 0 to 1, default 1. */
+  opacity?: number;
+}
+
+export interface Dimensions {
+  width: number;
+  height: number;
+}
+
+export interface ExifField {
+  tag: string;
+  value: string;
+  group: string;
+}
+
+export interface ExifData {
+  camera_make: string | undefined;
+  camera_model: string | undefined;
+  date_time: string | undefined;
+  exposure_time: string | undefined;
+  f_number: string | undefined;
+  iso: string | undefined;
+  focal_length: string | undefined;
+  orientation: string | undefined;
+  software: string | undefined;
+  gps_latitude: number | undefined;
+  gps_longitude: number | undefined;
+  has_gps: boolean;
+  all_fields: ExifField[];
+}
+
+export interface TextChunk {
+  keyword: string;
+  text: string;
+}
+
+export interface ImageMetadata {
+  width: number;
+  height: number;
+  format: string;
+  color_type: string;
+  bits_per_pixel: number;
+  has_alpha: boolean;
+  has_icc_profile: boolean;
+  exif: ExifData;
+  png_text_chunks: TextChunk[];
+}
+
+export interface ImageInspection {
+  format: ImageFormatName;
+  width: number;
+  height: number;
+  frame_count: number;
+  animated: boolean;
+  /** Where an animation's first frame is drawn; `width` and `height` are the canvas. */
+  first_frame: FrameRect | undefined;
+  progressive: boolean;
+  has_alpha: boolean;
+  has_icc_profile: boolean;
+  has_exif: boolean;
+  /** The sizes of an ICO file; the other fields describe the largest. */
+  icon_sizes: IconSize[];
+  decoded_bytes: number;
+}
+
+export interface FrameRect {
+  left: number;
+  top: number;
+  width: number;
+  height: number;
+}
+
+export interface IconSize {
+  width: number;
+  height: number;
+  bits_per_pixel: number;
+  png: boolean;
+}
+
+export interface WorkUnit {
+  index: number;
+  name: string;
+  cost: number;
+}
+
+export interface BatchShard {
+  units: WorkUnit[];
+  cost: number;
+}
+
+export interface BatchPlan {
+  shards: BatchShard[];
+}
+
+export interface FontInfo {
+  name: string;
+  glyph_count: number;
+  scripts: ("latin" | "cyrillic")[];
+}
+
+/** The policy object of `check_policy` and `convert_image_with_policy`. */
+export interface ConversionPolicy {
+  max_width?: number;
+  max_height?: number;
+  allowed_input_formats?: (ImageFormat | ImageFormatName)[];
+  output_format?: ImageFormat | ImageFormatName;
+  strip_metadata?: boolean;
+  min_quality?: number;
+  max_quality?: number;
+}
+
+export interface PolicyViolation {
+  rule: "input_format" | "output_format" | "max_width" | "max_height" | "quality";
+  message: string;
+}
+
+/** An image extracted from a container by `extract_embedded`. */
+export interface EmbeddedImage {
+  kind: "exif_thumbnail" | "icon_image" | "tiff_page" | "raw_preview";
+  index: number;
+  format: ImageFormatName;
+  width: number;
+  height: number;
+  data: Uint8Array;
+}
+
+/** A Photoshop document's headers. `layers` is 0 for flat files. */
+export interface PsdInfo {
+  width: number;
+  height: number;
+  channels: number;
+  depth: 1 | 8 | 16 | 32;
+  color_mode: "bitmap" | "grayscale" | "indexed" | "rgb" | "cmyk" | "multichannel" | "duotone" | "lab";
+  layers: number;
+  has_alpha: boolean;
+  large_document: boolean;
+}
+
+/** A registered preset and the options object it was defined with. */
+export interface PresetInfo {
+  name: string;
+  ops: Record<string, unknown>;
+}
+
+export interface SessionStats {
+  images: number;
+  image_bytes: number;
+  max_image_bytes: number;
+  luts: number;
+  palettes: number;
+  pooled_buffers: number;
+  pooled_bytes: number;
+}
+
+export interface DecodeMemory {
+  width: number;
+  height: number;
+  frames: number;
+  channels: number;
+  bits_per_channel: number;
+  bytes: number;
+}
+
+/** A color as `[r, g, b, a]`, each 0-255. */
+export type Rgba = [number, number, number, number];
+
+export interface PixelSample {
+  x: number;
+  y: number;
+  rgba: Rgba;
+}
+
+/** The samples `sample_region` took, and what they have in common. */
+export interface RegionSamples {
+  samples: PixelSample[];
+  mean: Rgba;
+  most_common: Rgba;
+  most_common_share: number;
+}
+
+/** An image's background color, detected from its border by `detect_background`. */
+export interface Background {
+  rgba: Rgba;
+  confidence: number;
+}
+
+export type LossyStep =
+  | "animation_flattened" | "grayscale" | "bit_depth_reduced" | "alpha_dropped"
+  | "palette_reduced" | "jpeg_compression";
+
+export interface ConversionPlan {
+  input_format: ImageFormatName;
+  target: ImageFormatName;
+  input_width: number;
+  input_height: number;
+  width: number;
+  height: number;
+  frames: number;
+  approx_bytes: number;
+  approx_memory_bytes: number;
+  lossy_steps: LossyStep[];
+}
+
+export interface OpTiming {
+  /** A `TransformName`, a registered operation's name, `"crop"`, `"resize"`,
+   * `"watermark"` or `"dither_16bit"`. */
+  name: string;
+  ms: number;
+}
+
+export interface AppliedOptions {
+  target: ImageFormatName;
+  quality: number | undefined;
+  transforms: TransformName[];
+  dithered_16bit: boolean;
+  indexed_png: boolean;
+  quantized_gif: boolean;
+  png_chunks: string[];
+  deterministic: boolean;
+  reduced_by: number | undefined;
+}
+
+export interface ConversionReport {
+  decode_ms: number;
+  ops: OpTiming[];
+  encode_ms: number;
+  input_bytes: number;
+  output_bytes: number;
+  width: number;
+  height: number;
+  frames: number;
+  peak_memory_bytes: number;
+  applied: AppliedOptions;
+}
+
+/** Thrown by the conversion functions when `max_memory_bytes` is too low for the
+ * input and it can't be decoded at reduced resolution. */
+export interface LimitExceededError extends Error {
+  name: "LimitExceeded";
+  required_bytes: number;
+  limit_bytes: number;
+}
+
+export interface ReportedConversion {
+  data: Uint8Array;
+  report: ConversionReport;
+}
+
+/** The settings `auto_enhance` chose, which `enhance` accepts back after tweaking. */
+export interface Enhancement {
+  white_balance: [number, number, number];
+  black_point: number;
+  white_point: number;
+  vibrance: number;
+  sharpen: number;
+}
+
+export interface EnhancedImage {
+  data: Uint8Array;
+  enhancement: Enhancement;
+}
+
+export interface DecodedRegion {
+  rgba: Uint8Array;
+  width: number;
+  height: number;
+  method: "chunks" | "jpeg_dc" | "full_decode";
+}
+
+export interface QuickPreview {
+  rgba: Uint8Array;
+  width: number;
+  height: number;
+  source_width: number;
+  source_height: number;
+}
+
+export interface PyramidLevel {
+  level: number;
+  width: number;
+  height: number;
+  columns: number;
+  rows: number;
+}
+
+export interface PyramidDescriptor {
+  width: number;
+  height: number;
+  tile_size: number;
+  overlap: number;
+  format: string;
+  levels: PyramidLevel[];
+}
+
+export interface PyramidTile {
+  name: string;
+  level: number;
+  column: number;
+  row: number;
+  data: Uint8Array;
+}
+
+export interface TilePyramid {
+  descriptor: PyramidDescriptor;
+  dzi: string;
+  tiles: PyramidTile[];
+}
+
+export interface TileLayout {
+  width: number;
+  height: number;
+  tile_width: number;
+  tile_height: number;
+}
+
+export interface Deskewed {
+  data: Uint8Array;
+  angle: number;
+}
+
+export interface Margins {
+  left: number;
+  right: number;
+  top: number;
+  bottom: number;
+}
+
+export interface Trimmed {
+  data: Uint8Array;
+  margins: Margins;
+}
+
+export interface Contour {
+  points: [number, number][];
+  closed: boolean;
+}
+
+export interface ExposureStats {
+  mean_luminance: number;
+  clipped_highlights: number;
+  crushed_shadows: number;
+}
+
+export interface CropBox {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+}
+
+export interface Face {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+  score: number;
+}
+
+export interface QrCodeLocation {
+  x: number;
+  y: number;
+  width: number;
+  height: number;
+  module_size: number;
+}
+
+export interface QrCode {
+  payload: string;
+  version: number;
+  error_correction: "L" | "M" | "Q" | "H";
+  location: QrCodeLocation;
+}
+
+export interface DetectedCode {
+  payload: string | undefined;
+  location: QrCodeLocation;
+}
+
+export interface DetectedCodes {
+  codes: DetectedCode[];
+  data: Uint8Array | undefined;
+}
+
+/** A fill for `generate`. Colors are hex strings such as `#ff8000`. */
+export type Fill =
+  | { kind: "solid"; color: string }
+  | { kind: "linear_gradient"; from: string; to: string; angle?: number }
+  | { kind: "radial_gradient"; inner: string; outer: string }
+  | { kind: "checkerboard"; a: string; b: string; cell_size?: number }
+  | { kind: "noise"; seed?: number; from?: string; to?: string };
+
+export interface GenerateSpec {
+  width: number;
+  height: number;
+  fill: Fill;
+}
+
+/** A fill layer for `overlay_fill`. */
+export interface FillLayer {
+  fill: Fill;
+  x?: number;
+  y?: number;
+  width?: number;
+  height?: number;
+  opacity?: number;
+}
+
+/** The options object of `contact_sheet`. */
+export interface ContactSheetOptions {
+  columns?: number;
+  cell_size?: number;
+  gap?: number;
+  labels?: string[];
+  header?: string;
+  background?: string;
+}
+
+/** The options object of `export_with_attribution`. */
+export interface AttributionOptions {
+  corner?: "top_left" | "top_right" | "bottom_left" | "bottom_right";
+  text_height?: number;
+  margin?: number;
+  color?: string;
+  background?: string;
+  fallback?: string;
+}
+
+/** The options object of `tilt_shift`. */
+export interface TiltShiftOptions {
+  shape?: "linear" | "radial";
+  center_x?: number;
+  center_y?: number;
+  band?: number;
+  transition?: number;
+  radius?: number;
+}
+
+export interface ResizeGeometry {
+  scaled_width: number;
+  scaled_height: number;
+  width: number;
+  height: number;
+  x: number;
+  y: number;
+  upscale_prevented: boolean;
+}
+
+export interface Features {
+  threads: boolean;
+  simd: boolean;
+  mozjpeg: boolean;
+  logging: boolean;
+  tracing: boolean;
+  raw_preview: boolean;
+  raw_decode: boolean;
+  faces: boolean;
+}
+
+export interface Capabilities {
+  version: string;
+  /** The built-in formats, then those of any codecs the host registered. */
+  input_formats: string[];
+  hint_required: ImageFormatName[];
+  /** The built-in formats, then those of any codecs the host registered. */
+  output_formats: string[];
+  /** The transforms, then any operations the host registered. */
+  operations: string[];
+  features: Features;
+}
+
+/** What every failing function throws: a plain `Error` whose message says what went
+ * wrong, prefixed with the step that failed (e.g. `"Invalid options: ..."`). */
+export type ImageToolsError = Error;
+"#;
+
+#[wasm_bindgen]
+extern "C" {
+    /// A `ConvertOptions` object (or `undefined`).
+    #[wasm_bindgen(typescript_type = "ConvertOptions")]
+    pub type TsConvertOptions;
+
+    #[wasm_bindgen(typescript_type = "Dimensions")]
+    pub type TsDimensions;
+
+    #[wasm_bindgen(typescript_type = "ImageMetadata")]
+    pub type TsImageMetadata;
+
+    #[wasm_bindgen(typescript_type = "ImageInspection")]
+    pub type TsImageInspection;
+
+    #[wasm_bindgen(typescript_type = "ConversionPlan")]
+    pub type TsConversionPlan;
+
+    #[wasm_bindgen(typescript_type = "ReportedConversion")]
+    pub type TsReportedConversion;
+
+    #[wasm_bindgen(typescript_type = "Enhancement")]
+    pub type TsEnhancement;
+
+    #[wasm_bindgen(typescript_type = "EnhancedImage")]
+    pub type TsEnhancedImage;
+
+    #[wasm_bindgen(typescript_type = "DecodedRegion")]
+    pub type TsDecodedRegion;
+
+    #[wasm_bindgen(typescript_type = "TilePyramid")]
+    pub type TsTilePyramid;
+
+    #[wasm_bindgen(typescript_type = "TileLayout")]
+    pub type TsTileLayout;
+
+    #[wasm_bindgen(typescript_type = "Deskewed")]
+    pub type TsDeskewed;
+
+    #[wasm_bindgen(typescript_type = "Trimmed")]
+    pub type TsTrimmed;
+
+    #[wasm_bindgen(typescript_type = "Contour[]")]
+    pub type TsContours;
+
+    #[wasm_bindgen(typescript_type = "ExposureStats")]
+    pub type TsExposureStats;
+
+    #[wasm_bindgen(typescript_type = "CropBox[]")]
+    pub type TsCropBoxes;
+
+    #[wasm_bindgen(typescript_type = "CropBox")]
+    pub type TsCropBox;
+
+    #[wasm_bindgen(typescript_type = "Face[]")]
+    pub type TsFaces;
+
+    #[wasm_bindgen(typescript_type = "Rgba")]
+    pub type TsRgba;
+
+    #[wasm_bindgen(typescript_type = "RegionSamples")]
+    pub type TsRegionSamples;
+
+    #[wasm_bindgen(typescript_type = "Background")]
+    pub type TsBackground;
+
+    #[wasm_bindgen(typescript_type = "QrCodeLocation[]")]
+    pub type TsQrCodeLocations;
+
+    #[wasm_bindgen(typescript_type = "QrCode[]")]
+    pub type TsQrCodes;
+
+    #[wasm_bindgen(typescript_type = "DetectedCodes")]
+    pub type TsDetectedCodes;
+
+    #[wasm_bindgen(typescript_type = "GenerateSpec")]
+    pub type TsGenerateSpec;
+
+    #[wasm_bindgen(typescript_type = "FillLayer")]
+    pub type TsFillLayer;
+
+    #[wasm_bindgen(typescript_type = "ContactSheetOptions")]
+    pub type TsContactSheetOptions;
+
+    #[wasm_bindgen(typescript_type = "AttributionOptions")]
+    pub type TsAttributionOptions;
+
+    #[wasm_bindgen(typescript_type = "TiltShiftOptions")]
+    pub type TsTiltShiftOptions;
+
+    #[wasm_bindgen(typescript_type = "ResizeGeometry")]
+    pub type TsResizeGeometry;
+
+    #[wasm_bindgen(typescript_type = "DecodeMemory")]
+    pub type TsDecodeMemory;
+
+    #[wasm_bindgen(typescript_type = "QuickPreview")]
+    pub type TsQuickPreview;
+
+    #[wasm_bindgen(typescript_type = "BatchPlan")]
+    pub type TsBatchPlan;
+
+    #[wasm_bindgen(typescript_type = "FontInfo")]
+    pub type TsFontInfo;
+
+    #[wasm_bindgen(typescript_type = "FontInfo[]")]
+    pub type TsFontInfos;
+
+    #[wasm_bindgen(typescript_type = "ConversionPolicy")]
+    pub type TsConversionPolicy;
+
+    #[wasm_bindgen(typescript_type = "PolicyViolation[]")]
+    pub type TsPolicyViolations;
+
+    #[wasm_bindgen(typescript_type = "EmbeddedImage[]")]
+    pub type TsEmbeddedImages;
+
+    #[wasm_bindgen(typescript_type = "ExifFields")]
+    pub type TsExifFields;
+
+    #[wasm_bindgen(typescript_type = "PsdInfo")]
+    pub type TsPsdInfo;
+
+    #[wasm_bindgen(typescript_type = "PresetInfo[]")]
+    pub type TsPresetInfos;
+
+    #[wasm_bindgen(typescript_type = "SessionStats")]
+    pub type TsSessionStats;
+
+    #[wasm_bindgen(typescript_type = "Capabilities")]
+    pub type TsCapabilities;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use serde::Serialize;
+
+    use super::*;
+    use crate::convert::{self, AppliedOptions, ConversionReport, Dimensions, OpTiming};
+    use crate::formats::ImageFormat;
+    use crate::metadata::{self, ExifData, ExifField, ImageMetadata, TextChunk};
+    use crate::{
+        adjust, animation, batch, canvas, capabilities, codes, edges, fonts, policy, presets, psd,
+        qr, resize, sample, session, smart_crop, stats, tiles,
+    };
+
+    /// The keys declared by `interface name` in [`TS_DEFINITIONS`].
+    fn interface_keys(name: &str) -> BTreeSet<String> {
+        let header = format!("export interface {name} {{");
+        let body = TS_DEFINITIONS
+            .split_once(&header)
+            .and_then(|(_, rest)| rest.split_once('}'))
+            .map(|(body, _)| body)
+            .unwrap_or_else(|| panic!("no interface {name}"));
+        body.lines()
+            .filter_map(|line| line.trim().split_once(':'))
+            .map(|(key, _)| key.trim_end_matches('?').to_owned())
+            .collect()
+    }
+
+    fn serialized_keys(value: &impl Serialize) -> BTreeSet<String> {
+        match serde_json::to_value(value).unwrap() {
+            serde_json::Value::Object(map) => map.keys().cloned().collect(),
+            other => panic!("not an object: {other}"),
+        }
+    }
+
+    fn png() -> Vec<u8> {
+        let mut buf = std::io::Cursor::new(Vec::new());
+        image::RgbaImage::new(2, 2)
+            .write_to(&mut buf, image::ImageFormat::Png)
+            .unwrap();
+        buf.into_inner()
+    }
+
+    #[test]
+    fn interfaces_match_serialized_structs() {
+        let png = png();
+        let caps = capabilities::capabilities();
+        let plan = convert::plan(
+            &png,
+            ImageFormat::Jpeg,
+            &convert::ConvertOptions::default(),
+            &[],
+        )
+        .unwrap();
+        let pyramid = tiles::generate_tiles(&png, 256, ImageFormat::Png, None).unwrap();
+        let cases = [
+            (
+                "Dimensions",
+                serialized_keys(&Dimensions {
+                    width: 1,
+                    height: 1,
+                }),
+            ),
+            ("ImageMetadata", serialized_keys(&ImageMetadata::default())),
+            ("ExifData", serialized_keys(&ExifData::default())),
+            (
+                "ExifField",
+                serialized_keys(&ExifField {
+                    tag: String::new(),
+                    value: String::new(),
+                    group: String::new(),
+                }),
+            ),
+            (
+                "TextChunk",
+                serialized_keys(&TextChunk {
+                    keyword: String::new(),
+                    text: String::new(),
+                }),
+            ),
+            (
+                "ImageInspection",
+                serialized_keys(&metadata::inspect(&png).unwrap()),
+            ),
+            (
+                "PixelSample",
+                serialized_keys(&sample::PixelSample {
+                    x: 0,
+                    y: 0,
+                    rgba: [0; 4],
+                }),
+            ),
+            (
+                "RegionSamples",
+                serialized_keys(&sample::RegionSamples {
+                    samples: Vec::new(),
+                    mean: [0; 4],
+                    most_common: [0; 4],
+                    most_common_share: 0.0,
+                }),
+            ),
+            (
+                "Background",
+                serialized_keys(&sample::Background {
+                    rgba: [0; 4],
+                    confidence: 0.0,
+                }),
+            ),
+            (
+                "FrameRect",
+                serialized_keys(&animation::FrameRect {
+                    left: 0,
+                    top: 0,
+                    width: 0,
+                    height: 0,
+                }),
+            ),
+            (
+                "DecodeMemory",
+                serialized_keys(&metadata::estimate_decode_memory(&png).unwrap()),
+            ),
+            (
+                "FontInfo",
+                serialized_keys(&fonts::FontInfo {
+                    name: String::new(),
+                    glyph_count: 0,
+                    scripts: Vec::new(),
+                }),
+            ),
+            (
+                "PolicyViolation",
+                serialized_keys(&policy::Violation {
+                    rule: policy::Rule::Quality,
+                    message: String::new(),
+                }),
+            ),
+            (
+                "PsdInfo",
+                serialized_keys(&psd::PsdInfo {
+                    width: 0,
+                    height: 0,
+                    channels: 0,
+                    depth: 8,
+                    color_mode: "rgb",
+                    layers: 0,
+                    has_alpha: false,
+                    large_document: false,
+                }),
+            ),
+            (
+                "PresetInfo",
+                serialized_keys(&presets::PresetInfo {
+                    name: String::new(),
+                    ops: serde_json::Value::Null,
+                }),
+            ),
+            (
+                "SessionStats",
+                serialized_keys(&session::Session::new().stats()),
+            ),
+            ("ConversionPlan", serialized_keys(&plan)),
+            (
+                "Enhancement",
+                serialized_keys(&adjust::Enhancement::default()),
+            ),
+            (
+                "ConversionReport",
+                serialized_keys(&ConversionReport::default()),
+            ),
+            (
+                "AppliedOptions",
+                serialized_keys(&AppliedOptions::default()),
+            ),
+            (
+                "OpTiming",
+                serialized_keys(&OpTiming {
+                    name: "invert",
+                    ms: 0.0,
+                }),
+            ),
+            ("PyramidDescriptor", serialized_keys(&pyramid.descriptor)),
+            (
+                "PyramidLevel",
+                serialized_keys(&pyramid.descriptor.levels[0]),
+            ),
+            (
+                "TileLayout",
+                serialized_keys(&tiles::TileLayout {
+                    width: 1,
+                    height: 1,
+                    tile_width: 1,
+                    tile_height: 1,
+                }),
+            ),
+            ("Margins", serialized_keys(&canvas::Margins::default())),
+            (
+                "WorkUnit",
+                serialized_keys(&batch::WorkUnit {
+                    index: 0,
+                    name: String::new(),
+                    cost: 0,
+                }),
+            ),
+            ("BatchShard", serialized_keys(&batch::Shard::default())),
+            (
+                "BatchPlan",
+                serialized_keys(&batch::BatchPlan { shards: Vec::new() }),
+            ),
+            (
+                "Contour",
+                serialized_keys(&edges::Contour {
+                    points: Vec::new(),
+                    closed: false,
+                }),
+            ),
+            (
+                "ExposureStats",
+                serialized_keys(&stats::exposure(&image::load_from_memory(&png).unwrap())),
+            ),
+            (
+                "CropBox",
+                serialized_keys(&smart_crop::CropBox {
+                    x: 0,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                }),
+            ),
+            (
+                "QrCodeLocation",
+                serialized_keys(&codes::QrCodeLocation {
+                    x: 0,
+                    y: 0,
+                    width: 1,
+                    height: 1,
+                    module_size: 1.0,
+                }),
+            ),
+            (
+                "QrCode",
+                serialized_keys(&qr::QrCode {
+                    payload: String::new(),
+                    version: 1,
+                    error_correction: qr::ErrorCorrection::L,
+                    location: codes::QrCodeLocation {
+                        x: 0,
+                        y: 0,
+                        width: 1,
+                        height: 1,
+                        module_size: 1.0,
+                    },
+                }),
+            ),
+            (
+                "DetectedCode",
+                serialized_keys(&codes::DetectedCode {
+                    payload: None,
+                    location: codes::QrCodeLocation {
+                        x: 0,
+                        y: 0,
+                        width: 1,
+                        height: 1,
+                        module_size: 1.0,
+                    },
+                }),
+            ),
+            (
+                "ResizeGeometry",
+                serialized_keys(
+                    &resize::geometry(2, 1, resize::ResizeMode::Fit, 1, 1, false).unwrap(),
+                ),
+            ),
+            ("Capabilities", serialized_keys(&caps)),
+            ("Features", serialized_keys(&caps.features)),
+        ];
+        for (name, keys) in cases {
+            assert_eq!(interface_keys(name), keys, "interface {name}");
+        }
+        #[cfg(feature = "faces")]
+        assert_eq!(
+            interface_keys("Face"),
+            serialized_keys(&crate::faces::Face {
+                x: 0,
+                y: 0,
+                width: 1,
+                height: 1,
+                score: 1.0,
+            }),
+        );
+    }
+
+    #[test]
+    fn name_unions_cover_every_variant() {
+        for format in ImageFormat::ALL {
+            assert!(TS_DEFINITIONS.contains(&format!("\"{}\"", format.as_str())));
+        }
+        for transform in crate::transforms::Transform::ALL {
+            assert!(TS_DEFINITIONS.contains(&format!("\"{}\"", transform.name())));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::fmt;
+
+use image::{DynamicImage, Rgba, RgbaImage};
+
+use crate::convert::{self, ConvertError};
+use crate::formats::ImageFormat;
+
+/// Largest side a warped image may have, so a stray corner can't ask for an image
+/// that exhausts memory.
+pub const MAX_WARP_SIDE: u32 = 16_384;
+
+/// Maps output coordinates back to the source image.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Mapping {
+    /// `(a u + b v + c, d u + e v + f) / (g u + h v + 1)` for `u`, `v` in 0..=1 across
+    /// the output.
+    Perspective([f64; 8]),
+    /// `(a x + b y + c, d x + e y + f)` for output pixel coordinates `x`, `y`.
+    Affine([f64; 6]),
+}
+
+impl Mapping {
+    /// The homography taking the unit square onto `corners` (top-left, top-right,
+    /// bottom-right, bottom-left), after Heckbert's square-to-quad construction.
+    fn square_to_quad(corners: [[f64; 2]; 4]) -> Option<Self> {
+        let [[x0, y0], [x1, y1], [x2, y2], [x3, y3]] = corners;
+        let sx = x0 - x1 + x2 - x3;
+        let sy = y0 - y1 + y2 - y3;
+        let (dx1, dx2, dy1, dy2) = (x1 - x2, x3 - x2, y1 - y2, y3 - y2);
+        let den = dx1 * dy2 - dx2 * dy1;
+        if den.abs() < f64::EPSILON {
+            return None;
+        }
+        let g = (sx * dy2 - dx2 * sy) / den;
+        let h = (dx1 * sy - sx * dy1) / den;
+        Some(Self::Perspective([
+            x1 - x0 + g * x1,
+            x3 - x0 + h * x3,
+            x0,
+            y1 - y0 + g * y1,
+            y3 - y0 + h * y3,
+            y0,
+            g,
+            h,
+        ]))
+    }
+
+    /// The inverse of the forward affine `matrix`, so output pixels can look up their
+    /// source.
+    fn inverse_affine(matrix: [f64; 6]) -> Option<Self> {
+        let [a, b, c, d, e, f] = matrix;
+        let det = a * e - b * d;
+        if det.abs() < f64::EPSILON {
+            return None;
+        }
+        Some(Self::Affine([
+            e / det,
+            -b / det,
+            (b * f - c * e) / det,
+            -d / det,
+            a / det,
+            (c * d - a * f) / det,
+        ]))
+    }
+
+    /// The source point for the center of output pixel `x`, `y`.
+    fn source(&self, x: u32, y: u32, width: u32, height: u32) -> Option<(f64, f64)> {
+        let (x, y) = (f64::from(x) + 0.5, f64::from(y) + 0.5);
+        match *self {
+            Self::Perspective([a, b, c, d, e, f, g, h]) => {
+                let (u, v) = (x / f64::from(width), y / f64::from(height));
+                let w = g * u + h * v + 1.0;
+                (w.abs() > f64::EPSILON).then(|| ((a * u + b * v + c) / w, (d * u + e * v + f) / w))
+            }
+            Self::Affine([a, b, c, d, e, f]) => Some((a * x + b * y + c, d * x + e * y + f)),
+        }
+    }
+}
+
+/// Rectifies the quadrilateral `corners` of `img` (top-left, top-right, bottom-right,
+/// bottom-left, in pixels) into a `width` x `height` image, e.g. a document or
+/// whiteboard photographed at an angle.
+///
+/// # Errors
+///
+/// Returns a `WarpError` if a corner isn't finite, the corners are degenerate (three
+/// in a line), or a dimension is zero or larger than [`MAX_WARP_SIDE`].
+pub fn perspective(
+    img: &RgbaImage,
+    corners: [[f64; 2]; 4],
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, WarpError> {
+    if !corners.iter().flatten().all(|value| value.is_finite()) {
+        return Err(WarpError::InvalidCorners);
+    }
+    check_size(width, height)?;
+    let mapping = Mapping::square_to_quad(corners).ok_or(WarpError::InvalidCorners)?;
+    Ok(resample(img, mapping, width, height))
+}
+
+/// The output size that keeps the quadrilateral's longer edges at their length in
+/// the photo, for [`perspective`] calls that don't set one.
+pub fn rectified_size(corners: [[f64; 2]; 4]) -> (u32, u32) {
+    let [tl, tr, br, bl] = corners;
+    let length = |[x0, y0]: [f64; 2], [x1, y1]: [f64; 2]| (x1 - x0).hypot(y1 - y0);
+    let width = length(tl, tr).max(length(bl, br));
+    let height = length(tl, bl).max(length(tr, br));
+    (to_side(width), to_side(height))
+}
+
+/// Applies the forward affine `matrix` `[a, b, c, d, e, f]`, which moves source point
+/// `(x, y)` to `(a x + b y + c, d x + e y + f)`, into a `width` x `height` image.
+/// Rotation, scaling, shear and translation are all affine.
+///
+/// # Errors
+///
+/// Returns a `WarpError` if the matrix isn't finite or can't be inverted, or a
+/// dimension is zero or larger than [`MAX_WARP_SIDE`].
+pub fn affine(
+    img: &RgbaImage,
+    matrix: [f64; 6],
+    width: u32,
+    height: u32,
+) -> Result<RgbaImage, WarpError> {
+    if !matrix.iter().all(|value| value.is_finite()) {
+        return Err(WarpError::InvalidMatrix);
+    }
+    check_size(width, height)?;
+    let mapping = Mapping::inverse_affine(matrix).ok_or(WarpError::InvalidMatrix)?;
+    Ok(resample(img, mapping, width, height))
+}
+
+fn check_size(width: u32, height: u32) -> Result<(), WarpError> {
+    if (1..=MAX_WARP_SIDE).contains(&width) && (1..=MAX_WARP_SIDE).contains(&height) {
+        Ok(())
+    } else {
+        Err(WarpError::InvalidSize { width, height })
+    }
+}
+
+// Safe: the value is rounded and clamped to 1..=MAX_WARP_SIDE (NaN saturates to 0 and
+// is clamped too) before the cast.
+#[allow(clippy::as_conversions)]
+fn to_side(length: f64) -> u32 {
+    (length.round() as u32).clamp(1, MAX_WARP_SIDE)
+}
+
+fn resample(img: &RgbaImage, mapping: Mapping, width: u32, height: u32) -> RgbaImage {
+    RgbaImage::from_fn(width, height, |x, y| {
+        mapping
+            .source(x, y, width, height)
+            .map_or(Rgba([0; 4]), |(sx, sy)| sample_bilinear(img, sx, sy))
+    })
+}
+
+/// Samples `img` at the continuous point `x`, `y` (pixel centers sit at half
+/// coordinates), blending the four nearest pixels with premultiplied alpha so
+/// transparent neighbours don't darken edges. Points outside the image are
+/// transparent; within the outer half pixel the edge pixels are repeated.
+fn sample_bilinear(img: &RgbaImage, x: f64, y: f64) -> Rgba<u8> {
+    let (width, height) = (f64::from(img.width()), f64::from(img.height()));
+    if !(0.0..=width).contains(&x) || !(0.0..=height).contains(&y) {
+        return Rgba([0; 4]);
+    }
+    let (x, y) = (x - 0.5, y - 0.5);
+    let (x0, y0) = (x.floor(), y.floor());
+    let (fx, fy) = (x - x0, y - y0);
+
+    let (mut r, mut g, mut b, mut alpha) = (0.0, 0.0, 0.0, 0.0);
+    for (dx, dy, weight) in [
+        (0.0, 0.0, (1.0 - fx) * (1.0 - fy)),
+        (1.0, 0.0, fx * (1.0 - fy)),
+        (0.0, 1.0, (1.0 - fx) * fy),
+        (1.0, 1.0, fx * fy),
+    ] {
+        let Some(pixel) = pixel_at(
+            img,
+            (x0 + dx).clamp(0.0, width - 1.0),
+            (y0 + dy).clamp(0.0, height - 1.0),
+        ) else {
+            continue;
+        };
+        let [red, green, blue, opacity] = pixel.0;
+        let coverage = f64::from(opacity) / 255.0 * weight;
+        r += f64::from(red) * coverage;
+        g += f64::from(green) * coverage;
+        b += f64::from(blue) * coverage;
+        alpha += coverage;
+    }
+
+    if alpha <= 0.0 {
+        return Rgba([0; 4]);
+    }
+    Rgba([
+        crate::color::to_u8(r / alpha),
+        crate::color::to_u8(g / alpha),
+        crate::color::to_u8(b / alpha),
+        crate::color::to_u8(alpha * 255.0),
+    ])
+}
+
+/// The pixel at whole coordinates `x`, `y`, if inside the image.
+// Safe: the coordinates are checked to lie within the image's u32 dimensions first.
+#[allow(clippy::as_conversions)]
+fn pixel_at(img: &RgbaImage, x: f64, y: f64) -> Option<&Rgba<u8>> {
+    if x < 0.0 || y < 0.0 || x >= f64::from(img.width()) || y >= f64::from(img.height()) {
+        return None;
+    }
+    img.get_pixel_checked(x as u32, y as u32)
+}
+
+/// Decodes `input`, rectifies the quadrilateral `corners` with [`perspective`], and
+/// encodes the result as `target`. `size` defaults to [`rectified_size`].
+///
+/// # Errors
+///
+/// Returns a `WarpError` if the corners or size are invalid, or decoding or encoding
+/// fails.
+pub fn rectify(
+    input: &[u8],
+    corners: [[f64; 2]; 4],
+    size: Option<(u32, u32)>,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, WarpError> {
+    let img = image::load_from_memory(input)
+        .map_err(WarpError::Decode)?
+        .into_rgba8();
+    let (width, height) = size.unwrap_or_else(|| rectified_size(corners));
+    let warped = perspective(&img, corners, width, height)?;
+    convert::encode(&DynamicImage::ImageRgba8(warped), target, quality).map_err(WarpError::Convert)
+}
+
+/// Decodes `input`, applies the affine `matrix` with [`affine`] into an image of
+/// `size` (the input's size by default), and encodes the result as `target`.
+///
+/// # Errors
+///
+/// Returns a `WarpError` if the matrix or size is invalid, or decoding or encoding
+/// fails.
+pub fn warp_affine(
+    input: &[u8],
+    matrix: [f64; 6],
+    size: Option<(u32, u32)>,
+    target: ImageFormat,
+    quality: Option<u8>,
+) -> Result<Vec<u8>, WarpError> {
+    let img = image::load_from_memory(input)
+        .map_err(WarpError::Decode)?
+        .into_rgba8();
+    let (width, height) = size.unwrap_or_else(|| img.dimensions());
+    let warped = affine(&img, matrix, width, height)?;
+    convert::encode(&DynamicImage::ImageRgba8(warped), target, quality).map_err(WarpError::Convert)
+}
+
+/// Errors that can occur while warping an image.
+#[derive(Debug)]
+pub enum WarpError {
+    /// The corners weren't four finite points forming a quadrilateral.
+    InvalidCorners,
+    /// The affine matrix wasn't six finite numbers or couldn't be inverted.
+    InvalidMatrix,
+    /// An output dimension was zero or larger than [`MAX_WARP_SIDE`].
+    InvalidSize { width: u32, height: u32 },
+    /// Failed to decode the input image.
+    Decode(image::ImageError),
+    /// Failed to encode the output image.
+    Convert(ConvertError),
+}
+
+impl fmt::Display for WarpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidCorners => write!(
+                f,
+                "Corners must be four finite points, no three of them in a line"
+            ),
+            Self::InvalidMatrix => {
+                write!(f, "Affine matrix must be six finite numbers and invertible")
+            }
+            Self::InvalidSize { width, height } => write!(
+                f,
+                "Output size must be between 1 and {MAX_WARP_SIDE} on each side, got {width}x{height}"
+            ),
+            Self::Decode(e) => write!(f, "Failed to decode image: {e}"),
+            Self::Convert(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for WarpError {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Safe: modulo 256 guarantees values fit in u8.
+    #[allow(clippy::as_conversions)]
+    fn pattern(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([(x * 13 % 256) as u8, (y * 29 % 256) as u8, 90, 255])
+        })
+    }
+
+    #[test]
+    fn perspective_with_the_image_corners_is_identity() {
+        let img = pattern(16, 12);
+        let corners = [[0.0, 0.0], [16.0, 0.0], [16.0, 12.0], [0.0, 12.0]];
+        assert_eq!(perspective(&img, corners, 16, 12).unwrap(), img);
+        assert_eq!(rectified_size(corners), (16, 12));
+    }
+
+    #[test]
+    fn perspective_rectifies_a_keystoned_quad() {
+        // A trapezoid narrower at the top, symmetric about a white line at x = 20.
+        let img = RgbaImage::from_fn(40, 40, |x, _| {
+            if x == 19 || x == 20 {
+                Rgba([255; 4])
+            } else {
+                Rgba([0, 0, 0, 255])
+            }
+        });
+        let corners = [[12.0, 8.0], [28.0, 8.0], [36.0, 32.0], [4.0, 32.0]];
+        let rectified = perspective(&img, corners, 21, 21).unwrap();
+        for y in 0..21 {
+            assert_eq!(rectified.get_pixel(10, y).0, [255; 4], "row {y}");
+            assert_eq!(rectified.get_pixel(0, y).0, [0, 0, 0, 255], "row {y}");
+        }
+    }
+
+    #[test]
+    fn affine_translates_and_scales() {
+        let img = pattern(8, 8);
+        let shifted = affine(&img, [1.0, 0.0, 2.0, 0.0, 1.0, 3.0], 8, 8).unwrap();
+        assert_eq!(shifted.get_pixel(5, 6), img.get_pixel(3, 3));
+        assert_eq!(shifted.get_pixel(0, 0).0, [0; 4]);
+
+        let doubled = affine(&img, [2.0, 0.0, 0.0, 0.0, 2.0, 0.0], 16, 16).unwrap();
+        assert_eq!(doubled.get_pixel(0, 0), img.get_pixel(0, 0));
+        assert_eq!(doubled.get_pixel(15, 15), img.get_pixel(7, 7));
+    }
+
+    #[test]
+    fn bilinear_blends_neighbours_without_dark_fringes() {
+        let mut img = RgbaImage::from_pixel(2, 1, Rgba([0, 0, 0, 0]));
+        img.put_pixel(0, 0, Rgba([200, 100, 50, 255]));
+        let between = sample_bilinear(&img, 1.0, 0.5);
+        assert_eq!(between.0, [200, 100, 50, 128]);
+    }
+
+    #[test]
+    fn rejects_degenerate_input() {
+        let img = pattern(4, 4);
+        let collinear = [[0.0, 0.0], [1.0, 1.0], [2.0, 2.0], [3.0, 3.0]];
+        assert!(matches!(
+            perspective(&img, collinear, 4, 4),
+            Err(WarpError::InvalidCorners)
+        ));
+        let corners = [[0.0, 0.0], [4.0, 0.0], [4.0, f64::NAN], [0.0, 4.0]];
+        assert!(matches!(
+            perspective(&img, corners, 4, 4),
+            Err(WarpError::InvalidCorners)
+        ));
+        assert!(matches!(
+            affine(&img, [1.0, 2.0, 0.0, 2.0, 4.0, 0.0], 4, 4),
+            Err(WarpError::InvalidMatrix)
+        ));
+        assert!(matches!(
+            affine(&img, [1.0, 0.0, 0.0, 0.0, 1.0, 0.0], 0, 4),
+            Err(WarpError::InvalidSize { .. })
+        ));
+    }
+}
@@ -0,0 +1,543 @@
+use std::fmt;
+
+use image::codecs::webp::WebPEncoder;
+use image::{imageops, Delay, ExtendedColorType, Frame, RgbaImage};
+use serde::Serialize;
+
+use crate::animation::{self, AnimationError, FrameRef};
+use crate::quantize::{self, Dither, QuantizeError, QuantizeOptions, Quantizer};
+
+/// Largest canvas dimension the WebP container can describe.
+const MAX_WEBP_DIMENSION: u32 = 16384;
+
+/// VP8X feature flag: the file contains an alpha channel.
+const VP8X_ALPHA: u8 = 0x10;
+/// VP8X feature flag: the file is an animation.
+const VP8X_ANIMATION: u8 = 0x02;
+/// ANMF flag: overwrite the frame rectangle instead of alpha-blending onto the canvas.
+const ANMF_NO_BLEND: u8 = 0x02;
+
+/// Reduction steps tried, in order, when a byte budget is set: (colors shared by all
+/// frames, or `None` to keep them all; keep every Nth frame). Color reduction comes
+/// first because it is less noticeable than dropping frames, and at 256 colors or
+/// fewer the lossless encoder stores each frame as palette indices.
+const BUDGET_LADDER: [(Option<u16>, usize); 8] = [
+    (None, 1),
+    (Some(256), 1),
+    (Some(64), 1),
+    (Some(64), 2),
+    (Some(32), 2),
+    (Some(32), 3),
+    (Some(16), 3),
+    (Some(16), 4),
+];
+
+/// An animated WebP produced by [`to_animated_webp`] and how it was reduced.
+#[derive(Debug, Clone)]
+pub struct AnimatedWebp {
+    /// The encoded WebP bytes.
+    pub data: Vec<u8>,
+    /// The reductions that were applied to reach the size budget.
+    pub report: WebpBudgetReport,
+}
+
+/// Summary of the reductions applied while re-encoding an animation as WebP.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WebpBudgetReport {
+    pub input_frames: usize,
+    pub output_frames: usize,
+    /// Consecutive identical frames that were merged into one longer frame.
+    pub duplicate_frames_merged: usize,
+    /// Only every `frame_step`-th frame was kept (1 = all frames).
+    pub frame_step: usize,
+    /// Colors the frames were quantized to, with one Wu palette shared by every
+    /// frame; `None` when they kept all their colors.
+    pub max_colors: Option<u16>,
+    pub output_bytes: usize,
+    /// `false` if even the most aggressive reduction exceeded the budget.
+    pub met_target: bool,
+}
+
+/// Re-encodes an animated GIF/WebP/APNG as a lossless animated WebP, optionally
+/// shrinking it to fit within `max_bytes`.
+///
+/// Consecutive duplicate frames are always merged. When `max_bytes` is set and the
+/// first attempt is too large, the encoder walks a fixed ladder of reductions —
+/// fewer colors, quantized with [`Quantizer::Wu`] to a palette shared by every frame,
+/// then keeping only every Nth frame (with delays merged so total duration is
+/// preserved) — and returns the first result that fits.
+/// If nothing fits, the smallest attempt is returned with `met_target: false`.
+///
+/// # Errors
+///
+/// Returns a `WebpAnimError` if the input cannot be decoded, the canvas exceeds
+/// WebP's 16384 px limit, or a frame fails to encode.
+pub fn to_animated_webp(
+    input: &[u8],
+    max_bytes: Option<usize>,
+) -> Result<AnimatedWebp, WebpAnimError> {
+    let frames = animation::decode_frames(input).map_err(WebpAnimError::Animation)?;
+    let input_frames = frames.len();
+
+    let mut best: Option<AnimatedWebp> = None;
+    for (max_colors, frame_step) in BUDGET_LADDER {
+        let reduced = reduce_frames(&frames, max_colors, frame_step)?;
+        let output_frames = reduced.len();
+        let data = encode_animated_webp(&reduced)?;
+        let met_target = max_bytes.is_none_or(|max| data.len() <= max);
+        let attempt = AnimatedWebp {
+            report: WebpBudgetReport {
+                input_frames,
+                output_frames,
+                duplicate_frames_merged: input_frames.div_ceil(frame_step) - output_frames,
+                frame_step,
+                max_colors,
+                output_bytes: data.len(),
+                met_target,
+            },
+            data,
+        };
+
+        if met_target {
+            return Ok(attempt);
+        }
+        if best
+            .as_ref()
+            .is_none_or(|b| attempt.data.len() < b.data.len())
+        {
+            best = Some(attempt);
+        }
+    }
+
+    best.ok_or(WebpAnimError::NoFrames)
+}
+
+/// Encodes full-canvas frames as a looping lossless animated WebP.
+///
+/// Each frame after the first is stored as the smallest even-aligned rectangle that
+/// differs from the previous frame, written without blending so it replaces that
+/// area of the canvas. All frames must share the first frame's dimensions.
+///
+/// # Errors
+///
+/// Returns a `WebpAnimError` if there are no frames, the canvas is too large for
+/// WebP, or a frame fails to encode.
+pub fn encode_animated_webp(frames: &[Frame]) -> Result<Vec<u8>, WebpAnimError> {
+    encode_frame_refs(&animation::frame_refs(frames))
+}
+
+/// [`encode_animated_webp`] for borrowed frames. A frame repeated from the one before
+/// costs a 1x1 rectangle.
+///
+/// # Errors
+///
+/// As for [`encode_animated_webp`].
+pub fn encode_frame_refs(frames: &[FrameRef<'_>]) -> Result<Vec<u8>, WebpAnimError> {
+    let (first, _) = frames.first().ok_or(WebpAnimError::NoFrames)?;
+    let (width, height) = first.dimensions();
+    if width == 0 || height == 0 || width > MAX_WEBP_DIMENSION || height > MAX_WEBP_DIMENSION {
+        return Err(WebpAnimError::TooLarge);
+    }
+
+    let has_alpha = frames
+        .iter()
+        .any(|(buffer, _)| buffer.pixels().any(|p| p.0[3] < 255));
+
+    let mut body = Vec::new();
+    body.extend_from_slice(b"WEBP");
+
+    let mut vp8x = vec![
+        VP8X_ANIMATION | if has_alpha { VP8X_ALPHA } else { 0 },
+        0,
+        0,
+        0,
+    ];
+    push_u24(&mut vp8x, width - 1);
+    push_u24(&mut vp8x, height - 1);
+    push_chunk(&mut body, *b"VP8X", &vp8x)?;
+
+    // Transparent background, loop forever.
+    push_chunk(&mut body, *b"ANIM", &[0, 0, 0, 0, 0, 0])?;
+
+    let mut previous: Option<&RgbaImage> = None;
+    for &(buffer, delay_ms) in frames {
+        if buffer.dimensions() != (width, height) {
+            return Err(WebpAnimError::FrameSizeMismatch);
+        }
+
+        let (x, y, w, h) = match previous {
+            Some(prev) => changed_rect(prev, buffer).unwrap_or((0, 0, 1, 1)),
+            None => (0, 0, width, height),
+        };
+        let region = imageops::crop_imm(buffer, x, y, w, h).to_image();
+        let vp8l = encode_vp8l(&region)?;
+
+        let mut anmf = Vec::with_capacity(16 + vp8l.len() + 8);
+        push_u24(&mut anmf, x / 2);
+        push_u24(&mut anmf, y / 2);
+        push_u24(&mut anmf, w - 1);
+        push_u24(&mut anmf, h - 1);
+        push_u24(&mut anmf, delay_ms.min(0x00FF_FFFF));
+        anmf.push(ANMF_NO_BLEND);
+        push_chunk(&mut anmf, *b"VP8L", &vp8l)?;
+        push_chunk(&mut body, *b"ANMF", &anmf)?;
+
+        previous = Some(buffer);
+    }
+
+    let mut out = Vec::with_capacity(body.len() + 8);
+    out.extend_from_slice(b"RIFF");
+    let riff_len = u32::try_from(body.len()).map_err(|_| WebpAnimError::TooLarge)?;
+    out.extend_from_slice(&riff_len.to_le_bytes());
+    out.extend_from_slice(&body);
+    Ok(out)
+}
+
+/// Applies one step of the budget ladder: decimate, quantize the kept frames to
+/// `max_colors` shared colors, then merge duplicates. Delays that would overflow
+/// saturate at `u32::MAX` ms.
+fn reduce_frames(
+    frames: &[Frame],
+    max_colors: Option<u16>,
+    frame_step: usize,
+) -> Result<Vec<Frame>, WebpAnimError> {
+    let mut kept: Vec<&RgbaImage> = Vec::new();
+    let mut delays: Vec<u32> = Vec::new();
+    for chunk in frames.chunks(frame_step.max(1)) {
+        let Some(first) = chunk.first() else {
+            continue;
+        };
+        kept.push(first.buffer());
+        delays.push(
+            chunk
+                .iter()
+                .map(animation::frame_delay_ms)
+                .fold(0, u32::saturating_add),
+        );
+    }
+    let buffers = match max_colors {
+        Some(max_colors) => {
+            let options = QuantizeOptions {
+                max_colors,
+                // Dithering noise differs from frame to frame, which defeats both
+                // duplicate merging and the changed-rectangle encoding.
+                dither: Dither::None,
+                quantizer: Quantizer::Wu { refine: 0 },
+                ..QuantizeOptions::default()
+            };
+            quantize::reduce_colors(&kept, &options).map_err(WebpAnimError::Quantize)?
+        }
+        None => kept.into_iter().cloned().collect(),
+    };
+
+    let mut reduced: Vec<Frame> = Vec::new();
+    for (buffer, delay_ms) in buffers.into_iter().zip(delays) {
+        if let Some(last) = reduced.last_mut() {
+            if last.buffer() == &buffer {
+                let merged = animation::frame_delay_ms(last).saturating_add(delay_ms);
+                *last = Frame::from_parts(buffer, 0, 0, Delay::from_numer_denom_ms(merged, 1));
+                continue;
+            }
+        }
+        reduced.push(Frame::from_parts(
+            buffer,
+            0,
+            0,
+            Delay::from_numer_denom_ms(delay_ms, 1),
+        ));
+    }
+    Ok(reduced)
+}
+
+/// Returns the even-aligned bounding rectangle `(x, y, w, h)` of pixels that differ
+/// between two equally sized frames, or `None` if they are identical.
+fn changed_rect(previous: &RgbaImage, current: &RgbaImage) -> Option<(u32, u32, u32, u32)> {
+    let mut bounds: Option<(u32, u32, u32, u32)> = None;
+    for ((x, y, a), b) in previous.enumerate_pixels().zip(current.pixels()) {
+        if a != b {
+            bounds = Some(match bounds {
+                None => (x, y, x, y),
+                Some((x0, y0, x1, y1)) => (x0.min(x), y0.min(y), x1.max(x), y1.max(y)),
+            });
+        }
+    }
+    // ANMF offsets are stored divided by two, so the origin must be even.
+    bounds.map(|(x0, y0, x1, y1)| {
+        let x = x0 & !1;
+        let y = y0 & !1;
+        (x, y, x1 - x + 1, y1 - y + 1)
+    })
+}
+
+/// Encodes an RGBA buffer as a lossless WebP and returns its raw `VP8L` bitstream.
+fn encode_vp8l(buffer: &RgbaImage) -> Result<Vec<u8>, WebpAnimError> {
+    let mut encoded = Vec::new();
+    WebPEncoder::new_lossless(&mut encoded)
+        .encode(
+            buffer.as_raw(),
+            buffer.width(),
+            buffer.height(),
+            ExtendedColorType::Rgba8,
+        )
+        .map_err(WebpAnimError::Encode)?;
+
+    let mut pos = 12;
+    while let Some(header) = encoded.get(pos..pos + 8) {
+        let (fourcc, len_bytes) = header.split_at(4);
+        let len_bytes: [u8; 4] = len_bytes.try_into().map_err(|_| WebpAnimError::Mux)?;
+        let len = usize::try_from(u32::from_le_bytes(len_bytes)).map_err(|_| WebpAnimError::Mux)?;
+        if fourcc == b"VP8L" {
+            return encoded
+                .get(pos + 8..pos + 8 + len)
+                .map(<[u8]>::to_vec)
+                .ok_or(WebpAnimError::Mux);
+        }
+        pos += 8 + len + (len & 1);
+    }
+    Err(WebpAnimError::Mux)
+}
+
+/// Appends a RIFF chunk (FourCC, little-endian length, payload, pad byte).
+fn push_chunk(out: &mut Vec<u8>, fourcc: [u8; 4], payload: &[u8]) -> Result<(), WebpAnimError> {
+    let len = u32::try_from(payload.len()).map_err(|_| WebpAnimError::TooLarge)?;
+    out.extend_from_slice(&fourcc);
+    out.extend_from_slice(&len.to_le_bytes());
+    out.extend_from_slice(payload);
+    if payload.len() % 2 == 1 {
+        out.push(0);
+    }
+    Ok(())
+}
+
+/// Appends the low 24 bits of `value` in little-endian order.
+fn push_u24(out: &mut Vec<u8>, value: u32) {
+    let [b0, b1, b2, _] = value.to_le_bytes();
+    out.extend_from_slice(&[b0, b1, b2]);
+}
+
+/// Errors that can occur while encoding an animated WebP.
+#[derive(Debug)]
+pub enum WebpAnimError {
+    /// Failed to read frames from the input.
+    Animation(AnimationError),
+    /// Failed to quantize the frames to fewer colors.
+    Quantize(QuantizeError),
+    /// The WebP encoder failed on a frame.
+    Encode(image::ImageError),
+    /// There were no frames to encode.
+    NoFrames,
+    /// A frame's dimensions differ from the first frame.
+    FrameSizeMismatch,
+    /// The canvas or a chunk exceeds what the WebP container can represent.
+    TooLarge,
+    /// The per-frame WebP output did not contain a `VP8L` bitstream.
+    Mux,
+}
+
+impl fmt::Display for WebpAnimError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Animation(e) => write!(f, "{e}"),
+            Self::Quantize(e) => write!(f, "{e}"),
+            Self::Encode(e) => write!(f, "Failed to encode WebP frame: {e}"),
+            Self::NoFrames => write!(f, "Animation has no frames"),
+            Self::FrameSizeMismatch => write!(f, "All frames must have the same dimensions"),
+            Self::TooLarge => write!(
+                f,
+                "Animation exceeds the WebP limit of {MAX_WEBP_DIMENSION}x{MAX_WEBP_DIMENSION} pixels"
+            ),
+            Self::Mux => write!(f, "Failed to assemble animated WebP container"),
+        }
+    }
+}
+
+impl std::error::Error for WebpAnimError {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use image::Rgba;
+
+    use super::*;
+
+    // ===== Fixture Helpers =====
+
+    fn frame(img: RgbaImage, delay_ms: u32) -> Frame {
+        Frame::from_parts(img, 0, 0, Delay::from_numer_denom_ms(delay_ms, 1))
+    }
+
+    fn solid(color: [u8; 4]) -> RgbaImage {
+        RgbaImage::from_pixel(16, 12, Rgba(color))
+    }
+
+    /// A moving gradient with many unique colors so lossless output is not trivially small.
+    #[allow(clippy::as_conversions)] // Safe: modulo 256 guarantees the value fits in u8.
+    fn gradient_frames(count: u32) -> Vec<Frame> {
+        (0..count)
+            .map(|i| {
+                let img = RgbaImage::from_fn(64, 48, |x, y| {
+                    Rgba([
+                        ((x * 7 + i * 13) % 256) as u8,
+                        ((y * 11 + i * 5) % 256) as u8,
+                        ((x * y + i) % 256) as u8,
+                        255,
+                    ])
+                });
+                frame(img, 40)
+            })
+            .collect()
+    }
+
+    fn gif_from(frames: Vec<Frame>) -> Vec<u8> {
+        animation::encode_gif(frames, None).unwrap()
+    }
+
+    // ===== encode_animated_webp Tests =====
+
+    #[test]
+    fn encode_round_trip() {
+        let frames = vec![
+            frame(solid([255, 0, 0, 255]), 50),
+            frame(solid([0, 0, 255, 255]), 70),
+        ];
+        let webp = encode_animated_webp(&frames).unwrap();
+        let decoded = animation::decode_frames(&webp).unwrap();
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0].buffer().dimensions(), (16, 12));
+        assert_eq!(*decoded[0].buffer().get_pixel(3, 3), Rgba([255, 0, 0, 255]));
+        assert_eq!(*decoded[1].buffer().get_pixel(3, 3), Rgba([0, 0, 255, 255]));
+        assert_eq!(animation::frame_delay_ms(&decoded[1]), 70);
+    }
+
+    #[test]
+    fn encode_partial_frame_update() {
+        let first = solid([0, 0, 0, 255]);
+        let mut second = first.clone();
+        second.put_pixel(9, 7, Rgba([255, 255, 255, 255]));
+        let webp = encode_animated_webp(&[frame(first, 10), frame(second, 10)]).unwrap();
+
+        let decoded = animation::decode_frames(&webp).unwrap();
+        let last = decoded[1].buffer();
+        assert_eq!(*last.get_pixel(9, 7), Rgba([255, 255, 255, 255]));
+        assert_eq!(*last.get_pixel(0, 0), Rgba([0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn encode_preserves_transparency() {
+        let webp = encode_animated_webp(&[frame(solid([10, 20, 30, 0]), 10)]).unwrap();
+        let decoded = animation::decode_frames(&webp).unwrap();
+        assert_eq!(decoded[0].buffer().get_pixel(0, 0).0[3], 0);
+    }
+
+    #[test]
+    fn encode_no_frames() {
+        assert!(matches!(
+            encode_animated_webp(&[]),
+            Err(WebpAnimError::NoFrames)
+        ));
+    }
+
+    #[test]
+    fn encode_mismatched_frames() {
+        let frames = vec![
+            frame(solid([0, 0, 0, 255]), 10),
+            frame(RgbaImage::new(4, 4), 10),
+        ];
+        assert!(matches!(
+            encode_animated_webp(&frames),
+            Err(WebpAnimError::FrameSizeMismatch)
+        ));
+    }
+
+    // ===== to_animated_webp Tests =====
+
+    #[test]
+    fn duplicate_frames_are_merged() {
+        let red = solid([255, 0, 0, 255]);
+        let gif = gif_from(vec![
+            frame(red.clone(), 100),
+            frame(red, 100),
+            frame(solid([0, 255, 0, 255]), 100),
+        ]);
+        let result = to_animated_webp(&gif, None).unwrap();
+        assert_eq!(result.report.input_frames, 3);
+        assert_eq!(result.report.output_frames, 2);
+        assert_eq!(result.report.duplicate_frames_merged, 1);
+
+        let decoded = animation::decode_frames(&result.data).unwrap();
+        assert_eq!(animation::frame_delay_ms(&decoded[0]), 200);
+    }
+
+    #[test]
+    fn budget_triggers_reduction() {
+        let webp_frames = gradient_frames(12);
+        let unconstrained = encode_animated_webp(&webp_frames).unwrap();
+        let source = encode_animated_webp(&webp_frames).unwrap();
+
+        let budget = unconstrained.len() / 2;
+        let result = to_animated_webp(&source, Some(budget)).unwrap();
+        assert!(result.report.met_target, "report: {:?}", result.report);
+        assert!(result.data.len() <= budget);
+        assert!(result.report.max_colors.is_some() || result.report.frame_step > 1);
+    }
+
+    #[test]
+    fn budget_preserves_total_duration() {
+        let source = encode_animated_webp(&gradient_frames(8)).unwrap();
+        let result = to_animated_webp(&source, Some(1)).unwrap();
+        let decoded = animation::decode_frames(&result.data).unwrap();
+        let total: u32 = decoded.iter().map(animation::frame_delay_ms).sum();
+        assert_eq!(total, 8 * 40);
+    }
+
+    #[test]
+    fn unreachable_budget_returns_smallest() {
+        let source = encode_animated_webp(&gradient_frames(4)).unwrap();
+        let result = to_animated_webp(&source, Some(1)).unwrap();
+        assert!(!result.report.met_target);
+        assert_eq!(result.report.max_colors, Some(16));
+        assert_eq!(result.report.output_bytes, result.data.len());
+    }
+
+    #[test]
+    fn reduced_frames_share_one_palette() {
+        let frames = gradient_frames(3);
+        let reduced = reduce_frames(&frames, Some(16), 1).unwrap();
+        assert_eq!(reduced.len(), 3);
+        let colors: HashSet<[u8; 4]> = reduced
+            .iter()
+            .flat_map(|frame| frame.buffer().pixels().map(|p| p.0))
+            .collect();
+        assert!(colors.len() <= 16, "{} colors", colors.len());
+
+        let unchanged = reduce_frames(&frames, None, 1).unwrap();
+        assert_eq!(unchanged[1].buffer(), frames[1].buffer());
+    }
+
+    #[test]
+    fn merged_delays_saturate() {
+        let long = vec![
+            frame(solid([255, 0, 0, 255]), u32::MAX),
+            frame(solid([255, 0, 0, 255]), 40),
+            frame(solid([0, 0, 255, 255]), u32::MAX),
+            frame(solid([0, 0, 255, 255]), 40),
+        ];
+        let merged = reduce_frames(&long, None, 1).unwrap();
+        assert_eq!(merged.len(), 2);
+        let decimated = reduce_frames(&long, Some(16), 2).unwrap();
+        assert_eq!(decimated.len(), 2);
+        for frame in merged.iter().chain(&decimated) {
+            assert_eq!(animation::frame_delay_ms(frame), u32::MAX);
+        }
+    }
+
+    #[test]
+    fn changed_rect_aligns_to_even() {
+        let a = RgbaImage::new(10, 10);
+        let mut b = a.clone();
+        b.put_pixel(5, 3, Rgba([1, 1, 1, 1]));
+        assert_eq!(changed_rect(&a, &b), Some((4, 2, 2, 2)));
+        assert_eq!(changed_rect(&a, &a), None);
+    }
+}